@@ -0,0 +1,99 @@
+//! Compare the stages of the pipeline on different shapes of input.
+//!
+//! - small: a short expression
+//! - large: a long balanced expression
+//! - deep: nested parentheses
+//! - wide: a long chain of operators
+//!
+//! The lexer is also compared on the short inputs typed in the
+//! interactive mode.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+use wcal::{calculator, eval_str_fast, eval_streaming, lexer, parser};
+use wcal::parser::top_down_parser;
+
+fn inputs() -> Vec<(&'static str, String)> {
+    let depth = parser::MAX_DEPTH - 1;
+    let large = (0..64)
+        .map(|i| format!("({}+{}*(0x{:x}-{})*-{})", i, i + 1, i + 2, i + 3, i + 4))
+        .collect::<Vec<_>>()
+        .join("-");
+    vec![
+        ("small", "1+2*(3-4)/-5".to_string()),
+        ("large", large),
+        ("deep", "(".repeat(depth) + "1" + &")".repeat(depth)),
+        ("wide", vec!["12"; depth].join("+")),
+    ]
+}
+
+fn interactive() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("number", "42"),
+        ("sum", "1 + 2"),
+        ("call", "max(a, 2) * 3"),
+        ("assign", "total = $1 + $2"),
+        ("nested", "f(g(x), [1, 2][0])"),
+    ]
+}
+
+fn bench_lexer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexer");
+    for (name, input) in inputs().iter() {
+        group.bench_with_input(BenchmarkId::new("spanned", name), input, |b, input| {
+            b.iter(|| lexer::spanned(black_box(input)))
+        });
+    }
+    for (name, input) in interactive() {
+        group.bench_with_input(BenchmarkId::new("interactive", name), input, |b, input| {
+            b.iter(|| lexer::spanned(black_box(input)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser");
+    for (name, input) in inputs().iter() {
+        let tokens = lexer::spanned(input).unwrap();
+        group.bench_with_input(BenchmarkId::new("top_down", name), &tokens, |b, tokens| {
+            b.iter(|| top_down_parser::parse(black_box(tokens.clone())))
+        });
+        group.bench_with_input(BenchmarkId::new("stream", name), input, |b, input| {
+            b.iter(|| top_down_parser::parse_stream(lexer::tokens(black_box(input))))
+        });
+    }
+    group.finish();
+}
+
+fn bench_evaluator(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluator");
+    for (name, input) in inputs().iter() {
+        group.bench_with_input(BenchmarkId::new("i128", name), input, |b, input| {
+            b.iter(|| calculator::<i128>(black_box(input), top_down_parser::parse))
+        });
+        group.bench_with_input(BenchmarkId::new("f64", name), input, |b, input| {
+            b.iter(|| calculator::<f64>(black_box(input), top_down_parser::parse))
+        });
+    }
+    group.finish();
+}
+
+fn bench_fast(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fast");
+    for (name, input) in inputs().iter() {
+        group.bench_with_input(BenchmarkId::new("calculator", name), input, |b, input| {
+            b.iter(|| calculator::<i128>(black_box(input), top_down_parser::parse))
+        });
+        group.bench_with_input(BenchmarkId::new("eval_str_fast", name), input, |b, input| {
+            b.iter(|| eval_str_fast::<i128>(black_box(input)))
+        });
+        group.bench_with_input(BenchmarkId::new("eval_streaming", name), input, |b, input| {
+            b.iter(|| eval_streaming::<i128>(black_box(input)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lexer, bench_parser, bench_evaluator, bench_fast);
+criterion_main!(benches);
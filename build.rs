@@ -0,0 +1,5 @@
+fn main() {
+    // Link flags of the Node.js addon
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
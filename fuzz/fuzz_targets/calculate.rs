@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use wcal::{diagnostics, eval_no_panic};
+
+fuzz_target!(|data: &str| {
+    if let Err(err) = eval_no_panic::<i128>(data) {
+        diagnostics::render(data, &err);
+    }
+    if let Err(err) = eval_no_panic::<f64>(data) {
+        diagnostics::render(data, &err);
+    }
+});
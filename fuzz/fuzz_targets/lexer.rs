@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use wcal::lexer;
+
+fuzz_target!(|data: &str| {
+    if let Ok(tokens) = lexer::spanned(data) {
+        for (_, span) in tokens {
+            assert!(span.start < span.end && span.end <= data.len());
+            assert!(data.is_char_boundary(span.start) && data.is_char_boundary(span.end));
+        }
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use wcal::{diagnostics, lexer, parser};
+
+fuzz_target!(|data: &str| {
+    match lexer::spanned(data).and_then(parser::rpn_parser::parse) {
+        Ok(ast) => assert!(ast.root.span().end <= data.len()),
+        Err(err) => {
+            diagnostics::render(data, &err);
+        }
+    }
+});
@@ -0,0 +1,145 @@
+//! Angles of the trigonometric functions of the `f64` backend.
+//!
+//! `sin`, `cos`, `tan` take an angle and `asin`, `acos`, `atan`
+//! return one, in the [`AngleUnit`] of the calculation. `deg(x)`
+//! converts `x` radians to degrees and `rad(x)` `x` degrees to
+//! radians, whatever the unit.
+//!
+//! A function registered in the [`FunctionRegistry`] of the same
+//! name takes precedence.
+//!
+//! [`FunctionRegistry`]: crate::FunctionRegistry
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Functions of the angles, all taking one argument.
+pub(crate) const FUNCTIONS: [&str; 8] = ["sin", "cos", "tan", "asin", "acos", "atan", "deg", "rad"];
+
+/// Unit of the angles of the trigonometric functions.
+///
+/// # Example
+/// ```
+/// use wcal::{AngleUnit, Calculator};
+///
+/// let calc = Calculator::<f64>::new().angle_unit(AngleUnit::Degree);
+/// assert_eq!(calc.eval("sin(90) + cos(180)"), Ok(0.0));
+/// assert_eq!(calc.eval("asin(1)"), Ok(90.0));
+/// assert_eq!(Calculator::<f64>::new().eval("deg(acos(-1))"), Ok(180.0));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum AngleUnit {
+    #[default]
+    Radian,
+    Degree,
+}
+
+impl AngleUnit {
+    /// The angle in radians.
+    pub fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Radian => angle,
+            AngleUnit::Degree => angle.to_radians(),
+        }
+    }
+
+    /// The angle of `radians` in the unit.
+    pub fn from_radians(self, radians: f64) -> f64 {
+        match self {
+            AngleUnit::Radian => radians,
+            AngleUnit::Degree => radians.to_degrees(),
+        }
+    }
+}
+
+impl fmt::Display for AngleUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AngleUnit::Radian => write!(f, "rad"),
+            AngleUnit::Degree => write!(f, "deg"),
+        }
+    }
+}
+
+impl FromStr for AngleUnit {
+    type Err = String;
+
+    /// `rad` or `deg`, or their names in full.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rad" | "radian" | "radians" => Ok(AngleUnit::Radian),
+            "deg" | "degree" | "degrees" => Ok(AngleUnit::Degree),
+            _ => Err(format!("Angle unit must be rad or deg, got {}", s)),
+        }
+    }
+}
+
+/// Value of the function of the angles at `x`, `None` if the name
+/// isn't one of [`FUNCTIONS`].
+pub(crate) fn call(name: &str, x: f64, unit: AngleUnit) -> Option<f64> {
+    let value = match name {
+        "sin" => sin_cos(x, unit).0,
+        "cos" => sin_cos(x, unit).1,
+        "tan" => {
+            let (sin, cos) = sin_cos(x, unit);
+            if cos == 0.0 { sin / 0.0 } else { sin / cos }
+        }
+        "asin" => unit.from_radians(x.asin()),
+        "acos" => unit.from_radians(x.acos()),
+        "atan" => unit.from_radians(x.atan()),
+        "deg" => x.to_degrees(),
+        "rad" => x.to_radians(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Sine and cosine of the angle. In degrees, the multiples of 90
+/// are exact, so `sin(180)` is `0` rather than `1.2e-16`.
+fn sin_cos(x: f64, unit: AngleUnit) -> (f64, f64) {
+    if unit == AngleUnit::Degree && x.is_finite() && x % 90.0 == 0.0 {
+        return match (x / 90.0).rem_euclid(4.0) as u8 {
+            0 => (0.0, 1.0),
+            1 => (1.0, 0.0),
+            2 => (0.0, -1.0),
+            _ => (-1.0, 0.0),
+        }
+    }
+    unit.to_radians(x).sin_cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call() {
+        use AngleUnit::*;
+
+        assert_eq!(call("sin", 90.0, Degree), Some(1.0));
+        assert_eq!(call("cos", -180.0, Degree), Some(-1.0));
+        assert_eq!(call("sin", 540.0, Degree), Some(0.0));
+        assert_eq!(call("tan", 45.0, Degree).map(|x| (x - 1.0).abs() < 1e-15), Some(true));
+        assert_eq!(call("tan", 90.0, Degree), Some(f64::INFINITY));
+        assert_eq!(call("sin", 90.0, Radian), Some(90f64.sin()));
+        assert_eq!(call("acos", -1.0, Degree), Some(180.0));
+        assert_eq!(call("atan", 1.0, Radian), Some(std::f64::consts::FRAC_PI_4));
+        assert_eq!(call("deg", std::f64::consts::PI, Radian), Some(180.0));
+        assert_eq!(call("rad", 180.0, Degree), Some(std::f64::consts::PI));
+        assert!(call("sin", f64::INFINITY, Degree).unwrap().is_nan());
+        assert_eq!(call("sqrt", 4.0, Radian), None);
+    }
+
+    #[test]
+    fn test_unit() {
+        assert_eq!("deg".parse(), Ok(AngleUnit::Degree));
+        assert_eq!("radians".parse(), Ok(AngleUnit::Radian));
+        assert!("grad".parse::<AngleUnit>().is_err());
+        assert_eq!(AngleUnit::Degree.to_string(), "deg");
+        assert_eq!(AngleUnit::default(), AngleUnit::Radian);
+    }
+}
@@ -0,0 +1,268 @@
+//! What the server knows of a `.calc` file, from the spans of the
+//! library.
+//!
+//! Each line is an expression followed by an optional `#` comment,
+//! like the script files of `wcal`. The lines are calculated in
+//! order as `i128`, so the variables of the earlier lines are known.
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, Hover, HoverContents, MarkupContent, MarkupKind, Position,
+    Range, SemanticToken, SemanticTokenType, TextEdit,
+};
+use wcal::{highlight, Calculator, FromAST, LineColumn, Session, SourceMap, TokenCategory};
+use wcal::lexer::Span;
+use wcal::parser::ast::{Expr, AST};
+use wcal::pretty::{self, Style};
+
+/// Types of the semantic tokens, their index is the type of
+/// [`SemanticToken`].
+pub const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::OPERATOR,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::STRING,
+];
+
+/// The expression and the comment of the line, split at `#`.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    match line.find('#') {
+        Some(i) => (&line[..i], Some(&line[i..])),
+        None => (line, None),
+    }
+}
+
+/// Position of the byte offset of the line, whose characters are
+/// counted in UTF-16 like the editors do.
+fn position(line: &str, line_no: usize, offset: usize) -> Position {
+    Position::new(line_no as u32, SourceMap::new(line).utf16_location(offset).column as u32)
+}
+
+fn range(line: &str, line_no: usize, span: &Span) -> Range {
+    Range::new(position(line, line_no, span.start), position(line, line_no, span.end))
+}
+
+/// Byte offset of the UTF-16 character of the line.
+fn offset(line: &str, character: u32) -> usize {
+    SourceMap::new(line).utf16_offset(LineColumn{line: 0, column: character as usize})
+}
+
+/// Errors and warnings of the lines, calculated in order.
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    let mut session = Session::<i128>::default();
+    let mut diagnostics = Vec::new();
+    for (no, line) in source.lines().enumerate() {
+        let (expr, _) = split_comment(line);
+        if expr.trim().is_empty() {
+            continue
+        }
+        let entry = session.eval(expr);
+        if let Err(err) = &entry.result {
+            let mut message = err.message();
+            if let Some((span, note)) = err.note() {
+                let at = position(line, no, span.start);
+                message = format!("{}\nnote: {} at {}:{}", message, note, at.line + 1, at.character + 1);
+            }
+            diagnostics.push(diagnostic(range(line, no, &err.span()), DiagnosticSeverity::ERROR, message));
+        }
+        for warning in entry.warnings.iter() {
+            diagnostics.push(diagnostic(range(line, no, &warning.span()), DiagnosticSeverity::WARNING, warning.to_string()));
+        }
+    }
+    diagnostics
+}
+
+fn diagnostic(range: Range, severity: DiagnosticSeverity, message: String) -> Diagnostic {
+    Diagnostic{
+        range,
+        severity: Some(severity),
+        source: Some("wcal".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// Whether the node is calculated the same in any file, without
+/// variables and functions.
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Var(_) | Expr::Assign(_) | Expr::Call(_) => false,
+        _ => expr.children().into_iter().all(is_constant),
+    }
+}
+
+/// Nodes containing the offset, from the root.
+fn path(root: &Expr, offset: usize) -> Vec<&Expr> {
+    let mut path = Vec::new();
+    let mut node = Some(root);
+    while let Some(expr) = node.filter(|expr| expr.span().contains(&offset)) {
+        path.push(expr);
+        node = expr.children().into_iter().find(|child| child.span().contains(&offset));
+    }
+    path
+}
+
+/// Value of the innermost operation under the cursor, if it is
+/// constant.
+pub fn hover(source: &str, at: Position) -> Option<Hover> {
+    let line = source.lines().nth(at.line as usize)?;
+    let (expr, _) = split_comment(line);
+    let calc = Calculator::<i128>::new();
+    let ast = calc.parse(expr).ok()?;
+    let node = *path(&ast.root, offset(line, at.character)).iter().rev()
+        .find(|node| !matches!(node, Expr::Num(_)))?;
+    if !is_constant(node) {
+        return None
+    }
+    let ast = AST{root: node.clone()};
+    let value = i128::from_ast_in(ast.clone(), &mut calc.context()).ok()?;
+    let text = pretty::format_ast(&ast, expr, &Style::default());
+    Some(Hover{
+        contents: HoverContents::Markup(MarkupContent{
+            kind: MarkupKind::Markdown,
+            value: format!("`{}` = {}", text, value),
+        }),
+        range: Some(range(line, at.line as usize, &node.span())),
+    })
+}
+
+/// Tokens of the lines with their types, encoded relatively to the
+/// previous token.
+pub fn semantic_tokens(source: &str) -> Vec<SemanticToken> {
+    let mut tokens = Vec::new();
+    let (mut last_line, mut last_start) = (0, 0);
+    for (no, line) in source.lines().enumerate() {
+        let (expr, comment) = split_comment(line);
+        let mut spans: Vec<_> = highlight(expr).into_iter()
+            .filter_map(|(span, category)| {
+                let kind = match category {
+                    TokenCategory::Number => SemanticTokenType::NUMBER,
+                    TokenCategory::Operator => SemanticTokenType::OPERATOR,
+                    TokenCategory::Identifier if expr[span.end..].trim_start().starts_with('(') => SemanticTokenType::FUNCTION,
+                    TokenCategory::Identifier => SemanticTokenType::VARIABLE,
+                    TokenCategory::Keyword => SemanticTokenType::KEYWORD,
+                    TokenCategory::Str => SemanticTokenType::STRING,
+                    TokenCategory::Paren | TokenCategory::Separator | TokenCategory::Error => return None,
+                };
+                Some((span, kind))
+            })
+            .collect();
+        if comment.is_some() {
+            spans.push((expr.len()..line.len(), SemanticTokenType::COMMENT));
+        }
+        for (span, kind) in spans {
+            let range = range(line, no, &span);
+            let start = range.start.character;
+            let delta_line = no as u32 - last_line;
+            tokens.push(SemanticToken{
+                delta_line,
+                delta_start: if delta_line == 0 { start - last_start } else { start },
+                length: range.end.character - start,
+                token_type: TOKEN_TYPES.iter().position(|token_type| *token_type == kind).unwrap_or_default() as u32,
+                token_modifiers_bitset: 0,
+            });
+            last_line = no as u32;
+            last_start = start;
+        }
+    }
+    tokens
+}
+
+/// Edit rewriting the lines in their canonical text with their
+/// comments, `None` if a line can't be parsed.
+pub fn formatting(source: &str) -> Option<Vec<TextEdit>> {
+    let style = Style::default();
+    let mut lines = Vec::new();
+    for line in source.lines() {
+        let (expr, comment) = split_comment(line);
+        let text = if expr.trim().is_empty() {
+            line.trim().to_string()
+        } else {
+            let text = pretty::format(expr, &style).ok()?;
+            match comment {
+                Some(comment) => format!("{} {}", text, comment.trim_end()),
+                None => text,
+            }
+        };
+        lines.push(text);
+    }
+    let mut text = lines.join("\n");
+    if source.ends_with('\n') {
+        text.push('\n');
+    }
+    if text == source {
+        return Some(Vec::new())
+    }
+    let last = source.lines().count().saturating_sub(1);
+    let end = match source.lines().last() {
+        Some(line) if !source.ends_with('\n') => position(line, last, line.len()),
+        _ => Position::new(source.lines().count() as u32, 0),
+    };
+    Some(vec![TextEdit::new(Range::new(Position::new(0, 0), end), text)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics() {
+        let diagnostics = diagnostics("x = 7 # rate\n\nx / 2\n(y + 1\n1/0");
+        let found: Vec<_> = diagnostics.iter()
+            .map(|diagnostic| (diagnostic.range, diagnostic.severity.unwrap(), diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(found, [
+            (Range::new(Position::new(2, 0), Position::new(2, 5)), DiagnosticSeverity::WARNING, "Division will cause a cast: 7/2"),
+            (Range::new(Position::new(3, 6), Position::new(3, 6)), DiagnosticSeverity::ERROR, "Expect ), got nothing\nnote: unclosed ( at 4:1"),
+            (Range::new(Position::new(4, 0), Position::new(4, 3)), DiagnosticSeverity::ERROR, "Division by zero"),
+        ]);
+    }
+
+    #[test]
+    fn test_hover() {
+        let source = "x * (2 + 3*4) # é\n0xff";
+        let value = |line, character| hover(source, Position::new(line, character)).map(|hover| match hover.contents {
+            HoverContents::Markup(content) => (content.value, hover.range.unwrap()),
+            _ => unreachable!(),
+        });
+        assert_eq!(value(0, 10), Some(("`3 * 4` = 12".to_string(), Range::new(Position::new(0, 9), Position::new(0, 12)))));
+        assert_eq!(value(0, 7).unwrap().0, "`2 + 3 * 4` = 14");
+        assert_eq!(value(0, 4).unwrap().0, "`2 + 3 * 4` = 14");
+        // Not constant
+        assert_eq!(value(0, 0), None);
+        assert_eq!(value(0, 2), None);
+        // Literal alone, comment and past the end
+        assert_eq!(value(1, 1), None);
+        assert_eq!(value(0, 16), None);
+        assert_eq!(value(2, 0), None);
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let tokens = semantic_tokens("x = max(1, 2)\n\n  é + y #n");
+        let found: Vec<_> = tokens.iter()
+            .map(|token| (token.delta_line, token.delta_start, token.length, TOKEN_TYPES[token.token_type as usize].as_str()))
+            .collect();
+        assert_eq!(found, [
+            (0, 0, 1, "variable"),
+            (0, 2, 1, "operator"),
+            (0, 2, 3, "function"),
+            (0, 4, 1, "number"),
+            (0, 3, 1, "number"),
+            (2, 4, 1, "operator"),
+            (0, 2, 1, "variable"),
+            (0, 2, 2, "comment"),
+        ]);
+    }
+
+    #[test]
+    fn test_formatting() {
+        let edits = formatting("x=1+2 # rate\n\nx*( 3 )\n").unwrap();
+        assert_eq!(edits, [TextEdit::new(Range::new(Position::new(0, 0), Position::new(3, 0)), "x = 1 + 2 # rate\n\nx * 3\n".to_string())]);
+        let edits = formatting("1+1").unwrap();
+        assert_eq!(edits[0].range.end, Position::new(0, 3));
+        assert_eq!(formatting("1 + 1\n"), Some(Vec::new()));
+        assert_eq!(formatting("1 +\n2"), None);
+    }
+}
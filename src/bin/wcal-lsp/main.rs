@@ -0,0 +1,136 @@
+//! `wcal-lsp`, the language server of `.calc` files over stdio.
+//!
+//! It reports the errors and warnings of the lines as diagnostics,
+//! the value of constant operations on hover, the semantic tokens
+//! of the lines and formats them like `wcal fmt`. Editors start it
+//! as the server of the files with the `calc` language.
+mod analysis;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::process;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Formatting, HoverRequest, Request as _, SemanticTokensFullRequest};
+use lsp_types::{
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentFormattingParams,
+    HoverParams, HoverProviderCapability, OneOf, PublishDiagnosticsParams, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities{
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        semantic_tokens_provider: Some(SemanticTokensOptions{
+            legend: SemanticTokensLegend{token_types: analysis::TOKEN_TYPES.to_vec(), token_modifiers: Vec::new()},
+            full: Some(SemanticTokensFullOptions::Bool(true)),
+            ..SemanticTokensOptions::default()
+        }.into()),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+fn main() {
+    let (connection, io_threads) = Connection::stdio();
+    let result = serde_json::to_value(capabilities())
+        .map_err(Box::<dyn Error + Sync + Send>::from)
+        .and_then(|capabilities| Ok(connection.initialize(capabilities)?))
+        .and_then(|_| serve(&connection));
+    drop(connection);
+    if let Err(err) = result.and_then(|_| Ok(io_threads.join()?)) {
+        eprintln!("wcal-lsp: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Answer the messages until the client shuts the server down.
+fn serve(connection: &Connection) -> Result<(), Box<dyn Error + Sync + Send>> {
+    // Text of the open documents
+    let mut documents = HashMap::<Url, String>::new();
+    for message in connection.receiver.iter() {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(())
+                }
+                connection.sender.send(Message::Response(respond(&documents, request)))?;
+            }
+            Message::Notification(notification) => {
+                let uri = match notification.method.as_str() {
+                    DidOpenTextDocument::METHOD => {
+                        let params: DidOpenTextDocumentParams = params(notification.params)?;
+                        documents.insert(params.text_document.uri.clone(), params.text_document.text);
+                        params.text_document.uri
+                    }
+                    DidChangeTextDocument::METHOD => {
+                        let params: DidChangeTextDocumentParams = params(notification.params)?;
+                        // Full sync, the last change is the whole text
+                        if let Some(change) = params.content_changes.into_iter().last() {
+                            documents.insert(params.text_document.uri.clone(), change.text);
+                        }
+                        params.text_document.uri
+                    }
+                    DidCloseTextDocument::METHOD => {
+                        let params: DidCloseTextDocumentParams = params(notification.params)?;
+                        documents.remove(&params.text_document.uri);
+                        params.text_document.uri
+                    }
+                    _ => continue,
+                };
+                let diagnostics = documents.get(&uri).map(|text| analysis::diagnostics(text)).unwrap_or_default();
+                let params = PublishDiagnosticsParams{uri, diagnostics, version: None};
+                connection.sender.send(Message::Notification(Notification::new(PublishDiagnostics::METHOD.to_string(), params)))?;
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn params<P: DeserializeOwned>(params: serde_json::Value) -> Result<P, serde_json::Error> {
+    serde_json::from_value(params)
+}
+
+/// Response of the request about an open document.
+fn respond(documents: &HashMap<Url, String>, request: Request) -> Response {
+    let Request{id, method, params: raw} = request;
+    let result = match method.as_str() {
+        HoverRequest::METHOD => params::<HoverParams>(raw).map(|params| {
+            let position = params.text_document_position_params;
+            text(documents, &position.text_document.uri).and_then(|text| analysis::hover(text, position.position))
+        }).map(|hover| json(&hover)),
+        SemanticTokensFullRequest::METHOD => params::<SemanticTokensParams>(raw).map(|params| {
+            text(documents, &params.text_document.uri).map(|text| {
+                SemanticTokensResult::Tokens(SemanticTokens{result_id: None, data: analysis::semantic_tokens(text)})
+            })
+        }).map(|tokens| json(&tokens)),
+        Formatting::METHOD => params::<DocumentFormattingParams>(raw).map(|params| {
+            text(documents, &params.text_document.uri).and_then(analysis::formatting)
+        }).map(|edits| json(&edits)),
+        _ => return error(id, ErrorCode::MethodNotFound, format!("Unknown method {}", method)),
+    };
+    match result {
+        Ok(result) => Response{id, result: Some(result), error: None},
+        Err(err) => error(id, ErrorCode::InvalidParams, err.to_string()),
+    }
+}
+
+fn text<'a>(documents: &'a HashMap<Url, String>, uri: &Url) -> Option<&'a str> {
+    documents.get(uri).map(String::as_str)
+}
+
+fn json<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+fn error(id: RequestId, code: ErrorCode, message: String) -> Response {
+    Response::new_err(id, code as i32, message)
+}
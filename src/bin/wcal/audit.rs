@@ -0,0 +1,224 @@
+//! `--audit FILE`, a record of each evaluation appended to the file
+//! as a line of JSON, to prove what was calculated and how.
+//!
+//! ```text
+//! {"ast":"a3e2…","backend":"i128","digest":"91cc…","error":null,"input":"7/2","options":{"angle_unit":"rad","rounding":"floor","warnings_as_errors":false},"prev":"0000…","result":3,"seq":1,"text":"3","warnings":["Division will cause a cast: 7/2"]}
+//! ```
+//! `ast` is the SHA-256 of the tree, the same for the inputs of one
+//! tree such as `1+2` and `(1) + 2`, and `null` if the input doesn't
+//! parse. The records are chained: `prev` is the `digest` of the
+//! record before, and `digest` the HMAC-SHA256 of the record without
+//! it keyed by `WCAL_AUDIT_KEY`, or its SHA-256 without a key. So a
+//! record changed, removed or moved breaks the chain. The records
+//! have no time, the same evaluations give the same records.
+//!
+//! A file appended to continues its chain. The records need the
+//! `audit` feature.
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde_json::{json, Value as Json};
+
+use wcal::Error;
+use wcal::error::Warning;
+use wcal::parser::ast::AST;
+
+use crate::format::{Format, Value};
+use crate::Status;
+
+/// Variable of the key of the digests.
+const KEY_VAR: &str = "WCAL_AUDIT_KEY";
+
+/// Audit of the evaluations, if asked.
+static AUDIT: Mutex<Option<Audit<File>>> = Mutex::new(None);
+
+/// Records written to `out`, after the record `seq` of the digest
+/// `prev`.
+struct Audit<W> {
+    out: W,
+    seq: u64,
+    prev: String,
+    key: Option<Vec<u8>>,
+}
+
+impl<W: Write> Audit<W> {
+    /// Chain the record, without its `seq`, `prev` and `digest`, and
+    /// write it.
+    fn append(&mut self, mut record: Json) -> io::Result<()> {
+        record["seq"] = json!(self.seq + 1);
+        record["prev"] = json!(self.prev);
+        let digest = digest(self.key.as_deref(), record.to_string().as_bytes());
+        record["digest"] = json!(digest);
+        writeln!(self.out, "{}", record)?;
+        self.out.flush()?;
+        self.seq += 1;
+        self.prev = digest;
+        Ok(())
+    }
+}
+
+/// `seq` and `digest` of the last record of the file, none before
+/// the first one.
+fn last(file: &File) -> Result<(u64, String), String> {
+    let mut last = None;
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|err| err.to_string())?;
+        if !line.trim().is_empty() {
+            last = Some(line);
+        }
+    }
+    let line = match last {
+        Some(line) => line,
+        None => return Ok((0, "0".repeat(64))),
+    };
+    let record: Json = serde_json::from_str(&line).map_err(|err| format!("its last record is invalid, {}", err))?;
+    match (record["seq"].as_u64(), record["digest"].as_str()) {
+        (Some(seq), Some(digest)) => Ok((seq, digest.to_string())),
+        _ => Err("its last record has no seq or digest".to_string()),
+    }
+}
+
+/// Record the evaluations in the file from now on.
+pub fn open(path: &Path) -> Result<(), String> {
+    if cfg!(not(feature = "audit")) {
+        return Err("Failed to audit: wcal is built without the audit feature".to_string())
+    }
+    let fail = |err: String| format!("Failed to audit to {}: {}", path.display(), err);
+    let file = OpenOptions::new().read(true).append(true).create(true).open(path).map_err(|err| fail(err.to_string()))?;
+    let (seq, prev) = last(&file).map_err(fail)?;
+    let key = std::env::var_os(KEY_VAR).map(|key| key.to_string_lossy().into_owned().into_bytes());
+    *AUDIT.lock().unwrap_or_else(|err| err.into_inner()) = Some(Audit{out: file, seq, prev, key});
+    Ok(())
+}
+
+/// Record the evaluation of the input, parsed to `ast`. A record
+/// that can't be written stops wcal, nothing is calculated
+/// unrecorded.
+pub fn record<T: Value>(input: &str, ast: Option<&AST>, result: &Result<T, Error>, warnings: &[Warning], format: &Format) {
+    let mut audit = AUDIT.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(audit) = audit.as_mut() {
+        if let Err(err) = audit.append(entry(input, ast, result, warnings, format)) {
+            eprintln!("Failed to write the audit: {}", err);
+            Status::IO.exit()
+        }
+    }
+}
+
+/// Record of the evaluation, before it is chained.
+fn entry<T: Value>(input: &str, ast: Option<&AST>, result: &Result<T, Error>, warnings: &[Warning], format: &Format) -> Json {
+    let warnings: Vec<String> = warnings.iter().map(|warning| warning.to_string()).collect();
+    json!({
+        "input": input,
+        "ast": ast.map(|ast| digest(None, ast.to_string().as_bytes())),
+        "backend": T::TYPE,
+        "options": {
+            "angle_unit": format.angle_unit.to_string(),
+            "rounding": format.rounding.to_string(),
+            "warnings_as_errors": format.warnings_as_errors,
+        },
+        "result": result.as_ref().map_or(Json::Null, |res| res.to_json()),
+        "text": result.as_ref().ok().map(|res| res.format(format)),
+        "error": result.as_ref().err().map(|err| err.message()),
+        "warnings": warnings,
+    })
+}
+
+/// HMAC-SHA256 of the bytes by the key, or their SHA-256 without
+/// one, in hexadecimal.
+#[cfg(feature = "audit")]
+fn digest(key: Option<&[u8]>, bytes: &[u8]) -> String {
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    let digest = match key {
+        Some(key) => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+            mac.update(bytes);
+            mac.finalize().into_bytes()
+        }
+        None => Sha256::digest(bytes),
+    };
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Never called, [`open`] fails first.
+#[cfg(not(feature = "audit"))]
+fn digest(_: Option<&[u8]>, _: &[u8]) -> String {
+    String::new()
+}
+
+#[cfg(all(test, feature = "audit"))]
+mod tests {
+    use super::*;
+    use wcal::Session;
+
+    fn audit(key: Option<&str>) -> Audit<Vec<u8>> {
+        Audit{out: Vec::new(), seq: 0, prev: "0".repeat(64), key: key.map(|key| key.as_bytes().to_vec())}
+    }
+
+    fn append(audit: &mut Audit<Vec<u8>>, input: &str) {
+        let mut session = Session::<i128>::default();
+        let evaluated = session.eval(input);
+        audit.append(entry(input, evaluated.ast.as_ref(), &evaluated.result, &evaluated.warnings, &Format::default())).unwrap();
+    }
+
+    fn records(audit: &Audit<Vec<u8>>) -> Vec<Json> {
+        String::from_utf8(audit.out.clone()).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_records() {
+        let mut audit = audit(None);
+        append(&mut audit, "7/2");
+        append(&mut audit, "(7) / 2");
+        append(&mut audit, "1 +");
+        let records = records(&audit);
+        assert_eq!(records[0]["result"], 3);
+        assert_eq!(records[0]["warnings"], json!(["Division will cause a cast: 7/2"]));
+        assert_eq!(records[0]["options"]["angle_unit"], "rad");
+        // The same tree, and no tree
+        assert_eq!(records[0]["ast"], records[1]["ast"]);
+        assert_eq!(records[0]["ast"], digest(None, b"(/ 7 2)"));
+        assert_eq!(records[2]["ast"], Json::Null);
+        assert_eq!(records[2]["error"], "Expect number, got nothing");
+
+        // Chained, each digest of the record without it
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(record["seq"], i + 1);
+            let prev = if i == 0 { "0".repeat(64) } else { records[i - 1]["digest"].as_str().unwrap().to_string() };
+            assert_eq!(record["prev"], prev);
+            let mut unsigned = record.clone();
+            unsigned.as_object_mut().unwrap().remove("digest");
+            assert_eq!(record["digest"], digest(None, unsigned.to_string().as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let run = |key| {
+            let mut audit = audit(key);
+            append(&mut audit, "x = 2");
+            append(&mut audit, "sqrt(16) + 1");
+            audit.out
+        };
+        assert_eq!(run(None), run(None));
+        assert_eq!(run(Some("secret")), run(Some("secret")));
+        assert_ne!(run(Some("secret")), run(Some("other")));
+        assert_ne!(run(None), run(Some("secret")));
+    }
+
+    #[test]
+    fn test_last() {
+        let path = std::env::temp_dir().join(format!("wcal-audit-{}.jsonl", std::process::id()));
+        let file = || OpenOptions::new().read(true).append(true).create(true).open(&path).unwrap();
+        assert_eq!(last(&file()), Ok((0, "0".repeat(64))));
+        writeln!(file(), r#"{{"seq":1,"digest":"ab"}}"#).unwrap();
+        writeln!(file(), r#"{{"seq":2,"digest":"cd"}}"#).unwrap();
+        assert_eq!(last(&file()), Ok((2, "cd".to_string())));
+        writeln!(file(), "{{").unwrap();
+        assert!(last(&file()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+//! `wcal bench EXPR`, calculate the expression many times with each
+//! backend and parser, reporting the throughput and the latencies.
+//!
+//! ```text
+//! $ wcal bench "1+2*(3-4)/-5" --backend i128 --backend f64
+//! backend   parser    runs      evals/s     p50       p90       p99
+//! i128      top-down  10000     908675      1.0µs     1.1µs     1.2µs
+//! f64       top-down  10000     954432      1.0µs     1.1µs     1.1µs
+//! ```
+//! Each run lexes, parses and calculates the expression, like an
+//! expression of the command line.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use wcal::{diagnostics, lexer, parser, BigInt, Decimal, Error, FromAST, Matrix, Rational, Time};
+
+use crate::{color, ParserKind, Status};
+use crate::config::Mode;
+
+/// When to stop the runs of a combination.
+#[derive(Clone, Copy, Debug)]
+pub enum Limit {
+    Runs(u64),
+    Duration(Duration),
+}
+
+/// Latencies of the runs of a combination.
+#[derive(Debug)]
+pub struct Report {
+    /// Nanoseconds of each run, sorted
+    latencies: Vec<u64>,
+    total: Duration,
+}
+
+impl Report {
+    pub fn runs(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// Runs per second.
+    pub fn throughput(&self) -> f64 {
+        self.runs() as f64 / self.total.as_secs_f64()
+    }
+
+    /// Latency that `p` percent of the runs don't exceed.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let index = ((self.runs() as f64 * p / 100.0).ceil() as usize).clamp(1, self.runs()) - 1;
+        Duration::from_nanos(self.latencies[index])
+    }
+}
+
+/// Calculate the expression until the limit, fail with the error
+/// of the expression if any.
+pub fn run<T: FromAST>(expr: &str, parse: parser::ParseFn, limit: Limit) -> Result<Report, Error> {
+    let eval = || lexer::spanned(black_box(expr)).and_then(parse).and_then(T::from_ast);
+    eval()?;
+    let mut latencies = Vec::new();
+    let start = Instant::now();
+    // At least one run, so that there are latencies
+    loop {
+        let run = Instant::now();
+        let _ = black_box(eval());
+        latencies.push(run.elapsed().as_nanos() as u64);
+        match limit {
+            Limit::Runs(runs) if latencies.len() as u64 >= runs => break,
+            Limit::Duration(duration) if start.elapsed() >= duration => break,
+            _ => {}
+        }
+    }
+    let total = start.elapsed();
+    latencies.sort_unstable();
+    Ok(Report{latencies, total})
+}
+
+/// Benchmark each combination of the backends and the parsers and
+/// print a row for each.
+pub fn bench(expr: &str, backends: &[Mode], parsers: &[ParserKind], limit: Limit) -> Status {
+    // A warning of each run would flood stderr and slow the runs
+    log::set_max_level(log::LevelFilter::Error);
+    let name = |value: Option<clap::builder::PossibleValue>| value.map(|value| value.get_name().to_string()).unwrap_or_default();
+    println!("backend   parser    runs      evals/s     p50       p90       p99");
+    for &backend in backends.iter() {
+        for &parser in parsers.iter() {
+            let report = match with_mode!(backend, run(expr, parser.parse_fn(), limit)) {
+                Ok(report) => report,
+                Err(err) => {
+                    eprintln!("{}", color::error(&diagnostics::render(expr, &err)));
+                    return Status::of(&err)
+                }
+            };
+            println!(
+                "{:<10}{:<10}{:<10}{:<12.0}{:<10.1?}{:<10.1?}{:.1?}",
+                backend.name(), name(parser.to_possible_value()), report.runs(), report.throughput(),
+                report.percentile(50.0), report.percentile(90.0), report.percentile(99.0),
+            );
+        }
+    }
+    Status::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let report = run::<i128>("1+2*3", parser::top_down_parser::parse, Limit::Runs(100)).unwrap();
+        assert_eq!(report.runs(), 100);
+        assert!(report.percentile(50.0) <= report.percentile(99.0));
+        assert!(report.percentile(100.0) <= report.total);
+        assert!(report.throughput() > 0.0);
+
+        let report = run::<f64>("1/3", parser::top_down_parser::parse, Limit::Duration(Duration::from_millis(5))).unwrap();
+        assert!(report.runs() > 0);
+
+        assert!(run::<i128>("1/0", parser::top_down_parser::parse, Limit::Runs(10)).is_err());
+    }
+
+    #[test]
+    fn test_percentile() {
+        let report = Report{latencies: (1..=10).collect(), total: Duration::from_nanos(55)};
+        assert_eq!(report.percentile(50.0), Duration::from_nanos(5));
+        assert_eq!(report.percentile(90.0), Duration::from_nanos(9));
+        assert_eq!(report.percentile(99.0), Duration::from_nanos(10));
+        assert_eq!(report.percentile(0.0), Duration::from_nanos(1));
+    }
+}
@@ -0,0 +1,124 @@
+//! `wcal check`, lex and parse files without calculating them.
+//!
+//! Every line with an error is reported, not only the first one,
+//! and the exit status is the one of the first error:
+//! ```text
+//! $ wcal check rates.calc
+//! Error: Expect number, got nothing
+//!  --> rates.calc:3:8
+//!   |
+//! 3 | x * 2 +
+//!   |        ^
+//! 1 error in 1 file
+//! ```
+//!
+//! With `--lint` the lines that parse are also linted, see
+//! [`wcal::lint`], and the lints reported as warnings that don't
+//! change the exit status.
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use wcal::lint::{self, Lint};
+use wcal::{diagnostics, lexer, parser, Error};
+
+use crate::{color, strip_comment, Status};
+
+/// Check the files, or stdin without them, linting them too with
+/// `lint`.
+pub fn check(files: &[PathBuf], lint: bool) -> Status {
+    let mut status = Status::default();
+    let mut errors = 0;
+    let mut warnings = 0;
+    let mut sources = Vec::new();
+    for path in files.iter() {
+        match fs::read_to_string(path) {
+            Ok(source) => sources.push((path.display().to_string(), source)),
+            Err(err) => {
+                eprintln!("Failed to read {}: {}", path.display(), err);
+                return Status::IO
+            }
+        }
+    }
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut source) {
+            eprintln!("Failed to read stdin: {}", err);
+            return Status::IO
+        }
+        sources.push(("<stdin>".to_string(), source));
+    }
+    for (name, source) in sources.iter() {
+        for (no, expr, checked) in check_source(source) {
+            match checked {
+                Err(err) => {
+                    eprintln!("{}", color::error(&diagnostics::render_file(name, no, expr, &err)));
+                    status.merge(Status::of(&err));
+                    errors += 1;
+                }
+                Ok(lints) if lint => for found in lints {
+                    eprintln!("{}", color::warning(&diagnostics::render_lint_file(name, no, expr, &found)));
+                    warnings += 1;
+                },
+                Ok(_) => {}
+            }
+        }
+    }
+    let plural = |n: usize, word: &str| if n == 1 { format!("{} {}", n, word) } else { format!("{} {}s", n, word) };
+    match (errors, warnings) {
+        (0, 0) => {}
+        (errors, 0) => eprintln!("{} in {}", plural(errors, "error"), plural(sources.len(), "file")),
+        (0, warnings) => eprintln!("{} in {}", plural(warnings, "warning"), plural(sources.len(), "file")),
+        (errors, warnings) => eprintln!("{} and {} in {}", plural(errors, "error"), plural(warnings, "warning"), plural(sources.len(), "file")),
+    }
+    status
+}
+
+/// Lints of a line that parses, or its error.
+type Checked = Result<Vec<Lint>, Error>;
+
+/// Line number, expression and either the lints or the error of
+/// the lines with an expression.
+fn check_source(source: &str) -> Vec<(usize, &str, Checked)> {
+    source.lines().enumerate()
+        .filter_map(|(i, line)| strip_comment(line).map(|expr| (i + 1, expr)))
+        .map(|(no, expr)| {
+            let checked = lexer::spanned(expr)
+                .and_then(parser::top_down_parser::parse)
+                .map(|ast| lint::lint(&ast));
+            (no, expr, checked)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wcal::error::{LexError, ParseError};
+
+    #[test]
+    fn test_check_source() {
+        // Errors of the calculation aren't checked
+        assert!(check_source("# rates\nx = 1/0\nunknown(1) * y\n").iter().all(|(_, _, checked)| checked.is_ok()));
+
+        let checked = check_source("1 +\n2\n# 1 $\n(3 $ 4)\n5 6");
+        let errors: Vec<_> = checked.iter()
+            .filter_map(|(no, expr, checked)| checked.as_ref().err().map(|err| (*no, *expr, err)))
+            .collect();
+        let lines: Vec<_> = errors.iter().map(|(no, _, _)| *no).collect();
+        assert_eq!(lines, [1, 4, 5]);
+        assert!(matches!(errors[0].2, Error::Parse(ParseError::UnexpectedEnd{..})));
+        assert!(matches!(errors[1].2, Error::Lex(LexError::InvalidCharacter{..})));
+        assert_eq!(errors[1].1, "(3 $ 4)");
+    }
+
+    #[test]
+    fn test_lint() {
+        let checked = check_source("x * 1\n# --y\n(a + b) * c\n--y");
+        let lints: Vec<_> = checked.iter()
+            .map(|(no, _, checked)| (*no, checked.as_ref().unwrap().len()))
+            .collect();
+        assert_eq!(lints, [(1, 1), (3, 0), (4, 1)]);
+        assert_eq!(checked[2].2, Ok(vec![Lint::DoubleNegation{op: wcal::lexer::Token::Minus, span: 0..3}]));
+    }
+}
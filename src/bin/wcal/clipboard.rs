@@ -0,0 +1,51 @@
+//! `--copy` and `:copy`, put the last result on the clipboard.
+//!
+//! The results are recorded as they are printed, the clipboard
+//! itself needs the `clipboard` feature.
+use std::sync::Mutex;
+
+/// Last successful result, formatted.
+static LAST: Mutex<Option<String>> = Mutex::new(None);
+
+/// Record the result as the last one.
+pub fn record(text: &str) {
+    *LAST.lock().unwrap_or_else(|err| err.into_inner()) = Some(text.to_string());
+}
+
+/// The last result, if any.
+pub fn last() -> Option<String> {
+    LAST.lock().unwrap_or_else(|err| err.into_inner()).clone()
+}
+
+/// Put the last result on the clipboard, return it.
+pub fn copy_last() -> Result<String, String> {
+    let text = last().ok_or_else(|| "No result to copy".to_string())?;
+    copy(&text)?;
+    Ok(text)
+}
+
+/// Put the text on the clipboard. On X11 and Wayland, a clipboard
+/// manager keeps it once wcal exits.
+#[cfg(feature = "clipboard")]
+fn copy(text: &str) -> Result<(), String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|err| format!("Failed to copy to the clipboard: {}", err))
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn copy(_: &str) -> Result<(), String> {
+    Err("Failed to copy to the clipboard: wcal is built without the clipboard feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        record("3");
+        record("0.5");
+        assert_eq!(last(), Some("0.5".to_string()));
+    }
+}
@@ -0,0 +1,146 @@
+//! ANSI colors of the terminal output.
+//!
+//! Colors are disabled by `--no-color`, by a non-empty `NO_COLOR`
+//! environment variable, or when stdout is not a terminal.
+use std::borrow::Cow;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use rustyline::completion::Completer;
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+
+use wcal::TokenCategory;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+const RESET: &str = "\x1b[0m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const RED_UNDERLINE: &str = "\x1b[31;4m";
+
+/// Decide whether to color, and print the warnings of the
+/// calculator in yellow.
+pub fn init(no_color: bool) {
+    let no_color = no_color || env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    ENABLED.store(!no_color && io::stdout().is_terminal(), Ordering::Relaxed);
+    if log::set_logger(&Logger).is_ok() {
+        log::set_max_level(LevelFilter::Warn);
+    }
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn paint(text: &str, style: &str) -> String {
+    if enabled() && !style.is_empty() {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn error(text: &str) -> String {
+    paint(text, RED)
+}
+
+pub fn warning(text: &str) -> String {
+    paint(text, YELLOW)
+}
+
+pub fn dim(text: &str) -> String {
+    paint(text, DIM)
+}
+
+pub fn prompt(text: &str) -> String {
+    paint(text, BOLD_BLUE)
+}
+
+fn style(category: TokenCategory) -> &'static str {
+    match category {
+        TokenCategory::Number => CYAN,
+        TokenCategory::Operator => MAGENTA,
+        TokenCategory::Paren => BOLD,
+        TokenCategory::Separator => "",
+        TokenCategory::Identifier => GREEN,
+        TokenCategory::Keyword => YELLOW,
+        TokenCategory::Str => RED,
+        TokenCategory::Error => RED_UNDERLINE,
+    }
+}
+
+/// Color the tokens of the expression.
+pub fn highlight(expr: &str) -> String {
+    if !enabled() {
+        return expr.to_string()
+    }
+    let mut colored = String::with_capacity(expr.len() * 2);
+    let mut end = 0;
+    for (span, category) in wcal::highlight(expr) {
+        colored.push_str(&expr[end..span.start]);
+        colored.push_str(&paint(&expr[span.clone()], style(category)));
+        end = span.end;
+    }
+    colored.push_str(&expr[end..]);
+    colored
+}
+
+/// Print the warnings logged by the calculator to stderr.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn && metadata.target() == "wcal"
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", warning(&format!("Warning: {}", record.args())));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Highlight the line being edited in the interactive mode.
+pub struct Helper;
+
+impl Highlighter for Helper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if enabled() {
+            Cow::Owned(highlight(line))
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, _default: bool) -> Cow<'b, str> {
+        Cow::Owned(self::prompt(prompt))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        enabled()
+    }
+}
+
+impl Completer for Helper {
+    type Candidate = String;
+}
+
+impl Hinter for Helper {
+    type Hint = String;
+}
+
+impl Validator for Helper {}
+
+impl rustyline::Helper for Helper {}
@@ -0,0 +1,167 @@
+//! User configuration, read from `~/.config/wcal/config.toml` or
+//! `--config`.
+//!
+//! ```toml
+//! mode = "f"          # "i", "f", "r", "b", "d" or "t"
+//! precision = 3
+//! base = 16           # 2, 8, 10 or 16
+//! prefix = true
+//! group = 4
+//! separator = "comma" # underscore, comma, period, space, apostrophe or locale
+//! color = false
+//! definitions = ["kb = 1024", "mb = kb * kb"]
+//! ```
+//!
+//! Every key is optional, the command line options take
+//! precedence.
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::format::Separator;
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mode: Option<Mode>,
+    pub precision: Option<usize>,
+    pub base: Option<Base>,
+    pub prefix: Option<bool>,
+    pub group: Option<u32>,
+    pub separator: Option<Separator>,
+    pub color: Option<bool>,
+    /// Expressions calculated before the interactive mode starts,
+    /// such as constants
+    pub definitions: Vec<String>,
+}
+
+/// Backend of the calculator, the type of the results.
+///
+/// A new backend is a variant here and an arm of `with_mode!`.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Integers, division truncates
+    #[serde(rename = "i", alias = "i128")]
+    #[value(name = "i128", alias = "i")]
+    Int,
+    /// Floating point numbers
+    #[serde(rename = "f", alias = "f64")]
+    #[value(name = "f64", alias = "f")]
+    Float,
+    /// Exact fractions, 7/2 is 7/2
+    #[serde(rename = "r", alias = "rational")]
+    #[value(name = "rational", alias = "r")]
+    Rational,
+    /// Integers that never overflow
+    #[serde(rename = "b", alias = "bigint")]
+    #[value(name = "bigint", alias = "b")]
+    Big,
+    /// Decimal numbers of 28 digits, 0.1*3 is 0.3
+    #[serde(rename = "d", alias = "decimal")]
+    #[value(name = "decimal", alias = "d")]
+    Decimal,
+    /// Durations such as 1h30m, and exact numbers
+    #[serde(rename = "t", alias = "duration")]
+    #[value(name = "duration", alias = "t")]
+    Duration,
+    /// Matrices such as [[1, 2], [3, 4]], and exact numbers
+    #[serde(rename = "m", alias = "matrix")]
+    #[value(name = "matrix", alias = "m")]
+    Matrix,
+}
+
+impl Mode {
+    /// Name of the backend, such as `i128`.
+    pub fn name(self) -> String {
+        self.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+    }
+
+    /// Backend of a name or its alias.
+    pub fn from_name(name: &str) -> Option<Mode> {
+        Mode::from_str(name, false).ok()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "u32")]
+pub struct Base(pub u32);
+
+impl TryFrom<u32> for Base {
+    type Error = String;
+
+    fn try_from(base: u32) -> Result<Self, Self::Error> {
+        match base {
+            2 | 8 | 10 | 16 => Ok(Base(base)),
+            _ => Err(format!("base must be 2, 8, 10 or 16, got {}", base)),
+        }
+    }
+}
+
+impl Config {
+    /// Read the file at `path`, or the default file if it exists.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let (path, source) = match path {
+            Some(path) => (path.to_path_buf(), fs::read_to_string(path)),
+            None => match default_path() {
+                Some(path) => {
+                    let source = fs::read_to_string(&path);
+                    if matches!(&source, Err(err) if err.kind() == io::ErrorKind::NotFound) {
+                        return Ok(Config::default())
+                    }
+                    (path, source)
+                }
+                None => return Ok(Config::default()),
+            },
+        };
+        let source = source.map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        toml::from_str(&source).map_err(|err| format!("Invalid config {}: {}", path.display(), err))
+    }
+}
+
+/// `$XDG_CONFIG_HOME/wcal/config.toml`, or under `~/.config`.
+fn default_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(dir.join("wcal").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = toml::from_str(r#"
+            mode = "f64"
+            precision = 3
+            base = 16
+            separator = "space"
+            definitions = ["x = 2"]
+        "#).unwrap();
+        assert_eq!(config, Config{
+            mode: Some(Mode::Float),
+            precision: Some(3),
+            base: Some(Base(16)),
+            separator: Some(Separator::Space),
+            definitions: vec!["x = 2".to_string()],
+            ..Config::default()
+        });
+
+        assert_eq!(toml::from_str::<Config>("").unwrap(), Config::default());
+        assert!(toml::from_str::<Config>("base = 3").is_err());
+        assert!(toml::from_str::<Config>("mode = \"x\"").is_err());
+        assert!(toml::from_str::<Config>("colour = true").is_err());
+    }
+
+    #[test]
+    fn test_load() {
+        assert!(Config::load(Some(Path::new("/nonexistent/config.toml"))).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+//! `wcal conv VALUE --to TARGET`, convert a value to another base
+//! or unit.
+//!
+//! The target is a base, `bin`, `oct`, `dec` or `hex`, and the value
+//! any `i128` expression:
+//! ```text
+//! $ wcal conv 0xFF --to bin
+//! 0b11111111
+//! ```
+//! Or the target is a unit, and the value a number followed by a
+//! unit of the same kind:
+//! ```text
+//! $ wcal conv 3.5km --to mi
+//! 2.174799172830669 mi
+//! ```
+use wcal::{diagnostics, Calculator};
+
+use crate::{color, Status};
+use crate::format::{Format, Value};
+
+/// Kind of quantity, only units of the same kind convert.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Dimension {
+    Length,
+    Mass,
+    Time,
+    Volume,
+    Data,
+}
+
+/// Unit with its size in the base unit of its dimension.
+struct Unit {
+    symbol: &'static str,
+    dimension: Dimension,
+    factor: f64,
+}
+
+const fn unit(symbol: &'static str, dimension: Dimension, factor: f64) -> Unit {
+    Unit{symbol, dimension, factor}
+}
+
+/// Known units, in meters, grams, seconds, liters and bytes.
+const UNITS: &[Unit] = &[
+    unit("mm", Dimension::Length, 0.001),
+    unit("cm", Dimension::Length, 0.01),
+    unit("m", Dimension::Length, 1.0),
+    unit("km", Dimension::Length, 1000.0),
+    unit("in", Dimension::Length, 0.0254),
+    unit("ft", Dimension::Length, 0.3048),
+    unit("yd", Dimension::Length, 0.9144),
+    unit("mi", Dimension::Length, 1609.344),
+    unit("nmi", Dimension::Length, 1852.0),
+    unit("mg", Dimension::Mass, 0.001),
+    unit("g", Dimension::Mass, 1.0),
+    unit("kg", Dimension::Mass, 1000.0),
+    unit("t", Dimension::Mass, 1_000_000.0),
+    unit("oz", Dimension::Mass, 28.349523125),
+    unit("lb", Dimension::Mass, 453.59237),
+    unit("ms", Dimension::Time, 0.001),
+    unit("s", Dimension::Time, 1.0),
+    unit("min", Dimension::Time, 60.0),
+    unit("h", Dimension::Time, 3600.0),
+    unit("d", Dimension::Time, 86400.0),
+    unit("ml", Dimension::Volume, 0.001),
+    unit("l", Dimension::Volume, 1.0),
+    unit("gal", Dimension::Volume, 3.785411784),
+    unit("B", Dimension::Data, 1.0),
+    unit("KB", Dimension::Data, 1e3),
+    unit("MB", Dimension::Data, 1e6),
+    unit("GB", Dimension::Data, 1e9),
+    unit("KiB", Dimension::Data, 1024.0),
+    unit("MiB", Dimension::Data, 1024.0 * 1024.0),
+    unit("GiB", Dimension::Data, 1024.0 * 1024.0 * 1024.0),
+];
+
+/// Base of the name of a target, such as `hex`.
+fn base(name: &str) -> Option<u32> {
+    match name {
+        "bin" | "2" => Some(2),
+        "oct" | "8" => Some(8),
+        "dec" | "10" => Some(10),
+        "hex" | "16" => Some(16),
+        _ => None,
+    }
+}
+
+fn find_unit(symbol: &str) -> Result<&'static Unit, String> {
+    UNITS.iter().find(|unit| unit.symbol == symbol)
+        .ok_or_else(|| format!("Unknown unit {:?}", symbol))
+}
+
+/// Convert the value and print it.
+pub fn conv(value: &str, to: &str, format: &Format) -> Status {
+    let result = match base(to) {
+        Some(base) => {
+            let format = Format{base, prefix: base != 10, ..*format};
+            match Calculator::<i128>::new().warnings_as_errors(format.warnings_as_errors).eval(value) {
+                Ok(res) => Ok(res.format(&format)),
+                Err(err) => {
+                    eprintln!("{}", color::error(&diagnostics::render(value, &err)));
+                    return Status::of(&err)
+                }
+            }
+        }
+        None => convert_unit(value, to).map(|res| format!("{} {}", res.format(format), to)),
+    };
+    match result {
+        Ok(text) => {
+            println!("{}", text);
+            Status::default()
+        }
+        Err(err) => {
+            eprintln!("{}", color::error(&format!("Error: {}", err)));
+            Status::USAGE
+        }
+    }
+}
+
+/// Quantity of a value such as `3.5km` or `3.5 km` in the unit.
+fn convert_unit(value: &str, to: &str) -> Result<f64, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '_' || c == '-'))
+        .ok_or_else(|| format!("Expect a unit after {}, such as {}km", value, value))?;
+    let (number, symbol) = value.split_at(split);
+    let number: f64 = number.replace('_', "").parse()
+        .map_err(|_| format!("Invalid number {:?}", number))?;
+    let from = find_unit(symbol.trim())?;
+    let to = find_unit(to)?;
+    if from.dimension != to.dimension {
+        return Err(format!("Can't convert {} to {}", from.symbol, to.symbol))
+    }
+    Ok(number * from.factor / to.factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_unit() {
+        assert!((convert_unit("3.5km", "mi").unwrap() - 2.174799).abs() < 1e-6);
+        assert_eq!(convert_unit("1 km", "m"), Ok(1000.0));
+        assert_eq!(convert_unit("1_500g", "kg"), Ok(1.5));
+        assert_eq!(convert_unit("2GiB", "MiB"), Ok(2048.0));
+        assert_eq!(convert_unit("3km", "kg"), Err("Can't convert km to kg".to_string()));
+        assert_eq!(convert_unit("3", "km"), Err("Expect a unit after 3, such as 3km".to_string()));
+        assert_eq!(convert_unit("3parsec", "km"), Err("Unknown unit \"parsec\"".to_string()));
+        assert!(convert_unit("1.2.3m", "km").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! `wcal expand VALUE --ops N`, a random expression of `N`
+//! operators calculated to the value, for exercises and the corpora
+//! of fuzzers.
+//!
+//! ```text
+//! $ wcal expand 42 --ops 6 --seed 7
+//! 84 / (18 / ((7 - 4) * (3 * (8 + -7))))
+//! ```
+//! The tree is built of `+ - * /` and integers, each `/` dividing
+//! exactly, and checked by calculating its text with the i128
+//! backend. The same seed gives the same expressions.
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use wcal::{diagnostics, lexer, parser, pretty, FromAST};
+use wcal::lexer::Token;
+use wcal::parser::ast::{BinOp, Expr, Neg, Number, AST};
+use wcal::pretty::Style;
+
+use crate::{color, Status};
+
+/// Factors of the products and the quotients.
+const FACTORS: std::ops::RangeInclusive<i128> = 2..=12;
+
+/// splitmix64, the expressions only need to look random.
+pub struct Rng(u64);
+
+impl Rng {
+    /// Generator of the seed, of the clock without one.
+    pub fn new(seed: Option<u64>) -> Self {
+        let clock = || SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+        Rng(seed.unwrap_or_else(clock))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Number of `0..n`, `n` isn't 0.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Number of `1..=n`, `n` is positive.
+    fn upto(&mut self, n: i128) -> i128 {
+        (self.next() as i128) % n + 1
+    }
+}
+
+fn literal(value: i128) -> Expr {
+    if value < 0 {
+        Neg::new(Number::new(value.unsigned_abs()))
+    } else {
+        Number::new(value as u128)
+    }
+}
+
+/// Operands of the operator for the value, `None` if they would
+/// overflow or the value has no factor.
+fn operands(op: &Token, value: i128, rng: &mut Rng) -> Option<(i128, i128)> {
+    // The terms stay about the size of the value
+    let bound = value.unsigned_abs().clamp(10, 1000) as i128;
+    match op {
+        Token::Plus => {
+            let lhs = rng.upto(bound);
+            Some((lhs, value.checked_sub(lhs)?))
+        }
+        Token::Minus => {
+            let rhs = rng.upto(bound);
+            Some((value.checked_add(rhs)?, rhs))
+        }
+        Token::Times => {
+            let factors: Vec<i128> = FACTORS.filter(|factor| value % factor == 0).collect();
+            let factor = *factors.get(rng.below(factors.len().max(1)))?;
+            Some((factor, value / factor))
+        }
+        _ => {
+            let factor = *FACTORS.start() + rng.below(FACTORS.count()) as i128;
+            Some((value.checked_mul(factor)?, factor))
+        }
+    }
+}
+
+/// Random tree of `ops` operators calculated to the value.
+pub fn expand(value: i128, ops: usize, rng: &mut Rng) -> Expr {
+    if ops == 0 {
+        return literal(value)
+    }
+    let op = [Token::Plus, Token::Minus, Token::Times, Token::Division][rng.below(4)].clone();
+    // Halves never overflow
+    let (op, (lhs, rhs)) = match operands(&op, value, rng) {
+        Some(operands) => (op, operands),
+        None => (Token::Plus, (value / 2, value - value / 2)),
+    };
+    let left = rng.below(ops);
+    BinOp::new(expand(lhs, left, rng), expand(rhs, ops - 1 - left, rng), op)
+}
+
+/// Print `count` expressions of `ops` operators calculated to the
+/// value of `value`, an integer expression.
+pub fn run(value: &str, ops: usize, count: u64, seed: Option<u64>) -> Status {
+    let target = match lexer::spanned(value).and_then(parser::top_down_parser::parse).and_then(i128::from_ast) {
+        Ok(target) => target,
+        Err(err) => {
+            eprintln!("{}", color::error(&diagnostics::render(value, &err)));
+            return Status::of(&err)
+        }
+    };
+    let mut rng = Rng::new(seed);
+    for _ in 0..count {
+        let text = pretty::format_ast(&AST{root: expand(target, ops, &mut rng)}, "", &Style::default());
+        // A tree too deep for the parser fails here
+        match lexer::spanned(&text).and_then(parser::top_down_parser::parse).and_then(i128::from_ast) {
+            Ok(res) if res == target => println!("{}", text),
+            Ok(res) => panic!("{} is {}, not {}", text, res, target),
+            Err(err) => {
+                eprintln!("{}", color::error(&diagnostics::render(&text, &err)));
+                return Status::of(&err)
+            }
+        }
+    }
+    Status::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(expr: &Expr) -> usize {
+        match expr {
+            Expr::BinOp(bin) => 1 + ops(&bin.lhs) + ops(&bin.rhs),
+            Expr::Neg(neg) => ops(&neg.expr),
+            _ => 0,
+        }
+    }
+
+    fn calculate(text: &str) -> i128 {
+        lexer::spanned(text).and_then(parser::top_down_parser::parse).and_then(i128::from_ast).unwrap()
+    }
+
+    #[test]
+    fn test_expand() {
+        for seed in 0..50 {
+            let mut rng = Rng::new(Some(seed));
+            for (value, n) in [(42, 50), (0, 7), (-1, 3), (97, 20), (1, 0), (i128::MAX, 30), (i128::MIN, 30)] {
+                let expr = expand(value, n, &mut rng);
+                assert_eq!(ops(&expr), n, "seed: {}", seed);
+                let text = pretty::format_ast(&AST{root: expr}, "", &Style::default());
+                assert_eq!(calculate(&text), value, "text: {}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seed() {
+        let text = |seed| pretty::format_ast(&AST{root: expand(42, 10, &mut Rng::new(Some(seed)))}, "", &Style::default());
+        assert_eq!(text(1), text(1));
+        assert_ne!(text(1), text(2));
+        // Exact, so no warning of a cast
+        let tokens = lexer::spanned(&text(3)).unwrap();
+        let functions = wcal::FunctionRegistry::new();
+        let mut ctx = wcal::generator::Context::new(&functions).warnings_as_errors(true);
+        assert_eq!(i128::from_ast_in(parser::top_down_parser::parse(tokens).unwrap(), &mut ctx), Ok(42));
+    }
+}
@@ -0,0 +1,136 @@
+//! `wcal fmt`, rewrite expressions in their canonical text.
+//!
+//! ```text
+//! $ wcal fmt "x=((1+2))*max( 3,4 )"
+//! x = (1 + 2) * max(3, 4)
+//! ```
+//! Lines of the files keep their comments, and a file with an
+//! error is left as it is.
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+use wcal::{diagnostics, Error};
+use wcal::pretty::{self, Style};
+
+use crate::{color, Status};
+
+/// Format the expressions, then the files, or stdin without them.
+pub fn fmt(exprs: &[String], files: &[PathBuf], in_place: bool, style: &Style) -> Status {
+    let mut status = Status::default();
+    for expr in exprs.iter() {
+        match pretty::format(expr, style) {
+            Ok(text) => println!("{}", text),
+            Err(err) => {
+                eprintln!("{}", color::error(&diagnostics::render(expr, &err)));
+                status.merge(Status::of(&err));
+            }
+        }
+    }
+    for path in files.iter() {
+        let name = path.display().to_string();
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Failed to read {}: {}", name, err);
+                return Status::IO
+            }
+        };
+        let text = match format_source(&name, &source, style) {
+            Ok(text) => text,
+            Err((err, diagnostics)) => {
+                for diagnostic in diagnostics {
+                    eprintln!("{}", color::error(&diagnostic));
+                }
+                status.merge(err);
+                continue
+            }
+        };
+        if !in_place {
+            print!("{}", text);
+        } else if text != source {
+            if let Err(err) = fs::write(path, text) {
+                eprintln!("Failed to write {}: {}", name, err);
+                return Status::IO
+            }
+        }
+    }
+    if exprs.is_empty() && files.is_empty() {
+        let mut source = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut source) {
+            eprintln!("Failed to read stdin: {}", err);
+            return Status::IO
+        }
+        match format_source("<stdin>", &source, style) {
+            Ok(text) => print!("{}", text),
+            Err((err, diagnostics)) => {
+                for diagnostic in diagnostics {
+                    eprintln!("{}", color::error(&diagnostic));
+                }
+                status.merge(err);
+            }
+        }
+    }
+    status
+}
+
+/// Canonical text of the lines, or the status and diagnostics of
+/// the lines that can't be parsed.
+fn format_source(name: &str, source: &str, style: &Style) -> Result<String, (Status, Vec<String>)> {
+    let mut text = String::new();
+    let mut status = Status::default();
+    let mut diagnostics = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        match format_line(line, style) {
+            Ok(line) => text.push_str(&line),
+            Err((expr, err)) => {
+                diagnostics.push(diagnostics::render_file(name, i + 1, expr, &err));
+                status.merge(Status::of(&err));
+            }
+        }
+        text.push('\n');
+    }
+    if !source.ends_with('\n') {
+        text.pop();
+    }
+    if diagnostics.is_empty() {
+        Ok(text)
+    } else {
+        Err((status, diagnostics))
+    }
+}
+
+/// Canonical text of the line with its comment, or the expression
+/// and its error.
+fn format_line<'a>(line: &'a str, style: &Style) -> Result<String, (&'a str, Error)> {
+    let (expr, comment) = match line.find('#') {
+        Some(i) => (&line[..i], Some(&line[i..])),
+        None => (line, None),
+    };
+    if expr.trim().is_empty() {
+        return Ok(line.trim().to_string())
+    }
+    let text = pretty::format(expr, style).map_err(|err| (expr, err))?;
+    Ok(match comment {
+        Some(comment) => format!("{} {}", text, comment.trim_end()),
+        None => text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_source() {
+        let style = Style{lowercase_hex: true};
+        let source = "# rates\n  x=1200*(0xAB)   # monthly\n\nx/12\n";
+        assert_eq!(format_source("a.calc", source, &style).unwrap(), "# rates\nx = 1200 * 0xab # monthly\n\nx / 12\n");
+        assert_eq!(format_source("a.calc", "1+1", &style).unwrap(), "1 + 1");
+
+        let (status, diagnostics) = format_source("a.calc", "1+1\n2*(3\n1 $ 1", &style).unwrap_err();
+        assert_eq!(status.0, 4);
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics[0].contains("a.calc:2:"));
+    }
+}
@@ -0,0 +1,513 @@
+//! Text of the results, as chosen by the command line options.
+use std::convert::TryFrom;
+use std::env;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value as Json;
+
+use wcal::{AngleUnit, BigInt, Decimal, DurationFormat, FromAST, Matrix, Rational, Rounding, Time};
+use wcal::format::{Notation, NumberFormat};
+use wcal::lexer::Token;
+
+use crate::Args;
+use crate::config::Config;
+use crate::output::Output;
+
+/// Separator of the groups of digits.
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Separator {
+    /// `1_234_567`, which can be read back
+    Underscore,
+    /// `1,234,567`
+    Comma,
+    /// `1.234.567`, with a decimal comma
+    Period,
+    /// `1 234 567`
+    Space,
+    /// `1'234'567`
+    Apostrophe,
+    /// The separator of the language of LC_ALL, LC_NUMERIC or LANG
+    Locale,
+}
+
+impl Separator {
+    pub fn char(self) -> char {
+        match self {
+            Separator::Underscore => '_',
+            Separator::Comma => ',',
+            Separator::Period => '.',
+            Separator::Space => ' ',
+            Separator::Apostrophe => '\'',
+            Separator::Locale => {
+                let locale = ["LC_ALL", "LC_NUMERIC", "LANG"].iter()
+                    .filter_map(|var| env::var(var).ok())
+                    .find(|locale| !locale.is_empty())
+                    .unwrap_or_default();
+                Separator::of_locale(&locale).char()
+            }
+        }
+    }
+
+    /// Separator of a locale such as `de_DE.UTF-8`.
+    fn of_locale(locale: &str) -> Separator {
+        let language = locale.split(['_', '.', '@']).next().unwrap_or_default();
+        match language {
+            _ if locale.starts_with("de_CH") || locale.starts_with("it_CH") => Separator::Apostrophe,
+            "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el" => Separator::Period,
+            "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "uk" | "hu" => Separator::Space,
+            "" | "C" | "POSIX" => Separator::Underscore,
+            _ => Separator::Comma,
+        }
+    }
+}
+
+/// How to print the results.
+#[derive(Clone, Copy)]
+pub struct Format {
+    pub precision: Option<usize>,
+    pub significant: Option<usize>,
+    pub notation: Notation,
+    /// 2, 8, 10 or 16
+    pub base: u32,
+    pub prefix: bool,
+    pub group: Option<u32>,
+    /// Separator of the groups
+    pub separator: char,
+    pub output: Output,
+    /// Print the durations of the stages
+    pub time: bool,
+    /// Follow fractions by their decimal approximation
+    pub decimal: bool,
+    /// Fail the expressions that raise a warning
+    pub warnings_as_errors: bool,
+    /// Unit of the angles of the trigonometric functions
+    pub angle_unit: AngleUnit,
+    /// Spelling of the results of the duration backend
+    pub duration_format: DurationFormat,
+    /// Rounding of the inexact integer roots
+    pub rounding: Rounding,
+}
+
+impl Default for Format {
+    /// Text of the numbers in base 10, without the options.
+    fn default() -> Self {
+        Format{
+            precision: None,
+            significant: None,
+            notation: Notation::Auto,
+            base: 10,
+            prefix: false,
+            group: None,
+            separator: '_',
+            output: Output::Text,
+            time: false,
+            decimal: false,
+            warnings_as_errors: false,
+            angle_unit: AngleUnit::Radian,
+            duration_format: DurationFormat::Units,
+            rounding: Rounding::Floor,
+        }
+    }
+}
+
+impl Format {
+    /// Format of the options, or of the config without them.
+    pub fn new(args: &Args, config: &Config) -> Self {
+        let base = if args.hex {
+            16
+        } else if args.oct {
+            8
+        } else if args.bin {
+            2
+        } else {
+            config.base.map_or(10, |base| base.0)
+        };
+        let notation = if args.fixed {
+            Notation::Fixed
+        } else if args.scientific {
+            Notation::Scientific
+        } else if args.engineering {
+            Notation::Engineering
+        } else if args.si {
+            Notation::Si
+        } else {
+            Notation::Auto
+        };
+        let separator = args.separator.or(config.separator);
+        Format{
+            precision: args.precision.or(config.precision),
+            significant: args.significant.map(|digits| digits as usize),
+            notation,
+            base,
+            prefix: args.prefix || config.prefix == Some(true),
+            // A separator alone groups by thousands
+            group: args.group.or(config.group).or_else(|| separator.map(|_| 3)),
+            separator: separator.map_or('_', Separator::char),
+            output: if args.json { Output::Json } else { args.format },
+            time: args.time,
+            decimal: args.decimal,
+            warnings_as_errors: args.warnings_as_errors,
+            angle_unit: if args.degrees { AngleUnit::Degree } else { AngleUnit::Radian },
+            duration_format: if args.clock { DurationFormat::Clock } else { DurationFormat::Units },
+            rounding: args.rounding.unwrap_or_default(),
+        }
+    }
+
+    /// The numbers in the base, the groups and the notation.
+    pub fn number(&self) -> NumberFormat {
+        NumberFormat{
+            base: self.base,
+            prefix: self.prefix,
+            group: self.group,
+            separator: self.separator,
+            notation: self.notation,
+            precision: self.precision,
+            significant: self.significant,
+        }
+    }
+}
+
+/// Integer as a JSON number, or as a string if it doesn't fit in
+/// 64 bits, which most JSON readers can't represent exactly.
+fn int_json<N: ToString>(n: &N, small: Option<i64>) -> Json {
+    small.map_or_else(|| Json::String(n.to_string()), Json::from)
+}
+
+/// Result of a mode of the calculator.
+pub trait Value: FromAST + Clone + Serialize + DeserializeOwned {
+    /// Prompt of the mode, such as `i> `
+    const PROMPT: &'static str;
+    /// Name of the type in the JSON output
+    const TYPE: &'static str;
+
+    fn format(&self, format: &Format) -> String;
+
+    /// Value in the JSON output.
+    fn to_json(&self) -> Json;
+
+    /// Value of a JSON input, such as the `value` of JSON-RPC.
+    fn from_json(value: &Json) -> Result<Self, String>;
+
+    /// Value of a number in a text input, such as a field of
+    /// `wcal map`.
+    fn parse(text: &str) -> Result<Self, String>;
+}
+
+/// Number of the text, the error names it.
+fn parse_number<T: FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("invalid number {:?}", text))
+}
+
+impl Value for i128 {
+    const PROMPT: &'static str = "i> ";
+    const TYPE: &'static str = "i128";
+
+    fn format(&self, format: &Format) -> String {
+        format.number().int(*self)
+    }
+
+    fn to_json(&self) -> Json {
+        int_json(self, i64::try_from(*self).ok())
+    }
+
+    fn from_json(value: &Json) -> Result<Self, String> {
+        i128::deserialize(value).map_err(|err| err.to_string())
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        parse_number(text)
+    }
+}
+
+impl Value for f64 {
+    const PROMPT: &'static str = "f> ";
+    const TYPE: &'static str = "f64";
+
+    fn format(&self, format: &Format) -> String {
+        format.number().float(*self)
+    }
+
+    fn to_json(&self) -> Json {
+        Json::from(*self)
+    }
+
+    fn from_json(value: &Json) -> Result<Self, String> {
+        f64::deserialize(value).map_err(|err| err.to_string())
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        parse_number(text)
+    }
+}
+
+impl Value for BigInt {
+    const PROMPT: &'static str = "b> ";
+    const TYPE: &'static str = "bigint";
+
+    fn format(&self, format: &Format) -> String {
+        format.number().bigint(self)
+    }
+
+    fn to_json(&self) -> Json {
+        int_json(self, i64::try_from(self).ok())
+    }
+
+    /// A JSON number, or a string of decimal digits for those past
+    /// 64 bits.
+    fn from_json(value: &Json) -> Result<Self, String> {
+        match value {
+            Json::String(digits) => digits.parse().map_err(|_| format!("invalid integer {:?}", digits)),
+            value => i128::from_json(value).map(BigInt::from),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        parse_number(text)
+    }
+}
+
+impl Value for Rational {
+    const PROMPT: &'static str = "r> ";
+    const TYPE: &'static str = "rational";
+
+    /// `num/den` in the base of the integers, such as `11/6 ≈ 1.833`
+    /// with the decimal.
+    fn format(&self, format: &Format) -> String {
+        let number = format.number();
+        let fraction = number.rational(*self);
+        if format.decimal && !self.is_integer() {
+            format!("{} ≈ {}", fraction, number.float(self.to_f64()))
+        } else {
+            fraction
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        serde_json::json!({"num": self.numer().to_json(), "den": self.denom().to_json()})
+    }
+
+    fn from_json(value: &Json) -> Result<Self, String> {
+        Rational::deserialize(value).map_err(|err| err.to_string())
+    }
+
+    /// A fraction such as `7/2`, or an exact decimal such as `3.5`.
+    fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid number {:?}", text);
+        let (num, den) = match (text.split_once('/'), text.split_once('.')) {
+            (Some((num, den)), _) => (parse_number(num)?, parse_number(den)?),
+            (None, Some((int, frac))) if frac.bytes().all(|b| b.is_ascii_digit()) => {
+                let den = 10i128.checked_pow(frac.len() as u32).ok_or_else(invalid)?;
+                (parse_number::<i128>(&format!("{}{}", int, frac))?, den)
+            }
+            (None, _) => (parse_number(text)?, 1),
+        };
+        Rational::new(num, den).ok_or_else(invalid)
+    }
+}
+
+impl Value for Time {
+    const PROMPT: &'static str = "t> ";
+    const TYPE: &'static str = "duration";
+
+    /// A duration such as `1h30m`, or `01:30:00` with `--clock`, a
+    /// date, or a number as a rational.
+    fn format(&self, format: &Format) -> String {
+        match self {
+            Time::Number(value) => value.format(format),
+            Time::Duration(_) | Time::Date(_) => Time::format(self, format.duration_format),
+        }
+    }
+
+    /// A number as a rational, `{"secs": rational}`, or
+    /// `{"date": "2024-05-01"}`.
+    fn to_json(&self) -> Json {
+        match self {
+            Time::Number(value) => value.to_json(),
+            Time::Duration(secs) => serde_json::json!({"secs": secs.to_json()}),
+            Time::Date(_) => serde_json::json!({"date": self.to_string()}),
+        }
+    }
+
+    fn from_json(value: &Json) -> Result<Self, String> {
+        Time::deserialize(value).map_err(|err| err.to_string())
+    }
+
+    /// A duration literal such as `1h30m`, or a rational.
+    fn parse(text: &str) -> Result<Self, String> {
+        match wcal::lexer::lexer(text).as_deref() {
+            Ok([Token::Duration(secs)]) => i128::try_from(*secs)
+                .map(|secs| Time::Duration(Rational::from(secs)))
+                .map_err(|_| format!("invalid duration {:?}", text)),
+            _ => Rational::parse(text).map(Time::Number),
+        }
+    }
+}
+
+impl Value for Matrix {
+    const PROMPT: &'static str = "m> ";
+    const TYPE: &'static str = "matrix";
+
+    /// A number as a rational, `[1, 2]` for a vector and
+    /// `[[1, 2], [3, 4]]` for the other matrices, each item as a
+    /// rational.
+    fn format(&self, format: &Format) -> String {
+        let row = |row: &[Rational]| format!("[{}]", row.iter().map(|item| item.format(format)).collect::<Vec<_>>().join(", "));
+        match self {
+            Matrix::Number(value) => value.format(format),
+            Matrix::Rows(rows) if rows.len() == 1 => row(&rows[0]),
+            Matrix::Rows(rows) => format!("[{}]", rows.iter().map(|items| row(items)).collect::<Vec<_>>().join(", ")),
+        }
+    }
+
+    /// A number as a rational, or `{"rows": [[rational]]}`.
+    fn to_json(&self) -> Json {
+        match self {
+            Matrix::Number(value) => value.to_json(),
+            Matrix::Rows(rows) => {
+                let rows: Vec<Vec<Json>> = rows.iter().map(|row| row.iter().map(Value::to_json).collect()).collect();
+                serde_json::json!({"rows": rows})
+            }
+        }
+    }
+
+    fn from_json(value: &Json) -> Result<Self, String> {
+        Rational::from_json(value).map(Matrix::Number)
+            .or_else(|_| Matrix::deserialize(value).map_err(|err| err.to_string()))
+    }
+
+    /// A number as a rational.
+    fn parse(text: &str) -> Result<Self, String> {
+        Rational::parse(text).map(Matrix::Number)
+    }
+}
+
+impl Value for Decimal {
+    const PROMPT: &'static str = "d> ";
+    const TYPE: &'static str = "decimal";
+
+    fn format(&self, format: &Format) -> String {
+        format.number().decimal(*self)
+    }
+
+    /// A string, which keeps all the digits.
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+
+    /// A string of digits, or a JSON number.
+    fn from_json(value: &Json) -> Result<Self, String> {
+        let text = match value {
+            Json::String(text) => text.clone(),
+            Json::Number(number) => number.to_string(),
+            value => return Err(format!("invalid decimal {}", value)),
+        };
+        text.parse().map_err(|_| format!("invalid decimal {:?}", text))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        parse_number(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_int() {
+        assert_eq!(255i128.format(&Format{base: 16, ..Format::default()}), "ff");
+        assert_eq!((-255i128).format(&Format{base: 16, prefix: true, ..Format::default()}), "-0xff");
+        assert_eq!(0x1234i128.format(&Format{base: 2, prefix: true, group: Some(4), ..Format::default()}), "0b1_0010_0011_0100");
+        assert_eq!(1234567i128.format(&Format{group: Some(3), ..Format::default()}), "1_234_567");
+        assert_eq!(i128::MIN.format(&Format{base: 8, ..Format::default()}), format!("-{:o}", i128::MIN.unsigned_abs()));
+    }
+
+    #[test]
+    fn test_big() {
+        let big = BigInt::from(u128::MAX) * BigInt::from(16);
+        assert_eq!(big.format(&Format::default()), "5444517870735015415413993718908291383280");
+        assert_eq!((-big.clone()).format(&Format{base: 16, prefix: true, group: Some(8), ..Format::default()}), "-0xf_ffffffff_ffffffff_ffffffff_fffffff0");
+        assert_eq!(big.to_json(), Json::from("5444517870735015415413993718908291383280"));
+        assert_eq!(BigInt::from(-3).to_json(), Json::from(-3));
+        assert_eq!(i128::MAX.to_json(), Json::from(i128::MAX.to_string()));
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(0.25f64.format(&Format::default()), "0.25");
+        assert_eq!(0.25f64.format(&Format{precision: Some(1), ..Format::default()}), "0.2");
+        assert_eq!(0.25f64.format(&Format{notation: Notation::Fixed, ..Format::default()}), "0.250000");
+        assert_eq!(1500f64.format(&Format{notation: Notation::Scientific, ..Format::default()}), "1.5e3");
+        assert_eq!(1500f64.format(&Format{notation: Notation::Scientific, precision: Some(2), ..Format::default()}), "1.50e3");
+        assert_eq!(12500f64.format(&Format{notation: Notation::Si, ..Format::default()}), "12.5k");
+    }
+
+    #[test]
+    fn test_rational() {
+        let r = |num, den| Rational::new(num, den).unwrap();
+        assert_eq!(r(11, 6).format(&Format::default()), "11/6");
+        assert_eq!(r(-4, 2).format(&Format::default()), "-2");
+        assert_eq!(r(11, 6).format(&Format{decimal: true, precision: Some(3), ..Format::default()}), "11/6 ≈ 1.833");
+        assert_eq!(r(1, 16).format(&Format{base: 16, prefix: true, ..Format::default()}), "0x1/0x10");
+        assert_eq!(Rational::parse("7/2"), Ok(r(7, 2)));
+        assert_eq!(Rational::parse("-3.25"), Ok(r(-13, 4)));
+        assert_eq!(Rational::parse("12"), Ok(r(12, 1)));
+        assert!(Rational::parse("1/0").is_err());
+        assert!(Rational::parse("1.2.3").is_err());
+    }
+
+    #[test]
+    fn test_duration() {
+        let duration = Time::Duration(Rational::from(5400));
+        assert_eq!(Value::format(&duration, &Format::default()), "1h30m");
+        assert_eq!(Value::format(&duration, &Format{duration_format: DurationFormat::Clock, ..Format::default()}), "01:30:00");
+        assert_eq!(Value::format(&Time::Number(Rational::new(3, 2).unwrap()), &Format::default()), "3/2");
+        assert_eq!(duration.to_json(), serde_json::json!({"secs": {"num": 5400, "den": 1}}));
+        assert_eq!(Time::parse("1h30m"), Ok(duration));
+        assert_eq!(Time::parse("7/2"), Ok(Time::Number(Rational::new(7, 2).unwrap())));
+        assert!(Time::parse("1h30").is_err());
+        assert_eq!(Time::Date(19844).to_json(), serde_json::json!({"date": "2024-05-01"}));
+    }
+
+    #[test]
+    fn test_matrix() {
+        let r = |num, den| Rational::new(num, den).unwrap();
+        let matrix = Matrix::Rows(vec![vec![r(1, 2), r(255, 1)], vec![r(0, 1), r(-1, 1)]]);
+        assert_eq!(Value::format(&matrix, &Format::default()), "[[1/2, 255], [0, -1]]");
+        assert_eq!(Value::format(&Matrix::Rows(vec![vec![r(255, 1)]]), &Format{base: 16, ..Format::default()}), "[ff]");
+        assert_eq!(Matrix::from_json(&matrix.to_json()), Ok(matrix));
+        assert_eq!(Matrix::from_json(&r(7, 2).to_json()), Ok(Matrix::Number(r(7, 2))));
+        assert_eq!(Matrix::parse("-3.25"), Ok(Matrix::Number(r(-13, 4))));
+    }
+
+    #[test]
+    fn test_decimal() {
+        let d = |text: &str| text.parse::<Decimal>().unwrap();
+        assert_eq!(d("0.30").format(&Format::default()), "0.30");
+        assert_eq!(d("1234.5").format(&Format{precision: Some(2), group: Some(3), ..Format::default()}), "1_234.50");
+        assert_eq!(d("0.25").format(&Format{notation: Notation::Fixed, ..Format::default()}), "0.250000");
+        assert_eq!(Decimal::from_json(&Json::from("7.25")), Ok(d("7.25")));
+        assert_eq!(Decimal::from_json(&Json::from(7)), Ok(d("7")));
+        assert!(Decimal::from_json(&Json::Null).is_err());
+    }
+
+    #[test]
+    fn test_separator() {
+        let format = Format{group: Some(3), separator: '.', ..Format::default()};
+        assert_eq!(1234567i128.format(&format), "1.234.567");
+        assert_eq!((-1234567.5f64).format(&format), "-1.234.567,5");
+        assert_eq!(1234.5f64.format(&Format{separator: ',', ..format}), "1,234.5");
+        assert_eq!(f64::NEG_INFINITY.format(&format), "-inf");
+        assert_eq!(1234.5f64.format(&Format{notation: Notation::Scientific, ..format}), "1.2345e3");
+
+        assert_eq!(Separator::of_locale("de_DE.UTF-8"), Separator::Period);
+        assert_eq!(Separator::of_locale("de_CH.UTF-8"), Separator::Apostrophe);
+        assert_eq!(Separator::of_locale("fr_FR"), Separator::Space);
+        assert_eq!(Separator::of_locale("en_US.UTF-8"), Separator::Comma);
+        assert_eq!(Separator::of_locale("C"), Separator::Underscore);
+    }
+}
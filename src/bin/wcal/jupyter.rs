@@ -0,0 +1,486 @@
+//! `wcal --jupyter-kernel FILE`, a Jupyter kernel, only with the
+//! `jupyter` feature.
+//!
+//! Each cell is calculated line by line in the session of the
+//! kernel, so the variables, `ans` and `$N` are kept between the
+//! cells. The result of the last line is the result of the cell, as
+//! text and as the JSON object of `--json`. The first error stops
+//! the cell.
+//!
+//! Jupyter starts the kernel with the connection file of its
+//! kernelspec, `kernel.json`:
+//! ```json
+//! {"argv": ["wcal", "--jupyter-kernel", "{connection_file}"], "display_name": "wcal", "language": "wcal"}
+//! ```
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use sha2::Sha256;
+use zeromq::{PubSocket, RepSocket, RouterSocket, Socket, SocketRecv, SocketSend, ZmqMessage};
+
+use wcal::{diagnostics, BigInt, Decimal, Error, Matrix, Rational, Session, Time};
+
+use crate::{output, session, strip_comment, Status};
+use crate::config::Mode;
+use crate::format::{Format, Value};
+
+/// Version of the messaging protocol.
+const PROTOCOL_VERSION: &str = "5.3";
+
+/// Separates the identities from the rest of a message.
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// Connection file written by Jupyter.
+#[derive(Debug, Deserialize)]
+struct Connection {
+    transport: String,
+    ip: String,
+    shell_port: u16,
+    iopub_port: u16,
+    stdin_port: u16,
+    control_port: u16,
+    hb_port: u16,
+    key: String,
+    signature_scheme: String,
+}
+
+impl Connection {
+    fn endpoint(&self, port: u16) -> String {
+        format!("{}://{}:{}", self.transport, self.ip, port)
+    }
+}
+
+/// Message of the protocol, without its signature.
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    /// Peers for the shell, topic for the IOPub
+    identities: Vec<Vec<u8>>,
+    header: Json,
+    parent_header: Json,
+    metadata: Json,
+    content: Json,
+}
+
+impl Message {
+    fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or_default()
+    }
+}
+
+/// Signature of the parts of a message with the key, empty without
+/// a key.
+fn sign(key: &[u8], parts: &[&[u8]]) -> String {
+    if key.is_empty() {
+        return String::new()
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    for part in parts.iter() {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Frames of the message, signed with the key.
+fn encode(key: &[u8], message: &Message) -> Vec<Vec<u8>> {
+    let parts: Vec<Vec<u8>> = [&message.header, &message.parent_header, &message.metadata, &message.content]
+        .iter()
+        .map(|part| part.to_string().into_bytes())
+        .collect();
+    let parts_ref: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+    let mut frames = message.identities.clone();
+    frames.push(DELIMITER.to_vec());
+    frames.push(sign(key, &parts_ref).into_bytes());
+    frames.extend(parts);
+    frames
+}
+
+/// Message of the frames, checking the signature with the key.
+fn decode(key: &[u8], frames: &[Vec<u8>]) -> Result<Message, String> {
+    let delimiter = frames.iter().position(|frame| frame == DELIMITER)
+        .ok_or_else(|| "Message without delimiter".to_string())?;
+    let rest = &frames[delimiter + 1..];
+    if rest.len() < 5 {
+        return Err(format!("Message with {} parts instead of 5", rest.len()))
+    }
+    let parts: Vec<&[u8]> = rest[1..5].iter().map(Vec::as_slice).collect();
+    if rest[0] != sign(key, &parts).into_bytes() {
+        return Err("Message with an invalid signature".to_string())
+    }
+    let part = |i: usize| serde_json::from_slice(parts[i]).map_err(|err| format!("Invalid message: {}", err));
+    Ok(Message{
+        identities: frames[..delimiter].to_vec(),
+        header: part(0)?,
+        parent_header: part(1)?,
+        metadata: part(2)?,
+        content: part(3)?,
+    })
+}
+
+/// UTC time in ISO 8601, such as `2024-01-31T12:00:00.000000Z`.
+fn date(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date of the days since 1970-01-01, by Howard Hinnant
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year, month, day, secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60, since.subsec_micros(),
+    )
+}
+
+/// Messages to send for a request.
+#[derive(Debug, Default)]
+struct Replies {
+    /// Reply on the channel of the request
+    reply: Option<Message>,
+    iopub: Vec<Message>,
+    shutdown: bool,
+}
+
+/// State of the kernel, kept between the cells.
+struct Kernel<T> {
+    session: Session<T>,
+    format: Format,
+    /// Session of the messages sent by the kernel
+    id: String,
+    sent: u64,
+    execution_count: u64,
+}
+
+impl<T: Value> Kernel<T> {
+    fn new(format: &Format) -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        Kernel{
+            session: session(format),
+            format: *format,
+            id: format!("{:x}-{:x}", process::id(), nanos),
+            sent: 0,
+            execution_count: 0,
+        }
+    }
+
+    /// Message answering the parent.
+    fn message(&mut self, parent: &Message, identities: Vec<Vec<u8>>, msg_type: &str, content: Json) -> Message {
+        self.sent += 1;
+        Message{
+            identities,
+            header: json!({
+                "msg_id": format!("{}-{}", self.id, self.sent),
+                "session": self.id,
+                "username": "wcal",
+                "date": date(SystemTime::now()),
+                "msg_type": msg_type,
+                "version": PROTOCOL_VERSION,
+            }),
+            parent_header: parent.header.clone(),
+            metadata: json!({}),
+            content,
+        }
+    }
+
+    fn reply(&mut self, request: &Message, msg_type: &str, content: Json) -> Message {
+        self.message(request, request.identities.clone(), msg_type, content)
+    }
+
+    fn publish(&mut self, request: &Message, msg_type: &str, content: Json) -> Message {
+        self.message(request, vec![msg_type.as_bytes().to_vec()], msg_type, content)
+    }
+
+    /// Answer the request of the shell or control channel, between
+    /// the busy and idle statuses.
+    fn handle(&mut self, request: &Message) -> Replies {
+        let mut replies = Replies::default();
+        replies.iopub.push(self.publish(request, "status", json!({"execution_state": "busy"})));
+        match request.msg_type() {
+            "kernel_info_request" => {
+                let content = json!({
+                    "status": "ok",
+                    "protocol_version": PROTOCOL_VERSION,
+                    "implementation": "wcal",
+                    "implementation_version": env!("CARGO_PKG_VERSION"),
+                    "language_info": {
+                        "name": "wcal",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "mimetype": "text/x-wcal",
+                        "file_extension": ".calc",
+                    },
+                    "banner": format!("wcal {}, calculating as {}", env!("CARGO_PKG_VERSION"), T::TYPE),
+                });
+                replies.reply = Some(self.reply(request, "kernel_info_reply", content));
+            }
+            "execute_request" => {
+                let code = request.content["code"].as_str().unwrap_or_default().to_string();
+                let content = self.execute(request, &code, &mut replies.iopub);
+                replies.reply = Some(self.reply(request, "execute_reply", content));
+            }
+            "shutdown_request" => {
+                let restart = request.content["restart"].as_bool().unwrap_or_default();
+                replies.reply = Some(self.reply(request, "shutdown_reply", json!({"status": "ok", "restart": restart})));
+                replies.shutdown = true;
+            }
+            "comm_info_request" => {
+                replies.reply = Some(self.reply(request, "comm_info_reply", json!({"status": "ok", "comms": {}})));
+            }
+            // Not supported, the frontends expect no reply
+            _ => {}
+        }
+        replies.iopub.push(self.publish(request, "status", json!({"execution_state": "idle"})));
+        replies
+    }
+
+    /// Calculate the lines of the cell, return the content of the
+    /// reply.
+    fn execute(&mut self, request: &Message, code: &str, iopub: &mut Vec<Message>) -> Json {
+        self.execution_count += 1;
+        let count = self.execution_count;
+        let input = self.publish(request, "execute_input", json!({"code": code, "execution_count": count}));
+        iopub.push(input);
+        let mut last = None;
+        for (i, line) in code.lines().enumerate() {
+            let expr = match strip_comment(line) {
+                Some(expr) => expr,
+                None => continue,
+            };
+            let entry = self.session.eval(expr);
+            let warnings: String = entry.warnings.iter().map(|warning| format!("Warning: {}\n", warning)).collect();
+            let object = output::json(expr, Some(i + 1), &entry.result, &entry.warnings);
+            let result = entry.result.clone();
+            if !warnings.is_empty() {
+                let stream = self.publish(request, "stream", json!({"name": "stderr", "text": warnings}));
+                iopub.push(stream);
+            }
+            match result {
+                Ok(res) => last = Some((res.format(&self.format), object)),
+                Err(err) => {
+                    let path = format!("In[{}]", count);
+                    let error = json!({
+                        "ename": kind(&err),
+                        "evalue": err.message(),
+                        "traceback": diagnostics::render_file(&path, i + 1, expr, &err).lines().collect::<Vec<_>>(),
+                    });
+                    let message = self.publish(request, "error", error.clone());
+                    iopub.push(message);
+                    let mut content = error;
+                    content["status"] = "error".into();
+                    content["execution_count"] = count.into();
+                    return content
+                }
+            }
+        }
+        if let Some((text, object)) = last {
+            let content = json!({
+                "execution_count": count,
+                "data": {"text/plain": text, "application/json": object},
+                "metadata": {},
+            });
+            let result = self.publish(request, "execute_result", content);
+            iopub.push(result);
+        }
+        json!({"status": "ok", "execution_count": count, "payload": [], "user_expressions": {}})
+    }
+}
+
+/// Name of the kind of error, the `ename` of Jupyter.
+fn kind(err: &Error) -> &'static str {
+    match err {
+        Error::Lex(_) => "LexError",
+        Error::Parse(_) => "ParseError",
+        Error::Eval(_) => "EvalError",
+    }
+}
+
+/// Serve the frontends of the connection file until they shut the
+/// kernel down.
+pub fn kernel(path: &Path, mode: Mode, format: &Format) -> Status {
+    let connection = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))
+        .and_then(|text| serde_json::from_str::<Connection>(&text).map_err(|err| format!("Invalid connection file {}: {}", path.display(), err)))
+        .and_then(|connection| match connection.signature_scheme.as_str() {
+            "hmac-sha256" => Ok(connection),
+            _ if connection.key.is_empty() => Ok(connection),
+            scheme => Err(format!("Unsupported signature scheme {}, use hmac-sha256", scheme)),
+        });
+    let connection = match connection {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Status::IO
+        }
+    };
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            eprintln!("Failed to start the kernel: {}", err);
+            return Status::IO
+        }
+    };
+    match with_mode!(mode, run(&runtime, &connection, format)) {
+        Ok(()) => Status::default(),
+        Err(err) => {
+            eprintln!("Kernel failed: {}", err);
+            Status::IO
+        }
+    }
+}
+
+fn run<T: Value>(runtime: &tokio::runtime::Runtime, connection: &Connection, format: &Format) -> Result<(), String> {
+    runtime.block_on(serve::<T>(connection, format))
+}
+
+async fn serve<T: Value>(connection: &Connection, format: &Format) -> Result<(), String> {
+    let key = connection.key.as_bytes();
+    let mut shell = RouterSocket::new();
+    let mut control = RouterSocket::new();
+    let mut stdin = RouterSocket::new();
+    let mut iopub = PubSocket::new();
+    let mut heartbeat = RepSocket::new();
+    for (socket, port) in [(&mut shell, connection.shell_port), (&mut control, connection.control_port), (&mut stdin, connection.stdin_port)] {
+        bind(socket, &connection.endpoint(port)).await?;
+    }
+    bind(&mut iopub, &connection.endpoint(connection.iopub_port)).await?;
+    bind(&mut heartbeat, &connection.endpoint(connection.hb_port)).await?;
+
+    let mut kernel = Kernel::<T>::new(format);
+    loop {
+        let (frames, from_control) = tokio::select! {
+            frames = shell.recv() => (frames, false),
+            frames = control.recv() => (frames, true),
+            ping = heartbeat.recv() => {
+                let ping = ping.map_err(|err| err.to_string())?;
+                heartbeat.send(ping).await.map_err(|err| err.to_string())?;
+                continue
+            }
+        };
+        let frames: Vec<Vec<u8>> = frames.map_err(|err| err.to_string())?.into_vec().into_iter().map(|frame| frame.to_vec()).collect();
+        let request = match decode(key, &frames) {
+            Ok(request) => request,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue
+            }
+        };
+        let replies = kernel.handle(&request);
+        let (busy, idle) = replies.iopub.split_at(1);
+        send(&mut iopub, key, &busy[0]).await?;
+        for message in idle[..idle.len() - 1].iter() {
+            send(&mut iopub, key, message).await?;
+        }
+        if let Some(reply) = &replies.reply {
+            let channel = if from_control { &mut control } else { &mut shell };
+            send(channel, key, reply).await?;
+        }
+        send(&mut iopub, key, &idle[idle.len() - 1]).await?;
+        if replies.shutdown {
+            return Ok(())
+        }
+    }
+}
+
+async fn bind<S: Socket>(socket: &mut S, endpoint: &str) -> Result<(), String> {
+    socket.bind(endpoint).await
+        .map(|_| ())
+        .map_err(|err| format!("Failed to bind {}: {}", endpoint, err))
+}
+
+async fn send<S: SocketSend>(socket: &mut S, key: &[u8], message: &Message) -> Result<(), String> {
+    let mut frames = encode(key, message).into_iter();
+    // Never empty, there is at least the delimiter
+    let mut message = ZmqMessage::from(frames.next().unwrap_or_default());
+    for frame in frames {
+        message.push_back(frame.into());
+    }
+    socket.send(message).await.map_err(|err| format!("Failed to send: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(msg_type: &str, content: Json) -> Message {
+        Message{
+            identities: vec![b"peer".to_vec()],
+            header: json!({"msg_id": "1", "msg_type": msg_type}),
+            parent_header: json!({}),
+            metadata: json!({}),
+            content,
+        }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let message = request("kernel_info_request", json!({}));
+        let frames = encode(b"secret", &message);
+        assert_eq!(frames[1], DELIMITER);
+        assert_eq!(frames[2].len(), 64);
+        assert_eq!(decode(b"secret", &frames), Ok(message.clone()));
+        assert_eq!(decode(b"other", &frames), Err("Message with an invalid signature".to_string()));
+        // Without a key, messages aren't signed
+        let frames = encode(b"", &message);
+        assert!(frames[2].is_empty());
+        assert_eq!(decode(b"", &frames), Ok(message));
+    }
+
+    #[test]
+    fn test_date() {
+        assert_eq!(date(UNIX_EPOCH), "1970-01-01T00:00:00.000000Z");
+        let time = UNIX_EPOCH + std::time::Duration::from_millis(1_709_210_096_500);
+        assert_eq!(date(time), "2024-02-29T12:34:56.500000Z");
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut kernel = Kernel::<i128>::new(&Format::default());
+        let replies = kernel.handle(&request("execute_request", json!({"code": "x = 7 # rate\n\nx / 2"})));
+        let types: Vec<_> = replies.iopub.iter().map(Message::msg_type).collect();
+        assert_eq!(types, ["status", "execute_input", "stream", "execute_result", "status"]);
+        assert_eq!(replies.iopub[2].content["text"], "Warning: Division will cause a cast: 7/2\n");
+        assert_eq!(replies.iopub[3].content["data"]["text/plain"], "3");
+        assert_eq!(replies.iopub[3].content["data"]["application/json"]["value"], 3);
+        let reply = replies.reply.unwrap();
+        assert_eq!(reply.identities, [b"peer".to_vec()]);
+        assert_eq!(reply.parent_header["msg_id"], "1");
+        assert_eq!(reply.content["status"], "ok");
+        assert_eq!(reply.content["execution_count"], 1);
+
+        // The session is kept between the cells
+        let replies = kernel.handle(&request("execute_request", json!({"code": "x * ans\nx / 0\n1"})));
+        let types: Vec<_> = replies.iopub.iter().map(Message::msg_type).collect();
+        assert_eq!(types, ["status", "execute_input", "error", "status"]);
+        let reply = replies.reply.unwrap();
+        assert_eq!(reply.content["status"], "error");
+        assert_eq!(reply.content["ename"], "EvalError");
+        assert_eq!(reply.content["evalue"], "Division by zero");
+        assert_eq!(reply.content["traceback"][1], " --> In[2]:2:1");
+        assert_eq!(reply.content["execution_count"], 2);
+    }
+
+    #[test]
+    fn test_requests() {
+        let mut kernel = Kernel::<f64>::new(&Format::default());
+        let reply = kernel.handle(&request("kernel_info_request", json!({}))).reply.unwrap();
+        assert_eq!(reply.msg_type(), "kernel_info_reply");
+        assert_eq!(reply.content["language_info"]["file_extension"], ".calc");
+
+        let replies = kernel.handle(&request("history_request", json!({})));
+        assert!(replies.reply.is_none());
+        assert_eq!(replies.iopub.len(), 2);
+
+        let replies = kernel.handle(&request("shutdown_request", json!({"restart": true})));
+        assert!(replies.shutdown);
+        assert_eq!(replies.reply.unwrap().content["restart"], true);
+    }
+}
@@ -0,0 +1,1282 @@
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use rustyline::Editor;
+use rustyline::history::DefaultHistory;
+use rustyline::error::ReadlineError;
+
+use wcal::{lexer, parser, diagnostics, grammar, pretty, AngleUnit, BigInt, Calculator, Decimal, Entry, Error, Matrix, Rational, Rounding, Session, Time};
+use wcal::explain::Reduction;
+use wcal::parser::ast::AST;
+use wcal::parser::export;
+use wcal::pretty::Style;
+
+/// Call the generic function with the result type of the mode.
+///
+/// Defined before the modules, which use it too.
+macro_rules! with_mode {
+    ($mode: expr, $($f: ident)::+ ($($arg: expr),*)) => {
+        match $mode {
+            Mode::Int => $($f)::+::<i128>($($arg),*),
+            Mode::Float => $($f)::+::<f64>($($arg),*),
+            Mode::Rational => $($f)::+::<Rational>($($arg),*),
+            Mode::Big => $($f)::+::<BigInt>($($arg),*),
+            Mode::Decimal => $($f)::+::<Decimal>($($arg),*),
+            Mode::Duration => $($f)::+::<Time>($($arg),*),
+            Mode::Matrix => $($f)::+::<Matrix>($($arg),*),
+        }
+    };
+}
+
+mod audit;
+mod bench;
+mod check;
+mod clipboard;
+mod color;
+mod config;
+mod conv;
+mod expand;
+mod fmt;
+mod format;
+#[cfg(feature = "jupyter")]
+mod jupyter;
+mod map;
+#[cfg(all(feature = "mmap", unix))]
+mod mmap;
+#[cfg(feature = "server")]
+mod metrics;
+mod output;
+mod rpc;
+mod script;
+#[cfg(feature = "server")]
+mod server;
+mod timing;
+mod watch;
+
+use config::{Config, Mode};
+use format::{Format, Separator, Value};
+use output::Output;
+use timing::Timings;
+
+/// A calculator for arithmetic expressions.
+///
+/// Calculate the files and the expressions in order. Without them,
+/// calculate each line of stdin if it is not a terminal, or start
+/// the interactive mode. Put `--` before an expression that
+/// starts with `-`, such as `wcal -- -1+2`.
+///
+/// The type of the results applies to all the expressions wherever
+/// it is written, `wcal 1+2 -f 7/2` calculates both to f64.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// Calculate to i128 (default)
+    #[arg(short, long, conflicts_with_all = ["float", "rational", "big", "backend"])]
+    int: bool,
+
+    /// Calculate to f64
+    #[arg(short, long, conflicts_with_all = ["rational", "big", "backend"])]
+    float: bool,
+
+    /// Calculate to exact fractions, such as 11/6
+    #[arg(short, long, conflicts_with_all = ["big", "backend"])]
+    rational: bool,
+
+    /// Calculate to integers that never overflow
+    #[arg(short, long, conflicts_with = "backend")]
+    big: bool,
+
+    /// Type of the results, -i, -f, -r and -b are short for
+    /// i128, f64, rational and bigint
+    #[arg(long, value_enum, value_name = "NAME")]
+    backend: Option<Mode>,
+
+    /// Follow fractions by their decimal approximation, rounded
+    /// by --precision
+    #[arg(long)]
+    decimal: bool,
+
+    /// Parser of the tokens
+    #[arg(long, value_enum, default_value_t = ParserKind::TopDown)]
+    parser: ParserKind,
+
+    /// Digits after the decimal point of f64 and decimal results
+    #[arg(long, value_name = "DIGITS")]
+    precision: Option<usize>,
+
+    /// Significant digits of f64 and decimal results, before
+    /// --precision, such as 1230 for 1234.5 with 3
+    #[arg(long, value_name = "DIGITS", value_parser = clap::value_parser!(u32).range(1..))]
+    significant: Option<u32>,
+
+    /// Print f64 and decimal results with a fixed number of decimals, 6
+    /// without --precision
+    #[arg(long, group = "notation")]
+    fixed: bool,
+
+    /// Print f64 and decimal results in scientific notation, such as 1.5e3
+    #[arg(long, group = "notation")]
+    scientific: bool,
+
+    /// Print f64 and decimal results in engineering notation, such as 12.5e3
+    #[arg(long, group = "notation")]
+    engineering: bool,
+
+    /// Print f64 and decimal results with SI prefixes, such as 12.5k or 3.3µ
+    #[arg(long, group = "notation")]
+    si: bool,
+
+    /// Print i128 results in hexadecimal
+    #[arg(long, group = "base")]
+    hex: bool,
+
+    /// Print i128 results in octal
+    #[arg(long, group = "base")]
+    oct: bool,
+
+    /// Print i128 results in binary
+    #[arg(long, group = "base")]
+    bin: bool,
+
+    /// Prefix i128 results in other bases with 0x, 0o or 0b
+    #[arg(long)]
+    prefix: bool,
+
+    /// Separate the digits of the results in groups of N, with `_`
+    /// without --separator
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    group: Option<u32>,
+
+    /// Separator of the groups of digits, groups of 3 without
+    /// --group
+    #[arg(long, value_enum, value_name = "STYLE")]
+    separator: Option<Separator>,
+
+    /// Print the durations of lexing, parsing and calculating each
+    /// expression to stderr
+    #[arg(long)]
+    time: bool,
+
+    /// Fail the expressions that raise a warning, such as the cast
+    /// of 7/2, with exit status 5
+    #[arg(long)]
+    warnings_as_errors: bool,
+
+    /// Take and return the angles of sin, cos, tan and their
+    /// inverses in degrees rather than radians
+    #[arg(long)]
+    degrees: bool,
+
+    /// Print the results of the duration backend as hh:mm:ss
+    /// rather than 1h30m
+    #[arg(long)]
+    clock: bool,
+
+    /// Rounding of the integer roots that aren't integers, floor,
+    /// ceil or nearest
+    #[arg(long, value_name = "MODE")]
+    rounding: Option<Rounding>,
+
+    /// Serve the HTTP API at the address, such as 127.0.0.1:8080
+    #[cfg(feature = "server")]
+    #[arg(long, value_name = "ADDR")]
+    serve: Option<String>,
+
+    /// Run as a Jupyter kernel with the connection file, needs the
+    /// jupyter feature
+    #[cfg(feature = "jupyter")]
+    #[arg(long, value_name = "FILE")]
+    jupyter_kernel: Option<PathBuf>,
+
+    /// Put the last result on the clipboard, needs the clipboard
+    /// feature
+    #[arg(long)]
+    copy: bool,
+
+    /// Answer JSON-RPC requests, one per line of stdin
+    #[arg(long)]
+    rpc: bool,
+
+    /// Append a record of each evaluation to the file, as a line of
+    /// JSON chained to the one before, signed by the key of
+    /// WCAL_AUDIT_KEY. Needs the audit feature
+    #[arg(long, value_name = "FILE")]
+    audit: Option<PathBuf>,
+
+    /// Config file instead of ~/.config/wcal/config.toml
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Don't color the output, same as setting NO_COLOR
+    #[arg(long)]
+    no_color: bool,
+
+    /// Print each evaluation as a JSON object, same as
+    /// --format json
+    #[arg(long, conflicts_with = "format")]
+    json: bool,
+
+    /// Output of the evaluations
+    #[arg(long, value_enum, default_value_t = Output::Text)]
+    format: Output,
+
+    /// File of expressions, one per line, `#` starts a comment.
+    /// Can be repeated
+    #[arg(short = 'F', long = "file", value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    /// Expressions to calculate
+    exprs: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Calculate the file each time it changes, showing the
+    /// results that changed
+    Watch {
+        /// File of expressions, as with --file
+        file: PathBuf,
+    },
+    /// Run a script of definitions, such as `f(x) = x * x`, and
+    /// expressions, printing the results of the expressions
+    Run {
+        script: PathBuf,
+        /// Map the script rather than read it, and run it a chunk at a
+        /// time without the history, for the files too large to read.
+        /// Needs the mmap feature, on Unix
+        #[cfg(all(feature = "mmap", unix))]
+        #[arg(long)]
+        mmap: bool,
+    },
+    /// Print the syntax tree of the expression without calculating
+    /// it
+    Ast {
+        expr: String,
+        /// Format of the tree
+        #[arg(long, value_enum, default_value_t = AstFormat::Tree)]
+        format: AstFormat,
+        /// Write the tree to the file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Tell whether two expressions are the same formula, ignoring
+    /// the parentheses and the order of the operands of `+` and `*`,
+    /// and show the first difference
+    Diff {
+        a: String,
+        b: String,
+    },
+    /// Rewrite the expressions in their canonical text, such as
+    /// `x = (1 + 2) * 3`, from the arguments, the files or stdin
+    Fmt {
+        exprs: Vec<String>,
+        /// File of expressions, comments are kept. Can be repeated
+        #[arg(short = 'F', long = "file", value_name = "FILE")]
+        files: Vec<PathBuf>,
+        /// Rewrite the files instead of printing them
+        #[arg(short, long, requires = "files")]
+        in_place: bool,
+        /// Write hexadecimal literals in lowercase
+        #[arg(long)]
+        lowercase_hex: bool,
+    },
+    /// Lex and parse the files, or stdin without them, reporting
+    /// every line with an error without calculating
+    Check {
+        files: Vec<PathBuf>,
+        /// Also report the lines that parse but could be written
+        /// more simply, as warnings that don't change the status
+        #[arg(long)]
+        lint: bool,
+    },
+    /// Calculate the expression for each number of stdin, bound to
+    /// `x`, such as `wcal map 'x*11/10'`
+    Map {
+        expr: String,
+        /// Field of each line that holds the number, from 1. The
+        /// other fields are printed as they are
+        #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        column: Option<u32>,
+        /// Separator of the fields with --column
+        #[arg(short, long, value_name = "CHAR", default_value_t = ',')]
+        delimiter: char,
+    },
+    /// Calculate the expression many times with each backend and
+    /// parser, printing the throughput and the latency percentiles
+    Bench {
+        expr: String,
+        /// Number of runs of each combination, 10000 without
+        /// --duration
+        #[arg(short = 'n', long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        runs: Option<u64>,
+        /// Seconds of runs of each combination instead of a number
+        #[arg(long, value_name = "SECS", conflicts_with = "runs")]
+        duration: Option<f64>,
+        /// Backend to run, all of them without it. Can be repeated
+        #[arg(long, value_enum, value_name = "NAME")]
+        backend: Vec<Mode>,
+        /// Parser to run, all of them without it. Can be repeated
+        #[arg(long, value_enum, value_name = "NAME")]
+        parser: Vec<ParserKind>,
+    },
+    /// Convert the value to a base, `bin`, `oct`, `dec` or `hex`,
+    /// or to a unit, such as `wcal conv 3.5km --to mi`
+    Conv {
+        value: String,
+        /// Base or unit of the result
+        #[arg(long, value_name = "TARGET")]
+        to: String,
+    },
+    /// Print random expressions of `+ - * /` calculated to the
+    /// integer, such as `wcal expand 42 --ops 50`, for exercises and
+    /// fuzzing
+    Expand {
+        value: String,
+        /// Number of operators of each expression
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        ops: usize,
+        /// Number of expressions, one per line
+        #[arg(short = 'n', long, value_name = "N", default_value_t = 1)]
+        count: u64,
+        /// Seed of the random expressions, the clock without it
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Print the completion script of the shell, such as
+    /// `wcal completions bash > /etc/bash_completion.d/wcal`
+    Completions {
+        shell: Shell,
+    },
+}
+
+/// Format of `wcal ast`.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum AstFormat {
+    /// Indented tree with the spans
+    Tree,
+    /// S-expression, such as (+ 1 (* 2 3))
+    Sexpr,
+    /// Graphviz graph, for `dot -Tsvg`
+    Dot,
+    /// Nested objects of the nodes
+    Json,
+    /// Postfix tokens, read back by `--parser rpn`
+    Rpn,
+    /// Binary `Ast` message of `proto/wcal.proto`
+    #[cfg(feature = "protobuf")]
+    Protobuf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ParserKind {
+    /// Infix expressions, such as `(3 + 4) * 2`
+    TopDown,
+    /// Postfix tokens, such as `3 4 + 2 *`
+    Rpn,
+}
+
+impl ParserKind {
+    fn parse_fn(self) -> parser::ParseFn {
+        match self {
+            ParserKind::TopDown => parser::top_down_parser::parse,
+            ParserKind::Rpn => parser::rpn_parser::parse,
+        }
+    }
+}
+
+fn print_result<T: Value>(expr: &str, result: &Result<T, Error>, format: &Format) {
+    match result {
+        Ok(res) => println!("{}", res.format(format)),
+        Err(err) => println!("{}", color::error(&diagnostics::render(expr, err)))
+    }
+}
+
+/// Print the text of a function such as `hex(255)`, or its error.
+fn print_text(expr: &str, text: &Result<String, Error>, format: &Format) {
+    if output::print_text(format, expr, None, text) {
+        return
+    }
+    match text {
+        Ok(text) => println!("{}", text),
+        Err(err) => println!("{}", color::error(&diagnostics::render(expr, err)))
+    }
+}
+
+/// Exit status of the first failed expression, 0 if all of them
+/// succeeded.
+#[derive(Default, Clone, Copy, Debug)]
+struct Status(i32);
+
+impl Status {
+    /// Failed to read a file or stdin, or to use the clipboard
+    const IO: Status = Status(1);
+    /// Invalid command line arguments, as reported by clap
+    const USAGE: Status = Status(2);
+    /// The expressions of `wcal diff` differ
+    const DIFFERENT: Status = Status(6);
+
+    /// Status of the kind of error.
+    fn of(err: &Error) -> Status {
+        match err {
+            Error::Lex(_) => Status(3),
+            Error::Parse(_) => Status(4),
+            Error::Eval(_) => Status(5),
+        }
+    }
+
+    fn record<T>(&mut self, result: &Result<T, Error>) {
+        if let (0, Err(err)) = (self.0, result) {
+            *self = Status::of(err);
+        }
+    }
+
+    fn merge(&mut self, other: Status) {
+        if self.0 == 0 {
+            self.0 = other.0;
+        }
+    }
+
+    fn exit(self) -> ! {
+        process::exit(self.0)
+    }
+}
+
+/// Calculate the expression with the parser, keeping the warnings
+/// and timing the stages.
+/// Calculate the expression in the session. To time the stages,
+/// the expression is lexed and parsed apart before.
+fn eval_in<'s, T: Value>(session: &'s mut Session<T>, expr: &str, format: &Format) -> (&'s Entry<T>, Option<Timings>) {
+    if !format.time {
+        return (session.eval(expr), None)
+    }
+    let mut timings = Timings::default();
+    let entry = match timings.parse(expr, parser::top_down_parser::parse) {
+        Ok(ast) => timings.eval(move || session.eval_ast(expr, ast)),
+        Err(_) => session.eval(expr),
+    };
+    (entry, Some(timings))
+}
+
+/// Calculate the expression in the session, parsed by the parser of
+/// the arguments. An expression the postfix parser rejects isn't
+/// recorded, its error is returned.
+fn eval_parsed<'s, T: Value>(session: &'s mut Session<T>, expr: &str, kind: ParserKind, format: &Format) -> (Result<&'s Entry<T>, Error>, Option<Timings>) {
+    if let ParserKind::TopDown = kind {
+        let (entry, timings) = eval_in(session, expr, format);
+        return (Ok(entry), timings)
+    }
+    let mut timings = Timings::default();
+    let entry = timings.parse(expr, kind.parse_fn())
+        .map(|ast| timings.eval(move || session.eval_ast(expr, ast)));
+    (entry, Some(timings).filter(|_| format.time))
+}
+
+/// Session of the CLI modes, with the warnings as errors if
+/// asked, the angle unit and the rounding.
+fn session<T: Value>(format: &Format) -> Session<T> {
+    let mut session = Session::default();
+    session.set_warnings_as_errors(format.warnings_as_errors);
+    session.set_angle_unit(format.angle_unit);
+    session.set_rounding(format.rounding);
+    session
+}
+
+fn print_timings(timings: Option<Timings>) {
+    if let Some(timings) = timings {
+        eprintln!("{}", color::dim(&timings.to_string()));
+    }
+}
+
+fn cmd_mod<T: Value>(args: &Args, format: &Format) -> Status {
+    let mut status = Status::default();
+    let mut session = session::<T>(format);
+    for expr in args.exprs.iter() {
+        if format.output == Output::Text {
+            println!("{}{}", color::prompt(T::PROMPT), color::highlight(expr));
+        }
+        if let Some(text) = session.eval_text(expr) {
+            status.record(&text);
+            print_text(expr, &text, format);
+            continue
+        }
+        if let Some(defined) = session.define(expr) {
+            status.record(&defined);
+            if let Err(err) = defined {
+                println!("{}", color::error(&diagnostics::render(expr, &err)));
+            }
+            continue
+        }
+        let (entry, timings) = eval_parsed(&mut session, expr, args.parser, format);
+        let failed;
+        let (ast, result, warnings) = match entry {
+            Ok(entry) => (entry.ast.as_ref(), &entry.result, entry.warnings.as_slice()),
+            Err(err) => {
+                failed = Err(err);
+                (None, &failed, &[][..])
+            }
+        };
+        status.record(result);
+        audit::record(expr, ast, result, warnings, format);
+        if !output::print(format, expr, None, result, warnings) {
+            print_result(expr, result, format);
+        }
+        print_timings(timings);
+    }
+    status
+}
+
+/// Calculate the lines of the file in a session, print the
+/// results with the line numbers.
+fn file_mod<T: Value>(path: &str, source: &str, format: &Format) -> Status {
+    let mut session = session::<T>(format);
+    let mut status = Status::default();
+    for (i, line) in source.lines().enumerate() {
+        let expr = match strip_comment(line) {
+            Some(expr) => expr,
+            None => continue,
+        };
+        if let Some(defined) = session.define(expr) {
+            status.record(&defined);
+            if let Err(err) = defined {
+                println!("{}", color::error(&diagnostics::render_file(path, i + 1, expr, &err)));
+            }
+            continue
+        }
+        let (entry, timings) = eval_in(&mut session, expr, format);
+        status.record(&entry.result);
+        audit::record(expr, entry.ast.as_ref(), &entry.result, &entry.warnings, format);
+        if !output::print(format, expr, Some(i + 1), &entry.result, &entry.warnings) {
+            match &entry.result {
+                Ok(res) => println!("{}:{}: {}", path, i + 1, res.format(format)),
+                Err(err) => println!("{}", color::error(&diagnostics::render_file(path, i + 1, expr, err))),
+            }
+        }
+        print_timings(timings);
+    }
+    status
+}
+
+/// Calculate the lines of stdin in a session, print one result per
+/// line and the errors to stderr.
+fn filter_mod<T: Value>(format: &Format) -> Status {
+    let mut session = session::<T>(format);
+    let mut status = Status::default();
+    for (i, line) in io::stdin().lock().lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("Failed to read stdin: {}", err);
+                return Status::IO
+            }
+        };
+        let expr = match strip_comment(&line) {
+            Some(expr) => expr,
+            None => continue,
+        };
+        if let Some(defined) = session.define(expr) {
+            status.record(&defined);
+            if let Err(err) = defined {
+                eprintln!("{}", color::error(&diagnostics::render_file("<stdin>", i + 1, expr, &err)));
+            }
+            continue
+        }
+        let (entry, timings) = eval_in(&mut session, expr, format);
+        status.record(&entry.result);
+        audit::record(expr, entry.ast.as_ref(), &entry.result, &entry.warnings, format);
+        if !output::print(format, expr, Some(i + 1), &entry.result, &entry.warnings) {
+            match &entry.result {
+                Ok(res) => println!("{}", res.format(format)),
+                Err(err) => eprintln!("{}", color::error(&diagnostics::render_file("<stdin>", i + 1, expr, err))),
+            }
+        }
+        print_timings(timings);
+    }
+    status
+}
+
+/// The expression before `#`, `None` if there is nothing to calculate.
+fn strip_comment(line: &str) -> Option<&str> {
+    let expr = line.split('#').next().unwrap_or_default();
+    if expr.trim().is_empty() {
+        None
+    } else {
+        Some(expr)
+    }
+}
+
+fn print_entry<T: Value>(entry: &Entry<T>, format: &Format) {
+    if let Ok(res) = &entry.result {
+        clipboard::record(&res.format(format));
+    }
+    print_result(&entry.expr, &entry.result, format)
+}
+
+/// Calculate the line of the interactive mode and print it. A
+/// definition prints nothing, a function such as `hex(255)` its
+/// text.
+fn eval_print<T: Value>(session: &mut Session<T>, input: &str, format: &Format) -> Option<Timings> {
+    if let Some(defined) = session.define(input) {
+        if let Err(err) = defined {
+            println!("{}", color::error(&diagnostics::render(input, &err)));
+        }
+        return None
+    }
+    if let Some(text) = session.eval_text(input) {
+        if let Ok(text) = &text {
+            clipboard::record(text);
+        }
+        print_text(input, &text, format);
+        return None
+    }
+    let (entry, timings) = eval_in(session, input, format);
+    audit::record(input, entry.ast.as_ref(), &entry.result, &entry.warnings, format);
+    print_entry(entry, format);
+    timings
+}
+
+/// Print the tokens of the expression with their spans, until the
+/// first error.
+fn print_tokens(expr: &str) {
+    for token in lexer::tokens(expr) {
+        match token {
+            Ok((token, span)) => println!("{:<8}{:?}", format!("{:?}", span), token),
+            Err(err) => {
+                println!("{}", color::error(&diagnostics::render(expr, &err)));
+                return
+            }
+        }
+    }
+}
+
+/// Print the tree of the expression as an S-expression, without
+/// calculating it.
+fn print_ast(expr: &str) {
+    match lexer::spanned(expr).and_then(parser::top_down_parser::parse) {
+        Ok(ast) => println!("{}", ast),
+        Err(err) => println!("{}", color::error(&diagnostics::render(expr, &err))),
+    }
+}
+
+/// Write the tree of the expression in the format, to the file or
+/// stdout.
+fn export_ast(expr: &str, format: AstFormat, output: Option<&Path>) -> Status {
+    let ast = match lexer::spanned(expr).and_then(parser::top_down_parser::parse) {
+        Ok(ast) => ast,
+        Err(err) => {
+            eprintln!("{}", color::error(&diagnostics::render(expr, &err)));
+            return Status::of(&err)
+        }
+    };
+    let text = match format {
+        AstFormat::Tree => export::tree(&ast),
+        AstFormat::Sexpr => format!("{}\n", ast),
+        AstFormat::Dot => export::dot(&ast),
+        AstFormat::Json => format!("{}\n", export::json(&ast)),
+        AstFormat::Rpn => format!("{}\n", export::rpn(&ast)),
+        #[cfg(feature = "protobuf")]
+        AstFormat::Protobuf => return write_output(output, &wcal::proto::encode(&ast)),
+    };
+    write_output(output, text.as_bytes())
+}
+
+/// Write the bytes to the file or stdout.
+fn write_output(output: Option<&Path>, bytes: &[u8]) -> Status {
+    match output {
+        Some(path) => match fs::write(path, bytes) {
+            Ok(()) => Status::default(),
+            Err(err) => {
+                eprintln!("Failed to write {}: {}", path.display(), err);
+                Status::IO
+            }
+        },
+        None => match io::stdout().write_all(bytes) {
+            Ok(()) => Status::default(),
+            Err(err) => {
+                eprintln!("Failed to write stdout: {}", err);
+                Status::IO
+            }
+        },
+    }
+}
+
+/// Compare the trees of the expressions, underlining the first
+/// differing subtree of each.
+fn diff_exprs(a: &str, b: &str) -> Status {
+    let mut trees = Vec::new();
+    for expr in [a, b] {
+        match lexer::spanned(expr).and_then(parser::top_down_parser::parse) {
+            Ok(ast) => trees.push(ast),
+            Err(err) => {
+                eprintln!("{}", color::error(&diagnostics::render(expr, &err)));
+                return Status::of(&err)
+            }
+        }
+    }
+    let diff = match trees[0].diff(&trees[1]) {
+        Some(diff) => diff,
+        None => {
+            println!("Equivalent");
+            return Status::default()
+        }
+    };
+    println!("Different:");
+    for (expr, node) in [(a, diff.left), (b, diff.right)] {
+        let span = node.span();
+        let column = expr[..span.start].chars().count();
+        let width = expr[span].chars().count().max(1);
+        println!("  {}\n  {}{}", expr, " ".repeat(column), color::error(&"^".repeat(width)));
+    }
+    Status::DIFFERENT
+}
+
+fn print_history<T: Value>(session: &Session<T>, format: &Format) {
+    for (i, entry) in session.iter().enumerate() {
+        match &entry.result {
+            Ok(res) => println!("${}\t{} = {}", i + 1, entry.expr, res.format(format)),
+            Err(err) => println!("${}\t{} : {}", i + 1, entry.expr, err.message())
+        }
+    }
+}
+
+/// Print the variables of the session with their values and type.
+fn print_vars<T: Value>(session: &Session<T>, format: &Format) {
+    if session.env().is_empty() {
+        println!("No variables");
+    }
+    for (name, value) in session.env().iter() {
+        println!("{}\t{}\t{}", name, value.format(format), T::TYPE);
+    }
+}
+
+/// Print the functions that can be called in the session, the
+/// defined ones with their body.
+fn print_funcs<T>(session: &Session<T>) {
+    let signatures = session.calculator().function_registry().signatures();
+    let mut defined = session.env().functions().peekable();
+    if signatures.is_empty() && defined.peek().is_none() {
+        println!("No functions");
+    }
+    for (name, arity) in signatures {
+        let params = vec!["x"; arity].join(", ");
+        let plural = if arity == 1 { "" } else { "s" };
+        println!("{}({})\t{} argument{}", name, params, arity, plural);
+    }
+    for (name, function) in defined {
+        let body = pretty::format_ast(&AST{root: function.body.clone()}, "", &Style::default());
+        println!("{}({}) = {}", name, function.params.join(", "), body);
+    }
+}
+
+/// Print the steps of the calculation of the expression, one
+/// operation per line, then its error if any.
+fn print_explain<T: Value>(session: &Session<T>, expr: &str, format: &Format) {
+    let (steps, result) = session.explain(expr);
+    for step in steps.iter() {
+        let value = step.value.format(format);
+        match &step.reduction {
+            Reduction::BinOp{lhs, op, rhs} => println!("{} {} {} = {}", lhs.format(format), op, rhs.format(format), value),
+            Reduction::ShortCircuit{lhs, op} => println!("{} {} ... = {}", lhs.format(format), op, value),
+            Reduction::Neg{operand} => println!("-({}) = {}", operand.format(format), value),
+            Reduction::Not{operand} => println!("!{} = {}", operand.format(format), value),
+            Reduction::Abs{operand} => println!("|{}| = {}", operand.format(format), value),
+            Reduction::Let{name} => println!("let {} = {}", name, value),
+            Reduction::If{cond} => println!("if {} then ... = {}", cond.format(format), value),
+            Reduction::Call{name, args} => {
+                let args: Vec<_> = args.iter().map(|arg| arg.format(format)).collect();
+                println!("{}({}) = {}", name, args.join(", "), value)
+            }
+            Reduction::Var{name} => println!("{} = {}", name, value),
+            Reduction::Assign{name} => println!("{} = {}, assigned", name, value),
+            Reduction::List => println!("{} = {}", expr.get(step.span.clone()).unwrap_or_default(), value),
+        }
+    }
+    match result {
+        Ok(res) if steps.is_empty() => println!("{}", res.format(format)),
+        Ok(_) => {}
+        Err(err) => println!("{}", color::error(&diagnostics::render(expr, &err))),
+    }
+}
+
+/// Put the last result on the clipboard for `--copy`.
+fn copy_last() -> Status {
+    match clipboard::copy_last() {
+        Ok(_) => Status::default(),
+        Err(err) => {
+            eprintln!("{}", color::error(&err));
+            Status::IO
+        }
+    }
+}
+
+/// Count of expressions, such as `1 expression`.
+fn expressions(len: usize) -> String {
+    format!("{} expression{}", len, if len == 1 { "" } else { "s" })
+}
+
+/// Print the syntax as described by the grammar of the library.
+fn print_grammar() {
+    println!("Operators, from the tightest:");
+    let mut operators = grammar::operators();
+    operators.sort_by_key(|op| std::cmp::Reverse(op.precedence));
+    for group in operators.chunk_by(|a, b| a.precedence == b.precedence) {
+        let symbols: Vec<_> = group.iter()
+            .map(|op| if op.arity == 1 { format!("{}x", op.symbol) } else { format!("x {} y", op.symbol) })
+            .collect();
+        let descriptions: Vec<_> = group.iter().map(|op| op.description).collect();
+        let associativity = match group[0].associativity {
+            grammar::Associativity::Left => "left to right",
+            grammar::Associativity::Right => "right to left",
+        };
+        println!("  {}\t{}, {}", symbols.join("  "), descriptions.join(", "), associativity);
+    }
+    println!("  (x)\tGrouping");
+    println!("  name = x\tAssignment, the result is x");
+    println!("Literals, `_` separates digits:");
+    for literal in grammar::literals() {
+        println!("  {}\t{}", literal.form, literal.description);
+    }
+    let functions = grammar::functions();
+    if !functions.is_empty() {
+        println!("Functions:");
+        for func in functions {
+            let params = vec!["x"; func.arity].join(", ");
+            println!("  {}({})\t{}", func.name, params, func.description);
+        }
+    }
+    println!("Constants:");
+    for constant in grammar::constants() {
+        println!("  {}\t{}", constant.name, constant.description);
+    }
+}
+
+/// Calculate the definitions of the config in the session, they
+/// can't be undone and don't appear in the history. Print the
+/// errors with `report`.
+fn define<T: Value>(session: &mut Session<T>, definitions: &[String], report: bool) {
+    for expr in definitions.iter() {
+        let err = match session.define(expr) {
+            Some(defined) => defined.err(),
+            None => session.eval(expr).result.as_ref().err().cloned(),
+        };
+        if let (true, Some(err)) = (report, err) {
+            eprintln!("{}", color::error(&format!("In the definition `{}`: {}", expr, err.message())));
+        }
+    }
+    while session.env_mut().commit() {}
+    session.clear();
+}
+
+/// Mode of the options, or of the config without them.
+fn mode(args: &Args, config: &Config) -> Mode {
+    if let Some(backend) = args.backend {
+        backend
+    } else if args.int {
+        Mode::Int
+    } else if args.float {
+        Mode::Float
+    } else if args.rational {
+        Mode::Rational
+    } else if args.big {
+        Mode::Big
+    } else {
+        config.mode.unwrap_or(Mode::Int)
+    }
+}
+
+/// Session of a backend in the interactive mode, whatever the type
+/// of its results.
+trait Backend {
+    fn prompt(&self) -> &'static str;
+    fn eval_print(&mut self, input: &str, format: &Format) -> Option<Timings>;
+    fn undo(&mut self) -> bool;
+    fn print_history(&self, format: &Format);
+    fn print_vars(&self, format: &Format);
+    fn print_funcs(&self);
+    fn explain(&self, expr: &str, format: &Format);
+    fn save(&self, path: &Path) -> io::Result<usize>;
+    fn load(&mut self, path: &Path, format: &Format) -> io::Result<usize>;
+    fn set_warnings_as_errors(&mut self, warnings_as_errors: bool);
+    fn set_angle_unit(&mut self, angle_unit: AngleUnit);
+}
+
+impl<T: Value> Backend for Session<T> {
+    fn prompt(&self) -> &'static str {
+        T::PROMPT
+    }
+
+    fn eval_print(&mut self, input: &str, format: &Format) -> Option<Timings> {
+        eval_print(self, input, format)
+    }
+
+    fn undo(&mut self) -> bool {
+        Session::undo(self)
+    }
+
+    fn print_history(&self, format: &Format) {
+        print_history(self, format)
+    }
+
+    fn print_vars(&self, format: &Format) {
+        print_vars(self, format)
+    }
+
+    fn print_funcs(&self) {
+        print_funcs(self)
+    }
+
+    fn explain(&self, expr: &str, format: &Format) {
+        print_explain(self, expr, format)
+    }
+
+    fn save(&self, path: &Path) -> io::Result<usize> {
+        Session::save(self, io::BufWriter::new(fs::File::create(path)?))?;
+        Ok(self.len())
+    }
+
+    /// Replace the session by the saved one, the number of its
+    /// expressions.
+    fn load(&mut self, path: &Path, format: &Format) -> io::Result<usize> {
+        let calc = Calculator::new()
+            .warnings_as_errors(format.warnings_as_errors)
+            .angle_unit(format.angle_unit)
+            .rounding(format.rounding);
+        *self = Session::load(calc, io::BufReader::new(fs::File::open(path)?))?;
+        Ok(self.len())
+    }
+
+    fn set_warnings_as_errors(&mut self, warnings_as_errors: bool) {
+        Session::set_warnings_as_errors(self, warnings_as_errors)
+    }
+
+    fn set_angle_unit(&mut self, angle_unit: AngleUnit) {
+        Session::set_angle_unit(self, angle_unit)
+    }
+}
+
+/// Session of the backend with the definitions.
+fn backend<T: Value + 'static>(format: &Format, definitions: &[String], report: bool) -> Box<dyn Backend> {
+    let mut session = session::<T>(format);
+    define(&mut session, definitions, report);
+    Box::new(session)
+}
+
+/// Backend of the REPL input, such as `f64`, `f` or `:backend f64`.
+fn switch_backend(input: &str) -> Option<Result<Mode, String>> {
+    match input.strip_prefix(":backend") {
+        Some(name) if name.is_empty() || name.starts_with(' ') => {
+            let name = name.trim();
+            Some(Mode::from_name(name).ok_or_else(|| {
+                let names: Vec<_> = Mode::value_variants().iter().map(|mode| mode.name()).collect();
+                format!("Backend must be one of {}", names.join(", "))
+            }))
+        }
+        _ => Mode::from_name(input).map(Ok),
+    }
+}
+
+fn interactive_mod(mode: Mode, format: Format, definitions: &[String]) {
+    let mut mode = mode;
+    let mut format = format;
+    // One session per backend, in the order of `Mode`
+    let mut backends: Vec<_> = Mode::value_variants().iter()
+        .map(|&backend_mode| with_mode!(backend_mode, backend(&format, definitions, backend_mode == mode)))
+        .collect();
+    let mut editor = Editor::<color::Helper, DefaultHistory>::new().expect("Failed to open the terminal");
+    editor.set_helper(Some(color::Helper));
+    loop {
+        let prompt = backends[mode as usize].prompt();
+        let input = match editor.readline(prompt) {
+            Ok(input) => input,
+            // Ctrl-C drops the line
+            Err(ReadlineError::Interrupted) => continue,
+            // Ctrl-D quits
+            Err(ReadlineError::Eof) => {
+                println!("Bye!");
+                return
+            }
+            Err(err) => panic!("Failed to read line: {}", err),
+        };
+        let input = input.trim();
+        if !input.is_empty() {
+            let _ = editor.add_history_entry(input);
+        }
+
+        if let Some(backend) = switch_backend(input) {
+            match backend {
+                Ok(backend) => {
+                    println!("Enter {} mod", backend.name());
+                    mode = backend
+                }
+                Err(err) => println!("{}", err),
+            }
+            continue
+        }
+        match input {
+            "" => {}
+            "q" | "quit" => {
+                println!("Bye!");
+                return
+            }
+            "undo" | ":undo" => {
+                if !backends[mode as usize].undo() {
+                    println!("Nothing to undo");
+                }
+            }
+            "history" => backends[mode as usize].print_history(&format),
+            ":copy" => match clipboard::copy_last() {
+                Ok(text) => println!("Copied {}", text),
+                Err(err) => println!("{}", color::error(&err)),
+            },
+            ":vars" => backends[mode as usize].print_vars(&format),
+            ":funcs" => backends[mode as usize].print_funcs(),
+            "h" | "help" => {
+                for backend in Mode::value_variants() {
+                    let value = backend.to_possible_value().expect("Backends aren't hidden");
+                    let help = value.get_help().map(|help| format!(", {}", help)).unwrap_or_default();
+                    let alias = value.get_name_and_aliases().last().unwrap_or_default();
+                    println!("{}\tEnter {} mod{}", alias, value.get_name(), help.to_lowercase());
+                }
+                println!(":backend NAME\tEnter the mod of the backend, such as `:backend decimal`");
+                println!("quit");
+                println!("q\tQuit");
+                println!("history\tList the expressions of the mod");
+                println!("undo\tDiscard the assignments of the last expression");
+                println!(":copy\tPut the last result on the clipboard");
+                println!(":vars\tList the variables of the mod with their values and type");
+                println!(":funcs\tList the functions that can be called with their arguments");
+                println!(":save FILE\tSave the variables and the history of the mod as JSON");
+                println!(":load FILE\tReplace the variables and the history of the mod by the saved ones");
+                println!(":explain EXPR\tPrint the steps of the calculation, one operation per line");
+                println!(":tokens EXPR\tPrint the tokens of the expression");
+                println!(":ast EXPR\tPrint the tree of the expression, such as (+ 1 (* 2 3))");
+                println!(":base N\tPrint i128 results in base 2, 8, 10 or 16");
+                println!(":prec N\tPrint f64 results with N decimals, `:prec auto` to reset");
+                println!(":time on\tPrint the durations of the stages, `:time off` to stop");
+                println!(":strict on\tFail the expressions that raise a warning, `:strict off` to stop");
+                println!(":deg\tTake and return the angles of sin, cos and tan in degrees, `:rad` for radians, `:angle` to show the unit");
+                println!();
+                print_grammar();
+            }
+            cmd if cmd.starts_with(":save") => {
+                match cmd[":save".len()..].trim() {
+                    "" => println!("Save needs a file, such as `:save session.json`"),
+                    path => match backends[mode as usize].save(Path::new(path)) {
+                        Ok(len) => println!("Saved {} to {}", expressions(len), path),
+                        Err(err) => println!("{}", color::error(&format!("Failed to save {}: {}", path, err))),
+                    }
+                }
+            }
+            cmd if cmd.starts_with(":load") => {
+                match cmd[":load".len()..].trim() {
+                    "" => println!("Load needs a file, such as `:load session.json`"),
+                    path => match backends[mode as usize].load(Path::new(path), &format) {
+                        Ok(len) => println!("Loaded {} from {}", expressions(len), path),
+                        Err(err) => println!("{}", color::error(&format!("Failed to load {}: {}", path, err))),
+                    }
+                }
+            }
+            cmd if cmd.starts_with(":explain") => {
+                match cmd[":explain".len()..].trim() {
+                    "" => println!("Explain needs an expression, such as `:explain 1 + 2 * 3`"),
+                    expr => backends[mode as usize].explain(expr, &format),
+                }
+            }
+            cmd if cmd.starts_with(":tokens") => print_tokens(cmd[":tokens".len()..].trim_start()),
+            cmd if cmd.starts_with(":ast") => print_ast(cmd[":ast".len()..].trim_start()),
+            cmd if cmd.starts_with(":time") => {
+                match cmd[":time".len()..].trim() {
+                    "" => println!("Time {}", if format.time { "on" } else { "off" }),
+                    "on" => format.time = true,
+                    "off" => format.time = false,
+                    _ => println!("Time must be on or off"),
+                }
+            }
+            cmd if cmd.starts_with(":strict") => {
+                let strict = match cmd[":strict".len()..].trim() {
+                    "" => {
+                        println!("Strict {}", if format.warnings_as_errors { "on" } else { "off" });
+                        continue
+                    }
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        println!("Strict must be on or off");
+                        continue
+                    }
+                };
+                format.warnings_as_errors = strict;
+                for backend in backends.iter_mut() {
+                    backend.set_warnings_as_errors(strict);
+                }
+            }
+            ":angle" => println!("Angles in {}", if format.angle_unit == AngleUnit::Degree { "degrees" } else { "radians" }),
+            ":deg" | ":rad" => {
+                format.angle_unit = input[1..].parse().expect("deg and rad are units");
+                for backend in backends.iter_mut() {
+                    backend.set_angle_unit(format.angle_unit);
+                }
+            }
+            cmd if cmd.starts_with(":base") => {
+                match cmd[":base".len()..].trim() {
+                    "" => println!("Base {}", format.base),
+                    base => match base.parse() {
+                        Ok(base @ (2 | 8 | 10 | 16)) => format.base = base,
+                        _ => println!("Base must be 2, 8, 10 or 16"),
+                    }
+                }
+            }
+            cmd if cmd.starts_with(":prec") => {
+                match cmd[":prec".len()..].trim() {
+                    "" => match format.precision {
+                        Some(precision) => println!("Precision {}", precision),
+                        None => println!("Precision auto"),
+                    },
+                    "auto" => format.precision = None,
+                    precision => match precision.parse() {
+                        Ok(precision) => format.precision = Some(precision),
+                        Err(_) => println!("Precision must be a number or auto"),
+                    }
+                }
+            }
+            _ => {
+                let timings = backends[mode as usize].eval_print(input, &format);
+                print_timings(timings);
+            }
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    // Before the configuration, which can't change the script
+    if let Some(Command::Completions{shell}) = args.command {
+        clap_complete::generate(shell, &mut Args::command(), "wcal", &mut io::stdout());
+        return
+    }
+    let config = Config::load(args.config.as_deref()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        Status::IO.exit()
+    });
+    color::init(args.no_color || config.color == Some(false));
+    let format = Format::new(&args, &config);
+    let mode = mode(&args, &config);
+    if let Some(path) = &args.audit {
+        audit::open(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            Status::IO.exit()
+        });
+    }
+    if args.rpc {
+        rpc::serve(format).exit();
+    }
+    match &args.command {
+        Some(Command::Watch{file}) => with_mode!(mode, watch::watch(file, &format)).exit(),
+        #[cfg(all(feature = "mmap", unix))]
+        Some(Command::Run{script, mmap: true}) => {
+            let file = fs::File::open(script).unwrap_or_else(|err| {
+                eprintln!("Failed to read {}: {}", script.display(), err);
+                Status::IO.exit()
+            });
+            output::header(&format);
+            let path = script.display().to_string();
+            let status = with_mode!(mode, mmap::run(&path, &file, &format));
+            output::footer(&format);
+            status.exit()
+        }
+        Some(Command::Run{script, ..}) => {
+            let source = fs::read_to_string(script).unwrap_or_else(|err| {
+                eprintln!("Failed to read {}: {}", script.display(), err);
+                Status::IO.exit()
+            });
+            output::header(&format);
+            let path = script.display().to_string();
+            let status = with_mode!(mode, script::run(&path, &source, &format));
+            output::footer(&format);
+            status.exit()
+        }
+        Some(Command::Ast{expr, format, output}) => export_ast(expr, *format, output.as_deref()).exit(),
+        Some(Command::Diff{a, b}) => diff_exprs(a, b).exit(),
+        Some(Command::Fmt{exprs, files, in_place, lowercase_hex}) => {
+            let style = Style{lowercase_hex: *lowercase_hex};
+            fmt::fmt(exprs, files, *in_place, &style).exit()
+        }
+        Some(Command::Check{files, lint}) => check::check(files, *lint).exit(),
+        Some(Command::Map{expr, column, delimiter}) => {
+            let fields = map::Fields{column: column.map(|column| column as usize), delimiter: *delimiter};
+            with_mode!(mode, map::map(expr, &fields, &format)).exit()
+        }
+        Some(Command::Bench{expr, runs, duration, backend, parser}) => {
+            let limit = match duration {
+                Some(secs) if secs.is_finite() && *secs >= 0.0 => bench::Limit::Duration(std::time::Duration::from_secs_f64(*secs)),
+                Some(_) => {
+                    eprintln!("{}", color::error("The duration must be a number of seconds"));
+                    Status::USAGE.exit()
+                }
+                None => bench::Limit::Runs(runs.unwrap_or(10_000)),
+            };
+            let backends = if backend.is_empty() { Mode::value_variants() } else { backend.as_slice() };
+            let parsers = if parser.is_empty() {
+                // The parsers of the notation of the expression, or
+                // the default one to report its error
+                let parses = |kind: &&ParserKind| lexer::spanned(expr).and_then(kind.parse_fn()).is_ok();
+                let parsers: Vec<_> = ParserKind::value_variants().iter().filter(parses).copied().collect();
+                if parsers.is_empty() { vec![ParserKind::TopDown] } else { parsers }
+            } else {
+                parser.clone()
+            };
+            bench::bench(expr, backends, &parsers, limit).exit()
+        }
+        Some(Command::Conv{value, to}) => conv::conv(value, to, &format).exit(),
+        Some(Command::Expand{value, ops, count, seed}) => expand::run(value, *ops, *count, *seed).exit(),
+        Some(Command::Completions{..}) | None => {}
+    }
+    #[cfg(feature = "server")]
+    if let Some(addr) = &args.serve {
+        server::serve(addr, format).exit();
+    }
+    #[cfg(feature = "jupyter")]
+    if let Some(path) = &args.jupyter_kernel {
+        jupyter::kernel(path, mode, &format).exit();
+    }
+    if args.files.is_empty() && args.exprs.is_empty() {
+        if io::stdin().is_terminal() {
+            interactive_mod(mode, format, &config.definitions);
+            return
+        }
+        output::header(&format);
+        let mut status = with_mode!(mode, filter_mod(&format));
+        output::footer(&format);
+        if args.copy {
+            status.merge(copy_last());
+        }
+        status.exit();
+    }
+
+    output::header(&format);
+    let mut status = Status::default();
+    for path in args.files.iter() {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Failed to read {}: {}", path.display(), err);
+                output::footer(&format);
+                Status::IO.exit();
+            }
+        };
+        let path = path.display().to_string();
+        status.merge(with_mode!(mode, file_mod(&path, &source, &format)));
+    }
+    status.merge(with_mode!(mode, cmd_mod(&args, &format)));
+    output::footer(&format);
+    if args.copy {
+        status.merge(copy_last());
+    }
+    status.exit();
+}
@@ -0,0 +1,112 @@
+//! `wcal map EXPR`, calculate the expression for each number of
+//! stdin, bound to `x`.
+//!
+//! The expression is parsed once. Each line is a number, or with
+//! `--column N` a row whose N-th field is replaced by the result:
+//! ```text
+//! $ printf 'apple,120\npear,80\n' | wcal map 'x*11/10' --column 2
+//! apple,132
+//! pear,88
+//! ```
+//! A field that isn't a number, such as the header of a CSV file,
+//! leaves its line unchanged with `--column`, and skips it without.
+use std::io::{self, BufRead};
+
+use wcal::{diagnostics, Calculator, Environment, Error};
+use wcal::parser::ast::AST;
+
+use crate::{color, Status};
+use crate::format::{Format, Value};
+
+/// Name of the variable bound to each number.
+const VAR: &str = "x";
+
+/// Where the numbers are in the lines.
+pub struct Fields {
+    /// Field from 1, the whole line without it
+    pub column: Option<usize>,
+    pub delimiter: char,
+}
+
+/// Calculate the expression for each line of stdin.
+pub fn map<T: Value>(expr: &str, fields: &Fields, format: &Format) -> Status {
+    let calc = Calculator::<T>::new()
+        .warnings_as_errors(format.warnings_as_errors)
+        .angle_unit(format.angle_unit)
+        .rounding(format.rounding);
+    let ast = match calc.parse(expr) {
+        Ok(ast) => ast,
+        Err(err) => {
+            eprintln!("{}", color::error(&diagnostics::render(expr, &err)));
+            return Status::of(&err)
+        }
+    };
+    let mut status = Status::default();
+    for (i, line) in io::stdin().lock().lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("Failed to read stdin: {}", err);
+                return Status::IO
+            }
+        };
+        if line.trim().is_empty() {
+            continue
+        }
+        match map_line(&calc, &ast, &line, fields, format) {
+            Ok(Some(text)) => println!("{}", text),
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("{}", color::error(&format!("<stdin>:{}: {}", i + 1, err.message())));
+                status.merge(Status::of(&err));
+            }
+        }
+    }
+    status
+}
+
+/// Line with the result of the expression, `None` for a line to
+/// skip.
+fn map_line<T: Value>(calc: &Calculator<T>, ast: &AST, line: &str, fields: &Fields, format: &Format) -> Result<Option<String>, Error> {
+    let mut row: Vec<&str> = match fields.column {
+        Some(_) => line.split(fields.delimiter).collect(),
+        None => vec![line],
+    };
+    let index = fields.column.map_or(0, |column| column - 1);
+    let value = match row.get(index).map(|field| T::parse(field.trim())) {
+        Some(Ok(value)) => value,
+        _ if fields.column.is_some() => return Ok(Some(line.to_string())),
+        _ => return Ok(None),
+    };
+    let mut env = Environment::new();
+    env.set(VAR, value);
+    let res = T::from_ast_in(ast.clone(), &mut calc.context().env(&mut env))?;
+    let text = res.format(format);
+    row[index] = &text;
+    Ok(Some(row.join(&fields.delimiter.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_line() {
+        let format = Format::default();
+        let calc = Calculator::<f64>::new();
+        let ast = calc.parse("x*11/10").unwrap();
+        let line = |line: &str, column: Option<usize>| {
+            map_line(&calc, &ast, line, &Fields{column, delimiter: ','}, &format)
+        };
+        assert_eq!(line("120", None), Ok(Some("132".to_string())));
+        assert_eq!(line(" 2.5 ", None), Ok(Some("2.75".to_string())));
+        assert_eq!(line("total", None), Ok(None));
+        assert_eq!(line("pear,80,kg", Some(2)), Ok(Some("pear,88,kg".to_string())));
+        assert_eq!(line("name,price", Some(2)), Ok(Some("name,price".to_string())));
+        assert_eq!(line("pear", Some(2)), Ok(Some("pear".to_string())));
+
+        let ast = calc.parse("x/y").unwrap();
+        let err = map_line(&calc, &ast, "1", &Fields{column: None, delimiter: ','}, &format).unwrap_err();
+        assert_eq!(err.message(), "Unknown variable y");
+    }
+}
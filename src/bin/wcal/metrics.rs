@@ -0,0 +1,111 @@
+//! Metrics of `wcal --serve`, exposed at `GET /metrics` in the
+//! Prometheus text format.
+//!
+//! ```text
+//! $ curl localhost:8080/metrics
+//! # HELP wcal_evaluations_total Expressions calculated.
+//! # TYPE wcal_evaluations_total counter
+//! wcal_evaluations_total 3
+//! ...
+//! ```
+use std::fmt::Write;
+use std::time::Duration;
+
+use wcal::Error;
+
+/// Content type of the Prometheus text format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Upper bounds of the latency buckets, in seconds.
+const BUCKETS: [f64; 7] = [0.000_01, 0.000_1, 0.001, 0.01, 0.1, 1.0, 10.0];
+
+/// Kinds of errors, the `kind` label of `wcal_errors_total`.
+const KINDS: [&str; 3] = ["lex", "parse", "eval"];
+
+/// Counters since the server started.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    evaluations: u64,
+    /// By kind, in the order of `KINDS`
+    errors: [u64; 3],
+    warnings: u64,
+    /// Evaluations of each bucket, not cumulated
+    buckets: [u64; BUCKETS.len()],
+    latency_sum: f64,
+}
+
+impl Metrics {
+    /// Count an evaluation with its latency, error and number of
+    /// warnings.
+    pub fn record(&mut self, latency: Duration, error: Option<&Error>, warnings: usize) {
+        self.evaluations += 1;
+        if let Some(err) = error {
+            let kind = match err {
+                Error::Lex(_) => 0,
+                Error::Parse(_) => 1,
+                Error::Eval(_) => 2,
+            };
+            self.errors[kind] += 1;
+        }
+        self.warnings += warnings as u64;
+        let secs = latency.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| secs <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.latency_sum += secs;
+    }
+
+    /// Metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        header(&mut out, "wcal_evaluations_total", "counter", "Expressions calculated.");
+        let _ = writeln!(out, "wcal_evaluations_total {}", self.evaluations);
+        header(&mut out, "wcal_errors_total", "counter", "Expressions that failed, by kind of error.");
+        for (kind, count) in KINDS.iter().zip(self.errors.iter()) {
+            let _ = writeln!(out, "wcal_errors_total{{kind=\"{}\"}} {}", kind, count);
+        }
+        header(&mut out, "wcal_warnings_total", "counter", "Warnings raised by the expressions.");
+        let _ = writeln!(out, "wcal_warnings_total {}", self.warnings);
+        header(&mut out, "wcal_evaluation_duration_seconds", "histogram", "Time to lex, parse and calculate an expression.");
+        let mut cumulated = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulated += count;
+            let _ = writeln!(out, "wcal_evaluation_duration_seconds_bucket{{le=\"{}\"}} {}", bound, cumulated);
+        }
+        let _ = writeln!(out, "wcal_evaluation_duration_seconds_bucket{{le=\"+Inf\"}} {}", self.evaluations);
+        let _ = writeln!(out, "wcal_evaluation_duration_seconds_sum {}", self.latency_sum);
+        let _ = writeln!(out, "wcal_evaluation_duration_seconds_count {}", self.evaluations);
+        out
+    }
+}
+
+/// Help and type lines of a metric.
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wcal::error::EvalError;
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::default();
+        metrics.record(Duration::from_micros(5), None, 1);
+        metrics.record(Duration::from_millis(5), Some(&EvalError::DivisionByZero{span: 0..3}.into()), 0);
+        metrics.record(Duration::from_secs(20), None, 2);
+        let text = metrics.render();
+        assert!(text.contains("# TYPE wcal_evaluations_total counter\nwcal_evaluations_total 3\n"), "{}", text);
+        assert!(text.contains("wcal_errors_total{kind=\"lex\"} 0\n"));
+        assert!(text.contains("wcal_errors_total{kind=\"eval\"} 1\n"));
+        assert!(text.contains("wcal_warnings_total 3\n"));
+        assert!(text.contains("wcal_evaluation_duration_seconds_bucket{le=\"0.00001\"} 1\n"), "{}", text);
+        assert!(text.contains("wcal_evaluation_duration_seconds_bucket{le=\"0.001\"} 1\n"));
+        assert!(text.contains("wcal_evaluation_duration_seconds_bucket{le=\"0.01\"} 2\n"));
+        assert!(text.contains("wcal_evaluation_duration_seconds_bucket{le=\"10\"} 2\n"));
+        assert!(text.contains("wcal_evaluation_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("wcal_evaluation_duration_seconds_count 3\n"));
+    }
+}
@@ -0,0 +1,167 @@
+//! `wcal run --mmap SCRIPT`, a script too large to read in memory,
+//! such as the formulas dumped by a generator.
+//!
+//! The file is mapped rather than read, and run a chunk of whole
+//! lines at a time. The pages of a chunk are given back once its
+//! lines are run, and the history of the session isn't kept, so
+//! `ans` and `$1` are unknown. The memory stays about a chunk
+//! whatever the size of the file, the variables and the functions
+//! aside.
+//!
+//! The file must not be truncated while it runs.
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::os::unix::io::AsRawFd;
+use std::{ptr, slice, str};
+
+use crate::format::{Format, Value};
+use crate::{session, Status};
+use crate::script;
+
+/// Bytes of the lines run between the pages given back.
+const CHUNK: usize = 64 << 20;
+
+/// A file mapped read-only.
+struct Mapped {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapped {
+    fn open(file: &File) -> io::Result<Self> {
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large to map"))?;
+        // A mapping can't be empty
+        if len == 0 {
+            return Ok(Mapped{ptr: ptr::null_mut(), len})
+        }
+        // SAFETY: a private read-only mapping of the whole file, unmapped on drop
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error())
+        }
+        // SAFETY: the range is the mapping, the advice only changes the read ahead
+        unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+        Ok(Mapped{ptr, len})
+    }
+
+    fn bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[]
+        }
+        // SAFETY: the mapping is `len` bytes, readable until dropped
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+
+    /// Give back the pages of the range, read again from the file
+    /// if used again.
+    fn release(&self, range: Range<usize>) {
+        if self.len == 0 {
+            return
+        }
+        // SAFETY: `sysconf` has no preconditions
+        let page = usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).unwrap_or(4096);
+        // The page of the start may hold the lines before it too
+        let start = range.start - range.start % page;
+        // SAFETY: the pages are in the mapping, which is private and
+        // never written, so they read the same afterwards
+        unsafe { libc::madvise(self.ptr.cast::<u8>().add(start).cast(), range.end - start, libc::MADV_DONTNEED) };
+    }
+}
+
+impl Drop for Mapped {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: mapped by `open`, and the borrows of `bytes` ended
+            unsafe { libc::munmap(self.ptr, self.len) };
+        }
+    }
+}
+
+/// Ranges of at least `size` bytes of whole lines, the last one
+/// shorter, and a line longer than `size` in one range.
+fn chunks(bytes: &[u8], size: usize) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        if start == bytes.len() {
+            return None
+        }
+        let end = match bytes[(start + size).min(bytes.len())..].iter().position(|b| *b == b'\n') {
+            Some(i) => (start + size).min(bytes.len()) + i + 1,
+            None => bytes.len(),
+        };
+        let chunk = start..end;
+        start = end;
+        Some(chunk)
+    })
+}
+
+/// Run the mapped script, print the results of the expressions.
+pub fn run<T: Value>(path: &str, file: &File, format: &Format) -> Status {
+    let mapped = match Mapped::open(file) {
+        Ok(mapped) => mapped,
+        Err(err) => {
+            eprintln!("Failed to map {}: {}", path, err);
+            return Status::IO
+        }
+    };
+    let bytes = mapped.bytes();
+    let mut session = session::<T>(format);
+    let mut line_no = 0;
+    for chunk in chunks(bytes, CHUNK) {
+        let source = match str::from_utf8(&bytes[chunk.clone()]) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Failed to read {}: {}", path, err);
+                return Status::IO
+            }
+        };
+        for line in source.lines() {
+            line_no += 1;
+            if let Err(status) = script::run_line(&mut session, path, line_no, line, format) {
+                return status
+            }
+            session.clear();
+        }
+        mapped.release(chunk);
+    }
+    Status::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_chunks() {
+        let bytes = b"1+2\n30*4\n\n5\n6";
+        let found: Vec<_> = chunks(bytes, 4).map(|chunk| &bytes[chunk]).collect();
+        assert_eq!(found, [&b"1+2\n30*4\n"[..], b"\n5\n6"]);
+        // A line longer than the chunks
+        let found: Vec<_> = chunks(bytes, 1).map(|chunk| &bytes[chunk]).collect();
+        assert_eq!(found, [&b"1+2\n"[..], b"30*4\n", b"\n5\n", b"6"]);
+        assert_eq!(chunks(b"", 4).count(), 0);
+        assert_eq!(chunks(b"x\n", 4).next(), Some(0..2));
+    }
+
+    #[test]
+    fn test_mapped() {
+        let path = std::env::temp_dir().join(format!("wcal-mmap-{}.calc", std::process::id()));
+        let source = "rate = 3\n".to_string() + &"rate * 2\n".repeat(4096);
+        File::create(&path).unwrap().write_all(source.as_bytes()).unwrap();
+        let mapped = Mapped::open(&File::open(&path).unwrap()).unwrap();
+        assert_eq!(mapped.bytes(), source.as_bytes());
+        // The pages given back read the same
+        mapped.release(9..source.len() / 2);
+        assert_eq!(mapped.bytes(), source.as_bytes());
+        drop(mapped);
+
+        File::create(&path).unwrap();
+        assert!(Mapped::open(&File::open(&path).unwrap()).unwrap().bytes().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
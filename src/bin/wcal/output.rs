@@ -0,0 +1,221 @@
+//! Structured output of the evaluations, for `--json` and
+//! `--format`.
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use serde_json::{json, Value as Json};
+
+use wcal::{Calculator, Error, SourceMap};
+use wcal::error::Warning;
+
+use crate::clipboard;
+use crate::format::{Format, Value};
+
+/// Output of the evaluations.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// Results and rendered errors
+    Text,
+    /// One JSON object per evaluation
+    Json,
+    /// `expression,result,error` rows
+    Csv,
+    /// `expression<TAB>result<TAB>error` rows
+    Tsv,
+    /// `| expression | result |` table, the warnings as footnotes
+    Markdown,
+}
+
+/// Warnings of the Markdown table, printed after it by [`footer`].
+static FOOTNOTES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Print the header of the rows, if any.
+pub fn header(format: &Format) {
+    match format.output {
+        Output::Csv => println!("expression,result,error"),
+        Output::Tsv => println!("expression\tresult\terror"),
+        Output::Markdown => println!("| expression | result |\n|---|---|"),
+        Output::Text | Output::Json => {}
+    }
+}
+
+/// Print what follows the rows, if any, such as the footnotes of
+/// the Markdown table.
+pub fn footer(format: &Format) {
+    if format.output != Output::Markdown {
+        return
+    }
+    let footnotes = FOOTNOTES.lock().unwrap_or_else(|err| err.into_inner());
+    if !footnotes.is_empty() {
+        println!();
+    }
+    for (i, footnote) in footnotes.iter().enumerate() {
+        println!("[^{}]: {}", i + 1, footnote);
+    }
+}
+
+/// Print the evaluation in the structured output. Return `false`
+/// for the text output, which is printed by each mode.
+pub fn print<T: Value>(format: &Format, expr: &str, line: Option<usize>, result: &Result<T, Error>, warnings: &[Warning]) -> bool {
+    let value = result.as_ref().map(|res| (res.format(format), res.to_json()));
+    print_row(format, expr, line, T::TYPE, value, warnings)
+}
+
+/// Print the evaluation of a function whose result is a text, such
+/// as `hex(255)`, like [`print`]. Its type is `text`.
+pub fn print_text(format: &Format, expr: &str, line: Option<usize>, result: &Result<String, Error>) -> bool {
+    let value = result.as_ref().map(|text| (text.clone(), Json::from(text.as_str())));
+    print_row(format, expr, line, "text", value, &[])
+}
+
+/// Print the formatted value and the JSON value of the result.
+fn print_row(format: &Format, expr: &str, line: Option<usize>, kind: &str, result: Result<(String, Json), &Error>, warnings: &[Warning]) -> bool {
+    let (value, error) = match &result {
+        Ok((value, _)) => {
+            clipboard::record(value);
+            (value.clone(), String::new())
+        }
+        Err(err) => (String::new(), err.message()),
+    };
+    match format.output {
+        Output::Text => return false,
+        Output::Json => println!("{}", object(expr, line, kind, result.map(|(_, json)| json), warnings)),
+        Output::Csv => println!("{},{},{}", csv_field(expr.trim()), csv_field(&value), csv_field(&error)),
+        Output::Tsv => println!("{}\t{}\t{}", tsv_field(expr.trim()), tsv_field(&value), tsv_field(&error)),
+        Output::Markdown => {
+            let cell = if error.is_empty() { value } else { format!("Error: {}", error) };
+            let mut footnotes = FOOTNOTES.lock().unwrap_or_else(|err| err.into_inner());
+            println!("{}", markdown_row(expr.trim(), &cell, warnings, &mut footnotes));
+        }
+    }
+    true
+}
+
+/// One JSON object of an evaluation. `line` is the line of the
+/// expression in a file or stdin.
+///
+/// ```text
+/// {"expr":"7/2","type":"i128","value":3,"warnings":[{"message":"...","span":[1,2]}]}
+/// ```
+pub fn json<T: Value>(expr: &str, line: Option<usize>, result: &Result<T, Error>, warnings: &[Warning]) -> Json {
+    object(expr, line, T::TYPE, result.as_ref().map(Value::to_json), warnings)
+}
+
+/// Object of [`json`] of the JSON value of the result.
+fn object(expr: &str, line: Option<usize>, kind: &str, result: Result<Json, &Error>, warnings: &[Warning]) -> Json {
+    let warnings: Vec<Json> = warnings.iter()
+        .map(|warning| json!({"message": warning.to_string(), "span": span(warning.span())}))
+        .collect();
+    let mut object = json!({
+        "expr": expr,
+        "type": kind,
+        "warnings": warnings,
+    });
+    match result {
+        Ok(value) => object["value"] = value,
+        Err(err) => {
+            object["value"] = Json::Null;
+            let at = SourceMap::new(expr).location(err.span().start);
+            object["error"] = json!({
+                "message": err.message(),
+                "span": span(err.span()),
+                "location": {"line": at.line + 1, "column": at.column + 1},
+            });
+        }
+    }
+    if let Some(line) = line {
+        object["line"] = json!(line);
+    }
+    object
+}
+
+/// Calculate the expression, return the object of [`json`] with
+/// the formatted `text` of the result, and the error if any.
+pub fn calculate<T: Value>(calc: &Calculator<T>, expr: &str, format: &Format) -> (Json, Option<Error>) {
+    let mut ctx = calc.context();
+    let result = calc.parse(expr).and_then(|ast| T::from_ast_in(ast, &mut ctx));
+    let mut object = json(expr, None, &result, ctx.warnings());
+    match result {
+        Ok(res) => {
+            object["text"] = res.format(format).into();
+            (object, None)
+        }
+        Err(err) => (object, Some(err)),
+    }
+}
+
+pub fn span(span: wcal::lexer::Span) -> Json {
+    json!([span.start, span.end])
+}
+
+/// Quote the field if it has a comma, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Row of the Markdown table, the warnings are referenced as
+/// footnotes numbered after the `footnotes` before.
+fn markdown_row(expr: &str, cell: &str, warnings: &[Warning], footnotes: &mut Vec<String>) -> String {
+    let mut cell = markdown_field(cell);
+    for warning in warnings {
+        footnotes.push(markdown_field(&warning.to_string()));
+        cell.push_str(&format!(" [^{}]", footnotes.len()));
+    }
+    format!("| `{}` | {} |", markdown_field(expr), cell)
+}
+
+/// Pipes end the cells even in code, and line breaks the rows.
+fn markdown_field(field: &str) -> String {
+    field.replace('|', "\\|").replace(['\n', '\r'], " ")
+}
+
+/// TSV can't escape, tabs and line breaks become spaces.
+fn tsv_field(field: &str) -> String {
+    field.replace(['\t', '\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wcal::Session;
+
+    #[test]
+    fn test_json() {
+        let mut session = Session::<i128>::default();
+        let entry = session.eval("7/2");
+        assert_eq!(
+            json(&entry.expr, None, &entry.result, &entry.warnings).to_string(),
+            r#"{"expr":"7/2","type":"i128","value":3,"warnings":[{"message":"Division will cause a cast: 7/2","span":[0,3]}]}"#
+        );
+
+        let entry = session.eval("1+");
+        assert_eq!(
+            json(&entry.expr, Some(2), &entry.result, &entry.warnings).to_string(),
+            r#"{"error":{"location":{"column":3,"line":1},"message":"Expect number, got nothing","span":[2,2]},"expr":"1+","line":2,"type":"i128","value":null,"warnings":[]}"#
+        );
+    }
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(csv_field("1+2"), "1+2");
+        assert_eq!(csv_field("max(1, 2)"), "\"max(1, 2)\"");
+        assert_eq!(csv_field("a\"b"), "\"a\"\"b\"");
+        assert_eq!(tsv_field("1\t+\n2"), "1 + 2");
+    }
+
+    #[test]
+    fn test_markdown() {
+        let mut session = Session::<i128>::default();
+        let mut footnotes = Vec::new();
+        let entry = session.eval("1+2");
+        assert_eq!(markdown_row(&entry.expr, "3", &entry.warnings, &mut footnotes), "| `1+2` | 3 |");
+        let entry = session.eval("7/2 + 5/2");
+        assert_eq!(markdown_row(&entry.expr, "5", &entry.warnings, &mut footnotes), "| `7/2 + 5/2` | 5 [^1] [^2] |");
+        assert_eq!(footnotes, ["Division will cause a cast: 7/2", "Division will cause a cast: 5/2"]);
+        assert_eq!(markdown_row("a|b", "Error: x\ny", &[], &mut footnotes), "| `a\\|b` | Error: x y |");
+    }
+}
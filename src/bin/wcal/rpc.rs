@@ -0,0 +1,277 @@
+//! JSON-RPC 2.0 of `wcal --rpc`, one request per line of stdin
+//! and one response per line of stdout.
+//!
+//! Methods:
+//! * `eval`: `{"expr", "mode"?, "precision"?}`, the object of
+//!   `--json` with the formatted `text`
+//! * `parse`: `{"expr"}`, the tree as an S-expression in `ast`
+//! * `tokens`: `{"expr"}`, the tokens with their `category` and `span`
+//! * `format`: `{"value", "mode"?, "precision"?, "base"?, "prefix"?, "group"?, "separator"?}`,
+//!   the value formatted as `text`, a `bigint` or `decimal` value
+//!   can be a string of digits
+//!
+//! A failed expression is answered with the exit status of its
+//! error as the code, and the object of `--json` as the data:
+//! ```text
+//! --> {"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"expr": "1+"}}
+//! <-- {"error":{"code":4,"data":{...},"message":"Expect number, got nothing"},"id":1,"jsonrpc":"2.0"}
+//! ```
+use std::io::{self, BufRead, Write};
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value as Json};
+
+use wcal::{lexer, BigInt, Calculator, Decimal, Error, Matrix, Rational, Time};
+
+use crate::Status;
+use crate::config::{Base, Mode};
+use crate::format::{Format, Separator, Value};
+use crate::output;
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Json,
+    /// `None` for a notification, which is not answered
+    id: Option<Json>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExprParams {
+    expr: String,
+    #[serde(default)]
+    mode: Option<Mode>,
+    #[serde(default)]
+    precision: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FormatParams {
+    value: Json,
+    #[serde(default)]
+    mode: Option<Mode>,
+    #[serde(default)]
+    precision: Option<usize>,
+    #[serde(default)]
+    base: Option<Base>,
+    #[serde(default)]
+    prefix: Option<bool>,
+    #[serde(default)]
+    group: Option<u32>,
+    #[serde(default)]
+    separator: Option<Separator>,
+}
+
+/// Error of a method, the code and message of the JSON-RPC error.
+struct RpcError {
+    code: i32,
+    message: String,
+    data: Option<Json>,
+}
+
+impl RpcError {
+    fn new(code: i32, message: String) -> Self {
+        RpcError{code, message, data: None}
+    }
+
+    /// Error of an expression, with the object of `--json` as data.
+    fn calc(err: &Error, data: Json) -> Self {
+        RpcError{code: Status::of(err).0, message: err.message(), data: Some(data)}
+    }
+}
+
+/// Answer the requests until the end of stdin.
+pub fn serve(format: Format) -> Status {
+    let stdout = io::stdout();
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("Failed to read stdin: {}", err);
+                return Status::IO
+            }
+        };
+        if line.trim().is_empty() {
+            continue
+        }
+        if let Some(response) = handle(&line, &format) {
+            let mut stdout = stdout.lock();
+            if writeln!(stdout, "{}", response).and_then(|_| stdout.flush()).is_err() {
+                return Status::IO
+            }
+        }
+    }
+    Status::default()
+}
+
+/// Response to a line, `None` for a notification.
+fn handle(line: &str, format: &Format) -> Option<Json> {
+    let request: Json = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => return Some(response(Json::Null, Err(RpcError::new(PARSE_ERROR, err.to_string())))),
+    };
+    let request: Request = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(err) => return Some(response(Json::Null, Err(RpcError::new(INVALID_REQUEST, err.to_string())))),
+    };
+    let result = if request.jsonrpc != "2.0" {
+        Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"".to_string()))
+    } else {
+        call(&request.method, request.params, format)
+    };
+    request.id.map(|id| response(id, result))
+}
+
+fn response(id: Json, result: Result<Json, RpcError>) -> Json {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(err) => {
+            let mut error = json!({"code": err.code, "message": err.message});
+            if let Some(data) = err.data {
+                error["data"] = data;
+            }
+            json!({"jsonrpc": "2.0", "id": id, "error": error})
+        }
+    }
+}
+
+fn params<P: DeserializeOwned>(params: Json) -> Result<P, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+fn call(method: &str, params_: Json, format: &Format) -> Result<Json, RpcError> {
+    match method {
+        "eval" => {
+            let params: ExprParams = params(params_)?;
+            let format = Format{precision: params.precision.or(format.precision), ..*format};
+            with_mode!(params.mode.unwrap_or(Mode::Int), eval(&params.expr, &format))
+        }
+        "parse" => {
+            let params: ExprParams = params(params_)?;
+            Calculator::<i128>::new().parse(&params.expr)
+                .map(|ast| json!({"ast": ast.to_string()}))
+                .map_err(|err| RpcError::calc(&err, Json::Null))
+        }
+        "tokens" => {
+            let params: ExprParams = params(params_)?;
+            let mut tokens = Vec::new();
+            for token in lexer::tokens(&params.expr) {
+                let (token, span) = token.map_err(|err| RpcError::calc(&err, Json::Null))?;
+                tokens.push(json!({
+                    "token": format!("{:?}", token),
+                    "category": format!("{:?}", token.category()),
+                    "span": output::span(span),
+                }));
+            }
+            Ok(Json::Array(tokens))
+        }
+        "format" => {
+            let params: FormatParams = params(params_)?;
+            let format = Format{
+                precision: params.precision.or(format.precision),
+                base: params.base.map_or(format.base, |base| base.0),
+                prefix: params.prefix.unwrap_or(format.prefix),
+                group: params.group.or(format.group).or_else(|| params.separator.map(|_| 3)),
+                separator: params.separator.map_or(format.separator, Separator::char),
+                ..*format
+            };
+            with_mode!(params.mode.unwrap_or(Mode::Int), format_value(&params.value, &format))
+                .map(|text| json!({"text": text}))
+                .map_err(|err| RpcError::new(INVALID_PARAMS, err))
+        }
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+    }
+}
+
+fn format_value<T: Value>(value: &Json, format: &Format) -> Result<String, String> {
+    T::from_json(value).map(|value| value.format(format))
+}
+
+fn eval<T: Value>(expr: &str, format: &Format) -> Result<Json, RpcError> {
+    match output::calculate(&Calculator::<T>::new(), expr, format) {
+        (object, None) => Ok(object),
+        (object, Some(err)) => Err(RpcError::calc(&err, object)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpc(line: &str) -> String {
+        let format = Format::default();
+        handle(line, &format).map(|response| response.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_methods() {
+        assert_eq!(
+            rpc(r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"expr": "1/4", "mode": "f"}}"#),
+            r#"{"id":1,"jsonrpc":"2.0","result":{"expr":"1/4","text":"0.25","type":"f64","value":0.25,"warnings":[]}}"#
+        );
+        assert_eq!(
+            rpc(r#"{"jsonrpc": "2.0", "id": "a", "method": "parse", "params": {"expr": "1+2*3"}}"#),
+            r#"{"id":"a","jsonrpc":"2.0","result":{"ast":"(+ 1 (* 2 3))"}}"#
+        );
+        assert_eq!(
+            rpc(r#"{"jsonrpc": "2.0", "id": 2, "method": "tokens", "params": {"expr": "x+1"}}"#),
+            r#"{"id":2,"jsonrpc":"2.0","result":[{"category":"Identifier","span":[0,1],"token":"Ident(\"x\")"},{"category":"Operator","span":[1,2],"token":"Plus"},{"category":"Number","span":[2,3],"token":"Number(1)"}]}"#
+        );
+        assert_eq!(
+            rpc(r#"{"jsonrpc": "2.0", "id": 3, "method": "format", "params": {"value": 255, "base": 16, "prefix": true}}"#),
+            r#"{"id":3,"jsonrpc":"2.0","result":{"text":"0xff"}}"#
+        );
+        assert_eq!(
+            rpc(r#"{"jsonrpc": "2.0", "id": 4, "method": "eval", "params": {"expr": "123456789123456789123456789*987654321", "mode": "b"}}"#),
+            r#"{"id":4,"jsonrpc":"2.0","result":{"expr":"123456789123456789123456789*987654321","text":"121932631234567900234567900112635269","type":"bigint","value":"121932631234567900234567900112635269","warnings":[]}}"#
+        );
+        assert_eq!(
+            rpc(r#"{"jsonrpc": "2.0", "id": 5, "method": "format", "params": {"value": "1000000000000000000000", "mode": "b", "group": 3}}"#),
+            r#"{"id":5,"jsonrpc":"2.0","result":{"text":"1_000_000_000_000_000_000_000"}}"#
+        );
+        assert_eq!(
+            rpc(r#"{"jsonrpc": "2.0", "id": 6, "method": "format", "params": {"value": 1234567, "separator": "comma"}}"#),
+            r#"{"id":6,"jsonrpc":"2.0","result":{"text":"1,234,567"}}"#
+        );
+        assert_eq!(
+            rpc(r#"{"jsonrpc": "2.0", "id": 7, "method": "eval", "params": {"expr": "1/10*3", "mode": "decimal"}}"#),
+            r#"{"id":7,"jsonrpc":"2.0","result":{"expr":"1/10*3","text":"0.3","type":"decimal","value":"0.3","warnings":[]}}"#
+        );
+        assert_eq!(
+            rpc(r#"{"jsonrpc": "2.0", "id": 8, "method": "format", "params": {"value": "1234.50", "mode": "d", "precision": 1, "group": 3}}"#),
+            r#"{"id":8,"jsonrpc":"2.0","result":{"text":"1_234.5"}}"#
+        );
+        assert_eq!(rpc(r#"{"jsonrpc": "2.0", "method": "eval", "params": {"expr": "1"}}"#), "");
+    }
+
+    #[test]
+    fn test_errors() {
+        let code = |line: &str| -> i64 {
+            let response: Json = serde_json::from_str(&rpc(line)).unwrap();
+            response["error"]["code"].as_i64().unwrap()
+        };
+        assert_eq!(code(r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"expr": "1+"}}"#), 4);
+        assert_eq!(code(r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"expr": "1/0"}}"#), 5);
+        assert_eq!(code(r#"{"jsonrpc": "2.0", "id": 1, "method": "tokens", "params": {"expr": "1+$"}}"#), 3);
+        assert_eq!(code("{"), PARSE_ERROR as i64);
+        assert_eq!(code("[]"), INVALID_REQUEST as i64);
+        assert_eq!(code(r#"{"jsonrpc": "1.0", "id": 1, "method": "eval"}"#), INVALID_REQUEST as i64);
+        assert_eq!(code(r#"{"jsonrpc": "2.0", "id": 1, "method": "solve"}"#), METHOD_NOT_FOUND as i64);
+        assert_eq!(code(r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {}}"#), INVALID_PARAMS as i64);
+        assert_eq!(code(r#"{"jsonrpc": "2.0", "id": 1, "method": "format", "params": {"value": 1.5}}"#), INVALID_PARAMS as i64);
+
+        let response = rpc(r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"expr": "1+"}}"#);
+        assert!(response.contains(r#""data":{"error":{"location":{"column":3,"line":1},"message":"Expect number, got nothing","span":[2,2]}"#), "{}", response);
+    }
+}
@@ -0,0 +1,171 @@
+//! `wcal run SCRIPT`, a file of definitions and expressions
+//! calculated top to bottom in one session.
+//!
+//! ```text
+//! # Assignments and definitions print nothing
+//! rate = 3
+//! total(price, n) = price * n * (100 + rate) / 100
+//! total(20, 5)
+//! print(half = total(10, 1) / 2)
+//! ```
+//! Lines that are expressions print their result, `print(expr)`
+//! also prints the result of an assignment, and a call of a function
+//! whose result is a text such as `hex(255)` prints the text. The
+//! script stops at the first error.
+//!
+//! A function is defined by `name(params) = body`, see
+//! [`Session::define`]. It can call itself and the functions
+//! defined after it, errors in the body point at the call.
+use wcal::{diagnostics, lexer, Error, Session};
+use wcal::lexer::{Span, Token};
+use wcal::parser::{self, ast::*};
+
+use crate::{audit, color, output, session, strip_comment, Status};
+use crate::format::{Format, Value};
+
+/// Tokens of a line with their span.
+type Tokens = [(Token, Span)];
+
+/// Parse a line that isn't a definition, and whether it prints.
+fn parse_line<T>(expr: &str, session: &Session<T>) -> Result<(AST, bool), Error> {
+    let tokens = lexer::spanned(expr)?;
+    let (tokens, print) = match print_arg(&tokens) {
+        Some(arg) if session.env().function("print").is_none() => (arg.to_vec(), true),
+        _ => (tokens, false),
+    };
+    let ast = parser::top_down_parser::parse(tokens)?;
+    let print = print || !matches!(ast.root, Expr::Assign(_));
+    Ok((ast, print))
+}
+
+/// Tokens of the argument of a line `print(...)`, which can be an
+/// assignment.
+fn print_arg(tokens: &Tokens) -> Option<&Tokens> {
+    match (tokens.first(), tokens.get(1), tokens.last()) {
+        (Some((Token::Ident(name), _)), Some((Token::LP, _)), Some((Token::RP, _))) if name == "print" && tokens.len() > 3 => {}
+        _ => return None,
+    }
+    // The parenthesis after print closes at the end
+    let mut depth = 0;
+    for (i, (token, _)) in tokens.iter().enumerate().skip(1) {
+        match token {
+            Token::LP => depth += 1,
+            Token::RP => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 && i != tokens.len() - 1 {
+            return None
+        }
+    }
+    Some(&tokens[2..tokens.len() - 1])
+}
+
+/// Run the script, print the results of the expressions.
+pub fn run<T: Value>(path: &str, source: &str, format: &Format) -> Status {
+    let mut session = session::<T>(format);
+    for (i, line) in source.lines().enumerate() {
+        if let Err(status) = run_line(&mut session, path, i + 1, line, format) {
+            return status
+        }
+    }
+    Status::default()
+}
+
+/// Run the line numbered `line_no` of the script, print its result
+/// or its error, the status of the error.
+pub fn run_line<T: Value>(session: &mut Session<T>, path: &str, line_no: usize, line: &str, format: &Format) -> Result<(), Status> {
+    let expr = match strip_comment(line) {
+        Some(expr) => expr,
+        None => return Ok(()),
+    };
+    let fail = |err: &Error| {
+        eprintln!("{}", color::error(&diagnostics::render_file(path, line_no, expr, err)));
+        Status::of(err)
+    };
+    if let Some(text) = session.eval_text(expr) {
+        let text = text.map_err(|err| fail(&err))?;
+        if !output::print_text(format, expr, Some(line_no), &Ok(text.clone())) {
+            println!("{}", text);
+        }
+        return Ok(())
+    }
+    let result = match session.define(expr) {
+        Some(defined) => defined.map(|_| None),
+        None => parse_line(expr, session).and_then(|(ast, print)| {
+            let entry = session.eval_ast(expr, ast);
+            audit::record(expr, entry.ast.as_ref(), &entry.result, &entry.warnings, format);
+            match &entry.result {
+                Ok(_) => Ok(Some(entry).filter(|_| print)),
+                Err(err) => Err(err.clone()),
+            }
+        }),
+    };
+    match result {
+        Ok(Some(entry)) => {
+            if !output::print(format, expr, Some(line_no), &entry.result, &entry.warnings) {
+                if let Ok(res) = &entry.result {
+                    println!("{}", res.format(format));
+                }
+            }
+            Ok(())
+        }
+        Ok(None) => Ok(()),
+        Err(err) => Err(fail(&err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wcal::error::EvalError;
+
+    use super::*;
+
+    /// Results of the lines that print, or the first error.
+    fn run(source: &str) -> Result<Vec<i128>, Error> {
+        let mut session = Session::<i128>::default();
+        let mut results = Vec::new();
+        for line in source.lines() {
+            if let Some(defined) = session.define(line) {
+                defined?;
+                continue
+            }
+            let (ast, print) = parse_line(line, &session)?;
+            let res = session.eval_ast(line, ast).result.clone()?;
+            if print {
+                results.push(res);
+            }
+        }
+        Ok(results)
+    }
+    #[test]
+    fn test_run() {
+        let script = "rate = 3\ntotal(price, n) = price * n * (100 + rate) / 100\ntotal(20, 5)\nhalf = total(10, 1) / 2\nprint(half + 1)";
+        assert_eq!(run(script), Ok(vec![103, 6]));
+        assert_eq!(run("x = 2\nf() = x * 10\nx = 3\nf()"), Ok(vec![30]));
+        // Parameters shadow the variables, not the variables of
+        // the functions called
+        assert_eq!(run("x = 1\ng(y) = y + x\nf(x) = g(2 * x)\nf(5)"), Ok(vec![11]));
+        assert_eq!(run("sq(x) = x * x\nsq(1 + 2)\nprint(y = sq(sq(2)))\nprint((y) + (y))"), Ok(vec![9, 16, 32]));
+        assert_eq!(run("fact(n) = if n then n * fact(n - 1) else 1\nfact(10)"), Ok(vec![3628800]));
+        // Calls the function defined after it
+        assert_eq!(run("even(n) = if n then odd(n - 1) else 1\nodd(n) = if n then even(n - 1) else 0\neven(10)"), Ok(vec![1]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = run("f(x) = 1 / x\n1 + f(0)").unwrap_err();
+        assert!(matches!(err, Error::Eval(EvalError::DivisionByZero{..})));
+        assert_eq!(err.span(), 4..8);
+
+        let err = run("f(x) = x\nf(1, 2)").unwrap_err();
+        assert!(matches!(err, Error::Eval(EvalError::WrongArity{expected: 1, found: 2, ..})));
+
+        // A recursion without end
+        let err = run("f(x) = f(x)\nf(1)").unwrap_err();
+        assert!(matches!(err, Error::Eval(EvalError::CallTooDeep{..})));
+
+        assert!(run("f(1) = 2").is_err());
+        // Only a call to print around the whole line prints
+        assert!(matches!(run("print(1) + (2)"), Err(Error::Eval(EvalError::UnknownFunction{..}))));
+    }
+}
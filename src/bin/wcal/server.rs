@@ -0,0 +1,149 @@
+//! HTTP API of `wcal --serve`, only with the `server` feature.
+//!
+//! `POST /eval` takes a JSON object and answers with the object of
+//! `--json`, plus the result formatted as `text`:
+//! ```text
+//! $ curl -d '{"expr": "1/3", "mode": "f", "precision": 2}' localhost:8080/eval
+//! {"expr":"1/3","text":"0.33","type":"f64","value":0.3333333333333333,"warnings":[]}
+//! ```
+//! Expressions are calculated with [`Limits::strict`], an
+//! expression that fails is answered with status 422.
+//!
+//! `GET /metrics` answers the [`Metrics`] of the evaluations for
+//! Prometheus.
+use std::io::Read;
+use std::time::Instant;
+
+use serde::Deserialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Response, Server};
+
+use wcal::{BigInt, Calculator, Decimal, Limits, Matrix, Rational, Time};
+
+use crate::Status;
+use crate::config::Mode;
+use crate::format::{Format, Value};
+use crate::metrics::{self, Metrics};
+use crate::output;
+
+/// Largest body that is read, longer expressions fail the limits
+/// anyway.
+const MAX_BODY: u64 = 64 * 1024;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EvalRequest {
+    expr: String,
+    #[serde(default)]
+    mode: Option<Mode>,
+    #[serde(default)]
+    precision: Option<usize>,
+}
+
+/// Answer the requests until the server fails.
+pub fn serve(addr: &str, format: Format) -> Status {
+    let server = match Server::http(addr) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("Failed to listen on {}: {}", addr, err);
+            return Status::IO
+        }
+    };
+    eprintln!("Listening on http://{}", addr);
+    let mut metrics = Metrics::default();
+    for mut request in server.incoming_requests() {
+        let (status, content_type, body) = match (request.method(), request.url()) {
+            (Method::Get, "/metrics") => (200, metrics::CONTENT_TYPE, metrics.render()),
+            _ => {
+                let mut body = String::new();
+                let read = request.as_reader().take(MAX_BODY).read_to_string(&mut body);
+                let (status, body) = match read {
+                    Ok(_) => handle(request.method(), request.url(), &body, format, &mut metrics),
+                    Err(err) => (400, error(&format!("Invalid body: {}", err))),
+                };
+                (status, "application/json", body)
+            }
+        };
+        let header = Header::from_bytes("Content-Type", content_type).expect("valid header");
+        let response = Response::from_string(body).with_status_code(status).with_header(header);
+        if let Err(err) = request.respond(response) {
+            eprintln!("Failed to respond: {}", err);
+        }
+    }
+    Status::default()
+}
+
+/// Status and body of the response to a request.
+fn handle(method: &Method, url: &str, body: &str, format: Format, metrics: &mut Metrics) -> (u16, String) {
+    match (method, url) {
+        (Method::Post, "/eval") => {}
+        (_, "/eval") => return (405, error("Use POST /eval")),
+        (_, "/metrics") => return (405, error("Use GET /metrics")),
+        _ => return (404, error("Not found, use POST /eval or GET /metrics")),
+    }
+    let request: EvalRequest = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(err) => return (400, error(&format!("Invalid request: {}", err))),
+    };
+    let format = Format{precision: request.precision.or(format.precision), ..format};
+    with_mode!(request.mode.unwrap_or(Mode::Int), eval(&request.expr, &format, metrics))
+}
+
+fn eval<T: Value>(expr: &str, format: &Format, metrics: &mut Metrics) -> (u16, String) {
+    let calc = Calculator::<T>::new().limits(Limits::strict());
+    let start = Instant::now();
+    let (object, err) = output::calculate(&calc, expr, format);
+    let latency = start.elapsed();
+    let status = if err.is_some() { 422 } else { 200 };
+    let warnings = object["warnings"].as_array().map_or(0, Vec::len);
+    metrics.record(latency, err.as_ref(), warnings);
+    (status, object.to_string())
+}
+
+fn error(message: &str) -> String {
+    json!({"error": {"message": message}}).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Output;
+
+    #[test]
+    fn test_eval() {
+        let format = Format{output: Output::Json, ..Format::default()};
+        let mut metrics = Metrics::default();
+        let (status, body) = handle(&Method::Post, "/eval", r#"{"expr": "1/3", "mode": "f", "precision": 2}"#, format, &mut metrics);
+        assert_eq!(status, 200);
+        assert_eq!(body, r#"{"expr":"1/3","text":"0.33","type":"f64","value":0.3333333333333333,"warnings":[]}"#);
+
+        let (status, body) = handle(&Method::Post, "/eval", r#"{"expr": "255"}"#, Format{base: 16, ..format}, &mut metrics);
+        assert_eq!(status, 200);
+        assert_eq!(body, r#"{"expr":"255","text":"ff","type":"i128","value":255,"warnings":[]}"#);
+
+        let (status, body) = handle(&Method::Post, "/eval", r#"{"expr": "1/0"}"#, format, &mut metrics);
+        assert_eq!(status, 422);
+        assert!(body.contains(r#""error":{"location":{"column":1,"line":1},"message":"Division by zero""#), "{}", body);
+
+        let (status, _) = handle(&Method::Post, "/eval", &format!(r#"{{"expr": "{}"}}"#, "1+".repeat(1000)), format, &mut metrics);
+        assert_eq!(status, 422);
+
+        let text = metrics.render();
+        assert!(text.contains("wcal_evaluations_total 4\n"), "{}", text);
+        assert!(text.contains("wcal_errors_total{kind=\"eval\"} 1\n"), "{}", text);
+        assert!(text.contains("wcal_evaluation_duration_seconds_count 4\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_bad_request() {
+        let format = Format{output: Output::Json, ..Format::default()};
+        let mut metrics = Metrics::default();
+        assert_eq!(handle(&Method::Post, "/eval", "{", format, &mut metrics).0, 400);
+        assert_eq!(handle(&Method::Post, "/eval", r#"{"expr": "1", "mode": "x"}"#, format, &mut metrics).0, 400);
+        assert_eq!(handle(&Method::Get, "/eval", "", format, &mut metrics).0, 405);
+        assert_eq!(handle(&Method::Post, "/", "", format, &mut metrics).0, 404);
+        assert_eq!(handle(&Method::Post, "/metrics", "", format, &mut metrics).0, 405);
+        // Only the calculated expressions are counted
+        assert!(metrics.render().contains("wcal_evaluations_total 0\n"));
+    }
+}
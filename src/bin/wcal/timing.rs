@@ -0,0 +1,57 @@
+//! Durations of the stages of the calculator, for `--time`.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use wcal::{lexer, parser, Error};
+use wcal::parser::ast::AST;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timings {
+    pub lex: Duration,
+    pub parse: Duration,
+    pub eval: Duration,
+}
+
+impl Timings {
+    /// Lex the expression then parse the tokens, timing both.
+    pub fn parse(&mut self, expr: &str, parse: parser::ParseFn) -> Result<AST, Error> {
+        let start = Instant::now();
+        let tokens = lexer::spanned(expr);
+        self.lex = start.elapsed();
+
+        let start = Instant::now();
+        let ast = tokens.and_then(parse);
+        self.parse = start.elapsed();
+        ast
+    }
+
+    /// Time the evaluation.
+    pub fn eval<R>(&mut self, eval: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let res = eval();
+        self.eval = start.elapsed();
+        res
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "lex {:?}, parse {:?}, eval {:?}", self.lex, self.parse, self.eval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings() {
+        let mut timings = Timings::default();
+        assert!(timings.parse("1+", parser::top_down_parser::parse).is_err());
+        let ast = timings.parse("1+2", parser::top_down_parser::parse).unwrap();
+        assert_eq!(timings.eval(|| wcal::FromAST::from_ast(ast)), Ok(3i128));
+
+        let text = Timings{lex: Duration::from_micros(2), parse: Duration::from_nanos(1500), eval: Duration::ZERO}.to_string();
+        assert_eq!(text, "lex 2µs, parse 1.5µs, eval 0ns");
+    }
+}
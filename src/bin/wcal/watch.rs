@@ -0,0 +1,125 @@
+//! `wcal watch FILE`, calculate the file again each time it changes.
+//!
+//! The results are printed like `--file`, a result that differs
+//! from the one of the same line before the change is followed by
+//! the previous one:
+//! ```text
+//! costs.calc:3: 1250 (was 1200)
+//! ```
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use wcal::diagnostics;
+
+use crate::{color, session, strip_comment, Status};
+use crate::format::{Format, Value};
+
+/// Interval between two checks of the modification time.
+const POLL: Duration = Duration::from_millis(300);
+
+/// Result of a line of the file.
+#[derive(Debug, PartialEq)]
+struct Line {
+    /// Number of the line, from 1
+    no: usize,
+    /// The result formatted, or the diagnostic of the error
+    text: String,
+    ok: bool,
+}
+
+/// Calculate the file each time its modification time changes,
+/// until it can't be read the first time.
+pub fn watch<T: Value>(path: &Path, format: &Format) -> Status {
+    let name = path.display().to_string();
+    let mut modified: Option<SystemTime> = None;
+    let mut previous: Option<Vec<Line>> = None;
+    loop {
+        // Editors may replace the file on save, so it can be missing
+        // for a moment after the first read
+        let time = fs::metadata(path).and_then(|meta| meta.modified());
+        let changed = match (&time, modified) {
+            (Ok(time), Some(modified)) => *time != modified,
+            (Ok(_), None) => true,
+            (Err(_), Some(_)) => false,
+            (Err(err), None) => {
+                eprintln!("Failed to read {}: {}", name, err);
+                return Status::IO
+            }
+        };
+        if changed {
+            if let Ok(source) = fs::read_to_string(path) {
+                modified = time.ok();
+                let lines = calculate::<T>(&name, &source, format);
+                if previous.is_some() {
+                    println!("{}", color::dim(&format!("--- {} changed", name)));
+                }
+                for text in render(&name, &lines, previous.as_deref()) {
+                    println!("{}", text);
+                }
+                previous = Some(lines);
+            }
+        }
+        thread::sleep(POLL);
+    }
+}
+
+/// Calculate the lines of the source in a session.
+fn calculate<T: Value>(name: &str, source: &str, format: &Format) -> Vec<Line> {
+    let mut session = session::<T>(format);
+    let mut lines = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let expr = match strip_comment(line) {
+            Some(expr) => expr,
+            None => continue,
+        };
+        let line = match &session.eval(expr).result {
+            Ok(res) => Line{no: i + 1, text: res.format(format), ok: true},
+            Err(err) => Line{no: i + 1, text: diagnostics::render_file(name, i + 1, expr, err), ok: false},
+        };
+        lines.push(line);
+    }
+    lines
+}
+
+/// Text of the lines, with the previous result of those that
+/// changed.
+fn render(name: &str, lines: &[Line], previous: Option<&[Line]>) -> Vec<String> {
+    lines.iter().map(|line| {
+        let text = if line.ok {
+            format!("{}:{}: {}", name, line.no, line.text)
+        } else {
+            color::error(&line.text)
+        };
+        let before = previous
+            .and_then(|previous| previous.iter().find(|before| before.no == line.no))
+            .filter(|before| before.text != line.text);
+        match before {
+            Some(before) if before.ok => format!("{} {}", text, color::dim(&format!("(was {})", before.text))),
+            Some(_) => format!("{} {}", text, color::dim("(was an error)")),
+            None => text,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let format = Format::default();
+        let before = calculate::<i128>("a.calc", "x = 2\n# price\nx * 600\n1/0", &format);
+        assert_eq!(before[1], Line{no: 3, text: "1200".to_string(), ok: true});
+        assert!(!before[2].ok);
+        assert_eq!(render("a.calc", &before, None)[..2], ["a.calc:1: 2", "a.calc:3: 1200"]);
+
+        let after = calculate::<i128>("a.calc", "x = 2\n# price\nx * 625\n1/1", &format);
+        assert_eq!(render("a.calc", &after, Some(&before)), [
+            "a.calc:1: 2",
+            "a.calc:3: 1250 (was 1200)",
+            "a.calc:4: 1 (was an error)",
+        ]);
+    }
+}
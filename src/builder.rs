@@ -0,0 +1,604 @@
+//! Calculator configured once and used for many expressions.
+use std::fmt;
+
+use crate::cache::Cache;
+use crate::{lexer, parser, AngleUnit, Rounding, CellResolver, Error, FromAST, FunctionRegistry, Limits, Resolver, UserFunction};
+use crate::error::{LexError, Warning};
+use crate::generator::Context;
+use crate::lexer::{Span, Token};
+use crate::parser::ast::{Expr, AST};
+use crate::parser::top_down_parser;
+use crate::text;
+
+type TokenHook = Box<dyn Fn(&Token, &Span) + Send + Sync>;
+type ParseHook = Box<dyn Fn(&AST) + Send + Sync>;
+type StepHook = Box<dyn Fn(&Expr) + Send + Sync>;
+type Tokens = Vec<Result<(Token, Span), Error>>;
+
+/// Calculator with functions and limits.
+///
+/// Lex and parse in one pass like [`eval_str_fast`], checking
+/// the [`Limits`] at each stage.
+///
+/// Hooks observe the stages without changing the result, for
+/// metrics, auditing or debugging:
+/// * `on_token`: each token, before it is parsed
+/// * `on_parse_complete`: the AST of each expression
+/// * `on_eval_step`: each node, before it is calculated
+///
+/// With [`warnings_as_errors`](Calculator::warnings_as_errors), a
+/// warning such as the cast of `7/2` fails the calculation.
+///
+/// With [`lenient`](Calculator::lenient), the `(` left open at the
+/// end of the input are closed with a [`Warning::Unclosed`], like
+/// a desk calculator, so `(1+2*3` is `7`.
+///
+/// With [`thousands`](Calculator::thousands), `1,234,567` is a
+/// number outside the arguments of the calls and the items of the
+/// lists, as pasted from a document.
+///
+/// With `dates` and the `dates` feature,
+/// `2024-05-01` is a date rather than `2024 - 5 - 1`.
+///
+/// With [`angle_unit`](Calculator::angle_unit), the `f64` functions
+/// of the angles such as `sin(90)` take degrees rather than radians.
+///
+/// With [`rounding`](Calculator::rounding), the integer roots such as
+/// `√10` are rounded up or to the nearest rather than down.
+///
+/// With [`cache`](Calculator::cache), [`eval`](Calculator::eval)
+/// keeps the results of the last expressions.
+///
+/// [`eval_str_fast`]: crate::eval_str_fast
+///
+/// # Example
+/// ```
+/// use wcal::{Calculator, FunctionRegistry, Limits};
+///
+/// let mut functions = FunctionRegistry::new();
+/// functions.register("max", 2, |args: &[i128]| args[0].max(args[1]));
+///
+/// let calc = Calculator::new().functions(functions).limits(Limits::strict());
+/// assert_eq!(calc.eval("max(1, 2) * 3"), Ok(6));
+/// assert!(calc.eval(&"1+".repeat(1000)).is_err());
+/// ```
+pub struct Calculator<T> {
+    functions: FunctionRegistry<T>,
+    limits: Limits,
+    on_token: Option<TokenHook>,
+    on_parse_complete: Option<ParseHook>,
+    on_eval_step: Option<StepHook>,
+    pub(crate) warnings_as_errors: bool,
+    lenient: bool,
+    lex_options: lexer::Options,
+    pub(crate) angle_unit: AngleUnit,
+    pub(crate) rounding: Rounding,
+    cache: Option<Cache<T>>,
+}
+
+impl<T> Calculator<T> {
+    pub fn new() -> Self {
+        Calculator{
+            functions: FunctionRegistry::new(),
+            limits: Limits::default(),
+            on_token: None,
+            on_parse_complete: None,
+            on_eval_step: None,
+            warnings_as_errors: false,
+            lenient: false,
+            lex_options: lexer::Options::default(),
+            angle_unit: AngleUnit::default(),
+            rounding: Rounding::default(),
+            cache: None,
+        }
+    }
+
+    /// Functions that can be called from the expressions.
+    pub fn functions(mut self, functions: FunctionRegistry<T>) -> Self {
+        self.functions = functions;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Fail with [`EvalError::Warning`] instead of raising a
+    /// warning.
+    ///
+    /// [`EvalError::Warning`]: crate::error::EvalError::Warning
+    pub fn warnings_as_errors(mut self, warnings_as_errors: bool) -> Self {
+        self.warnings_as_errors = warnings_as_errors;
+        self
+    }
+
+    /// Close the `(` left open at the end of the input instead of
+    /// failing, with a [`Warning::Unclosed`] for each one.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Read the numbers with `,` between their groups of 3 digits,
+    /// see [`lexer::Options::thousands`].
+    ///
+    /// # Example
+    /// ```
+    /// use wcal::Calculator;
+    ///
+    /// let calc = Calculator::<i128>::new().thousands(true);
+    /// assert_eq!(calc.eval("1,234,567 - 1"), Ok(1234566));
+    /// assert_eq!(calc.eval("sum([1,000, 2]) + (1,000)"), Ok(1003));
+    /// assert!(calc.eval("12,34").is_err());
+    /// ```
+    pub fn thousands(mut self, thousands: bool) -> Self {
+        self.lex_options.thousands = thousands;
+        self
+    }
+
+    /// Read `2024-05-01` as a date, see [`lexer::Options::dates`].
+    /// Only the [`Time`](crate::Time) results are dates, the other
+    /// ones count the days since 1970-01-01.
+    ///
+    /// # Example
+    /// ```
+    /// use wcal::{Calculator, Time};
+    ///
+    /// let calc = Calculator::<Time>::new().dates(true);
+    /// assert_eq!(calc.eval("2024-05-01 + 90d").unwrap().to_string(), "2024-07-30");
+    /// assert_eq!(Calculator::<i128>::new().dates(true).eval("2024-12-25 - 2024-05-01"), Ok(238));
+    /// assert_eq!(Calculator::<i128>::new().eval("2024-12-25 - 2024-05-01"), Ok(-43));
+    /// ```
+    #[cfg(feature = "dates")]
+    pub fn dates(mut self, dates: bool) -> Self {
+        self.lex_options.dates = dates;
+        self
+    }
+
+    /// Unit of the angles of the `f64` functions `sin`, `cos`, `tan`
+    /// and their inverses, radians by default.
+    pub fn angle_unit(mut self, angle_unit: AngleUnit) -> Self {
+        self.angle_unit = angle_unit;
+        self
+    }
+
+    /// Rounding of the roots of the integer calculators that aren't
+    /// integers, down by default.
+    pub fn rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Keep the results of the last `capacity` expressions and
+    /// inputs of [`eval`](Calculator::eval), so a formula seen again
+    /// is neither parsed nor calculated, as a dashboard or a
+    /// spreadsheet recalculates the same ones.
+    ///
+    /// The expressions of the same AST, parentheses included, share
+    /// their result, such as `1+2*3` and `1 + 2 * 3`, and the least
+    /// recently used are dropped first. The hooks are only called for the expressions
+    /// calculated, and the registered functions are expected to
+    /// return the same result for the same arguments.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use wcal::Calculator;
+    ///
+    /// let parsed = Arc::new(AtomicUsize::new(0));
+    /// let counter = parsed.clone();
+    /// let calc = Calculator::<i128>::new()
+    ///     .cache(1000)
+    ///     .on_parse_complete(move |_| { counter.fetch_add(1, Ordering::Relaxed); });
+    /// assert_eq!(calc.eval("price * 2").is_err(), true);
+    /// assert_eq!(calc.eval("(1 + 2) * 3"), Ok(9));
+    /// assert_eq!(calc.eval("(1 + 2) * 3"), Ok(9));
+    /// assert_eq!(parsed.load(Ordering::Relaxed), 2);
+    /// ```
+    pub fn cache(mut self, capacity: usize) -> Self
+    where T: Clone {
+        self.cache = Some(Cache::new(capacity));
+        self
+    }
+
+    /// Drop the results kept by [`cache`](Calculator::cache).
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    /// Number of the expressions and inputs kept by
+    /// [`cache`](Calculator::cache).
+    pub fn cache_len(&self) -> usize {
+        self.cache.as_ref().map_or(0, Cache::len)
+    }
+
+    /// Call `f` with each token and its span.
+    pub fn on_token<F>(mut self, f: F) -> Self
+    where F: Fn(&Token, &Span) + Send + Sync + 'static {
+        self.on_token = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with the AST of each parsed expression.
+    pub fn on_parse_complete<F>(mut self, f: F) -> Self
+    where F: Fn(&AST) + Send + Sync + 'static {
+        self.on_parse_complete = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with each node before calculating it.
+    pub fn on_eval_step<F>(mut self, f: F) -> Self
+    where F: Fn(&Expr) + Send + Sync + 'static {
+        self.on_eval_step = Some(Box::new(f));
+        self
+    }
+
+    /// Functions that can be called from the expressions.
+    pub fn function_registry(&self) -> &FunctionRegistry<T> {
+        &self.functions
+    }
+
+    /// Lex and parse the expression, checking the limits.
+    pub fn parse(&self, expr: &str) -> Result<AST, Error> {
+        self.parse_with_warnings(expr).map(|(ast, _)| ast)
+    }
+
+    /// Lex and parse the expression, checking the limits, with the
+    /// warnings of the [`lenient`](Calculator::lenient) mode.
+    ///
+    /// # Example
+    /// ```
+    /// use wcal::Calculator;
+    /// use wcal::error::Warning;
+    ///
+    /// let calc = Calculator::<i128>::new().lenient(true);
+    /// let (ast, warnings) = calc.parse_with_warnings("(1+2*(3").unwrap();
+    /// assert_eq!(ast.to_string(), "(+ 1 (* 2 3))");
+    /// assert_eq!(warnings, [Warning::Unclosed{span: 0..1}, Warning::Unclosed{span: 5..6}]);
+    /// assert_eq!(calc.eval("(1+2*3"), Ok(7));
+    /// ```
+    pub fn parse_with_warnings(&self, expr: &str) -> Result<(AST, Vec<Warning>), Error> {
+        let limits = &self.limits;
+        if expr.len() > limits.input_len {
+            return Err(LexError::TooLong{limit: limits.input_len, span: limits.input_len..expr.len()}.into())
+        }
+        let tokens = lexer::tokens_with(expr, self.lex_options).enumerate().map(|(count, token)| {
+            let (token, span) = token?;
+            if count >= limits.tokens {
+                return Err(LexError::TooManyTokens{limit: limits.tokens, span}.into())
+            }
+            if let Some(on_token) = &self.on_token {
+                on_token(&token, &span);
+            }
+            Ok((token, span))
+        });
+        let (ast, warnings) = if self.lenient {
+            let (tokens, warnings) = close(tokens, expr.len());
+            (top_down_parser::parse_limited(tokens, limits.depth)?, warnings)
+        } else {
+            (top_down_parser::parse_limited(tokens, limits.depth)?, Vec::new())
+        };
+        debug!(expr = expr; "parsed expression");
+        if let Some(on_parse_complete) = &self.on_parse_complete {
+            on_parse_complete(&ast);
+        }
+        Ok((ast, warnings))
+    }
+
+    /// Parse a definition `name(params) = body`, checking the
+    /// limits, `None` if the expression isn't one. The hooks aren't
+    /// called.
+    ///
+    /// # Example
+    /// ```
+    /// use wcal::Calculator;
+    ///
+    /// let calc = Calculator::<i128>::new();
+    /// let (name, function) = calc.parse_definition("f(x, y) = x * x + y").unwrap().unwrap();
+    /// assert_eq!((name.as_str(), function.params.len()), ("f", 2));
+    /// assert!(calc.parse_definition("f(x) = (x").unwrap().is_err());
+    /// assert!(calc.parse_definition("f(2) + 1").is_none());
+    /// ```
+    pub fn parse_definition(&self, expr: &str) -> Option<Result<(String, UserFunction), Error>> {
+        let limits = &self.limits;
+        // Too long for an expression as well, `parse` reports it
+        if expr.len() > limits.input_len {
+            return None
+        }
+        let tokens = lexer::spanned(expr).ok()?;
+        let (name, params, body) = parser::definition(&tokens)?;
+        if let Some((_, span)) = tokens.get(limits.tokens) {
+            return Some(Err(LexError::TooManyTokens{limit: limits.tokens, span: span.clone()}.into()))
+        }
+        let body = top_down_parser::parse_limited(body.iter().cloned().map(Ok), limits.depth);
+        Some(body.map(|ast| (name, UserFunction{params, body: ast.root})))
+    }
+
+    /// Context to calculate a parsed expression in.
+    pub fn context(&self) -> Context<'_, T> {
+        let ctx = Context::new(&self.functions)
+            .max_operations(self.limits.operations)
+            .max_call_depth(self.limits.calls)
+            .max_depth(self.limits.depth)
+            .warnings_as_errors(self.warnings_as_errors)
+            .angle_unit(self.angle_unit)
+            .rounding(self.rounding);
+        match &self.on_eval_step {
+            Some(on_eval_step) => ctx.on_step(on_eval_step.as_ref()),
+            None => ctx,
+        }
+    }
+}
+
+impl<T: FromAST> Calculator<T> {
+    /// Calculate the expression, or return its result kept by
+    /// [`cache`](Calculator::cache).
+    pub fn eval(&self, expr: &str) -> Result<T, Error> {
+        match &self.cache {
+            Some(cache) => cache.eval(expr, |expr| self.parse_with_warnings(expr), |ast, warnings| self.calculate(ast, warnings)),
+            None => {
+                let (ast, warnings) = self.parse_with_warnings(expr)?;
+                self.calculate(ast, warnings)
+            }
+        }
+    }
+
+    /// Calculate the AST, raising the warnings of its parsing first.
+    fn calculate(&self, ast: AST, warnings: Vec<Warning>) -> Result<T, Error> {
+        let mut ctx = self.context();
+        for warning in warnings {
+            ctx.warn(warning)?;
+        }
+        T::from_ast_in(ast, &mut ctx)
+    }
+
+    /// Text of an expression calling a function whose result is a
+    /// text, such as `hex(255)` or `format(pi, "%.3f")`, `None` if
+    /// the expression isn't such a call or can't be parsed. See
+    /// [`text`](crate::text).
+    ///
+    /// # Example
+    /// ```
+    /// use wcal::Calculator;
+    ///
+    /// let calc = Calculator::<f64>::new();
+    /// assert_eq!(calc.eval_text("format(1 / 3, \"%.3f\")"), Some(Ok("0.333".to_string())));
+    /// assert_eq!(calc.eval_text("hex(255)"), Some(Ok("0xff".to_string())));
+    /// assert_eq!(calc.eval_text("255"), None);
+    /// assert!(calc.eval("hex(255) + 1").is_err());
+    /// ```
+    pub fn eval_text(&self, expr: &str) -> Option<Result<String, Error>> {
+        let ast = self.parse(expr).ok()?;
+        text::eval(&ast, &mut self.context())
+    }
+
+    /// Calculate the expression, reading its variables from the
+    /// resolver.
+    pub fn eval_with(&self, expr: &str, resolver: &dyn Resolver<T>) -> Result<T, Error> {
+        let ast = self.parse(expr)?;
+        T::from_ast_in(ast, &mut self.context().resolver(resolver))
+    }
+
+    /// Calculate the expression, reading the cells such as `A1` from
+    /// the cell resolver, see [`CellResolver`].
+    pub fn eval_cells(&self, expr: &str, cells: &dyn CellResolver<T>) -> Result<T, Error> {
+        let ast = self.parse(expr)?;
+        T::from_ast_in(ast, &mut self.context().cells(cells))
+    }
+}
+
+/// Tokens with a `)` at the end for each `(` left open, and the
+/// warnings of the `(`. Read up to the first error, which is
+/// returned as is.
+fn close<I>(tokens: I, end: usize) -> (Tokens, Vec<Warning>)
+where I: Iterator<Item = Result<(Token, Span), Error>> {
+    let mut read = Vec::new();
+    let mut open = Vec::new();
+    for token in tokens {
+        match &token {
+            Ok((Token::LP, span)) => open.push(span.clone()),
+            Ok((Token::RP, _)) => {
+                open.pop();
+            }
+            Ok(_) => {}
+            Err(_) => {
+                read.push(token);
+                return (read, Vec::new())
+            }
+        }
+        read.push(token);
+    }
+    read.extend(open.iter().map(|_| Ok((Token::RP, end..end))));
+    (read, open.into_iter().map(|span| Warning::Unclosed{span}).collect())
+}
+
+impl<T> fmt::Debug for Calculator<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Calculator")
+            .field("functions", &self.functions)
+            .field("limits", &self.limits)
+            .field("on_token", &self.on_token.is_some())
+            .field("on_parse_complete", &self.on_parse_complete.is_some())
+            .field("on_eval_step", &self.on_eval_step.is_some())
+            .field("warnings_as_errors", &self.warnings_as_errors)
+            .field("lenient", &self.lenient)
+            .field("lex_options", &self.lex_options)
+            .field("angle_unit", &self.angle_unit)
+            .field("rounding", &self.rounding)
+            .field("cache", &self.cache.as_ref().map(Cache::capacity))
+            .finish()
+    }
+}
+
+impl<T> Default for Calculator<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{EvalError, ParseError, Warning};
+
+    #[test]
+    fn test_eval() {
+        let calc = Calculator::new();
+        assert_eq!(calc.eval("1+2*3"), Ok(7i128));
+        assert_eq!(calc.eval("1+2*(3"), crate::eval_no_panic("1+2*(3"));
+
+        let mut functions = FunctionRegistry::new();
+        functions.register("half", 1, |args: &[f64]| args[0] / 2f64);
+        let calc = Calculator::new().functions(functions);
+        assert_eq!(calc.eval("half(3)"), Ok(1.5));
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits{input_len: 8, tokens: 5, depth: 2, operations: 4, calls: 1};
+        let calc = Calculator::<i128>::new().limits(limits);
+
+        assert_eq!(calc.eval("1+2"), Ok(3));
+        assert_eq!(calc.eval("1 + 2 + 3"), Err(LexError::TooLong{limit: 8, span: 8..9}.into()));
+        assert_eq!(calc.eval("1+2+3+4"), Err(LexError::TooManyTokens{limit: 5, span: 5..6}.into()));
+        assert_eq!(calc.eval("(((1)))"), Err(ParseError::TooDeep{limit: 2, span: 2..3}.into()));
+        assert_eq!(calc.eval("1*2+3"), Err(EvalError::TooManyOperations{limit: 4, span: 4..5}.into()));
+        assert_eq!(calc.eval("--1"), Ok(1));
+
+        // Deeper than the default
+        let depth = parser::MAX_DEPTH + 1;
+        let deep = "(".repeat(depth) + "1" + &")".repeat(depth);
+        let calc = Calculator::<i128>::new().limits(Limits{depth, ..Limits::default()});
+        assert_eq!(calc.eval(&deep), Ok(1));
+        assert!(matches!(Calculator::<i128>::new().eval(&deep), Err(Error::Parse(ParseError::TooDeep{..}))));
+    }
+
+    #[test]
+    fn test_warnings_as_errors() {
+        let calc = Calculator::<i128>::new().warnings_as_errors(true);
+        assert_eq!(calc.eval("8/2"), Ok(4));
+        let warning = Warning::DivisionCast{lhs: 7, rhs: 2, span: 4..7};
+        assert_eq!(calc.eval("1 + 7/2"), Err(EvalError::Warning{warning}.into()));
+        assert_eq!(calc.eval("7/2").unwrap_err().to_string(), "Division will cause a cast: 7/2");
+        assert_eq!(Calculator::<f64>::new().warnings_as_errors(true).eval("7/2"), Ok(3.5));
+    }
+
+    #[test]
+    fn test_lenient() {
+        let calc = Calculator::<i128>::new();
+        assert!(matches!(calc.eval("(1+2*3"), Err(Error::Parse(ParseError::Unclosed{..}))));
+
+        let calc = Calculator::<i128>::new().lenient(true);
+        assert_eq!(calc.eval("(1+2*3"), Ok(7));
+        assert_eq!(calc.eval("max(1, (2"), Err(EvalError::UnknownFunction{name: "max".to_string(), suggestion: None, span: 0..9}.into()));
+        assert_eq!(calc.parse_with_warnings("(1) + 2").unwrap().1, []);
+        // Only the missing `)` at the end
+        assert!(calc.eval("(1))").is_err());
+        assert!(calc.eval("|(1").is_err());
+        assert!(calc.eval("(1 +").is_err());
+
+        let mut session = crate::Session::new(calc);
+        assert_eq!(session.eval("2 * (3 + 4").result, Ok(14));
+        assert_eq!(session.last().unwrap().warnings, [Warning::Unclosed{span: 4..5}]);
+        session.set_warnings_as_errors(true);
+        let warning = Warning::Unclosed{span: 0..1};
+        assert_eq!(session.eval("(1").result, Err(EvalError::Warning{warning}.into()));
+        assert_eq!(session.last().unwrap().result.as_ref().unwrap_err().to_string(), "Missing ) inserted at the end");
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let mut functions = FunctionRegistry::new();
+        functions.register("sqrt", 1, |args: &[f64]| args[0].sqrt());
+        let calc = Calculator::new().functions(functions);
+        assert_eq!(calc.eval("sqr(4)").unwrap_err().to_string(), "Unknown function sqr, did you mean sqrt?");
+        assert_eq!(calc.eval("mena([1, 2])").unwrap_err().to_string(), "Unknown function mena, did you mean mean?");
+        assert_eq!(calc.eval("let rate = 2 in rat * 3").unwrap_err().to_string(), "Unknown variable rat, did you mean rate?");
+        assert_eq!(calc.eval("foo(1)").unwrap_err().to_string(), "Unknown function foo");
+
+        let mut env = crate::Environment::new();
+        env.set("principal", 100.0);
+        let ast = calc.parse("principle").unwrap();
+        let err = f64::from_ast_in(ast, &mut calc.context().env(&mut env)).unwrap_err();
+        assert_eq!(err, EvalError::UnknownVariable{name: "principle".to_string(), suggestion: Some("principal".to_string()), span: 0..9}.into());
+    }
+
+    #[test]
+    fn test_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (tokens, parsed, steps) = (events.clone(), events.clone(), events.clone());
+        let calc = Calculator::<i128>::new()
+            .on_token(move |token, span| tokens.lock().unwrap().push(format!("token {} {:?}", token, span)))
+            .on_parse_complete(move |ast| parsed.lock().unwrap().push(format!("ast {:?}", ast.root.span())))
+            .on_eval_step(move |node| steps.lock().unwrap().push(format!("step {:?}", node.span())));
+
+        assert_eq!(calc.eval("1+2"), Ok(3));
+        assert_eq!(*events.lock().unwrap(), [
+            "token 1 0..1", "token + 1..2", "token 2 2..3",
+            "ast 0..3",
+            "step 0..3", "step 0..1", "step 2..3",
+        ]);
+
+        events.lock().unwrap().clear();
+        assert!(calc.eval("1+").is_err());
+        assert_eq!(*events.lock().unwrap(), ["token 1 0..1", "token + 1..2"]);
+    }
+
+    #[test]
+    fn test_cache() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let steps = Arc::new(AtomicUsize::new(0));
+        let counter = steps.clone();
+        let calc = Calculator::<i128>::new()
+            .cache(16)
+            .lenient(true)
+            .on_eval_step(move |_| { counter.fetch_add(1, Ordering::Relaxed); });
+        assert_eq!(calc.eval("1+2*3"), Ok(7));
+        assert_eq!(calc.eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(steps.load(Ordering::Relaxed), 5);
+        // The warnings of a lenient input keep it apart
+        assert_eq!(calc.eval("1 + (2 * 3"), Ok(7));
+        assert_eq!(steps.load(Ordering::Relaxed), 11);
+        assert_eq!(calc.eval("7/0"), Err(EvalError::DivisionByZero{span: 0..3}.into()));
+        assert_eq!(calc.eval(" 7/0"), Err(EvalError::DivisionByZero{span: 1..4}.into()));
+
+        assert_eq!(calc.cache_len(), 6);
+        calc.clear_cache();
+        assert_eq!(calc.cache_len(), 0);
+        assert_eq!(calc.eval("1+2*3"), Ok(7));
+        assert_eq!(steps.load(Ordering::Relaxed), 22);
+    }
+
+    #[test]
+    fn test_cache_parens() {
+        // -2^127 is i128::MIN, but 2^127 overflows before -(...) negates it
+        let inputs = ["-170141183460469231731687303715884105728", "-(170141183460469231731687303715884105728)"];
+        let cached = Calculator::<i128>::new().cache(16);
+        let uncached = Calculator::<i128>::new();
+        for input in inputs.iter() {
+            assert_eq!(cached.eval(input), uncached.eval(input), "input: {}", input);
+        }
+        assert!(cached.eval(inputs[1]).is_err());
+    }
+
+    #[test]
+    fn test_strict() {
+        let calc = Calculator::<i128>::new().limits(Limits::strict());
+        let inputs = [
+            "1".repeat(2000),
+            vec!["1"; 300].join("+"),
+            "(".repeat(100) + "1" + &")".repeat(100),
+        ];
+        for input in inputs.iter() {
+            assert!(calc.eval(input).is_err());
+        }
+    }
+}
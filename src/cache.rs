@@ -0,0 +1,228 @@
+//! Results of the expressions of a [`Calculator`], least recently
+//! used first out.
+//!
+//! The results are keyed by the text of their AST with its
+//! parentheses, so `1+2*3` and `1 + 2 * 3` or `0xff` and `255`
+//! share one, and each input by its text, so an input seen again
+//! isn't parsed either. The errors are only kept by input, as their
+//! spans are the ones of the input.
+//!
+//! [`Calculator`]: crate::Calculator
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::{Error, Warning};
+use crate::parser::ast::{Call, Expr, Number, AST};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Input(String),
+    /// Text of the AST
+    Normalized(String),
+}
+
+enum Cached<T> {
+    Result(Result<T, Error>),
+    /// Text of the AST of an input
+    Normalized(String),
+}
+
+/// Text of the AST with the parentheses its `Display` drops, as
+/// calls of `()`, since `-(1)` isn't calculated like `-1`.
+fn normalize(ast: &AST) -> String {
+    fn mark(expr: &mut Expr) {
+        for child in expr.children_mut() {
+            mark(child);
+        }
+        if let Expr::Pair(pair) = expr {
+            let inner = mem::replace(&mut *pair.expr, Number::new(0));
+            *expr = Call::new("()", vec![inner]);
+        }
+    }
+    let mut root = ast.root.clone();
+    mark(&mut root);
+    root.to_string()
+}
+
+struct Lru<T> {
+    entries: HashMap<Key, (Cached<T>, u64)>,
+    /// Keys by their last use
+    uses: BTreeMap<u64, Key>,
+    clock: u64,
+}
+
+impl<T> Lru<T> {
+    fn get(&mut self, key: &Key) -> Option<&Cached<T>> {
+        let (_, used) = self.entries.get_mut(key)?;
+        self.uses.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.uses.insert(self.clock, key.clone());
+        self.entries.get(key).map(|(cached, _)| cached)
+    }
+
+    fn insert(&mut self, key: Key, cached: Cached<T>, capacity: usize) {
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.insert(key.clone(), (cached, self.clock)) {
+            self.uses.remove(&used);
+        }
+        self.uses.insert(self.clock, key);
+        while self.entries.len() > capacity {
+            let Some((_, key)) = self.uses.pop_first() else { break };
+            self.entries.remove(&key);
+        }
+    }
+}
+
+/// Cache of at most `capacity` inputs and results.
+pub(crate) struct Cache<T> {
+    capacity: usize,
+    clone: fn(&T) -> T,
+    lru: Mutex<Lru<T>>,
+}
+
+impl<T> Cache<T> {
+    pub(crate) fn new(capacity: usize) -> Self
+    where T: Clone {
+        let lru = Lru{entries: HashMap::new(), uses: BTreeMap::new(), clock: 0};
+        Cache{capacity, clone: T::clone, lru: Mutex::new(lru)}
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Lru<T>> {
+        // A panic of another thread leaves it consistent
+        self.lru.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn copy(&self, result: &Result<T, Error>) -> Result<T, Error> {
+        result.as_ref().map(self.clone).map_err(Error::clone)
+    }
+
+    /// Result of the expression, parsed by `parse` and calculated
+    /// by `calculate` unless cached.
+    pub(crate) fn eval<P, C>(&self, expr: &str, parse: P, calculate: C) -> Result<T, Error>
+    where
+        P: FnOnce(&str) -> Result<(AST, Vec<Warning>), Error>,
+        C: FnOnce(AST, Vec<Warning>) -> Result<T, Error>,
+    {
+        let input = Key::Input(expr.to_string());
+        {
+            let mut lru = self.lock();
+            let normalized = match lru.get(&input) {
+                Some(Cached::Result(result)) => return self.copy(result),
+                Some(Cached::Normalized(normalized)) => Some(Key::Normalized(normalized.clone())),
+                None => None,
+            };
+            // The result of an input may be out while the input is in
+            if let Some(Cached::Result(result)) = normalized.and_then(|key| lru.get(&key)) {
+                return self.copy(result)
+            }
+        }
+        let (ast, warnings) = match parse(expr) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                self.lock().insert(input, Cached::Result(Err(err.clone())), self.capacity);
+                return Err(err)
+            }
+        };
+        // The warnings of the lenient parser belong to the input
+        let normalized = if warnings.is_empty() {
+            Some(normalize(&ast))
+        } else {
+            None
+        };
+        if let Some(normalized) = &normalized {
+            let mut lru = self.lock();
+            let key = Key::Normalized(normalized.clone());
+            if let Some(Cached::Result(result)) = lru.get(&key) {
+                let result = self.copy(result);
+                lru.insert(input, Cached::Normalized(normalized.clone()), self.capacity);
+                return result
+            }
+        }
+        let result = calculate(ast, warnings);
+        let mut lru = self.lock();
+        match (normalized, &result) {
+            (Some(normalized), Ok(_)) => {
+                lru.insert(Key::Normalized(normalized.clone()), Cached::Result(self.copy(&result)), self.capacity);
+                lru.insert(input, Cached::Normalized(normalized), self.capacity);
+            }
+            _ => lru.insert(input, Cached::Result(self.copy(&result)), self.capacity),
+        }
+        result
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of inputs and results kept.
+    pub(crate) fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut lru = self.lock();
+        lru.entries.clear();
+        lru.uses.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser, FromAST};
+
+    fn eval(cache: &Cache<i128>, expr: &str, calculated: &mut Vec<String>) -> Result<i128, Error> {
+        let parse = |expr: &str| Ok((parser::top_down_parser::parse(lexer::spanned(expr)?)?, Vec::new()));
+        cache.eval(expr, parse, |ast, _| {
+            calculated.push(ast.to_string());
+            i128::from_ast(ast)
+        })
+    }
+
+    #[test]
+    fn test_eval() {
+        let cache = Cache::new(8);
+        let mut calculated = Vec::new();
+        assert_eq!(eval(&cache, "1+2*3", &mut calculated), Ok(7));
+        assert_eq!(eval(&cache, "1 + 2 * 3", &mut calculated), Ok(7));
+        assert_eq!(eval(&cache, "1+2*3", &mut calculated), Ok(7));
+        assert_eq!(calculated, ["(+ 1 (* 2 3))"]);
+        // Two inputs and their result
+        assert_eq!(cache.len(), 3);
+
+        // The errors are kept by input, with their own spans
+        assert!(eval(&cache, "1/0", &mut calculated).is_err());
+        let err = eval(&cache, "1 / 0", &mut calculated).unwrap_err();
+        assert_eq!(err.span(), 0..5);
+        assert_eq!(calculated.len(), 3);
+        assert!(eval(&cache, "1 +", &mut calculated).is_err());
+        assert_eq!(cache.len(), 6);
+        cache.clear();
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalize = |expr| normalize(&parser::top_down_parser::parse(lexer::spanned(expr).unwrap()).unwrap());
+        assert_eq!(normalize("-((1)) + 0xff"), "(+ (- (() (() 1))) 255)");
+        assert_ne!(normalize("-(1)"), normalize("-1"));
+    }
+
+    #[test]
+    fn test_lru() {
+        let cache = Cache::new(4);
+        let mut calculated = Vec::new();
+        eval(&cache, "1", &mut calculated).unwrap();
+        eval(&cache, "2", &mut calculated).unwrap();
+        // Used again, so `2` is the least recently used
+        eval(&cache, "1", &mut calculated).unwrap();
+        eval(&cache, "3", &mut calculated).unwrap();
+        assert_eq!(cache.len(), 4);
+        eval(&cache, "1", &mut calculated).unwrap();
+        eval(&cache, "2", &mut calculated).unwrap();
+        assert_eq!(calculated, ["1", "2", "3", "2"]);
+        assert_eq!(cache.len(), 4);
+    }
+}
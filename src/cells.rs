@@ -0,0 +1,160 @@
+//! Cells of a spreadsheet, such as `A1` or `B12`, read by the
+//! expressions.
+use std::collections::{BTreeMap, HashMap};
+
+/// Content of a cell.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell<T> {
+    Value(T),
+    /// Expression calculated when a cell refers to it, which may
+    /// refer to other cells.
+    Formula(String),
+}
+
+/// Source of the cells of a spreadsheet, read only when an
+/// expression refers to them.
+///
+/// A name is a cell when it is capital letters followed by a row
+/// from 1, such as `A1` or `AB12`. The variables of the
+/// environment and of the [`Resolver`](crate::Resolver) come first.
+///
+/// Each formula is calculated once per expression, its errors and
+/// warnings point at the first reference to the cell. A formula
+/// referring back to its own cell fails with
+/// [`EvalError::CyclicCell`](crate::error::EvalError::CyclicCell),
+/// and a chain of formulas is nested like the calls of the user
+/// functions, up to [`Limits::calls`](crate::Limits::calls). The
+/// formulas are parsed with the [`Limits::depth`](crate::Limits::depth)
+/// of the expressions.
+///
+/// Implemented by the closures `Fn(&str) -> Option<Cell<T>>` and by
+/// the maps from `String`.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+/// use wcal::{Calculator, Cell};
+/// use wcal::error::EvalError;
+///
+/// let calc = Calculator::<i128>::new();
+/// let mut sheet = HashMap::new();
+/// sheet.insert("A1".to_string(), Cell::Value(2));
+/// sheet.insert("A2".to_string(), Cell::Formula("A1 * 10".to_string()));
+/// assert_eq!(calc.eval_cells("A1 + A2", &sheet), Ok(22));
+///
+/// sheet.insert("A1".to_string(), Cell::Formula("A2 + 1".to_string()));
+/// let err = calc.eval_cells("A2", &sheet).unwrap_err();
+/// assert!(matches!(err, wcal::Error::Eval(EvalError::CyclicCell{..})));
+/// ```
+pub trait CellResolver<T> {
+    /// Content of the cell, `None` if it is empty.
+    fn cell(&self, name: &str) -> Option<Cell<T>>;
+}
+
+impl<T, F> CellResolver<T> for F
+where F: Fn(&str) -> Option<Cell<T>> {
+    fn cell(&self, name: &str) -> Option<Cell<T>> {
+        self(name)
+    }
+}
+
+impl<T: Clone> CellResolver<T> for HashMap<String, Cell<T>> {
+    fn cell(&self, name: &str) -> Option<Cell<T>> {
+        self.get(name).cloned()
+    }
+}
+
+impl<T: Clone> CellResolver<T> for BTreeMap<String, Cell<T>> {
+    fn cell(&self, name: &str) -> Option<Cell<T>> {
+        self.get(name).cloned()
+    }
+}
+
+/// Whether the name is a cell, such as `B12`.
+pub(crate) fn is_cell(name: &str) -> bool {
+    let row = name.trim_start_matches(|c: char| c.is_ascii_uppercase());
+    row.len() < name.len() && row.starts_with(|c: char| matches!(c, '1'..='9')) && row.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{Calculator, Environment, FromAST};
+    use crate::error::{EvalError, ParseError, Warning};
+
+    fn sheet(cells: &[(&str, &str)]) -> HashMap<String, Cell<i128>> {
+        cells.iter()
+            .map(|(name, formula)| (name.to_string(), Cell::Formula(formula.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn test_is_cell() {
+        for name in ["A1", "B12", "AB100"].iter() {
+            assert!(is_cell(name), "{}", name);
+        }
+        for name in ["A", "1", "a1", "A0", "A01", "A1B", "x"].iter() {
+            assert!(!is_cell(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_formulas() {
+        let calc = Calculator::<i128>::new();
+        let sheet = sheet(&[("A1", "1"), ("A2", "A1 + 1"), ("A3", "A1 + A2"), ("B1", "sum([A1, A2, A3])")]);
+        assert_eq!(calc.eval_cells("B1 * 2", &sheet), Ok(12));
+        assert_eq!(
+            calc.eval_cells("A1 + C1", &sheet),
+            Err(EvalError::UnknownVariable{name: "C1".to_string(), suggestion: None, span: 5..7}.into()),
+        );
+
+        // The environment comes first, and `let` hides the cells
+        let mut env = Environment::new();
+        env.set("A1", 10);
+        let ast = calc.parse("A2 + let A3 = 0 in A3").unwrap();
+        let mut ctx = calc.context().env(&mut env).cells(&sheet);
+        assert_eq!(i128::from_ast_in(ast, &mut ctx), Ok(11));
+    }
+
+    #[test]
+    fn test_errors() {
+        let calc = Calculator::<i128>::new();
+        let sheet = sheet(&[("A1", "B1 + 1"), ("B1", "2 * A1"), ("C1", "1 / 0"), ("C2", "C1"), ("D1", "(1"), ("E1", "7 / 2")]);
+        let cyclic = |cell: &str, span| Err(EvalError::CyclicCell{cell: cell.to_string(), span}.into());
+        assert_eq!(calc.eval_cells("1 + A1", &sheet), cyclic("A1", 4..6));
+        assert_eq!(calc.eval_cells("B1", &sheet), cyclic("B1", 0..2));
+
+        let in_cell = |cell: &str, error: crate::Error, span| Err(EvalError::InCell{cell: cell.to_string(), error: Box::new(error), span}.into());
+        assert_eq!(calc.eval_cells("C2 + 1", &sheet), in_cell("C1", EvalError::DivisionByZero{span: 0..5}.into(), 0..2));
+        assert_eq!(calc.eval_cells("D1", &sheet), in_cell("D1", ParseError::Unclosed{open: 0..1, closing: ")", found: None, span: 2..2}.into(), 0..2));
+        assert_eq!(calc.eval_cells("C2", &sheet).unwrap_err().to_string(), "In cell C1: Division by zero");
+
+        let ast = calc.parse("E1 + E1").unwrap();
+        let mut ctx = calc.context().cells(&sheet);
+        assert_eq!(i128::from_ast_in(ast, &mut ctx), Ok(6));
+        assert_eq!(ctx.warnings(), &[Warning::DivisionCast{lhs: 7, rhs: 2, span: 0..2}]);
+    }
+
+    #[test]
+    fn test_chain() {
+        let calc = Calculator::<i128>::new();
+        let cells = |name: &str| {
+            let row: i128 = name.strip_prefix('A')?.parse().ok()?;
+            Some(if row == 1 { Cell::Value(1) } else { Cell::Formula(format!("A{} + 1", row - 1)) })
+        };
+        assert_eq!(calc.eval_cells("A64", &cells), Ok(64));
+        assert!(matches!(calc.eval_cells("A100", &cells), Err(crate::Error::Eval(EvalError::CallTooDeep{..}))));
+    }
+
+    #[test]
+    fn test_depth() {
+        let limits = crate::Limits{depth: 2, ..crate::Limits::default()};
+        let calc = Calculator::<i128>::new().limits(limits);
+        let sheet = sheet(&[("A1", "(((1)))"), ("A2", "((1))")]);
+        assert_eq!(calc.eval_cells("A2", &sheet), Ok(1));
+        let too_deep = ParseError::TooDeep{limit: 2, span: 2..3};
+        assert_eq!(calc.eval_cells("A1", &sheet), Err(EvalError::InCell{cell: "A1".to_string(), error: Box::new(too_deep.into()), span: 0..2}.into()));
+    }
+}
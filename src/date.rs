@@ -0,0 +1,85 @@
+//! Dates such as `2024-05-01`, counted in days since 1970-01-01.
+//!
+//! The literals and `today()` need the `dates` feature, and the
+//! literals `Options::dates`. The
+//! other calculators than [`Time`](crate::Time) read a date as its
+//! days, so `2024-12-25 - 2024-05-01` is `238`.
+use std::convert::TryFrom;
+
+#[cfg(feature = "dates")]
+use chrono::{Datelike, Local, NaiveDate};
+
+/// Days of 0000-01-01, the first date of 4 digits
+const MIN: i64 = -719528;
+/// Days of 9999-12-31
+const MAX: i64 = 2932896;
+
+/// The days if they are a date from 0000-01-01 to 9999-12-31.
+pub(crate) fn checked(days: i128) -> Option<i64> {
+    i64::try_from(days).ok().filter(|days| (MIN..=MAX).contains(days))
+}
+
+/// Year, month and day of the days, in the proleptic Gregorian
+/// calendar.
+fn civil(days: i64) -> (i64, i64, i64) {
+    // From 0000-03-01, so the leap day ends the years
+    let days = days + 719468;
+    let (era, day) = (days.div_euclid(146097), days.rem_euclid(146097));
+    let year = (day - day / 1460 + day / 36524 - day / 146096) / 365;
+    let day = day - (365 * year + year / 4 - year / 100);
+    let month = (5 * day + 2) / 153;
+    let (day, month) = (day - (153 * month + 2) / 5 + 1, if month < 10 { month + 3 } else { month - 9 });
+    (era * 400 + year + i64::from(month <= 2), month, day)
+}
+
+/// Spelling of the date, such as `2024-05-01`.
+pub(crate) fn spelling(days: i64) -> String {
+    let (year, month, day) = civil(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(feature = "dates")]
+fn days(date: NaiveDate) -> i64 {
+    // 1970-01-01 is the day 719163 from 0001-01-01
+    i64::from(date.num_days_from_ce()) - 719163
+}
+
+/// Days of a literal such as `2024-05-01`, `None` if the date
+/// doesn't exist, such as `2023-02-29`.
+#[cfg(feature = "dates")]
+pub(crate) fn parse(raw: &str) -> Option<i64> {
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d").ok().map(days)
+}
+
+/// Days of the local date.
+#[cfg(feature = "dates")]
+pub(crate) fn today() -> i64 {
+    days(Local::now().date_naive())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spelling() {
+        assert_eq!(spelling(0), "1970-01-01");
+        assert_eq!(spelling(19844), "2024-05-01");
+        assert_eq!(spelling(-1), "1969-12-31");
+        assert_eq!(spelling(MIN), "0000-01-01");
+        assert_eq!(spelling(MAX), "9999-12-31");
+        assert_eq!(checked(i128::from(MAX) + 1), None);
+    }
+
+    #[test]
+    #[cfg(feature = "dates")]
+    fn test_parse() {
+        assert_eq!(parse("2024-05-01"), Some(19844));
+        assert_eq!(parse("2024-02-29"), Some(19782));
+        assert_eq!(parse("2023-02-29"), None);
+        assert_eq!(parse("0000-01-01"), Some(MIN));
+        for days in (MIN..=MAX).step_by(997) {
+            assert_eq!(parse(&spelling(days)), Some(days));
+        }
+    }
+}
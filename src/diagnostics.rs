@@ -0,0 +1,216 @@
+//! Render errors against the source.
+//!
+//! The offending span is underlined below the line it belongs to,
+//! and the span of the note of the error, if any, with `-`:
+//! ```text
+//! Error: Expect ), got nothing
+//!  --> 1:7
+//!   |
+//! 1 | (((2))
+//!   | -     ^
+//!   = note: unclosed ( at 1:1
+//! ```
+use crate::error::Error;
+use crate::lexer::Span;
+use crate::lint::Lint;
+use crate::source_map::SourceMap;
+
+/// Render the error with its span underlined in the source.
+///
+/// # Example
+/// ```
+/// use wcal::{calculator, parser, diagnostics};
+///
+/// let err = calculator::<i128>("1 + * 2", parser::top_down_parser::parse).unwrap_err();
+/// assert_eq!(diagnostics::render("1 + * 2", &err), "\
+/// Error: Expect number, got *
+///  --> 1:5
+///   |
+/// 1 | 1 + * 2
+///   |     ^");
+/// ```
+pub fn render(source: &str, err: &Error) -> String {
+    render_from("Error", &err.message(), "", 1, source, err.span(), err.note())
+}
+
+/// Render the error of an expression read from line `line_no` of
+/// the file `path`, such as a line of a script.
+///
+/// # Example
+/// ```
+/// use wcal::{calculator, parser, diagnostics};
+///
+/// let err = calculator::<i128>("1 + * 2", parser::top_down_parser::parse).unwrap_err();
+/// assert_eq!(diagnostics::render_file("a.calc", 12, "1 + * 2", &err), "\
+/// Error: Expect number, got *
+///   --> a.calc:12:5
+///    |
+/// 12 | 1 + * 2
+///    |     ^");
+/// ```
+pub fn render_file(path: &str, line_no: usize, source: &str, err: &Error) -> String {
+    render_from("Error", &err.message(), &format!("{}:", path), line_no, source, err.span(), err.note())
+}
+
+/// Render the lint of an expression read from line `line_no` of the
+/// file `path` as a warning.
+///
+/// # Example
+/// ```
+/// use wcal::{lexer, lint, parser, diagnostics};
+///
+/// let ast = lexer::spanned("--x").and_then(parser::top_down_parser::parse).unwrap();
+/// assert_eq!(diagnostics::render_lint_file("a.calc", 3, "--x", &lint::lint(&ast)[0]), "\
+/// Warning: Double negation, --x is x
+///  --> a.calc:3:1
+///   |
+/// 3 | --x
+///   | ^^^");
+/// ```
+pub fn render_lint_file(path: &str, line_no: usize, source: &str, lint: &Lint) -> String {
+    render_from("Warning", &lint.to_string(), &format!("{}:", path), line_no, source, lint.span(), None)
+}
+
+/// Render the message at `level` with `first_line` as the number of
+/// the first line of the source, prefixing the location with
+/// `origin`.
+fn render_from(
+    level: &str, message: &str, origin: &str, first_line: usize, source: &str,
+    span: Span, note: Option<(Span, &str)>,
+) -> String {
+    let map = SourceMap::new(source);
+    let span = locate(&map, span, first_line);
+    let line = &source[span.line_start..span.line_end];
+    let gutter = " ".repeat(span.line_no.to_string().len());
+
+    let mut marker: Vec<char> = " ".repeat(span.column).chars().collect();
+    marker.extend("^".repeat(span.width).chars());
+    let note = note.map(|(note_span, label)| {
+        let note = locate(&map, note_span, first_line);
+        // Underline the note too when it is on the same line
+        if note.line_no == span.line_no {
+            if marker.len() < note.column + note.width {
+                marker.resize(note.column + note.width, ' ');
+            }
+            for mark in marker[note.column..note.column + note.width].iter_mut().filter(|mark| **mark == ' ') {
+                *mark = '-';
+            }
+        }
+        format!("\n{} = note: {} at {}:{}", gutter, label, note.line_no, note.column + 1)
+    });
+
+    format!(
+        "{}: {}\n{}--> {}{}:{}\n{} |\n{} | {}\n{} | {}{}",
+        level, message,
+        gutter, origin, span.line_no, span.column + 1,
+        gutter,
+        span.line_no, line,
+        gutter, marker.into_iter().collect::<String>().trim_end(), note.unwrap_or_default()
+    )
+}
+
+/// Position of a span in the source.
+struct Location {
+    line_no: usize,
+    /// Byte range of the line of the span
+    line_start: usize,
+    line_end: usize,
+    /// Characters before the span in its line
+    column: usize,
+    /// Characters of the span in its line, at least 1
+    width: usize,
+}
+
+fn locate(map: &SourceMap, span: Span, first_line: usize) -> Location {
+    let start = map.location(span.start);
+    let line = map.line_span(start.line).unwrap_or_default();
+    // The span is cut at the end of its first line
+    let end = map.location(span.end.min(line.end).max(span.start));
+    Location{
+        line_no: start.line + first_line,
+        line_start: line.start,
+        line_end: line.end,
+        column: start.column,
+        width: end.column.saturating_sub(start.column).max(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn render_err(source: &str) -> String {
+        let err = lexer::spanned(source)
+            .and_then(parser::top_down_parser::parse)
+            .unwrap_err();
+        render(source, &err)
+    }
+
+    #[test]
+    fn test_lex_error() {
+        assert_eq!(render_err("12 + 0x1_0000_0000_0000_0000_0000_0000_0000_0000"), "\
+Error: Parse int failed: number too large to fit in target type
+ --> 1:6
+  |
+1 | 12 + 0x1_0000_0000_0000_0000_0000_0000_0000_0000
+  |      ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^");
+
+        assert_eq!(render_err("1+\u{e9}"), "\
+Error: Invalid character: \u{e9}
+ --> 1:3
+  |
+1 | 1+\u{e9}
+  |   ^");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!(render_err("(((2))"), "\
+Error: Expect ), got nothing
+ --> 1:7
+  |
+1 | (((2))
+  | -     ^
+  = note: unclosed ( at 1:1");
+
+        assert_eq!(render_err("max(1, (2 3)"), "\
+Error: Expect ), got 3
+ --> 1:11
+  |
+1 | max(1, (2 3)
+  |        -  ^
+  = note: unclosed ( at 1:8");
+
+        assert_eq!(render_err("(2)(1)"), "\
+Error: Invalid expression
+ --> 1:4
+  |
+1 | (2)(1)
+  |    ^^^");
+    }
+
+    #[test]
+    fn test_multi_line() {
+        let source = "1\n2\n3 + a";
+        let err = Error::Lex(crate::error::LexError::InvalidCharacter{ch: 'a', span: 8..9});
+        assert_eq!(render(source, &err), "\
+Error: Invalid character: a
+ --> 3:5
+  |
+3 | 3 + a
+  |     ^");
+    }
+
+    #[test]
+    fn test_file() {
+        let source = "2\n3 + a";
+        let err = Error::Lex(crate::error::LexError::InvalidCharacter{ch: 'a', span: 6..7});
+        assert_eq!(render_file("dir/a.calc", 9, source, &err), "\
+Error: Invalid character: a
+  --> dir/a.calc:10:5
+   |
+10 | 3 + a
+   |     ^");
+    }
+}
@@ -0,0 +1,177 @@
+//! Durations such as `1h30m`, and the result of the duration
+//! calculator.
+//!
+//! A duration literal is a number of seconds for the other
+//! calculators, so `1h + 1` is `3601`, and the dates are days.
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::rational::Rational;
+
+/// Units of the durations, from the largest, with their seconds.
+const UNITS: [(char, i128); 4] = [('d', 86400), ('h', 3600), ('m', 60), ('s', 1)];
+
+/// Result of the duration calculator: a number, such as the ratio
+/// of two durations, a duration in seconds, or a date.
+///
+/// Durations add to durations, and are multiplied or divided by
+/// numbers. Dividing a duration by a duration is a number. Whole
+/// days, as a number or a duration, add to and subtract from a
+/// date, and a date minus a date is its number of days.
+///
+/// # Example
+/// ```
+/// use wcal::{DurationFormat, Time};
+///
+/// let res: Time = wcal::eval_no_panic("1h30m + 45m").unwrap();
+/// assert_eq!(res.to_string(), "2h15m");
+/// assert_eq!(res.format(DurationFormat::Clock), "02:15:00");
+/// assert_eq!(wcal::eval_no_panic::<Time>("90s * 4").unwrap().to_string(), "6m");
+/// assert_eq!(wcal::eval_no_panic::<Time>("1h / 20m").unwrap(), Time::Number(3.into()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Time {
+    Number(Rational),
+    /// Seconds
+    Duration(Rational),
+    /// Days since 1970-01-01
+    Date(i64),
+}
+
+/// Spelling of a duration, see [`Time::format`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurationFormat {
+    /// `1h30m`, as the literals
+    #[default]
+    Units,
+    /// `01:30:00`, the hours past 24 are kept, such as `26:00:00`
+    Clock,
+}
+
+impl Time {
+    /// The number, the seconds of the duration, or the days of the
+    /// date.
+    pub fn value(&self) -> Rational {
+        match self {
+            Time::Number(value) | Time::Duration(value) => *value,
+            Time::Date(days) => Rational::from(i128::from(*days)),
+        }
+    }
+
+    pub fn is_duration(&self) -> bool {
+        matches!(self, Time::Duration(_))
+    }
+
+    /// Spell the duration in the format, a number is displayed as
+    /// a [`Rational`] and a date such as `2024-05-01`.
+    pub fn format(&self, format: DurationFormat) -> String {
+        let secs = match self {
+            Time::Number(value) => return value.to_string(),
+            Time::Duration(secs) => *secs,
+            Time::Date(days) => return crate::date::spelling(*days),
+        };
+        let sign = if secs.numer() < 0 { "-" } else { "" };
+        let (whole, millis) = split(secs);
+        let fraction = if millis == 0 { String::new() } else { format!(".{:03}", millis).trim_end_matches('0').to_string() };
+        match format {
+            DurationFormat::Units => format!("{}{}{}", sign, units(whole, fraction.is_empty()), fraction_unit(&fraction)),
+            DurationFormat::Clock => {
+                let (hours, rest) = (whole / 3600, whole % 3600);
+                format!("{}{:02}:{:02}:{:02}{}", sign, hours, rest / 60, rest % 60, fraction)
+            }
+        }
+    }
+}
+
+/// `1h30m` of the whole seconds, `0s` for none. The seconds are
+/// written even if 0 when `whole` is false, for their fraction.
+fn units(secs: u128, whole: bool) -> String {
+    let mut out = String::new();
+    let mut rest = secs;
+    for (unit, len) in UNITS {
+        let len = len as u128;
+        let count = rest / len;
+        rest %= len;
+        if count > 0 || (unit == 's' && (out.is_empty() || !whole)) {
+            out.push_str(&count.to_string());
+            if unit != 's' || whole {
+                out.push(unit);
+            }
+        }
+    }
+    out
+}
+
+fn fraction_unit(fraction: &str) -> String {
+    if fraction.is_empty() {
+        String::new()
+    } else {
+        format!("{}s", fraction)
+    }
+}
+
+/// Whole seconds and milliseconds of the absolute value, rounded
+/// to the nearest millisecond.
+fn split(secs: Rational) -> (u128, u128) {
+    let (num, den) = (secs.numer().unsigned_abs(), secs.denom().unsigned_abs());
+    let (whole, rest) = (num / den, num % den);
+    let millis = (rest as f64 / den as f64 * 1000.0).round() as u128;
+    if millis == 1000 {
+        (whole + 1, 0)
+    } else {
+        (whole, millis)
+    }
+}
+
+/// Spelling of a literal of `secs` seconds, such as `1h30m`.
+pub(crate) fn spelling(secs: u128) -> String {
+    units(secs, true)
+}
+
+impl fmt::Display for Time {
+    /// The number, or the duration in [`DurationFormat::Units`].
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(DurationFormat::Units))
+    }
+}
+
+impl From<Rational> for Time {
+    fn from(value: Rational) -> Self {
+        Time::Number(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(num: i128, den: i128) -> Time {
+        Time::Duration(Rational::new(num, den).unwrap())
+    }
+
+    #[test]
+    fn test_format() {
+        let cases = [
+            (duration(5400, 1), "1h30m", "01:30:00"),
+            (duration(0, 1), "0s", "00:00:00"),
+            (duration(90061, 1), "1d1h1m1s", "25:01:01"),
+            (duration(-45, 1), "-45s", "-00:00:45"),
+            (duration(121, 2), "1m0.5s", "00:01:00.5"),
+            (duration(1, 3), "0.333s", "00:00:00.333"),
+            (duration(7201, 2), "1h0.5s", "01:00:00.5"),
+            (duration(1, 10000), "0s", "00:00:00"),
+            (duration(599999, 10000), "1m", "00:01:00"),
+        ];
+        for (time, units, clock) in cases {
+            assert_eq!(time.format(DurationFormat::Units), units);
+            assert_eq!(time.format(DurationFormat::Clock), clock);
+        }
+        assert_eq!(Time::Number(Rational::new(7, 2).unwrap()).format(DurationFormat::Clock), "7/2");
+        assert_eq!(spelling(86400), "1d");
+        assert_eq!(Time::Date(19844).format(DurationFormat::Clock), "2024-05-01");
+    }
+}
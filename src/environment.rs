@@ -0,0 +1,314 @@
+//! Variables assigned by expressions, such as `x = 1 + 2`, and
+//! functions defined by `f(x, y) = x * x + y`.
+use std::collections::btree_map::{BTreeMap, Iter};
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::parser::ast::Expr;
+
+/// Function defined by an expression, such as `f(x, y) = x * x + y`.
+///
+/// The body only sees the parameters and the variables of the
+/// environment when it is called.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UserFunction {
+    pub params: Vec<String>,
+    /// Saved as its canonical text, see [`pretty`](crate::pretty)
+    #[cfg_attr(feature = "serde", serde(with = "body_text"))]
+    pub body: Expr,
+}
+
+/// Values of the variables and the user functions, with
+/// transactional snapshots.
+///
+/// Assignments and definitions after a
+/// [`checkpoint`](Environment::checkpoint) are journaled, so
+/// [`rollback`](Environment::rollback) can discard them, such as
+/// the assignments of a failed statement.
+///
+/// With the `serde` feature, the variables and the functions can
+/// be serialized, checkpoints are not.
+///
+/// # Example
+/// ```
+/// use wcal::Environment;
+///
+/// let mut env = Environment::new();
+/// env.set("x", 1i128);
+///
+/// env.checkpoint();
+/// env.set("x", 2);
+/// env.set("y", 3);
+/// assert!(env.rollback());
+///
+/// assert_eq!(env.get("x"), Some(&1));
+/// assert_eq!(env.get("y"), None);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(deserialize = "T: Deserialize<'de>")))]
+pub struct Environment<T> {
+    vars: BTreeMap<String, T>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "BTreeMap::is_empty", with = "shared"))]
+    functions: BTreeMap<String, Arc<UserFunction>>,
+    /// Previous values of the names assigned or defined after the
+    /// first checkpoint
+    #[cfg_attr(feature = "serde", serde(skip))]
+    journal: Vec<Change<T>>,
+    /// Length of the journal at each checkpoint
+    #[cfg_attr(feature = "serde", serde(skip))]
+    checkpoints: Vec<usize>,
+}
+
+/// Previous value of a name, to roll it back.
+#[derive(Debug, Clone)]
+enum Change<T> {
+    Var(String, Option<T>),
+    Function(String, Option<Arc<UserFunction>>),
+}
+
+impl<T> Environment<T> {
+    pub fn new() -> Self {
+        Environment{vars: BTreeMap::new(), functions: BTreeMap::new(), journal: Vec::new(), checkpoints: Vec::new()}
+    }
+
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.vars.get(name)
+    }
+
+    /// Assign the variable, return the previous value.
+    pub fn set(&mut self, name: &str, value: T) -> Option<T>
+    where T: Clone {
+        let prev = self.vars.insert(name.to_string(), value);
+        if !self.checkpoints.is_empty() {
+            self.journal.push(Change::Var(name.to_string(), prev.clone()));
+        }
+        prev
+    }
+
+    pub fn function(&self, name: &str) -> Option<&UserFunction> {
+        self.functions.get(name).map(Arc::as_ref)
+    }
+
+    /// Function shared with the calls in progress.
+    pub(crate) fn shared_function(&self, name: &str) -> Option<Arc<UserFunction>> {
+        self.functions.get(name).cloned()
+    }
+
+    /// Define the function, replacing the previous one.
+    pub fn define(&mut self, name: &str, function: UserFunction) {
+        let prev = self.functions.insert(name.to_string(), Arc::new(function));
+        if !self.checkpoints.is_empty() {
+            self.journal.push(Change::Function(name.to_string(), prev));
+        }
+    }
+
+    /// Functions ordered by name.
+    pub fn functions(&self) -> impl Iterator<Item = (&String, &UserFunction)> {
+        self.functions.iter().map(|(name, function)| (name, function.as_ref()))
+    }
+
+    /// Variables ordered by name.
+    pub fn iter(&self) -> Iter<'_, String, T> {
+        self.vars.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// Start a snapshot, checkpoints can be nested.
+    pub fn checkpoint(&mut self) {
+        self.checkpoints.push(self.journal.len());
+    }
+
+    /// Discard the assignments and definitions since the last
+    /// checkpoint and remove it. Return `false` if there is no checkpoint.
+    pub fn rollback(&mut self) -> bool {
+        let mark = match self.checkpoints.pop() {
+            Some(mark) => mark,
+            None => return false,
+        };
+        while self.journal.len() > mark {
+            match self.journal.pop() {
+                Some(Change::Var(name, Some(value))) => {
+                    self.vars.insert(name, value);
+                }
+                Some(Change::Var(name, None)) => {
+                    self.vars.remove(&name);
+                }
+                Some(Change::Function(name, Some(function))) => {
+                    self.functions.insert(name, function);
+                }
+                Some(Change::Function(name, None)) => {
+                    self.functions.remove(&name);
+                }
+                None => {}
+            }
+        }
+        true
+    }
+
+    /// Keep the assignments and definitions since the last
+    /// checkpoint and remove it. Return `false` if there is no
+    /// checkpoint.
+    pub fn commit(&mut self) -> bool {
+        if self.checkpoints.pop().is_none() {
+            return false
+        }
+        if self.checkpoints.is_empty() {
+            self.journal.clear();
+        }
+        true
+    }
+
+    /// Number of checkpoints that can be rolled back.
+    pub fn checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+}
+
+impl<T> Default for Environment<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Environment<T> {
+    type Item = (&'a String, &'a T);
+    type IntoIter = Iter<'a, String, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Serde of the body as text, parsed back when loaded.
+#[cfg(feature = "serde")]
+mod body_text {
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    use crate::{lexer, pretty};
+    use crate::parser::ast::{Expr, AST};
+    use crate::parser::top_down_parser;
+
+    pub fn serialize<S: Serializer>(body: &Expr, serializer: S) -> Result<S::Ok, S::Error> {
+        // Without the input, the literals are written in decimal
+        let text = pretty::format_ast(&AST{root: body.clone()}, "", &pretty::Style::default());
+        serializer.serialize_str(&text)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Expr, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        lexer::spanned(&text)
+            .and_then(top_down_parser::parse)
+            .map(|ast| ast.root)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Serde of the shared functions by value.
+#[cfg(feature = "serde")]
+mod shared {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::UserFunction;
+
+    pub fn serialize<S: Serializer>(functions: &BTreeMap<String, Arc<UserFunction>>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(functions.iter().map(|(name, function)| (name, function.as_ref())))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, Arc<UserFunction>>, D::Error> {
+        let functions = BTreeMap::<String, UserFunction>::deserialize(deserializer)?;
+        Ok(functions.into_iter().map(|(name, function)| (name, Arc::new(function))).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+    use crate::parser::ast::{BinOp, Var};
+
+    #[test]
+    fn test_nested() {
+        let mut env = Environment::new();
+        env.set("a", 1);
+
+        env.checkpoint();
+        env.set("a", 2);
+        env.checkpoint();
+        env.set("a", 3);
+        env.set("b", 4);
+        assert_eq!(env.checkpoints(), 2);
+
+        assert!(env.rollback());
+        assert_eq!(env.get("a"), Some(&2));
+        assert_eq!(env.get("b"), None);
+
+        assert!(env.rollback());
+        assert_eq!(env.get("a"), Some(&1));
+        assert!(!env.rollback());
+    }
+
+    #[test]
+    fn test_commit() {
+        let mut env = Environment::new();
+        env.checkpoint();
+        env.set("a", 1);
+        env.checkpoint();
+        env.set("a", 2);
+        assert!(env.commit());
+        assert!(env.rollback());
+        assert!(env.is_empty());
+        assert!(!env.commit());
+
+        env.checkpoint();
+        env.set("a", 1);
+        assert!(env.commit());
+        assert_eq!(env.journal.len(), 0);
+        let vars: Vec<_> = env.iter().collect();
+        assert_eq!(vars, [(&"a".to_string(), &1)]);
+    }
+
+    #[test]
+    fn test_functions() {
+        let mut env = Environment::<i128>::new();
+        let square = UserFunction{params: vec!["x".to_string()], body: BinOp::new(Var::new("x"), Var::new("x"), Token::Times)};
+        env.define("f", square.clone());
+        env.checkpoint();
+        env.define("f", UserFunction{params: Vec::new(), ..square.clone()});
+        env.define("g", square.clone());
+        assert_eq!(env.functions().count(), 2);
+        assert!(env.is_empty());
+
+        assert!(env.rollback());
+        assert_eq!(env.function("f"), Some(&square));
+        assert_eq!(env.function("g"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let mut env = Environment::new();
+        env.set("a", 1.5);
+        env.checkpoint();
+        env.set("b", 2.0);
+
+        let json = serde_json::to_string(&env).unwrap();
+        assert_eq!(json, r#"{"vars":{"a":1.5,"b":2.0}}"#);
+        let mut env: Environment<f64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(env.get("b"), Some(&2.0));
+        assert!(!env.rollback());
+    }
+}
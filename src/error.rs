@@ -0,0 +1,368 @@
+//! Errors of the calculator.
+//!
+//! Each error keeps the span of the input it comes from, so it
+//! can be rendered against the source by [`diagnostics`].
+//!
+//! [`diagnostics`]: crate::diagnostics
+use std::fmt;
+
+use crate::lexer::{Span, Token};
+
+/// Error while parsing the input to tokens.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    /// Character that is not part of any token.
+    InvalidCharacter { ch: char, span: Span },
+    /// Number literal that can't be parsed, such as overflow.
+    ParseInt { raw: String, reason: String, span: Span },
+    /// Input longer than the limit, the span is the part past it.
+    TooLong { limit: usize, span: Span },
+    /// More tokens than the limit.
+    TooManyTokens { limit: usize, span: Span },
+    /// Number of [`Options::thousands`](crate::lexer::Options::thousands)
+    /// whose groups aren't 3 digits, such as `12,34`.
+    Grouping { raw: String, span: Span },
+    /// Date of `Options::dates` that
+    /// doesn't exist, such as `2023-02-29`.
+    InvalidDate { raw: String, span: Span },
+}
+
+/// Error while parsing tokens to AST.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// Got a token while expecting another one.
+    UnexpectedToken { expected: &'static str, found: Token, span: Span },
+    /// Got nothing while expecting a token.
+    UnexpectedEnd { expected: &'static str, span: Span },
+    /// Got another token or nothing while expecting the `closing`
+    /// `)`, `]` or `|` of the one at `open`.
+    Unclosed { open: Span, closing: &'static str, found: Option<Token>, span: Span },
+    /// Tokens remain after a complete expression.
+    InvalidExpression { span: Span },
+    /// Expression nested deeper than the limit.
+    TooDeep { limit: usize, span: Span },
+    /// Expression with more binary operators than the limit.
+    TooManyOperators { limit: usize, span: Span },
+    /// Operator that doesn't exist but is close to `suggestion`,
+    /// such as `**`.
+    UnknownOperator { found: String, suggestion: String, span: Span },
+    /// Placeholder of a template without a value.
+    Unbound { name: String, span: Span },
+}
+
+/// Error while calculating the AST.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// Divisor is zero.
+    DivisionByZero { span: Span },
+    /// Result doesn't fit in the result type.
+    Overflow { span: Span },
+    /// Operator that the calculator doesn't know.
+    InvalidOperator { op: Token, span: Span },
+    /// Variable that isn't assigned, with the closest known name.
+    UnknownVariable { name: String, suggestion: Option<String>, span: Span },
+    /// Call of a function that isn't registered, with the closest
+    /// known name.
+    UnknownFunction { name: String, suggestion: Option<String>, span: Span },
+    /// Call with a wrong number of arguments.
+    WrongArity { name: String, expected: usize, found: usize, span: Span },
+    /// More operations than the limit.
+    TooManyOperations { limit: usize, span: Span },
+    /// Calls of the user functions nested deeper than the limit,
+    /// such as a recursion without end.
+    CallTooDeep { limit: usize, span: Span },
+    /// Value of another kind than `expected`, such as a list where
+    /// a number is expected.
+    Mismatch { expected: &'static str, span: Span },
+    /// Index past the end of the list, or negative.
+    IndexOutOfRange { index: i128, len: usize, span: Span },
+    /// Range with more items than the limit.
+    ListTooLong { limit: usize, span: Span },
+    /// Sum or product iterating more times than the limit.
+    TooManyIterations { limit: usize, span: Span },
+    /// Equation of `solve` without a single solution, or that
+    /// isn't linear nor quadratic.
+    Unsolvable { reason: &'static str, span: Span },
+    /// Formula of a cell that refers back to the cell.
+    CyclicCell { cell: String, span: Span },
+    /// Error in the formula of a cell, at the span of the formula.
+    /// The span is the reference to the cell.
+    InCell { cell: String, error: Box<Error>, span: Span },
+    /// Warning raised while warnings are errors.
+    Warning { warning: Warning },
+}
+
+/// Warning raised while calculating, the result is still
+/// returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// Integer division dropped the remainder.
+    DivisionCast { lhs: i128, rhs: i128, span: Span },
+    /// Integer past 2^53 rounded to the nearest `f64`, such as
+    /// 9007199254740993 to 9007199254740992.
+    PrecisionLoss { value: i128, span: Span },
+    /// `(` left open at the end of the input, closed by the lenient
+    /// parser.
+    Unclosed { span: Span },
+    /// Integer root rounded by the [`Rounding`](crate::Rounding),
+    /// or fraction approximated, such as `root(2, 10)`.
+    InexactRoot { degree: u32, radicand: String, span: Span },
+}
+
+/// Error of the calculator.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Lex(LexError),
+    Parse(ParseError),
+    Eval(EvalError),
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::InvalidCharacter { span, .. } => span.clone(),
+            LexError::ParseInt { span, .. } => span.clone(),
+            LexError::TooLong { span, .. } => span.clone(),
+            LexError::TooManyTokens { span, .. } => span.clone(),
+            LexError::Grouping { span, .. } => span.clone(),
+            LexError::InvalidDate { span, .. } => span.clone(),
+        }
+    }
+
+    /// Message without the position.
+    pub fn message(&self) -> String {
+        match self {
+            LexError::InvalidCharacter { ch, .. } => format!("Invalid character: {}", ch),
+            LexError::ParseInt { reason, .. } => format!("Parse int failed: {}", reason),
+            LexError::Grouping { raw, .. } => format!("Invalid digit groups: {}", raw),
+            LexError::InvalidDate { raw, .. } => format!("Invalid date: {}", raw),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. } => span.clone(),
+            ParseError::UnexpectedEnd { span, .. } => span.clone(),
+            ParseError::Unclosed { span, .. } => span.clone(),
+            ParseError::InvalidExpression { span } => span.clone(),
+            ParseError::TooDeep { span, .. } => span.clone(),
+            ParseError::TooManyOperators { span, .. } => span.clone(),
+            ParseError::UnknownOperator { span, .. } => span.clone(),
+            ParseError::Unbound { span, .. } => span.clone(),
+        }
+    }
+
+    /// Message without the position.
+    pub fn message(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl EvalError {
+    pub fn span(&self) -> Span {
+        match self {
+            EvalError::DivisionByZero { span } => span.clone(),
+            EvalError::Overflow { span } => span.clone(),
+            EvalError::InvalidOperator { span, .. } => span.clone(),
+            EvalError::UnknownVariable { span, .. } => span.clone(),
+            EvalError::UnknownFunction { span, .. } => span.clone(),
+            EvalError::WrongArity { span, .. } => span.clone(),
+            EvalError::TooManyOperations { span, .. } => span.clone(),
+            EvalError::CallTooDeep { span, .. } => span.clone(),
+            EvalError::Mismatch { span, .. } => span.clone(),
+            EvalError::IndexOutOfRange { span, .. } => span.clone(),
+            EvalError::ListTooLong { span, .. } => span.clone(),
+            EvalError::TooManyIterations { span, .. } => span.clone(),
+            EvalError::Unsolvable { span, .. } => span.clone(),
+            EvalError::CyclicCell { span, .. } => span.clone(),
+            EvalError::InCell { span, .. } => span.clone(),
+            EvalError::Warning { warning } => warning.span(),
+        }
+    }
+
+    /// Same error at another span, such as the call of the user
+    /// function whose body failed.
+    pub(crate) fn with_span(mut self, at: Span) -> Self {
+        match &mut self {
+            EvalError::DivisionByZero { span }
+            | EvalError::Overflow { span }
+            | EvalError::InvalidOperator { span, .. }
+            | EvalError::UnknownVariable { span, .. }
+            | EvalError::UnknownFunction { span, .. }
+            | EvalError::WrongArity { span, .. }
+            | EvalError::TooManyOperations { span, .. }
+            | EvalError::CallTooDeep { span, .. }
+            | EvalError::Mismatch { span, .. }
+            | EvalError::IndexOutOfRange { span, .. }
+            | EvalError::ListTooLong { span, .. }
+            | EvalError::TooManyIterations { span, .. }
+            | EvalError::Unsolvable { span, .. }
+            | EvalError::CyclicCell { span, .. }
+            | EvalError::InCell { span, .. } => *span = at,
+            EvalError::Warning { warning } => *warning = warning.clone().with_span(at),
+        }
+        self
+    }
+
+    /// Message without the position.
+    pub fn message(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Warning {
+    pub fn span(&self) -> Span {
+        match self {
+            Warning::DivisionCast { span, .. } => span.clone(),
+            Warning::PrecisionLoss { span, .. } => span.clone(),
+            Warning::Unclosed { span } => span.clone(),
+            Warning::InexactRoot { span, .. } => span.clone(),
+        }
+    }
+
+    pub(crate) fn with_span(mut self, at: Span) -> Self {
+        match &mut self {
+            Warning::DivisionCast { span, .. }
+            | Warning::PrecisionLoss { span, .. }
+            | Warning::Unclosed { span }
+            | Warning::InexactRoot { span, .. } => *span = at,
+        }
+        self
+    }
+}
+
+impl Error {
+    /// Span of the input that cause the error.
+    pub fn span(&self) -> Span {
+        match self {
+            Error::Lex(err) => err.span(),
+            Error::Parse(err) => err.span(),
+            Error::Eval(err) => err.span(),
+        }
+    }
+
+    /// Message without the position.
+    pub fn message(&self) -> String {
+        match self {
+            Error::Lex(err) => err.message(),
+            Error::Parse(err) => err.message(),
+            Error::Eval(err) => err.message(),
+        }
+    }
+
+    /// Another span of the input that explains the error, with
+    /// what it is, such as the `(` that is never closed.
+    pub fn note(&self) -> Option<(Span, &'static str)> {
+        match self {
+            Error::Parse(ParseError::Unclosed { open, closing: "|", .. }) => Some((open.clone(), "unclosed |")),
+            Error::Parse(ParseError::Unclosed { open, closing: "]", .. }) => Some((open.clone(), "unclosed [")),
+            Error::Parse(ParseError::Unclosed { open, .. }) => Some((open.clone(), "unclosed (")),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::InvalidCharacter { ch, span } => write!(f, "Invalid character near {:?}: {}", span, ch),
+            LexError::ParseInt { raw, reason, span } => write!(f, "Parse int failed: {}\nNear {:?}: {}", reason, span, raw),
+            LexError::TooLong { limit, .. } => write!(f, "Input is longer than {} bytes", limit),
+            LexError::TooManyTokens { limit, .. } => write!(f, "Input has more than {} tokens", limit),
+            LexError::Grouping { raw, span } => write!(f, "Invalid digit groups near {:?}: {}", span, raw),
+            LexError::InvalidDate { raw, span } => write!(f, "Invalid date near {:?}: {}", span, raw),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken { expected, found, .. } => write!(f, "Expect {}, got {}", expected, found),
+            ParseError::UnexpectedEnd { expected, .. } => write!(f, "Expect {}, got nothing", expected),
+            ParseError::Unclosed { closing, found: Some(found), .. } => write!(f, "Expect {}, got {}", closing, found),
+            ParseError::Unclosed { closing, found: None, .. } => write!(f, "Expect {}, got nothing", closing),
+            ParseError::InvalidExpression { .. } => write!(f, "Invalid expression"),
+            ParseError::TooDeep { limit, .. } => write!(f, "Expression is nested deeper than {}", limit),
+            ParseError::TooManyOperators { limit, .. } => write!(f, "Expression has more than {} operators", limit),
+            ParseError::UnknownOperator { found, suggestion, .. } => write!(f, "Unknown operator {}, did you mean {}?", found, suggestion),
+            ParseError::Unbound { name, .. } => write!(f, "Placeholder {{{}}} has no value", name),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::DivisionByZero { .. } => write!(f, "Division by zero"),
+            EvalError::Overflow { .. } => write!(f, "Integer overflow"),
+            EvalError::InvalidOperator { op, .. } => write!(f, "Invalid operator {}", op),
+            EvalError::UnknownVariable { name, suggestion, .. } => write!(f, "Unknown variable {}{}", name, did_you_mean(suggestion)),
+            EvalError::UnknownFunction { name, suggestion, .. } => write!(f, "Unknown function {}{}", name, did_you_mean(suggestion)),
+            EvalError::WrongArity { name, expected, found, .. } => {
+                write!(f, "Function {} takes {} arguments, got {}", name, expected, found)
+            }
+            EvalError::TooManyOperations { limit, .. } => write!(f, "Calculation takes more than {} operations", limit),
+            EvalError::CallTooDeep { limit, .. } => write!(f, "Calls are nested deeper than {}", limit),
+            EvalError::Mismatch { expected, .. } => write!(f, "Expect {}", expected),
+            EvalError::IndexOutOfRange { index, len, .. } => write!(f, "Index {} is out of a list of {} items", index, len),
+            EvalError::ListTooLong { limit, .. } => write!(f, "List is longer than {} items", limit),
+            EvalError::TooManyIterations { limit, .. } => write!(f, "Iterates more than {} times", limit),
+            EvalError::Unsolvable { reason, .. } => write!(f, "Cannot solve the equation: {}", reason),
+            EvalError::CyclicCell { cell, .. } => write!(f, "Cell {} refers to itself", cell),
+            EvalError::InCell { cell, error, .. } => write!(f, "In cell {}: {}", cell, error),
+            EvalError::Warning { warning } => warning.fmt(f),
+        }
+    }
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    suggestion.as_ref().map(|name| format!(", did you mean {}?", name)).unwrap_or_default()
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::DivisionCast { lhs, rhs, .. } => write!(f, "Division will cause a cast: {}/{}", lhs, rhs),
+            Warning::PrecisionLoss { value, .. } => write!(f, "Precision loss: {} is {} as f64", value, *value as f64),
+            Warning::Unclosed { .. } => write!(f, "Missing ) inserted at the end"),
+            Warning::InexactRoot { degree, radicand, .. } => write!(f, "Root will cause a rounding: root({}, {})", degree, radicand),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Lex(err) => err.fmt(f),
+            Error::Parse(err) => err.fmt(f),
+            Error::Eval(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+impl std::error::Error for ParseError {}
+impl std::error::Error for EvalError {}
+impl std::error::Error for Error {}
+
+impl From<LexError> for Error {
+    fn from(err: LexError) -> Self {
+        Error::Lex(err)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(err: EvalError) -> Self {
+        Error::Eval(err)
+    }
+}
@@ -0,0 +1,398 @@
+//! Step-by-step calculation, for teaching the order of operations.
+//!
+//! Each operation is reduced once its operands are known, in the
+//! order of the calculators, and recorded with the values of its
+//! operands:
+//! ```text
+//! 1 + 2 * -(3)
+//!     -(3) = -3
+//!     2 * -3 = -6
+//!     1 + -6 = -5
+//! ```
+//!
+//! # Example
+//! ```
+//! use wcal::{explain, Calculator};
+//! use wcal::explain::Reduction;
+//! use wcal::lexer::Token;
+//!
+//! let calc = Calculator::<i128>::new();
+//! let ast = calc.parse("1 + 2 * 3").unwrap();
+//! let (steps, result) = explain::explain(&ast, &mut calc.context());
+//!
+//! assert_eq!(result, Ok(7));
+//! assert_eq!(steps[0].reduction, Reduction::BinOp{lhs: 2, op: Token::Times, rhs: 3});
+//! assert_eq!(steps[0].value, 6);
+//! assert_eq!(steps[1].reduction, Reduction::BinOp{lhs: 1, op: Token::Plus, rhs: 6});
+//! ```
+//!
+//! The right operand of `&&` and `||` is only calculated if the left
+//! one doesn't decide, otherwise the operation is reduced with the
+//! left operand alone. Only the branch taken by an `if` is
+//! calculated.
+//!
+//! A `let` is recorded once its value is known, then its body is
+//! explained with the variable bound. A call of a user function is
+//! one step, its body isn't explained.
+//!
+//! A list isn't the value of a step, so the nodes with a list, such
+//! as `a[0]`, `sum(1..10)` or a `let` of a list, are one step. So is
+//! a sum over a bound variable, such as `sum(i, 1, 10, i*i)`, and
+//! the solution of an equation, `solve(2*x == 3, x)`.
+use crate::{Environment, Error, FromAST};
+use crate::generator::{self, Binding, Context};
+use crate::generator::list::{is_aggregate, is_list};
+use crate::generator::solve::is_solve;
+use crate::lexer::{Span, Token};
+use crate::parser::ast::*;
+
+/// Operation with the values of its operands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reduction<T> {
+    BinOp { lhs: T, op: Token, rhs: T },
+    /// `&&` or `||` decided by its left operand
+    ShortCircuit { lhs: T, op: Token },
+    Neg { operand: T },
+    Not { operand: T },
+    Abs { operand: T },
+    /// `if` with the value of its condition
+    If { cond: T },
+    /// Variable bound by a `let`, only visible to its body
+    Let { name: String },
+    Call { name: String, args: Vec<T> },
+    /// Value of a variable, or of `ans` and `$N` in a session
+    Var { name: String },
+    /// Assignment, only visible to the rest of the expression
+    Assign { name: String },
+    /// Node with a list, or a `solve`, calculated at once
+    List,
+}
+
+/// An operation and its result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step<T> {
+    /// Span of the operation in the input
+    pub span: Span,
+    pub reduction: Reduction<T>,
+    pub value: T,
+}
+
+/// Calculate the AST in the context one operation at a time,
+/// return the steps until the result or the error.
+///
+/// The assignments only happen in a copy of the environment, the
+/// warnings are raised in the context.
+pub fn explain<T: FromAST + Clone>(ast: &AST, ctx: &mut Context<T>) -> (Vec<Step<T>>, Result<T, Error>) {
+    let scratch = ctx.env.as_ref().map(|env| (**env).clone()).unwrap_or_default();
+    let mut explainer = Explainer{ctx, scratch, steps: Vec::new()};
+    let result = explainer.expr(&ast.root);
+    (explainer.steps, result)
+}
+
+struct Explainer<'c, 'a, T> {
+    ctx: &'c mut Context<'a, T>,
+    /// Variables of the context, and the operands
+    scratch: Environment<T>,
+    steps: Vec<Step<T>>,
+}
+
+impl<T: FromAST + Clone> Explainer<'_, '_, T> {
+    fn expr(&mut self, node: &Expr) -> Result<T, Error> {
+        match node {
+            Expr::Pair(pair) => self.expr(&pair.expr),
+            Expr::Num(_) | Expr::Duration(_) | Expr::Date(_) | Expr::Bool(_) | Expr::Str(_) => self.reduce(node.clone(), &[]),
+            // A negative literal is calculated at once, see the calculators
+            Expr::Neg(neg) if matches!(*neg.expr, Expr::Num(_)) => self.reduce(node.clone(), &[]),
+            Expr::BinOp(expr) if generator::is_chain(expr) => self.comparisons(expr),
+            Expr::BinOp(expr) if matches!(expr.op, Token::And | Token::Or) => {
+                let lhs = self.expr(&expr.lhs)?;
+                let or = expr.op == Token::Or;
+                if self.is_true(&lhs) == or {
+                    let value = self.reduce(Bool::new(or).with_span(expr.span.clone()), &[])?;
+                    return Ok(self.step(&expr.span, Reduction::ShortCircuit{lhs, op: expr.op.clone()}, value))
+                }
+                let rhs = self.expr(&expr.rhs)?;
+                let operands = [lhs, rhs];
+                let value = self.reduce(BinOp::new(operand(0), operand(1), expr.op.clone()).with_span(expr.span.clone()), &operands)?;
+                let [lhs, rhs] = operands;
+                Ok(self.step(&expr.span, Reduction::BinOp{lhs, op: expr.op.clone(), rhs}, value))
+            }
+            Expr::BinOp(expr) => {
+                let lhs = self.expr(&expr.lhs)?;
+                let rhs = self.expr(&expr.rhs)?;
+                let operands = [lhs, rhs];
+                let value = self.reduce(BinOp::new(operand(0), operand(1), expr.op.clone()).with_span(expr.span.clone()), &operands)?;
+                let [lhs, rhs] = operands;
+                Ok(self.step(&expr.span, Reduction::BinOp{lhs, op: expr.op.clone(), rhs}, value))
+            }
+            Expr::Neg(neg) => {
+                let operand_ = self.expr(&neg.expr)?;
+                let value = self.reduce(Neg::new(operand(0)).with_span(neg.span.clone()), std::slice::from_ref(&operand_))?;
+                Ok(self.step(&neg.span, Reduction::Neg{operand: operand_}, value))
+            }
+            Expr::Not(not) => {
+                let operand_ = self.expr(&not.expr)?;
+                let value = self.reduce(Not::new(operand(0)).with_span(not.span.clone()), std::slice::from_ref(&operand_))?;
+                Ok(self.step(&not.span, Reduction::Not{operand: operand_}, value))
+            }
+            Expr::Abs(abs) => {
+                let operand_ = self.expr(&abs.expr)?;
+                let value = self.reduce(Abs::new(operand(0)).with_span(abs.span.clone()), std::slice::from_ref(&operand_))?;
+                Ok(self.step(&abs.span, Reduction::Abs{operand: operand_}, value))
+            }
+            Expr::If(node) => {
+                let cond = self.expr(&node.cond)?;
+                let branch = if self.is_true(&cond) { &node.then } else { &node.otherwise };
+                let value = self.expr(branch)?;
+                Ok(self.step(&node.span, Reduction::If{cond}, value))
+            }
+            Expr::List(_) | Expr::Range(_) | Expr::Index(_) => self.at_once(node),
+            Expr::Let(let_) if is_list(&let_.value, self.ctx) => self.at_once(node),
+            Expr::Call(call) if is_aggregate(call, self.ctx) || is_solve(call) => self.at_once(node),
+            Expr::Let(node) => {
+                let value = self.expr(&node.value)?;
+                let value = self.step(&node.span, Reduction::Let{name: node.name.clone()}, value);
+                self.ctx.scopes.push((node.name.clone(), Binding::Value(value)));
+                let result = self.expr(&node.body);
+                self.ctx.scopes.pop();
+                result
+            }
+            Expr::Call(call) => {
+                let args = call.args.iter().map(|arg| self.expr(arg)).collect::<Result<Vec<_>, _>>()?;
+                let params = (0..args.len()).map(operand).collect();
+                let value = self.reduce(Call::new(&call.name, params).with_span(call.span.clone()), &args)?;
+                Ok(self.step(&call.span, Reduction::Call{name: call.name.clone(), args}, value))
+            }
+            Expr::Var(var) => {
+                let value = self.reduce(node.clone(), &[])?;
+                Ok(self.step(&var.span, Reduction::Var{name: var.name.clone()}, value))
+            }
+            Expr::Assign(assign) => {
+                let operand_ = self.expr(&assign.expr)?;
+                let value = self.reduce(Assign::new(&assign.name, operand(0)).with_span(assign.span.clone()), &[operand_])?;
+                Ok(self.step(&assign.span, Reduction::Assign{name: assign.name.clone()}, value))
+            }
+        }
+    }
+
+    /// A chain of comparisons such as `1 < x < 10`, a step for each
+    /// comparison until a false one, with each operand once.
+    fn comparisons(&mut self, node: &BinOp) -> Result<T, Error> {
+        let mut lval = None;
+        let mut value = None;
+        for expr in generator::comparisons(node).1 {
+            if let Expr::BinOp(cmp) = expr {
+                let lhs = match lval.take() {
+                    Some(lhs) => lhs,
+                    None => self.expr(&cmp.lhs)?,
+                };
+                let rhs = self.expr(&cmp.rhs)?;
+                let operands = [lhs, rhs];
+                let truth = self.reduce(BinOp::new(operand(0), operand(1), cmp.op.clone()).with_span(cmp.span.clone()), &operands)?;
+                let [lhs, rhs] = operands;
+                let truth = self.step(&cmp.span, Reduction::BinOp{lhs, op: cmp.op.clone(), rhs: rhs.clone()}, truth);
+                if !self.is_true(&truth) {
+                    return Ok(truth)
+                }
+                lval = Some(rhs);
+                value = Some(truth);
+            }
+        }
+        Ok(value.expect("a chain has comparisons"))
+    }
+
+    /// Calculate the node as one step.
+    fn at_once(&mut self, node: &Expr) -> Result<T, Error> {
+        let value = self.reduce(node.clone(), &[])?;
+        Ok(self.step(&node.span(), Reduction::List, value))
+    }
+
+    /// Calculate the node, whose operands are the variables of
+    /// [`operand`], in a context like the one explained.
+    fn reduce(&mut self, node: Expr, operands: &[T]) -> Result<T, Error> {
+        for (i, value) in operands.iter().enumerate() {
+            self.scratch.set(&operand_name(i), value.clone());
+        }
+        let mut ctx = Context::new(self.ctx.functions)
+            .env(&mut self.scratch)
+            .warnings_as_errors(self.ctx.warnings_as_errors);
+        ctx.resolver = self.ctx.resolver;
+        ctx.cells = self.ctx.cells;
+        ctx.scopes = self.ctx.scopes.clone();
+        ctx.max_calls = self.ctx.max_calls;
+        let result = T::from_ast_in(AST{root: node}, &mut ctx);
+        self.ctx.warnings.extend(ctx.warnings);
+        result
+    }
+
+    /// Whether the value is true for `&&`, `||` and `if`.
+    fn is_true(&mut self, value: &T) -> bool {
+        self.scratch.set(&operand_name(0), value.clone());
+        // `%` is never set, so `%0 || %` only fails if `%0` is false
+        let root = BinOp::new(operand(0), Var::new("%"), Token::Or);
+        let mut ctx = Context::new(self.ctx.functions).env(&mut self.scratch);
+        T::from_ast_in(AST{root}, &mut ctx).is_ok()
+    }
+
+    fn step(&mut self, span: &Span, reduction: Reduction<T>, value: T) -> T {
+        self.steps.push(Step{span: span.clone(), reduction, value: value.clone()});
+        value
+    }
+}
+
+/// Name of the i-th operand, which can't be written in an
+/// expression.
+fn operand_name(i: usize) -> String {
+    format!("%{}", i)
+}
+
+fn operand(i: usize) -> Expr {
+    Var::new(&operand_name(i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Calculator, FunctionRegistry, Rational};
+    use crate::error::{EvalError, Warning};
+
+    fn explain_expr<T: FromAST + Clone>(calc: &Calculator<T>, expr: &str) -> (Vec<Step<T>>, Result<T, Error>) {
+        explain(&calc.parse(expr).unwrap(), &mut calc.context())
+    }
+
+    #[test]
+    fn test_order() {
+        let (steps, result) = explain_expr(&Calculator::<i128>::new(), "1 + 2 * -(3)");
+        assert_eq!(result, Ok(-5));
+        let reductions: Vec<_> = steps.iter().map(|step| (step.reduction.clone(), step.value, step.span.clone())).collect();
+        assert_eq!(reductions, [
+            (Reduction::Neg{operand: 3}, -3, 8..12),
+            (Reduction::BinOp{lhs: 2, op: Token::Times, rhs: -3}, -6, 4..12),
+            (Reduction::BinOp{lhs: 1, op: Token::Plus, rhs: -6}, -5, 0..12),
+        ]);
+    }
+
+    #[test]
+    fn test_vars_and_calls() {
+        let mut functions = FunctionRegistry::new();
+        functions.register("max", 2, |args: &[Rational]| if args[0].to_f64() >= args[1].to_f64() { args[0] } else { args[1] });
+        let calc = Calculator::new().functions(functions);
+        let mut env = Environment::new();
+        env.set("x", Rational::from(4));
+        let ast = calc.parse("y = max(x, 7/2) / 2").unwrap();
+        let (steps, result) = explain(&ast, &mut calc.context().env(&mut env));
+
+        assert_eq!(result, Ok(Rational::from(2)));
+        assert_eq!(steps.len(), 5);
+        assert_eq!(steps[0].reduction, Reduction::Var{name: "x".to_string()});
+        assert_eq!(steps[2].reduction, Reduction::Call{name: "max".to_string(), args: vec![Rational::from(4), Rational::new(7, 2).unwrap()]});
+        assert_eq!(steps[4].reduction, Reduction::Assign{name: "y".to_string()});
+        // Only assigned in the copy
+        assert_eq!(env.get("y"), None);
+    }
+
+    #[test]
+    fn test_error() {
+        let calc = Calculator::<i128>::new();
+        let mut ctx = calc.context();
+        let (steps, result) = explain(&calc.parse("7/2 + 1/0").unwrap(), &mut ctx);
+        assert_eq!(steps.len(), 1);
+        assert_eq!(result, Err(EvalError::DivisionByZero{span: 6..9}.into()));
+        assert_eq!(ctx.warnings(), [Warning::DivisionCast{lhs: 7, rhs: 2, span: 0..3}]);
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let calc = Calculator::<i128>::new();
+        let (steps, result) = explain_expr(&calc, "0 && 1/0 || !2");
+        assert_eq!(result, Ok(0));
+        let reductions: Vec<_> = steps.iter().map(|step| (step.reduction.clone(), step.value)).collect();
+        assert_eq!(reductions, [
+            (Reduction::ShortCircuit{lhs: 0, op: Token::And}, 0),
+            (Reduction::Not{operand: 2}, 0),
+            (Reduction::BinOp{lhs: 0, op: Token::Or, rhs: 0}, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let calc = Calculator::<i128>::new();
+        let mut ctx = calc.context();
+        let (steps, result) = explain(&calc.parse("0 < 7/2 < 10").unwrap(), &mut ctx);
+        assert_eq!(result, Ok(1));
+        let reductions: Vec<_> = steps.iter().map(|step| (step.reduction.clone(), step.value)).collect();
+        assert_eq!(reductions, [
+            (Reduction::BinOp{lhs: 7, op: Token::Division, rhs: 2}, 3),
+            (Reduction::BinOp{lhs: 0, op: Token::Less, rhs: 3}, 1),
+            (Reduction::BinOp{lhs: 3, op: Token::Less, rhs: 10}, 1),
+        ]);
+        assert_eq!(ctx.warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_lists() {
+        let calc = Calculator::<i128>::new();
+        let (steps, result) = explain_expr(&calc, "1 + sum(1..4) * [5, 6][1]");
+        assert_eq!(result, Ok(61));
+        let reductions: Vec<_> = steps.iter().map(|step| (step.span.clone(), step.reduction.clone(), step.value)).collect();
+        assert_eq!(reductions, [
+            (4..13, Reduction::List, 10),
+            (16..25, Reduction::List, 6),
+            (4..25, Reduction::BinOp{lhs: 10, op: Token::Times, rhs: 6}, 60),
+            (0..25, Reduction::BinOp{lhs: 1, op: Token::Plus, rhs: 60}, 61),
+        ]);
+        let (steps, result) = explain_expr(&calc, "sum(i, 1, 3, i * i)");
+        assert_eq!(result, Ok(14));
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].reduction, Reduction::List);
+        let (steps, result) = explain_expr(&calc, "solve(2 * x == 8, x)");
+        assert_eq!(result, Ok(4));
+        assert_eq!(steps.len(), 1);
+    }
+
+    #[test]
+    fn test_let() {
+        let calc = Calculator::<i128>::new();
+        let mut env = Environment::new();
+        env.set("h", 1);
+        let ast = calc.parse("let h = 2 * 3 in h + h").unwrap();
+        let (steps, result) = explain(&ast, &mut calc.context().env(&mut env));
+        assert_eq!(result, Ok(12));
+        let reductions: Vec<_> = steps.iter().map(|step| (step.reduction.clone(), step.value)).collect();
+        assert_eq!(reductions, [
+            (Reduction::BinOp{lhs: 2, op: Token::Times, rhs: 3}, 6),
+            (Reduction::Let{name: "h".to_string()}, 6),
+            (Reduction::Var{name: "h".to_string()}, 6),
+            (Reduction::Var{name: "h".to_string()}, 6),
+            (Reduction::BinOp{lhs: 6, op: Token::Plus, rhs: 6}, 12),
+        ]);
+    }
+
+    #[test]
+    fn test_user_function() {
+        let calc = Calculator::<i128>::new();
+        let mut env = Environment::new();
+        let (name, function) = calc.parse_definition("sq(x) = x * x").unwrap().unwrap();
+        env.define(&name, function);
+        let ast = calc.parse("sq(1 + 2)").unwrap();
+        let (steps, result) = explain(&ast, &mut calc.context().env(&mut env));
+        assert_eq!(result, Ok(9));
+        let reductions: Vec<_> = steps.iter().map(|step| (step.reduction.clone(), step.value)).collect();
+        assert_eq!(reductions, [
+            (Reduction::BinOp{lhs: 1, op: Token::Plus, rhs: 2}, 3),
+            (Reduction::Call{name: "sq".to_string(), args: vec![3]}, 9),
+        ]);
+    }
+
+    #[test]
+    fn test_if() {
+        let calc = Calculator::<Rational>::new();
+        let (steps, result) = explain_expr(&calc, "if 1/2 then 3 else 1/0");
+        assert_eq!(result, Ok(Rational::from(3)));
+        let reductions: Vec<_> = steps.iter().map(|step| step.reduction.clone()).collect();
+        assert_eq!(reductions, [
+            Reduction::BinOp{lhs: Rational::from(1), op: Token::Division, rhs: Rational::from(2)},
+            Reduction::If{cond: Rational::new(1, 2).unwrap()},
+        ]);
+    }
+}
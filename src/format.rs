@@ -0,0 +1,406 @@
+//! Text of the results: the integers in a radix with a prefix and
+//! grouped digits, and the floats in a notation with a precision or
+//! a number of significant digits.
+//!
+//! The command line prints every result with a [`NumberFormat`], so
+//! a library printing results the same way uses it too.
+use std::convert::TryFrom;
+
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+#[cfg(feature = "decimal")]
+use rust_decimal::Decimal;
+
+use crate::rational::Rational;
+
+/// Notation of the floats and the decimals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Notation {
+    /// Shortest text that reads back the same float, or fixed with
+    /// a precision or significant digits
+    #[default]
+    Auto,
+    /// `1500.000000`, 6 decimals without a precision
+    Fixed,
+    /// `1.5e3`
+    Scientific,
+    /// `1.5e3`, `15e3` and `150e3`, the exponent a multiple of 3
+    Engineering,
+    /// `12.5k` and `3.3µ`, the engineering notation with the SI
+    /// prefix of the exponent, or the exponent past `Q` and `q`
+    Si,
+}
+
+/// How to write a number.
+///
+/// The significant digits take precedence over the precision, and
+/// the groups only separate the digits before the decimal point of
+/// the floats, in the fixed notations.
+///
+/// # Example
+/// ```
+/// use wcal::format::{Notation, NumberFormat};
+///
+/// let hex = NumberFormat{base: 16, prefix: true, group: Some(4), ..NumberFormat::default()};
+/// assert_eq!(hex.int(-0x12345), "-0x1_2345");
+/// let eng = NumberFormat{notation: Notation::Engineering, significant: Some(3), ..NumberFormat::default()};
+/// assert_eq!(eng.float(0.000123456), "123e-6");
+/// let fixed = NumberFormat{significant: Some(2), group: Some(3), separator: ',', ..NumberFormat::default()};
+/// assert_eq!(fixed.float(1234.5), "1,200");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Radix of the integers, from 2 to 36
+    pub base: u32,
+    /// Prefix the integers in base 2, 8 and 16 with `0b`, `0o` or
+    /// `0x`, as the literals
+    pub prefix: bool,
+    /// Separate the digits in groups of this many from the right
+    pub group: Option<u32>,
+    /// Separator of the groups, `_` reads back. The decimal mark is
+    /// a comma if it is a period
+    pub separator: char,
+    pub notation: Notation,
+    /// Digits after the decimal point
+    pub precision: Option<usize>,
+    /// Significant digits, at least 1
+    pub significant: Option<usize>,
+}
+
+impl Default for NumberFormat {
+    /// Decimal integers and the shortest floats, without groups.
+    fn default() -> Self {
+        NumberFormat{base: 10, prefix: false, group: None, separator: '_', notation: Notation::Auto, precision: None, significant: None}
+    }
+}
+
+impl NumberFormat {
+    pub fn int(&self, n: i128) -> String {
+        self.integer(n < 0, &radix(n.unsigned_abs(), self.base))
+    }
+
+    #[cfg(feature = "bigint")]
+    pub fn bigint(&self, n: &BigInt) -> String {
+        self.integer(n.sign() == num_bigint::Sign::Minus, &n.magnitude().to_str_radix(self.base))
+    }
+
+    /// Sign, prefix and grouped digits of an integer whose digits
+    /// in the base are `digits`.
+    pub fn integer(&self, negative: bool, digits: &str) -> String {
+        let prefix = match (self.prefix, self.base) {
+            (true, 16) => "0x",
+            (true, 8) => "0o",
+            (true, 2) => "0b",
+            _ => "",
+        };
+        let sign = if negative { "-" } else { "" };
+        format!("{}{}{}", sign, prefix, self.grouped(digits))
+    }
+
+    /// `num/den` with the integers in the base, or the integer.
+    pub fn rational(&self, value: Rational) -> String {
+        if value.is_integer() {
+            return self.int(value.numer())
+        }
+        format!("{}/{}", self.int(value.numer()), self.int(value.denom()))
+    }
+
+    pub fn float(&self, x: f64) -> String {
+        // inf and NaN
+        if !x.is_finite() {
+            return x.to_string()
+        }
+        let text = match (self.notation, self.significant, self.precision) {
+            (Notation::Auto, None, None) => x.to_string(),
+            (Notation::Auto | Notation::Fixed, None, precision) => format!("{:.*}", precision.unwrap_or(6), x),
+            (notation, significant, precision) => {
+                // The exponent and the rounding of `{:e}`, which
+                // round-trips like `to_string`
+                let digits = match significant.or_else(|| precision.filter(|_| notation == Notation::Scientific).map(|p| p + 1)) {
+                    Some(significant) => format!("{:.*e}", significant.max(1) - 1, x),
+                    None => format!("{:e}", x),
+                };
+                return self.notation(Digits::parse(&digits))
+            }
+        };
+        self.fixed(&text)
+    }
+
+    #[cfg(feature = "decimal")]
+    pub fn decimal(&self, x: Decimal) -> String {
+        let text = match (self.notation, self.significant, self.precision) {
+            (Notation::Auto, None, None) => x.to_string(),
+            (Notation::Auto | Notation::Fixed, None, precision) => format!("{:.*}", precision.unwrap_or(6), x),
+            _ => return self.notation(Digits::decimal(x)),
+        };
+        self.fixed(&text)
+    }
+
+    /// Digits in the notation, rounded to the significant digits or
+    /// the precision.
+    fn notation(&self, mut digits: Digits) -> String {
+        match (self.notation, self.significant, self.precision) {
+            (Notation::Scientific, None, Some(precision)) => digits.round(precision + 1),
+            (Notation::Engineering | Notation::Si, None, Some(precision)) => {
+                // A carry can change the digits before the point
+                let shift = digits.exponent.rem_euclid(3) as usize;
+                digits.round(shift + 1 + precision);
+                let shift = digits.exponent.rem_euclid(3) as usize;
+                digits.round(shift + 1 + precision);
+            }
+            (_, Some(significant), _) => digits.round(significant.max(1)),
+            _ => {}
+        }
+        match self.notation {
+            Notation::Scientific => digits.scientific(0),
+            Notation::Engineering => digits.scientific(digits.exponent.rem_euclid(3) as usize),
+            Notation::Si => digits.si(),
+            Notation::Auto | Notation::Fixed => self.fixed(&digits.positional()),
+        }
+    }
+
+    /// Group the digits before the decimal point of a positional
+    /// float, with a decimal comma if the groups are separated by
+    /// periods.
+    fn fixed(&self, text: &str) -> String {
+        if self.group.is_none() {
+            return text.to_string()
+        }
+        let (sign, text) = match text.strip_prefix('-') {
+            Some(text) => ("-", text),
+            None => ("", text),
+        };
+        let (int, fraction) = text.split_at(text.find('.').unwrap_or(text.len()));
+        let mark = if self.separator == '.' { "," } else { "." };
+        let fraction = fraction.strip_prefix('.').map(|fraction| format!("{}{}", mark, fraction)).unwrap_or_default();
+        format!("{}{}{}", sign, self.grouped(int), fraction)
+    }
+
+    fn grouped(&self, digits: &str) -> String {
+        match self.group {
+            Some(group) => group_digits(digits, group as usize, self.separator),
+            None => digits.to_string(),
+        }
+    }
+}
+
+/// Digits of `n` in the base, in lowercase.
+fn radix(mut n: u128, base: u32) -> String {
+    assert!((2..=36).contains(&base), "base must be from 2 to 36, got {}", base);
+    let mut digits = Vec::new();
+    loop {
+        digits.push(char::from_digit((n % u128::from(base)) as u32, base).expect("a digit of the base"));
+        n /= u128::from(base);
+        if n == 0 {
+            break
+        }
+    }
+    digits.iter().rev().collect()
+}
+
+/// Separate the digits in groups of `group` from the right, `_`
+/// can be read back by the lexer.
+pub(crate) fn group_digits(digits: &str, group: usize, separator: char) -> String {
+    let mut grouped = String::with_capacity(digits.len() * 2);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(group) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Prefixes of the powers of 1000 from `1e-30` to `1e30`.
+const SI_PREFIXES: [&str; 21] = [
+    "q", "r", "y", "z", "a", "f", "p", "n", "µ", "m", "", "k", "M", "G", "T", "P", "E", "Z", "Y", "R", "Q",
+];
+
+/// Decimal digits of a finite number and the exponent of the first
+/// one, so `-0.0125` is `-125` at `-2`. Zero is `0` at `0`.
+#[derive(Debug, PartialEq)]
+struct Digits {
+    negative: bool,
+    digits: String,
+    exponent: i32,
+}
+
+impl Digits {
+    /// Digits of the `{:e}` text of a float, such as `-1.25e-2`.
+    fn parse(text: &str) -> Self {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text),
+        };
+        let (mantissa, exponent) = text.split_once('e').expect("the text of {:e}");
+        Digits{negative, digits: mantissa.replace('.', ""), exponent: exponent.parse().expect("the exponent of {:e}")}
+    }
+
+    /// Digits of the decimal, without the trailing zeros.
+    #[cfg(feature = "decimal")]
+    fn decimal(x: Decimal) -> Self {
+        let digits = x.mantissa().unsigned_abs().to_string();
+        let trimmed = digits.trim_end_matches('0');
+        if trimmed.is_empty() {
+            return Digits{negative: false, digits: "0".to_string(), exponent: 0}
+        }
+        let exponent = digits.len() as i32 - 1 - x.scale() as i32;
+        Digits{negative: x.is_sign_negative(), digits: trimmed.to_string(), exponent}
+    }
+
+    /// Round to `len` digits, the halves away from zero, or pad with
+    /// zeros.
+    fn round(&mut self, len: usize) {
+        if self.digits.len() <= len {
+            let pad = len - self.digits.len();
+            self.digits.extend(std::iter::repeat_n('0', pad));
+            return
+        }
+        let up = self.digits.as_bytes()[len] >= b'5';
+        self.digits.truncate(len);
+        if !up {
+            return
+        }
+        let mut digits = self.digits.clone().into_bytes();
+        let carry = digits.iter_mut().rev().all(|digit| {
+            let nine = *digit == b'9';
+            *digit = if nine { b'0' } else { *digit + 1 };
+            nine
+        });
+        if carry {
+            // 9.99 is 10.0, one more digit before the point
+            digits.insert(0, b'1');
+            digits.pop();
+            self.exponent += 1;
+        }
+        self.digits = String::from_utf8(digits).expect("ASCII digits");
+    }
+
+    /// `1.25e-2`, or `12.5e-3` with 1 more digit before the point.
+    fn scientific(&self, shift: usize) -> String {
+        let sign = if self.negative { "-" } else { "" };
+        let mut digits = self.digits.clone();
+        if digits.len() < shift + 1 {
+            digits.extend(std::iter::repeat_n('0', shift + 1 - digits.len()));
+        }
+        let (int, fraction) = digits.split_at(shift + 1);
+        let point = if fraction.is_empty() { "" } else { "." };
+        format!("{}{}{}{}e{}", sign, int, point, fraction, self.exponent - shift as i32)
+    }
+
+    /// `12.5k`, the engineering notation with the SI prefix of the
+    /// exponent, `12.5e33` past the prefixes.
+    fn si(&self) -> String {
+        let shift = self.exponent.rem_euclid(3);
+        let text = self.scientific(shift as usize);
+        let exponent = self.exponent - shift;
+        let prefix = usize::try_from((exponent + 30) / 3).ok().and_then(|i| SI_PREFIXES.get(i));
+        match (prefix, text.split_once('e')) {
+            (Some(prefix), Some((mantissa, _))) => format!("{}{}", mantissa, prefix),
+            _ => text,
+        }
+    }
+
+    /// `-0.0125`, the digits with a decimal point.
+    fn positional(&self) -> String {
+        let sign = if self.negative { "-" } else { "" };
+        let (int, fraction) = if self.exponent >= 0 {
+            let point = self.exponent as usize + 1;
+            let mut digits = self.digits.clone();
+            if digits.len() < point {
+                digits.extend(std::iter::repeat_n('0', point - digits.len()));
+            }
+            let (int, fraction) = digits.split_at(point);
+            (int.to_string(), fraction.to_string())
+        } else {
+            ("0".to_string(), "0".repeat((-self.exponent - 1) as usize) + &self.digits)
+        };
+        let point = if fraction.is_empty() { "" } else { "." };
+        format!("{}{}{}{}", sign, int, point, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format() -> NumberFormat {
+        NumberFormat::default()
+    }
+
+    #[test]
+    fn test_int() {
+        assert_eq!(NumberFormat{base: 16, ..format()}.int(255), "ff");
+        assert_eq!(NumberFormat{base: 16, prefix: true, ..format()}.int(-255), "-0xff");
+        assert_eq!(NumberFormat{base: 2, prefix: true, group: Some(4), ..format()}.int(0x1234), "0b1_0010_0011_0100");
+        assert_eq!(NumberFormat{group: Some(3), ..format()}.int(1234567), "1_234_567");
+        assert_eq!(NumberFormat{base: 8, ..format()}.int(i128::MIN), format!("-{:o}", i128::MIN.unsigned_abs()));
+        assert_eq!(NumberFormat{base: 36, prefix: true, ..format()}.int(-1295), "-zz");
+        assert_eq!(format().int(0), "0");
+        let r = |num, den| Rational::new(num, den).unwrap();
+        assert_eq!(NumberFormat{base: 16, prefix: true, ..format()}.rational(r(1, 16)), "0x1/0x10");
+        assert_eq!(format().rational(r(-4, 2)), "-2");
+    }
+
+    #[test]
+    fn test_float() {
+        let notation = |notation, precision, significant| NumberFormat{notation, precision, significant, ..format()};
+        use Notation::*;
+
+        let cases = [
+            (Auto, None, None, 0.25, "0.25"),
+            (Auto, Some(1), None, 0.25, "0.2"),
+            (Fixed, None, None, 0.25, "0.250000"),
+            (Auto, None, Some(3), 1234.5, "1230"),
+            (Fixed, Some(1), Some(3), 0.0012345, "0.00123"),
+            (Auto, None, Some(2), 9.96, "10"),
+            (Auto, None, Some(3), 1.5, "1.50"),
+            (Scientific, None, None, 1500.0, "1.5e3"),
+            (Scientific, Some(2), None, 1500.0, "1.50e3"),
+            (Scientific, None, Some(2), -0.0123, "-1.2e-2"),
+            (Engineering, None, None, 1500.0, "1.5e3"),
+            (Engineering, None, None, 15000.0, "15e3"),
+            (Engineering, None, None, 0.00015, "150e-6"),
+            (Engineering, Some(1), None, 999.96, "1.0e3"),
+            (Engineering, None, Some(4), 12345678.0, "12.35e6"),
+            (Engineering, None, Some(1), 0.0, "0e0"),
+            (Si, None, None, 12500.0, "12.5k"),
+            (Si, None, None, 3.3e-6, "3.3µ"),
+            (Si, None, None, -0.25, "-250m"),
+            (Si, None, None, 42.0, "42"),
+            (Si, Some(2), None, 999.999, "1.00k"),
+            (Si, None, Some(2), 1.5e-29, "15q"),
+            (Si, None, None, 1e33, "1e33"),
+            (Si, None, None, 0.0, "0"),
+            (Scientific, None, None, f64::NAN, "NaN"),
+        ];
+        for (notation_, precision, significant, x, text) in cases {
+            assert_eq!(notation(notation_, precision, significant).float(x), text, "{:?} {:?} {:?} {}", notation_, precision, significant, x);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "decimal")]
+    fn test_decimal() {
+        let d = |text: &str| text.parse::<Decimal>().unwrap();
+        assert_eq!(format().decimal(d("0.30")), "0.30");
+        assert_eq!(NumberFormat{precision: Some(2), group: Some(3), ..format()}.decimal(d("1234.5")), "1_234.50");
+        assert_eq!(NumberFormat{significant: Some(2), ..format()}.decimal(d("-0.0125")), "-0.013");
+        assert_eq!(NumberFormat{notation: Notation::Scientific, precision: Some(1), ..format()}.decimal(d("1.96")), "2.0e0");
+        assert_eq!(NumberFormat{notation: Notation::Engineering, ..format()}.decimal(d("12500.00")), "12.5e3");
+        assert_eq!(NumberFormat{notation: Notation::Scientific, ..format()}.decimal(d("0.000")), "0e0");
+    }
+
+    #[test]
+    fn test_group() {
+        assert_eq!(group_digits("1", 3, '_'), "1");
+        assert_eq!(group_digits("123", 3, '_'), "123");
+        assert_eq!(group_digits("1234", 3, '_'), "1_234");
+        assert_eq!(group_digits("101", 1, '_'), "1_0_1");
+        assert_eq!(group_digits("1234567", 3, ','), "1,234,567");
+        let format = NumberFormat{group: Some(3), separator: '.', ..format()};
+        assert_eq!(format.float(-1234567.5), "-1.234.567,5");
+        assert_eq!(format.float(f64::NEG_INFINITY), "-inf");
+        assert_eq!(NumberFormat{notation: Notation::Scientific, ..format}.float(1234.5), "1.2345e3");
+    }
+}
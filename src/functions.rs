@@ -0,0 +1,105 @@
+//! Functions registered at runtime.
+//!
+//! The parser accepts a call of any name, such as `hypot(3, 4)`.
+//! The name is resolved through a [`FunctionRegistry`] while
+//! calculating, an unknown name or a wrong number of arguments
+//! is an error pointing at the call.
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::error::EvalError;
+use crate::lexer::Span;
+
+pub(crate) type Callback<T> = Box<dyn Fn(&[T]) -> T + Send + Sync>;
+
+/// Functions that can be called from the expression, for the
+/// result type `T`.
+///
+/// # Example
+/// ```
+/// use wcal::{calculator_with, parser, FunctionRegistry};
+///
+/// let mut functions = FunctionRegistry::new();
+/// functions.register("hypot", 2, |args: &[f64]| args[0].hypot(args[1]));
+///
+/// let res: f64 = calculator_with("hypot(3, 4) + 1", parser::top_down_parser::parse, &functions).unwrap();
+/// assert_eq!(res, 6f64);
+/// ```
+pub struct FunctionRegistry<T> {
+    functions: HashMap<String, (usize, Callback<T>)>,
+}
+
+impl<T> FunctionRegistry<T> {
+    pub fn new() -> Self {
+        FunctionRegistry{functions: HashMap::new()}
+    }
+
+    /// Register a function taking `arity` arguments, replace the
+    /// function of the same name.
+    pub fn register<F>(&mut self, name: &str, arity: usize, f: F) -> &mut Self
+    where F: Fn(&[T]) -> T + Send + Sync + 'static {
+        self.functions.insert(name.to_string(), (arity, Box::new(f)));
+        self
+    }
+
+    /// Number of arguments of the function, `None` if unknown.
+    pub fn arity(&self, name: &str) -> Option<usize> {
+        self.functions.get(name).map(|(arity, _)| *arity)
+    }
+
+    /// Names and numbers of arguments of the functions, ordered by
+    /// name.
+    pub fn signatures(&self) -> Vec<(&str, usize)> {
+        let mut signatures: Vec<_> = self.functions.iter()
+            .map(|(name, (arity, _))| (name.as_str(), *arity))
+            .collect();
+        signatures.sort_unstable();
+        signatures
+    }
+
+    /// Find the function to call with `found` arguments.
+    pub(crate) fn resolve(&self, name: &str, found: usize, span: Span) -> Result<&Callback<T>, EvalError> {
+        match self.functions.get(name) {
+            Some((arity, f)) if *arity == found => Ok(f),
+            Some((arity, _)) => Err(EvalError::WrongArity{name: name.to_string(), expected: *arity, found, span}),
+            None => Err(EvalError::UnknownFunction{name: name.to_string(), suggestion: None, span}),
+        }
+    }
+}
+
+impl<T> Default for FunctionRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for FunctionRegistry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.functions.iter().map(|(name, (arity, _))| (name, arity)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let mut functions = FunctionRegistry::new();
+        functions
+            .register("max", 2, |args: &[i128]| args[0].max(args[1]))
+            .register("one", 0, |_| 1);
+
+        assert_eq!(functions.arity("max"), Some(2));
+        assert_eq!(functions.arity("min"), None);
+        assert_eq!(functions.signatures(), [("max", 2), ("one", 0)]);
+        assert_eq!(functions.resolve("max", 2, 0..3).map(|f| f(&[1, 2])), Ok(2));
+        assert_eq!(functions.resolve("one", 0, 0..3).map(|f| f(&[])), Ok(1));
+        assert_eq!(functions.resolve("max", 1, 0..3).err(),
+            Some(EvalError::WrongArity{name: "max".to_string(), expected: 2, found: 1, span: 0..3}));
+        assert_eq!(functions.resolve("min", 2, 0..3).err(),
+            Some(EvalError::UnknownFunction{name: "min".to_string(), suggestion: None, span: 0..3}));
+    }
+}
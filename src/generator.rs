@@ -2,8 +2,472 @@
 //!
 //! Current implemented generator:
 //! * `calculator`: calculate the expression to `i128`, will
-//! cause a cast in division
+//!   cause a cast in division
 //! * `calculator_f`: calculate the expression to `f64`
+//! * `calculator_r`: calculate the expression to an exact
+//!   [`Rational`](crate::Rational)
+//! * `calculator_t`: calculate the expression to a
+//!   [`Time`](crate::Time), a number or a duration
+//! * `calculator_m`: calculate the expression to a
+//!   [`Matrix`](crate::Matrix), a number or a matrix
+//! * `calculator_big`: calculate the expression to a `BigInt`
+//!   that never overflows, with the `bigint` feature
+//! * `calculator_d`: calculate the expression to a `Decimal` of 28
+//!   digits, with the `decimal` feature
 
 pub mod calculator;
 pub mod calculator_f;
+pub mod calculator_r;
+pub mod calculator_t;
+pub mod calculator_m;
+#[cfg(feature = "bigint")]
+pub mod calculator_big;
+#[cfg(feature = "decimal")]
+pub mod calculator_d;
+pub(crate) mod list;
+pub(crate) mod solve;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::angle::AngleUnit;
+use crate::root::Rounding;
+use crate::cells::{self, Cell, CellResolver};
+use crate::environment::{Environment, UserFunction};
+use crate::error::{Error, EvalError, Warning};
+use crate::functions::{Callback, FunctionRegistry};
+use crate::lexer::{self, Span, Token};
+use crate::parser::ast::{BinOp, Call, Expr, Number, Var};
+use crate::parser::{top_down_parser, MAX_DEPTH};
+use crate::resolver::Resolver;
+use crate::suggest;
+use crate::text;
+
+/// Max depth of nested calls of the user functions. Each call
+/// calculates its body on the stack, so a recursion fails past it
+/// rather than overflowing the stack.
+pub const MAX_CALL_DEPTH: usize = 64;
+
+/// Max items of a list, so a range fails before exhausting the
+/// memory.
+pub const MAX_LIST_LEN: usize = 1 << 20;
+
+/// Max iterations of a sum or a product over a bound variable, such
+/// as `sum(i, 1, 100, i*i)`.
+pub const MAX_ITERATIONS: usize = 1 << 20;
+
+/// Value bound to a name by a `let` or a parameter.
+#[derive(Debug, Clone)]
+pub(crate) enum Binding<T> {
+    Value(T),
+    List(Vec<T>),
+}
+
+/// Operations of a calculator on its values rather than on the
+/// AST, for [`eval_streaming`](crate::eval_streaming) which
+/// calculates `+ - * /` as it parses them.
+pub struct Arithmetic<T> {
+    pub(crate) number: fn(&Number, &mut Context<T>) -> Result<T, EvalError>,
+    /// `-num` of a literal, spanning the `-`
+    pub(crate) negative: fn(&Number, &Span, &mut Context<T>) -> Result<T, EvalError>,
+    pub(crate) negate: fn(T, &Span) -> Result<T, EvalError>,
+    pub(crate) binary: Binary<T>,
+}
+
+/// `lhs op rhs` of an arithmetic operator spanning `span`.
+pub(crate) type Binary<T> = fn(op: &Token, lhs: T, rhs: T, span: &Span, ctx: &mut Context<T>) -> Result<T, EvalError>;
+
+/// State of a calculation: the functions to call, the variables,
+/// the count of operations and the warnings raised.
+///
+/// Without an environment, variables are unknown and assignments
+/// only return the value.
+pub struct Context<'a, T> {
+    pub(crate) functions: &'a FunctionRegistry<T>,
+    pub(crate) env: Option<&'a mut Environment<T>>,
+    /// Values of the variables missing in the environment
+    pub(crate) resolver: Option<&'a dyn Resolver<T>>,
+    pub(crate) cells: Option<&'a dyn CellResolver<T>>,
+    /// Cells whose formulas are being calculated, and the values of
+    /// the calculated ones
+    visiting: Vec<String>,
+    cell_values: HashMap<String, T>,
+    /// Variables bound by the enclosing `let`, innermost last
+    pub(crate) scopes: Vec<(String, Binding<T>)>,
+    on_step: Option<&'a (dyn Fn(&Expr) + Send + Sync)>,
+    operations: usize,
+    max_operations: usize,
+    /// Calls of the user functions in progress
+    calls: usize,
+    pub(crate) max_calls: usize,
+    /// Max depth of the formulas of the cells
+    max_depth: usize,
+    pub(crate) warnings: Vec<Warning>,
+    pub(crate) warnings_as_errors: bool,
+    /// Unit of the angles of the trigonometric functions
+    pub(crate) angle_unit: AngleUnit,
+    /// Rounding of the inexact roots of the integer calculators
+    pub(crate) rounding: Rounding,
+}
+
+impl<'a, T> Context<'a, T> {
+    /// Context without limit of operations.
+    pub fn new(functions: &'a FunctionRegistry<T>) -> Self {
+        Context{
+            functions,
+            env: None,
+            resolver: None,
+            cells: None,
+            visiting: Vec::new(),
+            cell_values: HashMap::new(),
+            scopes: Vec::new(),
+            on_step: None,
+            operations: 0,
+            max_operations: usize::MAX,
+            calls: 0,
+            max_calls: MAX_CALL_DEPTH,
+            max_depth: MAX_DEPTH,
+            warnings: Vec::new(),
+            warnings_as_errors: false,
+            angle_unit: AngleUnit::default(),
+            rounding: Rounding::default(),
+        }
+    }
+
+    /// Read and assign the variables in the environment.
+    pub fn env(mut self, env: &'a mut Environment<T>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    /// Read the variables missing in the environment from the
+    /// resolver, such as the results of a session.
+    pub fn resolver(mut self, resolver: &'a dyn Resolver<T>) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Read the cells such as `A1` missing in the environment and
+    /// the resolver from the cell resolver.
+    pub fn cells(mut self, cells: &'a dyn CellResolver<T>) -> Self {
+        self.cells = Some(cells);
+        self
+    }
+
+    /// Fail after calculating `max_operations` nodes.
+    pub fn max_operations(mut self, max_operations: usize) -> Self {
+        self.max_operations = max_operations;
+        self
+    }
+
+    /// Fail when the calls of the user functions are nested deeper
+    /// than `max_calls`, capped by [`MAX_CALL_DEPTH`].
+    pub fn max_call_depth(mut self, max_calls: usize) -> Self {
+        self.max_calls = max_calls.min(MAX_CALL_DEPTH);
+        self
+    }
+
+    /// Fail when the formula of a cell is nested deeper than
+    /// `max_depth` rather than [`MAX_DEPTH`]. A deeper limit needs a
+    /// larger stack, see [`Limits::depth`](crate::Limits::depth).
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Fail with [`EvalError::Warning`] instead of raising a
+    /// warning.
+    pub fn warnings_as_errors(mut self, warnings_as_errors: bool) -> Self {
+        self.warnings_as_errors = warnings_as_errors;
+        self
+    }
+
+    /// Unit of the angles of `sin`, `cos`, `tan` and their inverses,
+    /// see [`AngleUnit`].
+    pub fn angle_unit(mut self, angle_unit: AngleUnit) -> Self {
+        self.angle_unit = angle_unit;
+        self
+    }
+
+    /// Rounding of `root(n, x)` when the root of an integer isn't
+    /// one, see [`Rounding`].
+    pub fn rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Call `f` with each node before calculating it.
+    pub fn on_step(mut self, f: &'a (dyn Fn(&Expr) + Send + Sync)) -> Self {
+        self.on_step = Some(f);
+        self
+    }
+
+    /// Value of the variable, from the `let` bindings first, then
+    /// from the environment. `None` if it is bound to a list.
+    pub(crate) fn var(&self, name: &str) -> Option<T>
+    where T: Clone {
+        match self.binding(name) {
+            Some(Binding::Value(value)) => return Some(value.clone()),
+            Some(Binding::List(_)) => return None,
+            None => {}
+        }
+        self.env.as_ref()
+            .and_then(|env| env.get(name).cloned())
+            .or_else(|| self.resolver.and_then(|resolver| resolver.resolve(name)))
+    }
+
+    /// Items of the list bound to the name by a `let`.
+    pub(crate) fn list(&self, name: &str) -> Option<&[T]> {
+        match self.binding(name) {
+            Some(Binding::List(items)) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn binding(&self, name: &str) -> Option<&Binding<T>> {
+        self.scopes.iter().rev().find(|(bound, _)| bound == name).map(|(_, binding)| binding)
+    }
+
+    /// Error of the variable that [`var`](Context::var) can't find.
+    pub(crate) fn unknown(&self, var: &Var) -> EvalError {
+        match self.binding(&var.name) {
+            Some(Binding::List(_)) => EvalError::Mismatch{expected: "a number, not a list", span: var.span.clone()},
+            _ => {
+                let scopes = self.scopes.iter().map(|(name, _)| name.as_str());
+                let env = self.env.iter().flat_map(|env| env.iter().map(|(name, _)| name.as_str()));
+                let suggestion = suggest::closest(&var.name, scopes.chain(env));
+                EvalError::UnknownVariable{name: var.name.clone(), suggestion, span: var.span.clone()}
+            }
+        }
+    }
+
+    /// Registered function called by the node, suggesting the
+    /// closest known name if there is none.
+    pub(crate) fn function(&self, call: &Call) -> Result<&'a Callback<T>, EvalError> {
+        let functions = self.functions;
+        functions.resolve(&call.name, call.args.len(), call.span.clone()).map_err(|err| match err {
+            // A text where a number is expected
+            EvalError::UnknownFunction{name, span, ..} if text::FUNCTIONS.contains(&name.as_str()) => text::not_a_number(span),
+            EvalError::UnknownFunction{name, span, ..} => {
+                let registered = functions.signatures().into_iter().map(|(name, _)| name);
+                let user = self.env.iter().flat_map(|env| env.functions().map(|(name, _)| name.as_str()));
+                let suggestion = suggest::closest(&name, registered.chain(user).chain(list::AGGREGATES.iter().copied()));
+                EvalError::UnknownFunction{name, suggestion, span}
+            }
+            err => err,
+        })
+    }
+
+    /// Call `f` with the variable bound to the value, as the body of
+    /// a `let`.
+    pub(crate) fn scoped<R>(&mut self, name: &str, value: Binding<T>, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.scopes.push((name.to_string(), value));
+        let res = f(self);
+        self.scopes.pop();
+        res
+    }
+
+    /// User function of the environment called by the node,
+    /// `None` if there is none of its name.
+    pub(crate) fn user_function(&self, call: &Call) -> Result<Option<Arc<UserFunction>>, EvalError> {
+        let function = match self.env.as_ref().and_then(|env| env.shared_function(&call.name)) {
+            Some(function) => function,
+            None => return Ok(None),
+        };
+        if function.params.len() != call.args.len() {
+            let (expected, found) = (function.params.len(), call.args.len());
+            return Err(EvalError::WrongArity{name: call.name.clone(), expected, found, span: call.span.clone()})
+        }
+        Ok(Some(function))
+    }
+
+    /// Calculate the body of the user function with `calculate`,
+    /// its parameters bound to the arguments. The body doesn't see
+    /// the `let` bindings of the call, and its errors and warnings
+    /// point at the call.
+    pub(crate) fn call_user<F>(&mut self, function: &UserFunction, args: Vec<T>, span: &Span, calculate: F) -> Result<T, EvalError>
+    where F: FnOnce(&Expr, &mut Self) -> Result<T, EvalError> {
+        let bound = function.params.iter().cloned().zip(args.into_iter().map(Binding::Value)).collect();
+        self.nested(bound, span, |ctx| calculate(&function.body, ctx))
+            .map_err(|err| err.with_span(span.clone()))
+    }
+
+    /// Value of the cell the variable refers to, calculating its
+    /// formula with `calculate`. `None` if the variable isn't a cell
+    /// of the cell resolver.
+    pub(crate) fn cell<F>(&mut self, var: &Var, calculate: F) -> Result<Option<T>, EvalError>
+    where F: FnOnce(&Expr, &mut Self) -> Result<T, EvalError>, T: Clone {
+        let cells = match self.cells {
+            Some(cells) if cells::is_cell(&var.name) && self.binding(&var.name).is_none() => cells,
+            _ => return Ok(None),
+        };
+        if let Some(value) = self.cell_values.get(&var.name) {
+            return Ok(Some(value.clone()))
+        }
+        let formula = match cells.cell(&var.name) {
+            Some(Cell::Formula(formula)) => formula,
+            Some(Cell::Value(value)) => return Ok(Some(value)),
+            None => return Ok(None),
+        };
+        if self.visiting.contains(&var.name) {
+            return Err(EvalError::CyclicCell{cell: var.name.clone(), span: var.span.clone()})
+        }
+        let in_cell = |error: Error| match error {
+            // Already about another cell, or about the whole calculation
+            Error::Eval(err @ EvalError::InCell{..}) | Error::Eval(err @ EvalError::CyclicCell{..})
+            | Error::Eval(err @ EvalError::CallTooDeep{..}) | Error::Eval(err @ EvalError::TooManyOperations{..}) => {
+                err.with_span(var.span.clone())
+            }
+            error => EvalError::InCell{cell: var.name.clone(), error: Box::new(error), span: var.span.clone()},
+        };
+        let ast = top_down_parser::parse_limited(lexer::tokens(&formula), self.max_depth).map_err(in_cell)?;
+        self.visiting.push(var.name.clone());
+        let res = self.nested(Vec::new(), &var.span, |ctx| calculate(&ast.root, ctx));
+        self.visiting.pop();
+        let value = res.map_err(|err| in_cell(err.into()))?;
+        self.cell_values.insert(var.name.clone(), value.clone());
+        Ok(Some(value))
+    }
+
+    /// Call `f` one call deeper, with only the bindings, as the body
+    /// of a user function or the formula of a cell. Its warnings
+    /// point at the span.
+    fn nested<R>(&mut self, bound: Vec<(String, Binding<T>)>, span: &Span, f: impl FnOnce(&mut Self) -> Result<R, EvalError>) -> Result<R, EvalError> {
+        if self.calls >= self.max_calls {
+            return Err(EvalError::CallTooDeep{limit: self.max_calls, span: span.clone()})
+        }
+        let scopes = std::mem::replace(&mut self.scopes, bound);
+        let raised = self.warnings.len();
+        self.calls += 1;
+        let res = f(self);
+        self.calls -= 1;
+        self.scopes = scopes;
+        for warning in self.warnings[raised..].iter_mut() {
+            *warning = warning.clone().with_span(span.clone());
+        }
+        res
+    }
+
+    /// Warnings raised so far.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub(crate) fn warn(&mut self, warning: Warning) -> Result<(), EvalError> {
+        if self.warnings_as_errors {
+            return Err(EvalError::Warning{warning})
+        }
+        self.warnings.push(warning);
+        Ok(())
+    }
+
+    /// Count the operation of calculating the node.
+    pub(crate) fn step(&mut self, node: &Expr) -> Result<(), EvalError> {
+        if self.operations >= self.max_operations {
+            return Err(EvalError::TooManyOperations{limit: self.max_operations, span: node.span()})
+        }
+        self.operations += 1;
+        if let Some(on_step) = self.on_step {
+            on_step(node);
+        }
+        Ok(())
+    }
+
+    /// Leftmost operand of the chain of operators of `node`, such as
+    /// `1` of `1+2+3`, and the operators from the innermost. Each one
+    /// below `node` counts as a step, so the calculators apply them in
+    /// a loop rather than a frame per operator.
+    pub(crate) fn chain<'b>(&mut self, node: &'b BinOp) -> Result<(&'b Expr, Vec<&'b BinOp>), EvalError> {
+        let mut chain = vec![node];
+        let mut lhs = &*node.lhs;
+        while let Expr::BinOp(inner) = lhs {
+            if is_chain(inner) {
+                break
+            }
+            self.step(lhs)?;
+            chain.push(inner);
+            lhs = &inner.lhs;
+        }
+        chain.reverse();
+        Ok((lhs, chain))
+    }
+
+    /// Truth of the chain of comparisons of `node`, see [`is_chain`],
+    /// calculating each operand once: the right operand of a
+    /// comparison is the left one of the next. `compare` is the truth
+    /// of a comparison of two values.
+    pub(crate) fn compare_chain(
+        &mut self,
+        node: &BinOp,
+        calculate: fn(&Expr, &mut Self) -> Result<T, EvalError>,
+        compare: fn(&BinOp, &T, &T) -> Result<bool, EvalError>,
+    ) -> Result<bool, EvalError> {
+        let (ands, comparisons) = comparisons(node);
+        for and in ands {
+            self.step(and)?;
+        }
+        let mut lval = None;
+        for expr in comparisons {
+            self.step(expr)?;
+            if let Expr::BinOp(cmp) = expr {
+                let lhs = match lval.take() {
+                    Some(lhs) => lhs,
+                    None => calculate(&cmp.lhs, self)?,
+                };
+                let rhs = calculate(&cmp.rhs, self)?;
+                if !compare(cmp, &lhs, &rhs)? {
+                    return Ok(false)
+                }
+                lval = Some(rhs);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The `&&` below `node` of a chain of comparisons, see [`is_chain`],
+/// and the comparisons from the left.
+pub(crate) fn comparisons(node: &BinOp) -> (Vec<&Expr>, Vec<&Expr>) {
+    let (mut ands, mut comparisons) = (Vec::new(), Vec::new());
+    let mut and = node;
+    loop {
+        comparisons.push(&*and.rhs);
+        match &*and.lhs {
+            Expr::BinOp(lhs) if lhs.op == Token::And => {
+                ands.push(&*and.lhs);
+                and = lhs;
+            }
+            first => {
+                comparisons.push(first);
+                break
+            }
+        }
+    }
+    comparisons.reverse();
+    (ands, comparisons)
+}
+
+/// Whether `node` is the `&&` of a chain of comparisons such as
+/// `1 < x < 10`, which the parser makes `1 < x && x < 10` with a
+/// copy of `x`.
+pub(crate) fn is_chain(node: &BinOp) -> bool {
+    let mut and = node;
+    loop {
+        let (lhs, next) = match (&and.op, &*and.lhs, &*and.rhs) {
+            (Token::And, Expr::BinOp(lhs), Expr::BinOp(next)) if next.op.is_comparison() => (lhs, next),
+            _ => return false,
+        };
+        let prev = match &*lhs.rhs {
+            Expr::BinOp(prev) if lhs.op == Token::And && prev.op.is_comparison() => prev,
+            _ if lhs.op.is_comparison() => lhs,
+            _ => return false,
+        };
+        if prev.rhs.span() != next.lhs.span() || prev.rhs != next.lhs {
+            return false
+        }
+        if lhs.op != Token::And {
+            return true
+        }
+        and = lhs;
+    }
+}
@@ -2,75 +2,255 @@
 //!
 //! A warning will raise while division cast happened
 //!
-//! A error will raise while division by zero
-use crate::lexer::Token;
+//! A error will raise while division by zero or overflow
+use std::convert::TryFrom;
+
+use crate::lexer::{Span, Token};
 use crate::parser::ast::*;
+use crate::error::{Error, EvalError, Warning};
+use crate::functions::FunctionRegistry;
+use crate::generator::{is_chain, list, solve, Arithmetic, Context};
+use crate::root;
+use crate::text;
 
 trait Calculable {
-    fn calculate(node: &Self) -> i128;
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError>;
 }
 
 impl Calculable for Expr {
-    fn calculate(node: &Self) -> i128 {
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        ctx.step(node)?;
         match node {
-            Expr::Pair(pair) => Pair::calculate(pair),
-            Expr::BinOp(expr) => BinOp::calculate(expr),
-            Expr::Neg(neg) => Neg::calculate(neg),
-            Expr::Num(num) => Number::calculate(num),
+            Expr::Pair(pair) => Pair::calculate(pair, ctx),
+            Expr::BinOp(expr) => BinOp::calculate(expr, ctx),
+            Expr::Neg(neg) => Neg::calculate(neg, ctx),
+            Expr::Not(not) => Not::calculate(not, ctx),
+            Expr::Abs(abs) => Abs::calculate(abs, ctx),
+            Expr::If(node) => If::calculate(node, ctx),
+            Expr::Let(node) => list::let_in(node, ctx),
+            Expr::List(_) | Expr::Range(_) => Err(list::not_a_number(node)),
+            Expr::Index(node) => list::index(node, ctx),
+            Expr::Call(call) => Call::calculate(call, ctx),
+            Expr::Var(var) => Var::calculate(var, ctx),
+            Expr::Assign(assign) => Assign::calculate(assign, ctx),
+            Expr::Num(num) => Number::calculate(num, ctx),
+            Expr::Duration(duration) => Number::calculate(&duration.to_number(), ctx),
+            Expr::Date(date) => Ok(i128::from(date.days)),
+            Expr::Bool(bool) => Ok(i128::from(bool.value)),
+            Expr::Str(_) => Err(text::not_a_number(node.span())),
         }
     }
 }
 
 impl Calculable for BinOp {
-    fn calculate(node: &Self) -> i128 {
-        let lval = Expr::calculate(&node.lhs);
-        let rval = Expr::calculate(&node.rhs);
-        match node.op {
-            Token::Plus => lval + rval,
-            Token::Minus => lval - rval,
-            Token::Times => lval * rval,
-            Token::Division => {
-                if rval == 0 {
-                    eprintln!("Error: division by zero");
-                    panic!()
-                }
-                if lval % rval != 0 {
-                    eprintln!("Warning: division will cause a cast");
-                }
-                lval / rval
-            },
-            _ => panic!("Unknown operator")
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        if is_chain(node) {
+            let value = ctx.compare_chain(node, Expr::calculate, |node, lhs, rhs| Ok(node.op.compares(lhs.partial_cmp(rhs))))?;
+            return Ok(i128::from(value))
+        }
+        let (lhs, chain) = ctx.chain(node)?;
+        let mut lval = Expr::calculate(lhs, ctx)?;
+        for node in chain {
+            lval = apply(node, lval, ctx)?;
         }
+        Ok(lval)
+    }
+}
+
+/// `lval` and the right operand of `node` with its operator.
+fn apply(node: &BinOp, lval: i128, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+    if let Token::And | Token::Or = node.op {
+        // The right operand only if the left one doesn't decide
+        let lhs = lval != 0;
+        let value = if lhs == (node.op == Token::Or) { lhs } else { Expr::calculate(&node.rhs, ctx)? != 0 };
+        return Ok(i128::from(value))
+    }
+    let rval = Expr::calculate(&node.rhs, ctx)?;
+    if node.op.is_comparison() {
+        return Ok(i128::from(node.op.compares(lval.partial_cmp(&rval))))
+    }
+    binary(&node.op, lval, rval, &node.span, ctx)
+}
+
+/// `lval op rval` of an arithmetic operator.
+fn binary(op: &Token, lval: i128, rval: i128, span: &Span, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+    let overflow = || EvalError::Overflow{span: span.clone()};
+    match op {
+        Token::Plus => lval.checked_add(rval).ok_or_else(overflow),
+        Token::Minus => lval.checked_sub(rval).ok_or_else(overflow),
+        Token::Times => lval.checked_mul(rval).ok_or_else(overflow),
+        Token::Division => {
+            if rval == 0 {
+                return Err(EvalError::DivisionByZero{span: span.clone()})
+            }
+            let res = lval.checked_div(rval).ok_or_else(overflow)?;
+            if lval.wrapping_rem(rval) != 0 {
+                ctx.warn(Warning::DivisionCast{lhs: lval, rhs: rval, span: span.clone()})?;
+                warn!(lhs = lval, rhs = rval; "division will cause a cast");
+            }
+            Ok(res)
+        },
+        op => Err(EvalError::InvalidOperator{op: op.clone(), span: span.clone()})
     }
 }
 
 impl Calculable for Number {
-    fn calculate(node: &Self) -> i128 {
-        node.num as i128
+    fn calculate(node: &Self, _: &mut Context<i128>) -> Result<i128, EvalError> {
+        i128::try_from(node.num).map_err(|_| EvalError::Overflow{span: node.span.clone()})
     }
 }
 
 impl Calculable for Pair {
-    fn calculate(node: &Self) -> i128 {
-        Expr::calculate(&node.expr)
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        Expr::calculate(&node.expr, ctx)
     }
 }
 
 impl Calculable for Neg {
-    fn calculate(node: &Self) -> i128 {
-        -Expr::calculate(&node.expr)
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        if let Expr::Num(num) = &*node.expr {
+            ctx.step(&node.expr)?;
+            return negative(num, &node.span, ctx)
+        }
+        negate(Expr::calculate(&node.expr, ctx)?, &node.span)
+    }
+}
+
+/// A negative literal, so `-170141183460469231731687303715884105728`
+/// is i128::MIN although its literal alone overflows.
+fn negative(num: &Number, span: &Span, _: &mut Context<i128>) -> Result<i128, EvalError> {
+    0i128.checked_sub_unsigned(num.num).ok_or_else(|| EvalError::Overflow{span: span.clone()})
+}
+
+fn negate(value: i128, span: &Span) -> Result<i128, EvalError> {
+    value.checked_neg().ok_or_else(|| EvalError::Overflow{span: span.clone()})
+}
+
+impl Calculable for Not {
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)? != 0;
+        Ok(i128::from(!value))
+    }
+}
+
+impl Calculable for Abs {
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        Expr::calculate(&node.expr, ctx)?
+            .checked_abs()
+            .ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for If {
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        let cond = Expr::calculate(&node.cond, ctx)?;
+        // Only the branch taken is calculated
+        let branch = if cond != 0 { &node.then } else { &node.otherwise };
+        Expr::calculate(branch, ctx)
+    }
+}
+
+impl Calculable for Call {
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        // The aggregates of a list, then the user functions and
+        // the registered ones
+        if let Some(value) = list::aggregate(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(function) = ctx.user_function(node)? {
+            let args = node.args.iter()
+                .map(|arg| Expr::calculate(arg, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            return ctx.call_user(&function, args, &node.span, Expr::calculate)
+        }
+        if let Some(value) = root(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(value) = solve::solve(node, ctx)? {
+            return Ok(value)
+        }
+        let f = ctx.function(node)?;
+        let args = node.args.iter()
+            .map(|arg| Expr::calculate(arg, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(f(&args))
+    }
+}
+
+/// Root of `root(n, x)`, `None` for the other calls and if the
+/// registry has a `root`.
+fn root(node: &Call, ctx: &mut Context<i128>) -> Result<Option<i128>, EvalError> {
+    let (n, x) = match root::args(node, ctx)? {
+        Some(args) => args,
+        None => return Ok(None),
+    };
+    let mismatch = |expected| EvalError::Mismatch{expected, span: node.span.clone()};
+    let n = root::degree(Some(n)).map_err(mismatch)?;
+    let (value, exact) = root::integer(n, x, ctx.rounding).map_err(mismatch)?;
+    if !exact {
+        ctx.warn(Warning::InexactRoot{degree: n, radicand: x.to_string(), span: node.span.clone()})?;
+        warn!(degree = n, radicand = x; "root will cause a rounding");
+    }
+    Ok(Some(value))
+}
+
+impl Calculable for Var {
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        if let Some(value) = ctx.var(&node.name) {
+            return Ok(value)
+        }
+        ctx.cell(node, Expr::calculate)?
+            .ok_or_else(|| ctx.unknown(node))
+    }
+}
+
+impl Calculable for Assign {
+    fn calculate(node: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?;
+        if let Some(env) = ctx.env.as_mut() {
+            env.set(&node.name, value);
+        }
+        Ok(value)
     }
 }
 
 impl Calculable for AST {
-    fn calculate(ast: &Self) -> i128 {
-        Expr::calculate(&ast.root)
+    fn calculate(ast: &Self, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        Expr::calculate(&ast.root, ctx)
+    }
+}
+
+impl list::Item for i128 {
+    fn calculate(node: &Expr, ctx: &mut Context<i128>) -> Result<i128, EvalError> {
+        Expr::calculate(node, ctx)
     }
+
+    fn to_integer(&self) -> Option<i128> {
+        Some(*self)
+    }
+
+    fn from_integer(n: i128) -> i128 {
+        n
+    }
+}
+
+/// Arithmetic of the streaming evaluator.
+pub(crate) const ARITHMETIC: Arithmetic<i128> = Arithmetic{number: Number::calculate, negative, negate, binary};
+
+/// Calculate the expression's AST to `i128`
+pub fn calculate(ast: AST) -> Result<i128, Error> {
+    calculate_with(ast, &FunctionRegistry::new())
 }
 
-/// Calculate the expression's AST to `i28`
-pub fn calculate(ast: AST) -> i128 {
-    AST::calculate(&ast)
+/// Calculate the expression's AST to `i128`, resolving calls
+/// through the registry
+pub fn calculate_with(ast: AST, functions: &FunctionRegistry<i128>) -> Result<i128, Error> {
+    calculate_in(ast, &mut Context::new(functions))
+}
+
+/// Calculate the expression's AST to `i128` in the context
+pub fn calculate_in(ast: AST, ctx: &mut Context<i128>) -> Result<i128, Error> {
+    Ok(AST::calculate(&ast, ctx)?)
 }
 
 
@@ -79,73 +259,204 @@ mod tests {
     use crate::generator::calculator;
     use crate::parser::ast::*;
     use crate::lexer::Token;
+    use crate::error::{Error, EvalError};
 
     #[test]
     fn test_num() {
         let res = calculator::calculate(AST{root: Number::new(3)});
-        assert_eq!(res, 3);
+        assert_eq!(res, Ok(3));
     }
 
     #[test]
     fn test_add() {
         let res = calculator::calculate(AST{root: BinOp::new(Number::new(1), Number::new(2), Token::Plus)});
-        assert_eq!(res, 3);
+        assert_eq!(res, Ok(3));
     }
 
     #[test]
     fn test_minus() {
         let res = calculator::calculate(AST{root: BinOp::new(Number::new(1), Number::new(2), Token::Minus)});
-        assert_eq!(res, -1);
+        assert_eq!(res, Ok(-1));
     }
 
     #[test]
     fn test_times() {
         let res = calculator::calculate(AST{root: BinOp::new(Number::new(1), Number::new(2), Token::Times)});
-        assert_eq!(res, 2);
+        assert_eq!(res, Ok(2));
     }
 
     #[test]
     fn test_division() {
         let res = calculator::calculate(AST{root: BinOp::new(Number::new(4), Number::new(2), Token::Division)});
-        assert_eq!(res, 2);
+        assert_eq!(res, Ok(2));
     }
 
     #[test]
     fn test_division_cast() {
         let res = calculator::calculate(AST{root: BinOp::new(Number::new(3), Number::new(2), Token::Division)});
-        assert_eq!(res, 1);
+        assert_eq!(res, Ok(1));
     }
 
     #[test]
-    #[should_panic]
     fn test_division_zero() {
-        calculator::calculate(AST{root: BinOp::new(Number::new(3), Number::new(0), Token::Division)});
+        let res = calculator::calculate(AST{root: BinOp::new(Number::new(3), Number::new(0), Token::Division)});
+        assert!(matches!(res, Err(Error::Eval(EvalError::DivisionByZero{..}))));
+    }
+
+    #[test]
+    fn test_overflow() {
+        // u64::MAX * u64::MAX
+        let res = calculator::calculate(AST{root: BinOp::new(Number::new(u64::MAX.into()), Number::new(u64::MAX.into()), Token::Times)});
+        assert!(matches!(res, Err(Error::Eval(EvalError::Overflow{..}))));
+        // -2^63 * 2^32 * 2^32 / -1
+        let min = BinOp::new(BinOp::new(Neg::new(Number::new(1 << 63)), Number::new(1 << 32), Token::Times), Number::new(1 << 32), Token::Times);
+        assert_eq!(calculator::calculate(AST{root: min}), Ok(i128::MIN));
+        let min = BinOp::new(BinOp::new(Neg::new(Number::new(1 << 63)), Number::new(1 << 32), Token::Times), Number::new(1 << 32), Token::Times);
+        let res = calculator::calculate(AST{root: BinOp::new(min, Neg::new(Number::new(1)), Token::Division)});
+        assert!(matches!(res, Err(Error::Eval(EvalError::Overflow{..}))));
+        // 2^127
+        let res = calculator::calculate(AST{root: Number::new(1 << 127)});
+        assert!(matches!(res, Err(Error::Eval(EvalError::Overflow{..}))));
+        // -2^127 is a negative literal, --2^127 still overflows
+        let res = calculator::calculate(AST{root: Neg::new(Number::new(1 << 127))});
+        assert_eq!(res, Ok(i128::MIN));
+        let res = calculator::calculate(AST{root: Neg::new(Neg::new(Number::new(1 << 127)))});
+        assert!(matches!(res, Err(Error::Eval(EvalError::Overflow{..}))));
+        let res = calculator::calculate(AST{root: Neg::new(Number::new((1 << 127) + 1))});
+        assert!(matches!(res, Err(Error::Eval(EvalError::Overflow{..}))));
+    }
+
+    #[test]
+    fn test_warnings() {
+        use crate::functions::FunctionRegistry;
+        use crate::generator::Context;
+        use crate::error::Warning;
+
+        let functions = FunctionRegistry::new();
+        let mut ctx = Context::new(&functions);
+        // 7/2 + 4/2
+        let res = calculator::calculate_in(AST{root: BinOp::new(
+            BinOp::new(Number::new(7), Number::new(2), Token::Division),
+            BinOp::new(Number::new(4), Number::new(2), Token::Division),
+            Token::Plus
+        )}, &mut ctx);
+        assert_eq!(res, Ok(5));
+        assert_eq!(ctx.warnings(), [Warning::DivisionCast{lhs: 7, rhs: 2, span: 0..0}]);
+    }
+
+    #[test]
+    fn test_root() {
+        use crate::error::Warning;
+        use crate::{Calculator, FunctionRegistry, Rounding};
+
+        let calc = Calculator::<i128>::new();
+        assert_eq!(calc.eval("root(3, -27) + √16 * 2√9"), Ok(9));
+        let ast = calc.parse("√10").unwrap();
+        let mut ctx = calc.context();
+        assert_eq!(calculator::calculate_in(ast, &mut ctx), Ok(3));
+        assert_eq!(ctx.warnings(), [Warning::InexactRoot{degree: 2, radicand: "10".to_string(), span: 0..5}]);
+        assert_eq!(ctx.warnings()[0].to_string(), "Root will cause a rounding: root(2, 10)");
+        assert_eq!(Calculator::<i128>::new().rounding(Rounding::Ceiling).eval("√10 + 3√-10"), Ok(2));
+        let mismatch = |expected, span| Err(Error::Eval(EvalError::Mismatch{expected, span}));
+        assert_eq!(calc.eval("√-4"), mismatch("an odd degree for a negative radicand", 0..5));
+        assert_eq!(calc.eval("0√4"), mismatch("a positive integer degree", 0..5));
+        assert!(matches!(calc.eval("root(4)"), Err(Error::Eval(EvalError::WrongArity{expected: 2, found: 1, ..}))));
+
+        // The registered functions first
+        let mut functions = FunctionRegistry::new();
+        functions.register("root", 2, |args: &[i128]| args[0] + args[1]);
+        assert_eq!(Calculator::new().functions(functions).eval("√16"), Ok(18));
+    }
+
+    #[test]
+    fn test_comparison() {
+        use crate::functions::FunctionRegistry;
+        use crate::generator::Context;
+        use crate::{Calculator, Limits};
+
+        let calc = Calculator::<i128>::new();
+        assert_eq!(calc.eval("1 < 2 < 3"), Ok(1));
+        // Not `(3 > 2) > 1`, which is `1 > 1`
+        assert_eq!(calc.eval("3 > 2 > 1"), Ok(1));
+        assert_eq!(calc.eval("(3 > 2) > 1"), Ok(0));
+        assert_eq!(calc.eval("1 + 1 == 2 != 3 && 2 >= 2 <= 2"), Ok(1));
+        assert_eq!(calc.eval("let x = 10 in 1 < x < 10"), Ok(0));
+        assert_eq!(calc.eval("0 < 1 < 1/0"), Err(Error::Eval(EvalError::DivisionByZero{span: 8..11})));
+
+        // The middle operand is calculated once, for both comparisons
+        let functions = FunctionRegistry::new();
+        let mut ctx = Context::new(&functions);
+        assert_eq!(calculator::calculate_in(calc.parse("0 < 7/2 < 10").unwrap(), &mut ctx), Ok(1));
+        assert_eq!(ctx.warnings().len(), 1);
+        let limits = Limits{operations: 8, ..Limits::default()};
+        assert_eq!(Calculator::<i128>::new().limits(limits).eval("0 < 7/2 < 10"), Ok(1));
+        assert_eq!(calc.eval("0 < 7/2 < 3 < 1/0"), Ok(0));
+    }
+
+    #[test]
+    fn test_var() {
+        use crate::environment::Environment;
+        use crate::functions::FunctionRegistry;
+        use crate::generator::Context;
+        use crate::error::{Error, EvalError};
+
+        let functions = FunctionRegistry::new();
+        let mut env = Environment::new();
+        // x = 1 + 2
+        let ast = AST{root: Assign::new("x", BinOp::new(Number::new(1), Number::new(2), Token::Plus))};
+        let res = calculator::calculate_in(ast, &mut Context::new(&functions).env(&mut env));
+        assert_eq!(res, Ok(3));
+        assert_eq!(env.get("x"), Some(&3));
+        // x
+        let res = calculator::calculate_in(AST{root: Var::new("x")}, &mut Context::new(&functions).env(&mut env));
+        assert_eq!(res, Ok(3));
+        // x without environment
+        let res = calculator::calculate(AST{root: Var::new("x")});
+        assert_eq!(res, Err(Error::Eval(EvalError::UnknownVariable{name: "x".to_string(), suggestion: None, span: 0..0})));
+    }
+
+    #[test]
+    fn test_call() {
+        use crate::functions::FunctionRegistry;
+        use crate::error::{Error, EvalError};
+
+        let mut functions = FunctionRegistry::new();
+        functions.register("max", 2, |args: &[i128]| args[0].max(args[1]));
+        // max(2, 5)
+        let res = calculator::calculate_with(AST{root: Call::new("max", vec![Number::new(2), Number::new(5)])}, &functions);
+        assert_eq!(res, Ok(5));
+        // max(2)
+        let res = calculator::calculate_with(AST{root: Call::new("max", vec![Number::new(2)])}, &functions);
+        assert_eq!(res, Err(Error::Eval(EvalError::WrongArity{name: "max".to_string(), expected: 2, found: 1, span: 0..0})));
+        // min(2, 5)
+        let res = calculator::calculate(AST{root: Call::new("min", vec![Number::new(2), Number::new(5)])});
+        assert_eq!(res, Err(Error::Eval(EvalError::UnknownFunction{name: "min".to_string(), suggestion: None, span: 0..0})));
     }
 
     #[test]
     fn test_neg() {
         // -3
         let res = calculator::calculate(AST{root: Neg::new(Number::new(3))});
-        assert_eq!(res, -3);
+        assert_eq!(res, Ok(-3));
         // --3
         let res = calculator::calculate(AST{root: Neg::new(Neg::new(Number::new(3)))});
-        assert_eq!(res, 3);
+        assert_eq!(res, Ok(3));
         // --3---3
         let res = calculator::calculate(AST{root: BinOp::new(
             Neg::new(Neg::new(Number::new(3))),
             Neg::new(Neg::new(Number::new(3))),
             Token::Minus
         )});
-        assert_eq!(res, 0);
+        assert_eq!(res, Ok(0));
     }
 
     #[test]
     fn test_pair() {
         // (3)
         let res = calculator::calculate(AST{root: Pair::new(Number::new(3))});
-        assert_eq!(res, 3);
+        assert_eq!(res, Ok(3));
         // ((3))
         let res = calculator::calculate(AST{root: Pair::new(Pair::new(Number::new(3)))});
-        assert_eq!(res, 3);
+        assert_eq!(res, Ok(3));
     }
 }
@@ -0,0 +1,300 @@
+//! Convert the expression AST to `BigInt`, only with the `bigint`
+//! feature
+//!
+//! Results never overflow, division truncates like `i128` and a
+//! warning will raise while division cast happened with operands
+//! that fit in `i128`
+//!
+//! A error will raise while division by zero
+use std::convert::TryFrom;
+
+use num_bigint::{BigInt, Sign};
+
+use crate::lexer::Token;
+use crate::parser::ast::*;
+use crate::error::{Error, EvalError, Warning};
+use crate::functions::FunctionRegistry;
+use crate::generator::{is_chain, list, solve, Context};
+use crate::root;
+use crate::text;
+
+trait Calculable {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError>;
+}
+
+impl Calculable for Expr {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        ctx.step(node)?;
+        match node {
+            Expr::Pair(pair) => Pair::calculate(pair, ctx),
+            Expr::BinOp(expr) => BinOp::calculate(expr, ctx),
+            Expr::Neg(neg) => Neg::calculate(neg, ctx),
+            Expr::Not(not) => Not::calculate(not, ctx),
+            Expr::Abs(abs) => Abs::calculate(abs, ctx),
+            Expr::If(node) => If::calculate(node, ctx),
+            Expr::Let(node) => list::let_in(node, ctx),
+            Expr::List(_) | Expr::Range(_) => Err(list::not_a_number(node)),
+            Expr::Index(node) => list::index(node, ctx),
+            Expr::Call(call) => Call::calculate(call, ctx),
+            Expr::Var(var) => Var::calculate(var, ctx),
+            Expr::Assign(assign) => Assign::calculate(assign, ctx),
+            Expr::Num(num) => Number::calculate(num, ctx),
+            Expr::Duration(duration) => Number::calculate(&duration.to_number(), ctx),
+            Expr::Date(date) => Ok(BigInt::from(date.days)),
+            Expr::Bool(bool) => Ok(BigInt::from(u8::from(bool.value))),
+            Expr::Str(_) => Err(text::not_a_number(node.span())),
+        }
+    }
+}
+
+impl Calculable for BinOp {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        if is_chain(node) {
+            let value = ctx.compare_chain(node, Expr::calculate, |node, lhs, rhs| Ok(node.op.compares(lhs.partial_cmp(rhs))))?;
+            return Ok(BigInt::from(u8::from(value)))
+        }
+        let (lhs, chain) = ctx.chain(node)?;
+        let mut lval = Expr::calculate(lhs, ctx)?;
+        for node in chain {
+            lval = apply(node, lval, ctx)?;
+        }
+        Ok(lval)
+    }
+}
+
+/// `lval` and the right operand of `node` with its operator.
+fn apply(node: &BinOp, lval: BigInt, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+    if let Token::And | Token::Or = node.op {
+        // The right operand only if the left one doesn't decide
+        let lhs = lval != BigInt::from(0);
+        let value = if lhs == (node.op == Token::Or) { lhs } else { Expr::calculate(&node.rhs, ctx)? != BigInt::from(0) };
+        return Ok(BigInt::from(u8::from(value)))
+    }
+    let rval = Expr::calculate(&node.rhs, ctx)?;
+    if node.op.is_comparison() {
+        return Ok(BigInt::from(u8::from(node.op.compares(lval.partial_cmp(&rval)))))
+    }
+    match &node.op {
+        Token::Plus => Ok(lval + rval),
+        Token::Minus => Ok(lval - rval),
+        Token::Times => Ok(lval * rval),
+        Token::Division => {
+            if rval == BigInt::from(0) {
+                return Err(EvalError::DivisionByZero{span: node.span.clone()})
+            }
+            let res = &lval / &rval;
+            if &res * &rval != lval {
+                if let (Ok(lhs), Ok(rhs)) = (i128::try_from(&lval), i128::try_from(&rval)) {
+                    ctx.warn(Warning::DivisionCast{lhs, rhs, span: node.span.clone()})?;
+                    warn!(lhs = lhs, rhs = rhs; "division will cause a cast");
+                }
+            }
+            Ok(res)
+        },
+        op => Err(EvalError::InvalidOperator{op: op.clone(), span: node.span.clone()})
+    }
+}
+
+impl Calculable for Number {
+    fn calculate(node: &Self, _: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        Ok(BigInt::from(node.num))
+    }
+}
+
+impl Calculable for Pair {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        Expr::calculate(&node.expr, ctx)
+    }
+}
+
+impl Calculable for Neg {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        Ok(-Expr::calculate(&node.expr, ctx)?)
+    }
+}
+
+impl Calculable for Not {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)? != BigInt::from(0);
+        Ok(BigInt::from(u8::from(!value)))
+    }
+}
+
+impl Calculable for Abs {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?;
+        Ok(if value < BigInt::from(0) { -value } else { value })
+    }
+}
+
+impl Calculable for If {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        let cond = Expr::calculate(&node.cond, ctx)?;
+        // Only the branch taken is calculated
+        let branch = if cond != BigInt::from(0) { &node.then } else { &node.otherwise };
+        Expr::calculate(branch, ctx)
+    }
+}
+
+impl Calculable for Call {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        // The aggregates of a list, then the user functions and
+        // the registered ones
+        if let Some(value) = list::aggregate(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(function) = ctx.user_function(node)? {
+            let args = node.args.iter()
+                .map(|arg| Expr::calculate(arg, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            return ctx.call_user(&function, args, &node.span, Expr::calculate)
+        }
+        if let Some(value) = root(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(value) = solve::solve(node, ctx)? {
+            return Ok(value)
+        }
+        let f = ctx.function(node)?;
+        let args = node.args.iter()
+            .map(|arg| Expr::calculate(arg, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(f(&args))
+    }
+}
+
+/// Root of `root(n, x)`, `None` for the other calls and if the
+/// registry has a `root`.
+fn root(node: &Call, ctx: &mut Context<BigInt>) -> Result<Option<BigInt>, EvalError> {
+    let (n, x) = match root::args(node, ctx)? {
+        Some(args) => args,
+        None => return Ok(None),
+    };
+    let mismatch = |expected| EvalError::Mismatch{expected, span: node.span.clone()};
+    let n = root::degree(i128::try_from(&n).ok()).map_err(mismatch)?;
+    let negative = x.sign() == Sign::Minus;
+    if negative && n.is_multiple_of(2) {
+        return Err(mismatch(root::NEGATIVE))
+    }
+    let magnitude = x.magnitude();
+    let mut value = magnitude.nth_root(n);
+    if value.pow(n) != *magnitude {
+        // Past the halfway if 2^n x is past (2 value + 1)^n
+        let up = ctx.rounding.away(negative, || (&value * 2u8 + 1u8).pow(n) < magnitude << n);
+        if up {
+            value += 1u8;
+        }
+        let radicand = x.to_string();
+        warn!(degree = n, radicand = radicand.as_str(); "root will cause a rounding");
+        ctx.warn(Warning::InexactRoot{degree: n, radicand, span: node.span.clone()})?;
+    }
+    let value = BigInt::from(value);
+    Ok(Some(if negative { -value } else { value }))
+}
+
+impl Calculable for Var {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        if let Some(value) = ctx.var(&node.name) {
+            return Ok(value)
+        }
+        ctx.cell(node, Expr::calculate)?
+            .ok_or_else(|| ctx.unknown(node))
+    }
+}
+
+impl Calculable for Assign {
+    fn calculate(node: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?;
+        if let Some(env) = ctx.env.as_mut() {
+            env.set(&node.name, value.clone());
+        }
+        Ok(value)
+    }
+}
+
+impl Calculable for AST {
+    fn calculate(ast: &Self, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        Expr::calculate(&ast.root, ctx)
+    }
+}
+
+impl list::Item for BigInt {
+    fn calculate(node: &Expr, ctx: &mut Context<BigInt>) -> Result<BigInt, EvalError> {
+        Expr::calculate(node, ctx)
+    }
+
+    fn to_integer(&self) -> Option<i128> {
+        i128::try_from(self).ok()
+    }
+
+    fn from_integer(n: i128) -> BigInt {
+        BigInt::from(n)
+    }
+}
+
+/// Calculate the expression's AST to `BigInt`
+pub fn calculate(ast: AST) -> Result<BigInt, Error> {
+    calculate_with(ast, &FunctionRegistry::new())
+}
+
+/// Calculate the expression's AST to `BigInt`, resolving calls
+/// through the registry
+pub fn calculate_with(ast: AST, functions: &FunctionRegistry<BigInt>) -> Result<BigInt, Error> {
+    calculate_in(ast, &mut Context::new(functions))
+}
+
+/// Calculate the expression's AST to `BigInt` in the context
+pub fn calculate_in(ast: AST, ctx: &mut Context<BigInt>) -> Result<BigInt, Error> {
+    Ok(AST::calculate(&ast, ctx)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+
+    use crate::generator::calculator_big;
+    use crate::parser::ast::*;
+    use crate::lexer::Token;
+    use crate::error::{Error, EvalError};
+
+    #[test]
+    fn test_no_overflow() {
+        // u128::MAX * u128::MAX
+        let res = calculator_big::calculate(AST{root: BinOp::new(Number::new(u128::MAX), Number::new(u128::MAX), Token::Times)});
+        assert_eq!(res, Ok(BigInt::from(u128::MAX) * BigInt::from(u128::MAX)));
+        // -(2^127)
+        let res = calculator_big::calculate(AST{root: Neg::new(Number::new(1 << 127))});
+        assert_eq!(res, Ok(BigInt::from(i128::MIN)));
+    }
+
+    #[test]
+    fn test_division() {
+        use crate::functions::FunctionRegistry;
+        use crate::generator::Context;
+        use crate::error::Warning;
+
+        let functions = FunctionRegistry::new();
+        let mut ctx = Context::new(&functions);
+        // -7/2
+        let res = calculator_big::calculate_in(AST{root: BinOp::new(Neg::new(Number::new(7)), Number::new(2), Token::Division)}, &mut ctx);
+        assert_eq!(res, Ok(BigInt::from(-3)));
+        assert_eq!(ctx.warnings(), [Warning::DivisionCast{lhs: -7, rhs: 2, span: 0..0}]);
+        // 3/0
+        let res = calculator_big::calculate(AST{root: BinOp::new(Number::new(3), Number::new(0), Token::Division)});
+        assert!(matches!(res, Err(Error::Eval(EvalError::DivisionByZero{..}))));
+    }
+
+    #[test]
+    fn test_root() {
+        use crate::{Calculator, Rounding};
+
+        let calc = Calculator::<BigInt>::new();
+        let big = BigInt::from(u128::MAX) + 1u8;
+        assert_eq!(calc.eval("let x = 18446744073709551616 in √(x * x * x * x)"), Ok(big.clone()));
+        assert_eq!(calc.eval("3√-10"), Ok(BigInt::from(-3)));
+        let calc = calc.rounding(Rounding::Nearest);
+        assert_eq!(calc.eval("let x = 18446744073709551616 in √(x * x * x * x + x)"), Ok(big));
+        assert_eq!(calc.eval("√13 + 3√-10"), Ok(BigInt::from(2)));
+        assert!(matches!(calc.eval("√-1"), Err(Error::Eval(EvalError::Mismatch{..}))));
+    }
+}
@@ -0,0 +1,279 @@
+//! Convert the expression AST to `Decimal`, only with the `decimal`
+//! feature
+//!
+//! Numbers have up to 28 significant digits, so `0.1*3` is exactly
+//! `0.3`. Division rounds to 28 digits, such as `1/3`
+//!
+//! A error will raise while division by zero or overflow past
+//! about 7.9e28
+use std::convert::TryFrom;
+
+use rust_decimal::Decimal;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+
+use crate::lexer::Token;
+use crate::parser::ast::*;
+use crate::error::{Error, EvalError};
+use crate::functions::FunctionRegistry;
+use crate::generator::{is_chain, list, solve, Context};
+use crate::root;
+use crate::text;
+
+trait Calculable {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError>;
+}
+
+impl Calculable for Expr {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        ctx.step(node)?;
+        match node {
+            Expr::Pair(pair) => Pair::calculate(pair, ctx),
+            Expr::BinOp(expr) => BinOp::calculate(expr, ctx),
+            Expr::Neg(neg) => Neg::calculate(neg, ctx),
+            Expr::Not(not) => Not::calculate(not, ctx),
+            Expr::Abs(abs) => Abs::calculate(abs, ctx),
+            Expr::If(node) => If::calculate(node, ctx),
+            Expr::Let(node) => list::let_in(node, ctx),
+            Expr::List(_) | Expr::Range(_) => Err(list::not_a_number(node)),
+            Expr::Index(node) => list::index(node, ctx),
+            Expr::Call(call) => Call::calculate(call, ctx),
+            Expr::Var(var) => Var::calculate(var, ctx),
+            Expr::Assign(assign) => Assign::calculate(assign, ctx),
+            Expr::Num(num) => Number::calculate(num, ctx),
+            Expr::Duration(duration) => Number::calculate(&duration.to_number(), ctx),
+            Expr::Date(date) => Ok(Decimal::from(date.days)),
+            Expr::Bool(bool) => Ok(Decimal::from(u8::from(bool.value))),
+            Expr::Str(_) => Err(text::not_a_number(node.span())),
+        }
+    }
+}
+
+impl Calculable for BinOp {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        if is_chain(node) {
+            let value = ctx.compare_chain(node, Expr::calculate, |node, lhs, rhs| Ok(node.op.compares(lhs.partial_cmp(rhs))))?;
+            return Ok(Decimal::from(u8::from(value)))
+        }
+        let (lhs, chain) = ctx.chain(node)?;
+        let mut lval = Expr::calculate(lhs, ctx)?;
+        for node in chain {
+            lval = apply(node, lval, ctx)?;
+        }
+        Ok(lval)
+    }
+}
+
+/// `lval` and the right operand of `node` with its operator.
+fn apply(node: &BinOp, lval: Decimal, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+    if let Token::And | Token::Or = node.op {
+        // The right operand only if the left one doesn't decide
+        let lhs = !lval.is_zero();
+        let value = if lhs == (node.op == Token::Or) { lhs } else { !Expr::calculate(&node.rhs, ctx)?.is_zero() };
+        return Ok(Decimal::from(u8::from(value)))
+    }
+    let rval = Expr::calculate(&node.rhs, ctx)?;
+    if node.op.is_comparison() {
+        return Ok(Decimal::from(u8::from(node.op.compares(lval.partial_cmp(&rval)))))
+    }
+    let overflow = || EvalError::Overflow{span: node.span.clone()};
+    match &node.op {
+        Token::Plus => lval.checked_add(rval).ok_or_else(overflow),
+        Token::Minus => lval.checked_sub(rval).ok_or_else(overflow),
+        Token::Times => lval.checked_mul(rval).ok_or_else(overflow),
+        Token::Division => {
+            if rval.is_zero() {
+                return Err(EvalError::DivisionByZero{span: node.span.clone()})
+            }
+            lval.checked_div(rval).map(|res| res.normalize()).ok_or_else(overflow)
+        },
+        op => Err(EvalError::InvalidOperator{op: op.clone(), span: node.span.clone()})
+    }
+}
+
+impl Calculable for Number {
+    fn calculate(node: &Self, _: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        i128::try_from(node.num).ok()
+            .and_then(|num| Decimal::try_from_i128_with_scale(num, 0).ok())
+            .ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for Pair {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        Expr::calculate(&node.expr, ctx)
+    }
+}
+
+impl Calculable for Neg {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        Ok(-Expr::calculate(&node.expr, ctx)?)
+    }
+}
+
+impl Calculable for Not {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        let value = !Expr::calculate(&node.expr, ctx)?.is_zero();
+        Ok(Decimal::from(u8::from(!value)))
+    }
+}
+
+impl Calculable for Abs {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        Ok(Expr::calculate(&node.expr, ctx)?.abs())
+    }
+}
+
+impl Calculable for If {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        let cond = Expr::calculate(&node.cond, ctx)?;
+        // Only the branch taken is calculated
+        let branch = if !cond.is_zero() { &node.then } else { &node.otherwise };
+        Expr::calculate(branch, ctx)
+    }
+}
+
+impl Calculable for Call {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        // The aggregates of a list, then the user functions and
+        // the registered ones
+        if let Some(value) = list::aggregate(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(function) = ctx.user_function(node)? {
+            let args = node.args.iter()
+                .map(|arg| Expr::calculate(arg, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            return ctx.call_user(&function, args, &node.span, Expr::calculate)
+        }
+        if let Some(value) = root(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(value) = solve::solve(node, ctx)? {
+            return Ok(value)
+        }
+        let f = ctx.function(node)?;
+        let args = node.args.iter()
+            .map(|arg| Expr::calculate(arg, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(f(&args))
+    }
+}
+
+/// Root of `root(n, x)`, `None` for the other calls and if the
+/// registry has a `root`.
+fn root(node: &Call, ctx: &mut Context<Decimal>) -> Result<Option<Decimal>, EvalError> {
+    let (n, x) = match root::args(node, ctx)? {
+        Some(args) => args,
+        None => return Ok(None),
+    };
+    let mismatch = |expected| EvalError::Mismatch{expected, span: node.span.clone()};
+    let n = root::degree(list::Item::to_integer(&n)).map_err(mismatch)?;
+    if x < Decimal::ZERO && n.is_multiple_of(2) {
+        return Err(mismatch(root::NEGATIVE))
+    }
+    let value = x.to_f64().map(|x| root::float(f64::from(n), x)).and_then(Decimal::from_f64);
+    value.map(Some).ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+}
+
+impl Calculable for Var {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        if let Some(value) = ctx.var(&node.name) {
+            return Ok(value)
+        }
+        ctx.cell(node, Expr::calculate)?
+            .ok_or_else(|| ctx.unknown(node))
+    }
+}
+
+impl Calculable for Assign {
+    fn calculate(node: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?;
+        if let Some(env) = ctx.env.as_mut() {
+            env.set(&node.name, value);
+        }
+        Ok(value)
+    }
+}
+
+impl Calculable for AST {
+    fn calculate(ast: &Self, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        Expr::calculate(&ast.root, ctx)
+    }
+}
+
+impl list::Item for Decimal {
+    fn calculate(node: &Expr, ctx: &mut Context<Decimal>) -> Result<Decimal, EvalError> {
+        Expr::calculate(node, ctx)
+    }
+
+    fn to_integer(&self) -> Option<i128> {
+        if self.fract().is_zero() { i128::try_from(*self).ok() } else { None }
+    }
+
+    fn from_integer(n: i128) -> Decimal {
+        Decimal::from(n)
+    }
+}
+
+/// Calculate the expression's AST to `Decimal`
+pub fn calculate(ast: AST) -> Result<Decimal, Error> {
+    calculate_with(ast, &FunctionRegistry::new())
+}
+
+/// Calculate the expression's AST to `Decimal`, resolving calls
+/// through the registry
+pub fn calculate_with(ast: AST, functions: &FunctionRegistry<Decimal>) -> Result<Decimal, Error> {
+    calculate_in(ast, &mut Context::new(functions))
+}
+
+/// Calculate the expression's AST to `Decimal` in the context
+pub fn calculate_in(ast: AST, ctx: &mut Context<Decimal>) -> Result<Decimal, Error> {
+    Ok(AST::calculate(&ast, ctx)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::generator::calculator_d;
+    use crate::parser::ast::*;
+    use crate::lexer::Token;
+    use crate::error::{Error, EvalError};
+
+    #[test]
+    fn test_division() {
+        // 7/2
+        let res = calculator_d::calculate(AST{root: BinOp::new(Number::new(7), Number::new(2), Token::Division)});
+        assert_eq!(res, Ok(Decimal::new(35, 1)));
+        // 1/10*3
+        let tenth = BinOp::new(Number::new(1), Number::new(10), Token::Division);
+        let res = calculator_d::calculate(AST{root: BinOp::new(tenth, Number::new(3), Token::Times)});
+        assert_eq!(res.unwrap().to_string(), "0.3");
+        // 1/3
+        let res = calculator_d::calculate(AST{root: BinOp::new(Number::new(1), Number::new(3), Token::Division)});
+        assert_eq!(res.unwrap().to_string(), "0.3333333333333333333333333333");
+        // 3/0
+        let res = calculator_d::calculate(AST{root: BinOp::new(Number::new(3), Number::new(0), Token::Division)});
+        assert!(matches!(res, Err(Error::Eval(EvalError::DivisionByZero{..}))));
+    }
+
+    #[test]
+    fn test_overflow() {
+        // u64::MAX * u64::MAX
+        let res = calculator_d::calculate(AST{root: BinOp::new(Number::new(u64::MAX.into()), Number::new(u64::MAX.into()), Token::Times)});
+        assert!(matches!(res, Err(Error::Eval(EvalError::Overflow{..}))));
+        // 2^100 is past 96 bits
+        let res = calculator_d::calculate(AST{root: Number::new(1 << 100)});
+        assert!(matches!(res, Err(Error::Eval(EvalError::Overflow{..}))));
+    }
+
+    #[test]
+    fn test_root() {
+        use crate::Calculator;
+
+        let calc = Calculator::<Decimal>::new();
+        assert_eq!(calc.eval("√(1/4) + 3√-27"), Ok(Decimal::new(-25, 1)));
+        assert_eq!(calc.eval("√2").unwrap().to_string(), "1.414213562373095");
+        assert!(matches!(calc.eval("√-2"), Err(Error::Eval(EvalError::Mismatch{..}))));
+    }
+}
@@ -1,67 +1,278 @@
 //! Convert the expression AST to `f64`
-use crate::lexer::Token;
+//!
+//! A warning will raise while an integer past 2^53, a literal or
+//! the result of `+` `-` `*` on integers, is rounded
+//!
+//! `sin`, `cos`, `tan`, their inverses, `deg` and `rad` are
+//! calculated in the unit of the context, see
+//! [`AngleUnit`](crate::AngleUnit)
+use std::convert::TryFrom;
+
+use crate::angle;
+use crate::root;
+use crate::text;
+use crate::lexer::{Span, Token};
 use crate::parser::ast::*;
+use crate::error::{Error, EvalError, Warning};
+use crate::functions::FunctionRegistry;
+use crate::generator::{is_chain, list, solve, Arithmetic, Context};
 
 trait Calculable {
-    fn calculate(node: &Self) -> f64;
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError>;
 }
 
 impl Calculable for Expr {
-    fn calculate(node: &Self) -> f64 {
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        ctx.step(node)?;
         match node {
-            Expr::Pair(pair) => Pair::calculate(pair),
-            Expr::BinOp(expr) => BinOp::calculate(expr),
-            Expr::Neg(neg) => Neg::calculate(neg),
-            Expr::Num(num) => Number::calculate(num),
+            Expr::Pair(pair) => Pair::calculate(pair, ctx),
+            Expr::BinOp(expr) => BinOp::calculate(expr, ctx),
+            Expr::Neg(neg) => Neg::calculate(neg, ctx),
+            Expr::Not(not) => Not::calculate(not, ctx),
+            Expr::Abs(abs) => Abs::calculate(abs, ctx),
+            Expr::If(node) => If::calculate(node, ctx),
+            Expr::Let(node) => list::let_in(node, ctx),
+            Expr::List(_) | Expr::Range(_) => Err(list::not_a_number(node)),
+            Expr::Index(node) => list::index(node, ctx),
+            Expr::Call(call) => Call::calculate(call, ctx),
+            Expr::Var(var) => Var::calculate(var, ctx),
+            Expr::Assign(assign) => Assign::calculate(assign, ctx),
+            Expr::Num(num) => Number::calculate(num, ctx),
+            Expr::Duration(duration) => Number::calculate(&duration.to_number(), ctx),
+            Expr::Date(date) => Ok(date.days as f64),
+            Expr::Bool(bool) => Ok(f64::from(u8::from(bool.value))),
+            Expr::Str(_) => Err(text::not_a_number(node.span())),
         }
     }
 }
 
 impl Calculable for BinOp {
-    fn calculate(node: &Self) -> f64 {
-        let lval = Expr::calculate(&node.lhs);
-        let rval = Expr::calculate(&node.rhs);
-        match node.op {
-            Token::Plus => lval + rval,
-            Token::Minus => lval - rval,
-            Token::Times => lval * rval,
-            Token::Division => lval / rval,
-            _ => panic!("Unknown operator")
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        if is_chain(node) {
+            let value = ctx.compare_chain(node, Expr::calculate, |node, lhs, rhs| Ok(node.op.compares(lhs.partial_cmp(rhs))))?;
+            return Ok(f64::from(u8::from(value)))
+        }
+        let (lhs, chain) = ctx.chain(node)?;
+        let mut lval = Expr::calculate(lhs, ctx)?;
+        for node in chain {
+            lval = apply(node, lval, ctx)?;
         }
+        Ok(lval)
+    }
+}
+
+/// `lval` and the right operand of `node` with its operator.
+fn apply(node: &BinOp, lval: f64, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+    if let Token::And | Token::Or = node.op {
+        // The right operand only if the left one doesn't decide
+        let lhs = lval != 0.0;
+        let value = if lhs == (node.op == Token::Or) { lhs } else { Expr::calculate(&node.rhs, ctx)? != 0.0 };
+        return Ok(f64::from(u8::from(value)))
+    }
+    let rval = Expr::calculate(&node.rhs, ctx)?;
+    if node.op.is_comparison() {
+        return Ok(f64::from(u8::from(node.op.compares(lval.partial_cmp(&rval)))))
     }
+    binary(&node.op, lval, rval, &node.span, ctx)
+}
+
+/// `lval op rval` of an arithmetic operator.
+fn binary(op: &Token, lval: f64, rval: f64, span: &Span, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+    let (res, exact) = match op {
+        Token::Plus => (lval + rval, integers(lval, rval).and_then(|(lhs, rhs)| lhs.checked_add(rhs))),
+        Token::Minus => (lval - rval, integers(lval, rval).and_then(|(lhs, rhs)| lhs.checked_sub(rhs))),
+        Token::Times => (lval * rval, integers(lval, rval).and_then(|(lhs, rhs)| lhs.checked_mul(rhs))),
+        Token::Division => (lval / rval, None),
+        op => return Err(EvalError::InvalidOperator{op: op.clone(), span: span.clone()})
+    };
+    if let Some(exact) = exact {
+        check_precision(exact, res, span, ctx)?;
+    }
+    Ok(res)
+}
+
+/// Both operands as `i128`, if they are integers.
+fn integers(lhs: f64, rhs: f64) -> Option<(i128, i128)> {
+    let integer = |value: f64| (value.fract() == 0.0 && value.abs() < I128_BOUND).then_some(value as i128);
+    Some((integer(lhs)?, integer(rhs)?))
+}
+
+/// 2^127, the first `f64` past `i128`.
+const I128_BOUND: f64 = 170141183460469231731687303715884105728.0;
+
+/// Warn if the integer is rounded to `value`.
+fn check_precision(exact: i128, value: f64, span: &Span, ctx: &mut Context<f64>) -> Result<(), EvalError> {
+    if value.abs() < I128_BOUND && value as i128 == exact {
+        return Ok(())
+    }
+    warn!(value = exact; "integer rounded to f64");
+    ctx.warn(Warning::PrecisionLoss{value: exact, span: span.clone()})
 }
 
 impl Calculable for Number {
-    fn calculate(node: &Self) -> f64 {
-        node.num as f64
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        let value = node.num as f64;
+        if let Ok(exact) = i128::try_from(node.num) {
+            check_precision(exact, value, &node.span, ctx)?;
+        }
+        Ok(value)
     }
 }
 
 impl Calculable for Pair {
-    fn calculate(node: &Self) -> f64 {
-        Expr::calculate(&node.expr)
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        Expr::calculate(&node.expr, ctx)
     }
 }
 
 impl Calculable for Neg {
-    fn calculate(node: &Self) -> f64 {
-        -Expr::calculate(&node.expr)
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        negate(Expr::calculate(&node.expr, ctx)?, &node.span)
+    }
+}
+
+/// A negative literal, the literal negated.
+fn negative(num: &Number, span: &Span, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+    negate(Number::calculate(num, ctx)?, span)
+}
+
+fn negate(value: f64, _: &Span) -> Result<f64, EvalError> {
+    Ok(-value)
+}
+
+impl Calculable for Not {
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)? != 0.0;
+        Ok(f64::from(u8::from(!value)))
+    }
+}
+
+impl Calculable for Abs {
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        Ok(Expr::calculate(&node.expr, ctx)?.abs())
+    }
+}
+
+impl Calculable for If {
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        let cond = Expr::calculate(&node.cond, ctx)?;
+        // Only the branch taken is calculated
+        let branch = if cond != 0.0 { &node.then } else { &node.otherwise };
+        Expr::calculate(branch, ctx)
+    }
+}
+
+impl Calculable for Call {
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        // The aggregates of a list, then the user functions and
+        // the registered ones
+        if let Some(value) = list::aggregate(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(function) = ctx.user_function(node)? {
+            let args = node.args.iter()
+                .map(|arg| Expr::calculate(arg, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            return ctx.call_user(&function, args, &node.span, Expr::calculate)
+        }
+        if let Some(value) = root(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(value) = solve::solve(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(value) = angle(node, ctx)? {
+            return Ok(value)
+        }
+        let f = ctx.function(node)?;
+        let args = node.args.iter()
+            .map(|arg| Expr::calculate(arg, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(f(&args))
+    }
+}
+
+/// Function of the angles called by the node, `None` for the other
+/// calls and if the registry has one of its name.
+fn angle(node: &Call, ctx: &mut Context<f64>) -> Result<Option<f64>, EvalError> {
+    if !angle::FUNCTIONS.contains(&node.name.as_str()) || ctx.functions.arity(&node.name).is_some() {
+        return Ok(None)
+    }
+    let arg = match node.args.as_slice() {
+        [arg] => Expr::calculate(arg, ctx)?,
+        args => return Err(EvalError::WrongArity{name: node.name.clone(), expected: 1, found: args.len(), span: node.span.clone()}),
+    };
+    Ok(angle::call(&node.name, arg, ctx.angle_unit))
+}
+
+/// Root of `root(n, x)`, `None` for the other calls and if the
+/// registry has a `root`.
+fn root(node: &Call, ctx: &mut Context<f64>) -> Result<Option<f64>, EvalError> {
+    Ok(root::args(node, ctx)?.map(|(n, x)| root::float(n, x)))
+}
+
+impl Calculable for Var {
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        if let Some(value) = ctx.var(&node.name) {
+            return Ok(value)
+        }
+        ctx.cell(node, Expr::calculate)?
+            .ok_or_else(|| ctx.unknown(node))
+    }
+}
+
+impl Calculable for Assign {
+    fn calculate(node: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?;
+        if let Some(env) = ctx.env.as_mut() {
+            env.set(&node.name, value);
+        }
+        Ok(value)
     }
 }
 
 impl Calculable for AST {
-    fn calculate(ast: &Self) -> f64 {
-        Expr::calculate(&ast.root)
+    fn calculate(ast: &Self, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        Expr::calculate(&ast.root, ctx)
+    }
+}
+
+impl list::Item for f64 {
+    fn calculate(node: &Expr, ctx: &mut Context<f64>) -> Result<f64, EvalError> {
+        Expr::calculate(node, ctx)
+    }
+
+    fn to_integer(&self) -> Option<i128> {
+        (self.fract() == 0.0 && self.abs() < 2f64.powi(127)).then_some(*self as i128)
+    }
+
+    fn from_integer(n: i128) -> f64 {
+        n as f64
     }
 }
 
+/// Arithmetic of the streaming evaluator.
+pub(crate) const ARITHMETIC: Arithmetic<f64> = Arithmetic{number: Number::calculate, negative, negate, binary};
+
 /// Calculate the expression's AST to `f64`
-pub fn calculate(ast: AST) -> f64 {
-    AST::calculate(&ast)
+pub fn calculate(ast: AST) -> Result<f64, Error> {
+    calculate_with(ast, &FunctionRegistry::new())
+}
+
+/// Calculate the expression's AST to `f64`, resolving calls
+/// through the registry
+pub fn calculate_with(ast: AST, functions: &FunctionRegistry<f64>) -> Result<f64, Error> {
+    calculate_in(ast, &mut Context::new(functions))
+}
+
+/// Calculate the expression's AST to `f64` in the context
+pub fn calculate_in(ast: AST, ctx: &mut Context<f64>) -> Result<f64, Error> {
+    Ok(AST::calculate(&ast, ctx)?)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::error::{Error, EvalError, Warning};
     use crate::generator::calculator_f;
     use crate::parser::ast::*;
     use crate::lexer::Token;
@@ -69,69 +280,176 @@ mod tests {
     #[test]
     fn test_num() {
         let res = calculator_f::calculate(AST{root: Number::new(3)});
-        assert_eq!(res, 3f64);
+        assert_eq!(res, Ok(3f64));
+    }
+
+    #[test]
+    fn test_precision_loss() {
+        let calc = crate::Calculator::<f64>::new();
+        let ast = calc.parse("9007199254740993 + 2").unwrap();
+        let mut ctx = calc.context();
+        assert_eq!(calculator_f::calculate_in(ast, &mut ctx), Ok(9007199254740994.0));
+        assert_eq!(ctx.warnings(), [Warning::PrecisionLoss{value: 9007199254740993, span: 0..16}]);
+        assert_eq!(ctx.warnings()[0].to_string(), "Precision loss: 9007199254740993 is 9007199254740992 as f64");
+
+        // Intermediates
+        let ast = calc.parse("0x20_0000_0000_0000 + 1 - 1").unwrap();
+        let mut ctx = calc.context();
+        assert_eq!(calculator_f::calculate_in(ast, &mut ctx), Ok(9007199254740991.0));
+        assert_eq!(ctx.warnings(), [Warning::PrecisionLoss{value: 9007199254740993, span: 0..23}]);
+
+        // Exact past 2^53, or not integers
+        for expr in ["0x20_0000_0000_0000 * 1024", "-9007199254740992", "1/3 + 9007199254740992", "0x8000_0000_0000_0000_0000_0000_0000_0000"] {
+            let ast = calc.parse(expr).unwrap();
+            let mut ctx = calc.context();
+            calculator_f::calculate_in(ast, &mut ctx).unwrap();
+            assert_eq!(ctx.warnings(), [], "{}", expr);
+        }
+
+        let calc = calc.warnings_as_errors(true);
+        assert!(matches!(calc.eval("2 * 9007199254740993"), Err(Error::Eval(EvalError::Warning{..}))));
+    }
+
+    #[test]
+    fn test_angle() {
+        use crate::{AngleUnit, Calculator, FunctionRegistry};
+
+        let calc = Calculator::<f64>::new();
+        assert_eq!(calc.eval("sin(0) + cos(0)"), Ok(1.0));
+        assert_eq!(calc.eval("deg(atan(1)) + rad(0)"), Ok(45.0));
+        let calc = calc.angle_unit(AngleUnit::Degree);
+        assert_eq!(calc.eval("sin(90) * 2 - cos(-180)"), Ok(3.0));
+        assert_eq!(calc.eval("rad(180) + asin(1)"), Ok(std::f64::consts::PI + 90.0));
+        assert_eq!(calc.eval("sin(1, 2)"), Err(Error::Eval(EvalError::WrongArity{name: "sin".to_string(), expected: 1, found: 2, span: 0..9})));
+
+        // The registered functions first
+        let mut functions = FunctionRegistry::new();
+        functions.register("sin", 1, |args: &[f64]| args[0]);
+        let calc = Calculator::new().functions(functions).angle_unit(AngleUnit::Degree);
+        assert_eq!(calc.eval("sin(30) + cos(0)"), Ok(31.0));
+        assert!(Calculator::<i128>::new().eval("sin(0)").is_err());
+    }
+
+    #[test]
+    fn test_root() {
+        use crate::Calculator;
+
+        let calc = Calculator::<f64>::new();
+        assert_eq!(calc.eval("√2"), Ok(2f64.sqrt()));
+        assert_eq!(calc.eval("root(3, 1000) + 3√-8 + root(1/2, 3)"), Ok(17.0));
+        assert!(calc.eval("√-1").unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_comparison() {
+        use crate::Calculator;
+
+        let calc = Calculator::<f64>::new();
+        assert_eq!(calc.eval("1/3 < 1/2 <= 1/2"), Ok(1.0));
+        // NaN is only not equal
+        assert_eq!(calc.eval("let nan = √-1 in (nan < 1) + (nan >= 1) + (nan == nan) + (nan != nan)"), Ok(1.0));
     }
 
     #[test]
     fn test_add() {
         let res = calculator_f::calculate(AST{root: BinOp::new(Number::new(1), Number::new(2), Token::Plus)});
-        assert_eq!(res, 3f64);
+        assert_eq!(res, Ok(3f64));
     }
 
     #[test]
     fn test_minus() {
         let res = calculator_f::calculate(AST{root: BinOp::new(Number::new(1), Number::new(2), Token::Minus)});
-        assert_eq!(res, -1f64);
+        assert_eq!(res, Ok(-1f64));
     }
 
     #[test]
     fn test_times() {
         let res = calculator_f::calculate(AST{root: BinOp::new(Number::new(1), Number::new(2), Token::Times)});
-        assert_eq!(res, 2f64);
+        assert_eq!(res, Ok(2f64));
     }
 
     #[test]
     fn test_division() {
         let res = calculator_f::calculate(AST{root: BinOp::new(Number::new(4), Number::new(2), Token::Division)});
-        assert_eq!(res, 2f64);
+        assert_eq!(res, Ok(2f64));
     }
 
     #[test]
     fn test_division_cast() {
         let res = calculator_f::calculate(AST{root: BinOp::new(Number::new(3), Number::new(2), Token::Division)});
-        assert_eq!(res, 1.5f64);
+        assert_eq!(res, Ok(1.5f64));
     }
 
     #[test]
     fn test_division_zero() {
         let res = calculator_f::calculate(AST{root: BinOp::new(Number::new(3), Number::new(0), Token::Division)});
-        assert_eq!(res, f64::INFINITY);
+        assert_eq!(res, Ok(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_var() {
+        use crate::environment::Environment;
+        use crate::functions::FunctionRegistry;
+        use crate::generator::Context;
+        use crate::error::{Error, EvalError};
+
+        let functions = FunctionRegistry::new();
+        let mut env = Environment::new();
+        // x = 1 + 2
+        let ast = AST{root: Assign::new("x", BinOp::new(Number::new(1), Number::new(2), Token::Plus))};
+        let res = calculator_f::calculate_in(ast, &mut Context::new(&functions).env(&mut env));
+        assert_eq!(res, Ok(3f64));
+        assert_eq!(env.get("x"), Some(&3f64));
+        // x
+        let res = calculator_f::calculate_in(AST{root: Var::new("x")}, &mut Context::new(&functions).env(&mut env));
+        assert_eq!(res, Ok(3f64));
+        // x without environment
+        let res = calculator_f::calculate(AST{root: Var::new("x")});
+        assert_eq!(res, Err(Error::Eval(EvalError::UnknownVariable{name: "x".to_string(), suggestion: None, span: 0..0})));
+    }
+
+    #[test]
+    fn test_call() {
+        use crate::functions::FunctionRegistry;
+        use crate::error::{Error, EvalError};
+
+        let mut functions = FunctionRegistry::new();
+        functions.register("max", 2, |args: &[f64]| args[0].max(args[1]));
+        // max(2, 5)
+        let res = calculator_f::calculate_with(AST{root: Call::new("max", vec![Number::new(2), Number::new(5)])}, &functions);
+        assert_eq!(res, Ok(5f64));
+        // max(2)
+        let res = calculator_f::calculate_with(AST{root: Call::new("max", vec![Number::new(2)])}, &functions);
+        assert_eq!(res, Err(Error::Eval(EvalError::WrongArity{name: "max".to_string(), expected: 2, found: 1, span: 0..0})));
+        // min(2, 5)
+        let res = calculator_f::calculate(AST{root: Call::new("min", vec![Number::new(2), Number::new(5)])});
+        assert_eq!(res, Err(Error::Eval(EvalError::UnknownFunction{name: "min".to_string(), suggestion: None, span: 0..0})));
     }
 
     #[test]
     fn test_neg() {
         // -3
         let res = calculator_f::calculate(AST{root: Neg::new(Number::new(3))});
-        assert_eq!(res, -3f64);
+        assert_eq!(res, Ok(-3f64));
         // --3
         let res = calculator_f::calculate(AST{root: Neg::new(Neg::new(Number::new(3)))});
-        assert_eq!(res, 3f64);
+        assert_eq!(res, Ok(3f64));
         // --3---3
         let res = calculator_f::calculate(AST{root: BinOp::new(
             Neg::new(Neg::new(Number::new(3))),
             Neg::new(Neg::new(Number::new(3))),
             Token::Minus
         )});
-        assert_eq!(res, 0f64);
+        assert_eq!(res, Ok(0f64));
     }
 
     #[test]
     fn test_pair() {
         // (3)
         let res = calculator_f::calculate(AST{root: Pair::new(Number::new(3))});
-        assert_eq!(res, 3f64);
+        assert_eq!(res, Ok(3f64));
         // ((3))
         let res = calculator_f::calculate(AST{root: Pair::new(Pair::new(Number::new(3)))});
-        assert_eq!(res, 3f64);
+        assert_eq!(res, Ok(3f64));
     }
 }
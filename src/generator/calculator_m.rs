@@ -0,0 +1,430 @@
+//! Convert the expression AST to `Matrix`
+//!
+//! The numbers are exact like `calculator_r`, and a list of numbers
+//! or of rows of the same length is a matrix, which can be assigned
+//! and bound by a `let`. Matrices of the same shape add and
+//! subtract, a matrix times a matrix is their product, and a number
+//! scales a matrix. The other mixes are a mismatch.
+use std::convert::TryFrom;
+
+use crate::lexer::{Span, Token};
+use crate::parser::ast::*;
+use crate::error::{Error, EvalError};
+use crate::functions::FunctionRegistry;
+use crate::generator::{is_chain, list, solve, Binding, Context};
+use crate::matrix::Matrix;
+use crate::rational::Rational;
+use crate::root;
+use crate::text;
+
+/// Functions of the matrices, unless the registry has one of the
+/// same name.
+pub(crate) const FUNCTIONS: [&str; 3] = ["transpose", "det", "hadamard"];
+
+trait Calculable {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError>;
+}
+
+/// The number of the value, a matrix is a mismatch at the span.
+fn number(value: &Matrix, span: &Span) -> Result<Rational, EvalError> {
+    match value {
+        Matrix::Number(value) => Ok(*value),
+        Matrix::Rows(_) => Err(EvalError::Mismatch{expected: "a number, not a matrix", span: span.clone()}),
+    }
+}
+
+fn boolean(value: bool) -> Matrix {
+    Matrix::Number(Rational::from(i128::from(value)))
+}
+
+impl Calculable for Expr {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        ctx.step(node)?;
+        match node {
+            Expr::Pair(pair) => Pair::calculate(pair, ctx),
+            Expr::BinOp(expr) => BinOp::calculate(expr, ctx),
+            Expr::Neg(neg) => Neg::calculate(neg, ctx),
+            Expr::Not(not) => Not::calculate(not, ctx),
+            Expr::Abs(abs) => Abs::calculate(abs, ctx),
+            Expr::If(node) => If::calculate(node, ctx),
+            Expr::Let(node) => Let::calculate(node, ctx),
+            Expr::List(_) | Expr::Range(_) => rows(node, ctx),
+            Expr::Index(node) => Index::calculate(node, ctx),
+            Expr::Call(call) => Call::calculate(call, ctx),
+            Expr::Var(var) => Var::calculate(var, ctx),
+            Expr::Assign(assign) => Assign::calculate(assign, ctx),
+            Expr::Num(num) => Number::calculate(num, ctx),
+            Expr::Duration(duration) => Number::calculate(&duration.to_number(), ctx),
+            Expr::Date(date) => Ok(Matrix::Number(Rational::from(i128::from(date.days)))),
+            Expr::Bool(bool) => Ok(boolean(bool.value)),
+            Expr::Str(_) => Err(text::not_a_number(node.span())),
+        }
+    }
+}
+
+/// Matrix of a list of numbers, a vector, or of a list of vectors
+/// of the same length, its rows.
+fn rows(node: &Expr, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+    let mismatch = || EvalError::Mismatch{expected: "numbers, or rows of the same length", span: node.span()};
+    let items = list::items(node, ctx)?;
+    let rows = match items.first() {
+        None => return Err(mismatch()),
+        Some(Matrix::Number(_)) => vec![items.iter().map(|item| number(item, &node.span())).collect::<Result<_, _>>()?],
+        Some(Matrix::Rows(_)) => items.into_iter()
+            .map(|item| match item {
+                Matrix::Rows(mut rows) if rows.len() == 1 => Ok(rows.remove(0)),
+                _ => Err(mismatch()),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    if rows.iter().any(|row| row.len() != rows[0].len()) {
+        return Err(mismatch())
+    }
+    Ok(Matrix::Rows(rows))
+}
+
+impl Calculable for BinOp {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        if is_chain(node) {
+            let value = ctx.compare_chain(node, Expr::calculate, |node, lhs, rhs| compare(node, lhs, rhs).map(|value| value == boolean(true)))?;
+            return Ok(boolean(value))
+        }
+        let (lhs, chain) = ctx.chain(node)?;
+        let mut lval = Expr::calculate(lhs, ctx)?;
+        for node in chain {
+            lval = apply(node, lval, ctx)?;
+        }
+        Ok(lval)
+    }
+}
+
+/// `lval` and the right operand of `node` with its operator.
+fn apply(node: &BinOp, lval: Matrix, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+    if let Token::And | Token::Or = node.op {
+        // The right operand only if the left one doesn't decide
+        let lhs = number(&lval, &node.lhs.span())?.numer() != 0;
+        let value = if lhs == (node.op == Token::Or) {
+            lhs
+        } else {
+            number(&Expr::calculate(&node.rhs, ctx)?, &node.rhs.span())?.numer() != 0
+        };
+        return Ok(boolean(value))
+    }
+    let rval = Expr::calculate(&node.rhs, ctx)?;
+    if node.op.is_comparison() {
+        return compare(node, &lval, &rval)
+    }
+    let overflow = || EvalError::Overflow{span: node.span.clone()};
+    let mismatch = |expected| EvalError::Mismatch{expected, span: node.span.clone()};
+    match (&node.op, &lval, &rval) {
+        (Token::Division, _, Matrix::Number(rhs)) if rhs.numer() == 0 => Err(EvalError::DivisionByZero{span: node.span.clone()}),
+        (op, Matrix::Number(lhs), Matrix::Number(rhs)) => {
+            let value = match op {
+                Token::Plus => lhs.checked_add(*rhs),
+                Token::Minus => lhs.checked_sub(*rhs),
+                Token::Times => lhs.checked_mul(*rhs),
+                Token::Division => lhs.checked_div(*rhs),
+                op => return Err(EvalError::InvalidOperator{op: op.clone(), span: node.span.clone()}),
+            };
+            value.map(Matrix::Number).ok_or_else(overflow)
+        }
+        (Token::Plus | Token::Minus, Matrix::Rows(lhs), Matrix::Rows(rhs)) if lval.shape() == rval.shape() => {
+            let plus = node.op == Token::Plus;
+            Matrix::zip_with(lhs, rhs, |l, r| if plus { l.checked_add(r) } else { l.checked_sub(r) }).ok_or_else(overflow)
+        }
+        (Token::Plus | Token::Minus, _, _) => Err(mismatch("both numbers, or matrices of the same shape")),
+        (Token::Times, Matrix::Number(factor), Matrix::Rows(rows)) | (Token::Times, Matrix::Rows(rows), Matrix::Number(factor)) => {
+            Matrix::map(rows, |item| item.checked_mul(*factor)).ok_or_else(overflow)
+        }
+        (Token::Times, Matrix::Rows(lhs), Matrix::Rows(rhs)) if lhs[0].len() == rhs.len() => {
+            Matrix::product(lhs, rhs).ok_or_else(overflow)
+        }
+        (Token::Times, _, _) => Err(mismatch("as many columns on the left as rows on the right")),
+        (Token::Division, Matrix::Rows(rows), Matrix::Number(divisor)) => {
+            Matrix::map(rows, |item| item.checked_div(*divisor)).ok_or_else(overflow)
+        }
+        (Token::Division, _, _) => Err(mismatch("a number as the divisor")),
+        (op, _, _) => Err(EvalError::InvalidOperator{op: op.clone(), span: node.span.clone()}),
+    }
+}
+
+/// A comparison of two numbers, or two values by `==` and `!=`.
+fn compare(node: &BinOp, lhs: &Matrix, rhs: &Matrix) -> Result<Matrix, EvalError> {
+    match (&node.op, lhs, rhs) {
+        (op, Matrix::Number(lhs), Matrix::Number(rhs)) => Ok(boolean(op.compares(lhs.partial_cmp(rhs)))),
+        (Token::Equal, lhs, rhs) => Ok(boolean(lhs == rhs)),
+        (Token::NotEqual, lhs, rhs) => Ok(boolean(lhs != rhs)),
+        _ => Err(EvalError::Mismatch{expected: "both numbers, or == and != of matrices", span: node.span.clone()}),
+    }
+}
+
+impl Calculable for Number {
+    fn calculate(node: &Self, _: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        i128::try_from(node.num)
+            .map(|num| Matrix::Number(Rational::from(num)))
+            .map_err(|_| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for Pair {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        Expr::calculate(&node.expr, ctx)
+    }
+}
+
+impl Calculable for Neg {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        // A negative literal, like the `i128` calculator
+        if let Expr::Num(num) = &*node.expr {
+            ctx.step(&node.expr)?;
+            return 0i128.checked_sub_unsigned(num.num)
+                .map(|num| Matrix::Number(Rational::from(num)))
+                .ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+        }
+        let value = match Expr::calculate(&node.expr, ctx)? {
+            Matrix::Number(value) => value.checked_neg().map(Matrix::Number),
+            Matrix::Rows(rows) => Matrix::map(&rows, Rational::checked_neg),
+        };
+        value.ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for Not {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        let value = number(&Expr::calculate(&node.expr, ctx)?, &node.expr.span())?;
+        Ok(boolean(value.numer() == 0))
+    }
+}
+
+impl Calculable for Abs {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        number(&Expr::calculate(&node.expr, ctx)?, &node.span)?
+            .checked_abs()
+            .map(Matrix::Number)
+            .ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for If {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        let cond = number(&Expr::calculate(&node.cond, ctx)?, &node.cond.span())?;
+        // Only the branch taken is calculated
+        let branch = if cond.numer() != 0 { &node.then } else { &node.otherwise };
+        Expr::calculate(branch, ctx)
+    }
+}
+
+impl Calculable for Let {
+    /// A list is bound as a matrix, not as a list.
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        let value = Expr::calculate(&node.value, ctx)?;
+        ctx.scoped(&node.name, Binding::Value(value), |ctx| Expr::calculate(&node.body, ctx))
+    }
+}
+
+impl Calculable for Index {
+    /// A row of a matrix, or an item of a vector, from 0.
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        let mut rows = match Expr::calculate(&node.list, ctx)? {
+            Matrix::Rows(rows) => rows,
+            Matrix::Number(_) => return Err(EvalError::Mismatch{expected: "a matrix", span: node.list.span()}),
+        };
+        let index = number(&Expr::calculate(&node.index, ctx)?, &node.index.span())?;
+        let index = index.is_integer().then(|| index.numer())
+            .ok_or_else(|| EvalError::Mismatch{expected: "an integer", span: node.index.span()})?;
+        let len = if rows.len() == 1 { rows[0].len() } else { rows.len() };
+        let i = usize::try_from(index).ok().filter(|i| *i < len)
+            .ok_or(EvalError::IndexOutOfRange{index, len, span: node.span.clone()})?;
+        Ok(if rows.len() == 1 { Matrix::Number(rows[0][i]) } else { Matrix::Rows(vec![rows.swap_remove(i)]) })
+    }
+}
+
+impl Calculable for Call {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        // The aggregates of a list, then the user functions and
+        // the registered ones
+        if let Some(value) = list::aggregate(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(function) = ctx.user_function(node)? {
+            let args = node.args.iter()
+                .map(|arg| Expr::calculate(arg, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            return ctx.call_user(&function, args, &node.span, Expr::calculate)
+        }
+        if let Some(value) = root(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(value) = solve::solve(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(value) = function(node, ctx)? {
+            return Ok(value)
+        }
+        let f = ctx.function(node)?;
+        let args = node.args.iter()
+            .map(|arg| Expr::calculate(arg, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(f(&args))
+    }
+}
+
+/// Root of `root(n, x)`, `None` for the other calls and if the
+/// registry has a `root`.
+fn root(node: &Call, ctx: &mut Context<Matrix>) -> Result<Option<Matrix>, EvalError> {
+    match root::args(node, ctx)? {
+        Some((Matrix::Number(n), Matrix::Number(x))) => root::fraction(node, n, x, ctx).map(|root| Some(Matrix::Number(root))),
+        Some(_) => Err(EvalError::Mismatch{expected: "numbers", span: node.span.clone()}),
+        None => Ok(None),
+    }
+}
+
+/// Value of a function of [`FUNCTIONS`], `None` for the other calls
+/// and if the registry has one of the same name.
+fn function(node: &Call, ctx: &mut Context<Matrix>) -> Result<Option<Matrix>, EvalError> {
+    if !FUNCTIONS.contains(&node.name.as_str()) || ctx.functions.arity(&node.name).is_some() {
+        return Ok(None)
+    }
+    let expected = if node.name == "hadamard" { 2 } else { 1 };
+    if node.args.len() != expected {
+        return Err(EvalError::WrongArity{name: node.name.clone(), expected, found: node.args.len(), span: node.span.clone()})
+    }
+    let args = node.args.iter()
+        .map(|arg| Expr::calculate(arg, ctx))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mismatch = |expected| EvalError::Mismatch{expected, span: node.span.clone()};
+    let value = match (node.name.as_str(), args.as_slice()) {
+        ("transpose", [value]) => value.transpose(),
+        ("det", [value]) if value.shape().is_some_and(|(rows, cols)| rows == cols) => {
+            value.det().map(Matrix::Number).ok_or_else(|| EvalError::Overflow{span: node.span.clone()})?
+        }
+        ("det", _) => return Err(mismatch("a square matrix")),
+        (_, [lhs @ Matrix::Rows(l), rhs @ Matrix::Rows(r)]) if lhs.shape() == rhs.shape() => {
+            Matrix::zip_with(l, r, Rational::checked_mul).ok_or_else(|| EvalError::Overflow{span: node.span.clone()})?
+        }
+        _ => return Err(mismatch("matrices of the same shape")),
+    };
+    Ok(Some(value))
+}
+
+impl Calculable for Var {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        if let Some(value) = ctx.var(&node.name) {
+            return Ok(value)
+        }
+        ctx.cell(node, Expr::calculate)?
+            .ok_or_else(|| ctx.unknown(node))
+    }
+}
+
+impl Calculable for Assign {
+    fn calculate(node: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?;
+        if let Some(env) = ctx.env.as_mut() {
+            env.set(&node.name, value.clone());
+        }
+        Ok(value)
+    }
+}
+
+impl Calculable for AST {
+    fn calculate(ast: &Self, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        Expr::calculate(&ast.root, ctx)
+    }
+}
+
+impl list::Item for Matrix {
+    fn calculate(node: &Expr, ctx: &mut Context<Matrix>) -> Result<Matrix, EvalError> {
+        Expr::calculate(node, ctx)
+    }
+
+    fn to_integer(&self) -> Option<i128> {
+        match self {
+            Matrix::Number(value) => value.is_integer().then(|| value.numer()),
+            Matrix::Rows(_) => None,
+        }
+    }
+
+    fn from_integer(n: i128) -> Matrix {
+        Matrix::Number(Rational::from(n))
+    }
+}
+
+/// Calculate the expression's AST to `Matrix`
+pub fn calculate(ast: AST) -> Result<Matrix, Error> {
+    calculate_with(ast, &FunctionRegistry::new())
+}
+
+/// Calculate the expression's AST to `Matrix`, resolving calls
+/// through the registry
+pub fn calculate_with(ast: AST, functions: &FunctionRegistry<Matrix>) -> Result<Matrix, Error> {
+    calculate_in(ast, &mut Context::new(functions))
+}
+
+/// Calculate the expression's AST to `Matrix` in the context
+pub fn calculate_in(ast: AST, ctx: &mut Context<Matrix>) -> Result<Matrix, Error> {
+    Ok(AST::calculate(&ast, ctx)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, EvalError};
+    use crate::matrix::Matrix;
+    use crate::rational::Rational;
+    use crate::{eval_no_panic, Calculator, Environment, FromAST};
+
+    fn eval(input: &str) -> String {
+        eval_no_panic::<Matrix>(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_ops() {
+        assert_eq!(eval("[[1, 2], [3, 4]] + [[10, 20], [30, 40]]"), "[[11, 22], [33, 44]]");
+        assert_eq!(eval("[1, 2, 3] - [1, 1, 1]"), "[0, 1, 2]");
+        assert_eq!(eval("[[1, 2], [3, 4]] * [[5], [6]]"), "[[17], [39]]");
+        assert_eq!(eval("[1, 2, 3] * transpose([4, 5, 6])"), "[32]");
+        assert_eq!(eval("2 * [[1, 2], [3, 4]] / 4"), "[[1/2, 1], [3/2, 2]]");
+        assert_eq!(eval("-[1, -2]"), "[-1, 2]");
+        assert_eq!(eval("hadamard([1, 2], [3, 4])"), "[3, 8]");
+        assert_eq!(eval("det([[2, 0, 1], [1, 3, 2], [1, 1, 2]]) + 1/2"), "13/2");
+        assert_eq!(eval("(1..3) * 2"), "[2, 4, 6]");
+        assert_eq!(eval("7/2"), "7/2");
+    }
+
+    #[test]
+    fn test_index() {
+        assert_eq!(eval("[[1, 2], [3, 4]][1]"), "[3, 4]");
+        assert_eq!(eval("[[1, 2], [3, 4]][1][0]"), "3");
+        assert_eq!(eval("let m = [[1, 2], [3, 4]] in m[0] * transpose(m[1])"), "[11]");
+        assert_eq!(eval("sum([[1, 2], [3, 4]])"), "[4, 6]");
+        assert_eq!(eval("len([1, 2]) + mean(1..3)"), "4");
+        let err = eval_no_panic::<Matrix>("[1, 2][2]");
+        assert_eq!(err, Err(Error::Eval(EvalError::IndexOutOfRange{index: 2, len: 2, span: 0..9})));
+    }
+
+    #[test]
+    fn test_comparison() {
+        assert_eq!(eval("[1, 2] == [1, 2] && [1, 2] != [[1], [2]]"), "1");
+        assert_eq!(eval("det([[1, 0], [0, 1]]) < 2"), "1");
+        assert!(matches!(eval_no_panic::<Matrix>("[1, 2] < [1, 3]"), Err(Error::Eval(EvalError::Mismatch{..}))));
+    }
+
+    #[test]
+    fn test_mismatch() {
+        for input in ["[1, 2] + [1, 2, 3]", "[1, 2] + 1", "[1, 2] * [3, 4]", "1 / [1, 2]", "det([1, 2])", "[[1, 2], [3]]", "[[1, 2], 3]", "[]", "|[1]|", "hadamard([1], [1, 2])"] {
+            let res = eval_no_panic::<Matrix>(input);
+            assert!(matches!(res, Err(Error::Eval(EvalError::Mismatch{..}))), "{}: {:?}", input, res);
+        }
+        assert!(matches!(eval_no_panic::<Matrix>("[1, 2] / 0"), Err(Error::Eval(EvalError::DivisionByZero{..}))));
+    }
+
+    #[test]
+    fn test_env() {
+        let calc = Calculator::<Matrix>::new();
+        let mut env = Environment::new();
+        let ast = calc.parse("m = [[1, 2], [3, 4]]").unwrap();
+        Matrix::from_ast_in(ast, &mut calc.context().env(&mut env)).unwrap();
+        let ast = calc.parse("det(m * m)").unwrap();
+        assert_eq!(Matrix::from_ast_in(ast, &mut calc.context().env(&mut env)), Ok(Matrix::Number(Rational::from(4))));
+    }
+}
@@ -0,0 +1,325 @@
+//! Convert the expression AST to `Rational`
+//!
+//! Division is exact, a error will raise while division by zero
+//! or overflow
+use std::convert::TryFrom;
+
+use crate::lexer::{Span, Token};
+use crate::parser::ast::*;
+use crate::error::{Error, EvalError};
+use crate::functions::FunctionRegistry;
+use crate::generator::{is_chain, list, solve, Arithmetic, Context};
+use crate::rational::Rational;
+use crate::root;
+use crate::text;
+
+trait Calculable {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError>;
+}
+
+impl Calculable for Expr {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        ctx.step(node)?;
+        match node {
+            Expr::Pair(pair) => Pair::calculate(pair, ctx),
+            Expr::BinOp(expr) => BinOp::calculate(expr, ctx),
+            Expr::Neg(neg) => Neg::calculate(neg, ctx),
+            Expr::Not(not) => Not::calculate(not, ctx),
+            Expr::Abs(abs) => Abs::calculate(abs, ctx),
+            Expr::If(node) => If::calculate(node, ctx),
+            Expr::Let(node) => list::let_in(node, ctx),
+            Expr::List(_) | Expr::Range(_) => Err(list::not_a_number(node)),
+            Expr::Index(node) => list::index(node, ctx),
+            Expr::Call(call) => Call::calculate(call, ctx),
+            Expr::Var(var) => Var::calculate(var, ctx),
+            Expr::Assign(assign) => Assign::calculate(assign, ctx),
+            Expr::Num(num) => Number::calculate(num, ctx),
+            Expr::Duration(duration) => Number::calculate(&duration.to_number(), ctx),
+            Expr::Date(date) => Ok(Rational::from(i128::from(date.days))),
+            Expr::Bool(bool) => Ok(Rational::from(i128::from(bool.value))),
+            Expr::Str(_) => Err(text::not_a_number(node.span())),
+        }
+    }
+}
+
+impl Calculable for BinOp {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        if is_chain(node) {
+            let value = ctx.compare_chain(node, Expr::calculate, |node, lhs, rhs| Ok(node.op.compares(lhs.partial_cmp(rhs))))?;
+            return Ok(Rational::from(i128::from(value)))
+        }
+        let (lhs, chain) = ctx.chain(node)?;
+        let mut lval = Expr::calculate(lhs, ctx)?;
+        for node in chain {
+            lval = apply(node, lval, ctx)?;
+        }
+        Ok(lval)
+    }
+}
+
+/// `lval` and the right operand of `node` with its operator.
+fn apply(node: &BinOp, lval: Rational, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+    if let Token::And | Token::Or = node.op {
+        // The right operand only if the left one doesn't decide
+        let lhs = lval.numer() != 0;
+        let value = if lhs == (node.op == Token::Or) { lhs } else { Expr::calculate(&node.rhs, ctx)?.numer() != 0 };
+        return Ok(Rational::from(i128::from(value)))
+    }
+    let rval = Expr::calculate(&node.rhs, ctx)?;
+    if node.op.is_comparison() {
+        return Ok(Rational::from(i128::from(node.op.compares(lval.partial_cmp(&rval)))))
+    }
+    binary(&node.op, lval, rval, &node.span, ctx)
+}
+
+/// `lval op rval` of an arithmetic operator.
+fn binary(op: &Token, lval: Rational, rval: Rational, span: &Span, _: &mut Context<Rational>) -> Result<Rational, EvalError> {
+    let overflow = || EvalError::Overflow{span: span.clone()};
+    match op {
+        Token::Plus => lval.checked_add(rval).ok_or_else(overflow),
+        Token::Minus => lval.checked_sub(rval).ok_or_else(overflow),
+        Token::Times => lval.checked_mul(rval).ok_or_else(overflow),
+        Token::Division => {
+            if rval.numer() == 0 {
+                return Err(EvalError::DivisionByZero{span: span.clone()})
+            }
+            lval.checked_div(rval).ok_or_else(overflow)
+        },
+        op => Err(EvalError::InvalidOperator{op: op.clone(), span: span.clone()})
+    }
+}
+
+impl Calculable for Number {
+    fn calculate(node: &Self, _: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        i128::try_from(node.num)
+            .map(Rational::from)
+            .map_err(|_| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for Pair {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        Expr::calculate(&node.expr, ctx)
+    }
+}
+
+impl Calculable for Neg {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        if let Expr::Num(num) = &*node.expr {
+            ctx.step(&node.expr)?;
+            return negative(num, &node.span, ctx)
+        }
+        negate(Expr::calculate(&node.expr, ctx)?, &node.span)
+    }
+}
+
+/// A negative literal, like the `i128` calculator.
+fn negative(num: &Number, span: &Span, _: &mut Context<Rational>) -> Result<Rational, EvalError> {
+    0i128.checked_sub_unsigned(num.num)
+        .map(Rational::from)
+        .ok_or_else(|| EvalError::Overflow{span: span.clone()})
+}
+
+fn negate(value: Rational, span: &Span) -> Result<Rational, EvalError> {
+    value.checked_neg().ok_or_else(|| EvalError::Overflow{span: span.clone()})
+}
+
+impl Calculable for Not {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?.numer() != 0;
+        Ok(Rational::from(i128::from(!value)))
+    }
+}
+
+impl Calculable for Abs {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        Expr::calculate(&node.expr, ctx)?
+            .checked_abs()
+            .ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for If {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        let cond = Expr::calculate(&node.cond, ctx)?;
+        // Only the branch taken is calculated
+        let branch = if cond.numer() != 0 { &node.then } else { &node.otherwise };
+        Expr::calculate(branch, ctx)
+    }
+}
+
+impl Calculable for Call {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        // The aggregates of a list, then the user functions and
+        // the registered ones
+        if let Some(value) = list::aggregate(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(function) = ctx.user_function(node)? {
+            let args = node.args.iter()
+                .map(|arg| Expr::calculate(arg, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            return ctx.call_user(&function, args, &node.span, Expr::calculate)
+        }
+        if let Some(value) = root(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(value) = solve::solve(node, ctx)? {
+            return Ok(value)
+        }
+        let f = ctx.function(node)?;
+        let args = node.args.iter()
+            .map(|arg| Expr::calculate(arg, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(f(&args))
+    }
+}
+
+/// Root of `root(n, x)`, `None` for the other calls and if the
+/// registry has a `root`.
+fn root(node: &Call, ctx: &mut Context<Rational>) -> Result<Option<Rational>, EvalError> {
+    match root::args(node, ctx)? {
+        Some((n, x)) => root::fraction(node, n, x, ctx).map(Some),
+        None => Ok(None),
+    }
+}
+
+impl Calculable for Var {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        if let Some(value) = ctx.var(&node.name) {
+            return Ok(value)
+        }
+        ctx.cell(node, Expr::calculate)?
+            .ok_or_else(|| ctx.unknown(node))
+    }
+}
+
+impl Calculable for Assign {
+    fn calculate(node: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?;
+        if let Some(env) = ctx.env.as_mut() {
+            env.set(&node.name, value);
+        }
+        Ok(value)
+    }
+}
+
+impl Calculable for AST {
+    fn calculate(ast: &Self, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        Expr::calculate(&ast.root, ctx)
+    }
+}
+
+impl list::Item for Rational {
+    fn calculate(node: &Expr, ctx: &mut Context<Rational>) -> Result<Rational, EvalError> {
+        Expr::calculate(node, ctx)
+    }
+
+    fn to_integer(&self) -> Option<i128> {
+        self.is_integer().then(|| self.numer())
+    }
+
+    fn from_integer(n: i128) -> Rational {
+        Rational::from(n)
+    }
+}
+
+/// Arithmetic of the streaming evaluator.
+pub(crate) const ARITHMETIC: Arithmetic<Rational> = Arithmetic{number: Number::calculate, negative, negate, binary};
+
+/// Calculate the expression's AST to `Rational`
+pub fn calculate(ast: AST) -> Result<Rational, Error> {
+    calculate_with(ast, &FunctionRegistry::new())
+}
+
+/// Calculate the expression's AST to `Rational`, resolving calls
+/// through the registry
+pub fn calculate_with(ast: AST, functions: &FunctionRegistry<Rational>) -> Result<Rational, Error> {
+    calculate_in(ast, &mut Context::new(functions))
+}
+
+/// Calculate the expression's AST to `Rational` in the context
+pub fn calculate_in(ast: AST, ctx: &mut Context<Rational>) -> Result<Rational, Error> {
+    Ok(AST::calculate(&ast, ctx)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generator::calculator_r;
+    use crate::parser::ast::*;
+    use crate::lexer::Token;
+    use crate::error::{Error, EvalError};
+    use crate::rational::Rational;
+
+    fn r(num: i128, den: i128) -> Rational {
+        Rational::new(num, den).unwrap()
+    }
+
+    #[test]
+    fn test_division() {
+        let res = calculator_r::calculate(AST{root: BinOp::new(Number::new(4), Number::new(2), Token::Division)});
+        assert_eq!(res, Ok(r(2, 1)));
+        let res = calculator_r::calculate(AST{root: BinOp::new(Number::new(3), Number::new(2), Token::Division)});
+        assert_eq!(res, Ok(r(3, 2)));
+        // 1/3*3
+        let third = BinOp::new(Number::new(1), Number::new(3), Token::Division);
+        let res = calculator_r::calculate(AST{root: BinOp::new(third, Number::new(3), Token::Times)});
+        assert_eq!(res, Ok(r(1, 1)));
+    }
+
+    #[test]
+    fn test_division_zero() {
+        // 3/(1-1)
+        let zero = BinOp::new(Number::new(1), Number::new(1), Token::Minus);
+        let res = calculator_r::calculate(AST{root: BinOp::new(Number::new(3), Pair::new(zero), Token::Division)});
+        assert!(matches!(res, Err(Error::Eval(EvalError::DivisionByZero{..}))));
+    }
+
+    #[test]
+    fn test_ops() {
+        // -(1/2 + 1/3) - 1
+        let sum = BinOp::new(
+            BinOp::new(Number::new(1), Number::new(2), Token::Division),
+            BinOp::new(Number::new(1), Number::new(3), Token::Division),
+            Token::Plus
+        );
+        let res = calculator_r::calculate(AST{root: BinOp::new(Neg::new(Pair::new(sum)), Number::new(1), Token::Minus)});
+        assert_eq!(res, Ok(r(-11, 6)));
+    }
+
+    #[test]
+    fn test_comparison() {
+        use crate::Calculator;
+
+        let calc = Calculator::<Rational>::new();
+        assert_eq!(calc.eval("0 < 1/3 < 1/2 != 2/4"), Ok(r(0, 1)));
+        assert_eq!(calc.eval("-1/2 < -1/3 == 2/6 < 1/3"), Ok(r(0, 1)));
+        assert_eq!(calc.eval("-1/2 < -1/3 && 2/6 == 1/3"), Ok(r(1, 1)));
+    }
+
+    #[test]
+    fn test_overflow() {
+        // u64::MAX * u64::MAX * u64::MAX
+        let res = calculator_r::calculate(AST{root: BinOp::new(
+            BinOp::new(Number::new(u64::MAX.into()), Number::new(u64::MAX.into()), Token::Times),
+            Number::new(u64::MAX.into()),
+            Token::Times
+        )});
+        assert!(matches!(res, Err(Error::Eval(EvalError::Overflow{..}))));
+    }
+
+    #[test]
+    fn test_root() {
+        use crate::error::Warning;
+        use crate::Calculator;
+
+        let calc = Calculator::<Rational>::new();
+        assert_eq!(calc.eval("√(9/4) + 3√(-1/8)"), Ok(r(1, 1)));
+        let ast = calc.parse("√2").unwrap();
+        let mut ctx = calc.context();
+        let root = calculator_r::calculate_in(ast, &mut ctx).unwrap();
+        assert_eq!(root.to_f64(), 2f64.sqrt());
+        assert_eq!(ctx.warnings(), [Warning::InexactRoot{degree: 2, radicand: "2".to_string(), span: 0..4}]);
+        assert!(matches!(calc.eval("root(1/2, 4)"), Err(Error::Eval(EvalError::Mismatch{..}))));
+    }
+}
@@ -0,0 +1,393 @@
+//! Convert the expression AST to `Time`
+//!
+//! The numbers are exact like `calculator_r`, and the duration
+//! literals are durations. Durations add to durations, and are
+//! multiplied or divided by numbers. Whole days add to dates, and
+//! a date minus a date is a number of days. The other mixes are a
+//! mismatch.
+use std::convert::TryFrom;
+
+use crate::lexer::Token;
+use crate::parser::ast::*;
+use crate::error::{Error, EvalError};
+use crate::functions::FunctionRegistry;
+use crate::generator::{is_chain, list, solve, Context};
+use crate::rational::Rational;
+use crate::duration::Time;
+use crate::root;
+use crate::text;
+
+trait Calculable {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError>;
+}
+
+fn truth(value: Time) -> bool {
+    value.value().numer() != 0
+}
+
+fn boolean(value: bool) -> Time {
+    Time::Number(Rational::from(i128::from(value)))
+}
+
+impl Calculable for Expr {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        ctx.step(node)?;
+        match node {
+            Expr::Pair(pair) => Pair::calculate(pair, ctx),
+            Expr::BinOp(expr) => BinOp::calculate(expr, ctx),
+            Expr::Neg(neg) => Neg::calculate(neg, ctx),
+            Expr::Not(not) => Not::calculate(not, ctx),
+            Expr::Abs(abs) => Abs::calculate(abs, ctx),
+            Expr::If(node) => If::calculate(node, ctx),
+            Expr::Let(node) => list::let_in(node, ctx),
+            Expr::List(_) | Expr::Range(_) => Err(list::not_a_number(node)),
+            Expr::Index(node) => list::index(node, ctx),
+            Expr::Call(call) => Call::calculate(call, ctx),
+            Expr::Var(var) => Var::calculate(var, ctx),
+            Expr::Assign(assign) => Assign::calculate(assign, ctx),
+            Expr::Num(num) => Number::calculate(num, ctx),
+            Expr::Duration(duration) => Duration::calculate(duration, ctx),
+            Expr::Date(date) => Ok(Time::Date(date.days)),
+            Expr::Bool(bool) => Ok(boolean(bool.value)),
+            Expr::Str(_) => Err(text::not_a_number(node.span())),
+        }
+    }
+}
+
+impl Calculable for BinOp {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        if is_chain(node) {
+            let value = ctx.compare_chain(node, Expr::calculate, |node, lhs, rhs| compare(node, *lhs, *rhs).map(truth))?;
+            return Ok(boolean(value))
+        }
+        let (lhs, chain) = ctx.chain(node)?;
+        let mut lval = Expr::calculate(lhs, ctx)?;
+        for node in chain {
+            lval = apply(node, lval, ctx)?;
+        }
+        Ok(lval)
+    }
+}
+
+/// `lval` and the right operand of `node` with its operator.
+fn apply(node: &BinOp, lval: Time, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+    if let Token::And | Token::Or = node.op {
+        // The right operand only if the left one doesn't decide
+        let lhs = truth(lval);
+        let value = if lhs == (node.op == Token::Or) { lhs } else { truth(Expr::calculate(&node.rhs, ctx)?) };
+        return Ok(boolean(value))
+    }
+    let rval = Expr::calculate(&node.rhs, ctx)?;
+    if node.op.is_comparison() {
+        return compare(node, lval, rval)
+    }
+    if let (Time::Date(_), _) | (_, Time::Date(_)) = (lval, rval) {
+        return date(node, lval, rval)
+    }
+    let overflow = || EvalError::Overflow{span: node.span.clone()};
+    let mismatch = |expected| EvalError::Mismatch{expected, span: node.span.clone()};
+    let (lhs, rhs) = (lval.value(), rval.value());
+    match (&node.op, lval.is_duration(), rval.is_duration()) {
+        (Token::Plus | Token::Minus, l, r) if l != r => Err(mismatch("both numbers or both durations")),
+        (Token::Plus, duration, _) => lhs.checked_add(rhs).map(|value| time(value, duration)).ok_or_else(overflow),
+        (Token::Minus, duration, _) => lhs.checked_sub(rhs).map(|value| time(value, duration)).ok_or_else(overflow),
+        (Token::Times, true, true) => Err(mismatch("a number as a factor of a duration")),
+        (Token::Times, l, r) => lhs.checked_mul(rhs).map(|value| time(value, l || r)).ok_or_else(overflow),
+        (Token::Division, false, true) => Err(mismatch("a duration divided by a duration or a number")),
+        (Token::Division, l, r) => {
+            if rhs.numer() == 0 {
+                return Err(EvalError::DivisionByZero{span: node.span.clone()})
+            }
+            // A duration per a duration is a number
+            lhs.checked_div(rhs).map(|value| time(value, l && !r)).ok_or_else(overflow)
+        },
+        (op, _, _) => Err(EvalError::InvalidOperator{op: op.clone(), span: node.span.clone()})
+    }
+}
+
+/// `date ± days`, `days + date` and `date - date`, the days are
+/// a whole number or a duration of whole days.
+fn date(node: &BinOp, lhs: Time, rhs: Time) -> Result<Time, EvalError> {
+    let mismatch = || EvalError::Mismatch{expected: "a date plus or minus whole days, or a date minus a date", span: node.span.clone()};
+    let days = |value: Time| match value {
+        Time::Number(days) if days.is_integer() => Some(days.numer()),
+        Time::Duration(secs) if secs.is_integer() && secs.numer() % 86400 == 0 => Some(secs.numer() / 86400),
+        _ => None,
+    };
+    let (date, days) = match (&node.op, lhs, rhs) {
+        (Token::Minus, Time::Date(lhs), Time::Date(rhs)) => return Ok(Time::Number(Rational::from(i128::from(lhs) - i128::from(rhs)))),
+        (Token::Plus, Time::Date(date), value) | (Token::Plus, value, Time::Date(date)) => (date, days(value).ok_or_else(mismatch)?),
+        (Token::Minus, Time::Date(date), value) => (date, 0i128.saturating_sub(days(value).ok_or_else(mismatch)?)),
+        (Token::Plus | Token::Minus | Token::Times | Token::Division, _, _) => return Err(mismatch()),
+        (op, _, _) => return Err(EvalError::InvalidOperator{op: op.clone(), span: node.span.clone()}),
+    };
+    // Days past the dates of 4 digits are an overflow
+    days.checked_add(i128::from(date))
+        .and_then(crate::date::checked)
+        .map(Time::Date)
+        .ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+}
+
+/// A comparison of two numbers, two durations or two dates.
+fn compare(node: &BinOp, lhs: Time, rhs: Time) -> Result<Time, EvalError> {
+    match (lhs, rhs) {
+        (Time::Number(_), Time::Number(_)) | (Time::Duration(_), Time::Duration(_)) | (Time::Date(_), Time::Date(_)) => {
+            Ok(boolean(node.op.compares(lhs.value().partial_cmp(&rhs.value()))))
+        }
+        _ => Err(EvalError::Mismatch{expected: "both numbers, both durations or both dates", span: node.span.clone()}),
+    }
+}
+
+fn time(value: Rational, duration: bool) -> Time {
+    if duration { Time::Duration(value) } else { Time::Number(value) }
+}
+
+impl Calculable for Number {
+    fn calculate(node: &Self, _: &mut Context<Time>) -> Result<Time, EvalError> {
+        i128::try_from(node.num)
+            .map(|num| Time::Number(Rational::from(num)))
+            .map_err(|_| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for Duration {
+    fn calculate(node: &Self, _: &mut Context<Time>) -> Result<Time, EvalError> {
+        i128::try_from(node.secs)
+            .map(|secs| Time::Duration(Rational::from(secs)))
+            .map_err(|_| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for Pair {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        Expr::calculate(&node.expr, ctx)
+    }
+}
+
+impl Calculable for Neg {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        // A negative literal, like the `i128` calculator
+        if let Expr::Num(num) = &*node.expr {
+            ctx.step(&node.expr)?;
+            return 0i128.checked_sub_unsigned(num.num)
+                .map(|num| Time::Number(Rational::from(num)))
+                .ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+        }
+        let value = Expr::calculate(&node.expr, ctx)?;
+        if let Time::Date(_) = value {
+            return Err(EvalError::Mismatch{expected: "a number or a duration", span: node.span.clone()})
+        }
+        value.value()
+            .checked_neg()
+            .map(|neg| time(neg, value.is_duration()))
+            .ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for Not {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        let value = truth(Expr::calculate(&node.expr, ctx)?);
+        Ok(boolean(!value))
+    }
+}
+
+impl Calculable for Abs {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?;
+        if let Time::Date(_) = value {
+            return Err(EvalError::Mismatch{expected: "a number or a duration", span: node.span.clone()})
+        }
+        value.value()
+            .checked_abs()
+            .map(|abs| time(abs, value.is_duration()))
+            .ok_or_else(|| EvalError::Overflow{span: node.span.clone()})
+    }
+}
+
+impl Calculable for If {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        let cond = Expr::calculate(&node.cond, ctx)?;
+        // Only the branch taken is calculated
+        let branch = if truth(cond) { &node.then } else { &node.otherwise };
+        Expr::calculate(branch, ctx)
+    }
+}
+
+impl Calculable for Call {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        // The aggregates of a list, then the user functions and
+        // the registered ones
+        if let Some(value) = list::aggregate(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(function) = ctx.user_function(node)? {
+            let args = node.args.iter()
+                .map(|arg| Expr::calculate(arg, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            return ctx.call_user(&function, args, &node.span, Expr::calculate)
+        }
+        if let Some(value) = root(node, ctx)? {
+            return Ok(value)
+        }
+        if let Some(value) = solve::solve(node, ctx)? {
+            return Ok(value)
+        }
+        #[cfg(feature = "dates")]
+        if let Some(today) = today(node, ctx)? {
+            return Ok(today)
+        }
+        let f = ctx.function(node)?;
+        let args = node.args.iter()
+            .map(|arg| Expr::calculate(arg, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(f(&args))
+    }
+}
+
+/// The local date of `today()`, `None` for the other calls and if
+/// the registry has a `today`.
+#[cfg(feature = "dates")]
+fn today(node: &Call, ctx: &Context<Time>) -> Result<Option<Time>, EvalError> {
+    if node.name != "today" || ctx.functions.arity(&node.name).is_some() {
+        return Ok(None)
+    }
+    if !node.args.is_empty() {
+        return Err(EvalError::WrongArity{name: node.name.clone(), expected: 0, found: node.args.len(), span: node.span.clone()})
+    }
+    Ok(Some(Time::Date(crate::date::today())))
+}
+
+/// Root of `root(n, x)`, `None` for the other calls and if the
+/// registry has a `root`.
+fn root(node: &Call, ctx: &mut Context<Time>) -> Result<Option<Time>, EvalError> {
+    match root::args(node, ctx)? {
+        Some((Time::Number(n), Time::Number(x))) => root::fraction(node, n, x, ctx).map(|root| Some(Time::Number(root))),
+        Some(_) => Err(EvalError::Mismatch{expected: "numbers", span: node.span.clone()}),
+        None => Ok(None),
+    }
+}
+
+impl Calculable for Var {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        if let Some(value) = ctx.var(&node.name) {
+            return Ok(value)
+        }
+        ctx.cell(node, Expr::calculate)?
+            .ok_or_else(|| ctx.unknown(node))
+    }
+}
+
+impl Calculable for Assign {
+    fn calculate(node: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        let value = Expr::calculate(&node.expr, ctx)?;
+        if let Some(env) = ctx.env.as_mut() {
+            env.set(&node.name, value);
+        }
+        Ok(value)
+    }
+}
+
+impl Calculable for AST {
+    fn calculate(ast: &Self, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        Expr::calculate(&ast.root, ctx)
+    }
+}
+
+impl list::Item for Time {
+    fn calculate(node: &Expr, ctx: &mut Context<Time>) -> Result<Time, EvalError> {
+        Expr::calculate(node, ctx)
+    }
+
+    fn to_integer(&self) -> Option<i128> {
+        match self {
+            Time::Number(value) => value.is_integer().then(|| value.numer()),
+            Time::Duration(_) | Time::Date(_) => None,
+        }
+    }
+
+    fn from_integer(n: i128) -> Time {
+        Time::Number(Rational::from(n))
+    }
+}
+
+/// Calculate the expression's AST to `Time`
+pub fn calculate(ast: AST) -> Result<Time, Error> {
+    calculate_with(ast, &FunctionRegistry::new())
+}
+
+/// Calculate the expression's AST to `Time`, resolving calls
+/// through the registry
+pub fn calculate_with(ast: AST, functions: &FunctionRegistry<Time>) -> Result<Time, Error> {
+    calculate_in(ast, &mut Context::new(functions))
+}
+
+/// Calculate the expression's AST to `Time` in the context
+pub fn calculate_in(ast: AST, ctx: &mut Context<Time>) -> Result<Time, Error> {
+    Ok(AST::calculate(&ast, ctx)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::duration::Time;
+    use crate::error::{Error, EvalError};
+    use crate::rational::Rational;
+    use crate::eval_no_panic;
+
+    fn duration(secs: i128) -> Time {
+        Time::Duration(Rational::from(secs))
+    }
+
+    #[test]
+    fn test_ops() {
+        assert_eq!(eval_no_panic::<Time>("1h30m + 45m"), Ok(duration(8100)));
+        assert_eq!(eval_no_panic::<Time>("2 * 90s - 1m"), Ok(duration(120)));
+        assert_eq!(eval_no_panic::<Time>("1d / 3"), Ok(duration(28800)));
+        assert_eq!(eval_no_panic::<Time>("1h / 7m30s"), Ok(Time::Number(Rational::from(8))));
+        assert_eq!(eval_no_panic::<Time>("-|-1m|"), Ok(duration(-60)));
+        assert_eq!(eval_no_panic::<Time>("1/2 + 1/4"), Ok(Time::Number(Rational::new(3, 4).unwrap())));
+        assert_eq!(eval_no_panic::<Time>("√(1/4) * 1h"), Ok(duration(1800)));
+        assert_eq!(eval_no_panic::<Time>("1m/0"), Err(Error::Eval(EvalError::DivisionByZero{span: 0..4})));
+    }
+
+    #[test]
+    fn test_comparison() {
+        let number = |n| Ok(Time::Number(Rational::from(n)));
+        assert_eq!(eval_no_panic::<Time>("59m < 1h <= 60m"), number(1));
+        assert_eq!(eval_no_panic::<Time>("1h / 1m == 60"), number(1));
+        assert!(matches!(eval_no_panic::<Time>("1h < 2"), Err(Error::Eval(EvalError::Mismatch{..}))));
+    }
+
+    #[test]
+    fn test_mismatch() {
+        for input in ["1h + 1", "2 - 1s", "1h * 1h", "1 / 1s", "√1h"] {
+            let res = eval_no_panic::<Time>(input);
+            assert!(matches!(res, Err(Error::Eval(EvalError::Mismatch{..}))), "{}", input);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "dates")]
+    fn test_date() {
+        use crate::Calculator;
+
+        let calc = Calculator::<Time>::new().dates(true);
+        assert_eq!(calc.eval("2024-05-01 + 90d"), Ok(Time::Date(19934)));
+        assert_eq!(calc.eval("2024-05-01 + 90").unwrap().to_string(), "2024-07-30");
+        assert_eq!(calc.eval("2 * 1d + 2024-05-01 - 1"), Ok(Time::Date(19845)));
+        assert_eq!(calc.eval("2024-12-25 - 2024-05-01"), Ok(Time::Number(Rational::from(238))));
+        assert_eq!(calc.eval("2024-12-25 - 2024-12-25 + today() - today()"), Ok(Time::Number(Rational::from(0))));
+        for input in ["2024-05-01 + 1h", "2024-05-01 * 2", "1 - 2024-05-01", "-2024-05-01", "2024-05-01 + 1/2", "today(1)"] {
+            assert!(matches!(calc.eval(input), Err(Error::Eval(EvalError::Mismatch{..} | EvalError::WrongArity{..}))), "{}", input);
+        }
+        assert!(matches!(calc.eval("9999-12-31 + 1"), Err(Error::Eval(EvalError::Overflow{..}))));
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(eval_no_panic::<Time>("sum([1h, 30m, 15m])"), Ok(duration(6300)));
+        assert_eq!(eval_no_panic::<Time>("mean([1m, 2m])"), Ok(duration(90)));
+        assert_eq!(eval_no_panic::<Time>("sum(n, 1, 3, n * 1m)"), Ok(duration(360)));
+        assert_eq!(eval_no_panic::<Time>("sum([])"), Ok(Time::Number(Rational::from(0))));
+        assert_eq!(eval_no_panic::<Time>("[1, 2, 3][1h / 1h]"), Ok(Time::Number(Rational::from(2))));
+    }
+}
@@ -0,0 +1,172 @@
+//! Lists of the backends, `[1, 2]`, `1..10` and `a[0]`
+//!
+//! A list only exists inside an expression: as an operand of an
+//! index, of an aggregate such as `sum`, or bound by a `let`. It
+//! isn't a result, nor a value of the environment.
+//!
+//! The aggregates are calculated by the operators of the backend, so
+//! overflows, warnings and the operation limit are the same as for
+//! the expression written out. So are the sums and products over a
+//! bound variable, `sum(i, 1, 100, i*i)`, which need no list.
+use std::convert::TryFrom;
+
+use crate::error::EvalError;
+use crate::generator::{Binding, Context, MAX_ITERATIONS, MAX_LIST_LEN};
+use crate::lexer::{Span, Token};
+use crate::parser::ast::{BinOp, Call, Expr, Index, Let, Var};
+
+/// Aggregates of a list, called with the list as only argument.
+pub(crate) const AGGREGATES: [&str; 4] = ["sum", "prod", "len", "mean"];
+
+/// Items of a backend.
+pub(crate) trait Item: Clone + Sized {
+    /// Calculate a node that is a number.
+    fn calculate(node: &Expr, ctx: &mut Context<Self>) -> Result<Self, EvalError>;
+    /// Integer value, for the indices and the bounds of the ranges,
+    /// `None` if it has a fraction.
+    fn to_integer(&self) -> Option<i128>;
+    fn from_integer(n: i128) -> Self;
+}
+
+/// Whether the node is a list: a literal, a range, or a name bound
+/// to one.
+pub(crate) fn is_list<T>(node: &Expr, ctx: &Context<T>) -> bool {
+    match node {
+        Expr::Pair(pair) => is_list(&pair.expr, ctx),
+        Expr::List(_) | Expr::Range(_) => true,
+        Expr::Var(var) => ctx.list(&var.name).is_some(),
+        _ => false,
+    }
+}
+
+/// Error of a list where a number is expected.
+pub(crate) fn not_a_number(node: &Expr) -> EvalError {
+    EvalError::Mismatch{expected: "a number, not a list", span: node.span()}
+}
+
+/// Items of the list node.
+pub(crate) fn items<T: Item>(node: &Expr, ctx: &mut Context<T>) -> Result<Vec<T>, EvalError> {
+    ctx.step(node)?;
+    match node {
+        Expr::Pair(pair) => items(&pair.expr, ctx),
+        Expr::List(list) => list.items.iter().map(|item| T::calculate(item, ctx)).collect(),
+        Expr::Range(range) => {
+            let start = integer(&range.start, ctx)?;
+            let end = integer(&range.end, ctx)?;
+            // Inclusive, empty if the end is before the start
+            let len = end.checked_sub(start).and_then(|diff| diff.checked_add(1)).unwrap_or(i128::MAX).max(0);
+            if len > MAX_LIST_LEN as i128 {
+                return Err(EvalError::ListTooLong{limit: MAX_LIST_LEN, span: range.span.clone()})
+            }
+            (start..=end).map(|n| ctx.step(node).map(|_| T::from_integer(n))).collect()
+        }
+        Expr::Var(var) => ctx.list(&var.name)
+            .map(<[T]>::to_vec)
+            .ok_or_else(|| EvalError::Mismatch{expected: "a list", span: var.span.clone()}),
+        _ => Err(EvalError::Mismatch{expected: "a list", span: node.span()}),
+    }
+}
+
+/// Value of the node as an integer.
+fn integer<T: Item>(node: &Expr, ctx: &mut Context<T>) -> Result<i128, EvalError> {
+    T::calculate(node, ctx)?
+        .to_integer()
+        .ok_or_else(|| EvalError::Mismatch{expected: "an integer", span: node.span()})
+}
+
+/// Item of the list at the index, from 0.
+pub(crate) fn index<T: Item>(node: &Index, ctx: &mut Context<T>) -> Result<T, EvalError> {
+    let items = items(&node.list, ctx)?;
+    let index = integer(&node.index, ctx)?;
+    usize::try_from(index).ok()
+        .and_then(|i| items.get(i).cloned())
+        .ok_or(EvalError::IndexOutOfRange{index, len: items.len(), span: node.span.clone()})
+}
+
+/// Body of the `let`, with the name bound to a list if the value is
+/// one.
+pub(crate) fn let_in<T: Item>(node: &Let, ctx: &mut Context<T>) -> Result<T, EvalError> {
+    let binding = if is_list(&node.value, ctx) {
+        Binding::List(items(&node.value, ctx)?)
+    } else {
+        Binding::Value(T::calculate(&node.value, ctx)?)
+    };
+    ctx.scoped(&node.name, binding, |ctx| T::calculate(&node.body, ctx))
+}
+
+/// Whether the call is an aggregate of a list, or a sum or a product
+/// over a bound variable.
+pub(crate) fn is_aggregate<T>(call: &Call, ctx: &Context<T>) -> bool {
+    match call.args.as_slice() {
+        [arg] => AGGREGATES.contains(&call.name.as_str()) && is_list(arg, ctx),
+        [Expr::Var(_), _, _, _] => call.name == "sum" || call.name == "prod",
+        _ => false,
+    }
+}
+
+/// Aggregate of the call, `None` for the other calls.
+pub(crate) fn aggregate<T: Item>(call: &Call, ctx: &mut Context<T>) -> Result<Option<T>, EvalError> {
+    if !is_aggregate(call, ctx) {
+        return Ok(None)
+    }
+    match call.args.as_slice() {
+        [Expr::Var(var), from, to, body] => series(call, &var.name, from, to, body, ctx).map(Some),
+        [arg] => {
+            let items = items(arg, ctx)?;
+            let len = T::from_integer(items.len() as i128);
+            let value = match call.name.as_str() {
+                "sum" => fold(items, Token::Plus, T::from_integer(0), &call.span, ctx)?,
+                "prod" => fold(items, Token::Times, T::from_integer(1), &call.span, ctx)?,
+                "len" => len,
+                _ => {
+                    let sum = fold(items, Token::Plus, T::from_integer(0), &call.span, ctx)?;
+                    operate(sum, Token::Division, len, &call.span, ctx)?
+                }
+            };
+            Ok(Some(value))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Sum or product of the body over the integers from `from` to `to`
+/// included, with the name bound to each.
+fn series<T: Item>(call: &Call, name: &str, from: &Expr, to: &Expr, body: &Expr, ctx: &mut Context<T>) -> Result<T, EvalError> {
+    let from = integer(from, ctx)?;
+    let to = integer(to, ctx)?;
+    let times = to.checked_sub(from).and_then(|diff| diff.checked_add(1)).unwrap_or(i128::MAX);
+    if times > MAX_ITERATIONS as i128 {
+        return Err(EvalError::TooManyIterations{limit: MAX_ITERATIONS, span: call.span.clone()})
+    }
+    let (op, init) = match call.name.as_str() {
+        "sum" => (Token::Plus, 0),
+        _ => (Token::Times, 1),
+    };
+    // From the first term, as `fold`
+    let mut acc = None;
+    for n in from..=to {
+        let value = ctx.scoped(name, Binding::Value(T::from_integer(n)), |ctx| T::calculate(body, ctx))?;
+        acc = Some(match acc {
+            Some(acc) => operate(acc, op.clone(), value, &call.span, ctx)?,
+            None => value,
+        });
+    }
+    Ok(acc.unwrap_or_else(|| T::from_integer(init)))
+}
+
+/// Fold from the first item, `init` only for no item, so the items
+/// needn't be numbers, such as durations.
+fn fold<T: Item>(items: Vec<T>, op: Token, init: T, span: &Span, ctx: &mut Context<T>) -> Result<T, EvalError> {
+    let mut items = items.into_iter();
+    let first = items.next().unwrap_or(init);
+    items.try_fold(first, |acc, item| operate(acc, op.clone(), item, span, ctx))
+}
+
+/// `lhs op rhs` by the operator of the backend, at the span.
+pub(crate) fn operate<T: Item>(lhs: T, op: Token, rhs: T, span: &Span, ctx: &mut Context<T>) -> Result<T, EvalError> {
+    // `%` can't be written in a name, so the operands hide no variable
+    let node = BinOp::new(Var::new("%lhs"), Var::new("%rhs"), op).with_span(span.clone());
+    ctx.scoped("%lhs", Binding::Value(lhs), |ctx| {
+        ctx.scoped("%rhs", Binding::Value(rhs), |ctx| T::calculate(&node, ctx))
+    })
+}
@@ -0,0 +1,250 @@
+//! Equations of one variable, `solve(2*x + 3 == 11, x)`
+//!
+//! The sides are expanded to a polynomial of the variable by `+`,
+//! `-`, `*` and `/` by a term without it, of degree 1 or 2, and the
+//! variable isolated. The coefficients and the solution are
+//! calculated by the operators of the backend, so the solution of
+//! `Rational` is exact, and the one of `i128` is cast as its
+//! divisions. Of the two solutions of a quadratic equation, the
+//! larger is returned, by the `root` of the backend.
+//!
+//! The variable needn't be assigned, and isn't assigned by the call.
+use crate::error::EvalError;
+use crate::generator::{list, Binding, Context};
+use crate::lexer::{Span, Token};
+use crate::parser::ast::{Call, Expr, Number, Var};
+use crate::root;
+
+pub(crate) const NAME: &str = "solve";
+
+/// Degree of the equations solved.
+const MAX_DEGREE: usize = 2;
+
+/// Whether the call solves an equation, `solve(lhs == rhs, x)`.
+pub(crate) fn is_solve(call: &Call) -> bool {
+    call.name == NAME && matches!(call.args.as_slice(), [Expr::BinOp(bin), Expr::Var(_)] if bin.op == Token::Equal)
+}
+
+/// Solution of the call, `None` for the other calls and if the
+/// registry has a `solve`.
+pub(crate) fn solve<T: list::Item>(call: &Call, ctx: &mut Context<T>) -> Result<Option<T>, EvalError> {
+    if call.name != NAME || ctx.functions.arity(&call.name).is_some() {
+        return Ok(None)
+    }
+    let (bin, name) = match call.args.as_slice() {
+        [Expr::BinOp(bin), Expr::Var(var)] if bin.op == Token::Equal => (bin, &var.name),
+        [_, _] => return Err(EvalError::Mismatch{expected: "an equation `lhs == rhs` and its variable", span: call.span.clone()}),
+        args => return Err(EvalError::WrongArity{name: call.name.clone(), expected: 2, found: args.len(), span: call.span.clone()}),
+    };
+    let solver = Solver{name, span: &call.span};
+    let lhs = solver.polynomial(&bin.lhs, ctx)?;
+    let rhs = solver.polynomial(&bin.rhs, ctx)?;
+    let mut coefficients = solver.combine(lhs, Token::Minus, rhs, ctx)?;
+    while coefficients.last().is_some_and(is_zero) {
+        coefficients.pop();
+    }
+    let zero = || T::from_integer(0);
+    match coefficients.as_slice() {
+        [] => Err(solver.unsolvable("every value is a solution")),
+        [_] => Err(solver.unsolvable("no value is a solution")),
+        [b, Some(a)] => {
+            // x = -b / a
+            let minus_b = solver.negate(b.clone().unwrap_or_else(zero), ctx)?;
+            solver.operate(minus_b, Token::Division, a.clone(), ctx).map(Some)
+        }
+        [c, b, Some(a)] => {
+            let (b, c) = (b.clone().unwrap_or_else(zero), c.clone().unwrap_or_else(zero));
+            solver.quadratic(a.clone(), b, c, ctx).map(Some)
+        }
+        _ => unreachable!("the products stop at the degree {}", MAX_DEGREE),
+    }
+}
+
+/// Coefficients of the powers of the variable from 0, `None` for a
+/// missing term, which adds to a number as to a duration.
+type Polynomial<T> = Vec<Option<T>>;
+
+fn is_zero<T: list::Item>(coefficient: &Option<T>) -> bool {
+    coefficient.as_ref().is_none_or(|c| c.to_integer() == Some(0))
+}
+
+/// Whether the variable is in the node.
+fn contains(node: &Expr, name: &str) -> bool {
+    match node {
+        Expr::Var(var) => var.name == name,
+        Expr::Pair(pair) => contains(&pair.expr, name),
+        Expr::BinOp(bin) => contains(&bin.lhs, name) || contains(&bin.rhs, name),
+        Expr::Neg(neg) => contains(&neg.expr, name),
+        Expr::Not(not) => contains(&not.expr, name),
+        Expr::Abs(abs) => contains(&abs.expr, name),
+        Expr::If(node) => [&node.cond, &node.then, &node.otherwise].iter().any(|expr| contains(expr, name)),
+        Expr::Let(node) => contains(&node.value, name) || contains(&node.body, name),
+        Expr::List(list) => list.items.iter().any(|item| contains(item, name)),
+        Expr::Range(range) => contains(&range.start, name) || contains(&range.end, name),
+        Expr::Index(index) => contains(&index.list, name) || contains(&index.index, name),
+        Expr::Call(call) => call.args.iter().any(|arg| contains(arg, name)),
+        Expr::Assign(assign) => assign.name == name || contains(&assign.expr, name),
+        Expr::Num(_) | Expr::Duration(_) | Expr::Date(_) | Expr::Bool(_) | Expr::Str(_) => false,
+    }
+}
+
+/// Solver of the variable `name`, the errors at the span of the
+/// call.
+struct Solver<'a> {
+    name: &'a str,
+    span: &'a Span,
+}
+
+impl Solver<'_> {
+    fn unsolvable(&self, reason: &'static str) -> EvalError {
+        EvalError::Unsolvable{reason, span: self.span.clone()}
+    }
+
+    fn operate<T: list::Item>(&self, lhs: T, op: Token, rhs: T, ctx: &mut Context<T>) -> Result<T, EvalError> {
+        list::operate(lhs, op, rhs, self.span, ctx)
+    }
+
+    fn negate<T: list::Item>(&self, value: T, ctx: &mut Context<T>) -> Result<T, EvalError> {
+        self.operate(value, Token::Times, T::from_integer(-1), ctx)
+    }
+
+    /// Coefficients of the node.
+    fn polynomial<T: list::Item>(&self, node: &Expr, ctx: &mut Context<T>) -> Result<Polynomial<T>, EvalError> {
+        if !contains(node, self.name) {
+            return Ok(vec![Some(T::calculate(node, ctx)?)])
+        }
+        ctx.step(node)?;
+        match node {
+            Expr::Var(_) => Ok(vec![None, Some(T::from_integer(1))]),
+            Expr::Pair(pair) => self.polynomial(&pair.expr, ctx),
+            Expr::Neg(neg) => {
+                let coefficients = self.polynomial(&neg.expr, ctx)?;
+                coefficients.into_iter().map(|c| c.map(|c| self.negate(c, ctx)).transpose()).collect()
+            }
+            Expr::BinOp(bin) if matches!(bin.op, Token::Plus | Token::Minus | Token::Times) => {
+                let lhs = self.polynomial(&bin.lhs, ctx)?;
+                let rhs = self.polynomial(&bin.rhs, ctx)?;
+                self.combine(lhs, bin.op.clone(), rhs, ctx)
+            }
+            Expr::BinOp(bin) if bin.op == Token::Division && !contains(&bin.rhs, self.name) => {
+                let lhs = self.polynomial(&bin.lhs, ctx)?;
+                let divisor = T::calculate(&bin.rhs, ctx)?;
+                lhs.into_iter()
+                    .map(|c| c.map(|c| self.operate(c, Token::Division, divisor.clone(), ctx)).transpose())
+                    .collect()
+            }
+            Expr::BinOp(bin) if bin.op == Token::Division => Err(self.unsolvable("the variable is in a divisor")),
+            _ => Err(self.unsolvable("the variable is only in `+ - * /`")),
+        }
+    }
+
+    /// Coefficients of `lhs op rhs`, `op` being `+`, `-` or `*`.
+    fn combine<T: list::Item>(&self, lhs: Polynomial<T>, op: Token, rhs: Polynomial<T>, ctx: &mut Context<T>) -> Result<Polynomial<T>, EvalError> {
+        if op != Token::Times {
+            let len = lhs.len().max(rhs.len());
+            let (mut lhs, mut rhs) = (lhs.into_iter(), rhs.into_iter());
+            return (0..len)
+                .map(|_| match (lhs.next().flatten(), rhs.next().flatten()) {
+                    (Some(l), Some(r)) => self.operate(l, op.clone(), r, ctx).map(Some),
+                    (Some(l), None) => Ok(Some(l)),
+                    (None, Some(r)) if op == Token::Minus => self.negate(r, ctx).map(Some),
+                    (None, r) => Ok(r),
+                })
+                .collect()
+        }
+        // The zero terms don't raise the degree, as `x * 0`
+        let degree = |c: &Polynomial<T>| c.iter().rposition(|c| !is_zero(c)).unwrap_or(0);
+        if degree(&lhs) + degree(&rhs) > MAX_DEGREE {
+            return Err(self.unsolvable("the degree is more than 2"))
+        }
+        let mut product = vec![None; degree(&lhs) + degree(&rhs) + 1];
+        for (i, l) in lhs.iter().enumerate().take(degree(&lhs) + 1) {
+            for (j, r) in rhs.iter().enumerate().take(degree(&rhs) + 1) {
+                if let (Some(l), Some(r)) = (l, r) {
+                    let term = self.operate(l.clone(), Token::Times, r.clone(), ctx)?;
+                    product[i + j] = Some(match product[i + j].take() {
+                        Some(sum) => self.operate(sum, Token::Plus, term, ctx)?,
+                        None => term,
+                    });
+                }
+            }
+        }
+        Ok(product)
+    }
+
+    /// Larger solution of `a x² + b x + c = 0`, `(-b ± √(b² - 4ac)) / 2a`.
+    fn quadratic<T: list::Item>(&self, a: T, b: T, c: T, ctx: &mut Context<T>) -> Result<T, EvalError> {
+        let b2 = self.operate(b.clone(), Token::Times, b.clone(), ctx)?;
+        let ac = self.operate(a.clone(), Token::Times, c, ctx)?;
+        let ac4 = self.operate(T::from_integer(4), Token::Times, ac, ctx)?;
+        let discriminant = self.operate(b2, Token::Minus, ac4, ctx)?;
+        let is_negative = |value: T, ctx: &mut Context<T>| {
+            self.operate(value, Token::Less, T::from_integer(0), ctx).map(|less| less.to_integer() == Some(1))
+        };
+        if is_negative(discriminant.clone(), ctx)? {
+            return Err(self.unsolvable("no real value is a solution"))
+        }
+        // By the `root` of the backend, `%` hides no variable
+        let sqrt = Call::new(root::NAME, vec![Number::new(2), Var::new("%d")]).with_span(self.span.clone());
+        let mut sqrt = ctx.scoped("%d", Binding::Value(discriminant), |ctx| T::calculate(&sqrt, ctx))?;
+        // The larger one is + √ if a is positive
+        if is_negative(a.clone(), ctx)? {
+            sqrt = self.negate(sqrt, ctx)?;
+        }
+        let minus_b = self.negate(b, ctx)?;
+        let numerator = self.operate(minus_b, Token::Plus, sqrt, ctx)?;
+        let denominator = self.operate(T::from_integer(2), Token::Times, a, ctx)?;
+        self.operate(numerator, Token::Division, denominator, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{eval_no_panic, Calculator, Environment, FromAST, Rational, Time};
+    use crate::error::{Error, EvalError};
+
+    #[test]
+    fn test_linear() {
+        assert_eq!(eval_no_panic::<i128>("solve(2*x + 3 == 11, x)"), Ok(4));
+        assert_eq!(eval_no_panic::<Rational>("solve(3*x == 2, x)"), Ok(Rational::new(2, 3).unwrap()));
+        assert_eq!(eval_no_panic::<Rational>("solve(x/4 - (1 - x) == 2*x + 1, x) * 3"), Ok(Rational::from(-8)));
+        assert_eq!(eval_no_panic::<f64>("solve(-(y - 1) == y / 2, y)"), Ok(2.0 / 3.0));
+        // The other variables are constants
+        assert_eq!(eval_no_panic::<i128>("let a = 5 in solve(a * x == 10 + x, x)"), Ok(2));
+        assert_eq!(eval_no_panic::<Time>("solve(2 * t + 10m == 1h, t)"), Ok(eval_no_panic::<Time>("25m").unwrap()));
+    }
+
+    #[test]
+    fn test_quadratic() {
+        assert_eq!(eval_no_panic::<Rational>("solve(x*x == 2*x + 3, x)"), Ok(Rational::from(3)));
+        assert_eq!(eval_no_panic::<Rational>("solve(-(2*x - 1) * (x + 3) == 0, x)"), Ok(Rational::new(1, 2).unwrap()));
+        assert_eq!(eval_no_panic::<i128>("solve((x - 4) * (x + 4) == 0, x)"), Ok(4));
+        assert!((eval_no_panic::<f64>("solve(x*x == 2, x)").unwrap() - 2f64.sqrt()).abs() < 1e-12);
+        // x * 0 doesn't raise the degree
+        assert_eq!(eval_no_panic::<i128>("solve(x*x*(x*0) + x == 1, x)"), Ok(1));
+    }
+
+    #[test]
+    fn test_unsolvable() {
+        let unsolvable = |input: &str, reason, span| {
+            assert_eq!(eval_no_panic::<Rational>(input), Err(Error::Eval(EvalError::Unsolvable{reason, span})));
+        };
+        unsolvable("solve(x + 1 == x, x)", "no value is a solution", 0..20);
+        unsolvable("solve(2*x == x + x, x)", "every value is a solution", 0..22);
+        unsolvable("solve(x*x == -1, x)", "no real value is a solution", 0..19);
+        unsolvable("solve(x*x*x == 8, x)", "the degree is more than 2", 0..20);
+        unsolvable("solve(1/x == 2, x)", "the variable is in a divisor", 0..18);
+        unsolvable("solve(|x| == 2, x)", "the variable is only in `+ - * /`", 0..18);
+        assert!(matches!(eval_no_panic::<i128>("solve(x, x)"), Err(Error::Eval(EvalError::Mismatch{..}))));
+    }
+
+    #[test]
+    fn test_unassigned() {
+        let mut env = Environment::new();
+        env.set("x", Rational::from(7));
+        let calc = Calculator::<Rational>::new();
+        let ast = calc.parse("solve(x + 1 == 3, x) + x").unwrap();
+        assert_eq!(Rational::from_ast_in(ast, &mut calc.context().env(&mut env)), Ok(Rational::from(9)));
+        assert_eq!(env.get("x"), Some(&Rational::from(7)));
+    }
+}
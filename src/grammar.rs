@@ -0,0 +1,202 @@
+//! Metadata of the grammar.
+//!
+//! Describe the operators and functions the calculator accepts, so
+//! help messages, completion and documentation can be generated
+//! from the calculator itself instead of being kept in sync by hand.
+//!
+//! # Example
+//! ```
+//! use wcal::grammar;
+//!
+//! for op in grammar::operators() {
+//!     println!("{}\t{}", op.symbol, op.description);
+//! }
+//! ```
+
+/// How operators of the same precedence group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `- - a` is `-(-a)`
+    Right,
+}
+
+/// Operator of the grammar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operator {
+    pub symbol: &'static str,
+    /// Higher binds tighter.
+    pub precedence: u8,
+    pub associativity: Associativity,
+    /// `1` for prefix operators, `2` for infix operators.
+    pub arity: usize,
+    pub description: &'static str,
+}
+
+/// Function of the grammar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: &'static str,
+    pub arity: usize,
+    pub description: &'static str,
+}
+
+/// Form of a number literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Literal {
+    pub form: &'static str,
+    pub example: &'static str,
+    /// Value of the example.
+    pub value: u128,
+    pub description: &'static str,
+}
+
+/// Name defined by the session rather than assigned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constant {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// Operators accepted by the parser, ordered by precedence.
+pub fn operators() -> Vec<Operator> {
+    vec![
+        Operator{symbol: "||", precedence: 1, associativity: Associativity::Left, arity: 2, description: "Logical or, 1 or 0"},
+        Operator{symbol: "&&", precedence: 2, associativity: Associativity::Left, arity: 2, description: "Logical and, 1 or 0"},
+        Operator{symbol: "<", precedence: 3, associativity: Associativity::Left, arity: 2, description: "Less than, 1 or 0, chains as 1 < x < 10"},
+        Operator{symbol: "<=", precedence: 3, associativity: Associativity::Left, arity: 2, description: "Less than or equal, 1 or 0"},
+        Operator{symbol: ">", precedence: 3, associativity: Associativity::Left, arity: 2, description: "Greater than, 1 or 0"},
+        Operator{symbol: ">=", precedence: 3, associativity: Associativity::Left, arity: 2, description: "Greater than or equal, 1 or 0"},
+        Operator{symbol: "==", precedence: 3, associativity: Associativity::Left, arity: 2, description: "Equal, 1 or 0"},
+        Operator{symbol: "!=", precedence: 3, associativity: Associativity::Left, arity: 2, description: "Not equal, 1 or 0"},
+        Operator{symbol: "+", precedence: 4, associativity: Associativity::Left, arity: 2, description: "Addition"},
+        Operator{symbol: "-", precedence: 4, associativity: Associativity::Left, arity: 2, description: "Subtraction"},
+        Operator{symbol: "*", precedence: 5, associativity: Associativity::Left, arity: 2, description: "Multiplication"},
+        Operator{symbol: "/", precedence: 5, associativity: Associativity::Left, arity: 2, description: "Division"},
+        Operator{symbol: "-", precedence: 6, associativity: Associativity::Right, arity: 1, description: "Negation"},
+        Operator{symbol: "+", precedence: 6, associativity: Associativity::Right, arity: 1, description: "Unary plus, no effect"},
+        Operator{symbol: "!", precedence: 6, associativity: Associativity::Right, arity: 1, description: "Logical not, 1 if zero else 0"},
+        Operator{symbol: "√", precedence: 6, associativity: Associativity::Right, arity: 1, description: "Square root, 3√x is a cube root"},
+    ]
+}
+
+/// Forms of the number literals accepted by the lexer, `_`
+/// separates digits in all of them.
+pub fn literals() -> Vec<Literal> {
+    vec![
+        Literal{form: "123", example: "1_000", value: 1000, description: "Decimal"},
+        Literal{form: "0b101", example: "0b1010", value: 10, description: "Binary"},
+        Literal{form: "0o17", example: "0o17", value: 15, description: "Octal"},
+        Literal{form: "0xff", example: "0xFF", value: 255, description: "Hexadecimal"},
+        Literal{form: "1d2h3m4s", example: "1h30m", value: 5400, description: "Duration, its seconds"},
+    ]
+}
+
+/// Names a `Session` defines, besides the assigned variables.
+pub fn constants() -> Vec<Constant> {
+    vec![
+        Constant{name: "ans", description: "The last result"},
+        Constant{name: "$1", description: "The result of the first expression"},
+    ]
+}
+
+/// Functions accepted by the parser, the aggregates of a list, the
+/// equations, the roots, the functions of the angles of the `f64`
+/// backend, of the matrices of the `Matrix` one, the date of the
+/// `Time` one and the texts of the results.
+pub fn functions() -> Vec<Function> {
+    vec![
+        Function{name: "sum", arity: 1, description: "Sum of the items of a list, 0 if empty"},
+        Function{name: "prod", arity: 1, description: "Product of the items of a list, 1 if empty"},
+        Function{name: "len", arity: 1, description: "Number of items of a list"},
+        Function{name: "mean", arity: 1, description: "Sum of the items of a list divided by their number"},
+        Function{name: "sum", arity: 4, description: "sum(i, from, to, x), sum of x for the integers i from `from` to `to`"},
+        Function{name: "prod", arity: 4, description: "prod(i, from, to, x), product of x for the integers i from `from` to `to`"},
+        Function{name: "solve", arity: 2, description: "solve(lhs == rhs, x), x of the linear or quadratic equation, the larger of two"},
+        Function{name: "root", arity: 2, description: "root(n, x), nth root of x, exact for the perfect powers and rounded otherwise"},
+        Function{name: "sin", arity: 1, description: "Sine of an angle in the angle unit, f64 only"},
+        Function{name: "cos", arity: 1, description: "Cosine of an angle in the angle unit, f64 only"},
+        Function{name: "tan", arity: 1, description: "Tangent of an angle in the angle unit, f64 only"},
+        Function{name: "asin", arity: 1, description: "Angle of a sine in the angle unit, f64 only"},
+        Function{name: "acos", arity: 1, description: "Angle of a cosine in the angle unit, f64 only"},
+        Function{name: "atan", arity: 1, description: "Angle of a tangent in the angle unit, f64 only"},
+        Function{name: "deg", arity: 1, description: "Radians converted to degrees, f64 only"},
+        Function{name: "rad", arity: 1, description: "Degrees converted to radians, f64 only"},
+        Function{name: "transpose", arity: 1, description: "Rows of a matrix as columns, Matrix only"},
+        Function{name: "det", arity: 1, description: "Determinant of a square matrix, Matrix only"},
+        Function{name: "hadamard", arity: 2, description: "hadamard(a, b), product item by item of matrices of the same shape, Matrix only"},
+        Function{name: "hex", arity: 1, description: "Text of an integer in hexadecimal, such as 0xff, only as the result"},
+        Function{name: "oct", arity: 1, description: "Text of an integer in octal, such as 0o17, only as the result"},
+        Function{name: "bin", arity: 1, description: "Text of an integer in binary, such as 0b1010, only as the result"},
+        Function{name: "format", arity: 2, description: "format(x, \"%.3f\"), text of x in a format of %d %x %o %b %f %e %g, only as the result"},
+        #[cfg(feature = "dates")]
+        Function{name: "today", arity: 0, description: "Local date, Time only"},
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser::top_down_parser::parse};
+    use crate::lexer::Token;
+    use crate::parser::ast::*;
+
+    fn binary() -> Vec<Operator> {
+        operators().into_iter().filter(|op| op.arity == 2).collect()
+    }
+
+    #[test]
+    fn test_operators_lex() {
+        for op in operators() {
+            let tokens = lexer::lexer(op.symbol).unwrap();
+            assert_eq!(tokens.len(), 1, "operator: {}", op.symbol);
+        }
+    }
+
+    #[test]
+    fn test_literals_lex() {
+        for literal in literals() {
+            assert_eq!(lexer::lexer(literal.form).unwrap().len(), 1, "literal: {}", literal.form);
+            match lexer::lexer(literal.example).unwrap()[..] {
+                [Token::Number(value) | Token::Duration(value)] => assert_eq!(value, literal.value),
+                ref tokens => panic!("literal: {}, tokens: {:?}", literal.example, tokens),
+            }
+        }
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut session = crate::Session::<i128>::default();
+        session.eval("6 * 7");
+        for constant in constants() {
+            assert_eq!(session.eval(constant.name).result, Ok(42), "constant: {}", constant.name);
+        }
+    }
+
+    /// The metadata must agree with the parser.
+    #[test]
+    fn test_operators_parse() {
+        for a in binary() {
+            for b in binary() {
+                let ast = parse(lexer::spanned(&format!("1{}2{}3", a.symbol, b.symbol)).unwrap()).unwrap();
+                let lhs_first = match ast.root {
+                    Expr::BinOp(BinOp{lhs, ..}) => matches!(*lhs, Expr::BinOp(_)),
+                    _ => unreachable!(),
+                };
+                if a.precedence == b.precedence {
+                    assert_eq!(lhs_first, a.associativity == Associativity::Left);
+                } else {
+                    assert_eq!(lhs_first, a.precedence > b.precedence);
+                }
+            }
+        }
+
+        let neg = operators().into_iter().find(|op| op.arity == 1).unwrap();
+        for op in binary() {
+            let ast = parse(lexer::spanned(&format!("{}1{}2", neg.symbol, op.symbol)).unwrap()).unwrap();
+            assert!(matches!(ast.root, Expr::BinOp(_)));
+            assert!(neg.precedence > op.precedence);
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Ranges of the values of an expression, found without
+//! calculating it.
+//!
+//! Each subtree gets a conservative range of the `i128` values it
+//! may take: the calculation never leaves it. A node whose operands
+//! make it fail whatever their values are, such as `x / 0` or
+//! `0x7FFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF + (1 + |x|)`, is
+//! reported as the error of the calculator at its span. It fails
+//! only if it is calculated, an `if` or a `&&` may skip it.
+//!
+//! The variables, the calls and the lists may be any value, the
+//! names of a `let` take the range of their value.
+//!
+//! # Example
+//! ```
+//! use wcal::{intervals, Calculator};
+//! use wcal::error::EvalError;
+//! use wcal::intervals::Interval;
+//!
+//! let calc = Calculator::<i128>::new();
+//! let analysis = intervals::analyze(&calc.parse("let d = 2 - 2 in (|x| + 1) / d").unwrap());
+//! assert_eq!(analysis.errors, [EvalError::DivisionByZero{span: 17..30}]);
+//! assert_eq!(analysis.range(8..13), Some(Interval::new(0, 0)));
+//! assert_eq!(analysis.range(18..25), Some(Interval::new(1, i128::MAX)));
+//! ```
+use std::convert::TryFrom;
+
+use crate::error::EvalError;
+use crate::lexer::{Span, Token};
+use crate::parser::ast::{Expr, AST};
+
+/// Integers from `min` to `max` included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub min: i128,
+    pub max: i128,
+}
+
+impl Interval {
+    /// Interval from `min` to `max`, swapped if `max` is the
+    /// smaller.
+    pub fn new(min: i128, max: i128) -> Self {
+        Interval{min: min.min(max), max: min.max(max)}
+    }
+
+    /// Any `i128`.
+    pub fn full() -> Self {
+        Interval{min: i128::MIN, max: i128::MAX}
+    }
+
+    fn value(n: i128) -> Self {
+        Interval{min: n, max: n}
+    }
+
+    fn bool() -> Self {
+        Interval{min: 0, max: 1}
+    }
+
+    fn contains(&self, n: i128) -> bool {
+        self.min <= n && n <= self.max
+    }
+
+    fn union(&self, other: &Interval) -> Self {
+        Interval{min: self.min.min(other.min), max: self.max.max(other.max)}
+    }
+}
+
+/// Ranges of the subtrees and the errors found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// Range of each subtree, the outer ones first
+    pub ranges: Vec<(Span, Interval)>,
+    /// Nodes that fail whenever they are calculated, in the order
+    /// of the calculation
+    pub errors: Vec<EvalError>,
+}
+
+impl Analysis {
+    /// Range of the outermost subtree at the span.
+    pub fn range(&self, span: Span) -> Option<Interval> {
+        self.ranges.iter().find(|(at, _)| *at == span).map(|(_, range)| *range)
+    }
+}
+
+/// Ranges and errors of the expression calculated to `i128`.
+pub fn analyze(ast: &AST) -> Analysis {
+    let mut analyzer = Analyzer{scopes: Vec::new(), analysis: Analysis{ranges: Vec::new(), errors: Vec::new()}};
+    analyzer.expr(&ast.root);
+    analyzer.analysis
+}
+
+struct Analyzer {
+    /// Ranges of the names bound by the enclosing `let`, innermost
+    /// last
+    scopes: Vec<(String, Interval)>,
+    analysis: Analysis,
+}
+
+/// Result of an operation on the ends of the ranges.
+enum Bound {
+    Value(i128),
+    /// Past `i128::MAX`
+    Above,
+    /// Past `i128::MIN`
+    Below,
+}
+
+impl Bound {
+    fn of(value: Option<i128>, positive: bool) -> Self {
+        match value {
+            Some(value) => Bound::Value(value),
+            None if positive => Bound::Above,
+            None => Bound::Below,
+        }
+    }
+
+    /// Range between the bounds, `None` if it is all out of `i128`.
+    fn interval(min: Bound, max: Bound) -> Option<Interval> {
+        let min = match min {
+            Bound::Value(min) => min,
+            Bound::Below => i128::MIN,
+            Bound::Above => return None,
+        };
+        let max = match max {
+            Bound::Value(max) => max,
+            Bound::Above => i128::MAX,
+            Bound::Below => return None,
+        };
+        Some(Interval{min, max})
+    }
+}
+
+impl Analyzer {
+    fn expr(&mut self, node: &Expr) -> Interval {
+        // Reserved before the subtrees, so the outer ones come first
+        let at = self.analysis.ranges.len();
+        self.analysis.ranges.push((node.span(), Interval::full()));
+        let range = self.range(node).unwrap_or_else(|error| {
+            self.analysis.errors.push(error);
+            Interval::full()
+        });
+        self.analysis.ranges[at].1 = range;
+        range
+    }
+
+    fn range(&mut self, node: &Expr) -> Result<Interval, EvalError> {
+        let overflow = || EvalError::Overflow{span: node.span()};
+        match node {
+            Expr::Pair(pair) => Ok(self.expr(&pair.expr)),
+            Expr::Num(num) => i128::try_from(num.num).map(Interval::value).map_err(|_| overflow()),
+            Expr::Duration(duration) => i128::try_from(duration.secs).map(Interval::value).map_err(|_| overflow()),
+            Expr::Date(date) => Ok(Interval::value(i128::from(date.days))),
+            // A negative literal, see the calculators
+            Expr::Neg(neg) => match &*neg.expr {
+                Expr::Num(num) if num.num == i128::MIN.unsigned_abs() => {
+                    self.expr(&neg.expr);
+                    Ok(Interval::value(i128::MIN))
+                }
+                expr => {
+                    let range = self.expr(expr);
+                    // Only `-i128::MIN` overflows
+                    match (range.min.checked_neg(), range.max.checked_neg()) {
+                        (min, Some(max)) => Ok(Interval::new(max, min.unwrap_or(i128::MAX))),
+                        (_, None) => Err(overflow()),
+                    }
+                }
+            },
+            Expr::Abs(abs) => {
+                let range = self.expr(&abs.expr);
+                // Only `|i128::MIN|` overflows
+                let max = range.max.checked_abs().ok_or_else(overflow)?;
+                let min = range.min.checked_abs().unwrap_or(i128::MAX);
+                if range.contains(0) {
+                    Ok(Interval::new(0, min.max(max)))
+                } else {
+                    Ok(Interval::new(min, max))
+                }
+            }
+            Expr::Bool(_) => Ok(Interval::bool()),
+            // Only an argument of the calls, which may be any value
+            Expr::Str(_) => Ok(Interval::full()),
+            Expr::Not(not) => {
+                self.expr(&not.expr);
+                Ok(Interval::bool())
+            }
+            Expr::BinOp(bin) if matches!(bin.op, Token::And | Token::Or) || bin.op.is_comparison() => {
+                self.expr(&bin.lhs);
+                self.expr(&bin.rhs);
+                Ok(Interval::bool())
+            }
+            Expr::BinOp(bin) => {
+                let lhs = self.expr(&bin.lhs);
+                let rhs = self.expr(&bin.rhs);
+                binary(&bin.op, lhs, rhs, &node.span())
+            }
+            Expr::If(node) => {
+                let cond = self.expr(&node.cond);
+                let then = self.expr(&node.then);
+                let otherwise = self.expr(&node.otherwise);
+                Ok(match (cond.contains(0), cond == Interval::value(0)) {
+                    (_, true) => otherwise,
+                    (false, _) => then,
+                    (true, false) => then.union(&otherwise),
+                })
+            }
+            Expr::Let(node) => {
+                let value = self.expr(&node.value);
+                self.scopes.push((node.name.clone(), value));
+                let body = self.expr(&node.body);
+                self.scopes.pop();
+                Ok(body)
+            }
+            Expr::Var(var) => Ok(self.scopes.iter().rev()
+                .find(|(name, _)| *name == var.name)
+                .map_or(Interval::full(), |(_, range)| *range)),
+            Expr::Assign(assign) => Ok(self.expr(&assign.expr)),
+            Expr::Call(call) => {
+                for arg in call.args.iter() {
+                    self.expr(arg);
+                }
+                Ok(Interval::full())
+            }
+            Expr::List(list) => {
+                for item in list.items.iter() {
+                    self.expr(item);
+                }
+                Ok(Interval::full())
+            }
+            Expr::Range(range) => {
+                self.expr(&range.start);
+                self.expr(&range.end);
+                Ok(Interval::full())
+            }
+            Expr::Index(index) => {
+                self.expr(&index.list);
+                self.expr(&index.index);
+                Ok(Interval::full())
+            }
+        }
+    }
+}
+
+/// Range of `lhs op rhs`, the error if it fails for all operands.
+fn binary(op: &Token, lhs: Interval, rhs: Interval, span: &Span) -> Result<Interval, EvalError> {
+    let overflow = || EvalError::Overflow{span: span.clone()};
+    match op {
+        Token::Plus => Bound::interval(
+            Bound::of(lhs.min.checked_add(rhs.min), lhs.min > 0),
+            Bound::of(lhs.max.checked_add(rhs.max), lhs.max > 0),
+        ).ok_or_else(overflow),
+        Token::Minus => Bound::interval(
+            Bound::of(lhs.min.checked_sub(rhs.max), lhs.min >= 0),
+            Bound::of(lhs.max.checked_sub(rhs.min), lhs.max >= 0),
+        ).ok_or_else(overflow),
+        Token::Times => {
+            let corners = [(lhs.min, rhs.min), (lhs.min, rhs.max), (lhs.max, rhs.min), (lhs.max, rhs.max)];
+            let products: Vec<_> = corners.iter()
+                .map(|(a, b)| a.checked_mul(*b).ok_or((*a > 0) == (*b > 0)))
+                .collect();
+            // Without 0 in the ranges, all the products overflow if
+            // the smallest one does
+            if products.iter().all(Result::is_err) && !lhs.contains(0) && !rhs.contains(0) {
+                return Err(overflow())
+            }
+            let clamp = |product: &Result<i128, bool>| match product {
+                Ok(product) => *product,
+                Err(true) => i128::MAX,
+                Err(false) => i128::MIN,
+            };
+            let min = products.iter().map(clamp).min().unwrap_or(i128::MIN);
+            let max = products.iter().map(clamp).max().unwrap_or(i128::MAX);
+            Ok(Interval{min, max})
+        }
+        Token::Division => {
+            if rhs == Interval::value(0) {
+                return Err(EvalError::DivisionByZero{span: span.clone()})
+            }
+            if lhs == Interval::value(i128::MIN) && rhs == Interval::value(-1) {
+                return Err(overflow())
+            }
+            if rhs.contains(0) {
+                // The quotient is at most the dividend in magnitude
+                let max = lhs.min.checked_abs().unwrap_or(i128::MAX).max(lhs.max.abs());
+                return Ok(Interval::new(-max, max))
+            }
+            let corners = [(lhs.min, rhs.min), (lhs.min, rhs.max), (lhs.max, rhs.min), (lhs.max, rhs.max)];
+            let quotients = corners.iter().map(|(a, b)| a.checked_div(*b).unwrap_or(i128::MAX));
+            let (min, max) = quotients.fold((i128::MAX, i128::MIN), |(min, max), q| (min.min(q), max.max(q)));
+            Ok(Interval{min, max})
+        }
+        _ => Ok(Interval::full()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval_no_panic, lexer, Error};
+    use crate::parser::top_down_parser;
+
+    fn analysis(input: &str) -> Analysis {
+        analyze(&lexer::spanned(input).and_then(top_down_parser::parse).unwrap())
+    }
+
+    fn root(input: &str) -> Interval {
+        analysis(input).ranges[0].1
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(root("1 + 2 * 3"), Interval::value(7));
+        assert_eq!(root("-(|x| + 1)"), Interval::new(-i128::MAX, -1));
+        assert_eq!(root("let a = if c then 2 else -3 in a * a"), Interval::new(-6, 9));
+        assert_eq!(root("(|x| + 10) / -5"), Interval::new(i128::MIN / 5, -2));
+        assert_eq!(root("10 / (y && z)"), Interval::new(-10, 10));
+        assert_eq!(root("if 1 then 1 else x"), Interval::value(1));
+        assert_eq!(root("f(1) - 1"), Interval::new(i128::MIN, i128::MAX - 1));
+        assert_eq!(root("-170141183460469231731687303715884105728"), Interval::value(i128::MIN));
+    }
+
+    #[test]
+    fn test_errors() {
+        let max = "170141183460469231731687303715884105727";
+        let inputs = [
+            "x / (3 - 3)".to_string(),
+            format!("{} + (1 + |x|)", max),
+            format!("-{} - 2", max),
+            format!("(|x| + 2) * ({} / 2 + 1)", max),
+            format!("|-{} - 1|", max),
+            format!("let m = -{} - 1 in m / -1", max),
+        ];
+        for input in inputs.iter() {
+            let errors = analysis(input).errors;
+            assert_eq!(errors.len(), 1, "input: {}", input);
+            // The calculation fails the same way with any value
+            let calculated = eval_no_panic::<i128>(&input.replace('x', "7"));
+            assert_eq!(calculated, Err(Error::Eval(errors[0].clone())), "input: {}", input);
+        }
+        // Overflows for some values only
+        for input in ["x + 1", "x * x", "|x|", "-x", "x / y"].iter() {
+            assert_eq!(analysis(input).errors, [], "input: {}", input);
+        }
+    }
+}
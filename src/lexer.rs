@@ -6,6 +6,30 @@
 //! Division: /
 //! LP: (
 //! RP: )
+//! Comma: ,
+//! LB: [
+//! RB: ]
+//! DotDot: ..
+//! Bar: |
+//! And: &&
+//! Or: ||
+//! Not: !
+//! Less: <
+//! LessEq: <=
+//! Greater: >
+//! GreaterEq: >=
+//! Equal: ==
+//! NotEqual: !=
+//! Root: √
+//! Assign: =
+//! Bool: true | false
+//! Str: "[^"\n#]*"
+//! If: if
+//! Then: then
+//! Else: else
+//! Let: let
+//! In: in
+//! Ident: [a-z A-Z _][a-z A-Z 0-9 _]* | $[0-9]+
 //! Number :
 //!   DEC_LITERAL | BIN_LITERAL | OCT_LITERAL | HEX_LITERAL
 //! DEC_LITERAL :
@@ -16,112 +40,634 @@
 //!   0o(OCT_DIGIT|_)*
 //! HEX_LITERAL :
 //!   0x(HEX_DIGIT|_)*
+//! Duration :
+//!   (DEC_LITERAL UNIT)+
+//! UNIT : d | h | m | s
+//! Date :
+//!   [0-9]{4} - [0-9]{2} - [0-9]{2}
 //!
 //! BIN_DIGIT : [0-1]
 //! OCT_DIGIT : [0-7]
-//! DEC_DIGIT : [0-9]
+//! DEC_DIGIT : [0-9 ０-９]
 //! HEX_DIGIT : [0-9 a-f A-F]
 //! ```
-use std::u64;
+//!
+//! The full-width digits `０-９` of the East Asian input methods are
+//! decimal digits, and so are the Arabic-Indic digits `٠-٩` and
+//! `۰-۹` with the `arabic-digits` feature.
+//!
+//! A date such as `2024-05-01` is only read with the `dates`
+//! feature and `Options::dates`, from its tokens written together.
+use std::cmp::Ordering;
+use std::collections::VecDeque;
 use std::fmt;
+use std::ops::Range;
+
+use crate::error::{Error, LexError};
+
+#[cfg(feature = "logos")]
+use logos::{Logos, Lexer};
 
-use logos::{Logos, Lexer, Span};
+#[cfg(any(not(feature = "logos"), test))]
+mod scanner;
 
-static mut ERROR_MSG: String = String::new();
+/// Byte range of a token in the input.
+pub type Span = Range<usize>;
+
+/// Tokens reserved by [`spanned`] up front. A token is at least a
+/// byte, so the inputs up to this length are never grown.
+const RESERVE: usize = 64;
+
+/// The ASCII digit of a decimal digit, such as `1` of `１`.
+fn ascii_digit(c: char) -> Option<char> {
+    let zero = match c {
+        '0'..='9' => '0',
+        '０'..='９' => '０',
+        #[cfg(feature = "arabic-digits")]
+        '٠'..='٩' => '٠',
+        #[cfg(feature = "arabic-digits")]
+        '۰'..='۹' => '۰',
+        _ => return None,
+    };
+    char::from_digit(c as u32 - zero as u32, 10)
+}
 
-fn parse_int(input: &str, radix: u32, span: Span, raw: &str) -> Option<u64> {
-    let input = input.replace("_", "");
-    if input.len() == 0 {
-        return Some(0)
+fn parse_int(input: &str, radix: u32, span: Span, raw: &str) -> Result<u128, LexError> {
+    let input: String = input.chars()
+        .filter(|c| *c != '_')
+        .map(|c| ascii_digit(c).unwrap_or(c))
+        .collect();
+    if input.is_empty() {
+        return Ok(0)
     }
-    match u64::from_str_radix(input.as_str(), radix) {
-        Ok(num) => Some(num),
-        Err(err) => {
-            unsafe{
-                ERROR_MSG = format!("Parse int failed: {}\nNear {:?}: {}", err, span, raw);
+    u128::from_str_radix(input.as_str(), radix)
+        .map_err(|err| LexError::ParseInt{raw: raw.to_string(), reason: err.to_string(), span})
+}
+
+/// Seconds of a duration such as `1h30m`, the sum of its parts.
+fn parse_duration(input: &str, span: Span) -> Result<u128, LexError> {
+    let overflow = || LexError::ParseInt{raw: input.to_string(), reason: "duration too large".to_string(), span: span.clone()};
+    let mut secs: u128 = 0;
+    let mut digits = String::new();
+    for c in input.chars() {
+        let unit = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => {
+                digits.push(c);
+                continue
             }
+        };
+        let num = parse_int(&digits, 10, span.clone(), input).map_err(|_| overflow())?;
+        secs = num.checked_mul(unit).and_then(|part| secs.checked_add(part)).ok_or_else(overflow)?;
+        digits.clear();
+    }
+    Ok(secs)
+}
+
+#[cfg(feature = "logos")]
+fn logos_duration(lex: &mut Lexer<Token>) -> Option<u128> {
+    match parse_duration(lex.slice(), lex.span()) {
+        Ok(secs) => Some(secs),
+        Err(err) => {
+            lex.extras = Some(err);
             None
         }
     }
 }
 
-fn bin_int(lex: &mut Lexer<Token>) -> Option<u64> {
+#[cfg(feature = "logos")]
+fn logos_int(lex: &mut Lexer<Token>, prefix: usize, radix: u32) -> Option<u128> {
     let slice = lex.slice();
-    parse_int(&slice[2..], 2, lex.span(), slice)
+    match parse_int(&slice[prefix..], radix, lex.span(), slice) {
+        Ok(num) => Some(num),
+        Err(err) => {
+            lex.extras = Some(err);
+            None
+        }
+    }
 }
 
-fn oct_int(lex: &mut Lexer<Token>) -> Option<u64> {
-    let slice = lex.slice();
-    parse_int(&slice[2..], 8, lex.span(), slice)
+#[cfg(feature = "logos")]
+fn bin_int(lex: &mut Lexer<Token>) -> Option<u128> {
+    logos_int(lex, 2, 2)
 }
 
-fn dec_int(lex: &mut Lexer<Token>) -> Option<u64> {
-    let slice = lex.slice();
-    parse_int(slice, 10, lex.span(), slice)
+#[cfg(feature = "logos")]
+fn oct_int(lex: &mut Lexer<Token>) -> Option<u128> {
+    logos_int(lex, 2, 8)
 }
 
-fn hex_int(lex: &mut Lexer<Token>) -> Option<u64> {
-    let slice = lex.slice();
-    parse_int(&slice[2..], 16, lex.span(), slice)
+#[cfg(feature = "logos")]
+fn dec_int(lex: &mut Lexer<Token>) -> Option<u128> {
+    logos_int(lex, 0, 10)
+}
+
+#[cfg(feature = "logos")]
+fn hex_int(lex: &mut Lexer<Token>) -> Option<u128> {
+    logos_int(lex, 2, 16)
 }
 
 /// Token of the calculator lexical structure.
-#[derive(Logos, Debug, PartialEq, Clone, Copy)]
+///
+/// Tokens are produced by `logos` by default. Without the
+/// `logos` feature a hand-written scanner produces the same
+/// tokens.
+///
+/// The error of a number literal is kept in the lexer's extras
+/// rather than in a global, so lexing is safe from many threads.
+#[cfg_attr(feature = "logos", derive(Logos))]
+#[cfg_attr(feature = "logos", logos(extras = Option<LexError>))]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Token {
-    #[error]
-    #[regex(r"[ \t]+", logos::skip)]
+    #[cfg_attr(feature = "logos", error)]
+    #[cfg_attr(feature = "logos", regex(r"[ \t]+", logos::skip))]
     Error,
 
     /// Lexer will stop while meet the `NewLine`
-    #[regex(r"[\n\f]+")]
+    #[cfg_attr(feature = "logos", regex(r"[\n\f]+"))]
     NewLine,
 
-    #[token("+")]
+    #[cfg_attr(feature = "logos", token("+"))]
     Plus,
 
-    #[token("-")]
+    #[cfg_attr(feature = "logos", token("-"))]
     Minus,
 
-    #[token("*")]
+    #[cfg_attr(feature = "logos", token("*"))]
     Times,
 
-    #[token("/")]
+    #[cfg_attr(feature = "logos", token("/"))]
     Division,
 
-    #[token("(")]
+    #[cfg_attr(feature = "logos", token("("))]
     LP,
 
-    #[token(")")]
+    #[cfg_attr(feature = "logos", token(")"))]
     RP,
 
-    /// Number contains `u64` variable, raise an error if overflow
-    #[regex(r"[0-9][0-9_]*", dec_int)]
-    #[regex(r"0b[0-1_]*", bin_int)]
-    #[regex(r"0o[0-7_]*", oct_int)]
-    #[regex(r"0x[0-9a-fA-F_]*", hex_int)]
-    Number(u64),
+    #[cfg_attr(feature = "logos", token(","))]
+    Comma,
+
+    #[cfg_attr(feature = "logos", token("["))]
+    LB,
+
+    #[cfg_attr(feature = "logos", token("]"))]
+    RB,
+
+    /// Between the bounds of a range, such as `1..10`
+    #[cfg_attr(feature = "logos", token(".."))]
+    DotDot,
+
+    /// Either side of an absolute value, such as `|x|`
+    #[cfg_attr(feature = "logos", token("|"))]
+    Bar,
+
+    /// `&&`
+    #[cfg_attr(feature = "logos", token("&&"))]
+    And,
+
+    /// `||`, or two bars of absolute values, such as `||x| - 1|`
+    #[cfg_attr(feature = "logos", token("||"))]
+    Or,
+
+    /// `!`
+    #[cfg_attr(feature = "logos", token("!"))]
+    Not,
+
+    /// `√`, the square root of `√x`, or the root of the degree
+    /// written before it such as `3√x`
+    #[cfg_attr(feature = "logos", token("√"))]
+    Root,
+
+    #[cfg_attr(feature = "logos", token("<"))]
+    Less,
+
+    #[cfg_attr(feature = "logos", token("<="))]
+    LessEq,
+
+    #[cfg_attr(feature = "logos", token(">"))]
+    Greater,
+
+    #[cfg_attr(feature = "logos", token(">="))]
+    GreaterEq,
+
+    #[cfg_attr(feature = "logos", token("=="))]
+    Equal,
+
+    #[cfg_attr(feature = "logos", token("!="))]
+    NotEqual,
+
+    #[cfg_attr(feature = "logos", token("="))]
+    Assign,
+
+    /// Name of a function or a variable, `$1` refers to the
+    /// first result of a session
+    #[cfg_attr(feature = "logos", regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_string()))]
+    #[cfg_attr(feature = "logos", regex(r"\$[0-9]+", |lex| lex.slice().to_string()))]
+    Ident(String),
+
+    /// `true` or `false`, calculated as `1` or `0`
+    #[cfg_attr(feature = "logos", token("true", |_| true))]
+    #[cfg_attr(feature = "logos", token("false", |_| false))]
+    Bool(bool),
+
+    /// Text between double quotes without them, such as the format
+    /// of `format(x, "%.3f")`. It can't hold a `"`, nor a `#` which
+    /// starts a comment
+    #[cfg_attr(feature = "logos", regex(r#""[^"\n#]*""#, |lex| { let slice = lex.slice(); slice[1..slice.len() - 1].to_string() }))]
+    Str(String),
+
+    #[cfg_attr(feature = "logos", token("if"))]
+    If,
+
+    #[cfg_attr(feature = "logos", token("then"))]
+    Then,
+
+    #[cfg_attr(feature = "logos", token("else"))]
+    Else,
+
+    #[cfg_attr(feature = "logos", token("let"))]
+    Let,
+
+    #[cfg_attr(feature = "logos", token("in"))]
+    In,
+
+    /// Number contains `u128` variable, raise an error if overflow.
+    /// Each calculator checks that it fits its result
+    #[cfg_attr(all(feature = "logos", not(feature = "arabic-digits")), regex(r"[0-9０-９][0-9０-９_]*", dec_int))]
+    #[cfg_attr(all(feature = "logos", feature = "arabic-digits"), regex(r"[0-9０-９٠-٩۰-۹][0-9０-９٠-٩۰-۹_]*", dec_int))]
+    #[cfg_attr(feature = "logos", regex(r"0b[0-1_]*", bin_int))]
+    #[cfg_attr(feature = "logos", regex(r"0o[0-7_]*", oct_int))]
+    #[cfg_attr(feature = "logos", regex(r"0x[0-9a-fA-F_]*", hex_int))]
+    Number(u128),
+
+    /// Duration in seconds, such as `1h30m` for 5400
+    #[cfg_attr(all(feature = "logos", not(feature = "arabic-digits")), regex(r"([0-9０-９][0-9０-９_]*[dhms])+", logos_duration))]
+    #[cfg_attr(all(feature = "logos", feature = "arabic-digits"), regex(r"([0-9０-９٠-٩۰-۹][0-9０-９٠-٩۰-۹_]*[dhms])+", logos_duration))]
+    Duration(u128),
+
+    /// Date in days since 1970-01-01, such as `2024-05-01` for
+    /// 19844, see `Options::dates`
+    Date(i64),
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
+        match self {
             Token::Plus => write!(f, "+"),
             Token::Minus => write!(f, "-"),
             Token::Times => write!(f, "*"),
             Token::Division => write!(f, "/"),
             Token::LP => write!(f, "("),
             Token::RP => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+            Token::LB => write!(f, "["),
+            Token::RB => write!(f, "]"),
+            Token::DotDot => write!(f, ".."),
+            Token::Bar => write!(f, "|"),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::Root => write!(f, "√"),
+            Token::Less => write!(f, "<"),
+            Token::LessEq => write!(f, "<="),
+            Token::Greater => write!(f, ">"),
+            Token::GreaterEq => write!(f, ">="),
+            Token::Equal => write!(f, "=="),
+            Token::NotEqual => write!(f, "!="),
+            Token::Assign => write!(f, "="),
+            Token::Ident(name) => write!(f, "{}", name),
             Token::Number(num) => write!(f, "{}", num),
+            Token::Duration(secs) => write!(f, "{}", crate::duration::spelling(*secs)),
+            Token::Date(days) => write!(f, "{}", crate::date::spelling(*days)),
+            Token::Bool(value) => write!(f, "{}", value),
+            Token::Str(text) => write!(f, "\"{}\"", text),
+            Token::If => write!(f, "if"),
+            Token::Then => write!(f, "then"),
+            Token::Else => write!(f, "else"),
+            Token::Let => write!(f, "let"),
+            Token::In => write!(f, "in"),
             _ => write!(f, "{:?}", self)
         }
     }
 }
 
+impl Token {
+    /// Category of the token for highlighting.
+    pub fn category(&self) -> TokenCategory {
+        match self {
+            Token::Plus | Token::Minus | Token::Times | Token::Division | Token::Assign
+                | Token::And | Token::Or | Token::Not | Token::Root | Token::DotDot => TokenCategory::Operator,
+            Token::Less | Token::LessEq | Token::Greater | Token::GreaterEq | Token::Equal | Token::NotEqual => TokenCategory::Operator,
+            Token::LP | Token::RP | Token::LB | Token::RB | Token::Bar => TokenCategory::Paren,
+            Token::Comma => TokenCategory::Separator,
+            Token::Ident(_) => TokenCategory::Identifier,
+            Token::Str(_) => TokenCategory::Str,
+            Token::Number(_) | Token::Duration(_) | Token::Date(_) | Token::Bool(_) => TokenCategory::Number,
+            Token::If | Token::Then | Token::Else | Token::Let | Token::In => TokenCategory::Keyword,
+            Token::Error | Token::NewLine => TokenCategory::Error,
+        }
+    }
+
+    /// Whether the token is one of `<`, `<=`, `>`, `>=`, `==` and
+    /// `!=`.
+    pub fn is_comparison(&self) -> bool {
+        matches!(self, Token::Less | Token::LessEq | Token::Greater | Token::GreaterEq | Token::Equal | Token::NotEqual)
+    }
+
+    /// Whether the comparison holds for operands of the ordering,
+    /// `None` if they are unordered such as a `NaN`, for which only
+    /// `!=` holds. `false` if the token isn't a comparison.
+    pub fn compares(&self, ordering: Option<Ordering>) -> bool {
+        use Ordering::*;
+
+        match (self, ordering) {
+            (Token::NotEqual, None) => true,
+            (_, None) => false,
+            (Token::Less, Some(ordering)) => ordering == Less,
+            (Token::LessEq, Some(ordering)) => ordering != Greater,
+            (Token::Greater, Some(ordering)) => ordering == Greater,
+            (Token::GreaterEq, Some(ordering)) => ordering != Less,
+            (Token::Equal, Some(ordering)) => ordering == Equal,
+            (Token::NotEqual, Some(ordering)) => ordering != Equal,
+            _ => false,
+        }
+    }
+}
+
+/// Category of a token, see [`highlight`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenCategory {
+    Number,
+    Operator,
+    Paren,
+    /// `,` between the arguments of a call
+    Separator,
+    Identifier,
+    /// `"%.3f"`
+    Str,
+    /// `if`, `then`, `else`, `let` and `in`
+    Keyword,
+    /// Invalid character or number literal
+    Error,
+}
+
+#[cfg(feature = "logos")]
+type Raw<'a> = Lexer<'a, Token>;
+#[cfg(not(feature = "logos"))]
+type Raw<'a> = scanner::Scanner<'a>;
+
+fn raw(input: &str) -> Raw<'_> {
+    #[cfg(feature = "logos")]
+    return Token::lexer(input);
+    #[cfg(not(feature = "logos"))]
+    return scanner::Scanner::new(input);
+}
+
+/// Options of the lexer, see [`tokens_with`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Read `1,234,567` as one number, its `,` next to the digits,
+    /// except inside the `()` of a call and the `[]` of a list whose
+    /// `,` separate the items. The first group is 1 to 3 digits, the
+    /// others 3 digits.
+    pub thousands: bool,
+    /// Read `2024-05-01` as a date, its `-` next to the digits,
+    /// rather than `2024 - 5 - 1`. The year is 4 digits, the month
+    /// and the day 2 digits.
+    #[cfg(feature = "dates")]
+    pub dates: bool,
+}
+
+/// Iterator of spanned tokens, created by [`tokens`].
+///
+/// Stop after an error or while meet `\n` or `\f`.
+pub struct Tokens<'a> {
+    lex: Raw<'a>,
+    done: bool,
+    input: &'a str,
+    options: Options,
+    /// Tokens read ahead while looking for the groups of a number
+    pending: VecDeque<Result<(Token, Span), Error>>,
+    /// Whether the `,` separate the items in each open bracket
+    brackets: Vec<bool>,
+    /// Whether the last token is a name, calling the next `(`
+    after_ident: bool,
+}
+
+impl<'a> Tokens<'a> {
+    fn pull(&mut self) -> Option<Result<(Token, Span), Error>> {
+        self.pending.pop_front().or_else(|| self.lex_next())
+    }
+
+    /// The date of the number, or the number with its groups, or
+    /// the number alone, as the options ask.
+    fn number(&mut self, num: u128, span: Span) -> Result<(Token, Span), Error> {
+        #[cfg(feature = "dates")]
+        if self.options.dates {
+            if let Some(date) = self.date(&span)? {
+                return Ok(date)
+            }
+        }
+        if self.options.thousands && self.brackets.last() != Some(&true) {
+            return self.group(num, span)
+        }
+        Ok((Token::Number(num), span))
+    }
+
+    /// The date of the year and the `-MM-DD` right after it, `None`
+    /// if they don't follow.
+    #[cfg(feature = "dates")]
+    fn date(&mut self, year: &Span) -> Result<Option<(Token, Span)>, Error> {
+        let input = self.input;
+        let digits = |span: &Span, len: usize| span.len() == len && input[span.clone()].bytes().all(|b| b.is_ascii_digit());
+        if !digits(year, 4) {
+            return Ok(None)
+        }
+        // `-`, the month, `-` and the day
+        let mut parts = Vec::new();
+        let mut end = year.end;
+        for i in 0..4 {
+            let part = self.pull();
+            let follows = match &part {
+                Some(Ok((Token::Minus, span))) => i % 2 == 0 && span.start == end,
+                Some(Ok((Token::Number(_), span))) => i % 2 == 1 && span.start == end && digits(span, 2),
+                _ => false,
+            };
+            if let (true, Some(Ok((_, span)))) = (follows, &part) {
+                end = span.end;
+            }
+            parts.extend(part);
+            if !follows {
+                for part in parts.into_iter().rev() {
+                    self.pending.push_front(part);
+                }
+                return Ok(None)
+            }
+        }
+        let span = year.start..end;
+        let raw = &input[span.clone()];
+        match crate::date::parse(raw) {
+            Some(days) => Ok(Some((Token::Date(days), span))),
+            None => Err(LexError::InvalidDate{raw: raw.to_string(), span}.into()),
+        }
+    }
+
+    /// The number with the `,` and the groups after it.
+    fn group(&mut self, num: u128, span: Span) -> Result<(Token, Span), Error> {
+        let mut end = span.end;
+        while let Some(comma) = self.pull() {
+            let group = match &comma {
+                Ok((Token::Comma, comma_span)) if comma_span.start == end => self.pull(),
+                _ => {
+                    self.pending.push_front(comma);
+                    break
+                }
+            };
+            match group {
+                Some(Ok((Token::Number(_), group))) if group.start == end + 1 => end = group.end,
+                group => {
+                    if let Some(group) = group {
+                        self.pending.push_front(group);
+                    }
+                    self.pending.push_front(comma);
+                    break
+                }
+            }
+        }
+        if end == span.end {
+            return Ok((Token::Number(num), span))
+        }
+        let span = span.start..end;
+        let raw = &self.input[span.clone()];
+        let digits = |group: &str, len: Range<usize>| {
+            len.contains(&group.chars().count()) && group.chars().all(|c| ascii_digit(c).is_some())
+        };
+        let mut groups = raw.split(',');
+        let first = groups.next().unwrap_or_default();
+        if !digits(first, 1..4) || !groups.all(|group| digits(group, 3..4)) {
+            return Err(LexError::Grouping{raw: raw.to_string(), span}.into())
+        }
+        Ok((Token::Number(parse_int(&raw.replace(',', ""), 10, span.clone(), raw)?), span))
+    }
+
+    fn lex_next(&mut self) -> Option<Result<(Token, Span), Error>> {
+        if self.done {
+            return None
+        }
+        match self.lex.next()? {
+            Token::Error => {
+                self.done = true;
+                if let Some(err) = self.lex.extras.take() {
+                    return Some(Err(err.into()))
+                }
+                let ch = self.lex.slice().chars().next().unwrap_or_default();
+                Some(Err(LexError::InvalidCharacter{ch, span: self.lex.span()}.into()))
+            }
+            Token::NewLine => {
+                self.done = true;
+                None
+            }
+            token => Some(Ok((token, self.lex.span())))
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<(Token, Span), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (token, span) = match self.pull()? {
+            Ok((Token::Number(num), span)) => match self.number(num, span) {
+                Ok(token) => token,
+                Err(err) => return Some(Err(err)),
+            },
+            Ok(token) => token,
+            Err(err) => return Some(Err(err)),
+        };
+        match token {
+            Token::LP => self.brackets.push(self.after_ident),
+            Token::LB => self.brackets.push(true),
+            Token::RP | Token::RB => {
+                self.brackets.pop();
+            }
+            _ => (),
+        }
+        self.after_ident = matches!(token, Token::Ident(_));
+        Some(Ok((token, span)))
+    }
+}
+
+/// Parse string into spanned tokens lazily, without collecting
+/// them. Only parse one line input.
+///
+/// # Example
+/// ```
+/// use wcal::lexer::{tokens, Token};
+///
+/// let mut iter = tokens("1+$");
+/// assert_eq!(iter.next(), Some(Ok((Token::Number(1), 0..1))));
+/// assert_eq!(iter.next(), Some(Ok((Token::Plus, 1..2))));
+/// assert!(matches!(iter.next(), Some(Err(_))));
+/// assert_eq!(iter.next(), None);
+/// ```
+pub fn tokens(input: &str) -> Tokens<'_> {
+    tokens_with(input, Options::default())
+}
+
+/// Parse string into spanned tokens lazily with the options.
+///
+/// # Example
+/// ```
+/// use wcal::lexer::{tokens_with, Options, Token};
+///
+/// let options = Options{thousands: true, ..Options::default()};
+/// let tokens: Vec<_> = tokens_with("1,234 + max(1,234)", options).map(|token| token.unwrap().0).collect();
+/// assert_eq!(tokens[0], Token::Number(1234));
+/// assert_eq!(tokens[4..7], [Token::Number(1), Token::Comma, Token::Number(234)]);
+/// assert!(tokens_with("12,34", options).next().unwrap().is_err());
+/// ```
+pub fn tokens_with(input: &str, options: Options) -> Tokens<'_> {
+    Tokens{lex: raw(input), done: false, input, options, pending: VecDeque::new(), brackets: Vec::new(), after_ident: false}
+}
+
+/// Categorize every token of the input for highlighting.
+///
+/// Unlike [`tokens`], keep going after errors and new lines, so
+/// the whole input is colored with the same rules as the lexer.
+///
+/// # Example
+/// ```
+/// use wcal::{highlight, TokenCategory};
+///
+/// assert_eq!(highlight("max(1, $)"), [
+///     (0..3, TokenCategory::Identifier),
+///     (3..4, TokenCategory::Paren),
+///     (4..5, TokenCategory::Number),
+///     (5..6, TokenCategory::Separator),
+///     (7..8, TokenCategory::Error),
+///     (8..9, TokenCategory::Paren),
+/// ]);
+/// ```
+pub fn highlight(input: &str) -> Vec<(Span, TokenCategory)> {
+    let mut lex = raw(input);
+    let mut res = Vec::new();
+    while let Some(token) = lex.next() {
+        if token != Token::NewLine {
+            res.push((lex.span(), token.category()));
+        }
+    }
+    res
+}
+
 /// Parse string into tokens. Only parse one line input.
 ///
 /// Parse will stop while meet `\n` or `\f`.
 ///
-/// Return `Err(String)` while input is invalid.
+/// Return `Err(Error)` while input is invalid.
 /// # Example
 /// ```
 /// use wcal::lexer::{lexer, Token};
@@ -142,27 +688,110 @@ impl fmt::Display for Token {
 ///     Token::Number(0)
 /// ]);
 /// ```
-pub fn lexer(input: &str) -> Result<Vec<Token>, String> {
-    let mut lex = Token::lexer(input);
-    let mut tokens : Vec<Token> = Vec::new();
-    while let Some(token) = lex.next() {
-        match token {
-            Token::Error => {
-                if lex.slice().len() == 1 {
-                    return Err(format!("Invalid character near {:?}: {}", lex.span(), lex.slice()));
-                } else {
-                    unsafe {
-                        let err: String = ERROR_MSG.clone();
-                        ERROR_MSG = String::new();
-                        return Err(err)
-                    }
+pub fn lexer(input: &str) -> Result<Vec<Token>, Error> {
+    let mut res = Vec::with_capacity(input.len().min(RESERVE));
+    for token in tokens(input) {
+        res.push(token?.0);
+    }
+    Ok(res)
+}
+
+/// Parse string into tokens with their spans, which is the
+/// input of parsers.
+///
+/// # Example
+/// ```
+/// use wcal::lexer::{spanned, Token};
+///
+/// let tokens = spanned("12 * 3").unwrap();
+/// assert_eq!(tokens, [
+///     (Token::Number(12), 0..2),
+///     (Token::Times, 3..4),
+///     (Token::Number(3), 5..6),
+/// ]);
+/// ```
+pub fn spanned(input: &str) -> Result<Vec<(Token, Span)>, Error> {
+    let mut res = Vec::with_capacity(input.len().min(RESERVE));
+    for token in tokens(input) {
+        res.push(token?);
+    }
+    Ok(res)
+}
+
+/// Token of [`lossless`], with the text before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lexeme<'a> {
+    pub token: Token,
+    pub span: Span,
+    /// Whitespace, new lines and comments before the token
+    pub trivia: &'a str,
+    /// Spelling of the token, such as `0x_1A` for `Token::Number(26)`
+    pub text: &'a str,
+}
+
+/// Tokens of the whole input with their trivia, displayed back as
+/// the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lossless<'a> {
+    pub lexemes: Vec<Lexeme<'a>>,
+    /// Trivia after the last token
+    pub trailing: &'a str,
+}
+
+impl fmt::Display for Lossless<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for lexeme in self.lexemes.iter() {
+            write!(f, "{}{}", lexeme.trivia, lexeme.text)?;
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+/// Parse every line of the input into tokens, keeping the text
+/// between them, so the input is rebuilt exactly from them. `#`
+/// starts a comment up to the end of the line, like in the files of
+/// the binary.
+///
+/// # Example
+/// ```
+/// use wcal::lexer::{lossless, Token};
+///
+/// let source = "x = 0x_1A  # hex\n\t+ 1";
+/// let lossless = lossless(source).unwrap();
+/// assert_eq!(lossless.lexemes[2].token, Token::Number(26));
+/// assert_eq!(lossless.lexemes[2].text, "0x_1A");
+/// assert_eq!(lossless.lexemes[3].trivia, "  # hex\n\t");
+/// assert_eq!(lossless.to_string(), source);
+/// ```
+pub fn lossless(input: &str) -> Result<Lossless<'_>, Error> {
+    let mut lexemes = Vec::new();
+    // End of the last token, and start of the code left
+    let mut last = 0;
+    let mut pos = 0;
+    while pos < input.len() {
+        let comment = input[pos..].find('#').map_or(input.len(), |i| pos + i);
+        let mut lex = raw(&input[pos..comment]);
+        while let Some(token) = lex.next() {
+            let span = pos + lex.span().start..pos + lex.span().end;
+            match token {
+                Token::NewLine => continue,
+                Token::Error => {
+                    let err = match lex.extras.take() {
+                        Some(LexError::ParseInt{raw, reason, ..}) => LexError::ParseInt{raw, reason, span},
+                        Some(err) => err,
+                        None => LexError::InvalidCharacter{ch: input[span.start..].chars().next().unwrap_or_default(), span},
+                    };
+                    return Err(err.into())
+                }
+                token => {
+                    lexemes.push(Lexeme{token, trivia: &input[last..span.start], text: &input[span.clone()], span: span.clone()});
+                    last = span.end;
                 }
             }
-            Token::NewLine => break,
-            _ => tokens.push(token)
         }
+        pos = input[comment..].find('\n').map_or(input.len(), |i| comment + i);
     }
-    Ok(tokens)
+    Ok(Lossless{lexemes, trailing: &input[last..]})
 }
 
 #[cfg(test)]
@@ -170,6 +799,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[cfg(feature = "logos")]
     fn test_number() {
         let mut lex = Token::lexer("12_3 0b0000_1111 0o00_13 0x00_1a 0x____");
 
@@ -182,8 +812,9 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "logos")]
     fn test_symbol() {
-        let mut lex = Token::lexer("+- * / ()");
+        let mut lex = Token::lexer("+- * / (),=| && ||| ! [..] < <= > >= == != !");
 
         assert_eq!(lex.next(), Some(Token::Plus));
         assert_eq!(lex.next(), Some(Token::Minus));
@@ -191,20 +822,59 @@ mod tests {
         assert_eq!(lex.next(), Some(Token::Division));
         assert_eq!(lex.next(), Some(Token::LP));
         assert_eq!(lex.next(), Some(Token::RP));
+        assert_eq!(lex.next(), Some(Token::Comma));
+        assert_eq!(lex.next(), Some(Token::Assign));
+        assert_eq!(lex.next(), Some(Token::Bar));
+        assert_eq!(lex.next(), Some(Token::And));
+        assert_eq!(lex.next(), Some(Token::Or));
+        assert_eq!(lex.next(), Some(Token::Bar));
+        assert_eq!(lex.next(), Some(Token::Not));
+        assert_eq!(lex.next(), Some(Token::LB));
+        assert_eq!(lex.next(), Some(Token::DotDot));
+        assert_eq!(lex.next(), Some(Token::RB));
+        assert_eq!(lex.next(), Some(Token::Less));
+        assert_eq!(lex.next(), Some(Token::LessEq));
+        assert_eq!(lex.next(), Some(Token::Greater));
+        assert_eq!(lex.next(), Some(Token::GreaterEq));
+        assert_eq!(lex.next(), Some(Token::Equal));
+        assert_eq!(lex.next(), Some(Token::NotEqual));
+        assert_eq!(lex.next(), Some(Token::Not));
         assert_eq!(lex.next(), None);
     }
 
     #[test]
+    #[cfg(feature = "logos")]
+    fn test_ident() {
+        let mut lex = Token::lexer("hypot _a1 $12 0xag true falsey if elsewhere let in inch");
+
+        assert_eq!(lex.next(), Some(Token::Ident("hypot".to_string())));
+        assert_eq!(lex.next(), Some(Token::Ident("_a1".to_string())));
+        assert_eq!(lex.next(), Some(Token::Ident("$12".to_string())));
+        assert_eq!(lex.next(), Some(Token::Number(10)));
+        assert_eq!(lex.next(), Some(Token::Ident("g".to_string())));
+        assert_eq!(lex.next(), Some(Token::Bool(true)));
+        assert_eq!(lex.next(), Some(Token::Ident("falsey".to_string())));
+        assert_eq!(lex.next(), Some(Token::If));
+        assert_eq!(lex.next(), Some(Token::Ident("elsewhere".to_string())));
+        assert_eq!(lex.next(), Some(Token::Let));
+        assert_eq!(lex.next(), Some(Token::In));
+        assert_eq!(lex.next(), Some(Token::Ident("inch".to_string())));
+        assert_eq!(lex.next(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "logos")]
     fn test_overflow() {
-        let mut lex = Token::lexer("123456789123456789123456789123456789");
+        let mut lex = Token::lexer("1234567891234567891234567891234567891234");
 
         assert_eq!(lex.next(), Some(Token::Error));
-        assert_eq!(lex.span(), 0..36);
+        assert_eq!(lex.span(), 0..40);
     }
 
     #[test]
+    #[cfg(feature = "logos")]
     fn test_mismatch() {
-        let mut lex = Token::lexer("0abc");
+        let mut lex = Token::lexer("0#bc");
 
         assert_eq!(lex.next(), Some(Token::Number(0)));
         assert_eq!(lex.next(), Some(Token::Error));
@@ -213,7 +883,7 @@ mod tests {
     }
 
     #[test]
-    fn test_lexer() -> Result<(), String> {
+    fn test_lexer() -> Result<(), Error> {
         let tokens = lexer("12*(0x_1A-0b01)+-0o12/0\n123")?;
         assert_eq!(tokens, [
             Token::Number(12),
@@ -233,14 +903,150 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_full_width() {
+        assert_eq!(spanned("\u{ff11}\u{ff12}_3+4\u{ff10}").unwrap(), [
+            (Token::Number(123), 0..8),
+            (Token::Plus, 8..9),
+            (Token::Number(40), 9..13),
+        ]);
+        // Only decimal, like `0xg`
+        assert_eq!(lexer("0x\u{ff11}").unwrap(), [Token::Number(0), Token::Number(1)]);
+        let arabic = spanned("\u{661}\u{662}\u{6f3}");
+        #[cfg(feature = "arabic-digits")]
+        assert_eq!(arabic.unwrap(), [(Token::Number(123), 0..6)]);
+        #[cfg(not(feature = "arabic-digits"))]
+        assert!(arabic.is_err());
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(spanned("1h30m*2").unwrap(), [
+            (Token::Duration(5400), 0..5),
+            (Token::Times, 5..6),
+            (Token::Number(2), 6..7),
+        ]);
+        assert_eq!(lexer("1_0m 2d").unwrap(), [Token::Duration(600), Token::Duration(172800)]);
+        assert_eq!(Token::Duration(90061).to_string(), "1d1h1m1s");
+        assert!(lexer("1h30").is_err());
+        assert!(matches!(lexer("99999999999999999999999999999999999999d"), Err(Error::Lex(LexError::ParseInt{..}))));
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(spanned("f(\"%.3f\", \"\")").unwrap(), [
+            (Token::Ident("f".to_string()), 0..1),
+            (Token::LP, 1..2),
+            (Token::Str("%.3f".to_string()), 2..8),
+            (Token::Comma, 8..9),
+            (Token::Str(String::new()), 10..12),
+            (Token::RP, 12..13),
+        ]);
+        assert_eq!(Token::Str("%d".to_string()).to_string(), "\"%d\"");
+        // Not closed on the line, or with a `#`
+        for input in ["\"abc", "\"a\nb\"", "\"#\""] {
+            assert!(matches!(lexer(input), Err(Error::Lex(LexError::InvalidCharacter{ch: '"', ..}))), "{:?}", input);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "dates")]
+    fn test_dates() {
+        let options = Options{dates: true, ..Options::default()};
+        let tokens = |input| tokens_with(input, options).collect::<Result<Vec<_>, _>>();
+        assert_eq!(tokens("2024-05-01+1").unwrap(), [
+            (Token::Date(19844), 0..10),
+            (Token::Plus, 10..11),
+            (Token::Number(1), 11..12),
+        ]);
+        // Not written together, or not 4, 2 and 2 digits
+        for input in ["2024 -05-01", "2024-05 -01", "2024-5-01", "2024-05-1", "20_24-05-01", "2024-05", "2024-05-0x1", "12024-05-01"] {
+            assert!(tokens(input).unwrap().iter().all(|(token, _)| !matches!(token, Token::Date(_))), "{}", input);
+        }
+        assert_eq!(tokens("2024-05").unwrap().len(), 3);
+        assert_eq!(tokens("2023-02-29"), Err(LexError::InvalidDate{raw: "2023-02-29".to_string(), span: 0..10}.into()));
+        assert_eq!(lexer("2024-05-01").unwrap().len(), 5);
+    }
+
+    #[test]
+    // `dates` is only with the feature
+    #[allow(clippy::needless_update)]
+    fn test_thousands() {
+        let options = Options{thousands: true, ..Options::default()};
+        let lex = |input| tokens_with(input, options).collect::<Result<Vec<_>, _>>().map(|tokens| tokens.into_iter().map(|(token, _)| token).collect::<Vec<_>>());
+        let grouped = |input| tokens_with(input, options).next().unwrap();
+        assert_eq!(grouped("1,234,567"), Ok((Token::Number(1234567), 0..9)));
+        assert_eq!(grouped("\u{ff11},000"), Ok((Token::Number(1000), 0..7)));
+        assert_eq!(lex("f((1,000), [2,000], 3,000)").unwrap()[3], Token::Number(1000));
+        assert_eq!(lex("f(1,000)").unwrap(), lex("f(1, 000)").unwrap());
+        assert_eq!(lex("1, 000").unwrap(), [Token::Number(1), Token::Comma, Token::Number(0)]);
+        assert_eq!(lex("(1,000 $").unwrap_err(), LexError::InvalidCharacter{ch: '$', span: 7..8}.into());
+        assert_eq!(lex("1,$").unwrap_err(), LexError::InvalidCharacter{ch: '$', span: 2..3}.into());
+
+        let grouping = |raw: &str, span| Err(LexError::Grouping{raw: raw.to_string(), span}.into());
+        assert_eq!(grouped("1234,567"), grouping("1234,567", 0..8));
+        assert_eq!(grouped("1,23"), grouping("1,23", 0..4));
+        assert_eq!(grouped("0x1,000"), grouping("0x1,000", 0..7));
+        assert_eq!(grouped("1,000_000"), grouping("1,000_000", 0..9));
+        assert!(matches!(grouped("999,999,999,999,999,999,999,999,999,999,999,999,999"), Err(Error::Lex(LexError::ParseInt{..}))));
+        assert_eq!(tokens("1,000").count(), 3);
+    }
+
+    #[test]
+    // `dates` is only with the feature
+    #[allow(clippy::needless_update)]
+    fn test_reserve() {
+        // The `(` of a group in calls keeps the `,` of the number in it
+        let options = Options{thousands: true, ..Options::default()};
+        let input = "f(".repeat(20) + "(1,000)" + &")".repeat(20);
+        let tokens: Vec<_> = tokens_with(&input, options).map(|token| token.unwrap().0).collect();
+        assert_eq!(tokens[41], Token::Number(1000));
+        assert_eq!(spanned(&"1+".repeat(RESERVE)).unwrap().len(), 2 * RESERVE);
+    }
+
+    #[test]
+    fn test_highlight() {
+        use TokenCategory::*;
+
+        let res = highlight("-0x_1A * (2\n+ 0b12)/ \u{e9}1 0x1_0000_0000_0000_0000_0000_0000_0000_0000");
+        assert_eq!(res, [
+            (0..1, Operator), (1..6, Number), (7..8, Operator), (9..10, Paren), (10..11, Number),
+            (12..13, Operator), (14..17, Number), (17..18, Number), (18..19, Paren), (19..20, Operator),
+            (21..23, Error), (23..24, Number), (25..68, Error),
+        ]);
+        assert_eq!(highlight(""), []);
+    }
+
     #[test]
     fn test_lexer_error() {
-        let res = lexer("123456789123456789123456789123456789");
+        let res = lexer("1234567891234567891234567891234567891234");
         assert!(res.is_err());
-        assert_eq!(res.unwrap_err(), "Parse int failed: number too large to fit in target type\nNear 0..36: 123456789123456789123456789123456789");
+        assert_eq!(res.unwrap_err().to_string(), "Parse int failed: number too large to fit in target type\nNear 0..40: 1234567891234567891234567891234567891234");
 
-        let res = lexer("0+a");
+        let res = lexer("0+$");
         assert!(res.is_err());
-        assert_eq!(res.unwrap_err(), "Invalid character near 2..3: a");
+        assert_eq!(res.unwrap_err().to_string(), "Invalid character near 2..3: $");
+
+        let res = lexer("1+\u{e9}");
+        assert!(res.is_err());
+        assert_eq!(res.unwrap_err(), Error::Lex(LexError::InvalidCharacter{ch: '\u{e9}', span: 2..4}));
+    }
+
+    #[test]
+    fn test_lossless() {
+        let sources = [
+            "", "  \n", "# only a comment", "12*(0x_1A-0b01)+-0o12/0\n123", "f(a , b)\n\n  # total\ny = 1_000 # end\n",
+            "x = 1 ## #\n\x0c2", "if  a then\tb else c",
+        ];
+        for source in sources.iter() {
+            assert_eq!(lossless(source).unwrap().to_string(), *source);
+        }
+        let line = "max( 0xFF ,|x|)..  y";
+        let tokens: Vec<_> = lossless(line).unwrap().lexemes.into_iter().map(|lexeme| (lexeme.token, lexeme.span)).collect();
+        assert_eq!(tokens, spanned(line).unwrap());
+
+        assert_eq!(lossless("1 # $\n2 $").unwrap_err(), Error::Lex(LexError::InvalidCharacter{ch: '$', span: 8..9}));
+        let err = lossless("# big\n1234567891234567891234567891234567891234").unwrap_err();
+        assert_eq!(err.span(), 6..46);
     }
 }
@@ -0,0 +1,298 @@
+//! Hand-written scanner used while the `logos` feature is disabled.
+//!
+//! Produce the same tokens, spans and errors as the `logos`
+//! lexer, without the dependency. The interface follows
+//! `logos::Lexer`, so [`Tokens`](super::Tokens) drives both.
+use super::{ascii_digit, parse_duration, parse_int, Span, Token};
+use crate::error::LexError;
+
+pub(super) struct Scanner<'a> {
+    input: &'a str,
+    pos: usize,
+    span: Span,
+    /// Error of the last number literal
+    pub(super) extras: Option<LexError>,
+}
+
+impl<'a> Scanner<'a> {
+    pub(super) fn new(input: &'a str) -> Self {
+        Scanner{input, pos: 0, span: 0..0, extras: None}
+    }
+
+    pub(super) fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    pub(super) fn slice(&self) -> &'a str {
+        &self.input[self.span.clone()]
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.input.as_bytes().get(self.pos + offset).copied()
+    }
+
+    fn eat_while(&mut self, accept: fn(u8) -> bool) {
+        while self.peek(0).is_some_and(accept) {
+            self.pos += 1;
+        }
+    }
+
+    fn number(&mut self) -> Token {
+        let (prefix, radix) = match (self.peek(0), self.peek(1)) {
+            (Some(b'0'), Some(b'b')) => (2, 2),
+            (Some(b'0'), Some(b'o')) => (2, 8),
+            (Some(b'0'), Some(b'x')) => (2, 16),
+            _ => (0, 10),
+        };
+        self.pos += prefix;
+        match radix {
+            2 => self.eat_while(|c| matches!(c, b'0'..=b'1' | b'_')),
+            8 => self.eat_while(|c| matches!(c, b'0'..=b'7' | b'_')),
+            16 => self.eat_while(|c| c.is_ascii_hexdigit() || c == b'_'),
+            _ => {
+                self.pos += self.decimal();
+                if let Some(token) = self.duration() {
+                    return token
+                }
+            }
+        }
+        self.span = self.span.start..self.pos;
+        let slice = self.slice();
+        match parse_int(&slice[prefix..], radix, self.span(), slice) {
+            Ok(num) => Token::Number(num),
+            Err(err) => {
+                self.extras = Some(err);
+                Token::Error
+            }
+        }
+    }
+}
+
+impl<'a> Scanner<'a> {
+    /// Length of the decimal digits at the position.
+    fn decimal(&self) -> usize {
+        let rest = &self.input[self.pos..];
+        rest.find(|c| c != '_' && ascii_digit(c).is_none()).unwrap_or(rest.len())
+    }
+
+    /// Eat the units and the digits after them while they form a
+    /// duration, such as `h30m`, `None` if there is no unit. Digits
+    /// without a unit after a duration are an error, as `1h30`.
+    fn duration(&mut self) -> Option<Token> {
+        let unit = |c: Option<u8>| matches!(c, Some(b'd' | b'h' | b'm' | b's'));
+        if !unit(self.peek(0)) {
+            return None
+        }
+        self.pos += 1;
+        loop {
+            let first = self.input[self.pos..].chars().next();
+            if first.is_none_or(|c| ascii_digit(c).is_none()) {
+                return Some(self.parse_duration())
+            }
+            self.pos += self.decimal();
+            if !unit(self.peek(0)) {
+                self.span = self.span.start..self.pos;
+                return Some(Token::Error)
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn parse_duration(&mut self) -> Token {
+        self.span = self.span.start..self.pos;
+        match parse_duration(self.slice(), self.span()) {
+            Ok(secs) => Token::Duration(secs),
+            Err(err) => {
+                self.extras = Some(err);
+                Token::Error
+            }
+        }
+    }
+
+    fn ident(&mut self) -> Token {
+        self.eat_while(|c| c.is_ascii_alphanumeric() || c == b'_');
+        self.span = self.span.start..self.pos;
+        match self.slice() {
+            "true" => Token::Bool(true),
+            "false" => Token::Bool(false),
+            "if" => Token::If,
+            "then" => Token::Then,
+            "else" => Token::Else,
+            "let" => Token::Let,
+            "in" => Token::In,
+            name => Token::Ident(name.to_string()),
+        }
+    }
+
+    /// The character repeated, such as `&&`, or alone
+    fn double(&mut self, double: Token, single: Token) -> Token {
+        if self.peek(1) == self.peek(0) {
+            self.pos += 1;
+            double
+        } else {
+            single
+        }
+    }
+
+    /// The token followed by `=`, such as `<=`, or the single one.
+    fn or_equal(&mut self, with_equal: Token, single: Token) -> Token {
+        if self.peek(1) == Some(b'=') {
+            self.pos += 1;
+            with_equal
+        } else {
+            single
+        }
+    }
+
+    /// Text up to the closing `"`, `None` if it isn't closed on
+    /// the line or holds a `#`.
+    fn string(&mut self) -> Option<Token> {
+        let rest = &self.input[self.pos + 1..];
+        let len = rest.find(['"', '\n', '#']).filter(|&len| rest[len..].starts_with('"'))?;
+        self.pos += len + 2;
+        self.span = self.span.start..self.pos;
+        Some(Token::Str(rest[..len].to_string()))
+    }
+
+    /// `$` followed by digits, such as `$1`
+    fn result_ref(&mut self) -> Option<Token> {
+        if !self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            return None
+        }
+        self.pos += 1;
+        self.eat_while(|c| c.is_ascii_digit());
+        self.span = self.span.start..self.pos;
+        Some(Token::Ident(self.slice().to_string()))
+    }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.eat_while(|c| c == b' ' || c == b'\t');
+        self.span = self.pos..self.pos;
+        let c = self.input[self.pos..].chars().next()?;
+        let token = match c {
+            '\n' | '\x0c' => {
+                self.eat_while(|c| c == b'\n' || c == b'\x0c');
+                Token::NewLine
+            }
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Times,
+            '/' => Token::Division,
+            '(' => Token::LP,
+            ')' => Token::RP,
+            ',' => Token::Comma,
+            '[' => Token::LB,
+            ']' => Token::RB,
+            '.' => self.double(Token::DotDot, Token::Error),
+            '|' => self.double(Token::Or, Token::Bar),
+            '&' => self.double(Token::And, Token::Error),
+            '!' => self.or_equal(Token::NotEqual, Token::Not),
+            '<' => self.or_equal(Token::LessEq, Token::Less),
+            '>' => self.or_equal(Token::GreaterEq, Token::Greater),
+            '√' => Token::Root,
+            '=' => self.double(Token::Equal, Token::Assign),
+            c if ascii_digit(c).is_some() => return Some(self.number()),
+            'a'..='z' | 'A'..='Z' | '_' => return Some(self.ident()),
+            '"' => match self.string() {
+                Some(token) => return Some(token),
+                None => Token::Error,
+            },
+            '$' => match self.result_ref() {
+                Some(token) => return Some(token),
+                None => Token::Error,
+            },
+            _ => Token::Error,
+        };
+        if token != Token::NewLine {
+            self.pos += c.len_utf8();
+        }
+        self.span = self.span.start..self.pos;
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scanner() {
+        let mut scanner = Scanner::new("12_3 0b0000_1111\t0o00_13 0x00_1a 0x____ +- * / ()");
+
+        assert_eq!(scanner.next(), Some(Token::Number(123)));
+        assert_eq!(scanner.span(), 0..4);
+        assert_eq!(scanner.next(), Some(Token::Number(15)));
+        assert_eq!(scanner.next(), Some(Token::Number(11)));
+        assert_eq!(scanner.next(), Some(Token::Number(26)));
+        assert_eq!(scanner.next(), Some(Token::Number(0)));
+        assert_eq!(scanner.next(), Some(Token::Plus));
+        assert_eq!(scanner.next(), Some(Token::Minus));
+        assert_eq!(scanner.next(), Some(Token::Times));
+        assert_eq!(scanner.next(), Some(Token::Division));
+        assert_eq!(scanner.next(), Some(Token::LP));
+        assert_eq!(scanner.next(), Some(Token::RP));
+        assert_eq!(scanner.span(), 48..49);
+        assert_eq!(scanner.next(), None);
+
+        let mut scanner = Scanner::new("hypot(x_1, 2)");
+
+        assert_eq!(scanner.next(), Some(Token::Ident("hypot".to_string())));
+        assert_eq!(scanner.span(), 0..5);
+        assert_eq!(scanner.next(), Some(Token::LP));
+        assert_eq!(scanner.next(), Some(Token::Ident("x_1".to_string())));
+        assert_eq!(scanner.next(), Some(Token::Comma));
+        assert_eq!(scanner.next(), Some(Token::Number(2)));
+        assert_eq!(scanner.next(), Some(Token::RP));
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn test_scanner_error() {
+        let mut scanner = Scanner::new("0#\u{1F600}0x1_0000_0000_0000_0000_0000_0000_0000_0000");
+
+        assert_eq!(scanner.next(), Some(Token::Number(0)));
+        assert_eq!(scanner.next(), Some(Token::Error));
+        assert_eq!(scanner.slice(), "#");
+        assert_eq!(scanner.next(), Some(Token::Error));
+        assert_eq!(scanner.span(), 2..6);
+        assert_eq!(scanner.extras, None);
+        assert_eq!(scanner.next(), Some(Token::Error));
+        assert_eq!(scanner.extras.unwrap().to_string(),
+            "Parse int failed: number too large to fit in target type\nNear 6..49: 0x1_0000_0000_0000_0000_0000_0000_0000_0000");
+    }
+
+    #[test]
+    #[cfg(feature = "logos")]
+    fn test_same_as_logos() {
+        use logos::Logos;
+
+        let inputs = [
+            "12*(0x_1A-0b01)+-0o12/0\n123", "0b2", "0o8", "0x1g", "0b_", "12_", "__",
+            "1 \t 2", "1\r2", "\u{e9}", "1\u{1F600}2", "\n\n1", "0xFFFFFFFFFFFFFFFFF+1",
+            "18446744073709551615", "18446744073709551616", "",
+            "hypot(1, 2)", "x = 1", "==", "|||x", "a && !b", "&", "&&&", "true1", "false", "if x then 1 else y", "iffy", "thenelse", "let x = 1 in x", "inlet", "$1+$23", "$", "$a", "$1a", "a_1b", "_", "1a", "0xag", "x\u{e9}", "f(,)",
+            "[1, 2][0]", "1..10", "1...2", ".", ".1", "a[]",
+            "\u{ff11}\u{ff12}_3+4\u{ff10}", "0\u{ff58}1", "0x\u{ff11}", "\u{661}\u{662}\u{6f3}", "1\u{3000}2",
+            "1h30m", "90s*4", "1_0m", "1d2", "1h30m2+1", "1d2 3", "1h3_", "1h_", "2hours", "1hh", "1h_2m", "_1s", "0x1s", "0b1m", "\u{ff11}h\u{ff12}s", "5ms", "99999999999999999999999999999999999999d",
+            "\u{221a}2", "3\u{221a}x+\u{221a}\u{221a}16", "\u{221a}\u{e9}",
+            "1 < x <= 2", "a>=b>c", "x == 1 != y", "!==", "<<=>", "=>", "!=!",
+        ];
+        for input in inputs.iter() {
+            let mut lex = Token::lexer(input);
+            let mut scanner = Scanner::new(input);
+            loop {
+                let token = lex.next();
+                assert_eq!(scanner.next(), token, "input: {:?}", input);
+                if token.is_none() {
+                    break;
+                }
+                assert_eq!(scanner.span(), lex.span(), "input: {:?}", input);
+                assert_eq!(scanner.extras, lex.extras, "input: {:?}", input);
+            }
+        }
+    }
+}
@@ -1,16 +1,30 @@
 //! A calculator that implement for Arithmetic.
 //! 
-//! Allow operator: `+` `-` `*` `/` `(` `)`.
+//! Allow operator: `+` `-` `*` `/` `(` `)`, absolute values such as
+//! `|x - 3|`, logical operators `&&` `||` `!` with `true` and
+//! `false`, conditions `if c then a else b`, local names
+//! `let x = a in b`, calls of functions
+//! registered in a [`FunctionRegistry`], and variables assigned
+//! by `name = expr` in an [`Environment`].
 //!
-//! Result can be `i128` or `f64`. A warning will
-//! occur while result is `i128` and division cast
-//! happened, such as `3/2=1`.
+//! Result can be `i128`, `f64` or an exact [`Rational`], a
+//! [`Time`] of the durations such as `1h30m`, a [`Matrix`] such
+//! as `[[1, 2], [3, 4]]`, or a `BigInt` with the `bigint` feature
+//! and a `Decimal` with the `decimal` feature. A warning will
+//! occur while result is `i128` and division cast happened, such
+//! as `3/2=1`, or while result is `f64` and an integer past 2^53
+//! is rounded, such as `9007199254740993`.
 //!
 //! This calculator has three steps:
 //! * Use `logos` to parse the expression to tokens.
+//!   Disable the default `logos` feature to use a
+//!   hand-written scanner instead.
 //! * Use a parser to parse tokens to a AST.
 //! * Calculate the result from the AST.
 //!
+//! Each step keeps no global state, so they are safe to
+//! call from many threads at the same time.
+//!
 //! The following parser is available:
 //! * Top-down parser (default)
 //! 
@@ -29,53 +43,297 @@
 //!     assert_eq!(res, 3f64);
 //! }
 //! ```
+#[macro_use]
+mod logging;
+
+pub mod error;
+pub mod diagnostics;
 pub mod lexer;
 pub mod parser;
 pub mod generator;
+pub mod grammar;
+pub mod pretty;
+pub mod format;
+pub mod lint;
+pub mod explain;
+pub mod intervals;
+pub mod text;
+#[cfg(feature = "node")]
+pub mod node;
+#[cfg(feature = "protobuf")]
+pub mod proto;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod functions;
+pub mod environment;
+pub mod limits;
+mod rational;
+mod duration;
+mod matrix;
+mod date;
+mod angle;
+mod root;
+mod builder;
+mod cache;
+mod resolver;
+mod cells;
+mod template;
+mod suggest;
+mod session;
+mod source_map;
+mod streaming;
+#[cfg(feature = "optimize")]
+mod optimize;
+
+pub use error::Error;
+pub use functions::FunctionRegistry;
+pub use environment::{Environment, UserFunction};
+pub use limits::Limits;
+pub use rational::Rational;
+pub use angle::AngleUnit;
+pub use root::Rounding;
+pub use duration::{DurationFormat, Time};
+pub use matrix::Matrix;
+#[cfg(feature = "bigint")]
+pub use num_bigint::BigInt;
+#[cfg(feature = "decimal")]
+pub use rust_decimal::Decimal;
+pub use builder::Calculator;
+pub use resolver::Resolver;
+pub use cells::{Cell, CellResolver};
+pub use template::Template;
+pub use session::{Entry, Session};
+pub use source_map::{LineColumn, SourceMap};
+pub use lexer::{highlight, TokenCategory};
 
 use parser::ast::AST;
-use generator::{calculator, calculator_f};
+use generator::{calculator, calculator_f, calculator_r, Arithmetic, Context};
 
 /// Use default parser to calculate the expression.
 #[macro_export]
 macro_rules! calculator{
     ($expr: expr) => {
-        calculator($expr, parser::top_down_parser::parse);
+        calculator($expr, parser::top_down_parser::parse)
     };
     ($expr: expr, $type: ty) => {
-        calculator::<$type>($expr, parser::top_down_parser::parse);
+        calculator::<$type>($expr, parser::top_down_parser::parse)
     }
 }
 
 /// Result that can be calculate from the AST
-pub trait FromAST{
-    fn from_ast(ast: AST) -> Self;
+pub trait FromAST: Sized {
+    fn from_ast(ast: AST) -> Result<Self, Error> {
+        Self::from_ast_with(ast, &FunctionRegistry::new())
+    }
+
+    /// Calculate with the functions in the registry.
+    fn from_ast_with(ast: AST, functions: &FunctionRegistry<Self>) -> Result<Self, Error> {
+        Self::from_ast_in(ast, &mut Context::new(functions))
+    }
+
+    /// Calculate in the context, such as with a limit of operations.
+    fn from_ast_in(ast: AST, ctx: &mut Context<Self>) -> Result<Self, Error>;
+
+    /// Arithmetic of [`eval_streaming`], `None` if the expressions
+    /// are always parsed to an AST first.
+    fn arithmetic() -> Option<Arithmetic<Self>> {
+        None
+    }
+
+    /// Number of the result for the functions of the texts such as
+    /// `hex(255)`, `None` if it isn't a number.
+    fn to_number(&self) -> Option<text::Number> {
+        None
+    }
 }
 
 impl FromAST for i128 {
-    fn from_ast(ast: AST) -> i128 {
-        calculator::calculate(ast)
+    fn from_ast_in(ast: AST, ctx: &mut Context<i128>) -> Result<i128, Error> {
+        calculator::calculate_in(ast, ctx)
+    }
+
+    fn arithmetic() -> Option<Arithmetic<i128>> {
+        Some(calculator::ARITHMETIC)
+    }
+
+    fn to_number(&self) -> Option<text::Number> {
+        Some(text::Number::Integer(*self))
     }
 }
 
 impl FromAST for f64 {
-    fn from_ast(ast: AST) -> f64 {
-        calculator_f::calculate(ast)
+    fn from_ast_in(ast: AST, ctx: &mut Context<f64>) -> Result<f64, Error> {
+        calculator_f::calculate_in(ast, ctx)
+    }
+
+    fn arithmetic() -> Option<Arithmetic<f64>> {
+        Some(calculator_f::ARITHMETIC)
+    }
+
+    fn to_number(&self) -> Option<text::Number> {
+        Some(text::Number::Float(*self))
+    }
+}
+
+impl FromAST for Rational {
+    fn from_ast_in(ast: AST, ctx: &mut Context<Rational>) -> Result<Rational, Error> {
+        calculator_r::calculate_in(ast, ctx)
+    }
+
+    fn arithmetic() -> Option<Arithmetic<Rational>> {
+        Some(calculator_r::ARITHMETIC)
+    }
+
+    fn to_number(&self) -> Option<text::Number> {
+        Some(text::Number::from(*self))
+    }
+}
+
+impl FromAST for Time {
+    fn from_ast_in(ast: AST, ctx: &mut Context<Time>) -> Result<Time, Error> {
+        generator::calculator_t::calculate_in(ast, ctx)
+    }
+
+    fn to_number(&self) -> Option<text::Number> {
+        match self {
+            Time::Number(value) => Some(text::Number::from(*value)),
+            _ => None,
+        }
+    }
+}
+
+impl FromAST for Matrix {
+    fn from_ast_in(ast: AST, ctx: &mut Context<Matrix>) -> Result<Matrix, Error> {
+        generator::calculator_m::calculate_in(ast, ctx)
+    }
+
+    fn to_number(&self) -> Option<text::Number> {
+        match self {
+            Matrix::Number(value) => Some(text::Number::from(*value)),
+            Matrix::Rows(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl FromAST for BigInt {
+    fn from_ast_in(ast: AST, ctx: &mut Context<BigInt>) -> Result<BigInt, Error> {
+        generator::calculator_big::calculate_in(ast, ctx)
+    }
+
+    fn to_number(&self) -> Option<text::Number> {
+        use std::convert::TryFrom;
+
+        // Past i128 as the nearest float
+        match i128::try_from(self) {
+            Ok(n) => Some(text::Number::Integer(n)),
+            Err(_) => self.to_string().parse().ok().map(text::Number::Float),
+        }
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl FromAST for Decimal {
+    fn from_ast_in(ast: AST, ctx: &mut Context<Decimal>) -> Result<Decimal, Error> {
+        generator::calculator_d::calculate_in(ast, ctx)
+    }
+
+    fn to_number(&self) -> Option<text::Number> {
+        use rust_decimal::prelude::ToPrimitive;
+
+        if self.fract().is_zero() {
+            self.to_i128().map(text::Number::Integer)
+        } else {
+            self.to_f64().map(text::Number::Float)
+        }
     }
 }
 
 /// Use a parser to calculate the expression.
-pub fn calculator<T: FromAST>(expr: &str, parser: fn(Vec<lexer::Token>)->Result<AST, String>) -> Result<T, String> {
-    let tokens = lexer::lexer(expr)?;
+pub fn calculator<T: FromAST>(expr: &str, parser: parser::ParseFn) -> Result<T, Error> {
+    calculator_with(expr, parser, &FunctionRegistry::new())
+}
+
+/// Use a parser to calculate the expression, calling the
+/// functions in the registry.
+pub fn calculator_with<T: FromAST>(expr: &str, parser: parser::ParseFn, functions: &FunctionRegistry<T>) -> Result<T, Error> {
+    let tokens = lexer::spanned(expr)?;
+    debug!(expr = expr, tokens = tokens.len(); "lexed expression");
     let ast = parser(tokens)?;
-    Ok(T::from_ast(ast))
+    debug!(expr = expr; "parsed expression");
+    T::from_ast_with(ast, functions)
+}
+
+/// Use default parser to calculate the expression, never panic.
+///
+/// Any input, such as invalid characters, overflow, division by
+/// zero or deeply nested expressions, returns an `Error` instead
+/// of panicking or overflowing the stack. Use it to calculate
+/// untrusted text. The guarantee is checked by the fuzz targets
+/// in `fuzz/`.
+///
+/// # Example
+/// ```
+/// use wcal::eval_no_panic;
+///
+/// assert_eq!(eval_no_panic::<i128>("(1+2)*3"), Ok(9));
+/// assert!(eval_no_panic::<i128>("1/0").is_err());
+/// assert!(eval_no_panic::<i128>(&"(".repeat(100_000)).is_err());
+/// ```
+pub fn eval_no_panic<T: FromAST>(expr: &str) -> Result<T, Error> {
+    calculator(expr, parser::top_down_parser::parse)
+}
+
+/// Calculate the expression, lexing and parsing in one pass.
+///
+/// Same as [`eval_no_panic`] but the tokens are never collected,
+/// which saves an allocation per expression. See `benches/` for
+/// the numbers.
+///
+/// # Example
+/// ```
+/// use wcal::eval_str_fast;
+///
+/// assert_eq!(eval_str_fast::<i128>("(1+2)*3"), Ok(9));
+/// assert_eq!(eval_str_fast::<f64>("7/2"), Ok(3.5));
+/// ```
+pub fn eval_str_fast<T: FromAST>(expr: &str) -> Result<T, Error> {
+    let ast = parser::top_down_parser::parse_stream(lexer::tokens(expr))?;
+    debug!(expr = expr; "parsed expression");
+    T::from_ast(ast)
+}
+
+/// Calculate the expression as it is lexed, without an AST.
+///
+/// The numbers, `+ - * /`, the negation and the parentheses are
+/// calculated on a stack as the tokens come, in the `i128`, `f64`
+/// and [`Rational`] calculators. Any other expression, and an
+/// expression that fails, is calculated by [`eval_str_fast`], so
+/// the results and the errors are the same. See `benches/` for the
+/// numbers.
+///
+/// # Example
+/// ```
+/// use wcal::eval_streaming;
+///
+/// assert_eq!(eval_streaming::<i128>("(1+2)*-3"), Ok(-9));
+/// assert_eq!(eval_streaming::<f64>("|2 - 9| / 2"), Ok(3.5));
+/// assert!(eval_streaming::<i128>("1/0").is_err());
+/// ```
+pub fn eval_streaming<T: FromAST>(expr: &str) -> Result<T, Error> {
+    let functions = FunctionRegistry::new();
+    let mut ctx = Context::new(&functions);
+    match T::arithmetic().and_then(|arithmetic| streaming::eval(expr, &arithmetic, &mut ctx)) {
+        Some(value) => Ok(value),
+        None => eval_str_fast(expr),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
-    fn test_cal() -> Result<(), String> {
+    fn test_cal() -> Result<(), Error> {
         let res: i128 = calculator("1+2", parser::top_down_parser::parse)?;
         assert_eq!(res, 3);
 
@@ -88,7 +346,7 @@ mod tests {
     }
 
     #[test]
-    fn test_cal_macro() -> Result<(), String> {
+    fn test_cal_macro() -> Result<(), Error> {
         let res: f64 = calculator!("1+2")?;
         assert_eq!(res, 3f64);
 
@@ -96,4 +354,213 @@ mod tests {
         assert_eq!(res, 3f64);
         Ok(())
     }
+
+    #[test]
+    fn test_cal_functions() {
+        let mut functions = FunctionRegistry::new();
+        functions.register("hypot", 2, |args: &[f64]| args[0].hypot(args[1]));
+
+        let res = calculator_with("hypot(3, 2*2) - -1", parser::top_down_parser::parse, &functions);
+        assert_eq!(res, Ok(6f64));
+
+        let err = calculator_with("1 + hypot(3)", parser::top_down_parser::parse, &functions).unwrap_err();
+        assert_eq!(err.to_string(), "Function hypot takes 2 arguments, got 1");
+        assert_eq!(err.span(), 4..12);
+
+        let err = calculator::<f64>("1 + hypot(3, 4)", parser::top_down_parser::parse).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown function hypot");
+        assert_eq!(err.span(), 4..15);
+    }
+
+    #[test]
+    fn test_big_literals() {
+        // Literals are lexed up to u128, each result type checks its range
+        let parse = parser::top_down_parser::parse;
+        assert_eq!(calculator::<i128>("18446744073709551616 - 1", parse), Ok(u64::MAX as i128));
+        assert_eq!(calculator::<i128>("170141183460469231731687303715884105727", parse), Ok(i128::MAX));
+        let err = calculator::<i128>("1 + 170141183460469231731687303715884105728", parse).unwrap_err();
+        assert_eq!(err, error::EvalError::Overflow{span: 4..43}.into());
+        assert_eq!(calculator::<Rational>("170141183460469231731687303715884105728", parse).unwrap_err().span(), 0..39);
+        assert_eq!(calculator::<i128>("-170141183460469231731687303715884105728", parse), Ok(i128::MIN));
+        assert_eq!(calculator::<Rational>("-170141183460469231731687303715884105728", parse), Ok(Rational::from(i128::MIN)));
+        assert_eq!(calculator::<f64>("0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF", parse), Ok(u128::MAX as f64));
+        let err = calculator::<f64>("0x1_0000_0000_0000_0000_0000_0000_0000_0000", parse).unwrap_err();
+        assert!(matches!(err, Error::Lex(_)));
+    }
+
+    #[test]
+    fn test_abs() {
+        assert_eq!(eval_no_panic::<i128>("|3 - 5| * |-2|"), Ok(4));
+        assert_eq!(eval_no_panic::<f64>("|1/4 - 1|"), Ok(0.75));
+        assert_eq!(eval_no_panic::<Rational>("||1 - 3| - 7/2|"), Ok(Rational::new(3, 2).unwrap()));
+        #[cfg(feature = "bigint")]
+        assert_eq!(eval_no_panic::<BigInt>("|-0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF * 2|"), Ok(BigInt::from(u128::MAX) * 2));
+        #[cfg(feature = "decimal")]
+        assert_eq!(eval_no_panic::<Decimal>("|0 - 1/4|").map(|res| res.to_string()), Ok("0.25".to_string()));
+        let err = eval_no_panic::<i128>("1 + |-170141183460469231731687303715884105728|").unwrap_err();
+        assert_eq!(err, error::EvalError::Overflow{span: 4..46}.into());
+    }
+
+    #[test]
+    fn test_logical() {
+        assert_eq!(eval_no_panic::<i128>("!0 && (2 || 1/0) + true"), Ok(1));
+        assert_eq!(eval_no_panic::<i128>("1 && 0 || !!-5"), Ok(1));
+        assert_eq!(eval_no_panic::<f64>("1/2 && !false"), Ok(1.0));
+        assert_eq!(eval_no_panic::<Rational>("1/3 - 1/3 || 0"), Ok(Rational::from(0)));
+        #[cfg(feature = "bigint")]
+        assert_eq!(eval_no_panic::<BigInt>("0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF * 2 && 1"), Ok(BigInt::from(1)));
+        #[cfg(feature = "decimal")]
+        assert_eq!(eval_no_panic::<Decimal>("!(1/4)").map(|res| res.to_string()), Ok("0".to_string()));
+        // The right operand is not calculated once the left one decides
+        assert_eq!(eval_no_panic::<i128>("0 && 1/0"), Ok(0));
+        assert_eq!(eval_no_panic::<Rational>("true || 1/0"), Ok(Rational::from(1)));
+        assert_eq!(eval_no_panic::<i128>("1 && 1/0"), Err(error::EvalError::DivisionByZero{span: 5..8}.into()));
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(eval_no_panic::<i128>("if 2 - 2 then 1/0 else 7 * 6"), Ok(42));
+        assert_eq!(eval_no_panic::<f64>("1 + if true then 1/4 else 1/0"), Ok(1.25));
+        assert_eq!(eval_no_panic::<Rational>("if 1/3 && !0 then 1/3 else 0"), Ok(Rational::new(1, 3).unwrap()));
+        #[cfg(feature = "bigint")]
+        assert_eq!(eval_no_panic::<BigInt>("if 0 then 1 else 0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF + 1"), Ok(BigInt::from(u128::MAX) + 1));
+        #[cfg(feature = "decimal")]
+        assert_eq!(eval_no_panic::<Decimal>("if 1/10 then 1/10 else 0").map(|res| res.to_string()), Ok("0.1".to_string()));
+        let err = eval_no_panic::<i128>("if 1 then 1/0 else 0").unwrap_err();
+        assert_eq!(err, error::EvalError::DivisionByZero{span: 10..13}.into());
+    }
+
+    #[test]
+    fn test_let() {
+        assert_eq!(eval_no_panic::<i128>("let h = 3 * 4 in h * h - h"), Ok(132));
+        assert_eq!(eval_no_panic::<f64>("let x = 1/2 in let y = x + 1 in x * y"), Ok(0.75));
+        // The inner binding hides the outer one, only in its body
+        assert_eq!(eval_no_panic::<Rational>("let x = 1 in (let x = 1/3 in x) + x"), Ok(Rational::new(4, 3).unwrap()));
+        #[cfg(feature = "bigint")]
+        assert_eq!(eval_no_panic::<BigInt>("let a = 0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF in a + a"), Ok(BigInt::from(u128::MAX) * 2));
+        #[cfg(feature = "decimal")]
+        assert_eq!(eval_no_panic::<Decimal>("let t = 1/10 in t * 3").map(|res| res.to_string()), Ok("0.3".to_string()));
+        let err = eval_no_panic::<i128>("(let x = 1 in x) + x").unwrap_err();
+        assert_eq!(err, error::EvalError::UnknownVariable{name: "x".to_string(), suggestion: None, span: 19..20}.into());
+    }
+
+    #[test]
+    fn test_lists() {
+        use error::EvalError;
+
+        assert_eq!(eval_no_panic::<i128>("sum(1..100) + [10, 20, 30][1] * len([])"), Ok(5050));
+        assert_eq!(eval_no_panic::<i128>("let a = 2..5 in prod(a) - a[0 - -3]"), Ok(115));
+        assert_eq!(eval_no_panic::<f64>("mean([1, 2, 4]) + sum(3..1)"), Ok(7.0 / 3.0));
+        assert_eq!(eval_no_panic::<Rational>("mean([1/2, 1/3])"), Ok(Rational::new(5, 12).unwrap()));
+        #[cfg(feature = "bigint")]
+        assert_eq!(eval_no_panic::<BigInt>("prod([0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF, 2])"), Ok(BigInt::from(u128::MAX) * 2));
+        #[cfg(feature = "decimal")]
+        assert_eq!(eval_no_panic::<Decimal>("sum([1/10, 2/10])").map(|res| res.to_string()), Ok("0.3".to_string()));
+        // Lists only exist inside an expression
+        let err = eval_no_panic::<i128>("1 + [1, 2]").unwrap_err();
+        assert_eq!(err, EvalError::Mismatch{expected: "a number, not a list", span: 4..10}.into());
+        let err = eval_no_panic::<i128>("let a = [1] in a * 2").unwrap_err();
+        assert_eq!(err, EvalError::Mismatch{expected: "a number, not a list", span: 15..16}.into());
+        let err = eval_no_panic::<i128>("[1, 2][2]").unwrap_err();
+        assert_eq!(err, EvalError::IndexOutOfRange{index: 2, len: 2, span: 0..9}.into());
+        let err = eval_no_panic::<f64>("[1, 2][1/2]").unwrap_err();
+        assert_eq!(err, EvalError::Mismatch{expected: "an integer", span: 7..10}.into());
+        let err = eval_no_panic::<i128>("len(0..0x1_0000_0000)").unwrap_err();
+        assert_eq!(err, EvalError::ListTooLong{limit: generator::MAX_LIST_LEN, span: 4..20}.into());
+        let err = eval_no_panic::<i128>("sum([0x7FFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF, 1])").unwrap_err();
+        assert_eq!(err, EvalError::Overflow{span: 0..51}.into());
+    }
+
+    #[test]
+    fn test_series() {
+        use error::EvalError;
+
+        assert_eq!(eval_no_panic::<i128>("sum(i, 1, 100, i*i)"), Ok(338350));
+        assert_eq!(eval_no_panic::<i128>("prod(k, 1, 10, k) + sum(i, 3, 1, 1/0)"), Ok(3628800));
+        // The bound variable hides the outer one, only in the body
+        assert_eq!(eval_no_panic::<i128>("let i = 10 in sum(i, 1, i, sum(j, 1, i, j)) + i"), Ok(230));
+        assert_eq!(eval_no_panic::<Rational>("sum(n, 1, 3, 1/n)"), Ok(Rational::new(11, 6).unwrap()));
+        assert_eq!(eval_no_panic::<f64>("prod(k, 1, 3, 1/2)"), Ok(0.125));
+        let err = eval_no_panic::<i128>("sum(i, 0, 0x1_0000_0000, i)").unwrap_err();
+        assert_eq!(err, EvalError::TooManyIterations{limit: generator::MAX_ITERATIONS, span: 0..27}.into());
+        let err = eval_no_panic::<i128>("prod(k, 1, 100, k)").unwrap_err();
+        assert_eq!(err, EvalError::Overflow{span: 0..18}.into());
+        let err = eval_no_panic::<f64>("sum(i, 1/2, 3, i)").unwrap_err();
+        assert_eq!(err, EvalError::Mismatch{expected: "an integer", span: 7..10}.into());
+        let calc = Calculator::<i128>::new().limits(Limits::strict());
+        assert!(matches!(calc.eval("sum(i, 1, 1000, i)"), Err(Error::Eval(EvalError::TooManyOperations{..}))));
+    }
+
+    #[test]
+    fn test_no_panic() {
+        let inputs = [
+            "1/0", "0x8000_0000_0000_0000*0x8000_0000_0000_0000*-2/-1",
+            "0xFFFF_FFFF_FFFF_FFFF*0xFFFF_FFFF_FFFF_FFFF*0xFFFF_FFFF_FFFF_FFFF",
+            "-(0x8000_0000_0000_0000*0x8000_0000_0000_0000*-2)", "((((", "))))", "", "\u{1F600}",
+        ];
+        for input in inputs.iter() {
+            assert!(eval_no_panic::<i128>(input).is_err(), "input: {}", input);
+            let _ = eval_no_panic::<f64>(input);
+            let _ = eval_no_panic::<Rational>(input);
+            #[cfg(feature = "decimal")]
+            let _ = eval_no_panic::<Decimal>(input);
+        }
+    }
+
+    #[test]
+    fn test_fast() {
+        let inputs = ["1+2*(3-4)/-5", "7/2", "1/0", "(((2))", "1 + $", "f(1)", "(2)(1)", "0x1_0000_0000_0000_0000", ""];
+        for input in inputs.iter() {
+            assert_eq!(eval_str_fast::<i128>(input), eval_no_panic::<i128>(input), "input: {}", input);
+            assert_eq!(eval_str_fast::<f64>(input), eval_no_panic::<f64>(input), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_no_stack_overflow() {
+        // Run with the default stack size of spawned threads
+        std::thread::spawn(|| {
+            let depth = parser::MAX_DEPTH;
+            let inputs = [
+                "(".repeat(depth) + "1" + &")".repeat(depth),
+                "-".repeat(depth) + "1",
+                vec!["1"; parser::MAX_OPERATORS + 1].join("+"),
+                vec!["2"; parser::MAX_OPERATORS + 1].join("/"),
+                "1*(".repeat(depth / 2) + "1" + &")".repeat(depth / 2),
+            ];
+            for input in inputs.iter() {
+                assert!(eval_no_panic::<i128>(input).is_ok());
+                assert!(eval_no_panic::<f64>(input).is_ok());
+            }
+            assert!(eval_no_panic::<i128>(&"(".repeat(1_000_000)).is_err());
+            assert!(eval_no_panic::<i128>(&"-".repeat(1_000_000)).is_err());
+            assert!(eval_no_panic::<i128>(&"1+".repeat(1_000_000)).is_err());
+        }).join().unwrap();
+    }
+
+    static_assertions::assert_impl_all!(lexer::Token: Send, Sync);
+    static_assertions::assert_impl_all!(AST: Send, Sync);
+    static_assertions::assert_impl_all!(Error: Send, Sync);
+    static_assertions::assert_impl_all!(FunctionRegistry<f64>: Send, Sync);
+    static_assertions::assert_impl_all!(Calculator<f64>: Send, Sync);
+
+    #[test]
+    fn test_threads() {
+        let handles: Vec<_> = (0..8).map(|id| {
+            std::thread::spawn(move || {
+                for i in 0..500u64 {
+                    let expr = format!("{}*({}+1)", id, i);
+                    let res: i128 = calculator(&expr, parser::top_down_parser::parse).unwrap();
+                    assert_eq!(res, (id * (i + 1)) as i128);
+
+                    let expr = format!("{}+{}{}", id, i, "9".repeat(40));
+                    let err = lexer::lexer(&expr).unwrap_err();
+                    assert!(err.to_string().ends_with(&expr[2..]), "{} got {}", expr, err);
+                }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
 }
@@ -0,0 +1,68 @@
+//! Caps on the resources of a calculation.
+//!
+//! Each stage checks its own limits, so the error points at the
+//! part of the input that crossed it:
+//! * the lexer checks the length of the input and the number of
+//!   tokens
+//! * the parser checks the depth of nested expressions
+//! * the calculator checks the number of operations and the depth
+//!   of the calls of the user functions
+use crate::generator::MAX_CALL_DEPTH;
+use crate::parser::MAX_DEPTH;
+
+/// Limits of a [`Calculator`](crate::Calculator).
+///
+/// The default only limits the depths, to [`MAX_DEPTH`] and
+/// [`MAX_CALL_DEPTH`]. Set the other fields before calculating
+/// untrusted input.
+///
+/// # Example
+/// ```
+/// use wcal::{Calculator, Limits};
+///
+/// let calc = Calculator::<i128>::new().limits(Limits{input_len: 16, ..Limits::default()});
+/// assert_eq!(calc.eval("1+2"), Ok(3));
+/// assert!(calc.eval("1+2+3+4+5+6+7+8+9").is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Max length of the input in bytes.
+    pub input_len: usize,
+    /// Max number of tokens.
+    pub tokens: usize,
+    /// Max depth of nested expressions. The parser, the calculators
+    /// and the other passes over the AST recurse once per level, so
+    /// a depth over [`MAX_DEPTH`] needs them to run on a thread with
+    /// a larger stack.
+    pub depth: usize,
+    /// Max number of nodes to calculate.
+    pub operations: usize,
+    /// Max depth of nested calls of the user functions, capped by
+    /// [`MAX_CALL_DEPTH`].
+    pub calls: usize,
+}
+
+impl Limits {
+    /// Limits for a public-facing calculator.
+    pub fn strict() -> Self {
+        Limits{
+            input_len: 1024,
+            tokens: 512,
+            depth: 64,
+            operations: 1024,
+            calls: 16,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits{
+            input_len: usize::MAX,
+            tokens: usize::MAX,
+            depth: MAX_DEPTH,
+            operations: usize::MAX,
+            calls: MAX_CALL_DEPTH,
+        }
+    }
+}
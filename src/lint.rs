@@ -0,0 +1,232 @@
+//! Advice on expressions that calculate fine but could be written
+//! more simply, found without calculating them.
+//!
+//! The lints are advisory: each names a span and what to change,
+//! and none changes the result.
+//!
+//! # Example
+//! ```
+//! use wcal::{lint, Calculator};
+//! use wcal::lint::Lint;
+//!
+//! let calc = Calculator::<i128>::new();
+//! let ast = calc.parse("(x * 1) + 60 * 60").unwrap();
+//! assert_eq!(lint::lint(&ast), [
+//!     Lint::RedundantParens{span: 0..7},
+//!     Lint::TrivialFactor{factor: 1, span: 1..6},
+//!     Lint::Constant{value: 3600, span: 10..17},
+//! ]);
+//! assert_eq!(lint::lint(&ast)[2].to_string(), "Constant expression, could be written 3600");
+//! ```
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::lexer::{Span, Token};
+use crate::parser::ast::{Expr, AST};
+use crate::pretty;
+
+/// Nesting of brackets past which [`Lint::DeepNesting`] is reported.
+pub const MAX_NESTING: usize = 8;
+
+/// Advice on a span of the expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lint {
+    /// Parentheses the precedence doesn't need, such as `(a * b) + c`.
+    RedundantParens { span: Span },
+    /// `--x`, which is `x`, or `!!x`, which is `x != 0`.
+    DoubleNegation { op: Token, span: Span },
+    /// `x * 0`, which is `0`, or `x * 1`, which is `x`.
+    TrivialFactor { factor: u8, span: Span },
+    /// `+`, `-` and `*` of literals only, such as `60 * 60`, which
+    /// could be written as their value. Divisions are kept, as
+    /// their value depends on the backend.
+    Constant { value: i128, span: Span },
+    /// Brackets, calls, lists and indices nested deeper than
+    /// [`MAX_NESTING`], the outermost node past it.
+    DeepNesting { depth: usize, span: Span },
+}
+
+impl Lint {
+    pub fn span(&self) -> Span {
+        match self {
+            Lint::RedundantParens { span } => span.clone(),
+            Lint::DoubleNegation { span, .. } => span.clone(),
+            Lint::TrivialFactor { span, .. } => span.clone(),
+            Lint::Constant { span, .. } => span.clone(),
+            Lint::DeepNesting { span, .. } => span.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lint::RedundantParens { .. } => write!(f, "Redundant parentheses"),
+            Lint::DoubleNegation { op: Token::Not, .. } => write!(f, "Double negation, !!x is x != 0"),
+            Lint::DoubleNegation { .. } => write!(f, "Double negation, --x is x"),
+            Lint::TrivialFactor { factor: 0, .. } => write!(f, "Multiplication by 0, always 0"),
+            Lint::TrivialFactor { .. } => write!(f, "Multiplication by 1, no effect"),
+            Lint::Constant { value, .. } => write!(f, "Constant expression, could be written {}", value),
+            Lint::DeepNesting { depth, .. } => write!(f, "Nesting of depth {}, past {}", depth, MAX_NESTING),
+        }
+    }
+}
+
+/// Lints of the expression, in the order of their spans, the outer
+/// ones first.
+pub fn lint(ast: &AST) -> Vec<Lint> {
+    let mut lints: Vec<Lint> = pretty::redundant_parens(ast).into_iter()
+        .map(|span| Lint::RedundantParens{span})
+        .collect();
+    visit(&ast.root, 0, false, &mut lints);
+    // Stable, so the outer ones stay first at the same start
+    lints.sort_by_key(|lint| lint.span().start);
+    lints
+}
+
+fn visit(expr: &Expr, depth: usize, folded: bool, lints: &mut Vec<Lint>) {
+    let depth = if nests(expr) { depth + 1 } else { depth };
+    if depth == MAX_NESTING + 1 && nests(expr) {
+        lints.push(Lint::DeepNesting{depth: max_depth(expr, depth), span: expr.span()});
+    }
+    let mut folded = folded;
+    match expr {
+        // A constant in parentheses spans them
+        Expr::Pair(pair) if !folded && matches!(unparen(&pair.expr), Expr::BinOp(bin) if bin.op != Token::Division) => {
+            if let Some(value) = constant(expr) {
+                lints.push(Lint::Constant{value, span: pair.span.clone()});
+                folded = true;
+            }
+        }
+        Expr::BinOp(bin) if !folded => {
+            if let Some(value) = constant(expr).filter(|_| bin.op != Token::Division) {
+                lints.push(Lint::Constant{value, span: bin.span.clone()});
+                folded = true;
+            } else if bin.op == Token::Times {
+                let factor = [&bin.lhs, &bin.rhs].iter().find_map(|operand| match literal(operand) {
+                    Some(n @ (0 | 1)) => Some(n as u8),
+                    _ => None,
+                });
+                if let Some(factor) = factor {
+                    lints.push(Lint::TrivialFactor{factor, span: bin.span.clone()});
+                }
+            }
+        }
+        Expr::Neg(neg) if matches!(unparen(&neg.expr), Expr::Neg(_)) => {
+            lints.push(Lint::DoubleNegation{op: Token::Minus, span: neg.span.clone()});
+        }
+        Expr::Not(not) if matches!(unparen(&not.expr), Expr::Not(_)) => {
+            lints.push(Lint::DoubleNegation{op: Token::Not, span: not.span.clone()});
+        }
+        _ => {}
+    }
+    for child in expr.children() {
+        visit(child, depth, folded, lints);
+    }
+}
+
+/// Whether the node brackets its children.
+fn nests(expr: &Expr) -> bool {
+    matches!(expr, Expr::Pair(_) | Expr::Abs(_) | Expr::List(_) | Expr::Index(_) | Expr::Call(_))
+}
+
+/// Deepest nesting below the node at `depth`.
+fn max_depth(expr: &Expr, depth: usize) -> usize {
+    expr.children().into_iter()
+        .map(|child| max_depth(child, if nests(child) { depth + 1 } else { depth }))
+        .max()
+        .unwrap_or(depth)
+}
+
+fn unparen(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Pair(pair) => unparen(&pair.expr),
+        expr => expr,
+    }
+}
+
+/// Value of a literal, in parentheses or not.
+fn literal(expr: &Expr) -> Option<u128> {
+    match unparen(expr) {
+        Expr::Num(num) => Some(num.num),
+        _ => None,
+    }
+}
+
+/// Value of the `+`, `-`, `*` and `/` of literals, `None` if a
+/// calculation overflows or divides by zero.
+fn constant(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Pair(pair) => constant(&pair.expr),
+        Expr::Num(num) => i128::try_from(num.num).ok(),
+        Expr::Neg(neg) => constant(&neg.expr)?.checked_neg(),
+        Expr::BinOp(bin) => {
+            let (lhs, rhs) = (constant(&bin.lhs)?, constant(&bin.rhs)?);
+            match bin.op {
+                Token::Plus => lhs.checked_add(rhs),
+                Token::Minus => lhs.checked_sub(rhs),
+                Token::Times => lhs.checked_mul(rhs),
+                // Only its operands are folded
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+    use crate::parser::top_down_parser;
+
+    fn lints(input: &str) -> Vec<Lint> {
+        lint(&top_down_parser::parse(lexer::spanned(input).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_parens() {
+        assert_eq!(lints("(a * b) + c"), [Lint::RedundantParens{span: 0..7}]);
+        assert_eq!(lints("((a + b)) * c"), [Lint::RedundantParens{span: 1..8}]);
+        assert_eq!(lints("f((x)) + (1 - (y - z))"), [Lint::RedundantParens{span: 2..5}]);
+        assert!(lints("(a < b) < c").is_empty());
+        assert!(lints("|(a || b)|").is_empty());
+    }
+
+    #[test]
+    fn test_negation() {
+        assert_eq!(lints("--x"), [Lint::DoubleNegation{op: Token::Minus, span: 0..3}]);
+        assert_eq!(lints("!(!x)"), [Lint::DoubleNegation{op: Token::Not, span: 0..5}, Lint::RedundantParens{span: 1..5}]);
+        assert_eq!(lints("!!x")[0].to_string(), "Double negation, !!x is x != 0");
+        assert!(lints("-!x").is_empty());
+    }
+
+    #[test]
+    fn test_factor() {
+        assert_eq!(lints("x * 0"), [Lint::TrivialFactor{factor: 0, span: 0..5}]);
+        assert_eq!(lints("1 * f(y)"), [Lint::TrivialFactor{factor: 1, span: 0..8}]);
+        assert!(lints("x * 2 + x / 1").is_empty());
+    }
+
+    #[test]
+    fn test_constant() {
+        assert_eq!(lints("x + 60 * 60 * 24"), [Lint::Constant{value: 86400, span: 4..16}]);
+        // The outermost only, and the operands of a division
+        assert_eq!(lints("(2 + 3) * -4 / (1 - 1)"), [
+            Lint::Constant{value: -20, span: 0..12},
+            Lint::Constant{value: 0, span: 15..22},
+        ]);
+        assert_eq!(lints("x * (1 * 2)"), [Lint::Constant{value: 2, span: 4..11}]);
+        assert!(lints("7 / 2 + 1h").is_empty());
+        assert!(lints("0x7FFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF + 1").is_empty());
+    }
+
+    #[test]
+    fn test_nesting() {
+        let deep = "f(".repeat(MAX_NESTING) + "[x][0]" + &")".repeat(MAX_NESTING);
+        let found = lints(&deep);
+        assert_eq!(found, [Lint::DeepNesting{depth: MAX_NESTING + 2, span: 2 * MAX_NESTING..2 * MAX_NESTING + 6}]);
+        assert_eq!(found[0].to_string(), format!("Nesting of depth {}, past {}", MAX_NESTING + 2, MAX_NESTING));
+        assert!(lints(&("g(".repeat(MAX_NESTING) + "x" + &")".repeat(MAX_NESTING))).is_empty());
+    }
+}
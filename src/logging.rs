@@ -0,0 +1,74 @@
+//! Messages raised while calculating.
+//!
+//! With the `log` feature, messages go through the `log` facade
+//! under the `wcal` target, with the values as structured
+//! key-values, so the host application controls destination and
+//! verbosity. Without it, warnings are printed to stderr and
+//! debug messages are dropped.
+
+/// Log a warning, such as a division cast.
+macro_rules! warn {
+    ($($key: ident = $value: expr),* ; $($arg: tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::warn!(target: "wcal", $($key = $value),* ; $($arg)+);
+        #[cfg(not(feature = "log"))]
+        {
+            $(let _ = &$value;)*
+            eprintln!("Warning: {}", format_args!($($arg)+));
+        }
+    }};
+}
+
+/// Log the progress of the pipeline, only with the `log` feature.
+macro_rules! debug {
+    ($($key: ident = $value: expr),* ; $($arg: tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::debug!(target: "wcal", $($key = $value),* ; $($arg)+);
+        #[cfg(not(feature = "log"))]
+        {
+            $(let _ = &$value;)*
+        }
+    }};
+}
+
+#[cfg(all(test, feature = "log"))]
+mod tests {
+    use std::sync::Mutex;
+
+    use log::kv::Key;
+    use log::{Level, Log, Metadata, Record};
+
+    struct TestLogger {
+        records: Mutex<Vec<(Level, String, Option<String>)>>,
+    }
+
+    impl Log for TestLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == "wcal"
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                let lhs = record.key_values().get(Key::from_str("lhs")).map(|v| v.to_string());
+                self.records.lock().unwrap().push((record.level(), record.args().to_string(), lhs));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: TestLogger = TestLogger{records: Mutex::new(Vec::new())};
+
+    #[test]
+    fn test_log_warning() {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let res: i128 = crate::calculator("7/2", crate::parser::top_down_parser::parse).unwrap();
+        assert_eq!(res, 3);
+
+        let records = LOGGER.records.lock().unwrap();
+        assert!(records.contains(&(Level::Warn, "division will cause a cast".to_string(), Some("7".to_string()))));
+        assert!(records.iter().any(|(level, _, _)| *level == Level::Debug));
+    }
+}
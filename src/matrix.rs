@@ -0,0 +1,164 @@
+//! Matrices such as `[[1, 2], [3, 4]]`, and the result of the matrix
+//! calculator.
+//!
+//! The items are exact like [`Rational`], and a vector such as
+//! `[1, 2, 3]` is a matrix of one row.
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::rational::Rational;
+
+/// Result of the matrix calculator: a number, or a matrix of rows
+/// of the same length, never empty.
+///
+/// Matrices of the same shape add and subtract item by item, and
+/// are multiplied or divided by numbers. A matrix times a matrix is
+/// their product, `hadamard(a, b)` their product item by item,
+/// `transpose(m)` swaps the rows and the columns and `det(m)` is the
+/// determinant of a square matrix. An index is a row of a matrix,
+/// or an item of a vector.
+///
+/// # Example
+/// ```
+/// use wcal::{Matrix, Rational};
+///
+/// let res: Matrix = wcal::eval_no_panic("[[1, 2], [3, 4]] * [[0, 1], [1, 0]]").unwrap();
+/// assert_eq!(res.to_string(), "[[2, 1], [4, 3]]");
+/// assert_eq!(wcal::eval_no_panic::<Matrix>("transpose([1, 2]) / 2").unwrap().to_string(), "[[1/2], [1]]");
+/// assert_eq!(wcal::eval_no_panic::<Matrix>("det([[1, 2], [3, 4]])"), Ok(Matrix::Number(Rational::from(-2))));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Matrix {
+    Number(Rational),
+    /// Rows of the same length
+    Rows(Vec<Vec<Rational>>),
+}
+
+impl Matrix {
+    /// Rows and columns, `None` for a number.
+    pub fn shape(&self) -> Option<(usize, usize)> {
+        match self {
+            Matrix::Number(_) => None,
+            Matrix::Rows(rows) => Some((rows.len(), rows[0].len())),
+        }
+    }
+
+    /// Rows as columns, a number is the same.
+    pub fn transpose(&self) -> Matrix {
+        match self {
+            Matrix::Number(_) => self.clone(),
+            Matrix::Rows(rows) => Matrix::Rows((0..rows[0].len()).map(|j| rows.iter().map(|row| row[j]).collect()).collect()),
+        }
+    }
+
+    /// Determinant of a square matrix, `None` for the other values
+    /// and if it overflows.
+    pub fn det(&self) -> Option<Rational> {
+        let mut rows = match self {
+            Matrix::Rows(rows) if rows.len() == rows[0].len() => rows.clone(),
+            _ => return None,
+        };
+        // Gaussian elimination, each swap of rows changes the sign
+        let mut det = Rational::from(1);
+        for col in 0..rows.len() {
+            let pivot = match (col..rows.len()).find(|&row| rows[row][col].numer() != 0) {
+                Some(pivot) => pivot,
+                None => return Some(Rational::from(0)),
+            };
+            if pivot != col {
+                rows.swap(pivot, col);
+                det = det.checked_neg()?;
+            }
+            det = det.checked_mul(rows[col][col])?;
+            let (above, below) = rows.split_at_mut(col + 1);
+            let pivot = &above[col];
+            for row in below {
+                let factor = row[col].checked_div(pivot[col])?;
+                for (item, above) in row.iter_mut().zip(pivot).skip(col) {
+                    *item = item.checked_sub(factor.checked_mul(*above)?)?;
+                }
+            }
+        }
+        Some(det)
+    }
+
+    /// Item by item of two matrices of the same shape, `None` if
+    /// an item is.
+    pub(crate) fn zip_with<F>(lhs: &[Vec<Rational>], rhs: &[Vec<Rational>], f: F) -> Option<Matrix>
+    where F: Fn(Rational, Rational) -> Option<Rational> {
+        lhs.iter().zip(rhs)
+            .map(|(l, r)| l.iter().zip(r).map(|(l, r)| f(*l, *r)).collect())
+            .collect::<Option<_>>()
+            .map(Matrix::Rows)
+    }
+
+    /// Each item mapped, `None` if an item is.
+    pub(crate) fn map<F>(rows: &[Vec<Rational>], f: F) -> Option<Matrix>
+    where F: Fn(Rational) -> Option<Rational> {
+        rows.iter()
+            .map(|row| row.iter().map(|item| f(*item)).collect())
+            .collect::<Option<_>>()
+            .map(Matrix::Rows)
+    }
+
+    /// Product of a matrix of `n` columns by one of `n` rows, `None`
+    /// if it overflows.
+    pub(crate) fn product(lhs: &[Vec<Rational>], rhs: &[Vec<Rational>]) -> Option<Matrix> {
+        lhs.iter()
+            .map(|row| (0..rhs[0].len())
+                .map(|j| row.iter().zip(rhs).try_fold(Rational::from(0), |sum, (l, r)| sum.checked_add(l.checked_mul(r[j])?)))
+                .collect())
+            .collect::<Option<_>>()
+            .map(Matrix::Rows)
+    }
+}
+
+impl fmt::Display for Matrix {
+    /// The number, `[1, 2]` for a vector, `[[1, 2], [3, 4]]` for
+    /// the other matrices.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let row = |row: &[Rational]| format!("[{}]", row.iter().map(Rational::to_string).collect::<Vec<_>>().join(", "));
+        match self {
+            Matrix::Number(value) => write!(f, "{}", value),
+            Matrix::Rows(rows) if rows.len() == 1 => write!(f, "{}", row(&rows[0])),
+            Matrix::Rows(rows) => write!(f, "[{}]", rows.iter().map(|items| row(items)).collect::<Vec<_>>().join(", ")),
+        }
+    }
+}
+
+impl From<Rational> for Matrix {
+    fn from(value: Rational) -> Self {
+        Matrix::Number(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[&[i128]]) -> Matrix {
+        Matrix::Rows(rows.iter().map(|row| row.iter().map(|n| Rational::from(*n)).collect()).collect())
+    }
+
+    #[test]
+    fn test_det() {
+        assert_eq!(rows(&[&[3]]).det(), Some(Rational::from(3)));
+        assert_eq!(rows(&[&[1, 2], &[3, 4]]).det(), Some(Rational::from(-2)));
+        // A pivot swapped, and a singular matrix
+        assert_eq!(rows(&[&[0, 1, 2], &[1, 0, 3], &[4, -3, 8]]).det(), Some(Rational::from(-2)));
+        assert_eq!(rows(&[&[1, 2], &[2, 4]]).det(), Some(Rational::from(0)));
+        assert_eq!(rows(&[&[1, 2, 3]]).det(), None);
+        assert_eq!(Matrix::Number(Rational::from(2)).det(), None);
+    }
+
+    #[test]
+    fn test_transpose() {
+        assert_eq!(rows(&[&[1, 2, 3]]).transpose(), rows(&[&[1], &[2], &[3]]));
+        assert_eq!(rows(&[&[1, 2], &[3, 4]]).transpose().to_string(), "[[1, 3], [2, 4]]");
+        assert_eq!(rows(&[&[1, 2]]).shape(), Some((1, 2)));
+    }
+}
@@ -0,0 +1,93 @@
+//! Node.js bindings, only with the `node` feature.
+//!
+//! Build the addon with `cargo rustc --lib --release --features node
+//! --crate-type cdylib` and load the library renamed to `wcal.node`:
+//! ```js
+//! const wcal = require('./wcal.node');
+//!
+//! wcal.evalInt('7/2');          // 3n, a BigInt like the i128 results
+//! wcal.evalFloat('7/2');        // 3.5
+//! wcal.parse('1+2').op;         // '+', see `export::json`
+//! try {
+//!     wcal.evalInt('1/0');
+//! } catch (err) {
+//!     err.code;                 // 'EvalError'
+//!     err.message;              // 'Division by zero'
+//! }
+//! ```
+//! The expressions are calculated exactly like [`Calculator::eval`],
+//! warnings are ignored.
+use napi::bindgen_prelude::BigInt as JsBigInt;
+use napi_derive::napi;
+use serde_json::Value as Json;
+
+use crate::{Calculator, Error};
+use crate::parser::export;
+
+/// Kind of the errors, the `code` of the thrown JS errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    LexError,
+    ParseError,
+    EvalError,
+}
+
+impl AsRef<str> for ErrorKind {
+    fn as_ref(&self) -> &str {
+        match self {
+            ErrorKind::LexError => "LexError",
+            ErrorKind::ParseError => "ParseError",
+            ErrorKind::EvalError => "EvalError",
+        }
+    }
+}
+
+impl From<Error> for napi::Error<ErrorKind> {
+    fn from(err: Error) -> Self {
+        let kind = match err {
+            Error::Lex(_) => ErrorKind::LexError,
+            Error::Parse(_) => ErrorKind::ParseError,
+            Error::Eval(_) => ErrorKind::EvalError,
+        };
+        napi::Error::new(kind, err.message())
+    }
+}
+
+type Result<T> = napi::Result<T, ErrorKind>;
+
+/// Calculate the expression as `i128`, return a BigInt.
+#[napi(js_name = "evalInt")]
+pub fn eval_int(expr: String) -> Result<JsBigInt> {
+    Ok(Calculator::<i128>::new().eval(&expr)?.into())
+}
+
+/// Calculate the expression as `f64`.
+#[napi(js_name = "evalFloat")]
+pub fn eval_float(expr: String) -> Result<f64> {
+    Ok(Calculator::<f64>::new().eval(&expr)?)
+}
+
+/// Tree of the expression as the object of [`export::json`].
+#[napi]
+pub fn parse(expr: String) -> Result<Json> {
+    let ast = Calculator::<i128>::new().parse(&expr)?;
+    Ok(export::json(&ast))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings() {
+        let res = eval_int("-7/2".to_string()).unwrap();
+        assert_eq!((res.sign_bit, res.words), (true, vec![3, 0]));
+        assert_eq!(eval_float("7/2".to_string()).unwrap(), 3.5);
+        assert_eq!(parse("x".to_string()).unwrap()["node"], "var");
+
+        let kind = |err: napi::Error<ErrorKind>| (err.status, err.reason);
+        assert_eq!(kind(eval_int("1/0".to_string()).unwrap_err()), (ErrorKind::EvalError, "Division by zero".to_string()));
+        assert_eq!(kind(eval_float("1 $".to_string()).unwrap_err()).0, ErrorKind::LexError);
+        assert_eq!(kind(parse("(1".to_string()).unwrap_err()).0, ErrorKind::ParseError);
+    }
+}
@@ -0,0 +1,517 @@
+//! Optimizer of the arithmetic of an expression by equality
+//! saturation, with the `optimize` feature.
+//!
+//! An e-graph holds classes of equal expressions, sharing their
+//! operands. The rules of [`RULES`] add the rewrites of each class
+//! to it until nothing new is found, then the cheapest expression of
+//! the root class is extracted: every node costs 1.
+//!
+//! The `+ - * /` of the numbers, the variables and the negations
+//! are rewritten, the other nodes are kept, their operands
+//! optimized. The rules hold for the exact arithmetic, and keep
+//! every operand which may fail, so `x * 0` and `x - x` stay. The
+//! overflows of the intermediate results of `i128` and the rounding
+//! of `f64` may change.
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::iter::Peekable;
+
+use crate::lexer::{Span, Token};
+use crate::parser::ast::*;
+
+/// Rewrites of the arithmetic, `?a` matches any operand. Constants
+/// are folded apart, the divisions only if exact.
+const RULES: &[(&str, &str)] = &[
+    ("(+ ?a ?b)", "(+ ?b ?a)"),
+    ("(* ?a ?b)", "(* ?b ?a)"),
+    ("(+ (+ ?a ?b) ?c)", "(+ ?a (+ ?b ?c))"),
+    ("(+ ?a (+ ?b ?c))", "(+ (+ ?a ?b) ?c)"),
+    ("(* (* ?a ?b) ?c)", "(* ?a (* ?b ?c))"),
+    ("(* ?a (* ?b ?c))", "(* (* ?a ?b) ?c)"),
+    ("(- ?a ?b)", "(+ ?a (- ?b))"),
+    ("(+ ?a (- ?b))", "(- ?a ?b)"),
+    ("(- (- ?a))", "?a"),
+    ("(- 0 ?a)", "(- ?a)"),
+    ("(* ?a (- ?b))", "(- (* ?a ?b))"),
+    ("(- (* ?a ?b))", "(* ?a (- ?b))"),
+    ("(+ (* ?a ?b) (* ?a ?c))", "(* ?a (+ ?b ?c))"),
+    ("(+ ?a 0)", "?a"),
+    ("(- ?a 0)", "?a"),
+    ("(* ?a 1)", "?a"),
+    ("(/ ?a 1)", "?a"),
+];
+
+/// Rounds of the rules, and nodes of the e-graph, past which the
+/// saturation stops.
+const MAX_ROUNDS: usize = 16;
+const MAX_NODES: usize = 10_000;
+
+type Id = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Num(u128),
+    Var(String),
+    Op(Op, Vec<Id>),
+    /// Node kept as is, an index of `EGraph::kept`
+    Kept(usize),
+}
+
+/// Rule of [`RULES`], its holes numbered from 0.
+struct Rule {
+    lhs: Pattern,
+    rhs: Pattern,
+    holes: usize,
+}
+
+#[derive(Debug)]
+enum Pattern {
+    Hole(usize),
+    Num(u128),
+    Op(Op, Vec<Pattern>),
+}
+
+/// Class of equal nodes.
+#[derive(Debug, Default)]
+struct Class {
+    nodes: Vec<Node>,
+    /// Value of the constant expressions
+    value: Option<i128>,
+    /// Span of the first expression of the class
+    span: Span,
+}
+
+#[derive(Debug, Default)]
+struct EGraph {
+    /// Union-find of the classes, a root is its own parent
+    parents: Vec<Id>,
+    classes: Vec<Class>,
+    memo: HashMap<Node, Id>,
+    kept: Vec<Expr>,
+}
+
+impl EGraph {
+    fn find(&self, mut id: Id) -> Id {
+        while self.parents[id] != id {
+            id = self.parents[id];
+        }
+        id
+    }
+
+    fn canonical(&self, node: &Node) -> Node {
+        match node {
+            Node::Op(op, args) => Node::Op(*op, args.iter().map(|&arg| self.find(arg)).collect()),
+            node => node.clone(),
+        }
+    }
+
+    fn add(&mut self, node: Node, span: &Span) -> Id {
+        let node = self.canonical(&node);
+        if let Some(&id) = self.memo.get(&node) {
+            return self.find(id)
+        }
+        let id = self.parents.len();
+        self.parents.push(id);
+        self.classes.push(Class{nodes: vec![node.clone()], value: None, span: span.clone()});
+        self.memo.insert(node.clone(), id);
+        if let Some(value) = self.fold(&node) {
+            self.set_value(id, value);
+        }
+        id
+    }
+
+    /// Merge the classes, return whether they were apart.
+    fn union(&mut self, a: Id, b: Id) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false
+        }
+        self.parents[b] = a;
+        let class = std::mem::take(&mut self.classes[b]);
+        self.classes[a].nodes.extend(class.nodes);
+        if self.classes[a].value.is_none() {
+            self.classes[a].value = class.value;
+        }
+        true
+    }
+
+    /// Value of the node from the values of its operands.
+    fn fold(&self, node: &Node) -> Option<i128> {
+        let value = |id: &Id| self.classes[self.find(*id)].value;
+        match node {
+            Node::Num(num) => i128::try_from(*num).ok(),
+            Node::Op(Op::Neg, args) => value(&args[0])?.checked_neg(),
+            Node::Op(op, args) => {
+                let (lhs, rhs) = (value(&args[0])?, value(&args[1])?);
+                match op {
+                    Op::Add => lhs.checked_add(rhs),
+                    Op::Sub => lhs.checked_sub(rhs),
+                    Op::Mul => lhs.checked_mul(rhs),
+                    _ if rhs != 0 && lhs.checked_rem(rhs) == Some(0) => lhs.checked_div(rhs),
+                    _ => None,
+                }
+            }
+            Node::Var(_) | Node::Kept(_) => None,
+        }
+    }
+
+    /// Add the constant to the class.
+    fn set_value(&mut self, id: Id, value: i128) {
+        self.classes[id].value = Some(value);
+        let span = self.classes[id].span.clone();
+        let num = self.add(Node::Num(value.unsigned_abs()), &span);
+        let constant = if value < 0 { self.add(Node::Op(Op::Neg, vec![num]), &span) } else { num };
+        self.union(id, constant);
+    }
+
+    /// Restore the congruence after the unions: the nodes of the same
+    /// operator and operands are in the same class.
+    fn rebuild(&mut self) {
+        loop {
+            let mut merges = Vec::new();
+            let mut values = Vec::new();
+            self.memo.clear();
+            for id in 0..self.classes.len() {
+                if self.find(id) != id {
+                    continue
+                }
+                let mut nodes: Vec<Node> = Vec::new();
+                for node in std::mem::take(&mut self.classes[id].nodes) {
+                    let node = self.canonical(&node);
+                    if !nodes.contains(&node) {
+                        nodes.push(node);
+                    }
+                }
+                for node in &nodes {
+                    match self.memo.get(node) {
+                        Some(&other) => merges.push((other, id)),
+                        None => {
+                            self.memo.insert(node.clone(), id);
+                        }
+                    }
+                    if self.classes[id].value.is_none() {
+                        if let Some(value) = self.fold(node) {
+                            values.push((id, value));
+                        }
+                    }
+                }
+                self.classes[id].nodes = nodes;
+            }
+            if merges.is_empty() && values.is_empty() {
+                return
+            }
+            for (a, b) in merges {
+                self.union(a, b);
+            }
+            for (id, value) in values {
+                let id = self.find(id);
+                if self.classes[id].value.is_none() {
+                    self.set_value(id, value);
+                }
+            }
+        }
+    }
+
+    /// Bindings of the holes for which the pattern matches the class.
+    fn matches(&self, pattern: &Pattern, id: Id, holes: Vec<Option<Id>>) -> Vec<Vec<Option<Id>>> {
+        let id = self.find(id);
+        match pattern {
+            Pattern::Hole(hole) => match holes[*hole] {
+                Some(bound) if self.find(bound) != id => Vec::new(),
+                _ => {
+                    let mut holes = holes;
+                    holes[*hole] = Some(id);
+                    vec![holes]
+                }
+            },
+            Pattern::Num(num) if self.classes[id].nodes.contains(&Node::Num(*num)) => vec![holes],
+            Pattern::Num(_) => Vec::new(),
+            Pattern::Op(op, patterns) => {
+                let mut found = Vec::new();
+                for node in &self.classes[id].nodes {
+                    let args = match node {
+                        Node::Op(node_op, args) if node_op == op && args.len() == patterns.len() => args,
+                        _ => continue,
+                    };
+                    let mut bindings = vec![holes.clone()];
+                    for (pattern, &arg) in patterns.iter().zip(args) {
+                        bindings = bindings.into_iter().flat_map(|holes| self.matches(pattern, arg, holes)).collect();
+                    }
+                    found.extend(bindings);
+                }
+                found
+            }
+        }
+    }
+
+    fn instantiate(&mut self, pattern: &Pattern, holes: &[Option<Id>], span: &Span) -> Id {
+        match pattern {
+            Pattern::Hole(hole) => holes[*hole].expect("hole bound by the left side"),
+            Pattern::Num(num) => self.add(Node::Num(*num), span),
+            Pattern::Op(op, patterns) => {
+                let args = patterns.iter().map(|pattern| self.instantiate(pattern, holes, span)).collect();
+                self.add(Node::Op(*op, args), span)
+            }
+        }
+    }
+
+    /// Add the expression, its parentheses dropped.
+    fn add_expr(&mut self, expr: &Expr) -> Id {
+        let span = expr.span();
+        let node = match expr {
+            Expr::Pair(pair) => return self.add_expr(&pair.expr),
+            Expr::Num(num) => Node::Num(num.num),
+            Expr::Var(var) => Node::Var(var.name.clone()),
+            Expr::Neg(neg) => Node::Op(Op::Neg, vec![self.add_expr(&neg.expr)]),
+            Expr::BinOp(bin) if binary(&bin.op).is_some() => {
+                let args = vec![self.add_expr(&bin.lhs), self.add_expr(&bin.rhs)];
+                Node::Op(binary(&bin.op).unwrap(), args)
+            }
+            expr => {
+                let mut expr = expr.clone();
+                for child in expr.children_mut() {
+                    *child = optimize(child);
+                }
+                let index = match self.kept.iter().position(|kept| *kept == expr) {
+                    Some(index) => index,
+                    None => {
+                        self.kept.push(expr);
+                        self.kept.len() - 1
+                    }
+                };
+                Node::Kept(index)
+            }
+        };
+        self.add(node, &span)
+    }
+
+    /// Apply the rules until nothing changes, or past the limits.
+    fn saturate(&mut self, rules: &[Rule]) {
+        for _ in 0..MAX_ROUNDS {
+            let mut found = Vec::new();
+            for rule in rules {
+                for id in 0..self.classes.len() {
+                    if self.find(id) == id {
+                        for holes in self.matches(&rule.lhs, id, vec![None; rule.holes]) {
+                            found.push((id, &rule.rhs, holes));
+                        }
+                    }
+                }
+            }
+            let mut changed = false;
+            for (id, rhs, holes) in found {
+                if self.parents.len() > MAX_NODES {
+                    break
+                }
+                let span = self.classes[self.find(id)].span.clone();
+                let new = self.instantiate(rhs, &holes, &span);
+                changed |= self.union(id, new);
+            }
+            self.rebuild();
+            if !changed || self.parents.len() > MAX_NODES {
+                return
+            }
+        }
+    }
+
+    /// Cheapest expression of the class.
+    fn extract(&self, root: Id) -> Expr {
+        let mut best: Vec<Option<(usize, &Node)>> = vec![None; self.classes.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in 0..self.classes.len() {
+                if self.find(id) != id {
+                    continue
+                }
+                for node in &self.classes[id].nodes {
+                    let cost = match node {
+                        Node::Op(_, args) => args.iter()
+                            .map(|&arg| best[self.find(arg)].map(|(cost, _)| cost))
+                            .sum::<Option<usize>>()
+                            .map(|cost| cost + 1),
+                        _ => Some(1),
+                    };
+                    if let Some(cost) = cost {
+                        if best[id].is_none_or(|(best, _)| cost < best) {
+                            best[id] = Some((cost, node));
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+        self.build(&best, root)
+    }
+
+    fn build(&self, best: &[Option<(usize, &Node)>], id: Id) -> Expr {
+        let id = self.find(id);
+        let span = self.classes[id].span.clone();
+        let node = match best[id] {
+            Some((_, node)) => node,
+            None => unreachable!("every class has a node without operands or with cheaper operands"),
+        };
+        let expr = match node {
+            Node::Num(num) => Number::new(*num),
+            Node::Var(name) => Var::new(name),
+            Node::Op(Op::Neg, args) => Neg::new(self.build(best, args[0])),
+            Node::Op(op, args) => {
+                let token = match op {
+                    Op::Add => Token::Plus,
+                    Op::Sub => Token::Minus,
+                    Op::Mul => Token::Times,
+                    _ => Token::Division,
+                };
+                BinOp::new(self.build(best, args[0]), self.build(best, args[1]), token)
+            }
+            Node::Kept(index) => return self.kept[*index].clone(),
+        };
+        expr.with_span(span)
+    }
+}
+
+fn binary(token: &Token) -> Option<Op> {
+    match token {
+        Token::Plus => Some(Op::Add),
+        Token::Minus => Some(Op::Sub),
+        Token::Times => Some(Op::Mul),
+        Token::Division => Some(Op::Div),
+        _ => None,
+    }
+}
+
+/// Parse a pattern of [`RULES`], numbering its holes.
+fn pattern(text: &str, holes: &mut Vec<String>) -> Pattern {
+    let text = text.replace('(', " ( ").replace(')', " ) ");
+    parse_pattern(&mut text.split_whitespace().peekable(), holes)
+}
+
+fn parse_pattern<'a, I>(words: &mut Peekable<I>, holes: &mut Vec<String>) -> Pattern
+where I: Iterator<Item = &'a str> {
+    match words.next() {
+        Some("(") => {
+            let op = words.next().expect("operator of the pattern");
+            let mut args = Vec::new();
+            while words.peek() != Some(&")") {
+                args.push(parse_pattern(words, holes));
+            }
+            words.next();
+            let op = match (op, args.len()) {
+                ("+", 2) => Op::Add,
+                ("-", 2) => Op::Sub,
+                ("*", 2) => Op::Mul,
+                ("/", 2) => Op::Div,
+                ("-", 1) => Op::Neg,
+                _ => panic!("unknown operator {} of the pattern", op),
+            };
+            Pattern::Op(op, args)
+        }
+        Some(word) if word.starts_with('?') => {
+            let index = holes.iter().position(|hole| hole == word).unwrap_or_else(|| {
+                holes.push(word.to_string());
+                holes.len() - 1
+            });
+            Pattern::Hole(index)
+        }
+        Some(word) => Pattern::Num(word.parse().expect("number of the pattern")),
+        None => panic!("empty pattern"),
+    }
+}
+
+fn optimize(expr: &Expr) -> Expr {
+    let rules: Vec<Rule> = RULES.iter()
+        .map(|(lhs, rhs)| {
+            let mut holes = Vec::new();
+            let lhs = pattern(lhs, &mut holes);
+            let rhs = pattern(rhs, &mut holes);
+            Rule{lhs, rhs, holes: holes.len()}
+        })
+        .collect();
+    let mut egraph = EGraph::default();
+    let root = egraph.add_expr(expr);
+    egraph.saturate(&rules);
+    egraph.extract(root)
+}
+
+impl AST {
+    /// Cheapest expression equal to this one, found by equality
+    /// saturation, such as `x * 2` for `(x + 0) * (3 - 1)`. See the
+    /// `optimize` module for the rules.
+    ///
+    /// # Example
+    /// ```
+    /// use wcal::Calculator;
+    ///
+    /// let calc = Calculator::<i128>::new();
+    /// let ast = calc.parse("a * b + a * -c + 2 * 3").unwrap();
+    /// assert_eq!(ast.optimize().to_string(), "(+ (* a (- b c)) 6)");
+    /// ```
+    pub fn optimize(&self) -> AST {
+        AST{root: optimize(&self.root)}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lexer, Calculator, Environment, FromAST};
+    use crate::parser::top_down_parser;
+    use super::*;
+
+    fn parse(input: &str) -> AST {
+        lexer::spanned(input).and_then(top_down_parser::parse).unwrap()
+    }
+
+    #[test]
+    fn test_optimize() {
+        let cases = [
+            ("(x + 0) * (3 - 1)", "(* x 2)"),
+            ("--x - -y", "(+ x y)"),
+            ("0 - x * 1", "(- x)"),
+            ("1 + x + 2", "(+ x 3)"),
+            ("a * b + a * c", "(* a (+ b c))"),
+            ("2 * 3 * x / 6", "(/ (* 6 x) 6)"),
+            ("7 / 2 + 8 / 4", "(+ (/ 7 2) 2)"),
+            ("x * 0 + (y - y)", "(+ (* x 0) (- y y))"),
+            ("f(1 + 1, |x - 0|) * 1", "(f 2 (abs x))"),
+            ("z = let h = 2 * 2 in h + 0", "(= z (let h 4 h))"),
+        ];
+        for (input, optimized) in cases.iter() {
+            assert_eq!(parse(input).optimize().to_string(), *optimized, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_same_value() {
+        let mut env = Environment::new();
+        for (name, value) in [("a", 7), ("b", -3), ("c", 12), ("x", 5), ("y", -2)].iter() {
+            env.set(name, *value);
+        }
+        let calc = Calculator::<i128>::new();
+        let inputs = [
+            "a * b + a * c - (b + 0)", "-(a - b) * -(c * 1) + 4 * 5", "|a - c| * (b + b) / 2", "a / b + 10 / 5",
+            "if a then x * 1 else y", "(1 + a) * (2 - b) - (a + 1) * c",
+        ];
+        for input in inputs.iter() {
+            let ast = parse(input);
+            let optimized = ast.optimize();
+            let eval = |ast: AST| i128::from_ast_in(ast, &mut calc.context().env(&mut env.clone()));
+            assert_eq!(eval(optimized.clone()), eval(ast), "input: {}, optimized: {}", input, optimized);
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let input = (1..40).map(|i| format!("x{}", i)).collect::<Vec<_>>().join(" + ");
+        let ast = parse(&input);
+        assert_eq!(ast.optimize().to_string().matches('+').count(), 38);
+    }
+}
@@ -0,0 +1,161 @@
+//! Calculate the large trees on the thread pool of `rayon`, with the
+//! `parallel` feature.
+//!
+//! The operands of the `+ - * /` of at least [`THRESHOLD`] nodes
+//! each are calculated concurrently, the threads stealing each
+//! other's work, and the smaller subtrees by the sequential
+//! calculator. A tree smaller than the threshold is only calculated
+//! by the sequential one. The trees of machine-generated
+//! expressions, with millions of nodes, gain the most.
+//!
+//! The `i128`, `f64` and [`Rational`](crate::Rational) calculators
+//! split the trees, the other ones calculate them sequentially. The
+//! result and the error are the ones of [`FromAST::from_ast_with`],
+//! the first error in the order of the operands.
+//!
+//! # Example
+//! ```
+//! use wcal::parser::ast::{BinOp, Number, AST};
+//! use wcal::lexer::Token;
+//! use wcal::{parallel, FunctionRegistry};
+//!
+//! // The sum of 1 to 2^16, balanced
+//! let mut level: Vec<_> = (1..=1 << 16).map(Number::new).collect();
+//! while level.len() > 1 {
+//!     level = level.chunks(2).map(|pair| BinOp::new(pair[0].clone(), pair[1].clone(), Token::Plus)).collect();
+//! }
+//! let ast = AST{root: level.pop().unwrap()};
+//! assert_eq!(parallel::calculate::<i128>(ast, &FunctionRegistry::new()), Ok(2147516416));
+//! ```
+use crate::error::Error;
+use crate::generator::{Arithmetic, Context};
+use crate::lexer::Token;
+use crate::parser::ast::{BinOp, Expr, AST};
+use crate::{FromAST, FunctionRegistry};
+
+/// Nodes of each operand past which they are calculated
+/// concurrently.
+pub const THRESHOLD: usize = 4096;
+
+/// Calculate the AST, splitting it on the thread pool if it is
+/// large, see the [module](self).
+pub fn calculate<T>(ast: AST, functions: &FunctionRegistry<T>) -> Result<T, Error>
+where T: FromAST + Send {
+    calculate_with_threshold(ast, functions, THRESHOLD)
+}
+
+/// Calculate the AST, calculating the operands of at least
+/// `threshold` nodes concurrently.
+pub fn calculate_with_threshold<T>(ast: AST, functions: &FunctionRegistry<T>, threshold: usize) -> Result<T, Error>
+where T: FromAST + Send {
+    match T::arithmetic() {
+        Some(arithmetic) if at_least(&ast.root, threshold) => split(ast.root, &arithmetic, functions, threshold.max(1)),
+        _ => T::from_ast_with(ast, functions),
+    }
+}
+
+fn split<T>(expr: Expr, arithmetic: &Arithmetic<T>, functions: &FunctionRegistry<T>, threshold: usize) -> Result<T, Error>
+where T: FromAST + Send {
+    match expr {
+        Expr::Pair(pair) => split(*pair.expr, arithmetic, functions, threshold),
+        Expr::Neg(neg) if !matches!(*neg.expr, Expr::Num(_)) && at_least(&neg.expr, threshold) => {
+            let value = split(*neg.expr, arithmetic, functions, threshold)?;
+            Ok((arithmetic.negate)(value, &neg.span)?)
+        }
+        Expr::BinOp(bin) if is_arithmetic(&bin.op) && at_least(&bin.lhs, threshold) && at_least(&bin.rhs, threshold) => {
+            let BinOp{lhs, rhs, op, span} = bin;
+            let (lhs, rhs) = rayon::join(
+                || split(*lhs, arithmetic, functions, threshold),
+                || split(*rhs, arithmetic, functions, threshold),
+            );
+            let mut ctx = Context::new(functions);
+            Ok((arithmetic.binary)(&op, lhs?, rhs?, &span, &mut ctx)?)
+        }
+        // The large side alone is split further
+        Expr::BinOp(bin) if is_arithmetic(&bin.op) && (at_least(&bin.lhs, threshold) || at_least(&bin.rhs, threshold)) => {
+            let lhs = split(*bin.lhs, arithmetic, functions, threshold)?;
+            let rhs = split(*bin.rhs, arithmetic, functions, threshold)?;
+            let mut ctx = Context::new(functions);
+            Ok((arithmetic.binary)(&bin.op, lhs, rhs, &bin.span, &mut ctx)?)
+        }
+        expr => T::from_ast_with(AST{root: expr}, functions),
+    }
+}
+
+fn is_arithmetic(op: &Token) -> bool {
+    matches!(op, Token::Plus | Token::Minus | Token::Times | Token::Division)
+}
+
+/// Whether the tree has at least `n` nodes, counting up to `n`.
+fn at_least(expr: &Expr, n: usize) -> bool {
+    let mut count = 0;
+    let mut stack = vec![expr];
+    while let Some(expr) = stack.pop() {
+        count += 1;
+        if count >= n {
+            return true
+        }
+        stack.extend(expr.children());
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EvalError;
+    use crate::parser::ast::{Neg, Number, Var};
+    use crate::{Rational, Time};
+
+    /// Balanced tree of the leaves, with the operators in turn.
+    fn tree(leaves: Vec<Expr>, ops: &[Token]) -> Expr {
+        let mut level = leaves;
+        let mut depth = 0;
+        while level.len() > 1 {
+            let op = &ops[depth % ops.len()];
+            level = level.chunks(2)
+                .map(|pair| match pair {
+                    [lhs, rhs] => BinOp::new(lhs.clone(), rhs.clone(), op.clone()),
+                    [expr] => expr.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            depth += 1;
+        }
+        level.pop().unwrap()
+    }
+
+    fn same<T>(root: Expr, threshold: usize)
+    where T: FromAST + Send + PartialEq + std::fmt::Debug {
+        let functions = FunctionRegistry::new();
+        let parallel = calculate_with_threshold::<T>(AST{root: root.clone()}, &functions, threshold);
+        assert_eq!(parallel, T::from_ast(AST{root}));
+    }
+
+    #[test]
+    fn test_calculate() {
+        let leaves: Vec<_> = (1..=5000u128).map(|n| Number::new(n % 7 + 1)).collect();
+        let root = tree(leaves, &[Token::Plus, Token::Times, Token::Minus, Token::Division]);
+        for threshold in [1, 16, 1000, usize::MAX] {
+            same::<i128>(root.clone(), threshold);
+            same::<f64>(root.clone(), threshold);
+            same::<Rational>(root.clone(), threshold);
+            same::<Time>(root.clone(), threshold);
+        }
+        let lopsided = BinOp::new(Neg::new(root.clone()), Number::new(3), Token::Times);
+        same::<i128>(lopsided, 16);
+    }
+
+    #[test]
+    fn test_error() {
+        // The first error in the order of the operands
+        let leaves: Vec<_> = (0..1000).map(|n| if n == 700 { Var::new("x") } else { Number::new(n) }).collect();
+        let sum = tree(leaves, &[Token::Plus]);
+        let zero = BinOp::new(Number::new(1), Number::new(0), Token::Division);
+        let root = BinOp::new(sum.clone(), zero.clone(), Token::Minus);
+        let err = calculate_with_threshold::<i128>(AST{root: root.clone()}, &FunctionRegistry::new(), 8).unwrap_err();
+        assert!(matches!(err, Error::Eval(EvalError::UnknownVariable{..})));
+        same::<i128>(root, 8);
+        same::<i128>(BinOp::new(zero, sum, Token::Minus), 8);
+    }
+}
@@ -1,13 +1,109 @@
 //! Parser for the Arithmetic calculator grammar.
 //! ```text
-//! S ::= expr
+//! S ::= ident = expr
+//!     | expr
 //! expr ::= expr + expr
 //!        | expr - expr
 //!        | expr * expr
 //!        | expr / expr
+//!        | expr && expr
+//!        | expr || expr
+//!        | expr cmp expr
+//!        | expr .. expr
 //!        | - expr
+//!        | + expr
+//!        | ! expr
+//!        | √ expr
+//!        | number √ expr
 //!        | ( expr )
+//!        | if expr then expr else expr
+//!        | let ident = expr in expr
+//!        | [ args ]
+//!        | expr [ expr ]
+//!        | | expr |
+//!        | ident ( args )
+//!        | sum ( ident , expr , expr , expr )
+//!        | prod ( ident , expr , expr , expr )
+//!        | ident
 //!        | number
+//!        | bool
+//!        | string
+//! args ::= expr , args
+//!        | expr
+//!        | <empty>
+//! cmp ::= < | <= | > | >= | == | !=
 //! ```
+//!
+//! Comparisons chain, `a < b < c` is `a < b && b < c`.
+//!
+//! A definition of a function, `ident ( params ) = expr`, is
+//! recognized by [`definition`], and only its body is parsed.
 pub mod ast;
+pub mod diff;
+pub mod export;
+pub mod rpn_parser;
 pub mod top_down_parser;
+
+use crate::error::Error;
+use crate::lexer::{Span, Token};
+use ast::AST;
+
+/// Default max depth of nested expressions, such as parentheses,
+/// negations and calls. Parsers return an error past it rather than
+/// overflowing the stack, and a deeper limit needs a larger stack.
+pub const MAX_DEPTH: usize = 256;
+
+/// Max binary operators of an expression. A chain such as `1+2+3`
+/// isn't nested, but its tree is as deep as it is long.
+pub const MAX_OPERATORS: usize = 1024;
+
+/// Tokens with their span.
+type Tokens = [(Token, Span)];
+
+/// Parser that parse spanned tokens to AST.
+pub type ParseFn = fn(Vec<(Token, Span)>) -> Result<AST, Error>;
+
+/// Name, parameters and tokens of the body of a definition
+/// `name(params) = body`, `None` if the tokens aren't one.
+///
+/// # Example
+/// ```
+/// use wcal::{lexer, parser};
+///
+/// let tokens = lexer::spanned("f(x, y) = x * x + y").unwrap();
+/// let (name, params, body) = parser::definition(&tokens).unwrap();
+/// assert_eq!((name.as_str(), params), ("f", vec!["x".to_string(), "y".to_string()]));
+/// assert_eq!(body.len(), 5);
+/// assert!(parser::definition(&lexer::spanned("f(1) = 2").unwrap()).is_none());
+/// ```
+pub fn definition(tokens: &Tokens) -> Option<(String, Vec<String>, &Tokens)> {
+    let name = match tokens.first() {
+        // `$N` are the results of a session
+        Some((Token::Ident(name), _)) if !name.starts_with('$') => name.clone(),
+        _ => return None,
+    };
+    if !matches!(tokens.get(1), Some((Token::LP, _))) {
+        return None
+    }
+    let mut params = Vec::new();
+    let mut i = 2;
+    loop {
+        match tokens.get(i) {
+            Some((Token::RP, _)) if params.is_empty() => break,
+            Some((Token::Ident(param), _)) => params.push(param.clone()),
+            _ => return None,
+        }
+        match tokens.get(i + 1) {
+            Some((Token::Comma, _)) => i += 2,
+            Some((Token::RP, _)) => {
+                i += 1;
+                break
+            }
+            _ => return None,
+        }
+    }
+    match tokens.get(i + 1) {
+        Some((Token::Assign, _)) => Some((name, params, &tokens[i + 2..])),
+        _ => None,
+    }
+}
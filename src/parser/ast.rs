@@ -7,81 +7,631 @@
 //!         | expr - expr
 //!         | expr * expr
 //!         | expr / expr
+//!         | expr && expr
+//!         | expr || expr
 //!
 //! Neg    -> - expr
 //!
+//! Not    -> ! expr
+//!
+//! Abs    -> | expr |
+//!
+//! If     -> if expr then expr else expr
+//!
+//! Let    -> let ident = expr in expr
+//!
 //! Pair   -> ( expr )
 //!
+//! List   -> [ expr, ... ]
+//!
+//! Range  -> expr .. expr
+//!
+//! Index  -> expr [ expr ]
+//!
+//! Call   -> ident ( expr, ... )
+//!
+//! Var    -> ident
+//!
+//! Assign -> ident = expr
+//!
 //! Number -> number
+//!
+//! Duration -> duration
+//!
+//! Date   -> date
+//!
+//! Bool   -> true | false
+//!
+//! Str    -> "text"
 //! ```
-use crate::lexer::Token;
+//!
+//! Every node keeps the span of the input it comes from. Spans
+//! are ignored while comparing nodes, so trees built by hand are
+//! equal to the parsed ones.
+//!
+//! Trees are displayed as S-expressions, such as `(+ 1 (* 2 3))`.
+//! Parentheses of the input are implied by the nesting.
+#![allow(clippy::new_ret_no_self)]
+
+use std::fmt;
+
+use crate::lexer::{Span, Token};
 
 /// `expr`
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Pair(Pair),
     BinOp(BinOp),
     Neg(Neg),
+    Not(Not),
+    Abs(Abs),
+    If(If),
+    Let(Let),
+    List(List),
+    Range(Range),
+    Index(Index),
+    Call(Call),
+    Var(Var),
+    Assign(Assign),
     Num(Number),
+    Duration(Duration),
+    Date(Date),
+    Bool(Bool),
+    Str(Str),
+}
+
+impl Expr {
+    /// Span of the expression in the input.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Pair(pair) => pair.span.clone(),
+            Expr::BinOp(expr) => expr.span.clone(),
+            Expr::Neg(neg) => neg.span.clone(),
+            Expr::Not(not) => not.span.clone(),
+            Expr::Abs(abs) => abs.span.clone(),
+            Expr::If(node) => node.span.clone(),
+            Expr::Let(node) => node.span.clone(),
+            Expr::List(list) => list.span.clone(),
+            Expr::Range(range) => range.span.clone(),
+            Expr::Index(index) => index.span.clone(),
+            Expr::Call(call) => call.span.clone(),
+            Expr::Var(var) => var.span.clone(),
+            Expr::Assign(assign) => assign.span.clone(),
+            Expr::Num(num) => num.span.clone(),
+            Expr::Duration(duration) => duration.span.clone(),
+            Expr::Date(date) => date.span.clone(),
+            Expr::Bool(bool) => bool.span.clone(),
+            Expr::Str(text) => text.span.clone(),
+        }
+    }
+
+    /// Children of the node, from left to right.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Pair(pair) => vec![&pair.expr],
+            Expr::BinOp(bin) => vec![&bin.lhs, &bin.rhs],
+            Expr::Neg(neg) => vec![&neg.expr],
+            Expr::Not(not) => vec![&not.expr],
+            Expr::Abs(abs) => vec![&abs.expr],
+            Expr::If(node) => vec![&node.cond, &node.then, &node.otherwise],
+            Expr::Let(node) => vec![&node.value, &node.body],
+            Expr::List(list) => list.items.iter().collect(),
+            Expr::Range(range) => vec![&range.start, &range.end],
+            Expr::Index(index) => vec![&index.list, &index.index],
+            Expr::Call(call) => call.args.iter().collect(),
+            Expr::Assign(assign) => vec![&assign.expr],
+            Expr::Var(_) | Expr::Num(_) | Expr::Duration(_) | Expr::Date(_) | Expr::Bool(_) | Expr::Str(_) => Vec::new(),
+        }
+    }
+
+    /// Children of the node to change, from left to right.
+    pub fn children_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Expr::Pair(pair) => vec![&mut pair.expr],
+            Expr::BinOp(bin) => vec![&mut bin.lhs, &mut bin.rhs],
+            Expr::Neg(neg) => vec![&mut neg.expr],
+            Expr::Not(not) => vec![&mut not.expr],
+            Expr::Abs(abs) => vec![&mut abs.expr],
+            Expr::If(node) => vec![&mut node.cond, &mut node.then, &mut node.otherwise],
+            Expr::Let(node) => vec![&mut node.value, &mut node.body],
+            Expr::List(list) => list.items.iter_mut().collect(),
+            Expr::Range(range) => vec![&mut range.start, &mut range.end],
+            Expr::Index(index) => vec![&mut index.list, &mut index.index],
+            Expr::Call(call) => call.args.iter_mut().collect(),
+            Expr::Assign(assign) => vec![&mut assign.expr],
+            Expr::Var(_) | Expr::Num(_) | Expr::Duration(_) | Expr::Date(_) | Expr::Bool(_) | Expr::Str(_) => Vec::new(),
+        }
+    }
+
+    /// Set the span of the expression.
+    pub fn with_span(mut self, span: Span) -> Expr {
+        match &mut self {
+            Expr::Pair(pair) => pair.span = span,
+            Expr::BinOp(expr) => expr.span = span,
+            Expr::Neg(neg) => neg.span = span,
+            Expr::Not(not) => not.span = span,
+            Expr::Abs(abs) => abs.span = span,
+            Expr::If(node) => node.span = span,
+            Expr::Let(node) => node.span = span,
+            Expr::List(list) => list.span = span,
+            Expr::Range(range) => range.span = span,
+            Expr::Index(index) => index.span = span,
+            Expr::Call(call) => call.span = span,
+            Expr::Var(var) => var.span = span,
+            Expr::Assign(assign) => assign.span = span,
+            Expr::Num(num) => num.span = span,
+            Expr::Duration(duration) => duration.span = span,
+            Expr::Date(date) => date.span = span,
+            Expr::Bool(bool) => bool.span = span,
+            Expr::Str(text) => text.span = span,
+        }
+        self
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Pair(pair) => write!(f, "{}", pair.expr),
+            Expr::BinOp(expr) => write!(f, "({} {} {})", expr.op, expr.lhs, expr.rhs),
+            Expr::Neg(neg) => write!(f, "(- {})", neg.expr),
+            Expr::Not(not) => write!(f, "(! {})", not.expr),
+            Expr::Abs(abs) => write!(f, "(abs {})", abs.expr),
+            Expr::If(node) => write!(f, "(if {} {} {})", node.cond, node.then, node.otherwise),
+            Expr::Let(node) => write!(f, "(let {} {} {})", node.name, node.value, node.body),
+            Expr::List(list) => {
+                write!(f, "(list")?;
+                for item in list.items.iter() {
+                    write!(f, " {}", item)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Range(range) => write!(f, "(.. {} {})", range.start, range.end),
+            Expr::Index(index) => write!(f, "(index {} {})", index.list, index.index),
+            Expr::Call(call) => {
+                write!(f, "({}", call.name)?;
+                for arg in call.args.iter() {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Var(var) => write!(f, "{}", var.name),
+            Expr::Assign(assign) => write!(f, "(= {} {})", assign.name, assign.expr),
+            Expr::Num(num) => write!(f, "{}", num.num),
+            Expr::Duration(duration) => write!(f, "{}", duration),
+            Expr::Date(date) => write!(f, "{}", date),
+            Expr::Bool(bool) => write!(f, "{}", bool.value),
+            Expr::Str(text) => write!(f, "\"{}\"", text.value),
+        }
+    }
 }
 
 /// `( expr )`
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Pair {
-    pub expr: Box<Expr>
+    pub expr: Box<Expr>,
+    pub span: Span,
 }
 
 impl Pair {
     pub fn new(expr: Expr) -> Expr {
-        Expr::Pair(Pair{expr: Box::new(expr)})
+        let span = expr.span();
+        Expr::Pair(Pair{expr: Box::new(expr), span})
+    }
+}
+
+impl PartialEq for Pair {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
     }
 }
 
 /// `lhs op rhs`
 ///
-/// op is `+` `-` `*` or `/`
-#[derive(Debug, PartialEq)]
+/// op is `+` `-` `*` `/` `&&` or `||`
+#[derive(Debug, Clone)]
 pub struct BinOp{
     pub lhs: Box<Expr>,
     pub rhs: Box<Expr>,
-    pub op: Token
+    pub op: Token,
+    pub span: Span,
 }
 
 impl BinOp {
+    /// The span covers both operands.
     pub fn new(lhs: Expr, rhs: Expr, token: Token) -> Expr {
+        let span = lhs.span().start..rhs.span().end;
         Expr::BinOp(BinOp{
             lhs: Box::new(lhs),
             rhs: Box::new(rhs),
-            op: token
+            op: token,
+            span,
         })
     }
 }
 
+impl PartialEq for BinOp {
+    fn eq(&self, other: &Self) -> bool {
+        self.lhs == other.lhs && self.rhs == other.rhs && self.op == other.op
+    }
+}
+
 /// `- expr`
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Neg{
-    pub expr: Box<Expr>
+    pub expr: Box<Expr>,
+    pub span: Span,
 }
 
 impl Neg {
     pub fn new(expr: Expr) -> Expr {
-        Expr::Neg(Neg{expr: Box::new(expr)})
+        let span = expr.span();
+        Expr::Neg(Neg{expr: Box::new(expr), span})
+    }
+}
+
+impl PartialEq for Neg {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
     }
 }
 
-/// number store as `u64`
-#[derive(Debug, PartialEq)]
+/// `! expr`
+#[derive(Debug, Clone)]
+pub struct Not{
+    pub expr: Box<Expr>,
+    pub span: Span,
+}
+
+impl Not {
+    pub fn new(expr: Expr) -> Expr {
+        let span = expr.span();
+        Expr::Not(Not{expr: Box::new(expr), span})
+    }
+}
+
+impl PartialEq for Not {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
+    }
+}
+
+/// `| expr |`
+#[derive(Debug, Clone)]
+pub struct Abs{
+    pub expr: Box<Expr>,
+    pub span: Span,
+}
+
+impl Abs {
+    pub fn new(expr: Expr) -> Expr {
+        let span = expr.span();
+        Expr::Abs(Abs{expr: Box::new(expr), span})
+    }
+}
+
+impl PartialEq for Abs {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
+    }
+}
+
+/// `if cond then expr else expr`
+#[derive(Debug, Clone)]
+pub struct If{
+    pub cond: Box<Expr>,
+    pub then: Box<Expr>,
+    pub otherwise: Box<Expr>,
+    pub span: Span,
+}
+
+impl If {
+    /// The span covers the condition and both branches.
+    pub fn new(cond: Expr, then: Expr, otherwise: Expr) -> Expr {
+        let span = cond.span().start..otherwise.span().end;
+        Expr::If(If{cond: Box::new(cond), then: Box::new(then), otherwise: Box::new(otherwise), span})
+    }
+}
+
+impl PartialEq for If {
+    fn eq(&self, other: &Self) -> bool {
+        self.cond == other.cond && self.then == other.then && self.otherwise == other.otherwise
+    }
+}
+
+/// `let name = value in body`, `name` is only visible in `body`
+#[derive(Debug, Clone)]
+pub struct Let{
+    pub name: String,
+    pub value: Box<Expr>,
+    pub body: Box<Expr>,
+    pub span: Span,
+}
+
+impl Let {
+    /// The span covers the value and the body.
+    pub fn new(name: &str, value: Expr, body: Expr) -> Expr {
+        let span = value.span().start..body.span().end;
+        Expr::Let(Let{name: name.to_string(), value: Box::new(value), body: Box::new(body), span})
+    }
+}
+
+impl PartialEq for Let {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.value == other.value && self.body == other.body
+    }
+}
+
+/// `[ items ]`
+#[derive(Debug, Clone)]
+pub struct List{
+    pub items: Vec<Expr>,
+    pub span: Span,
+}
+
+impl List {
+    pub fn new(items: Vec<Expr>) -> Expr {
+        Expr::List(List{items, span: 0..0})
+    }
+}
+
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        self.items == other.items
+    }
+}
+
+/// `start .. end`, the integers from `start` to `end` included
+#[derive(Debug, Clone)]
+pub struct Range{
+    pub start: Box<Expr>,
+    pub end: Box<Expr>,
+    pub span: Span,
+}
+
+impl Range {
+    /// The span covers both bounds.
+    pub fn new(start: Expr, end: Expr) -> Expr {
+        let span = start.span().start..end.span().end;
+        Expr::Range(Range{start: Box::new(start), end: Box::new(end), span})
+    }
+}
+
+impl PartialEq for Range {
+    fn eq(&self, other: &Self) -> bool {
+        self.start == other.start && self.end == other.end
+    }
+}
+
+/// `list [ index ]`, from 0
+#[derive(Debug, Clone)]
+pub struct Index{
+    pub list: Box<Expr>,
+    pub index: Box<Expr>,
+    pub span: Span,
+}
+
+impl Index {
+    /// The span covers the list and the index.
+    pub fn new(list: Expr, index: Expr) -> Expr {
+        let span = list.span().start..index.span().end;
+        Expr::Index(Index{list: Box::new(list), index: Box::new(index), span})
+    }
+}
+
+impl PartialEq for Index {
+    fn eq(&self, other: &Self) -> bool {
+        self.list == other.list && self.index == other.index
+    }
+}
+
+/// `name ( args )`
+#[derive(Debug, Clone)]
+pub struct Call{
+    pub name: String,
+    pub args: Vec<Expr>,
+    pub span: Span,
+}
+
+impl Call {
+    pub fn new(name: &str, args: Vec<Expr>) -> Expr {
+        Expr::Call(Call{name: name.to_string(), args, span: 0..0})
+    }
+}
+
+impl PartialEq for Call {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.args == other.args
+    }
+}
+
+/// `name`
+#[derive(Debug, Clone)]
+pub struct Var{
+    pub name: String,
+    pub span: Span,
+}
+
+impl Var {
+    pub fn new(name: &str) -> Expr {
+        Expr::Var(Var{name: name.to_string(), span: 0..0})
+    }
+}
+
+impl PartialEq for Var {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// `name = expr`
+#[derive(Debug, Clone)]
+pub struct Assign{
+    pub name: String,
+    pub expr: Box<Expr>,
+    pub span: Span,
+}
+
+impl Assign {
+    pub fn new(name: &str, expr: Expr) -> Expr {
+        let span = expr.span();
+        Expr::Assign(Assign{name: name.to_string(), expr: Box::new(expr), span})
+    }
+}
+
+impl PartialEq for Assign {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.expr == other.expr
+    }
+}
+
+/// number store as `u128`
+#[derive(Debug, Clone)]
 pub struct Number{
-    pub num: u64
+    pub num: u128,
+    pub span: Span,
 }
 
 impl Number {
-    pub fn new(num: u64) -> Expr {
-        Expr::Num(Number{num})
+    pub fn new(num: u128) -> Expr {
+        Expr::Num(Number{num, span: 0..0})
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.num == other.num
+    }
+}
+
+/// Duration such as `1h30m`, in seconds
+#[derive(Debug, Clone)]
+pub struct Duration{
+    pub secs: u128,
+    pub span: Span,
+}
+
+impl Duration {
+    pub fn new(secs: u128) -> Expr {
+        Expr::Duration(Duration{secs, span: 0..0})
+    }
+
+    /// The seconds as a number literal at the same span, for the
+    /// calculators without durations.
+    pub fn to_number(&self) -> Number {
+        Number{num: self.secs, span: self.span.clone()}
+    }
+}
+
+impl fmt::Display for Duration {
+    /// The spelling of the literal, such as `1h30m`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", crate::duration::spelling(self.secs))
+    }
+}
+
+impl PartialEq for Duration {
+    fn eq(&self, other: &Self) -> bool {
+        self.secs == other.secs
+    }
+}
+
+/// Date such as `2024-05-01`, in days since 1970-01-01
+#[derive(Debug, Clone)]
+pub struct Date{
+    pub days: i64,
+    pub span: Span,
+}
+
+impl Date {
+    pub fn new(days: i64) -> Expr {
+        Expr::Date(Date{days, span: 0..0})
+    }
+}
+
+impl fmt::Display for Date {
+    /// The spelling of the literal, such as `2024-05-01`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", crate::date::spelling(self.days))
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl PartialEq for Date {
+    fn eq(&self, other: &Self) -> bool {
+        self.days == other.days
+    }
+}
+
+/// `true` or `false`
+#[derive(Debug, Clone)]
+pub struct Bool{
+    pub value: bool,
+    pub span: Span,
+}
+
+impl Bool {
+    pub fn new(value: bool) -> Expr {
+        Expr::Bool(Bool{value, span: 0..0})
+    }
+}
+
+impl PartialEq for Bool {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+/// `"text"`, only an argument of the functions returning a text,
+/// such as the format of `format(x, "%.3f")`
+#[derive(Debug, Clone)]
+pub struct Str{
+    pub value: String,
+    pub span: Span,
+}
+
+impl Str {
+    pub fn new(value: &str) -> Expr {
+        Expr::Str(Str{value: value.to_string(), span: 0..0})
+    }
+}
+
+impl PartialEq for Str {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct AST{
     pub root: Expr
 }
+
+impl fmt::Display for AST {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lexer, parser};
+
+    fn sexpr(input: &str) -> String {
+        lexer::spanned(input).and_then(parser::top_down_parser::parse).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(sexpr("1+2*3"), "(+ 1 (* 2 3))");
+        assert_eq!(sexpr("(1-2)-3"), "(- (- 1 2) 3)");
+        assert_eq!(sexpr("---7--2"), "(- (- (- (- 7))) (- 2))");
+        assert_eq!(sexpr("x = max(1, y/2)"), "(= x (max 1 (/ y 2)))");
+        assert_eq!(sexpr("f()"), "(f)");
+        assert_eq!(sexpr("|x - |3||"), "(abs (- x (abs 3)))");
+        assert_eq!(sexpr("!a || b && true"), "(|| (! a) (&& b true))");
+        assert_eq!(sexpr("1 + if x then 2 else 3 * 4"), "(+ 1 (if x 2 (* 3 4)))");
+        assert_eq!(sexpr("let h = b*b in h - 1"), "(let h (* b b) (- h 1))");
+        assert_eq!(sexpr("[1, 2][x + 1]"), "(index (list 1 2) (+ x 1))");
+        assert_eq!(sexpr("sum(1..n * 2)"), "(sum (.. 1 (* n 2)))");
+    }
+}
@@ -0,0 +1,152 @@
+//! Compare the trees of two expressions, ignoring how they are
+//! written.
+//!
+//! Both trees are normalized before the comparison:
+//! - parentheses are dropped, only the nesting matters
+//! - chains of `+` and of `*` are flattened, and their operands are
+//!   sorted, so `(1 + x) * 2` is equivalent to `2 * (x + 1)`
+//!
+//! Nothing is calculated, so `2 + 2` differs from `4`, and the order
+//! of the operations is ignored even where it changes rounding or
+//! overflows.
+//!
+//! # Example
+//! ```
+//! use wcal::Calculator;
+//!
+//! let calc = Calculator::<i128>::new();
+//! let (a, b) = (calc.parse("x * (y + 2)").unwrap(), calc.parse("(2 + y) * x").unwrap());
+//! assert_eq!(a.diff(&b), None);
+//!
+//! let c = calc.parse("(3 + y) * x").unwrap();
+//! let diff = a.diff(&c).unwrap();
+//! assert_eq!((diff.left.span(), diff.right.span()), (9..10, 1..2));
+//! ```
+use crate::lexer::Token;
+use crate::parser::ast::{Expr, AST};
+
+/// First subtree that differs between two trees, see [`AST::diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diff<'a> {
+    /// Subtree of `self`
+    pub left: &'a Expr,
+    /// Subtree of `other`
+    pub right: &'a Expr,
+}
+
+/// Normalized node.
+struct Node<'a> {
+    /// Outermost node of the input, the first operation of a
+    /// flattened chain
+    expr: &'a Expr,
+    label: String,
+    children: Vec<Node<'a>>,
+    /// S-expression of the normalized node, to sort the operands
+    key: String,
+}
+
+fn is_commutative(op: &Token) -> bool {
+    matches!(op, Token::Plus | Token::Times)
+}
+
+/// Operands of a chain of the operator, through parentheses.
+fn operands<'a>(expr: &'a Expr, op: &Token, out: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::Pair(pair) => operands(&pair.expr, op, out),
+        Expr::BinOp(bin) if bin.op == *op => {
+            operands(&bin.lhs, op, out);
+            operands(&bin.rhs, op, out);
+        }
+        _ => out.push(expr),
+    }
+}
+
+fn normalize(expr: &Expr) -> Node<'_> {
+    let (label, children) = match expr {
+        Expr::Pair(pair) => return normalize(&pair.expr),
+        Expr::BinOp(bin) if is_commutative(&bin.op) => {
+            let mut terms = Vec::new();
+            operands(expr, &bin.op, &mut terms);
+            let mut children: Vec<_> = terms.into_iter().map(normalize).collect();
+            children.sort_by(|a, b| a.key.cmp(&b.key));
+            (bin.op.to_string(), children)
+        }
+        Expr::BinOp(bin) => (bin.op.to_string(), vec![normalize(&bin.lhs), normalize(&bin.rhs)]),
+        Expr::Neg(neg) => ("neg".to_string(), vec![normalize(&neg.expr)]),
+        Expr::Not(not) => ("!".to_string(), vec![normalize(&not.expr)]),
+        Expr::Abs(abs) => ("abs".to_string(), vec![normalize(&abs.expr)]),
+        Expr::Let(node) => (format!("let {}", node.name), vec![normalize(&node.value), normalize(&node.body)]),
+        Expr::If(node) => ("if".to_string(), vec![normalize(&node.cond), normalize(&node.then), normalize(&node.otherwise)]),
+        Expr::List(list) => ("[]".to_string(), list.items.iter().map(normalize).collect()),
+        Expr::Range(range) => ("..".to_string(), vec![normalize(&range.start), normalize(&range.end)]),
+        Expr::Index(index) => ("[i]".to_string(), vec![normalize(&index.list), normalize(&index.index)]),
+        Expr::Call(call) => (format!("{}()", call.name), call.args.iter().map(normalize).collect()),
+        Expr::Var(var) => (var.name.clone(), Vec::new()),
+        Expr::Assign(assign) => (format!("{} =", assign.name), vec![normalize(&assign.expr)]),
+        Expr::Num(num) => (num.num.to_string(), Vec::new()),
+        Expr::Duration(duration) => (duration.to_string(), Vec::new()),
+        Expr::Date(date) => (date.to_string(), Vec::new()),
+        Expr::Bool(bool) => (bool.value.to_string(), Vec::new()),
+        Expr::Str(_) => (expr.to_string(), Vec::new()),
+    };
+    let key = if children.is_empty() {
+        label.clone()
+    } else {
+        let keys: Vec<_> = children.iter().map(|child| child.key.as_str()).collect();
+        format!("({} {})", label, keys.join(" "))
+    };
+    Node{expr, label, children, key}
+}
+
+/// First differing pair of nodes, from the root and left to right.
+fn first_diff<'a>(left: &Node<'a>, right: &Node<'a>) -> Option<Diff<'a>> {
+    if left.label != right.label || left.children.len() != right.children.len() {
+        return Some(Diff{left: left.expr, right: right.expr})
+    }
+    left.children.iter().zip(right.children.iter())
+        .find_map(|(left, right)| first_diff(left, right))
+}
+
+impl AST {
+    /// First subtree where the normalized trees differ, `None` if
+    /// they are equivalent.
+    pub fn diff<'a>(&'a self, other: &'a AST) -> Option<Diff<'a>> {
+        first_diff(&normalize(&self.root), &normalize(&other.root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lexer, parser};
+    use super::*;
+
+    fn parse(input: &str) -> AST {
+        lexer::spanned(input).and_then(parser::top_down_parser::parse).unwrap()
+    }
+
+    /// Text of the differing subtrees, as `left | right`.
+    fn diff(a: &str, b: &str) -> Option<String> {
+        let (left, right) = (parse(a), parse(b));
+        let diff = left.diff(&right)?;
+        Some(format!("{} | {}", &a[diff.left.span()], &b[diff.right.span()]))
+    }
+
+    #[test]
+    fn test_equivalent() {
+        assert_eq!(diff("(1 + x) * 2", "2 * (x + 1)"), None);
+        assert_eq!(diff("1 + 2 + 3", "3 + (2 + 1)"), None);
+        assert_eq!(diff("y = max(a*b, -c)", "y = max((b*a), -(c))"), None);
+    }
+
+    #[test]
+    fn test_different() {
+        assert_eq!(diff("x * (y + 2)", "x * (y + 3)").as_deref(), Some("2 | 3"));
+        assert_eq!(diff("a - b", "b - a").as_deref(), Some("a | b"));
+        assert_eq!(diff("2 + 2", "4").as_deref(), Some("2 + 2 | 4"));
+        assert_eq!(diff("f(1, 2)", "f(1)").as_deref(), Some("f(1, 2) | f(1)"));
+        assert_eq!(diff("a * b * c", "a * (b + c)").as_deref(), Some("a * b * c | a * (b + c)"));
+        assert_eq!(diff("-x", "- y").as_deref(), Some("x | y"));
+        assert_eq!(diff("|x + 1|", "|1 + x|"), None);
+        assert_eq!(diff("|x|", "-x").as_deref(), Some("|x| | -x"));
+    }
+}
@@ -0,0 +1,280 @@
+//! Export the AST for other tools, besides the S-expressions of
+//! `Display`.
+//!
+//! Unlike the S-expressions, the exports keep the parentheses of
+//! the input as `()` nodes and the span of every node.
+//!
+//! # Example
+//! ```
+//! use wcal::{lexer, parser::{export, top_down_parser}};
+//!
+//! let ast = top_down_parser::parse(lexer::spanned("1+2*3").unwrap()).unwrap();
+//! assert_eq!(export::tree(&ast), "\
+//! + 0..5
+//! ├── 1 0..1
+//! └── * 2..5
+//!     ├── 2 2..3
+//!     └── 3 4..5
+//! ");
+//! ```
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+#[cfg(feature = "serde")]
+use serde_json::{json, Value as Json};
+
+#[cfg(feature = "serde")]
+use crate::parser::ast::BinOp;
+use crate::parser::ast::{Expr, AST};
+
+/// Label of the node, without its children.
+fn label(expr: &Expr) -> String {
+    match expr {
+        Expr::Pair(_) => "()".to_string(),
+        Expr::BinOp(expr) => expr.op.to_string(),
+        Expr::Neg(_) => "-".to_string(),
+        Expr::Not(_) => "!".to_string(),
+        Expr::Abs(_) => "||".to_string(),
+        Expr::If(_) => "if".to_string(),
+        Expr::Let(node) => format!("let {}", node.name),
+        Expr::List(_) => "[]".to_string(),
+        Expr::Range(_) => "..".to_string(),
+        Expr::Index(_) => "[i]".to_string(),
+        Expr::Call(call) => format!("{}()", call.name),
+        Expr::Var(var) => var.name.clone(),
+        Expr::Assign(assign) => format!("{} =", assign.name),
+        Expr::Num(num) => num.num.to_string(),
+        Expr::Duration(duration) => duration.to_string(),
+        Expr::Date(date) => date.to_string(),
+        Expr::Bool(bool) => bool.value.to_string(),
+        Expr::Str(_) => expr.to_string(),
+    }
+}
+
+/// Indented tree with the span of each node, one node per line.
+pub fn tree(ast: &AST) -> String {
+    let mut out = String::new();
+    tree_node(&ast.root, "", "", &mut out);
+    out
+}
+
+fn tree_node(expr: &Expr, head: &str, indent: &str, out: &mut String) {
+    let span = expr.span();
+    let _ = writeln!(out, "{}{} {}..{}", head, label(expr), span.start, span.end);
+    let children = expr.children();
+    for (i, child) in children.iter().enumerate() {
+        if i + 1 == children.len() {
+            tree_node(child, &format!("{}└── ", indent), &format!("{}    ", indent), out);
+        } else {
+            tree_node(child, &format!("{}├── ", indent), &format!("{}│   ", indent), out);
+        }
+    }
+}
+
+/// Graphviz graph, such as for `dot -Tsvg`.
+pub fn dot(ast: &AST) -> String {
+    let mut out = String::from("digraph ast {\n    node [shape=box];\n");
+    dot_node(&ast.root, &mut 0, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+/// Write the node and its children, return the id of the node.
+fn dot_node(expr: &Expr, next: &mut usize, out: &mut String) -> usize {
+    let id = *next;
+    *next += 1;
+    let span = expr.span();
+    let label = label(expr).replace('\\', "\\\\").replace('"', "\\\"");
+    let _ = writeln!(out, "    n{} [label=\"{}\\n{}..{}\"];", id, label, span.start, span.end);
+    for child in expr.children() {
+        let child = dot_node(child, next, out);
+        let _ = writeln!(out, "    n{} -> n{};", id, child);
+    }
+    id
+}
+
+/// Postfix tokens of the tree, such as `1 2 3 * +`, parsed back by
+/// [`rpn_parser`](super::rpn_parser). Parentheses are dropped.
+pub fn rpn(ast: &AST) -> String {
+    let mut out = Vec::new();
+    rpn_node(&ast.root, &mut out);
+    out.join(" ")
+}
+
+fn rpn_node(expr: &Expr, out: &mut Vec<String>) {
+    // The name is the first operand
+    match expr {
+        Expr::Let(node) => out.push(node.name.clone()),
+        Expr::Assign(assign) => out.push(assign.name.clone()),
+        _ => {}
+    }
+    for child in expr.children() {
+        rpn_node(child, out);
+    }
+    let op = match expr {
+        Expr::Pair(_) => return,
+        Expr::BinOp(expr) => expr.op.to_string(),
+        Expr::Neg(_) => "-(1)".to_string(),
+        Expr::Not(_) => "!".to_string(),
+        Expr::Abs(_) => "|".to_string(),
+        Expr::If(_) => "if".to_string(),
+        Expr::Let(_) => "let".to_string(),
+        Expr::List(list) => format!("[{}]", list.items.len()),
+        Expr::Range(_) => "..".to_string(),
+        Expr::Index(_) => "[]".to_string(),
+        Expr::Call(call) => format!("{}({})", call.name, call.args.len()),
+        Expr::Var(var) => var.name.clone(),
+        Expr::Assign(_) => "=".to_string(),
+        Expr::Num(num) => num.num.to_string(),
+        Expr::Duration(duration) => duration.to_string(),
+        Expr::Date(date) => date.to_string(),
+        Expr::Bool(bool) => bool.value.to_string(),
+        Expr::Str(_) => expr.to_string(),
+    };
+    out.push(op);
+}
+
+/// JSON object of each node with its `node` kind and `span`:
+/// ```text
+/// {"node":"binop","op":"+","span":[0,3],"lhs":{...},"rhs":{...}}
+/// ```
+/// Numbers past 64 bits are strings, like the results.
+#[cfg(feature = "serde")]
+pub fn json(ast: &AST) -> Json {
+    json_node(&ast.root)
+}
+
+#[cfg(feature = "serde")]
+fn json_node(expr: &Expr) -> Json {
+    let span = expr.span();
+    let span = json!([span.start, span.end]);
+    match expr {
+        Expr::Pair(pair) => json!({"node": "pair", "span": span, "expr": json_node(&pair.expr)}),
+        Expr::BinOp(node) => {
+            // The chain of operators such as `1+2+3` in a loop rather
+            // than a frame per operator
+            let mut chain = vec![node];
+            let mut lhs = &*node.lhs;
+            while let Expr::BinOp(inner) = lhs {
+                chain.push(inner);
+                lhs = &inner.lhs;
+            }
+            chain.into_iter().rev().fold(json_node(lhs), json_binop)
+        }
+        Expr::Neg(neg) => json!({"node": "neg", "span": span, "expr": json_node(&neg.expr)}),
+        Expr::Not(not) => json!({"node": "not", "span": span, "expr": json_node(&not.expr)}),
+        Expr::Abs(abs) => json!({"node": "abs", "span": span, "expr": json_node(&abs.expr)}),
+        Expr::If(node) => json!({
+            "node": "if",
+            "span": span,
+            "cond": json_node(&node.cond),
+            "then": json_node(&node.then),
+            "else": json_node(&node.otherwise),
+        }),
+        Expr::Let(node) => json!({
+            "node": "let",
+            "name": node.name,
+            "span": span,
+            "value": json_node(&node.value),
+            "body": json_node(&node.body),
+        }),
+        Expr::List(list) => {
+            let items: Vec<Json> = list.items.iter().map(json_node).collect();
+            json!({"node": "list", "span": span, "items": items})
+        }
+        Expr::Range(range) => json!({
+            "node": "range",
+            "span": span,
+            "start": json_node(&range.start),
+            "end": json_node(&range.end),
+        }),
+        Expr::Index(index) => json!({
+            "node": "index",
+            "span": span,
+            "list": json_node(&index.list),
+            "index": json_node(&index.index),
+        }),
+        Expr::Call(call) => {
+            let args: Vec<Json> = call.args.iter().map(json_node).collect();
+            json!({"node": "call", "name": call.name, "span": span, "args": args})
+        }
+        Expr::Var(var) => json!({"node": "var", "name": var.name, "span": span}),
+        Expr::Assign(assign) => json!({"node": "assign", "name": assign.name, "span": span, "expr": json_node(&assign.expr)}),
+        Expr::Num(num) => {
+            let value = match u64::try_from(num.num) {
+                Ok(num) => json!(num),
+                Err(_) => json!(num.num.to_string()),
+            };
+            json!({"node": "number", "value": value, "span": span})
+        }
+        Expr::Duration(duration) => {
+            let secs = match u64::try_from(duration.secs) {
+                Ok(secs) => json!(secs),
+                Err(_) => json!(duration.secs.to_string()),
+            };
+            json!({"node": "duration", "secs": secs, "span": span})
+        }
+        Expr::Date(date) => json!({"node": "date", "value": date.to_string(), "span": span}),
+        Expr::Bool(bool) => json!({"node": "bool", "value": bool.value, "span": span}),
+        Expr::Str(text) => json!({"node": "str", "value": text.value, "span": span}),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn json_binop(lhs: Json, node: &BinOp) -> Json {
+    json!({
+        "node": "binop",
+        "op": node.op.to_string(),
+        "span": [node.span.start, node.span.end],
+        "lhs": lhs,
+        "rhs": json_node(&node.rhs),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn parse(input: &str) -> AST {
+        lexer::spanned(input).and_then(parser::top_down_parser::parse).unwrap()
+    }
+
+    #[test]
+    fn test_tree() {
+        assert_eq!(tree(&parse("x = -(1)")), "x = 0..8\n└── - 4..8\n    └── () 5..8\n        └── 1 6..7\n");
+        assert_eq!(tree(&parse("max(1, y)")), "max() 0..9\n├── 1 4..5\n└── y 7..8\n");
+        assert_eq!(tree(&parse("|y|")), "|| 0..3\n└── y 1..2\n");
+        assert_eq!(tree(&parse("[1, 2..3][0]")), "[i] 0..12\n├── [] 0..9\n│   ├── 1 1..2\n│   └── .. 4..8\n│       ├── 2 4..5\n│       └── 3 7..8\n└── 0 10..11\n");
+        assert_eq!(tree(&parse("if x then 1 else 2")), "if 0..18\n├── x 3..4\n├── 1 10..11\n└── 2 17..18\n");
+        assert_eq!(tree(&parse("!x && true")), "&& 0..10\n├── ! 0..2\n│   └── x 1..2\n└── true 6..10\n");
+    }
+
+    #[test]
+    fn test_dot() {
+        assert_eq!(dot(&parse("f(2)")), "\
+digraph ast {
+    node [shape=box];
+    n0 [label=\"f()\\n0..4\"];
+    n1 [label=\"2\\n2..3\"];
+    n0 -> n1;
+}
+");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_json() {
+        assert_eq!(json(&parse("-a*2")).to_string(), concat!(
+            r#"{"lhs":{"expr":{"name":"a","node":"var","span":[1,2]},"node":"neg","span":[0,2]},"#,
+            r#""node":"binop","op":"*","rhs":{"node":"number","span":[3,4],"value":2},"span":[0,4]}"#,
+        ));
+        let big = json(&parse("0x1_0000_0000_0000_0000"));
+        assert_eq!(big["value"], "18446744073709551616");
+        let chain = vec!["1"; parser::MAX_OPERATORS + 1].join("+");
+        let chain = json(&parse(&chain));
+        assert_eq!(chain["span"], json!([0, 2 * parser::MAX_OPERATORS + 1]));
+        assert_eq!(chain["rhs"]["span"], json!([2 * parser::MAX_OPERATORS, 2 * parser::MAX_OPERATORS + 1]));
+    }
+}
@@ -0,0 +1,256 @@
+//! Parse postfix tokens, such as `3 4 + 2 *`, to the AST of the
+//! infix parsers.
+//!
+//! Operands are pushed on a stack and each operator pops its
+//! operands, the last one on top:
+//! - `+ - * / && || ..` pop two operands, `! |` one, `|` is the
+//!   absolute value
+//! - `-(1)` is the negation, and `name(n)` calls the function with
+//!   `n` arguments, a name alone is a variable
+//! - `[n]` makes a list of `n` items, `[]` indexes a list
+//! - `if` pops the condition and the two branches
+//! - `let` and `=` pop a name first, `x 1 2 + =` is `x = 1 + 2`
+//!
+//! It is the output of [`export::rpn`](super::export::rpn). The
+//! spans reach from the first operand to the operator, and the tree
+//! is at most [`MAX_DEPTH`] deep, or as deep as [`parse_limited`]
+//! takes, like the infix parsers.
+//!
+//! # Example
+//! ```
+//! use wcal::{lexer, parser::rpn_parser};
+//!
+//! let ast = rpn_parser::parse(lexer::spanned("3 4 + 2 *").unwrap()).unwrap();
+//! assert_eq!(ast.to_string(), "(* (+ 3 4) 2)");
+//! let ast = rpn_parser::parse(lexer::spanned("x 1 -(1) 2 max(2) =").unwrap()).unwrap();
+//! assert_eq!(ast.to_string(), "(= x (max (- 1) 2))");
+//! ```
+use std::convert::TryFrom;
+use std::iter::Peekable;
+
+use crate::error::{Error, ParseError};
+use crate::lexer::{Span, Token};
+use super::ast::*;
+use super::MAX_DEPTH;
+
+/// Operands on the stack, with the depth of their trees.
+struct Stack {
+    operands: Vec<(Expr, usize)>,
+    /// Depth and start of the operands popped for the next node
+    depth: usize,
+    start: Option<usize>,
+    max_depth: usize,
+}
+
+impl Stack {
+    /// Pop the last operand of the operator.
+    fn pop(&mut self, op: &Token, span: &Span) -> Result<Expr, Error> {
+        let (expr, depth) = self.operands.pop()
+            .ok_or_else(|| ParseError::UnexpectedToken{expected: "operand", found: op.clone(), span: span.clone()})?;
+        // An assignment is only the whole expression
+        if let Expr::Assign(assign) = &expr {
+            return Err(ParseError::InvalidExpression{span: assign.span.clone()}.into())
+        }
+        self.depth = self.depth.max(depth + 1);
+        self.start = Some(expr.span().start);
+        Ok(expr)
+    }
+
+    /// Pop the `n` last operands, the first one first.
+    fn pop_n(&mut self, n: usize, op: &Token, span: &Span) -> Result<Vec<Expr>, Error> {
+        let mut operands = (0..n).map(|_| self.pop(op, span)).collect::<Result<Vec<_>, _>>()?;
+        operands.reverse();
+        Ok(operands)
+    }
+
+    /// Push the node of the popped operands, spanning from the first
+    /// one to the operator.
+    fn push(&mut self, node: Expr, span: &Span) -> Result<(), Error> {
+        let depth = std::mem::take(&mut self.depth);
+        if depth > self.max_depth {
+            return Err(ParseError::TooDeep{limit: self.max_depth, span: span.clone()}.into())
+        }
+        let start = self.start.take().unwrap_or(span.start);
+        self.operands.push((node.with_span(start..span.end), depth));
+        Ok(())
+    }
+}
+
+/// Name of the operand, for `let` and `=`.
+fn name(operand: &Expr, op: &Token, span: &Span) -> Result<String, Error> {
+    match operand {
+        Expr::Var(var) => Ok(var.name.clone()),
+        _ => Err(ParseError::UnexpectedToken{expected: "name", found: op.clone(), span: span.clone()}.into()),
+    }
+}
+
+/// Number of operands written after an operator, `(n)`, with the
+/// span of the `)`.
+fn arity<I>(tokens: &mut Peekable<I>) -> Result<Option<(usize, Span)>, Error>
+where I: Iterator<Item = (Token, Span)> {
+    let open = match tokens.peek() {
+        Some((Token::LP, span)) => span.clone(),
+        _ => return Ok(None),
+    };
+    tokens.next();
+    let n = match tokens.next() {
+        Some((Token::Number(n), span)) => usize::try_from(n).map_err(|_| ParseError::UnexpectedToken{expected: "number of operands", found: Token::Number(n), span})?,
+        Some((found, span)) => return Err(ParseError::UnexpectedToken{expected: "number of operands", found, span}.into()),
+        None => return Err(ParseError::UnexpectedEnd{expected: "number of operands", span: open.end..open.end}.into()),
+    };
+    match tokens.next() {
+        Some((Token::RP, span)) => Ok(Some((n, span))),
+        found => {
+            let span = found.as_ref().map_or(open.end..open.end, |(_, span)| span.clone());
+            Err(ParseError::Unclosed{open, closing: ")", found: found.map(|(token, _)| token), span}.into())
+        }
+    }
+}
+
+/// Parse postfix tokens to AST.
+pub fn parse(tokens: Vec<(Token, Span)>) -> Result<AST, Error> {
+    parse_limited(tokens, MAX_DEPTH)
+}
+
+/// Parse postfix tokens to AST, fail if nested deeper than
+/// `max_depth` rather than [`MAX_DEPTH`]. A deeper limit needs a
+/// larger stack, see [`Limits::depth`](crate::Limits::depth).
+pub fn parse_limited(tokens: Vec<(Token, Span)>, max_depth: usize) -> Result<AST, Error> {
+    let end = tokens.last().map_or(0, |(_, span)| span.end);
+    let mut tokens = tokens.into_iter().peekable();
+    let mut stack = Stack{operands: Vec::new(), depth: 0, start: None, max_depth};
+    while let Some((token, span)) = tokens.next() {
+        let arity = arity(&mut tokens)?;
+        let span = match &arity {
+            Some((_, close)) => span.start..close.end,
+            None => span,
+        };
+        let node = match (&token, arity.map(|(n, _)| n)) {
+            (Token::Number(num), None) => Number::new(*num),
+            (Token::Duration(secs), None) => Duration::new(*secs),
+            (Token::Date(days), None) => Date::new(*days),
+            (Token::Bool(value), None) => Bool::new(*value),
+            (Token::Str(value), None) => Str::new(value),
+            (Token::Ident(name), None) => Var::new(name),
+            (Token::Ident(name), Some(n)) => Call::new(name, stack.pop_n(n, &token, &span)?),
+            (Token::Minus, Some(1)) => Neg::new(stack.pop(&token, &span)?),
+            (op, None) if op.is_comparison()
+                || matches!(op, Token::Plus | Token::Minus | Token::Times | Token::Division | Token::And | Token::Or) => {
+                let rhs = stack.pop(&token, &span)?;
+                BinOp::new(stack.pop(&token, &span)?, rhs, token.clone())
+            }
+            (Token::DotDot, None) => {
+                let end = stack.pop(&token, &span)?;
+                Range::new(stack.pop(&token, &span)?, end)
+            }
+            (Token::Not, None) => Not::new(stack.pop(&token, &span)?),
+            // `x √` is `root(2, x)`, as `2 x root(2)`
+            (Token::Root, None) => Call::new(crate::root::NAME, vec![Number::new(2).with_span(span.clone()), stack.pop(&token, &span)?]),
+            (Token::Bar, None) => Abs::new(stack.pop(&token, &span)?),
+            (Token::If, None) => {
+                let otherwise = stack.pop(&token, &span)?;
+                let then = stack.pop(&token, &span)?;
+                If::new(stack.pop(&token, &span)?, then, otherwise)
+            }
+            (Token::Let, None) => {
+                let body = stack.pop(&token, &span)?;
+                let value = stack.pop(&token, &span)?;
+                Let::new(&name(&stack.pop(&token, &span)?, &token, &span)?, value, body)
+            }
+            (Token::Assign, None) => {
+                let value = stack.pop(&token, &span)?;
+                Assign::new(&name(&stack.pop(&token, &span)?, &token, &span)?, value)
+            }
+            // `[n]` or `[]`
+            (Token::LB, None) => {
+                let (items, close) = match tokens.next() {
+                    Some((Token::RB, close)) => (None, close),
+                    Some((Token::Number(n), _)) => match tokens.next() {
+                        Some((Token::RB, close)) => (Some(n), close),
+                        found => return Err(unclosed(span, found, end)),
+                    },
+                    found => return Err(unclosed(span, found, end)),
+                };
+                let span = span.start..close.end;
+                let node = match items {
+                    Some(n) => List::new(stack.pop_n(usize::try_from(n).unwrap_or(usize::MAX), &token, &span)?),
+                    None => {
+                        let index = stack.pop(&token, &span)?;
+                        Index::new(stack.pop(&token, &span)?, index)
+                    }
+                };
+                stack.push(node, &span)?;
+                continue
+            }
+            _ => return Err(ParseError::UnexpectedToken{expected: "operand or operator", found: token, span}.into()),
+        };
+        stack.push(node, &span)?;
+    }
+    match stack.operands.len() {
+        1 => Ok(AST{root: stack.operands.remove(0).0}),
+        0 => Err(ParseError::UnexpectedEnd{expected: "operand", span: end..end}.into()),
+        _ => Err(ParseError::UnexpectedEnd{expected: "operator", span: end..end}.into()),
+    }
+}
+
+fn unclosed(open: Span, found: Option<(Token, Span)>, end: usize) -> Error {
+    let span = found.as_ref().map_or(end..end, |(_, span)| span.clone());
+    ParseError::Unclosed{open, closing: "]", found: found.map(|(token, _)| token), span}.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+    use crate::parser::{export, top_down_parser};
+
+    fn rpn(input: &str) -> Result<AST, Error> {
+        parse(lexer::spanned(input)?)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(rpn("3 4 + 2 *").unwrap().to_string(), "(* (+ 3 4) 2)");
+        assert_eq!(rpn("1 2 3 - -").unwrap().to_string(), "(- 1 (- 2 3))");
+        assert_eq!(rpn("a ! b && |").unwrap().to_string(), "(abs (&& (! a) b))");
+        assert_eq!(rpn("16 √ 3 27 root(2) +").unwrap().to_string(), "(+ (root 2 16) (root 3 27))");
+        assert_eq!(rpn("f(0) x 1 2 [2] 0 [] g(3)").unwrap().to_string(), "(g (f) x (index (list 1 2) 0))");
+        let ast = rpn("1 2 + 3 *").unwrap();
+        assert_eq!(export::tree(&ast), "\
+* 0..9
+├── + 0..5
+│   ├── 1 0..1
+│   └── 2 2..3
+└── 3 6..7
+");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(rpn("1 +").unwrap_err(), ParseError::UnexpectedToken{expected: "operand", found: Token::Plus, span: 2..3}.into());
+        assert_eq!(rpn("1 2").unwrap_err(), ParseError::UnexpectedEnd{expected: "operator", span: 3..3}.into());
+        assert_eq!(rpn("").unwrap_err(), ParseError::UnexpectedEnd{expected: "operand", span: 0..0}.into());
+        assert_eq!(rpn("1 2 = ").unwrap_err(), ParseError::UnexpectedToken{expected: "name", found: Token::Assign, span: 4..5}.into());
+        assert_eq!(rpn("x 1 = 2 +").unwrap_err(), ParseError::InvalidExpression{span: 0..5}.into());
+        assert!(matches!(rpn("1 max(2"), Err(Error::Parse(ParseError::Unclosed{..}))));
+        assert!(matches!(rpn("1 [1"), Err(Error::Parse(ParseError::Unclosed{..}))));
+        let deep = "1 ".to_string() + &"-(1) ".repeat(MAX_DEPTH + 1);
+        assert!(matches!(rpn(&deep), Err(Error::Parse(ParseError::TooDeep{limit: MAX_DEPTH, ..}))));
+        let tokens = lexer::spanned("1 2 3 * +").unwrap();
+        assert_eq!(parse_limited(tokens.clone(), 2).map(|ast| ast.to_string()), Ok("(+ 1 (* 2 3))".to_string()));
+        assert_eq!(parse_limited(tokens, 1), Err(ParseError::TooDeep{limit: 1, span: 8..9}.into()));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let inputs = [
+            "x = -(1 + 2) * |y| / 3", "max(1, f(), -x) - 7", "!a || b && true", "if c then 1 else let h = 2 in h * h",
+            "sum([1, 2..n][0]) + sum(i, 1, 10, i * i)", "3√27 - √x", "0 <= x < 10 == (y != 1)",
+        ];
+        for input in inputs.iter() {
+            let ast = top_down_parser::parse(lexer::spanned(input).unwrap()).unwrap();
+            let postfix = export::rpn(&ast);
+            assert_eq!(rpn(&postfix).unwrap().to_string(), ast.to_string(), "input: {}", input);
+        }
+    }
+}
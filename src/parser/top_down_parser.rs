@@ -3,120 +3,523 @@
 //! Convert the grammar to the following grammar
 //! to solve priority:
 //! ```text
-//! S ::= expr
+//! S ::= Ident Assign <expr>
+//!     | <expr>
 //!
-//! <expr> ::= <term> <expr_tail>
-//! <expr_tail> ::= Plus <term> <expr_tail>
-//!               | Minus <term> <expr_tail>
+//! <expr> ::= <disj> DotDot <disj>
+//!          | <disj>
+//!
+//! <disj> ::= <conj> <disj_tail>
+//! <disj_tail> ::= Or <conj> <disj_tail>
+//!               | <empty>
+//!
+//! <conj> ::= <cmp> <conj_tail>
+//! <conj_tail> ::= And <cmp> <conj_tail>
 //!               | <empty>
 //!
+//! <cmp> ::= <sum> <cmp_tail>
+//! <cmp_tail> ::= <cmp_op> <sum> <cmp_tail>
+//!              | <empty>
+//! <cmp_op> ::= Less | LessEq | Greater | GreaterEq | Equal | NotEqual
+//!
+//! <sum> ::= <term> <sum_tail>
+//! <sum_tail> ::= Plus <term> <sum_tail>
+//!              | Minus <term> <sum_tail>
+//!              | <empty>
+//!
 //! <term> ::= <factor> <term_tail>
 //! <term_tail> ::= Times <factor> <term_tail>
 //!               | Division <factor> <term_tail>
 //!               | <empty>
 //!
-//! <factor> ::= LP <expr> RP
-//!            | Number
+//! <factor> ::= <primary> <index_tail>
+//!            | Number Root <factor>
+//!            | Root <factor>
 //!            | Minus <factor>
+//!            | Plus <factor>
+//!            | Not <factor>
+//!            | If <expr> Then <expr> Else <expr>
+//!            | Let Ident Assign <expr> In <expr>
+//! <index_tail> ::= LB <expr> RB <index_tail>
+//!                | <empty>
+//!
+//! <primary> ::= LP <expr> RP
+//!            | LB <args> RB
+//!            | Number
+//!            | Duration
+//!            | Date
+//!            | Bool
+//!            | Str
+//!            | Bar <expr> Bar
+//!            | Ident LP <args> RP
+//!            | Ident
+//!
+//! <args> ::= <expr> <args_tail>
+//!          | <empty>
+//! <args_tail> ::= Comma <expr> <args_tail>
+//!               | <empty>
 //! ```
-use crate::lexer::Token;
+//!
+//! A `|` where an operand is expected opens an absolute value, and
+//! one where an operator is expected closes the innermost open
+//! absolute value, so `||x| - 1|` nests. A binary operator written
+//! `|`, such as a bitwise or, would only be one outside of the bars.
+//!
+//! So `||` is a logical or only where an operator is expected outside
+//! of the bars, elsewhere it is two bars: `||x| - 1|` and `|x - |y||`
+//! nest, and a logical or inside bars is written in parentheses, such
+//! as `|(a || b)|`.
+//!
+//! The comparisons chain as in mathematics, so `1 < x < 10` is
+//! `1 < x && x < 10` rather than the comparison of `1 < x`, 0 or 1,
+//! with 10. The operand between two comparisons is in both, with
+//! the same span, and the calculators calculate it once.
+//!
+//! `√x` is `root(2, x)` and `3√x` is `root(3, x)`, the degree
+//! being the number written right before the `√`.
+//!
+//! The `else` branch of an `if` and the body of a `let` reach as
+//! far right as they can, so `if c then 1 else 2 + 3` is
+//! `if c then 1 else (2 + 3)`.
+use crate::lexer::{Span, Token};
+use crate::error::{Error, ParseError};
 use super::ast::*;
+use super::{MAX_DEPTH, MAX_OPERATORS};
 
 use std::iter::Peekable;
-use std::slice::Iter;
 
 
-struct Parser<'a> {
-    iter: Peekable<Iter<'a, Token>>
+struct Parser<I: Iterator<Item = Result<(Token, Span), Error>>> {
+    iter: Peekable<I>,
+    /// End of the last token
+    end: usize,
+    depth: usize,
+    max_depth: usize,
+    /// Binary operators so far
+    operators: usize,
+    /// Absolute values open since the last parenthesis
+    bars: usize,
+    /// Second bar of a `||` split in two bars
+    pending: Option<Span>,
+    /// Last token if it is `*` or `/`, and the one before, to
+    /// suggest `*` for a `**`
+    last_op: Option<(Token, Span)>,
+    prev_op: Option<(Token, Span)>,
 }
 
-impl<'a> Parser<'a> {
-    fn eof(&mut self) -> bool {
-        self.iter.peek().is_none()
+impl<I: Iterator<Item = Result<(Token, Span), Error>>> Parser<I> {
+    fn s(&mut self) -> Result<Expr, Error> {
+        let expr = self.expr()?;
+        match expr {
+            Expr::Var(var) if self.peek()? == Some(&Token::Assign) => {
+                self.get_token("=")?;
+                let value = self.expr()?;
+                let end = value.span().end;
+                Ok(Assign::new(&var.name, value).with_span(var.span.start..end))
+            }
+            expr => Ok(expr)
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        // The range is parsed by the tail, so the frames of the nested
+        // expressions stay as small as without it
+        let lhs = self.conj()?;
+        self.expr_tail(lhs)
+    }
+
+    fn expr_tail(&mut self, lhs: Expr) -> Result<Expr, Error> {
+        let start = self.disj_tail(lhs)?;
+        if self.peek()? != Some(&Token::DotDot) {
+            return Ok(start)
+        }
+        self.range(start)
+    }
+
+    fn range(&mut self, start: Expr) -> Result<Expr, Error> {
+        self.get_token("..")?;
+        let end = self.disj()?;
+        Ok(Range::new(start, end))
+    }
+
+    fn disj(&mut self) -> Result<Expr, Error> {
+        let lhs = self.conj()?;
+        self.disj_tail(lhs)
+    }
+
+    fn disj_tail(&mut self, mut lhs: Expr) -> Result<Expr, Error> {
+        // Inside bars, `||` closes two of them
+        while self.bars == 0 && self.peek()? == Some(&Token::Or) {
+            let (_, span) = self.get_token("||")?;
+            self.operator(span)?;
+            let rhs = self.conj()?;
+            lhs = BinOp::new(lhs, rhs, Token::Or);
+        }
+        Ok(lhs)
+    }
+
+    fn conj(&mut self) -> Result<Expr, Error> {
+        let lhs = self.cmp()?;
+        self.conj_tail(lhs)
+    }
+
+    fn conj_tail(&mut self, mut lhs: Expr) -> Result<Expr, Error> {
+        while self.peek()? == Some(&Token::And) {
+            let (_, span) = self.get_token("&&")?;
+            self.operator(span)?;
+            let rhs = self.cmp()?;
+            lhs = BinOp::new(lhs, rhs, Token::And);
+        }
+        Ok(lhs)
+    }
+
+    // Inlined to keep the stack of the nested parentheses small
+    #[inline(always)]
+    fn cmp(&mut self) -> Result<Expr, Error> {
+        let lhs = self.sum()?;
+        self.cmp_tail(None, lhs)
     }
 
-    fn s(&mut self) -> Result<Expr, String> {
-        self.expr()
+    /// Comparisons after `lhs`, the operand of the next one, and
+    /// the conjunction of the ones before it if any.
+    #[inline(never)]
+    fn cmp_tail(&mut self, mut chain: Option<Expr>, mut lhs: Expr) -> Result<Expr, Error> {
+        loop {
+            let op = match self.peek()? {
+                Some(op) if op.is_comparison() => op.clone(),
+                _ => return Ok(chain.unwrap_or(lhs)),
+            };
+            let (_, span) = self.get_token("comparison")?;
+            self.operator(span)?;
+            let rhs = self.sum()?;
+            let cmp = BinOp::new(lhs, rhs.clone(), op);
+            chain = Some(match chain {
+                Some(chain) => BinOp::new(chain, cmp, Token::And),
+                None => cmp,
+            });
+            lhs = rhs;
+        }
     }
 
-    fn expr(&mut self) -> Result<Expr, String> {
+    fn sum(&mut self) -> Result<Expr, Error> {
         let lhs = self.term()?;
-        self.expr_tail(lhs)
+        self.sum_tail(lhs)
     }
 
-    fn expr_tail(&mut self, lhs: Expr) -> Result<Expr, String> {
-        let token = self.iter.peek();
-        match token {
-            Some(Token::Plus) => {
-                self.get_token("+")?;
-                let rhs = self.term()?;
-                self.expr_tail(BinOp::new(lhs, rhs, Token::Plus))
-            }
-            Some(Token::Minus) => {
-                self.get_token("-")?;
-                let rhs = self.term()?;
-                self.expr_tail(BinOp::new(lhs, rhs, Token::Minus))
-            }
-            _ => {
-                Ok(lhs)
-            }
+    fn sum_tail(&mut self, mut lhs: Expr) -> Result<Expr, Error> {
+        loop {
+            let op = match self.peek()? {
+                Some(Token::Plus) => Token::Plus,
+                Some(Token::Minus) => Token::Minus,
+                _ => return Ok(lhs),
+            };
+            let (_, span) = self.get_token("+ or -")?;
+            self.operator(span)?;
+            let rhs = self.term()?;
+            lhs = BinOp::new(lhs, rhs, op);
         }
     }
 
-    fn term(&mut self) -> Result<Expr, String> {
+    fn term(&mut self) -> Result<Expr, Error> {
         let lval = self.factor()?;
         self.term_tail(lval)
     }
 
-    fn term_tail(&mut self, lhs: Expr) -> Result<Expr, String> {
-        let token = self.iter.peek();
-        match token {
-            Some(Token::Times) => {
-                self.get_token("*")?;
-                let rhs = self.factor()?;
-                self.term_tail(BinOp::new(lhs, rhs, Token::Times))
-            }
-            Some(Token::Division) => {
-                self.get_token("/")?;
-                let rhs = self.factor()?;
-                self.term_tail(BinOp::new(lhs, rhs, Token::Division))
-            }
-            _ => {
-                Ok(lhs)
-            }
+    fn term_tail(&mut self, mut lhs: Expr) -> Result<Expr, Error> {
+        loop {
+            let op = match self.peek()? {
+                Some(Token::Times) => Token::Times,
+                Some(Token::Division) => Token::Division,
+                _ => return Ok(lhs),
+            };
+            let (_, span) = self.get_token("* or /")?;
+            self.operator(span)?;
+            let rhs = self.factor()?;
+            lhs = BinOp::new(lhs, rhs, op);
+        }
+    }
+
+    fn index_tail(&mut self, mut list: Expr) -> Result<Expr, Error> {
+        while self.peek()? == Some(&Token::LB) {
+            let (_, open) = self.get_token("[")?;
+            list = self.nested(open.clone(), |parser| {
+                let bars = std::mem::replace(&mut parser.bars, 0);
+                let index = parser.expr()?;
+                parser.bars = bars;
+                let end = parser.expect(Token::RB, "]").map_err(|err| unclosed(err, &open, "]"))?;
+                let start = list.span().start;
+                Ok(Index::new(list, index).with_span(start..end.end))
+            })?;
         }
+        Ok(list)
     }
 
-    fn factor(&mut self) -> Result<Expr, String> {
-        let token = self.get_token("number")?;
+    fn factor(&mut self) -> Result<Expr, Error> {
+        let (token, span) = self.get_token("number")?;
+        let (token, span) = self.split(token, span);
+        // The primaries parse their indices in tail position, so the
+        // frames of the nested expressions stay as small as without them
         match token {
             Token::LP => {
-                let expr = self.expr()?;
-                self.get_token(")")?;
-                Ok(Pair::new(expr))
+                self.nested(span.clone(), |parser| {
+                    let bars = std::mem::replace(&mut parser.bars, 0);
+                    let expr = parser.expr()?;
+                    parser.bars = bars;
+                    let end = parser.expect(Token::RP, ")").map_err(|err| unclosed(err, &span, ")"))?;
+                    parser.index_tail(Pair::new(expr).with_span(span.start..end.end))
+                })
             }
             Token::Minus => {
-                let expr = self.factor()?;
-                Ok(Neg::new(expr))
+                self.nested(span.clone(), |parser| {
+                    let expr = parser.factor()?;
+                    let end = expr.span().end;
+                    Ok(Neg::new(expr).with_span(span.start..end))
+                })
+            }
+            Token::Bar => {
+                self.nested(span.clone(), |parser| {
+                    parser.bars += 1;
+                    let expr = parser.expr()?;
+                    parser.bars -= 1;
+                    let end = parser.expect(Token::Bar, "|").map_err(|err| unclosed(err, &span, "|"))?;
+                    parser.index_tail(Abs::new(expr).with_span(span.start..end.end))
+                })
+            }
+            Token::Plus => {
+                self.nested(span.clone(), |parser| {
+                    let expr = parser.factor()?;
+                    let end = expr.span().end;
+                    Ok(expr.with_span(span.start..end))
+                })
+            }
+            Token::If => {
+                self.nested(span.clone(), |parser| {
+                    // The keywords close the condition and the first
+                    // branch, like parentheses
+                    let bars = std::mem::replace(&mut parser.bars, 0);
+                    let cond = parser.expr()?;
+                    parser.expect(Token::Then, "then")?;
+                    let then = parser.expr()?;
+                    parser.expect(Token::Else, "else")?;
+                    parser.bars = bars;
+                    let otherwise = parser.expr()?;
+                    let end = otherwise.span().end;
+                    Ok(If::new(cond, then, otherwise).with_span(span.start..end))
+                })
+            }
+            Token::Let => {
+                self.nested(span.clone(), |parser| {
+                    let name = match parser.get_token("name")? {
+                        (Token::Ident(name), _) => name,
+                        (found, span) => return Err(ParseError::UnexpectedToken{expected: "name", found, span}.into()),
+                    };
+                    parser.expect(Token::Assign, "=")?;
+                    let bars = std::mem::replace(&mut parser.bars, 0);
+                    let value = parser.expr()?;
+                    parser.expect(Token::In, "in")?;
+                    parser.bars = bars;
+                    let body = parser.expr()?;
+                    let end = body.span().end;
+                    Ok(Let::new(&name, value, body).with_span(span.start..end))
+                })
+            }
+            Token::Not => {
+                self.nested(span.clone(), |parser| {
+                    let expr = parser.factor()?;
+                    let end = expr.span().end;
+                    Ok(Not::new(expr).with_span(span.start..end))
+                })
+            }
+            Token::Ident(name) => self.ident(name, span),
+            Token::LB => self.list(span),
+            Token::Root => self.root(Number::new(2).with_span(span.clone()), span),
+            Token::Number(num) => self.number(num, span),
+            Token::Duration(secs) => {
+                self.index_tail(Duration::new(secs).with_span(span))
             }
-            Token::Number(num) => {
-                Ok(Number::new(num))
+            Token::Date(days) => {
+                self.index_tail(Date::new(days).with_span(span))
             }
-            _ => {
-                Err(format!("Expect number, got {}", token))
+            Token::Bool(value) => {
+                self.index_tail(Bool::new(value).with_span(span))
             }
+            Token::Str(value) => Ok(Str::new(&value).with_span(span)),
+            _ => Err(self.near_miss(token, span)),
         }
     }
 
-    fn get_token(&mut self, expect: &str) -> Result<Token, String> {
-        if let Some(token) = self.iter.next() {
-            Ok(*token)
+    /// Error of the token where a number is expected, an unknown
+    /// operator if it doubles the previous one, such as `**`.
+    fn near_miss(&self, found: Token, span: Span) -> Error {
+        match &self.prev_op {
+            Some((prev, prev_span)) if *prev == found && prev_span.end == span.start => {
+                let (found, suggestion) = (format!("{}{}", prev, found), prev.to_string());
+                ParseError::UnknownOperator{found, suggestion, span: prev_span.start..span.end}.into()
+            }
+            _ => ParseError::UnexpectedToken{expected: "number", found, span}.into(),
+        }
+    }
+
+    /// Variable, or call if the name is followed by `(`. Apart from
+    /// `factor` to keep its frame small.
+    #[inline(never)]
+    fn ident(&mut self, name: String, span: Span) -> Result<Expr, Error> {
+        if self.peek()? != Some(&Token::LP) {
+            return self.index_tail(Var::new(&name).with_span(span))
+        }
+        let open = self.expect(Token::LP, "(")?;
+        self.nested(span.clone(), |parser| {
+            let bars = std::mem::replace(&mut parser.bars, 0);
+            let (args, end) = parser.args(Token::RP).map_err(|err| unclosed(err, &open, ")"))?;
+            parser.bars = bars;
+            parser.index_tail(Call::new(&name, args).with_span(span.start..end.end))
+        })
+    }
+
+    /// Number, or the root of the degree before a `√`. Apart from
+    /// `factor` to keep its frame small.
+    #[inline(never)]
+    fn number(&mut self, num: u128, span: Span) -> Result<Expr, Error> {
+        let number = Number::new(num).with_span(span.clone());
+        if self.peek()? == Some(&Token::Root) {
+            let root = self.expect(Token::Root, "√")?;
+            return self.root(number, span.start..root.end)
+        }
+        self.index_tail(number)
+    }
+
+    /// Root of the factor after the `√` spanning `span`, of the
+    /// degree written before it.
+    fn root(&mut self, degree: Expr, span: Span) -> Result<Expr, Error> {
+        self.nested(span.clone(), |parser| {
+            let radicand = parser.factor()?;
+            let end = radicand.span().end;
+            Ok(Call::new(crate::root::NAME, vec![degree, radicand]).with_span(span.start..end))
+        })
+    }
+
+    fn list(&mut self, open: Span) -> Result<Expr, Error> {
+        self.nested(open.clone(), |parser| {
+            let bars = std::mem::replace(&mut parser.bars, 0);
+            let (items, end) = parser.args(Token::RB).map_err(|err| unclosed(err, &open, "]"))?;
+            parser.bars = bars;
+            parser.index_tail(List::new(items).with_span(open.start..end.end))
+        })
+    }
+
+    /// Parse the arguments of a call or the items of a list,
+    /// return them with the span of the `closing` `)` or `]`.
+    fn args(&mut self, closing: Token) -> Result<(Vec<Expr>, Span), Error> {
+        let (name, expected) = match closing {
+            Token::RB => ("]", ", or ]"),
+            _ => (")", ", or )"),
+        };
+        let mut args = Vec::new();
+        if self.peek()? == Some(&closing) {
+            let end = self.expect(closing, name)?;
+            return Ok((args, end))
+        }
+        loop {
+            args.push(self.expr()?);
+            let (token, span) = self.get_token(name)?;
+            match token {
+                Token::Comma => {}
+                token if token == closing => return Ok((args, span)),
+                _ => return Err(ParseError::UnexpectedToken{expected, found: token, span}.into()),
+            }
+        }
+    }
+
+    /// Parse a nested expression, fail if nested too deep.
+    fn nested<F>(&mut self, span: Span, f: F) -> Result<Expr, Error>
+    where F: FnOnce(&mut Self) -> Result<Expr, Error> {
+        if self.depth >= self.max_depth {
+            return Err(ParseError::TooDeep{limit: self.max_depth, span}.into())
+        }
+        self.depth += 1;
+        let expr = f(self);
+        self.depth -= 1;
+        expr
+    }
+
+    /// Count a binary operator, fail past [`MAX_OPERATORS`].
+    fn operator(&mut self, span: Span) -> Result<(), Error> {
+        if self.operators >= MAX_OPERATORS {
+            return Err(ParseError::TooManyOperators{limit: MAX_OPERATORS, span}.into())
+        }
+        self.operators += 1;
+        Ok(())
+    }
+
+    fn expect(&mut self, expect: Token, name: &'static str) -> Result<Span, Error> {
+        let (token, span) = self.get_token(name)?;
+        let (token, span) = match expect {
+            Token::Bar => self.split(token, span),
+            _ => (token, span),
+        };
+        if token == expect {
+            Ok(span)
         } else {
-            Err(format!("Expect {}, got nothing", expect))
+            Err(ParseError::UnexpectedToken{expected: name, found: token, span}.into())
+        }
+    }
+
+    /// Split a `||` in two bars, return the first one and read the
+    /// second one next.
+    fn split(&mut self, token: Token, span: Span) -> (Token, Span) {
+        match token {
+            Token::Or => {
+                self.pending = Some(span.start + 1..span.end);
+                (Token::Bar, span.start..span.start + 1)
+            }
+            token => (token, span),
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token>, Error> {
+        if self.pending.is_some() {
+            return Ok(Some(&Token::Bar))
+        }
+        match self.iter.peek() {
+            Some(Ok((token, _))) => Ok(Some(token)),
+            Some(Err(err)) => Err(err.clone()),
+            None => Ok(None),
+        }
+    }
+
+    fn get_token(&mut self, expect: &'static str) -> Result<(Token, Span), Error> {
+        if let Some(span) = self.pending.take() {
+            self.end = span.end;
+            self.prev_op = self.last_op.take();
+            return Ok((Token::Bar, span))
+        }
+        match self.iter.next() {
+            Some(Ok((token, span))) => {
+                self.end = span.end;
+                self.prev_op = self.last_op.take();
+                if matches!(token, Token::Times | Token::Division) {
+                    self.last_op = Some((token.clone(), span.clone()));
+                }
+                Ok((token, span))
+            }
+            Some(Err(err)) => Err(err),
+            None => Err(ParseError::UnexpectedEnd{expected: expect, span: self.end..self.end}.into()),
         }
     }
 }
 
+/// Point the missing `)` or `|` of the error at the `(` or `|`
+/// that it closes.
+fn unclosed(err: Error, open: &Span, closing: &'static str) -> Error {
+    match err {
+        Error::Parse(ParseError::UnexpectedEnd{expected, span}) if expected == closing =>
+            ParseError::Unclosed{open: open.clone(), closing, found: None, span}.into(),
+        Error::Parse(ParseError::UnexpectedToken{expected, found, span}) if expected == closing =>
+            ParseError::Unclosed{open: open.clone(), closing, found: Some(found), span}.into(),
+        err => err,
+    }
+}
+
 /// Parse tokens to AST.
 ///
 /// # Example
@@ -125,19 +528,59 @@ impl<'a> Parser<'a> {
 /// use wcal::parser::top_down_parser::parse;
 /// use wcal::parser::ast::*;
 ///
-/// let tokens = lexer::lexer("12+3").unwrap();
+/// let tokens = lexer::spanned("12+3").unwrap();
 /// let ast = parse(tokens).unwrap();
 /// assert_eq!(ast, AST{root: BinOp::new(Number::new(12), Number::new(3), lexer::Token::Plus)});
 /// ```
-pub fn parse(tokens: Vec<Token>) -> Result<AST, String> {
+pub fn parse(tokens: Vec<(Token, Span)>) -> Result<AST, Error> {
+    parse_stream(tokens.into_iter().map(Ok))
+}
+
+/// Parse tokens to AST while lexing, without collecting the tokens.
+///
+/// Errors of the lexer are returned as they are met.
+///
+/// # Example
+/// ```
+/// use wcal::lexer;
+/// use wcal::parser::top_down_parser::parse_stream;
+/// use wcal::parser::ast::*;
+///
+/// let ast = parse_stream(lexer::tokens("12+3")).unwrap();
+/// assert_eq!(ast, AST{root: BinOp::new(Number::new(12), Number::new(3), lexer::Token::Plus)});
+/// ```
+pub fn parse_stream<I>(tokens: I) -> Result<AST, Error>
+where I: IntoIterator<Item = Result<(Token, Span), Error>> {
+    parse_limited(tokens, MAX_DEPTH)
+}
+
+/// Parse tokens to AST while lexing, fail if nested deeper than
+/// `max_depth` rather than [`MAX_DEPTH`]. A deeper limit needs a
+/// larger stack, see [`Limits::depth`](crate::Limits::depth).
+pub fn parse_limited<I>(tokens: I, max_depth: usize) -> Result<AST, Error>
+where I: IntoIterator<Item = Result<(Token, Span), Error>> {
     let mut parser = Parser{
-        iter: tokens.iter().peekable()
+        iter: tokens.into_iter().peekable(),
+        end: 0,
+        depth: 0,
+        max_depth,
+        operators: 0,
+        bars: 0,
+        pending: None,
+        last_op: None,
+        prev_op: None,
     };
     let root = parser.s()?;
-    if parser.eof() {
-        Ok(AST{root})
+    let rest = match parser.pending.take() {
+        Some(span) => Some(Ok((Token::Bar, span))),
+        None => parser.iter.next(),
+    };
+    if let Some(token) = rest {
+        let (_, span) = token?;
+        let end = parser.iter.try_fold(span.end, |_, token| token.map(|(_, span)| span.end))?;
+        Err(ParseError::InvalidExpression{span: span.start..end}.into())
     } else {
-        Err(String::from("Invalid expression"))
+        Ok(AST{root})
     }
 }
 
@@ -147,32 +590,32 @@ mod tests {
     use crate::lexer;
 
     #[test]
-    fn test_add() -> Result<(), String> {
-        let tokens = lexer::lexer("12+3")?;
+    fn test_add() -> Result<(), Error> {
+        let tokens = lexer::spanned("12+3")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: BinOp::new(Number::new(12), Number::new(3), lexer::Token::Plus)});
         Ok(())
     }
 
     #[test]
-    fn test_sub() -> Result<(), String> {
-        let tokens = lexer::lexer("12-3")?;
+    fn test_sub() -> Result<(), Error> {
+        let tokens = lexer::spanned("12-3")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: BinOp::new(Number::new(12), Number::new(3), lexer::Token::Minus)});
         Ok(())
     }
 
     #[test]
-    fn test_times() -> Result<(), String> {
-        let tokens = lexer::lexer("12*3")?;
+    fn test_times() -> Result<(), Error> {
+        let tokens = lexer::spanned("12*3")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: BinOp::new(Number::new(12), Number::new(3), lexer::Token::Times)});
         Ok(())
     }
 
     #[test]
-    fn test_div() -> Result<(), String> {
-        let tokens = lexer::lexer("12/3")?;
+    fn test_div() -> Result<(), Error> {
+        let tokens = lexer::spanned("12/3")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: BinOp::new(Number::new(12), Number::new(3), lexer::Token::Division)});
         Ok(())
@@ -180,62 +623,233 @@ mod tests {
 
 
     #[test]
-    fn test_num() -> Result<(), String> {
-        let tokens = lexer::lexer("12")?;
+    fn test_num() -> Result<(), Error> {
+        let tokens = lexer::spanned("12")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: Number::new(12)});
 
-        let tokens = lexer::lexer("-12")?;
+        let tokens = lexer::spanned("-12")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: Neg::new(Number::new(12))});
         Ok(())
     }
 
     #[test]
-    fn test_pair() -> Result<(), String> {
-        let tokens = lexer::lexer("((12))")?;
+    fn test_pair() -> Result<(), Error> {
+        let tokens = lexer::spanned("((12))")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: Pair::new(Pair::new(Number::new(12)))});
         Ok(())
     }
 
+    #[test]
+    fn test_call() -> Result<(), Error> {
+        let tokens = lexer::spanned("f()")?;
+        let ast = parse(tokens)?;
+        assert_eq!(ast, AST{root: Call::new("f", vec![])});
+
+        let tokens = lexer::spanned("-hypot(3, 2*2)")?;
+        let ast = parse(tokens)?;
+        assert_eq!(ast, AST{root: Neg::new(Call::new("hypot", vec![
+            Number::new(3),
+            BinOp::new(Number::new(2), Number::new(2), lexer::Token::Times),
+        ]))});
+        if let Expr::Neg(Neg{expr, ..}) = &ast.root {
+            assert_eq!(expr.span(), 1..14);
+        } else {
+            panic!("Expect Neg");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_assign() -> Result<(), Error> {
+        let tokens = lexer::spanned("x")?;
+        let ast = parse(tokens)?;
+        assert_eq!(ast, AST{root: Var::new("x")});
+
+        let tokens = lexer::spanned("x = y * f(y)")?;
+        let ast = parse(tokens)?;
+        assert_eq!(ast, AST{root: Assign::new("x", BinOp::new(
+            Var::new("y"),
+            Call::new("f", vec![Var::new("y")]),
+            lexer::Token::Times,
+        ))});
+        assert_eq!(ast.root.span(), 0..12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_assign_error() {
+        let tokens = lexer::spanned("x =").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Expect number, got nothing");
+
+        let tokens = lexer::spanned("(x) = 1").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid expression");
+
+        let tokens = lexer::spanned("x = y = 1").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.span(), 6..9);
+    }
+
+    #[test]
+    fn test_call_error() {
+        let tokens = lexer::spanned("f(1,)").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Expect number, got )");
+
+        let tokens = lexer::spanned("f(1 2)").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Expect , or ), got 2");
+        assert_eq!(err.span(), 4..5);
+
+        let tokens = lexer::spanned("1, 2").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid expression");
+    }
+
     #[test]
     fn test_expect_num() {
-        let tokens = lexer::lexer("1+").unwrap();
+        let tokens = lexer::spanned("1+").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Expect number, got nothing");
+
+        let tokens = lexer::spanned("*").unwrap();
         let err = parse(tokens).err().unwrap();
-        assert_eq!(err, "Expect number, got nothing");
+        assert_eq!(err.to_string(), "Expect number, got *");
 
-        let tokens = lexer::lexer("+").unwrap();
+        // Unary plus
+        let tokens = lexer::spanned("+").unwrap();
         let err = parse(tokens).err().unwrap();
-        assert_eq!(err, "Expect number, got +");
+        assert_eq!(err.to_string(), "Expect number, got nothing");
 
-        let tokens = lexer::lexer("(").unwrap();
+        let tokens = lexer::spanned("(").unwrap();
         let err = parse(tokens).err().unwrap();
-        assert_eq!(err, "Expect number, got nothing");
+        assert_eq!(err.to_string(), "Expect number, got nothing");
+    }
+
+    #[test]
+    fn test_unknown_operator() {
+        let err = parse(lexer::spanned("2**+3").unwrap()).unwrap_err();
+        assert_eq!(err, ParseError::UnknownOperator{found: "**".to_string(), suggestion: "*".to_string(), span: 1..3}.into());
+        assert_eq!(err.to_string(), "Unknown operator **, did you mean *?");
+        let err = parse(lexer::spanned("4 // 2").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown operator //, did you mean /?");
+        // Not written as one operator
+        let err = parse(lexer::spanned("2 * * 3").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Expect number, got *");
+        let err = parse(lexer::spanned("2 */ 3").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Expect number, got /");
     }
 
     #[test]
     fn test_pair_error() {
-        let tokens = lexer::lexer("(((2))").unwrap();
+        let tokens = lexer::spanned("(((2))").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Expect ), got nothing");
+
+        let tokens = lexer::spanned("(2)(1)").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid expression");
+
+        let tokens = lexer::spanned("(())").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Expect number, got )");
+
+        let tokens = lexer::spanned("(1 2)").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err.to_string(), "Expect ), got 2");
+    }
+
+    #[test]
+    fn test_error_span() {
+        let tokens = lexer::spanned("(((2)) ").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err, Error::Parse(ParseError::Unclosed{open: 0..1, closing: ")", found: None, span: 6..6}));
+
+        let tokens = lexer::spanned("((1) 2)").unwrap();
+        let err = parse(tokens).err().unwrap();
+        assert_eq!(err, Error::Parse(ParseError::Unclosed{open: 0..1, closing: ")", found: Some(Token::Number(2)), span: 5..6}));
+
+        let tokens = lexer::spanned("f(g(1)").unwrap();
         let err = parse(tokens).err().unwrap();
-        assert_eq!(err, "Expect ), got nothing");
+        assert_eq!(err, Error::Parse(ParseError::Unclosed{open: 1..2, closing: ")", found: None, span: 6..6}));
 
-        let tokens = lexer::lexer("(2)(1)").unwrap();
+        let tokens = lexer::spanned("1 + * 2").unwrap();
         let err = parse(tokens).err().unwrap();
-        assert_eq!(err, "Invalid expression");
+        assert_eq!(err.span(), 4..5);
 
-        let tokens = lexer::lexer("(())").unwrap();
+        let tokens = lexer::spanned("(2) (1)").unwrap();
         let err = parse(tokens).err().unwrap();
-        assert_eq!(err, "Expect number, got )");
+        assert_eq!(err.span(), 4..7);
+
+        let err = parse(Vec::new()).err().unwrap();
+        assert_eq!(err.span(), 0..0);
+    }
+
+    #[test]
+    fn test_stream() {
+        let inputs = ["1+2*(3-4)/-5", "(((2))", "1 + * 2", "(2) (1)", "(2) (1) $", "1 + $", "1 + 0x1_0000_0000_0000_0000 + $", "f(1, 2", "f(1 $", ""];
+        for input in inputs.iter() {
+            let collected = lexer::spanned(input).and_then(parse);
+            assert_eq!(parse_stream(lexer::tokens(input)), collected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_span() -> Result<(), Error> {
+        let ast = parse(lexer::spanned("-(1 + 2) * 30")?)?;
+        assert_eq!(ast.root.span(), 0..13);
+        if let Expr::BinOp(BinOp{lhs, rhs, ..}) = &ast.root {
+            assert_eq!(lhs.span(), 0..8);
+            assert_eq!(rhs.span(), 11..13);
+        } else {
+            panic!("Expect BinOp");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_too_deep() {
+        let expr = "(".repeat(MAX_DEPTH + 1) + "1" + &")".repeat(MAX_DEPTH + 1);
+        let err = parse(lexer::spanned(&expr).unwrap()).err().unwrap();
+        assert_eq!(err, Error::Parse(ParseError::TooDeep{limit: MAX_DEPTH, span: MAX_DEPTH..MAX_DEPTH + 1}));
+
+        let expr = "-".repeat(MAX_DEPTH) + "1";
+        assert!(parse(lexer::spanned(&expr).unwrap()).is_ok());
+
+        // A chain of operators isn't nested
+        let expr = vec!["1"; 1000].join("+");
+        assert!(parse(lexer::spanned(&expr).unwrap()).is_ok());
+        assert!(parse_limited(lexer::tokens("1+2*3-4/5"), 1).is_ok());
+
+        let expr = vec!["1"; MAX_OPERATORS + 2].join("+");
+        let err = parse(lexer::spanned(&expr).unwrap()).err().unwrap();
+        let at = 2 * MAX_OPERATORS + 1;
+        assert_eq!(err, Error::Parse(ParseError::TooManyOperators{limit: MAX_OPERATORS, span: at..at + 1}));
     }
 
     #[test]
-    fn test_priority() -> Result<(), String> {
-        let tokens = lexer::lexer("1+3*6")?;
+    fn test_limited() {
+        assert!(parse_limited(lexer::tokens("((1))"), 2).is_ok());
+
+        let err = parse_limited(lexer::tokens("(((1)))"), 2).err().unwrap();
+        assert_eq!(err, Error::Parse(ParseError::TooDeep{limit: 2, span: 2..3}));
+
+        // The limit isn't capped by the default one
+        let expr = "(".repeat(MAX_DEPTH + 1) + "1" + &")".repeat(MAX_DEPTH + 1);
+        assert!(parse_limited(lexer::tokens(&expr), MAX_DEPTH + 1).is_ok());
+    }
+
+    #[test]
+    fn test_priority() -> Result<(), Error> {
+        let tokens = lexer::spanned("1+3*6")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: BinOp::new(Number::new(1), BinOp::new(Number::new(3), Number::new(6), lexer::Token::Times), lexer::Token::Plus)});
 
-        let tokens = lexer::lexer("6/(2-3)")?;
+        let tokens = lexer::spanned("6/(2-3)")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: BinOp::new(Number::new(6), Pair::new(BinOp::new(Number::new(2), Number::new(3), lexer::Token::Minus)), lexer::Token::Division)});
 
@@ -243,19 +857,141 @@ mod tests {
     }
 
     #[test]
-    fn test_neg() -> Result<(), String> {
-        let tokens = lexer::lexer("-7--2")?;
+    fn test_neg() -> Result<(), Error> {
+        let tokens = lexer::spanned("-7--2")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: BinOp::new(Neg::new(Number::new(7)), Neg::new(Number::new(2)), lexer::Token::Minus)});
 
-        let tokens = lexer::lexer("---7")?;
+        let tokens = lexer::spanned("---7")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: Neg::new(Neg::new(Neg::new(Number::new(7))))});
 
-        let tokens = lexer::lexer("-(1+2)")?;
+        let tokens = lexer::spanned("-(1+2)")?;
         let ast = parse(tokens)?;
         assert_eq!(ast, AST{root: Neg::new(Pair::new(BinOp::new(Number::new(1), Number::new(2), lexer::Token::Plus)))});
 
         Ok(())
     }
+
+    #[test]
+    fn test_unary_plus() -> Result<(), Error> {
+        let ast = parse(lexer::spanned("+7 - +-2")?)?;
+        assert_eq!(ast, AST{root: BinOp::new(Number::new(7), Neg::new(Number::new(2)), lexer::Token::Minus)});
+        assert_eq!(ast.root.span(), 0..8);
+        if let Expr::BinOp(BinOp{rhs, ..}) = &ast.root {
+            assert_eq!(rhs.span(), 5..8);
+        }
+        assert!(parse(lexer::spanned("1 + +")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_comparison() -> Result<(), Error> {
+        let ast = parse(lexer::spanned("1 + 1 < x <= 10 && y != 0")?)?;
+        assert_eq!(ast.to_string(), "(&& (&& (< (+ 1 1) x) (<= x 10)) (!= y 0))");
+        if let Expr::BinOp(BinOp{lhs, ..}) = &ast.root {
+            assert_eq!(lhs.span(), 0..15);
+        }
+        assert_eq!(parse(lexer::spanned("a < b > c == d")?)?.to_string(), "(&& (&& (< a b) (> b c)) (== c d))");
+        assert_eq!(parse(lexer::spanned("(a < b) < c")?)?.to_string(), "(< (< a b) c)");
+        assert!(parse(lexer::spanned("1 < < 2")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_root() -> Result<(), Error> {
+        let ast = parse(lexer::spanned("√16 * 3√-x")?)?;
+        assert_eq!(ast.to_string(), "(* (root 2 16) (root 3 (- x)))");
+        if let Expr::BinOp(BinOp{lhs, rhs, ..}) = &ast.root {
+            assert_eq!((lhs.span(), rhs.span()), (0..5, 8..14));
+        }
+        assert_eq!(parse(lexer::spanned("√√16 + 1")?)?.to_string(), "(+ (root 2 (root 2 16)) 1)");
+        assert!(parse(lexer::spanned("2√")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_abs() -> Result<(), Error> {
+        let ast = parse(lexer::spanned("2*|x - 3|")?)?;
+        assert_eq!(ast, AST{root: BinOp::new(Number::new(2), Abs::new(BinOp::new(Var::new("x"), Number::new(3), lexer::Token::Minus)), lexer::Token::Times)});
+        assert_eq!(ast.root.span(), 0..9);
+
+        // A bar after an operand closes
+        let ast = parse(lexer::spanned("||x| - |-1||")?)?;
+        assert_eq!(ast, AST{root: Abs::new(BinOp::new(Abs::new(Var::new("x")), Abs::new(Neg::new(Number::new(1))), lexer::Token::Minus))});
+
+        let err = parse(lexer::spanned("|(1| + 2")?).unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::Unclosed{open: 1..2, closing: ")", found: Some(lexer::Token::Bar), span: 3..4}));
+        let err = parse(lexer::spanned("1 + |2")?).unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::Unclosed{open: 4..5, closing: "|", found: None, span: 6..6}));
+        assert_eq!(err.note(), Some((4..5, "unclosed |")));
+        assert_eq!(parse(lexer::spanned("1 | 2")?).unwrap_err().to_string(), "Invalid expression");
+        Ok(())
+    }
+
+    #[test]
+    fn test_logical() -> Result<(), Error> {
+        use lexer::Token::{And, Or, Plus};
+
+        // `&&` binds tighter than `||`, both looser than `+`
+        let ast = parse(lexer::spanned("a || b && c + 1")?)?;
+        assert_eq!(ast, AST{root: BinOp::new(Var::new("a"), BinOp::new(Var::new("b"), BinOp::new(Var::new("c"), Number::new(1), Plus), And), Or)});
+        let ast = parse(lexer::spanned("!x && true")?)?;
+        assert_eq!(ast, AST{root: BinOp::new(Not::new(Var::new("x")), Bool::new(true), And)});
+        assert_eq!(ast.root.span(), 0..10);
+
+        // `||` inside bars closes them, unless in parentheses
+        let ast = parse(lexer::spanned("|x - |y||")?)?;
+        assert_eq!(ast, AST{root: Abs::new(BinOp::new(Var::new("x"), Abs::new(Var::new("y")), lexer::Token::Minus))});
+        let ast = parse(lexer::spanned("|(a || b)| || c")?)?;
+        assert_eq!(ast, AST{root: BinOp::new(Abs::new(Pair::new(BinOp::new(Var::new("a"), Var::new("b"), Or))), Var::new("c"), Or)});
+        let ast = parse(lexer::spanned("|f(a || b)|")?)?;
+        assert_eq!(ast, AST{root: Abs::new(Call::new("f", vec![BinOp::new(Var::new("a"), Var::new("b"), Or)]))});
+        assert_eq!(ast.root.span(), 0..11);
+
+        assert_eq!(parse(lexer::spanned("|x||")?).unwrap_err(), Error::Parse(ParseError::InvalidExpression{span: 3..4}));
+        assert!(parse(lexer::spanned("a &&")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_if() -> Result<(), Error> {
+        use lexer::Token::{Or, Plus, Times};
+
+        let ast = parse(lexer::spanned("2 * if x then y else z + 1")?)?;
+        let branch = If::new(Var::new("x"), Var::new("y"), BinOp::new(Var::new("z"), Number::new(1), Plus));
+        assert_eq!(ast, AST{root: BinOp::new(Number::new(2), branch, Times)});
+        if let Expr::BinOp(BinOp{rhs, ..}) = &ast.root {
+            assert_eq!(rhs.span(), 4..26);
+        }
+
+        // Nested in the condition, and a `||` in bars before `then`
+        let ast = parse(lexer::spanned("|if if a then b else c || d then 1 else -1|")?)?;
+        let cond = If::new(Var::new("a"), Var::new("b"), BinOp::new(Var::new("c"), Var::new("d"), Or));
+        assert_eq!(ast, AST{root: Abs::new(If::new(cond, Number::new(1), Neg::new(Number::new(1))))});
+
+        let err = parse(lexer::spanned("if x then 1")?).unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::UnexpectedEnd{expected: "else", span: 11..11}));
+        let err = parse(lexer::spanned("if x else 1")?).unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::UnexpectedToken{expected: "then", found: lexer::Token::Else, span: 5..9}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_let() -> Result<(), Error> {
+        use lexer::Token::{Minus, Times};
+
+        let ast = parse(lexer::spanned("y = let h = b*b in h - 1")?)?;
+        let value = BinOp::new(Var::new("b"), Var::new("b"), Times);
+        assert_eq!(ast, AST{root: Assign::new("y", Let::new("h", value, BinOp::new(Var::new("h"), Number::new(1), Minus)))});
+        if let Expr::Assign(Assign{expr, ..}) = &ast.root {
+            assert_eq!(expr.span(), 4..24);
+        }
+
+        let err = parse(lexer::spanned("let 1 = 2 in 3")?).unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::UnexpectedToken{expected: "name", found: lexer::Token::Number(1), span: 4..5}));
+        let err = parse(lexer::spanned("let x = 2")?).unwrap_err();
+        assert_eq!(err, Error::Parse(ParseError::UnexpectedEnd{expected: "in", span: 9..9}));
+        Ok(())
+    }
 }
@@ -0,0 +1,283 @@
+//! Canonical text of expressions, like rustfmt for the calculator.
+//!
+//! Operators are spaced, the arguments are separated by `, ` and
+//! only the parentheses needed by the precedence, or around a
+//! negated literal such as `-(1)`, are kept, so the text parses back
+//! to the same tree. Number literals are kept as
+//! written, in their base and with their `_`.
+//!
+//! # Example
+//! ```
+//! use wcal::pretty::{self, Style};
+//!
+//! let style = Style::default();
+//! assert_eq!(pretty::format("x=((1+2))*max( 3,0xFF )", &style).unwrap(), "x = (1 + 2) * max(3, 0xFF)");
+//! assert_eq!(pretty::format("1-(2-3)", &style).unwrap(), "1 - (2 - 3)");
+//!
+//! let style = Style{lowercase_hex: true};
+//! assert_eq!(pretty::format("0xFF_FF", &style).unwrap(), "0xff_ff");
+//! ```
+use std::cell::RefCell;
+
+use crate::{grammar, lexer, Error};
+use crate::lexer::{Span, Token};
+use crate::parser::ast::{Expr, AST};
+use crate::parser::top_down_parser;
+
+/// Choices of the canonical text.
+#[derive(Debug, Clone, Default)]
+pub struct Style {
+    /// Write hexadecimal literals in lowercase, such as `0xff`
+    pub lowercase_hex: bool,
+}
+
+/// Canonical text of the expression.
+pub fn format(input: &str, style: &Style) -> Result<String, Error> {
+    let ast = top_down_parser::parse(lexer::spanned(input)?)?;
+    Ok(format_ast(&ast, input, style))
+}
+
+/// Canonical text of the AST parsed from `input`, which has the
+/// literals as written.
+pub fn format_ast(ast: &AST, input: &str, style: &Style) -> String {
+    let mut out = String::new();
+    Printer{input, style, redundant: RefCell::default()}.expr(&ast.root, &mut out);
+    out
+}
+
+/// Spans of the parentheses the canonical text drops, the outer
+/// ones first.
+pub(crate) fn redundant_parens(ast: &AST) -> Vec<Span> {
+    let printer = Printer{input: "", style: &Style::default(), redundant: RefCell::default()};
+    printer.expr(&ast.root, &mut String::new());
+    printer.redundant.into_inner()
+}
+
+/// Precedence of the atoms, such as numbers and calls.
+const ATOM: u8 = u8::MAX;
+
+/// Precedence of the node, parentheses are looked through.
+fn precedence(expr: &Expr) -> u8 {
+    let arity = match expr {
+        Expr::Pair(pair) => return precedence(&pair.expr),
+        Expr::BinOp(_) => 2,
+        Expr::Neg(_) | Expr::Not(_) => 1,
+        // Only at the root, or where a whole expression is
+        Expr::Assign(_) | Expr::Range(_) => return 0,
+        // Reach as far right as they can
+        Expr::If(_) | Expr::Let(_) => return 0,
+        Expr::Abs(_) | Expr::List(_) | Expr::Index(_) | Expr::Call(_) | Expr::Var(_) | Expr::Num(_) | Expr::Duration(_) | Expr::Date(_) | Expr::Bool(_) | Expr::Str(_) => return ATOM,
+    };
+    let symbol = match expr {
+        Expr::BinOp(expr) => expr.op.to_string(),
+        Expr::Not(_) => Token::Not.to_string(),
+        _ => Token::Minus.to_string(),
+    };
+    grammar::operators().iter()
+        .find(|op| op.arity == arity && op.symbol == symbol)
+        .map_or(ATOM, |op| op.precedence)
+}
+
+/// Whether the node is a `||`, which would close the bars around
+/// it without parentheses.
+fn is_or(expr: &Expr) -> bool {
+    match expr {
+        Expr::Pair(pair) => is_or(&pair.expr),
+        Expr::BinOp(node) => node.op == Token::Or,
+        _ => false,
+    }
+}
+
+/// Whether the node is a literal in parentheses, such as `(1)`.
+/// The calculators negate `-1` as one literal but `-(1)` after it,
+/// so `-(170141183460469231731687303715884105728)` overflows.
+fn is_paren_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Pair(pair) => matches!(*pair.expr, Expr::Num(_)) || is_paren_literal(&pair.expr),
+        _ => false,
+    }
+}
+
+struct Printer<'a> {
+    input: &'a str,
+    style: &'a Style,
+    /// Spans of the parentheses dropped
+    redundant: RefCell<Vec<Span>>,
+}
+
+impl Printer<'_> {
+    fn expr(&self, expr: &Expr, out: &mut String) {
+        match expr {
+            Expr::Pair(pair) => {
+                self.redundant.borrow_mut().push(pair.span.clone());
+                self.expr(&pair.expr, out)
+            }
+            Expr::BinOp(node) => {
+                let prec = precedence(expr);
+                // Operators are left associative, so a right operand
+                // of the same precedence keeps its parentheses. A left
+                // comparison too, as `(a < b) < c` isn't a chain
+                let lhs = precedence(&node.lhs);
+                self.operand(&node.lhs, lhs < prec || (lhs == prec && node.op.is_comparison()), out);
+                out.push_str(&format!(" {} ", node.op));
+                self.operand(&node.rhs, precedence(&node.rhs) <= prec, out);
+            }
+            Expr::Neg(neg) => {
+                out.push('-');
+                self.operand(&neg.expr, is_paren_literal(&neg.expr) || precedence(&neg.expr) < precedence(expr), out);
+            }
+            Expr::Not(not) => {
+                out.push('!');
+                self.operand(&not.expr, precedence(&not.expr) < precedence(expr), out);
+            }
+            Expr::Abs(abs) => {
+                out.push('|');
+                self.operand(&abs.expr, is_or(&abs.expr), out);
+                out.push('|');
+            }
+            Expr::If(node) => {
+                out.push_str("if ");
+                self.expr(&node.cond, out);
+                out.push_str(" then ");
+                self.expr(&node.then, out);
+                out.push_str(" else ");
+                // A `||` would close the bars around the `if`
+                self.operand(&node.otherwise, is_or(&node.otherwise), out);
+            }
+            Expr::Let(node) => {
+                out.push_str(&format!("let {} = ", node.name));
+                self.expr(&node.value, out);
+                out.push_str(" in ");
+                self.operand(&node.body, is_or(&node.body), out);
+            }
+            Expr::List(list) => {
+                out.push('[');
+                self.items(&list.items, out);
+                out.push(']');
+            }
+            Expr::Range(range) => {
+                self.operand(&range.start, precedence(&range.start) == 0, out);
+                out.push_str("..");
+                self.operand(&range.end, precedence(&range.end) == 0, out);
+            }
+            Expr::Index(index) => {
+                self.operand(&index.list, precedence(&index.list) < ATOM, out);
+                out.push('[');
+                self.expr(&index.index, out);
+                out.push(']');
+            }
+            Expr::Call(call) => {
+                out.push_str(&call.name);
+                out.push('(');
+                self.items(&call.args, out);
+                out.push(')');
+            }
+            Expr::Var(var) => out.push_str(&var.name),
+            Expr::Assign(assign) => {
+                out.push_str(&assign.name);
+                out.push_str(" = ");
+                self.expr(&assign.expr, out);
+            }
+            Expr::Bool(bool) => out.push_str(&bool.value.to_string()),
+            Expr::Str(_) => out.push_str(&expr.to_string()),
+            Expr::Duration(duration) => out.push_str(&duration.to_string()),
+            Expr::Date(date) => out.push_str(&date.to_string()),
+            Expr::Num(num) => {
+                let literal = self.input.get(num.span.clone()).unwrap_or_default();
+                if literal.is_empty() {
+                    out.push_str(&num.num.to_string());
+                } else if self.style.lowercase_hex && literal.starts_with("0x") {
+                    out.push_str(&literal.to_lowercase());
+                } else {
+                    out.push_str(literal);
+                }
+            }
+        }
+    }
+
+    /// Items of a list or arguments of a call, separated by commas.
+    fn items(&self, items: &[Expr], out: &mut String) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            self.expr(item, out);
+        }
+    }
+
+    fn operand(&self, expr: &Expr, parens: bool, out: &mut String) {
+        if parens {
+            out.push('(');
+            // Parentheses written here are needed
+            match expr {
+                Expr::Pair(pair) => self.expr(&pair.expr, out),
+                expr => self.expr(expr, out),
+            }
+            out.push(')');
+        } else {
+            self.expr(expr, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(input: &str) -> String {
+        format(input, &Style::default()).unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(fmt("1+2*3"), "1 + 2 * 3");
+        assert_eq!(fmt("(1+2)*3"), "(1 + 2) * 3");
+        assert_eq!(fmt("((1))+(2*3)"), "1 + 2 * 3");
+        assert_eq!(fmt("(1-2)-3"), "1 - 2 - 3");
+        assert_eq!(fmt("1-(2+3)"), "1 - (2 + 3)");
+        assert_eq!(fmt("8/(4/2)"), "8 / (4 / 2)");
+        assert_eq!(fmt("-(1+2)"), "-(1 + 2)");
+        assert_eq!(fmt("-(-(1))*2"), "--(1) * 2");
+        assert_eq!(fmt("-((1))+-(x)"), "-(1) + -x");
+        assert_eq!(fmt("1-(-2)"), "1 - -2");
+        assert_eq!(fmt("  f( )+g(1,(2) ,x)"), "f() + g(1, 2, x)");
+        assert_eq!(fmt("y=1_000*0b1"), "y = 1_000 * 0b1");
+        assert_eq!(fmt("|(x)-|-1| |*2"), "|x - |-1|| * 2");
+        assert_eq!(fmt("(a&&b)||!(c||false)"), "a && b || !(c || false)");
+        assert_eq!(fmt("(a<b)<(c==(1+2))"), "(a < b) < (c == 1 + 2)");
+        assert_eq!(fmt("0<=x<10"), "0 <= x && x < 10");
+        assert_eq!(fmt("|(a||b)| || |c|"), "|(a || b)| || |c|");
+        assert_eq!(fmt("(if(x)then 1 else 2)+if y then 3 else 4*5"), "(if x then 1 else 2) + (if y then 3 else 4 * 5)");
+        assert_eq!(fmt("let h=(b*b) in (h/2)"), "let h = b * b in h / 2");
+        assert_eq!(fmt("sum([1,(2)] )+(a)[ 0 ]*(b+1)[1..2][0]"), "sum([1, 2]) + a[0] * (b + 1)[1..2][0]");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let inputs = ["1-(2-3)*-(4/(5*6))", "x = -(-(a)) - -b", "!x && (y || z) + 1", "-if a then b else c - 1", "2 * (let x = 1 in x) - 1", "|if a then b else (c || d)|", "|(a || b)| && ||c| - 1|", "max(1+2, (3)) / (7 - 0x1F)", "---7--2", "(1..(if a then 2 else 3))..4", "-[1, -2][(0)]", "a < (b < c) != (d == e)"];
+        for input in inputs.iter() {
+            let text = fmt(input);
+            assert_eq!(fmt(&text), text, "input: {}", input);
+            let parse = |input: &str| top_down_parser::parse(lexer::spanned(input).unwrap()).unwrap().to_string();
+            assert_eq!(parse(&text), parse(input), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_negative_literal() {
+        // -2^127 is i128::MIN, but 2^127 overflows before -(...) negates it
+        let inputs = ["-(170141183460469231731687303715884105728)", "-((170141183460469231731687303715884105728))", "-170141183460469231731687303715884105728"];
+        for input in inputs.iter() {
+            let text = fmt(input);
+            assert_eq!(crate::eval_no_panic::<i128>(&text).ok(), crate::eval_no_panic::<i128>(input).ok(), "input: {}", input);
+            assert_eq!(crate::eval_no_panic::<crate::Rational>(&text).ok(), crate::eval_no_panic::<crate::Rational>(input).ok(), "input: {}", input);
+        }
+        assert_eq!(crate::eval_no_panic::<i128>(&fmt(inputs[0])).ok(), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(format("1+", &Style::default()).is_err());
+        assert!(format("1 $ 2", &Style::default()).is_err());
+    }
+}
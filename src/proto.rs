@@ -0,0 +1,491 @@
+//! Protocol Buffers encoding of the syntax trees and results, only
+//! with the `protobuf` feature.
+//!
+//! The schema is [`SCHEMA`], shipped as `proto/wcal.proto`, so the
+//! services written in other languages can generate their own code
+//! to receive the expressions parsed by wcal. The messages of the
+//! schema are in [`message`].
+//!
+//! # Example
+//! ```
+//! use wcal::{proto, Calculator};
+//!
+//! let ast = Calculator::<i128>::new().parse("x * (1 + 2)").unwrap();
+//! let bytes = proto::encode(&ast);
+//! assert_eq!(proto::decode(&bytes).unwrap(), ast);
+//! ```
+use std::convert::TryFrom;
+use std::fmt;
+
+use prost::Message;
+
+use crate::error::Warning;
+use crate::lexer::{Span, Token};
+use crate::parser::ast::{self, Expr, AST};
+use crate::Error;
+
+/// `proto/wcal.proto`
+pub const SCHEMA: &str = include_str!("../proto/wcal.proto");
+
+/// Messages of [`SCHEMA`], written like the code of `prost-build`.
+pub mod message {
+    #![allow(missing_docs)]
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Span {
+        #[prost(uint64, tag = "1")]
+        pub start: u64,
+        #[prost(uint64, tag = "2")]
+        pub end: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Ast {
+        #[prost(message, optional, tag = "1")]
+        pub root: Option<Expr>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Expr {
+        #[prost(message, optional, tag = "1")]
+        pub span: Option<Span>,
+        #[prost(oneof = "expr::Node", tags = "2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19")]
+        pub node: Option<expr::Node>,
+    }
+
+    pub mod expr {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Node {
+            #[prost(message, tag = "2")]
+            Pair(super::Pair),
+            #[prost(message, tag = "3")]
+            Binop(super::BinOp),
+            #[prost(message, tag = "4")]
+            Neg(super::Neg),
+            #[prost(message, tag = "5")]
+            Call(super::Call),
+            #[prost(message, tag = "6")]
+            Var(super::Var),
+            #[prost(message, tag = "7")]
+            Assign(super::Assign),
+            #[prost(message, tag = "8")]
+            Number(super::Number),
+            #[prost(message, tag = "9")]
+            Abs(super::Abs),
+            #[prost(message, tag = "10")]
+            Not(super::Not),
+            #[prost(bool, tag = "11")]
+            Bool(bool),
+            #[prost(message, tag = "12")]
+            If(super::If),
+            #[prost(message, tag = "13")]
+            Let(super::Let),
+            #[prost(message, tag = "14")]
+            List(super::List),
+            #[prost(message, tag = "15")]
+            Range(super::Range),
+            #[prost(message, tag = "16")]
+            Index(super::Index),
+            #[prost(message, tag = "17")]
+            Duration(super::Number),
+            #[prost(int64, tag = "18")]
+            Date(i64),
+            #[prost(string, tag = "19")]
+            Str(String),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Pair {
+        #[prost(message, optional, boxed, tag = "1")]
+        pub expr: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Op {
+        Unspecified = 0,
+        Add = 1,
+        Sub = 2,
+        Mul = 3,
+        Div = 4,
+        And = 5,
+        Or = 6,
+        Less = 7,
+        LessEq = 8,
+        Greater = 9,
+        GreaterEq = 10,
+        Equal = 11,
+        NotEqual = 12,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BinOp {
+        #[prost(enumeration = "Op", tag = "1")]
+        pub op: i32,
+        #[prost(message, optional, boxed, tag = "2")]
+        pub lhs: Option<Box<Expr>>,
+        #[prost(message, optional, boxed, tag = "3")]
+        pub rhs: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Neg {
+        #[prost(message, optional, boxed, tag = "1")]
+        pub expr: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Abs {
+        #[prost(message, optional, boxed, tag = "1")]
+        pub expr: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct If {
+        #[prost(message, optional, boxed, tag = "1")]
+        pub cond: Option<Box<Expr>>,
+        #[prost(message, optional, boxed, tag = "2")]
+        pub then: Option<Box<Expr>>,
+        #[prost(message, optional, boxed, tag = "3")]
+        pub otherwise: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Let {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(message, optional, boxed, tag = "2")]
+        pub value: Option<Box<Expr>>,
+        #[prost(message, optional, boxed, tag = "3")]
+        pub body: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct List {
+        #[prost(message, repeated, tag = "1")]
+        pub items: Vec<Expr>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Range {
+        #[prost(message, optional, boxed, tag = "1")]
+        pub start: Option<Box<Expr>>,
+        #[prost(message, optional, boxed, tag = "2")]
+        pub end: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Index {
+        #[prost(message, optional, boxed, tag = "1")]
+        pub list: Option<Box<Expr>>,
+        #[prost(message, optional, boxed, tag = "2")]
+        pub index: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Not {
+        #[prost(message, optional, boxed, tag = "1")]
+        pub expr: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Call {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(message, repeated, tag = "2")]
+        pub args: Vec<Expr>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Var {
+        #[prost(string, tag = "1")]
+        pub name: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Assign {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(message, optional, boxed, tag = "2")]
+        pub expr: Option<Box<Expr>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Number {
+        #[prost(uint64, tag = "1")]
+        pub low: u64,
+        #[prost(uint64, tag = "2")]
+        pub high: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Evaluation {
+        #[prost(string, tag = "1")]
+        pub expr: String,
+        #[prost(oneof = "evaluation::Outcome", tags = "2, 3")]
+        pub outcome: Option<evaluation::Outcome>,
+        #[prost(message, repeated, tag = "4")]
+        pub warnings: Vec<Warning>,
+    }
+
+    pub mod evaluation {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Outcome {
+            #[prost(string, tag = "2")]
+            Value(String),
+            #[prost(message, tag = "3")]
+            Error(super::Error),
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum ErrorKind {
+        Unspecified = 0,
+        Lex = 1,
+        Parse = 2,
+        Eval = 3,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Error {
+        #[prost(enumeration = "ErrorKind", tag = "1")]
+        pub kind: i32,
+        #[prost(string, tag = "2")]
+        pub message: String,
+        #[prost(message, optional, tag = "3")]
+        pub span: Option<Span>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Warning {
+        #[prost(string, tag = "1")]
+        pub message: String,
+        #[prost(message, optional, tag = "2")]
+        pub span: Option<Span>,
+    }
+}
+
+/// Error of [`decode`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// The bytes are not a message of the schema.
+    Protobuf(prost::DecodeError),
+    /// A node lacks a required field, such as an operand.
+    Missing(&'static str),
+    /// Operator unknown to wcal.
+    Op(i32),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Protobuf(err) => err.fmt(f),
+            DecodeError::Missing(field) => write!(f, "Missing field {}", field),
+            DecodeError::Op(op) => write!(f, "Unknown operator {}", op),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<prost::DecodeError> for DecodeError {
+    fn from(err: prost::DecodeError) -> Self {
+        DecodeError::Protobuf(err)
+    }
+}
+
+fn span(span: &Span) -> Option<message::Span> {
+    Some(message::Span{start: span.start as u64, end: span.end as u64})
+}
+
+fn expr(expr: &Expr) -> message::Expr {
+    use message::expr::Node;
+
+    let boxed = |child: &Expr| Some(Box::new(self::expr(child)));
+    let node = match expr {
+        Expr::Pair(pair) => Node::Pair(message::Pair{expr: boxed(&pair.expr)}),
+        Expr::BinOp(bin) => {
+            let op = match bin.op {
+                Token::Plus => message::Op::Add,
+                Token::Minus => message::Op::Sub,
+                Token::Times => message::Op::Mul,
+                Token::Division => message::Op::Div,
+                Token::And => message::Op::And,
+                Token::Or => message::Op::Or,
+                Token::Less => message::Op::Less,
+                Token::LessEq => message::Op::LessEq,
+                Token::Greater => message::Op::Greater,
+                Token::GreaterEq => message::Op::GreaterEq,
+                Token::Equal => message::Op::Equal,
+                Token::NotEqual => message::Op::NotEqual,
+                _ => message::Op::Unspecified,
+            };
+            Node::Binop(message::BinOp{op: op as i32, lhs: boxed(&bin.lhs), rhs: boxed(&bin.rhs)})
+        }
+        Expr::Neg(neg) => Node::Neg(message::Neg{expr: boxed(&neg.expr)}),
+        Expr::Abs(abs) => Node::Abs(message::Abs{expr: boxed(&abs.expr)}),
+        Expr::Not(not) => Node::Not(message::Not{expr: boxed(&not.expr)}),
+        Expr::Let(node) => Node::Let(message::Let{name: node.name.clone(), value: boxed(&node.value), body: boxed(&node.body)}),
+        Expr::If(node) => Node::If(message::If{cond: boxed(&node.cond), then: boxed(&node.then), otherwise: boxed(&node.otherwise)}),
+        Expr::List(list) => Node::List(message::List{items: list.items.iter().map(self::expr).collect()}),
+        Expr::Range(range) => Node::Range(message::Range{start: boxed(&range.start), end: boxed(&range.end)}),
+        Expr::Index(index) => Node::Index(message::Index{list: boxed(&index.list), index: boxed(&index.index)}),
+        Expr::Call(call) => Node::Call(message::Call{
+            name: call.name.clone(),
+            args: call.args.iter().map(self::expr).collect(),
+        }),
+        Expr::Var(var) => Node::Var(message::Var{name: var.name.clone()}),
+        Expr::Assign(assign) => Node::Assign(message::Assign{name: assign.name.clone(), expr: boxed(&assign.expr)}),
+        Expr::Num(num) => Node::Number(message::Number{low: num.num as u64, high: (num.num >> 64) as u64}),
+        Expr::Duration(duration) => Node::Duration(message::Number{low: duration.secs as u64, high: (duration.secs >> 64) as u64}),
+        Expr::Date(date) => Node::Date(date.days),
+        Expr::Bool(bool) => Node::Bool(bool.value),
+        Expr::Str(text) => Node::Str(text.value.clone()),
+    };
+    message::Expr{span: span(&expr.span()), node: Some(node)}
+}
+
+/// Encode the tree as an `Ast` message.
+pub fn encode(ast: &AST) -> Vec<u8> {
+    message::Ast{root: Some(expr(&ast.root))}.encode_to_vec()
+}
+
+fn child(expr: Option<Box<message::Expr>>, field: &'static str) -> Result<Expr, DecodeError> {
+    node(*expr.ok_or(DecodeError::Missing(field))?)
+}
+
+fn node(expr: message::Expr) -> Result<Expr, DecodeError> {
+    use message::expr::Node;
+
+    let node = match expr.node.ok_or(DecodeError::Missing("node"))? {
+        Node::Pair(pair) => ast::Pair::new(child(pair.expr, "expr")?),
+        Node::Binop(bin) => {
+            let op = match message::Op::try_from(bin.op) {
+                Ok(message::Op::Add) => Token::Plus,
+                Ok(message::Op::Sub) => Token::Minus,
+                Ok(message::Op::Mul) => Token::Times,
+                Ok(message::Op::Div) => Token::Division,
+                Ok(message::Op::And) => Token::And,
+                Ok(message::Op::Or) => Token::Or,
+                Ok(message::Op::Less) => Token::Less,
+                Ok(message::Op::LessEq) => Token::LessEq,
+                Ok(message::Op::Greater) => Token::Greater,
+                Ok(message::Op::GreaterEq) => Token::GreaterEq,
+                Ok(message::Op::Equal) => Token::Equal,
+                Ok(message::Op::NotEqual) => Token::NotEqual,
+                _ => return Err(DecodeError::Op(bin.op)),
+            };
+            ast::BinOp::new(child(bin.lhs, "lhs")?, child(bin.rhs, "rhs")?, op)
+        }
+        Node::Neg(neg) => ast::Neg::new(child(neg.expr, "expr")?),
+        Node::Abs(abs) => ast::Abs::new(child(abs.expr, "expr")?),
+        Node::Not(not) => ast::Not::new(child(not.expr, "expr")?),
+        Node::Let(node) => ast::Let::new(&node.name, child(node.value, "value")?, child(node.body, "body")?),
+        Node::If(node) => ast::If::new(child(node.cond, "cond")?, child(node.then, "then")?, child(node.otherwise, "otherwise")?),
+        Node::List(list) => ast::List::new(list.items.into_iter().map(node).collect::<Result<Vec<_>, _>>()?),
+        Node::Range(range) => ast::Range::new(child(range.start, "start")?, child(range.end, "end")?),
+        Node::Index(index) => ast::Index::new(child(index.list, "list")?, child(index.index, "index")?),
+        Node::Call(call) => {
+            let args = call.args.into_iter().map(node).collect::<Result<Vec<_>, _>>()?;
+            ast::Call::new(&call.name, args)
+        }
+        Node::Var(var) => ast::Var::new(&var.name),
+        Node::Assign(assign) => ast::Assign::new(&assign.name, child(assign.expr, "expr")?),
+        Node::Number(num) => ast::Number::new((num.high as u128) << 64 | num.low as u128),
+        Node::Duration(secs) => ast::Duration::new((secs.high as u128) << 64 | secs.low as u128),
+        Node::Date(days) => ast::Date::new(days),
+        Node::Bool(value) => ast::Bool::new(value),
+        Node::Str(value) => ast::Str::new(&value),
+    };
+    Ok(match expr.span {
+        Some(span) => node.with_span(span.start as usize..span.end as usize),
+        None => node,
+    })
+}
+
+/// Decode an `Ast` message, such as from [`encode`].
+pub fn decode(bytes: &[u8]) -> Result<AST, DecodeError> {
+    let ast = message::Ast::decode(bytes)?;
+    Ok(AST{root: node(ast.root.ok_or(DecodeError::Missing("root"))?)?})
+}
+
+/// Encode the result of the expression as an `Evaluation` message,
+/// the value as its text.
+pub fn encode_result<T: fmt::Display>(expr: &str, result: &Result<T, Error>, warnings: &[Warning]) -> Vec<u8> {
+    use message::evaluation::Outcome;
+
+    let outcome = match result {
+        Ok(value) => Outcome::Value(value.to_string()),
+        Err(err) => {
+            let kind = match err {
+                Error::Lex(_) => message::ErrorKind::Lex,
+                Error::Parse(_) => message::ErrorKind::Parse,
+                Error::Eval(_) => message::ErrorKind::Eval,
+            };
+            Outcome::Error(message::Error{kind: kind as i32, message: err.message(), span: span(&err.span())})
+        }
+    };
+    message::Evaluation{
+        expr: expr.to_string(),
+        outcome: Some(outcome),
+        warnings: warnings.iter()
+            .map(|warning| message::Warning{message: warning.to_string(), span: span(&warning.span())})
+            .collect(),
+    }.encode_to_vec()
+}
+
+/// Decode an `Evaluation` message, such as from [`encode_result`].
+pub fn decode_result(bytes: &[u8]) -> Result<message::Evaluation, DecodeError> {
+    Ok(message::Evaluation::decode(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Calculator;
+    use crate::parser::export;
+
+    #[test]
+    fn test_round_trip() {
+        let calc = Calculator::<i128>::new();
+        for input in ["x = -(1 + 2) * |y|", "max(1, f(), 0x1_0000_0000_0000_0000)", "7/2", "!a || b && true", "if x then 1 else -2", "let h = 2 in h * h", "sum([1, 2..n][0])", "1h30m * 2", "0 <= x < 10 != (y == 2)"] {
+            let ast = calc.parse(input).unwrap();
+            let decoded = decode(&encode(&ast)).unwrap();
+            assert_eq!(decoded, ast);
+            assert_eq!(export::tree(&decoded), export::tree(&ast));
+        }
+    }
+
+    #[test]
+    fn test_decode_error() {
+        assert!(matches!(decode(&[0xff]), Err(DecodeError::Protobuf(_))));
+        let empty = message::Ast{root: Some(message::Expr{span: None, node: None})}.encode_to_vec();
+        assert_eq!(decode(&empty), Err(DecodeError::Missing("node")));
+        let pow = message::Expr{
+            span: None,
+            node: Some(message::expr::Node::Binop(message::BinOp{op: 13, lhs: None, rhs: None})),
+        };
+        let pow = message::Ast{root: Some(pow)}.encode_to_vec();
+        assert_eq!(decode(&pow).unwrap_err().to_string(), "Unknown operator 13");
+    }
+
+    #[test]
+    fn test_result() {
+        let calc = Calculator::<i128>::new();
+        let evaluation = decode_result(&encode_result("1/0", &calc.eval("1/0"), &[])).unwrap();
+        assert_eq!(evaluation.expr, "1/0");
+        match evaluation.outcome {
+            Some(message::evaluation::Outcome::Error(err)) => {
+                assert_eq!((err.kind(), err.message.as_str()), (message::ErrorKind::Eval, "Division by zero"));
+                assert_eq!(err.span, Some(message::Span{start: 0, end: 3}));
+            }
+            outcome => panic!("{:?}", outcome),
+        }
+        let warning = Warning::DivisionCast{lhs: 7, rhs: 2, span: 0..3};
+        let evaluation = decode_result(&encode_result("7/2", &Ok(3), &[warning])).unwrap();
+        assert_eq!(evaluation.outcome, Some(message::evaluation::Outcome::Value("3".to_string())));
+        assert_eq!(evaluation.warnings[0].message, "Division will cause a cast: 7/2");
+    }
+}
@@ -0,0 +1,280 @@
+//! Exact fractions of `i128`, the result of the rational
+//! calculator.
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Fraction in lowest terms with a positive denominator, so
+/// `7/2` is `7/2` instead of `3` and `1/3*3` is exactly `1`.
+///
+/// The operations return `None` on overflow or division by zero.
+///
+/// With the `serde` feature, it is serialized as
+/// `{"num": 7, "den": 2}`.
+///
+/// # Example
+/// ```
+/// use wcal::{eval_no_panic, Rational};
+///
+/// let res: Rational = eval_no_panic("1/2 + 1/3").unwrap();
+/// assert_eq!(res, Rational::new(5, 6).unwrap());
+/// assert_eq!(res.to_string(), "5/6");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "Parts", try_from = "Parts"))]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    /// `num/den` in lowest terms, `None` if `den` is zero or the
+    /// result overflows.
+    pub fn new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 {
+            return None
+        }
+        let divisor = gcd(num.unsigned_abs(), den.unsigned_abs());
+        let negative = (num < 0) != (den < 0);
+        let num = signed(num.unsigned_abs() / divisor, negative)?;
+        let den = i128::try_from(den.unsigned_abs() / divisor).ok()?;
+        Some(Rational{num, den})
+    }
+
+    pub fn numer(&self) -> i128 {
+        self.num
+    }
+
+    /// The denominator, always positive.
+    pub fn denom(&self) -> i128 {
+        self.den
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den == 1
+    }
+
+    /// Nearest `f64`, for an approximation.
+    pub fn to_f64(&self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        let divisor = gcd(self.den.unsigned_abs(), rhs.den.unsigned_abs()) as i128;
+        let lhs_num = self.num.checked_mul(rhs.den / divisor)?;
+        let rhs_num = rhs.num.checked_mul(self.den / divisor)?;
+        Rational::new(lhs_num.checked_add(rhs_num)?, (self.den / divisor).checked_mul(rhs.den)?)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.checked_add(rhs.checked_neg()?)
+    }
+
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        // Reduce crosswise first so the products stay small
+        let lhs = Rational::new(self.num, rhs.den)?;
+        let rhs = Rational::new(rhs.num, self.den)?;
+        Rational::new(lhs.num.checked_mul(rhs.num)?, lhs.den.checked_mul(rhs.den)?)
+    }
+
+    /// `None` if `rhs` is zero or the result overflows.
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        self.checked_mul(Rational::new(rhs.den, rhs.num)?)
+    }
+
+    pub fn checked_neg(self) -> Option<Self> {
+        Some(Rational{num: self.num.checked_neg()?, den: self.den})
+    }
+
+    pub fn checked_abs(self) -> Option<Self> {
+        Some(Rational{num: self.num.checked_abs()?, den: self.den})
+    }
+
+    /// Nearest fraction of the `f64` with a denominator up to 10^12,
+    /// from its continued fraction. The values past `i128` saturate.
+    pub(crate) fn approx(value: f64) -> Self {
+        const MAX_DEN: i128 = 1_000_000_000_000;
+        if value.is_nan() || value.abs() >= 1e24 {
+            return Rational::from(value as i128)
+        }
+        // Convergents h/k, the previous one first
+        let (mut h, mut k) = ((1i128, value.floor() as i128), (0i128, 1i128));
+        let mut rest = value - value.floor();
+        while rest > 0.0 && (h.1 as f64 / k.1 as f64) != value {
+            rest = rest.recip();
+            let a = rest.floor();
+            rest -= a;
+            let a = a as i128;
+            let next = (a * h.1 + h.0, a * k.1 + k.0);
+            if next.1 > MAX_DEN {
+                break
+            }
+            h = (h.1, next.0);
+            k = (k.1, next.1);
+        }
+        Rational::new(h.1, k.1).expect("denominators are positive")
+    }
+}
+
+impl From<i128> for Rational {
+    fn from(num: i128) -> Self {
+        Rational{num, den: 1}
+    }
+}
+
+impl Ord for Rational {
+    /// Compared by their continued fractions, so without overflow.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (lhs, rhs) = (self.num.div_euclid(self.den), other.num.div_euclid(other.den));
+        if lhs != rhs {
+            return lhs.cmp(&rhs)
+        }
+        // The fractions left are in [0, 1), the larger one has the
+        // smaller reciprocal
+        match (self.num.rem_euclid(self.den), other.num.rem_euclid(other.den)) {
+            (0, 0) => Ordering::Equal,
+            (0, _) => Ordering::Less,
+            (_, 0) => Ordering::Greater,
+            (lhs, rhs) => Rational{num: other.den, den: rhs}.cmp(&Rational{num: self.den, den: lhs}),
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Rational {
+    /// `num/den`, or only `num` for an integer.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    // gcd(0, 0), only for 0/den after the check of den
+    a.max(1)
+}
+
+/// `abs` with the sign, `None` if it doesn't fit.
+fn signed(abs: u128, negative: bool) -> Option<i128> {
+    if negative {
+        if abs == i128::MIN.unsigned_abs() {
+            Some(i128::MIN)
+        } else {
+            i128::try_from(abs).ok().map(|abs| -abs)
+        }
+    } else {
+        i128::try_from(abs).ok()
+    }
+}
+
+/// Fields as serialized, checked on deserialization.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct Parts {
+    num: i128,
+    den: i128,
+}
+
+#[cfg(feature = "serde")]
+impl From<Rational> for Parts {
+    fn from(rational: Rational) -> Self {
+        Parts{num: rational.num, den: rational.den}
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<Parts> for Rational {
+    type Error = &'static str;
+
+    fn try_from(parts: Parts) -> Result<Self, Self::Error> {
+        Rational::new(parts.num, parts.den).ok_or("denominator must not be zero")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(num: i128, den: i128) -> Rational {
+        Rational::new(num, den).unwrap()
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(r(4, -6), Rational{num: -2, den: 3});
+        assert_eq!(r(0, -5), Rational{num: 0, den: 1});
+        assert_eq!(r(i128::MIN, 2), Rational{num: i128::MIN / 2, den: 1});
+        assert_eq!(r(i128::MIN, i128::MIN), Rational{num: 1, den: 1});
+        assert_eq!(Rational::new(1, 0), None);
+        assert_eq!(Rational::new(i128::MIN, -1), None);
+        assert_eq!(Rational::new(1, i128::MIN), None);
+    }
+
+    #[test]
+    fn test_cmp() {
+        assert!(r(1, 3) < r(1, 2));
+        assert!(r(-1, 2) < r(-1, 3));
+        assert!(r(7, 2) > r(3, 1));
+        assert_eq!(r(2, 4).cmp(&r(1, 2)), Ordering::Equal);
+        assert!(r(i128::MAX, i128::MAX - 1) < r(i128::MAX - 1, i128::MAX - 2));
+        assert!(r(i128::MIN, 3) < r(i128::MIN + 1, 3));
+    }
+
+    #[test]
+    fn test_approx() {
+        assert_eq!(Rational::approx(0.75), r(3, 4));
+        assert_eq!(Rational::approx(-2.5), r(-5, 2));
+        assert_eq!(Rational::approx(1.0 / 3.0), r(1, 3));
+        assert_eq!(Rational::approx(std::f64::consts::PI).to_f64(), std::f64::consts::PI);
+        assert_eq!(Rational::approx(1e30), r(1000000000000000019884624838656, 1));
+    }
+
+    #[test]
+    fn test_ops() {
+        assert_eq!(r(1, 2).checked_add(r(1, 3)), Some(r(5, 6)));
+        assert_eq!(r(1, 6).checked_add(r(1, 3)), Some(r(1, 2)));
+        assert_eq!(r(1, 2).checked_sub(r(1, 2)), Some(r(0, 1)));
+        assert_eq!(r(2, 3).checked_mul(r(9, 4)), Some(r(3, 2)));
+        assert_eq!(r(1, 3).checked_mul(r(3, 1)), Some(r(1, 1)));
+        assert_eq!(r(7, 1).checked_div(r(-2, 1)), Some(r(-7, 2)));
+        assert_eq!(r(7, 1).checked_div(r(0, 1)), None);
+        assert_eq!(r(i128::MAX, 1).checked_add(r(1, 1)), None);
+        assert_eq!(r(i128::MAX, 2).checked_mul(r(2, 1)), Some(r(i128::MAX, 1)));
+        assert_eq!(r(i128::MIN, 1).checked_neg(), None);
+        assert_eq!(r(i128::MIN, 1).checked_abs(), None);
+        assert_eq!(r(-1, 2).checked_abs(), Some(r(1, 2)));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(r(11, 6).to_string(), "11/6");
+        assert_eq!(r(-1, 2).to_string(), "-1/2");
+        assert_eq!(r(4, 2).to_string(), "2");
+        assert_eq!(r(1, 4).to_f64(), 0.25);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        assert_eq!(serde_json::to_string(&r(-1, 2)).unwrap(), r#"{"num":-1,"den":2}"#);
+        assert_eq!(serde_json::from_str::<Rational>(r#"{"num":2,"den":-4}"#).unwrap(), r(-1, 2));
+        assert!(serde_json::from_str::<Rational>(r#"{"num":1,"den":0}"#).is_err());
+    }
+}
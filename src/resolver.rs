@@ -0,0 +1,82 @@
+//! Values of the variables from the host application.
+use std::collections::{BTreeMap, HashMap};
+
+/// Source of the variables missing in the environment, such as a
+/// config map, a database or the cells of a spreadsheet, read only
+/// when an expression uses them.
+///
+/// Implemented by the closures `Fn(&str) -> Option<T>` and by the
+/// maps from `String`.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+/// use wcal::Calculator;
+///
+/// let calc = Calculator::<i128>::new();
+/// let config = HashMap::from([("width".to_string(), 80)]);
+/// assert_eq!(calc.eval_with("width / 2", &config), Ok(40));
+///
+/// let cells = |name: &str| name.strip_prefix('A')?.parse::<i128>().ok();
+/// assert_eq!(calc.eval_with("A1 + A2", &cells), Ok(3));
+/// ```
+pub trait Resolver<T> {
+    /// Value of the variable, `None` if it is unknown.
+    fn resolve(&self, name: &str) -> Option<T>;
+}
+
+impl<T, F> Resolver<T> for F
+where F: Fn(&str) -> Option<T> {
+    fn resolve(&self, name: &str) -> Option<T> {
+        self(name)
+    }
+}
+
+impl<T: Clone> Resolver<T> for HashMap<String, T> {
+    fn resolve(&self, name: &str) -> Option<T> {
+        self.get(name).cloned()
+    }
+}
+
+impl<T: Clone> Resolver<T> for BTreeMap<String, T> {
+    fn resolve(&self, name: &str) -> Option<T> {
+        self.get(name).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::{Calculator, Environment, FromAST};
+    use crate::error::EvalError;
+
+    #[test]
+    fn test_resolver() {
+        let calc = Calculator::<f64>::new();
+        let reads = Cell::new(0);
+        let resolver = |name: &str| {
+            reads.set(reads.get() + 1);
+            (name == "rate").then_some(0.5)
+        };
+        assert_eq!(calc.eval_with("rate * 4", &resolver), Ok(2.0));
+        assert_eq!(reads.get(), 1);
+        assert_eq!(calc.eval_with("1 + 2", &resolver), Ok(3.0));
+        assert_eq!(reads.get(), 1);
+        assert_eq!(
+            calc.eval_with("rate + y", &resolver),
+            Err(EvalError::UnknownVariable{name: "y".to_string(), suggestion: None, span: 7..8}.into()),
+        );
+    }
+
+    #[test]
+    fn test_environment_first() {
+        let calc = Calculator::<i128>::new();
+        let mut env = Environment::new();
+        env.set("x", 1);
+        let resolver = |_: &str| Some(10);
+        let ast = calc.parse("x + y").unwrap();
+        let mut ctx = calc.context().env(&mut env).resolver(&resolver);
+        assert_eq!(i128::from_ast_in(ast, &mut ctx), Ok(11));
+    }
+}
@@ -0,0 +1,249 @@
+//! Roots of `root(n, x)`, also written `√x` for `root(2, x)` and
+//! `3√x` for `root(3, x)`.
+//!
+//! The integer calculators return the root exactly when `x` is a
+//! perfect power, and otherwise round it by the [`Rounding`] of the
+//! calculation with a [`Warning::InexactRoot`]. The rational ones
+//! return the nearest fraction of the `f64` root when the numerator
+//! or the denominator isn't a perfect power, and the `f64` one the
+//! `f64` root, with the odd roots of the negative numbers.
+//!
+//! A function registered in the [`FunctionRegistry`] of the same
+//! name takes precedence.
+//!
+//! [`Warning::InexactRoot`]: crate::error::Warning::InexactRoot
+//! [`FunctionRegistry`]: crate::FunctionRegistry
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::error::{EvalError, Warning};
+use crate::generator::{list, Context};
+use crate::parser::ast::Call;
+use crate::rational::Rational;
+
+pub(crate) const NAME: &str = "root";
+
+/// Rounding of the roots that aren't integers in the integer
+/// calculators.
+///
+/// # Example
+/// ```
+/// use wcal::{Calculator, Rounding};
+///
+/// assert_eq!(Calculator::<i128>::new().eval("root(2, 10) + √16"), Ok(7));
+/// let calc = Calculator::<i128>::new().rounding(Rounding::Nearest);
+/// assert_eq!(calc.eval("3√-10"), Ok(-2));
+/// assert_eq!(calc.eval("√15"), Ok(4));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Rounding {
+    /// Toward negative infinity, so `√10` is `3` and `3√-10` is `-3`
+    #[default]
+    Floor,
+    /// Toward positive infinity
+    Ceiling,
+    /// To the nearest integer. A root of an integer is never halfway
+    Nearest,
+}
+
+impl Rounding {
+    /// Whether the magnitude of an inexact root is rounded up, the
+    /// root of a negative radicand being negative. `past_half` is
+    /// whether the magnitude is past the halfway.
+    pub(crate) fn away<F>(self, negative: bool, past_half: F) -> bool
+    where F: FnOnce() -> bool {
+        match self {
+            Rounding::Floor => negative,
+            Rounding::Ceiling => !negative,
+            Rounding::Nearest => past_half(),
+        }
+    }
+}
+
+impl fmt::Display for Rounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rounding::Floor => write!(f, "floor"),
+            Rounding::Ceiling => write!(f, "ceil"),
+            Rounding::Nearest => write!(f, "nearest"),
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    /// `floor`, `ceil` or `nearest`, or `ceiling` and `round`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "floor" => Ok(Rounding::Floor),
+            "ceil" | "ceiling" => Ok(Rounding::Ceiling),
+            "nearest" | "round" => Ok(Rounding::Nearest),
+            _ => Err(format!("Rounding must be floor, ceil or nearest, got {}", s)),
+        }
+    }
+}
+
+/// Degree and radicand of a call of `root`, `None` for the other
+/// calls and if the registry has a `root`.
+pub(crate) fn args<T: list::Item>(node: &Call, ctx: &mut Context<T>) -> Result<Option<(T, T)>, EvalError> {
+    if node.name != NAME || ctx.functions.arity(&node.name).is_some() {
+        return Ok(None)
+    }
+    match node.args.as_slice() {
+        [degree, radicand] => Ok(Some((T::calculate(degree, ctx)?, T::calculate(radicand, ctx)?))),
+        args => Err(EvalError::WrongArity{name: node.name.clone(), expected: 2, found: args.len(), span: node.span.clone()}),
+    }
+}
+
+/// The degree if it is a positive integer, else what a degree is
+/// expected to be.
+pub(crate) fn degree(n: Option<i128>) -> Result<u32, &'static str> {
+    n.and_then(|n| u32::try_from(n).ok()).filter(|n| *n > 0).ok_or("a positive integer degree")
+}
+
+pub(crate) const NEGATIVE: &str = "an odd degree for a negative radicand";
+
+/// Root of `x` and whether it is exact, rounded by the rounding
+/// otherwise. `Err` if `x` is negative and the degree even.
+pub(crate) fn integer(n: u32, x: i128, rounding: Rounding) -> Result<(i128, bool), &'static str> {
+    let negative = x < 0;
+    if negative && n.is_multiple_of(2) {
+        return Err(NEGATIVE)
+    }
+    let magnitude = x.unsigned_abs();
+    let root = floor_root(n, magnitude);
+    let exact = root.checked_pow(n) == Some(magnitude);
+    let up = !exact && rounding.away(negative, || past_half(n, magnitude, root));
+    let root = if up { root + 1 } else { root };
+    // Only the first root of i128::MIN is past i128::MAX, and it is negative
+    let root = if negative { 0i128.checked_sub_unsigned(root) } else { i128::try_from(root).ok() };
+    Ok((root.expect("roots are at most the radicand"), exact))
+}
+
+/// Largest integer whose `n`th power is at most `x`.
+fn floor_root(n: u32, x: u128) -> u128 {
+    if n == 1 || x < 2 {
+        return x
+    }
+    // The estimate is off by a little for the large numbers
+    let mut root = (x as f64).powf(1.0 / f64::from(n)) as u128;
+    while (root + 1).checked_pow(n).is_some_and(|power| power <= x) {
+        root += 1;
+    }
+    while root.checked_pow(n).is_none_or(|power| power > x) {
+        root -= 1;
+    }
+    root
+}
+
+/// Whether the root of `x` is past `root + 1/2`, so whether `2^n x`
+/// is past `(2 root + 1)^n`. Approximated with `f64` if they
+/// overflow.
+fn past_half(n: u32, x: u128, root: u128) -> bool {
+    let doubled = 2u128.checked_pow(n).and_then(|power| power.checked_mul(x));
+    match (doubled, (2 * root + 1).checked_pow(n)) {
+        (Some(doubled), Some(power)) => doubled > power,
+        _ => (x as f64).powf(1.0 / f64::from(n)) - root as f64 > 0.5,
+    }
+}
+
+/// Root of the fraction and whether it is exact, the nearest
+/// fraction of the `f64` root otherwise. `Err` if `x` is negative
+/// and the degree even.
+pub(crate) fn rational(n: u32, x: Rational) -> Result<(Rational, bool), &'static str> {
+    let (num, num_exact) = integer(n, x.numer(), Rounding::Floor)?;
+    let (den, den_exact) = integer(n, x.denom(), Rounding::Floor)?;
+    if num_exact && den_exact {
+        return Ok((Rational::new(num, den).expect("roots of a fraction in lowest terms"), true))
+    }
+    Ok((Rational::approx(float(f64::from(n), x.to_f64())), false))
+}
+
+/// Root of a call of `root` in the fractions, with a warning if it
+/// is approximated.
+pub(crate) fn fraction<T>(node: &Call, n: Rational, x: Rational, ctx: &mut Context<T>) -> Result<Rational, EvalError> {
+    let mismatch = |expected| EvalError::Mismatch{expected, span: node.span.clone()};
+    let n = degree(Some(n.numer()).filter(|_| n.is_integer())).map_err(mismatch)?;
+    let (root, exact) = rational(n, x).map_err(mismatch)?;
+    if !exact {
+        let radicand = x.to_string();
+        warn!(degree = n, radicand = radicand.as_str(); "root will cause a rounding");
+        ctx.warn(Warning::InexactRoot{degree: n, radicand, span: node.span.clone()})?;
+    }
+    Ok(root)
+}
+
+/// Root of `x`, `NaN` if `x` is negative and the degree isn't an
+/// odd integer. The roots of the perfect powers are exact, so
+/// `root(3, 27)` is `3` rather than `3.0000000000000004`.
+pub(crate) fn float(n: f64, x: f64) -> f64 {
+    if x < 0.0 && (n % 2.0).abs() == 1.0 {
+        return -float(n, -x)
+    }
+    let root = x.powf(n.recip());
+    let rounded = root.round();
+    if rounded.powf(n) == x { rounded } else { root }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer() {
+        use Rounding::*;
+
+        assert_eq!(integer(2, 16, Floor), Ok((4, true)));
+        assert_eq!(integer(3, -27, Ceiling), Ok((-3, true)));
+        assert_eq!(integer(2, 10, Floor), Ok((3, false)));
+        assert_eq!(integer(2, 10, Ceiling), Ok((4, false)));
+        assert_eq!(integer(2, 12, Nearest), Ok((3, false)));
+        assert_eq!(integer(2, 13, Nearest), Ok((4, false)));
+        assert_eq!(integer(3, -10, Floor), Ok((-3, false)));
+        assert_eq!(integer(3, -10, Ceiling), Ok((-2, false)));
+        assert_eq!(integer(3, -10, Nearest), Ok((-2, false)));
+        assert_eq!(integer(2, -4, Floor), Err(NEGATIVE));
+        assert_eq!(integer(1, i128::MIN, Floor), Ok((i128::MIN, true)));
+        assert_eq!(integer(127, i128::MIN, Floor), Ok((-2, true)));
+        assert_eq!(integer(2, i128::MAX, Floor), Ok((13043817825332782212, false)));
+        assert_eq!(integer(2, i128::MAX, Nearest), Ok((13043817825332782212, false)));
+        assert_eq!(integer(2, 13043817825332782212i128.pow(2), Ceiling), Ok((13043817825332782212, true)));
+        assert_eq!(integer(100, 0, Ceiling), Ok((0, true)));
+    }
+
+    #[test]
+    fn test_rational() {
+        let r = |num, den| Rational::new(num, den).unwrap();
+        assert_eq!(rational(2, r(9, 4)), Ok((r(3, 2), true)));
+        assert_eq!(rational(3, r(-1, 8)), Ok((r(-1, 2), true)));
+        let (root, exact) = rational(2, r(2, 1)).unwrap();
+        assert!(!exact && (root.to_f64() - 2f64.sqrt()).abs() < 1e-15);
+        assert_eq!(rational(2, r(-1, 4)), Err(NEGATIVE));
+        assert_eq!(degree(Some(0)), Err("a positive integer degree"));
+        assert_eq!(degree(None), Err("a positive integer degree"));
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(float(3.0, 27.0), 3.0);
+        assert_eq!(float(3.0, -8.0), -2.0);
+        assert_eq!(float(2.0, 2.0), 2f64.sqrt());
+        assert_eq!(float(0.5, 3.0), 9.0);
+        assert!(float(2.0, -4.0).is_nan());
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!("ceiling".parse(), Ok(Rounding::Ceiling));
+        assert_eq!(Rounding::Nearest.to_string().parse(), Ok(Rounding::Nearest));
+        assert!("up".parse::<Rounding>().is_err());
+        assert_eq!(Rounding::default(), Rounding::Floor);
+    }
+}
@@ -0,0 +1,582 @@
+//! History of the evaluated expressions.
+use std::slice::Iter;
+use std::time::SystemTime;
+#[cfg(feature = "serde")]
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{AngleUnit, Calculator, Rounding, Environment, Error, FromAST};
+use crate::error::Warning;
+use crate::explain::{self, Step};
+use crate::text;
+use crate::parser::ast::AST;
+
+/// An evaluated expression.
+#[derive(Debug, Clone)]
+pub struct Entry<T> {
+    pub expr: String,
+    /// `None` if the expression can't be parsed.
+    pub ast: Option<AST>,
+    pub result: Result<T, Error>,
+    pub warnings: Vec<Warning>,
+    /// Unit of the angles the expression was evaluated in.
+    pub angle_unit: AngleUnit,
+    /// When the evaluation finished.
+    pub time: SystemTime,
+}
+
+/// Calculator that records every evaluated expression, such as
+/// the history of a REPL.
+///
+/// Variables assigned by the expressions are kept in the
+/// session's [`Environment`]. Assignments of a failed expression
+/// are rolled back, and [`undo`](Session::undo) discards the
+/// assignments of the last successful one.
+///
+/// Functions defined by [`define`](Session::define), such as
+/// `f(x) = x * x`, are kept in the environment too.
+///
+/// Previous results can be referenced by the expressions: `ans`
+/// is the last successful result, and `$1`, `$2`, ... are the
+/// results of the first, second, ... evaluated expressions.
+/// Variables assigned with the same name take precedence.
+///
+/// # Example
+/// ```
+/// use wcal::Session;
+///
+/// let mut session = Session::<i128>::default();
+/// session.eval("7/2");
+/// session.eval("1+");
+///
+/// assert_eq!(session.len(), 2);
+/// assert_eq!(session.nth(0).unwrap().result, Ok(3));
+/// assert_eq!(session.nth(0).unwrap().warnings.len(), 1);
+/// assert!(session.last().unwrap().result.is_err());
+/// assert_eq!(session.eval("ans * 2 + $1").result, Ok(9));
+/// ```
+#[derive(Debug)]
+pub struct Session<T> {
+    calculator: Calculator<T>,
+    env: Environment<T>,
+    entries: Vec<Entry<T>>,
+}
+
+impl<T> Session<T> {
+    /// Session evaluating with the calculator.
+    pub fn new(calculator: Calculator<T>) -> Self {
+        Session{calculator, env: Environment::new(), entries: Vec::new()}
+    }
+
+    /// Variables assigned in the session.
+    pub fn env(&self) -> &Environment<T> {
+        &self.env
+    }
+
+    pub fn env_mut(&mut self) -> &mut Environment<T> {
+        &mut self.env
+    }
+
+    /// Calculator of the expressions, such as to list its
+    /// functions.
+    pub fn calculator(&self) -> &Calculator<T> {
+        &self.calculator
+    }
+
+    /// Fail the expressions that raise a warning, see
+    /// [`Calculator::warnings_as_errors`].
+    pub fn set_warnings_as_errors(&mut self, warnings_as_errors: bool) {
+        self.calculator.warnings_as_errors = warnings_as_errors;
+        self.calculator.clear_cache();
+    }
+
+    /// Evaluate the next expressions with the unit of the angles,
+    /// see [`Calculator::angle_unit`]. Each [`Entry`] records its
+    /// own.
+    pub fn set_angle_unit(&mut self, angle_unit: AngleUnit) {
+        self.calculator.angle_unit = angle_unit;
+        self.calculator.clear_cache();
+    }
+
+    /// Evaluate the next expressions with the rounding of the
+    /// roots, see [`Calculator::rounding`].
+    pub fn set_rounding(&mut self, rounding: Rounding) {
+        self.calculator.rounding = rounding;
+        self.calculator.clear_cache();
+    }
+
+    /// Define the function of a definition `name(params) = body`
+    /// and return its name, `None` if the expression isn't one.
+    /// Definitions aren't recorded, [`undo`](Session::undo)
+    /// discards them like assignments.
+    ///
+    /// # Example
+    /// ```
+    /// use wcal::Session;
+    ///
+    /// let mut session = Session::<i128>::default();
+    /// assert_eq!(session.define("f(n) = if n then n * f(n - 1) else 1"), Some(Ok("f".to_string())));
+    /// assert_eq!(session.define("1 + 2"), None);
+    /// assert_eq!(session.eval("f(5)").result, Ok(120));
+    /// assert!(session.eval("f(1, 2)").result.is_err());
+    /// ```
+    pub fn define(&mut self, expr: &str) -> Option<Result<String, Error>> {
+        let (name, function) = match self.calculator.parse_definition(expr)? {
+            Ok(definition) => definition,
+            Err(err) => return Some(Err(err)),
+        };
+        self.env.checkpoint();
+        self.env.define(&name, function);
+        Some(Ok(name))
+    }
+
+    /// Discard the assignments or the definition of the last
+    /// successful expression that isn't undone yet. Return `false`
+    /// if there is none.
+    pub fn undo(&mut self) -> bool {
+        self.env.rollback()
+    }
+
+    /// The last evaluated expression.
+    pub fn last(&self) -> Option<&Entry<T>> {
+        self.entries.last()
+    }
+
+    /// The `i`th evaluated expression, from 0.
+    pub fn nth(&self, i: usize) -> Option<&Entry<T>> {
+        self.entries.get(i)
+    }
+
+    /// Evaluated expressions from the oldest.
+    pub fn iter(&self) -> Iter<'_, Entry<T>> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Forget the evaluated expressions.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<T: FromAST + Clone> Session<T> {
+    /// Evaluate the expression and record it.
+    pub fn eval(&mut self, expr: &str) -> &Entry<T> {
+        match self.calculator.parse_with_warnings(expr) {
+            Ok((ast, warnings)) => self.eval_warned(expr, ast, warnings),
+            Err(err) => self.push(expr, None, Err(err), Vec::new()),
+        }
+    }
+
+    /// Evaluate the expression parsed elsewhere, such as by another
+    /// parser, and record it.
+    pub fn eval_ast(&mut self, expr: &str, ast: AST) -> &Entry<T> {
+        self.eval_warned(expr, ast, Vec::new())
+    }
+
+    /// Evaluate the AST after raising the warnings of its parsing.
+    fn eval_warned(&mut self, expr: &str, ast: AST, warnings: Vec<Warning>) -> &Entry<T> {
+        self.env.checkpoint();
+        let entries = &self.entries;
+        let results = |name: &str| result_ref(entries, name);
+        let mut ctx = self.calculator.context().env(&mut self.env).resolver(&results);
+        let result = warnings.into_iter()
+            .try_for_each(|warning| ctx.warn(warning))
+            .map_err(Error::from)
+            .and_then(|_| T::from_ast_in(ast.clone(), &mut ctx));
+        let warnings = ctx.warnings().to_vec();
+        if result.is_err() {
+            self.env.rollback();
+        }
+        self.push(expr, Some(ast), result, warnings)
+    }
+
+    /// Text of an expression calling a function whose result is a
+    /// text, with the variables and the results of the session, see
+    /// [`Calculator::eval_text`]. Nothing is recorded, and the
+    /// assignments of a failed one are rolled back.
+    ///
+    /// # Example
+    /// ```
+    /// use wcal::Session;
+    ///
+    /// let mut session = Session::<i128>::default();
+    /// session.eval("x = 255");
+    /// assert_eq!(session.eval_text("hex(x)"), Some(Ok("0xff".to_string())));
+    /// assert_eq!(session.eval_text("bin(ans - 250)"), Some(Ok("0b101".to_string())));
+    /// assert_eq!(session.len(), 1);
+    /// ```
+    pub fn eval_text(&mut self, expr: &str) -> Option<Result<String, Error>> {
+        let ast = self.calculator.parse(expr).ok()?;
+        self.env.checkpoint();
+        let entries = &self.entries;
+        let results = |name: &str| result_ref(entries, name);
+        let mut ctx = self.calculator.context().env(&mut self.env).resolver(&results);
+        let text = text::eval(&ast, &mut ctx);
+        if !matches!(text, Some(Ok(_))) {
+            self.env.rollback();
+        }
+        text
+    }
+
+    /// Steps of the evaluation of the expression, see
+    /// [`explain`](crate::explain::explain). Nothing is recorded or
+    /// assigned.
+    pub fn explain(&self, expr: &str) -> (Vec<Step<T>>, Result<T, Error>) {
+        let ast = match self.calculator.parse(expr) {
+            Ok(ast) => ast,
+            Err(err) => return (Vec::new(), Err(err)),
+        };
+        let mut env = self.env.clone();
+        let entries = &self.entries;
+        let results = |name: &str| result_ref(entries, name);
+        let mut ctx = self.calculator.context().env(&mut env).resolver(&results);
+        explain::explain(&ast, &mut ctx)
+    }
+
+    fn push(&mut self, expr: &str, ast: Option<AST>, result: Result<T, Error>, warnings: Vec<Warning>) -> &Entry<T> {
+        self.entries.push(Entry{
+            expr: expr.to_string(),
+            ast,
+            result,
+            warnings,
+            angle_unit: self.calculator.angle_unit,
+            time: SystemTime::now(),
+        });
+        &self.entries[self.entries.len() - 1]
+    }
+}
+
+/// Value of `ans` or `$N` in the history, `None` for other names
+/// and failed expressions.
+fn result_ref<T: Clone>(entries: &[Entry<T>], name: &str) -> Option<T> {
+    let entry = if name == "ans" {
+        entries.iter().rev().find(|entry| entry.result.is_ok())?
+    } else {
+        let n: usize = name.strip_prefix('$')?.parse().ok()?;
+        entries.get(n.checked_sub(1)?)?
+    };
+    entry.result.as_ref().ok().cloned()
+}
+
+/// What is saved of a session, the rest is rebuilt on load.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct Saved<E> {
+    env: E,
+    history: Vec<SavedEntry>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SavedEntry {
+    expr: String,
+    /// Missing in the sessions saved before the angle units
+    #[serde(default)]
+    angle_unit: AngleUnit,
+    time: SystemTime,
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize> Session<T> {
+    /// Save the variables, the functions and the history as JSON,
+    /// only with the `serde` feature.
+    ///
+    /// Registered functions are closures, so they are not saved.
+    /// Register them again in the calculator passed to
+    /// [`load`](Session::load).
+    pub fn save<W: Write>(&self, writer: W) -> io::Result<()> {
+        let saved = Saved{
+            env: &self.env,
+            history: self.entries.iter()
+                .map(|entry| SavedEntry{expr: entry.expr.clone(), angle_unit: entry.angle_unit, time: entry.time})
+                .collect(),
+        };
+        serde_json::to_writer_pretty(writer, &saved)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned + FromAST + Clone> Session<T> {
+    /// Restore a session saved by [`save`](Session::save), only
+    /// with the `serde` feature.
+    ///
+    /// The history is evaluated again by the calculator, with the
+    /// saved functions and the angle unit of each expression, to
+    /// rebuild the ASTs, results and warnings, then the variables
+    /// are set to the saved ones.
+    ///
+    /// # Example
+    /// ```
+    /// use wcal::{Calculator, Session};
+    ///
+    /// let mut session = Session::<i128>::default();
+    /// session.eval("x = 7");
+    /// session.eval("x / 2");
+    ///
+    /// let mut file = Vec::new();
+    /// session.save(&mut file).unwrap();
+    ///
+    /// let session = Session::<i128>::load(Calculator::new(), file.as_slice()).unwrap();
+    /// assert_eq!(session.last().unwrap().result, Ok(3));
+    /// assert_eq!(session.env().get("x"), Some(&7));
+    /// ```
+    pub fn load<R: Read>(calculator: Calculator<T>, reader: R) -> io::Result<Self> {
+        let saved: Saved<Environment<T>> = serde_json::from_reader(reader)?;
+        let mut session = Session::new(calculator);
+        for (name, function) in saved.env.functions() {
+            session.env.define(name, function.clone());
+        }
+        let angle_unit = session.calculator.angle_unit;
+        for entry in saved.history {
+            session.set_angle_unit(entry.angle_unit);
+            session.eval(&entry.expr);
+            if let Some(last) = session.entries.last_mut() {
+                last.time = entry.time;
+            }
+        }
+        session.set_angle_unit(angle_unit);
+        session.env = saved.env;
+        Ok(session)
+    }
+}
+
+impl<T> Default for Session<T> {
+    fn default() -> Self {
+        Self::new(Calculator::new())
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Session<T> {
+    type Item = &'a Entry<T>;
+    type IntoIter = Iter<'a, Entry<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FunctionRegistry, Limits};
+    use crate::error::EvalError;
+    use crate::parser::ast::*;
+
+    #[test]
+    fn test_session() {
+        let mut session = Session::<i128>::default();
+        assert!(session.is_empty());
+        assert!(session.last().is_none());
+
+        assert_eq!(session.eval("1+2").result, Ok(3));
+        assert_eq!(session.eval("(1").ast, None);
+        assert_eq!(session.eval("1/0").ast, Some(AST{root: BinOp::new(Number::new(1), Number::new(0), crate::lexer::Token::Division)}));
+
+        assert_eq!(session.len(), 3);
+        let exprs: Vec<_> = session.iter().map(|entry| entry.expr.as_str()).collect();
+        assert_eq!(exprs, ["1+2", "(1", "1/0"]);
+        assert!((&session).into_iter().skip(1).all(|entry| entry.result.is_err()));
+        assert!(session.nth(0).unwrap().time <= session.last().unwrap().time);
+        assert!(session.nth(3).is_none());
+
+        session.clear();
+        assert!(session.is_empty());
+    }
+
+    #[test]
+    fn test_session_env() {
+        let mut session = Session::<i128>::default();
+
+        assert_eq!(session.eval("x = 2").result, Ok(2));
+        assert_eq!(session.eval("y = x * 3").result, Ok(6));
+        assert!(session.eval("x = 1/0").result.is_err());
+        assert_eq!(session.env().get("x"), Some(&2));
+        assert!(session.eval("z").result.is_err());
+        // Bound by `let` without touching the environment
+        assert_eq!(session.eval("z = let x = 5 in x + ans").result, Ok(11));
+        assert_eq!(session.env().get("x"), Some(&2));
+        assert!(session.undo());
+
+        assert!(session.undo());
+        assert_eq!(session.env().get("y"), None);
+        assert!(session.undo());
+        assert!(session.env().is_empty());
+        assert!(!session.undo());
+    }
+
+    #[test]
+    fn test_eval_ast() {
+        let mut session = Session::<i128>::default();
+        session.eval("x = 4");
+        let ast = AST{root: BinOp::new(Var::new("x"), Var::new("ans"), crate::lexer::Token::Times)};
+        assert_eq!(session.eval_ast("x * ans", ast.clone()).result, Ok(16));
+        assert_eq!(session.last().unwrap().ast, Some(ast));
+        assert_eq!(session.len(), 2);
+    }
+
+    #[test]
+    fn test_session_results() {
+        let mut session = Session::<i128>::default();
+        assert!(session.eval("ans").result.is_err());
+        assert_eq!(session.eval("2").result, Ok(2));
+        assert!(session.eval("1/0").result.is_err());
+        assert_eq!(session.eval("ans * 3 + 1").result, Ok(7));
+        assert_eq!(session.eval("ans + $2").result, Ok(9));
+        assert_eq!(session.eval("$5 - $4").result, Ok(2));
+
+        for name in ["$1", "$3", "$0", "$99", "$99999999999999999999999"].iter() {
+            let err = session.eval(name).result.clone().unwrap_err();
+            assert_eq!(err.to_string(), format!("Unknown variable {}", name));
+        }
+
+        assert_eq!(session.eval("ans = 10").result, Ok(10));
+        assert_eq!(session.eval("ans + 1").result, Ok(11));
+        assert_eq!(session.eval("ans").result, Ok(10));
+    }
+
+    #[test]
+    fn test_define() {
+        let mut session = Session::<i128>::default();
+        assert_eq!(session.define("f(x, y) = x * x + y"), Some(Ok("f".to_string())));
+        assert_eq!(session.eval("f(3, 1) + f(1, 0)").result, Ok(11));
+        // Only the expression is recorded
+        assert_eq!(session.len(), 1);
+        let arity = EvalError::WrongArity{name: "f".to_string(), expected: 2, found: 1, span: 4..8};
+        assert_eq!(session.eval("1 + f(2)").result, Err(arity.into()));
+
+        // Errors and warnings of the body point at the call
+        session.define("half(x) = x / 2");
+        let entry = session.eval("1 + half(7)");
+        assert_eq!((&entry.result, entry.warnings[0].span()), (&Ok(4), 4..11));
+        session.define("inv(x) = 1 / x");
+        assert_eq!(session.eval("2 * inv(0)").result.as_ref().unwrap_err().span(), 4..10);
+        // The body doesn't see the `let` around the call
+        session.define("g(x) = x + y");
+        assert!(session.eval("let y = 1 in g(1)").result.is_err());
+
+        session.define("fact(n) = if n then n * fact(n - 1) else 1");
+        assert_eq!(session.eval("fact(20)").result, Ok(2432902008176640000));
+        let err = session.eval("fact(-1)").result.clone();
+        assert_eq!(err, Err(EvalError::CallTooDeep{limit: 64, span: 0..8}.into()));
+
+        assert!(session.define("f(1) = 2").is_none());
+        assert!(matches!(session.define("f(x) = (x"), Some(Err(_))));
+        session.define("f(x) = x");
+        assert_eq!(session.eval("f(5)").result, Ok(5));
+        // The expression, then the definition
+        assert!(session.undo() && session.undo());
+        assert_eq!(session.eval("f(5, 0)").result, Ok(25));
+
+        let mut session = Session::<f64>::default();
+        session.define("avg(a, b) = (a + b) / 2");
+        assert_eq!(session.eval("avg(1, 2)").result, Ok(1.5));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_save_load_functions() {
+        let mut session = Session::<i128>::default();
+        session.define("sq(x) = x * x + 0x10");
+        session.eval("sq(2)");
+        let mut file = Vec::new();
+        session.save(&mut file).unwrap();
+        let json = String::from_utf8(file.clone()).unwrap();
+        assert!(json.contains(r#""body": "x * x + 16""#), "{}", json);
+
+        let mut loaded = Session::<i128>::load(Calculator::new(), file.as_slice()).unwrap();
+        assert_eq!(loaded.last().unwrap().result, Ok(20));
+        assert_eq!(loaded.eval("sq(3)").result, Ok(25));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_save_load() {
+        let mut session = Session::<f64>::default();
+        session.eval("x = 1");
+        session.eval("y = x / 4");
+        session.eval("1 +");
+        session.undo();
+
+        let mut file = Vec::new();
+        session.save(&mut file).unwrap();
+        let loaded = Session::<f64>::load(Calculator::new(), file.as_slice()).unwrap();
+
+        assert_eq!(loaded.len(), 3);
+        for (entry, saved) in loaded.iter().zip(session.iter()) {
+            assert_eq!(entry.expr, saved.expr);
+            assert_eq!(entry.ast, saved.ast);
+            assert_eq!(entry.result, saved.result);
+            assert_eq!(entry.time, saved.time);
+        }
+        assert_eq!(loaded.env().get("x"), Some(&1f64));
+        assert_eq!(loaded.env().get("y"), None);
+
+        assert!(Session::<f64>::load(Calculator::new(), "{".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_angle_unit() {
+        let mut session = Session::<f64>::default();
+        assert_eq!(session.eval("cos(0)").angle_unit, AngleUnit::Radian);
+        session.set_angle_unit(AngleUnit::Degree);
+        let entry = session.eval("sin(90)");
+        assert_eq!((&entry.result, entry.angle_unit), (&Ok(1.0), AngleUnit::Degree));
+        assert_eq!(session.eval("acos(ans - 1)").result, Ok(90.0));
+
+        #[cfg(feature = "serde")]
+        {
+            let mut file = Vec::new();
+            session.save(&mut file).unwrap();
+            let loaded = Session::<f64>::load(Calculator::new(), file.as_slice()).unwrap();
+            let units: Vec<_> = loaded.iter().map(|entry| entry.angle_unit).collect();
+            assert_eq!(units, [AngleUnit::Radian, AngleUnit::Degree, AngleUnit::Degree]);
+            assert_eq!(loaded.last().unwrap().result, Ok(90.0));
+            assert_eq!(loaded.calculator().angle_unit, AngleUnit::Radian);
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "serde", feature = "bigint", feature = "decimal"))]
+    fn test_save_load_types() {
+        let mut session = Session::<crate::BigInt>::default();
+        session.eval("x = 0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF * 16");
+        let mut file = Vec::new();
+        session.save(&mut file).unwrap();
+        let loaded = Session::<crate::BigInt>::load(Calculator::new(), file.as_slice()).unwrap();
+        assert_eq!(loaded.env().get("x"), session.env().get("x"));
+
+        let mut session = Session::<crate::Decimal>::default();
+        session.eval("x = 1/8");
+        let mut file = Vec::new();
+        session.save(&mut file).unwrap();
+        let loaded = Session::<crate::Decimal>::load(Calculator::new(), file.as_slice()).unwrap();
+        assert_eq!(loaded.env().get("x").map(ToString::to_string), Some("0.125".to_string()));
+    }
+
+    #[test]
+    fn test_session_calculator() {
+        let mut functions = FunctionRegistry::new();
+        functions.register("sq", 1, |args: &[f64]| args[0] * args[0]);
+        let calc = crate::Calculator::new()
+            .functions(functions)
+            .limits(Limits{operations: 3, ..Limits::default()});
+        let mut session = Session::new(calc);
+
+        assert_eq!(session.eval("sq(3)").result, Ok(9f64));
+        assert!(session.eval("sq(3) + 1").result.is_err());
+        assert!(session.last().unwrap().ast.is_some());
+        assert!(session.last().unwrap().warnings.is_empty());
+        assert_eq!(session.calculator().function_registry().signatures(), [("sq", 1)]);
+    }
+}
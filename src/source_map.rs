@@ -0,0 +1,160 @@
+//! Lines and columns of the byte offsets of a source.
+use std::fmt;
+
+use crate::lexer::Span;
+
+/// Position in a source, both counted from 0. The column counts
+/// characters, or UTF-16 units for the editors.
+///
+/// Displayed from 1, such as `1:5`, like the diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineColumn {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LineColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+/// Converter between the byte offsets of the spans and the lines
+/// and columns of a source of any number of lines.
+///
+/// An offset inside a multi-byte character is the character, and
+/// an offset past the end is the end.
+///
+/// # Example
+/// ```
+/// use wcal::{LineColumn, SourceMap};
+///
+/// let map = SourceMap::new("x = 1\ny = \u{e9}t\u{e9} + 2");
+/// assert_eq!(map.location(4), LineColumn{line: 0, column: 4});
+/// assert_eq!(map.location(15), LineColumn{line: 1, column: 7});
+/// assert_eq!(map.location(15).to_string(), "2:8");
+/// assert_eq!(map.offset(LineColumn{line: 1, column: 7}), 15);
+/// assert_eq!(map.line(1), Some("y = \u{e9}t\u{e9} + 2"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SourceMap<'a> {
+    source: &'a str,
+    /// Offset of the start of each line
+    starts: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        SourceMap{source, starts}
+    }
+
+    /// Number of lines, at least 1.
+    pub fn lines(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Byte range of the line, without its `\n`.
+    pub fn line_span(&self, line: usize) -> Option<Span> {
+        let start = *self.starts.get(line)?;
+        let end = self.starts.get(line + 1).map_or(self.source.len(), |next| next - 1);
+        Some(start..end)
+    }
+
+    /// Text of the line, without its `\n`.
+    pub fn line(&self, line: usize) -> Option<&'a str> {
+        self.line_span(line).map(|span| &self.source[span])
+    }
+
+    /// Line and column in characters of the offset.
+    pub fn location(&self, offset: usize) -> LineColumn {
+        self.locate(offset, |text| text.chars().count())
+    }
+
+    /// Line and column in UTF-16 units of the offset, like the
+    /// positions of the language server protocol.
+    pub fn utf16_location(&self, offset: usize) -> LineColumn {
+        self.locate(offset, |text| text.encode_utf16().count())
+    }
+
+    /// Offset of the line and column in characters, the end of the
+    /// line past it.
+    pub fn offset(&self, at: LineColumn) -> usize {
+        self.find(at, |_| 1)
+    }
+
+    /// Offset of the line and column in UTF-16 units.
+    pub fn utf16_offset(&self, at: LineColumn) -> usize {
+        self.find(at, char::len_utf16)
+    }
+
+    /// Offset at the start of its character, at most the end.
+    fn boundary(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+
+    fn locate(&self, offset: usize, width: fn(&str) -> usize) -> LineColumn {
+        let offset = self.boundary(offset);
+        let line = match self.starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        LineColumn{line, column: width(&self.source[self.starts[line]..offset])}
+    }
+
+    fn find(&self, at: LineColumn, width: fn(char) -> usize) -> usize {
+        let span = match self.line_span(at.line) {
+            Some(span) => span,
+            None => return self.source.len(),
+        };
+        let mut column = 0;
+        for (i, ch) in self.source[span.clone()].char_indices() {
+            if column >= at.column {
+                return span.start + i
+            }
+            column += width(ch);
+        }
+        span.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location() {
+        let map = SourceMap::new("ab\n\u{1F600}c\n\n");
+        assert_eq!(map.lines(), 4);
+        let at = |line, column| LineColumn{line, column};
+        assert_eq!(map.location(0), at(0, 0));
+        assert_eq!(map.location(2), at(0, 2));
+        assert_eq!(map.location(3), at(1, 0));
+        assert_eq!(map.location(5), at(1, 0));
+        assert_eq!(map.location(7), at(1, 1));
+        assert_eq!(map.utf16_location(7), at(1, 2));
+        assert_eq!(map.location(9), at(2, 0));
+        assert_eq!(map.location(100), at(3, 0));
+        assert_eq!(map.line(2), Some(""));
+        assert_eq!(map.line(4), None);
+    }
+
+    #[test]
+    fn test_offset() {
+        let map = SourceMap::new("ab\n\u{1F600}c");
+        let at = |line, column| LineColumn{line, column};
+        assert_eq!(map.offset(at(1, 1)), 7);
+        assert_eq!(map.utf16_offset(at(1, 2)), 7);
+        assert_eq!(map.utf16_offset(at(1, 1)), 7);
+        assert_eq!(map.offset(at(0, 9)), 2);
+        assert_eq!(map.offset(at(5, 0)), 8);
+        for offset in [0, 1, 3, 7, 8].iter() {
+            assert_eq!(map.offset(map.location(*offset)), *offset);
+            assert_eq!(map.utf16_offset(map.utf16_location(*offset)), *offset);
+        }
+    }
+}
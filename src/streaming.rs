@@ -0,0 +1,203 @@
+//! Calculate `+ - * /` of numbers as the tokens are lexed, without
+//! an AST, for [`eval_streaming`](crate::eval_streaming).
+//!
+//! The operands wait on a stack of levels, one per open
+//! parenthesis: the sum and the product so far with their
+//! operators, and the negations before the next factor. A factor
+//! is folded into them as soon as it is complete, so the operators
+//! are calculated in the order of the calculators of the AST.
+//!
+//! Any other token, or an error, gives up with `None`, and the
+//! depth of the top-down parser is followed so the expressions it
+//! rejects as too deep give up too.
+use crate::generator::{Arithmetic, Context};
+use crate::lexer::{self, Span, Token};
+use crate::parser::ast::Number;
+use crate::parser::MAX_DEPTH;
+
+/// A calculated operand, spanning as its node would.
+struct Operand<T> {
+    value: T,
+    span: Span,
+}
+
+/// Operands waiting in a parenthesis, or in the whole expression.
+struct Level<T> {
+    /// Span of the `(`, `None` for the whole expression
+    open: Option<Span>,
+    /// Depth of the parser at the start of the level
+    base: usize,
+    sum: Option<(Operand<T>, Token)>,
+    product: Option<(Operand<T>, Token)>,
+    /// Starts of the `-` before the next factor
+    negs: Vec<usize>,
+    /// Operators of the sum and of the current product, each one
+    /// nesting the parser one level deeper
+    sums: usize,
+    products: usize,
+}
+
+impl<T> Level<T> {
+    fn new(open: Option<Span>, base: usize) -> Self {
+        Level{open, base, sum: None, product: None, negs: Vec::new(), sums: 0, products: 0}
+    }
+
+    /// Depth of the parser at the next token, `None` if it can't
+    /// nest another node.
+    fn nest(&self) -> Option<usize> {
+        let depth = self.base + self.sums + self.products + self.negs.len();
+        (depth < MAX_DEPTH).then_some(depth)
+    }
+
+    /// Negate the factor by the `-` before it, and multiply it
+    /// into the product.
+    fn factor(&mut self, factor: Operand<T>, arithmetic: &Arithmetic<T>, ctx: &mut Context<T>) -> Option<Operand<T>> {
+        let mut factor = factor;
+        while let Some(start) = self.negs.pop() {
+            let span = start..factor.span.end;
+            factor = Operand{value: (arithmetic.negate)(factor.value, &span).ok()?, span};
+        }
+        match self.product.take() {
+            Some((lhs, op)) => binary(arithmetic, &op, lhs, factor, ctx),
+            None => Some(factor),
+        }
+    }
+
+    /// Add the product, complete, to the sum.
+    fn term(&mut self, term: Operand<T>, arithmetic: &Arithmetic<T>, ctx: &mut Context<T>) -> Option<Operand<T>> {
+        self.products = 0;
+        match self.sum.take() {
+            Some((lhs, op)) => binary(arithmetic, &op, lhs, term, ctx),
+            None => Some(term),
+        }
+    }
+}
+
+fn binary<T>(arithmetic: &Arithmetic<T>, op: &Token, lhs: Operand<T>, rhs: Operand<T>, ctx: &mut Context<T>) -> Option<Operand<T>> {
+    let span = lhs.span.start..rhs.span.end;
+    let value = (arithmetic.binary)(op, lhs.value, rhs.value, &span, ctx).ok()?;
+    Some(Operand{value, span})
+}
+
+/// Value of the expression, `None` if it has more than numbers,
+/// `+ - * /` and parentheses, isn't valid or fails.
+pub(crate) fn eval<T>(expr: &str, arithmetic: &Arithmetic<T>, ctx: &mut Context<T>) -> Option<T> {
+    let mut tokens = lexer::tokens(expr);
+    let mut outer: Vec<Level<T>> = Vec::new();
+    let mut level = Level::new(None, 0);
+    loop {
+        // The negations and parentheses up to a number
+        let mut operand = loop {
+            let (token, span) = tokens.next()?.ok()?;
+            match token {
+                Token::Number(num) => {
+                    let num = Number{num, span};
+                    // The `-` right before a literal makes a negative literal
+                    break match level.negs.pop() {
+                        Some(start) => {
+                            let span = start..num.span.end;
+                            Operand{value: (arithmetic.negative)(&num, &span, ctx).ok()?, span}
+                        }
+                        None => Operand{value: (arithmetic.number)(&num, ctx).ok()?, span: num.span},
+                    }
+                }
+                Token::Minus => {
+                    level.nest()?;
+                    level.negs.push(span.start);
+                }
+                Token::LP => {
+                    let depth = level.nest()?;
+                    outer.push(std::mem::replace(&mut level, Level::new(Some(span), depth + 1)));
+                }
+                _ => return None,
+            }
+        };
+        // The operators up to the next operand
+        loop {
+            operand = level.factor(operand, arithmetic, ctx)?;
+            match tokens.next().transpose().ok()? {
+                Some((op @ (Token::Times | Token::Division), _)) => {
+                    level.nest()?;
+                    level.product = Some((operand, op));
+                    level.products += 1;
+                    break
+                }
+                Some((op @ (Token::Plus | Token::Minus), _)) => {
+                    let sum = level.term(operand, arithmetic, ctx)?;
+                    level.nest()?;
+                    level.sum = Some((sum, op));
+                    level.sums += 1;
+                    break
+                }
+                Some((Token::RP, close)) => {
+                    let open = level.open.clone()?;
+                    let value = level.term(operand, arithmetic, ctx)?.value;
+                    level = outer.pop()?;
+                    operand = Operand{value, span: open.start..close.end};
+                }
+                Some(_) => return None,
+                None if level.open.is_none() => return Some(level.term(operand, arithmetic, ctx)?.value),
+                None => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::FunctionRegistry;
+    use crate::generator::{calculator, calculator_f, calculator_r};
+    use crate::{eval_str_fast, eval_streaming, Rational};
+
+    fn streamed<T>(expr: &str, arithmetic: &Arithmetic<T>) -> Option<T> {
+        eval(expr, arithmetic, &mut Context::new(&FunctionRegistry::new()))
+    }
+
+    #[test]
+    fn test_eval() {
+        let exprs = [
+            "1+2*3", "(1+2)*3", "12 - 4 - 3", "7/2*2", "-2*-3", "--5", "-(4)", "2*(3+(4-1))/3",
+            "-170141183460469231731687303715884105728", "9007199254740993", "2 * 9007199254740993",
+            "10 - (2 - 3) * (0x10 / 4)",
+        ];
+        for expr in exprs {
+            let value = streamed(expr, &calculator::ARITHMETIC);
+            assert!(value.is_some(), "expr: {}", expr);
+            assert_eq!(value.map(Ok), Some(eval_str_fast::<i128>(expr)), "expr: {}", expr);
+            assert_eq!(streamed(expr, &calculator_f::ARITHMETIC).map(Ok), Some(eval_str_fast::<f64>(expr)), "expr: {}", expr);
+            assert_eq!(streamed(expr, &calculator_r::ARITHMETIC).map(Ok), Some(eval_str_fast::<Rational>(expr)), "expr: {}", expr);
+        }
+    }
+
+    #[test]
+    fn test_give_up() {
+        // Other tokens, invalid expressions, and failures
+        let exprs = ["x + 1", "max(1, 2)", "1 < 2", "1h", "(1", "1)", "1 +", "2(3)", "1 $ 2", "1/0", "(1)[0]", ""];
+        for expr in exprs {
+            assert_eq!(streamed(expr, &calculator::ARITHMETIC), None, "expr: {}", expr);
+            assert_eq!(eval_streaming::<i128>(expr), eval_str_fast::<i128>(expr), "expr: {}", expr);
+        }
+        assert_eq!(streamed("-170141183460469231731687303715884105728 * -1", &calculator::ARITHMETIC), None);
+    }
+
+    #[test]
+    fn test_depth() {
+        // The depth of the parser is followed around its limit
+        for n in MAX_DEPTH - 2..=MAX_DEPTH + 1 {
+            let exprs = [
+                "(".repeat(n) + "1" + &")".repeat(n),
+                "-".repeat(n) + "1",
+                vec!["1"; n].join("+"),
+                vec!["1"; n].join("*"),
+                vec!["1*1"; n / 2].join("-") + "*(-1)",
+                "-(".repeat(n / 2) + "1" + &")".repeat(n / 2),
+            ];
+            for expr in exprs.iter() {
+                let expected = eval_str_fast::<i128>(expr);
+                assert_eq!(streamed(expr, &calculator::ARITHMETIC).is_some(), expected.is_ok(), "expr: {}", expr);
+                assert_eq!(eval_streaming::<i128>(expr), expected, "expr: {}", expr);
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! "Did you mean" suggestions for the unknown names.
+
+/// Candidate closest to the name, `None` if none is close enough:
+/// one edit for the names up to 5 characters, two past it, and
+/// fewer edits than characters, so `x` isn't suggested for `y`. An
+/// edit inserts, removes or replaces a character, or swaps two
+/// adjacent ones.
+pub(crate) fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let len = name.chars().count();
+    let max = if len <= 5 { 1 } else { 2 };
+    let max = max.min(len.saturating_sub(1));
+    candidates.into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Edits from `a` to `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // Distances from the prefixes of `a` to the prefixes of `b`
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let replace = rows[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            row[j] = replace.min(rows[i - 1][j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("sqr", "sqrt"), 1);
+        assert_eq!(distance("mena", "mean"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_closest() {
+        let names = ["sqrt", "sum", "mean", "principal"];
+        assert_eq!(closest("sqr", names.iter().copied()).as_deref(), Some("sqrt"));
+        assert_eq!(closest("sun", names.iter().copied()).as_deref(), Some("sum"));
+        assert_eq!(closest("principle", names.iter().copied()).as_deref(), Some("principal"));
+        assert_eq!(closest("max", names.iter().copied()), None);
+        assert_eq!(closest("y", ["x"].iter().copied()), None);
+        assert_eq!(closest("sum", names.iter().copied()), None);
+    }
+}
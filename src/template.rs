@@ -0,0 +1,186 @@
+//! Formulas with placeholders, such as `{rate} * {principal} + 1`,
+//! bound to expressions before they are calculated.
+use crate::{lexer, Error, Resolver};
+use crate::error::ParseError;
+use crate::lexer::Span;
+use crate::parser::ast::{Bool, Expr, AST};
+use crate::parser::top_down_parser;
+
+/// Parsed formula with named holes, written `{name}`.
+///
+/// Unlike the variables, every hole must be bound: [`bind`] fails
+/// with the list of the unbound ones before anything is calculated.
+/// A hole stands for a whole operand, and may appear many times.
+///
+/// [`bind`]: Template::bind
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+/// use wcal::{Calculator, FromAST, Template};
+/// use wcal::error::ParseError;
+///
+/// let calc = Calculator::<i128>::new();
+/// let template = Template::parse("{rate} * {principal} + 1").unwrap();
+/// assert_eq!(template.holes(), ["rate", "principal"]);
+///
+/// let mut values = HashMap::new();
+/// values.insert("rate".to_string(), calc.parse("2").unwrap().root);
+/// assert_eq!(
+///     template.bind(&values),
+///     Err(vec![ParseError::Unbound{name: "principal".to_string(), span: 9..20}]),
+/// );
+///
+/// values.insert("principal".to_string(), calc.parse("100 - 1").unwrap().root);
+/// let ast = template.bind(&values).unwrap();
+/// assert_eq!(i128::from_ast(ast), Ok(199));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    ast: AST,
+    /// Name of each hole, with the span of its `{name}`
+    holes: Vec<(String, Span)>,
+}
+
+impl Template {
+    /// Parse the formula, whose holes are operands.
+    pub fn parse(text: &str) -> Result<Template, Error> {
+        let mut holes = Vec::new();
+        // Each hole is parsed as a variable of its name, the braces
+        // blanked out so the spans don't move
+        let mut blanked = text.to_string();
+        let mut rest = text;
+        while let Some(open) = rest.find('{') {
+            let start = text.len() - rest.len() + open;
+            rest = &rest[open + 1..];
+            let close = match rest.find('}') {
+                Some(close) => close,
+                None => break,
+            };
+            let name = &rest[..close];
+            if !is_name(name) {
+                continue
+            }
+            let end = start + name.len() + 2;
+            blanked.replace_range(start..start + 1, " ");
+            blanked.replace_range(end - 1..end, " ");
+            holes.push((name.to_string(), start..end));
+            rest = &rest[close + 1..];
+        }
+        let ast = lexer::spanned(&blanked).and_then(top_down_parser::parse)?;
+        // Such as `{f}(1)` or `{x} = 1`, which aren't operands
+        let mut found = Vec::new();
+        visit(&ast.root, &mut |expr| if let Expr::Var(var) = expr {
+            found.push(var.span.start);
+        });
+        if let Some((_, span)) = holes.iter().find(|(_, span)| !found.contains(&(span.start + 1))) {
+            return Err(ParseError::InvalidExpression{span: span.clone()}.into())
+        }
+        Ok(Template{ast, holes})
+    }
+
+    /// Names of the holes, each one once, in order of appearance.
+    pub fn holes(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for (name, _) in &self.holes {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Tree of the formula with each hole replaced by its value, at
+    /// the span of the hole. Fails with [`ParseError::Unbound`] for
+    /// each hole without a value.
+    pub fn bind(&self, values: &dyn Resolver<Expr>) -> Result<AST, Vec<ParseError>> {
+        let mut unbound = Vec::new();
+        let mut root = self.ast.root.clone();
+        visit_mut(&mut root, &mut |expr| {
+            let (name, span) = match expr {
+                Expr::Var(var) => match self.holes.iter().find(|(_, span)| span.start + 1 == var.span.start) {
+                    Some(hole) => hole,
+                    None => return,
+                },
+                _ => return,
+            };
+            match values.resolve(name) {
+                Some(mut value) => {
+                    visit_mut(&mut value, &mut |node| {
+                        *node = std::mem::replace(node, Bool::new(false)).with_span(span.clone());
+                    });
+                    *expr = value;
+                }
+                None => unbound.push(ParseError::Unbound{name: name.clone(), span: span.clone()}),
+            }
+        });
+        if !unbound.is_empty() {
+            return Err(unbound)
+        }
+        Ok(AST{root})
+    }
+}
+
+/// Whether the text is a name of a variable.
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn visit<'a>(expr: &'a Expr, f: &mut impl FnMut(&'a Expr)) {
+    f(expr);
+    for child in expr.children() {
+        visit(child, f);
+    }
+}
+
+/// Call `f` with the children of each node before the node, so a
+/// replaced node isn't visited.
+fn visit_mut(expr: &mut Expr, f: &mut impl FnMut(&mut Expr)) {
+    for child in expr.children_mut() {
+        visit_mut(child, f);
+    }
+    f(expr);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{Calculator, FromAST};
+    use crate::error::{EvalError, LexError};
+
+    fn values(values: &[(&str, &str)]) -> HashMap<String, Expr> {
+        values.iter()
+            .map(|(name, value)| (name.to_string(), lexer::spanned(value).and_then(top_down_parser::parse).unwrap().root))
+            .collect()
+    }
+
+    #[test]
+    fn test_bind() {
+        let template = Template::parse("{a} * ({b} + a) - {a}").unwrap();
+        assert_eq!(template.holes(), ["a", "b"]);
+        let ast = template.bind(&values(&[("a", "2 + 1"), ("b", "x")])).unwrap();
+        assert_eq!(ast.to_string(), "(- (* (+ 2 1) (+ x a)) (+ 2 1))");
+
+        let calc = Calculator::<i128>::new();
+        let ast = template.bind(&values(&[("a", "1 / 0"), ("b", "1")])).unwrap();
+        assert_eq!(i128::from_ast(ast), Err(EvalError::DivisionByZero{span: 0..3}.into()));
+
+        let unbound = |name: &str, span| ParseError::Unbound{name: name.to_string(), span};
+        assert_eq!(template.bind(&values(&[])), Err(vec![unbound("a", 0..3), unbound("b", 7..10), unbound("a", 18..21)]));
+        let resolver = |name: &str| calc.parse(&name.len().to_string()).ok().map(|ast| ast.root);
+        assert_eq!(template.bind(&resolver).map(|ast| ast.to_string()), Ok("(- (* 1 (+ 1 a)) 1)".to_string()));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Template::parse("{f}(1)"), Err(ParseError::InvalidExpression{span: 0..3}.into()));
+        assert_eq!(Template::parse("{x} = 1"), Err(ParseError::InvalidExpression{span: 0..3}.into()));
+        assert_eq!(Template::parse("{1} + 1"), Err(LexError::InvalidCharacter{ch: '{', span: 0..1}.into()));
+        assert_eq!(Template::parse("{a} + {"), Err(LexError::InvalidCharacter{ch: '{', span: 6..7}.into()));
+        assert!(Template::parse("{a} +").is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Functions whose result is a text rather than a number, such as
+//! `hex(255)` or `format(pi, "%.3f")`.
+//!
+//! A string such as `"%.3f"` is only an argument of these functions,
+//! and their result is only the result of the whole expression, see
+//! [`Calculator::eval_text`](crate::Calculator::eval_text). Anywhere
+//! else a string or a call of them is an error, as a number is
+//! expected.
+//!
+//! * `hex(x)`, `oct(x)` and `bin(x)`: the integer with the prefix of
+//!   its literals, such as `0xff`
+//! * `format(x, "%.3f")`: the number in the format, the text around
+//!   one conversion of `%d`, `%x`, `%o` and `%b` for the integers,
+//!   `%f` for fixed decimals, `%e` for the scientific notation and
+//!   `%g` for significant digits. `%.3f` has 3 decimals or digits,
+//!   `%,d` groups the digits by `,`, and `%%` is a `%`
+//!
+//! The backend calculates the arguments, so `format(7/2, "%.1f")` is
+//! `3.0` with `i128` and `3.5` with `f64`.
+use crate::error::{Error, EvalError};
+use crate::format::{Notation, NumberFormat};
+use crate::generator::Context;
+use crate::lexer::Span;
+use crate::parser::ast::{Call, Expr, AST};
+use crate::rational::Rational;
+use crate::FromAST;
+
+/// Names of the functions returning a text.
+pub(crate) const FUNCTIONS: [&str; 4] = ["hex", "oct", "bin", "format"];
+
+/// Number of a result, the argument of the functions of the texts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Integer(i128),
+    Float(f64),
+}
+
+impl Number {
+    /// Integer value, `None` if it has a fraction or doesn't fit.
+    fn integer(self) -> Option<i128> {
+        match self {
+            Number::Integer(n) => Some(n),
+            Number::Float(x) if x.fract() == 0.0 && x.abs() < 2f64.powi(127) => Some(x as i128),
+            Number::Float(_) => None,
+        }
+    }
+
+    fn float(self) -> f64 {
+        match self {
+            Number::Integer(n) => n as f64,
+            Number::Float(x) => x,
+        }
+    }
+}
+
+impl From<Rational> for Number {
+    /// The integer, or the float of a fraction.
+    fn from(value: Rational) -> Self {
+        if value.is_integer() {
+            Number::Integer(value.numer())
+        } else {
+            Number::Float(value.to_f64())
+        }
+    }
+}
+
+/// Error of a string or a call of these functions where a number is
+/// expected.
+pub(crate) fn not_a_number(span: Span) -> EvalError {
+    EvalError::Mismatch{expected: "a number, not a string", span}
+}
+
+/// Whether the call returns a text, `false` if the registry or the
+/// environment has a function of its name.
+fn is_text<T>(call: &Call, ctx: &Context<T>) -> bool {
+    FUNCTIONS.contains(&call.name.as_str())
+        && ctx.functions.arity(&call.name).is_none()
+        && ctx.env.as_ref().is_none_or(|env| env.function(&call.name).is_none())
+}
+
+/// Text of the expression, `None` if it isn't a call of these
+/// functions.
+pub(crate) fn eval<T: FromAST>(ast: &AST, ctx: &mut Context<T>) -> Option<Result<String, Error>> {
+    let mut root = &ast.root;
+    while let Expr::Pair(pair) = root {
+        root = &pair.expr;
+    }
+    match root {
+        Expr::Call(call) if is_text(call, ctx) => Some(text(call, ctx)),
+        _ => None,
+    }
+}
+
+fn text<T: FromAST>(call: &Call, ctx: &mut Context<T>) -> Result<String, Error> {
+    let wrong_arity = |expected| EvalError::WrongArity{name: call.name.clone(), expected, found: call.args.len(), span: call.span.clone()};
+    let base = match call.name.as_str() {
+        "hex" => 16,
+        "oct" => 8,
+        "bin" => 2,
+        _ => return match call.args.as_slice() {
+            [arg, Expr::Str(format)] => {
+                let spec = Spec::parse(&format.value)
+                    .ok_or_else(|| EvalError::Mismatch{expected: "a format such as \"%.3f\"", span: format.span.clone()})?;
+                Ok(spec.format(number(arg, ctx)?).ok_or_else(|| integer_expected(arg))?)
+            }
+            [_, format] => Err(EvalError::Mismatch{expected: "a string", span: format.span()}.into()),
+            _ => Err(wrong_arity(2).into()),
+        },
+    };
+    let arg = match call.args.as_slice() {
+        [arg] => arg,
+        _ => return Err(wrong_arity(1).into()),
+    };
+    let n = number(arg, ctx)?.integer().ok_or_else(|| integer_expected(arg))?;
+    Ok(NumberFormat{base, prefix: true, ..NumberFormat::default()}.int(n))
+}
+
+/// Number of the argument calculated by the backend.
+fn number<T: FromAST>(arg: &Expr, ctx: &mut Context<T>) -> Result<Number, Error> {
+    T::from_ast_in(AST{root: arg.clone()}, ctx)?
+        .to_number()
+        .ok_or_else(|| EvalError::Mismatch{expected: "a number", span: arg.span()}.into())
+}
+
+fn integer_expected(arg: &Expr) -> EvalError {
+    EvalError::Mismatch{expected: "an integer", span: arg.span()}
+}
+
+/// Format of `format`, `%,.3f` between the text before and after it.
+#[derive(Debug, PartialEq)]
+struct Spec {
+    before: String,
+    after: String,
+    group: bool,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+impl Spec {
+    /// `None` without exactly one conversion, or with a precision
+    /// of an integer.
+    fn parse(format: &str) -> Option<Spec> {
+        let mut before = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                before.push(c);
+                continue
+            }
+            if chars.next_if_eq(&'%').is_some() {
+                before.push('%');
+                continue
+            }
+            let group = chars.next_if_eq(&',').is_some();
+            let precision = match chars.next_if_eq(&'.') {
+                Some(_) => {
+                    let mut digits = String::new();
+                    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                        digits.push(digit);
+                    }
+                    Some(digits.parse().ok()?)
+                }
+                None => None,
+            };
+            let conversion = chars.next().filter(|c| match c {
+                'd' | 'x' | 'o' | 'b' => precision.is_none(),
+                'f' | 'e' | 'g' => true,
+                _ => false,
+            })?;
+            let after: String = chars.collect();
+            if after.replace("%%", "").contains('%') {
+                return None
+            }
+            return Some(Spec{before, after: after.replace("%%", "%"), group, precision, conversion})
+        }
+        None
+    }
+
+    /// Text of the number, `None` if an integer is expected.
+    fn format(&self, number: Number) -> Option<String> {
+        let group = |digits| Some(digits).filter(|_| self.group);
+        let separator = ',';
+        let text = match self.conversion {
+            'f' | 'e' | 'g' => {
+                let format = NumberFormat{
+                    group: group(3),
+                    separator,
+                    notation: match self.conversion {
+                        'f' => Notation::Fixed,
+                        'e' => Notation::Scientific,
+                        _ => Notation::Auto,
+                    },
+                    precision: self.precision.filter(|_| self.conversion != 'g'),
+                    // 6 significant digits as in C
+                    significant: Some(self.precision.unwrap_or(6)).filter(|_| self.conversion == 'g'),
+                    ..NumberFormat::default()
+                };
+                format.float(number.float())
+            }
+            conversion => {
+                let (base, digits) = match conversion {
+                    'x' => (16, 4),
+                    'o' => (8, 3),
+                    'b' => (2, 4),
+                    _ => (10, 3),
+                };
+                NumberFormat{base, group: group(digits), separator, ..NumberFormat::default()}.int(number.integer()?)
+            }
+        };
+        Some(format!("{}{}{}", self.before, text, self.after))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Calculator;
+
+    #[test]
+    fn test_spec() {
+        let spec = Spec::parse("%,.2f USD").unwrap();
+        assert_eq!((spec.group, spec.precision, spec.conversion, spec.after.as_str()), (true, Some(2), 'f', " USD"));
+        assert_eq!(Spec::parse("100%% of %d").unwrap().before, "100% of ");
+        for format in ["", "%", "%%", "%.2d", "%q", "%d %d", "%.xf"] {
+            assert_eq!(Spec::parse(format), None, "{}", format);
+        }
+        let format = |format, number| Spec::parse(format).unwrap().format(number);
+        assert_eq!(format("%,d", Number::Integer(-1234567)).as_deref(), Some("-1,234,567"));
+        assert_eq!(format("%,x", Number::Float(65535.0)).as_deref(), Some("ffff"));
+        assert_eq!(format("%.1e", Number::Integer(1500)).as_deref(), Some("1.5e3"));
+        assert_eq!(format("%.3g", Number::Float(0.000123456)).as_deref(), Some("0.000123"));
+        assert_eq!(format("%d", Number::Float(1.5)), None);
+    }
+
+    #[test]
+    fn test_eval_text() {
+        let calc = Calculator::<f64>::new();
+        assert_eq!(calc.eval_text("hex(255)"), Some(Ok("0xff".to_string())));
+        assert_eq!(calc.eval_text("(bin(10))"), Some(Ok("0b1010".to_string())));
+        assert_eq!(calc.eval_text("oct(-8)"), Some(Ok("-0o10".to_string())));
+        assert_eq!(calc.eval_text("format(7/2, \"%.3f\")"), Some(Ok("3.500".to_string())));
+        assert_eq!(calc.eval_text("hex(1) + 1"), None);
+        assert_eq!(calc.eval("hex(1) + 1"), Err(EvalError::Mismatch{expected: "a number, not a string", span: 0..6}.into()));
+        assert_eq!(calc.eval("1 + \"a\""), Err(EvalError::Mismatch{expected: "a number, not a string", span: 4..7}.into()));
+
+        assert_eq!(calc.eval_text("hex(3/2)"), Some(Err(EvalError::Mismatch{expected: "an integer", span: 4..7}.into())));
+        assert_eq!(calc.eval_text("format(1, 2)"), Some(Err(EvalError::Mismatch{expected: "a string", span: 10..11}.into())));
+        assert_eq!(calc.eval_text("format(1, \"%\")"), Some(Err(EvalError::Mismatch{expected: "a format such as \"%.3f\"", span: 10..13}.into())));
+        assert!(matches!(calc.eval_text("hex(1, 2)"), Some(Err(Error::Eval(EvalError::WrongArity{expected: 1, found: 2, ..})))));
+        // Calculated by the backend
+        assert_eq!(Calculator::<i128>::new().eval_text("format(7/2, \"%.1f\")"), Some(Ok("3.0".to_string())));
+    }
+}
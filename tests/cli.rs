@@ -0,0 +1,40 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn wcal(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wcal"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_filter_define() {
+    let output = wcal(&[], "f(a) = a*a\nf(3)\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "9");
+}
+
+#[test]
+fn test_file_define() {
+    let path = std::env::temp_dir().join(format!("wcal-cli-{}.wcal", std::process::id()));
+    std::fs::write(&path, "f(a) = a*a\nf(3)\n").unwrap();
+    let output = wcal(&["-F", path.to_str().unwrap()], "");
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    assert!(String::from_utf8_lossy(&output.stdout).trim().ends_with(":2: 9"));
+}
+
+#[test]
+fn test_cmd_session() {
+    let output = wcal(&["--json", "x = 2", "x*3", "ans+1"], "");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 3);
+    assert!(stdout.lines().last().unwrap().contains("\"value\":7"));
+}
@@ -0,0 +1,121 @@
+//! Compile-time evaluation for the `wcal` calculator.
+//!
+//! `const_calc!` lexes, parses and calculates the expression
+//! while compiling, and expands to a literal. This keeps
+//! readable arithmetic in const contexts.
+//!
+//! Without a type the expression is calculated to `i128` and
+//! expands to an unsuffixed integer literal, so it fits any
+//! integer type that can hold the result. With a type the
+//! literal is suffixed and checked against the range of that
+//! type.
+//!
+//! On top of the syntax of `wcal`, the shifts `<<` and `>>` of
+//! integers are calculated first, binding looser than the
+//! arithmetic as in Rust.
+//!
+//! # Example
+//! ```
+//! use wcal_macros::const_calc;
+//!
+//! const MASK: u32 = const_calc!("0x1F * 3 + (1<<4)");
+//! assert_eq!(MASK, 109);
+//!
+//! const HALF: f64 = const_calc!("1/2", f64);
+//! assert_eq!(HALF, 0.5);
+//!
+//! let byte = const_calc!("2*100+55", u8);
+//! assert_eq!(byte, 255u8);
+//! ```
+use std::convert::TryFrom;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, Span};
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, Ident, LitStr, Token};
+
+mod shift;
+
+use wcal::{calculator, parser};
+
+struct ConstCalc {
+    expr: LitStr,
+    ty: Option<Ident>,
+}
+
+impl Parse for ConstCalc {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let expr = input.parse()?;
+        let ty = if input.is_empty() {
+            None
+        } else {
+            input.parse::<Token![,]>()?;
+            Some(input.parse()?)
+        };
+        Ok(ConstCalc{expr, ty})
+    }
+}
+
+fn int_literal(value: i128, ty: &str) -> Result<Literal, String> {
+    macro_rules! suffixed {
+        ($($name: ident => $method: ident),*) => {
+            match ty {
+                $(stringify!($name) => $name::try_from(value)
+                    .map(Literal::$method)
+                    .map_err(|_| format!("Result {} out of range for {}", value, ty)),)*
+                _ => Err(format!("Unsupported type: {}", ty))
+            }
+        };
+    }
+    suffixed!(
+        i8 => i8_suffixed, i16 => i16_suffixed, i32 => i32_suffixed,
+        i64 => i64_suffixed, i128 => i128_suffixed, isize => isize_suffixed,
+        u8 => u8_suffixed, u16 => u16_suffixed, u32 => u32_suffixed,
+        u64 => u64_suffixed, u128 => u128_suffixed, usize => usize_suffixed
+    )
+}
+
+fn evaluate(expr: &LitStr, ty: Option<&Ident>) -> syn::Result<Literal> {
+    let text = shift::rewrite(&expr.value()).map_err(|err| syn::Error::new(expr.span(), err))?;
+    let int = || calculator::<i128>(&text, parser::top_down_parser::parse)
+        .map_err(|err| syn::Error::new(expr.span(), err.message()));
+    let float = || calculator::<f64>(&text, parser::top_down_parser::parse)
+        .map_err(|err| syn::Error::new(expr.span(), err.message()));
+    // A literal of an infinity or a NaN panics
+    let finite = |value: f64, ty: &Ident| if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(syn::Error::new(expr.span(), format!("Result {} isn't a finite {}", value, ty)))
+    };
+    match ty {
+        None => Ok(Literal::i128_unsuffixed(int()?)),
+        Some(ty) if ty == "f64" => Ok(Literal::f64_suffixed(finite(float()?, ty)?)),
+        Some(ty) if ty == "f32" => Ok(Literal::f32_suffixed(finite(f64::from(float()? as f32), ty)? as f32)),
+        Some(ty) => int_literal(int()?, &ty.to_string())
+            .map_err(|err| syn::Error::new(ty.span(), err))
+    }
+}
+
+/// Calculate the expression at compile time and expand to a literal.
+///
+/// Use `const_calc!(expr)` for an unsuffixed integer literal, or
+/// `const_calc!(expr, type)` for a literal of the given integer or
+/// float type. Invalid expressions are reported as compile errors,
+/// and so are the infinities and NaN of a float type:
+/// ```compile_fail
+/// use wcal_macros::const_calc;
+///
+/// const INF: f64 = const_calc!("1/0", f64);
+/// ```
+#[proc_macro]
+pub fn const_calc(input: TokenStream) -> TokenStream {
+    let ConstCalc{expr, ty} = parse_macro_input!(input as ConstCalc);
+    match evaluate(&expr, ty.as_ref()) {
+        Ok(mut lit) => {
+            lit.set_span(Span::call_site());
+            quote!(#lit).into()
+        }
+        Err(err) => err.to_compile_error().into()
+    }
+}
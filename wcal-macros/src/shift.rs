@@ -0,0 +1,127 @@
+//! Shifts `<<` and `>>`, which `wcal` doesn't have, calculated
+//! before the rest of the expression.
+//!
+//! A shift binds looser than the arithmetic, as in Rust, so
+//! `1 + 1 << 2` is 8. The groups in parentheses and brackets are
+//! rewritten first, then the shifts of each group or argument are
+//! calculated from left to right on `i128` and replaced by their
+//! result.
+use std::convert::TryFrom;
+
+use wcal::lexer::{self, Span, Token};
+use wcal::{calculator, parser};
+
+type Tokens = [(Token, Span)];
+
+/// The expression with its shifts replaced by their results.
+pub fn rewrite(expr: &str) -> Result<String, String> {
+    let tokens = lexer::spanned(expr).map_err(|err| err.message())?;
+    if !(0..tokens.len()).any(|i| shift(&tokens, i).is_some()) {
+        return Ok(expr.to_string())
+    }
+    group(expr, &tokens)
+}
+
+/// `<<` or `>>` of the two tokens at `i` written together.
+fn shift(tokens: &Tokens, i: usize) -> Option<&'static str> {
+    let ((first, span), (second, next)) = (tokens.get(i)?, tokens.get(i + 1)?);
+    if span.end != next.start {
+        return None
+    }
+    match (first, second) {
+        (Token::Less, Token::Less) => Some("<<"),
+        (Token::Greater, Token::Greater) => Some(">>"),
+        _ => None,
+    }
+}
+
+/// Index of the `)` or `]` closing the bracket at `open`, or the end.
+fn closing(tokens: &Tokens, open: usize) -> usize {
+    let mut depth = 0;
+    for (i, (token, _)) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::LP | Token::LB => depth += 1,
+            Token::RP | Token::RB => depth -= 1,
+            _ => continue,
+        }
+        if depth == 0 {
+            return i
+        }
+    }
+    tokens.len()
+}
+
+/// Text of the tokens, each item between the `,` rewritten.
+fn group(expr: &str, tokens: &Tokens) -> Result<String, String> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i].0 {
+            Token::LP | Token::LB => i = closing(tokens, i),
+            Token::Comma => {
+                items.push(item(expr, &tokens[start..i])?);
+                start = i + 1;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    items.push(item(expr, &tokens[start..])?);
+    Ok(items.join(","))
+}
+
+/// Text of an item with its shifts calculated.
+fn item(expr: &str, tokens: &Tokens) -> Result<String, String> {
+    let mut operands = vec![String::new()];
+    let mut ops = Vec::new();
+    // Operators that bind looser than a shift
+    let mut loose = false;
+    let mut i = 0;
+    while i < tokens.len() {
+        if let Some(op) = shift(tokens, i) {
+            ops.push(op);
+            operands.push(String::new());
+            i += 2;
+            continue
+        }
+        let (token, span) = &tokens[i];
+        loose |= token.is_comparison() || matches!(token, Token::And | Token::Or | Token::Assign | Token::DotDot);
+        let text = operands.last_mut().unwrap();
+        text.push(' ');
+        text.push_str(&expr[span.clone()]);
+        if let Token::LP | Token::LB = token {
+            let close = closing(tokens, i);
+            text.push_str(&group(expr, &tokens[i + 1..close])?);
+            if let Some((_, span)) = tokens.get(close) {
+                text.push(' ');
+                text.push_str(&expr[span.clone()]);
+            }
+            i = close;
+        }
+        i += 1;
+    }
+    if ops.is_empty() {
+        return Ok(operands.pop().unwrap())
+    }
+    if loose {
+        return Err("Comparisons next to a shift need parentheses".to_string())
+    }
+    let int = |text: &str| calculator::<i128>(text, parser::top_down_parser::parse).map_err(|err| err.message());
+    let mut value = int(&operands[0])?;
+    for (op, operand) in ops.into_iter().zip(&operands[1..]) {
+        let by = int(operand)?;
+        value = shifted(value, op, by).ok_or_else(|| format!("Shift out of range: {} {} {}", value, op, by))?;
+    }
+    Ok(format!(" ({})", value))
+}
+
+/// `value << by` or `value >> by`, `None` if the bits shifted out
+/// change the value.
+fn shifted(value: i128, op: &str, by: i128) -> Option<i128> {
+    let by = u32::try_from(by).ok().filter(|by| *by < 128)?;
+    match op {
+        "<<" => Some(value << by).filter(|res| res >> by == value),
+        _ => Some(value >> by),
+    }
+}
@@ -0,0 +1,34 @@
+use wcal_macros::const_calc;
+
+const MASK: u32 = const_calc!("0x1F * 3 + (1<<4)");
+const NEG: i64 = const_calc!("-(2*3)+1");
+
+#[test]
+fn test_const() {
+    assert_eq!(MASK, 109);
+    assert_eq!(NEG, -5);
+}
+
+#[test]
+fn test_typed() {
+    assert_eq!(const_calc!("1+2", i128), 3i128);
+    assert_eq!(const_calc!("255", u8), 255u8);
+    assert_eq!(const_calc!("-7/2", i32), -3i32);
+    assert_eq!(const_calc!("1/2", f64), 0.5f64);
+    assert_eq!(const_calc!("3/4", f32), 0.75f32);
+}
+
+#[test]
+fn test_shift() {
+    assert_eq!(const_calc!("1 + 1 << 2"), 8);
+    assert_eq!(const_calc!("-(1 << 3) >> 1 >> 1"), -2);
+    assert_eq!(const_calc!("[1, 2 << 4][1]"), 32);
+    assert_eq!(const_calc!("-1 << 127", i128), i128::MIN);
+    assert_eq!(const_calc!("(0x100 >> 4) / 2", f64), 8.0);
+}
+
+#[test]
+fn test_array_len() {
+    let arr = [0u8; const_calc!("4*(1+1)")];
+    assert_eq!(arr.len(), 8);
+}